@@ -1,4 +1,6 @@
 fn main() {
     cli_batteries::build_rs().unwrap();
     println!("cargo:rerun-if-changed=migrations");
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/sequencer.proto").unwrap();
 }
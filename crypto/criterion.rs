@@ -1,9 +1,76 @@
 use kzg_ceremony_crypto as lib;
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
+/// With `BENCH_JSON=<file>`, the results of the run are also written to the
+/// file, one JSON object per benchmark in the format of
+/// `cargo bench -- -Z unstable-options --format json`, so that they can be
+/// compared across releases.
 fn main() {
+    lib::select_curve(lib::Curve::Bls12_381).unwrap();
+    let started = SystemTime::now();
+    let output_directory = std::env::var_os("CRITERION_HOME")
+        .map_or_else(|| PathBuf::from("../target/criterion"), PathBuf::from);
     let mut criterion = criterion::Criterion::default()
+        .output_directory(&output_directory)
         .configure_from_args()
         .sample_size(10);
     lib::bench::group(&mut criterion);
     criterion.final_summary();
+
+    if let Some(path) = std::env::var_os("BENCH_JSON") {
+        let mut results = Vec::new();
+        collect_results(&output_directory, started, &mut results).unwrap();
+        results.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        let mut file = fs::File::create(path).unwrap();
+        for result in results {
+            writeln!(file, "{result}").unwrap();
+        }
+    }
+}
+
+/// Reads the estimates criterion saved during this run from `new/` below
+/// `directory`.
+fn collect_results(
+    directory: &Path,
+    since: SystemTime,
+    results: &mut Vec<Value>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let estimates = path.join("new/estimates.json");
+        if estimates.is_file() && fs::metadata(&estimates)?.modified()? >= since {
+            let benchmark = read_json(&path.join("new/benchmark.json"))?;
+            let estimates = read_json(&estimates)?;
+            results.push(json!({
+                "type": "bench",
+                "name": benchmark["full_id"],
+                "median": nanoseconds(&estimates["median"]),
+                "deviation": nanoseconds(&estimates["median_abs_dev"]),
+            }));
+        } else {
+            collect_results(&path, since, results)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_json(path: &Path) -> io::Result<Value> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn nanoseconds(estimate: &Value) -> u64 {
+    estimate["point_estimate"]
+        .as_f64()
+        .unwrap_or_default()
+        .round() as u64
 }
@@ -1,7 +1,8 @@
 use crate::{
-    signature::{identity::Identity, EcdsaSignature},
+    signature::{identity::Identity, ContributionTypedData, EcdsaSignature},
     CeremoniesError, Contribution, Engine, Entropy, Tau, G2,
 };
+use ethers_core::types::transaction::eip712::Eip712;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
 use rayon::prelude::*;
@@ -9,11 +10,19 @@ use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
+/// Maximum length in bytes of [`BatchContribution::entropy_attestation`].
+pub const MAX_ENTROPY_ATTESTATION_LENGTH: usize = 256;
+
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct BatchContribution {
-    pub contributions:   Vec<Contribution>,
-    pub ecdsa_signature: EcdsaSignature,
+    pub contributions:       Vec<Contribution>,
+    pub ecdsa_signature:     EcdsaSignature,
+    /// Free text in which the client may record the sources of its entropy,
+    /// e.g. `blst 0.3.10, drand round 2409021`. It is kept in the transcript
+    /// as given and is not covered by the signatures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entropy_attestation: Option<String>,
 }
 
 impl BatchContribution {
@@ -42,8 +51,44 @@ impl BatchContribution {
         res
     }
 
+    /// Checks the ECDSA signature over the contribution against the Ethereum
+    /// address of `identity`. Returns `false` if there is no signature, or no
+    /// address to check it against.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremoniesError::InvalidEcdsaSignature`] if the signature was
+    /// not made by the address.
+    #[instrument(level = "info", skip_all)]
+    pub fn verify_ecdsa_signature(&self, identity: &Identity) -> Result<bool, CeremoniesError> {
+        match (&self.ecdsa_signature.0, identity) {
+            (Some(signature), Identity::Ethereum { address }) => {
+                let message = ContributionTypedData::from(self)
+                    .encode_eip712()
+                    .map_err(|_| CeremoniesError::InvalidEcdsaSignature)?;
+                signature
+                    .verify(message, address)
+                    .map_err(|_| CeremoniesError::InvalidEcdsaSignature)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Checks that the entropy attestation, if any, is at most
+    /// [`MAX_ENTROPY_ATTESTATION_LENGTH`] bytes long and free of control
+    /// characters.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CeremoniesError::InvalidEntropyAttestation`] if it is not.
+    pub fn validate_entropy_attestation(&self) -> Result<(), CeremoniesError> {
+        validate_entropy_attestation(self.entropy_attestation.as_deref())
+    }
+
     #[instrument(level = "info", skip_all, fields(n=self.contributions.len()))]
     pub fn validate<E: Engine>(&mut self) -> Result<(), CeremoniesError> {
+        self.validate_entropy_attestation()?;
         let res =
             self.contributions
                 .par_iter_mut()
@@ -57,6 +102,20 @@ impl BatchContribution {
     }
 }
 
+pub(crate) fn validate_entropy_attestation(
+    attestation: Option<&str>,
+) -> Result<(), CeremoniesError> {
+    match attestation {
+        Some(attestation)
+            if attestation.len() > MAX_ENTROPY_ATTESTATION_LENGTH
+                || attestation.chars().any(char::is_control) =>
+        {
+            Err(CeremoniesError::InvalidEntropyAttestation)
+        }
+        _ => Ok(()),
+    }
+}
+
 fn derive_taus<E: Engine>(entropy: &Entropy, size: usize) -> Vec<Tau> {
     // TODO: ChaCha20Rng does not implement Zeroize.
     let mut rng = ChaCha20Rng::from_seed(*entropy.expose_secret());
@@ -69,10 +128,17 @@ fn derive_taus<E: Engine>(entropy: &Entropy, size: usize) -> Vec<Tau> {
         .collect()
 }
 
-#[allow(clippy::missing_panics_doc)] // Does not panic.
 #[must_use]
 pub fn get_pot_pubkeys<E: Engine>(entropy: &Entropy) -> Vec<G2> {
-    let taus = derive_taus::<E>(entropy, 4);
+    derive_pot_pubkeys::<E>(entropy, 4)
+}
+
+/// The `pot_pubkey`s of the `size` contributions
+/// [`BatchContribution::add_entropy`] makes from `entropy`.
+#[allow(clippy::missing_panics_doc)] // Does not panic.
+#[must_use]
+pub fn derive_pot_pubkeys<E: Engine>(entropy: &Entropy, size: usize) -> Vec<G2> {
+    let taus = derive_taus::<E>(entropy, size);
     let result: Vec<G2> = taus
         .into_par_iter()
         .map(|tau| {
@@ -92,7 +158,7 @@ pub mod tests {
         engine::tests::arb_entropy,
         get_pot_pubkeys,
         signature::EcdsaSignature,
-        BatchContribution, CeremoniesError, DefaultEngine, G2,
+        BatchContribution, CeremoniesError, DefaultEngine, G2, MAX_ENTROPY_ATTESTATION_LENGTH,
     };
     use ark_bls12_381::{Fr, G2Affine};
     use ark_ec::{AffineCurve, ProjectiveCurve};
@@ -102,12 +168,13 @@ pub mod tests {
     #[test]
     fn test_validate() {
         let mut invalid = BatchContribution {
-            contributions:   vec![
+            contributions:       vec![
                 valid_contribution(),
                 invalid_g2_contribution(),
                 valid_contribution(),
             ],
-            ecdsa_signature: EcdsaSignature::empty(),
+            ecdsa_signature:     EcdsaSignature::empty(),
+            entropy_attestation: None,
         };
         assert!(matches!(
             invalid.validate::<DefaultEngine>(),
@@ -115,10 +182,22 @@ pub mod tests {
         ));
 
         let mut valid = BatchContribution {
-            contributions:   vec![valid_contribution(), valid_contribution()],
-            ecdsa_signature: EcdsaSignature::empty(),
+            contributions:       vec![valid_contribution(), valid_contribution()],
+            ecdsa_signature:     EcdsaSignature::empty(),
+            entropy_attestation: Some("drand round 2409021".to_string()),
         };
         assert!(valid.validate::<DefaultEngine>().is_ok());
+
+        for attestation in [
+            "a".repeat(MAX_ENTROPY_ATTESTATION_LENGTH + 1),
+            "a\nb".to_string(),
+        ] {
+            valid.entropy_attestation = Some(attestation);
+            assert_eq!(
+                valid.validate::<DefaultEngine>(),
+                Err(CeremoniesError::InvalidEntropyAttestation)
+            );
+        }
     }
 
     #[test]
@@ -153,9 +232,10 @@ pub mod bench {
         signature::identity::Identity,
         Arkworks, BatchTranscript, Both, BLST,
     };
-    use criterion::{BatchSize, Criterion};
+    use criterion::{black_box, BatchSize, Criterion};
 
     pub fn group(criterion: &mut Criterion) {
+        bench_deserialize(criterion);
         #[cfg(feature = "arkworks")]
         bench_add_tau::<Arkworks>(criterion, "arkworks");
         #[cfg(feature = "blst")]
@@ -164,6 +244,18 @@ pub mod bench {
         bench_add_tau::<Both<Arkworks, BLST>>(criterion, "both");
     }
 
+    /// Parsing a contribution of the full ceremony size as the sequencer
+    /// receives it, without validating the points.
+    fn bench_deserialize(criterion: &mut Criterion) {
+        let json =
+            serde_json::to_vec(&BatchTranscript::new(BATCH_SIZE.iter()).contribution()).unwrap();
+        criterion.bench_function("batch_contribution/deserialize", move |bencher| {
+            bencher.iter(|| {
+                black_box(serde_json::from_slice::<BatchContribution>(black_box(&json)).unwrap())
+            });
+        });
+    }
+
     fn bench_add_tau<E: Engine>(criterion: &mut Criterion, name: &str) {
         // Create a non-trivial transcript
         let transcript = {
@@ -1,6 +1,7 @@
 use crate::{
+    batch_contribution::validate_entropy_attestation,
     signature::{identity::Identity, ContributionTypedData, EcdsaSignature},
-    BatchContribution, CeremoniesError, Engine, Transcript,
+    BatchContribution, CeremoniesError, Check, Contribution, Engine, Transcript, VerifyTimings,
 };
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -9,9 +10,58 @@ use tracing::instrument;
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct BatchTranscript {
-    pub transcripts:                  Vec<Transcript>,
-    pub participant_ids:              Vec<Identity>,
+    pub transcripts: Vec<Transcript>,
+    pub participant_ids: Vec<Identity>,
     pub participant_ecdsa_signatures: Vec<EcdsaSignature>,
+    /// The entropy attestations of the participants, see
+    /// [`BatchContribution::entropy_attestation`]. Empty until the first
+    /// participant sends one, after that it has an entry for every
+    /// participant.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub participant_entropy_attestations: Vec<Option<String>>,
+}
+
+/// A batch contribution verified by [`BatchTranscript::verify_observed`],
+/// with its signatures pruned, to be added by [`BatchTranscript::apply`].
+/// It serializes so that it can be verified in another process.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VerifiedContribution {
+    /// Number of participants of the transcript it was verified against.
+    num_participants:    usize,
+    contributions:       Vec<Contribution>,
+    ecdsa_signature:     EcdsaSignature,
+    entropy_attestation: Option<String>,
+    identity:            Identity,
+    timings:             VerifyTimings,
+}
+
+impl VerifiedContribution {
+    /// Number of participants of the transcript it was verified against.
+    #[must_use]
+    pub const fn num_participants(&self) -> usize {
+        self.num_participants
+    }
+
+    /// The contributions to the sub-ceremonies, as they are added.
+    #[must_use]
+    pub fn contributions(&self) -> &[Contribution] {
+        &self.contributions
+    }
+
+    #[must_use]
+    pub const fn ecdsa_signature(&self) -> &EcdsaSignature {
+        &self.ecdsa_signature
+    }
+
+    #[must_use]
+    pub fn entropy_attestation(&self) -> Option<&str> {
+        self.entropy_attestation.as_deref()
+    }
+
+    #[must_use]
+    pub const fn identity(&self) -> &Identity {
+        &self.identity
+    }
 }
 
 impl BatchTranscript {
@@ -20,12 +70,31 @@ impl BatchTranscript {
         I: IntoIterator<Item = &'a (usize, usize)> + 'a,
     {
         Self {
-            transcripts:                  iter
+            transcripts: iter
                 .into_iter()
                 .map(|(num_g1, num_g2)| Transcript::new(*num_g1, *num_g2))
                 .collect(),
-            participant_ids:              vec![Identity::None],
+            participant_ids: vec![Identity::None],
             participant_ecdsa_signatures: vec![EcdsaSignature::empty()],
+            participant_entropy_attestations: Vec::new(),
+        }
+    }
+
+    /// The entropy attestation of the participant at `index`.
+    #[must_use]
+    pub fn entropy_attestation(&self, index: usize) -> Option<&str> {
+        self.participant_entropy_attestations
+            .get(index)
+            .and_then(Option::as_deref)
+    }
+
+    /// Records the entropy attestation of the participant about to be added,
+    /// filling in the participants before them once there is a first one.
+    pub fn push_entropy_attestation(&mut self, attestation: Option<String>) {
+        if attestation.is_some() || !self.participant_entropy_attestations.is_empty() {
+            self.participant_entropy_attestations
+                .resize(self.participant_ids.len(), None);
+            self.participant_entropy_attestations.push(attestation);
         }
     }
 
@@ -39,75 +108,347 @@ impl BatchTranscript {
     #[must_use]
     pub fn contribution(&self) -> BatchContribution {
         BatchContribution {
-            contributions:   self
+            contributions:       self
                 .transcripts
                 .iter()
                 .map(Transcript::contribution)
                 .collect(),
-            ecdsa_signature: EcdsaSignature::empty(),
+            ecdsa_signature:     EcdsaSignature::empty(),
+            entropy_attestation: None,
         }
     }
 
     /// Adds a batch contribution to the transcript. The contribution must be
     /// valid.
-    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
     pub fn verify_add<E: Engine>(
         &mut self,
-        mut contribution: BatchContribution,
+        contribution: BatchContribution,
         identity: Identity,
     ) -> Result<(), CeremoniesError> {
+        self.verify_add_timed::<E>(contribution, identity)
+            .map(|_| ())
+    }
+
+    /// Adds a batch contribution like [`Self::verify_add`], and reports how
+    /// long the checks of the slowest sub-ceremony took.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contribution is invalid.
+    pub fn verify_add_timed<E: Engine>(
+        &mut self,
+        contribution: BatchContribution,
+        identity: Identity,
+    ) -> Result<VerifyTimings, CeremoniesError> {
+        self.verify_add_observed::<E>(contribution, identity, &|_, _| ())
+    }
+
+    /// Adds a batch contribution like [`Self::verify_add_timed`], and calls
+    /// `observe` with the index of the sub-ceremony and the check whenever
+    /// one of the checks of a sub-ceremony is done, see
+    /// [`Transcript::verify_observed`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contribution is invalid.
+    pub fn verify_add_observed<E: Engine>(
+        &mut self,
+        contribution: BatchContribution,
+        identity: Identity,
+        observe: &(dyn Fn(usize, Check) + Sync),
+    ) -> Result<VerifyTimings, CeremoniesError> {
+        let verified = self.verify_observed::<E>(contribution, identity, observe)?;
+        self.apply(verified)
+    }
+
+    /// Verifies a batch contribution without adding it, so that the
+    /// transcript only needs to be borrowed mutably for [`Self::apply`]. Each
+    /// sub-ceremony is verified, and its BLS signature pruned, in a parallel
+    /// task, alongside the ECDSA signature.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contribution is invalid.
+    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    pub fn verify_observed<E: Engine>(
+        &self,
+        contribution: BatchContribution,
+        identity: Identity,
+        observe: &(dyn Fn(usize, Check) + Sync),
+    ) -> Result<VerifiedContribution, CeremoniesError> {
+        Self::verify_on::<E>(
+            &self.transcripts,
+            self.num_participants(),
+            contribution,
+            identity,
+            observe,
+        )
+    }
+
+    /// Verifies a batch contribution like [`Self::verify_observed`], against
+    /// the transcript with `num_participants` participants whose contribution
+    /// base is `base`, see [`Self::contribution`]. The checks only need the
+    /// current powers, so a process can verify contributions without the rest
+    /// of the transcript.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contribution is invalid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a sub-ceremony of `base` has fewer than two powers.
+    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    pub fn verify_on_base<E: Engine>(
+        base: BatchContribution,
+        num_participants: usize,
+        contribution: BatchContribution,
+        identity: Identity,
+        observe: &(dyn Fn(usize, Check) + Sync),
+    ) -> Result<VerifiedContribution, CeremoniesError> {
+        let transcripts = base
+            .contributions
+            .into_iter()
+            .map(|base| {
+                let mut transcript = Transcript::new(base.powers.g1.len(), base.powers.g2.len());
+                transcript.powers = base.powers;
+                transcript
+            })
+            .collect::<Vec<_>>();
+        Self::verify_on::<E>(
+            &transcripts,
+            num_participants,
+            contribution,
+            identity,
+            observe,
+        )
+    }
+
+    fn verify_on<E: Engine>(
+        transcripts: &[Transcript],
+        num_participants: usize,
+        contribution: BatchContribution,
+        identity: Identity,
+        observe: &(dyn Fn(usize, Check) + Sync),
+    ) -> Result<VerifiedContribution, CeremoniesError> {
         // Verify contribution count
+        if transcripts.len() != contribution.contributions.len() {
+            return Err(CeremoniesError::UnexpectedNumContributions(
+                transcripts.len(),
+                contribution.contributions.len(),
+            ));
+        }
+        contribution.validate_entropy_attestation()?;
+
+        let typed_data = ContributionTypedData::from(&contribution);
+        let BatchContribution {
+            contributions,
+            ecdsa_signature,
+            entropy_attestation,
+        } = contribution;
+        let message = identity.to_string();
+        let (verified, ecdsa_signature) = rayon::join(
+            || {
+                transcripts
+                    .par_iter()
+                    .zip(contributions)
+                    .enumerate()
+                    .map(|(i, (transcript, mut contribution))| {
+                        let timings = transcript
+                            .verify_observed::<E>(&contribution, &|check| observe(i, check))
+                            .map_err(|e| CeremoniesError::InvalidCeremony(i, e))?;
+                        contribution.bls_signature = contribution
+                            .bls_signature
+                            .prune::<E>(message.as_bytes(), contribution.pot_pubkey);
+                        Ok::<_, CeremoniesError>((contribution, timings))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            },
+            || ecdsa_signature.prune(&identity, &typed_data),
+        );
+        let (contributions, timings): (Vec<_>, Vec<_>) = verified?.into_iter().unzip();
+
+        Ok(VerifiedContribution {
+            num_participants,
+            contributions,
+            ecdsa_signature,
+            entropy_attestation,
+            identity,
+            timings: timings
+                .into_iter()
+                .fold(VerifyTimings::default(), VerifyTimings::max),
+        })
+    }
+
+    /// Adds a contribution verified by [`Self::verify_observed`] against
+    /// this transcript, all sub-ceremonies at once. Returns how long the
+    /// checks of the slowest sub-ceremony took.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, and leaves the transcript as is, if participants
+    /// were added since the contribution was verified.
+    pub fn apply(
+        &mut self,
+        verified: VerifiedContribution,
+    ) -> Result<VerifyTimings, CeremoniesError> {
+        if verified.num_participants != self.num_participants() {
+            return Err(CeremoniesError::UnexpectedNumParticipants(
+                verified.num_participants,
+                self.num_participants(),
+            ));
+        }
+        self.participant_ecdsa_signatures
+            .push(verified.ecdsa_signature);
+        self.push_entropy_attestation(verified.entropy_attestation);
+        for (transcript, contribution) in self.transcripts.iter_mut().zip(verified.contributions) {
+            transcript.add(contribution);
+        }
+        self.participant_ids.push(verified.identity);
+        Ok(verified.timings)
+    }
+
+    /// Checks the structure of a batch contribution without adding it, see
+    /// [`Transcript::validate`]. The pairing checks and signatures are left
+    /// to [`Self::verify_add`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of contributions or of their powers
+    /// does not match the transcript, or if a point is invalid.
+    #[instrument(level = "info", skip_all, fields(n=contribution.contributions.len()))]
+    pub fn validate<E: Engine>(
+        &self,
+        contribution: &BatchContribution,
+    ) -> Result<(), CeremoniesError> {
         if self.transcripts.len() != contribution.contributions.len() {
             return Err(CeremoniesError::UnexpectedNumContributions(
                 self.transcripts.len(),
                 contribution.contributions.len(),
             ));
         }
-
-        // Verify contributions in parallel
+        contribution.validate_entropy_attestation()?;
         self.transcripts
-            .par_iter_mut()
+            .par_iter()
             .zip(&contribution.contributions)
             .enumerate()
             .try_for_each(|(i, (transcript, contribution))| {
                 transcript
-                    .verify::<E>(contribution)
+                    .validate::<E>(contribution)
                     .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
-            })?;
-
-        self.participant_ecdsa_signatures.push(
-            contribution
-                .ecdsa_signature
-                .prune(&identity, &ContributionTypedData::from(&contribution)),
-        );
-
-        // Prune BLS Signatures
-        contribution.contributions.iter_mut().for_each(|c| {
-            c.bls_signature = c
-                .bls_signature
-                .prune::<E>(identity.to_string().as_bytes(), c.pot_pubkey);
-        });
+            })
+    }
 
-        // Add contributions
-        for (transcript, contribution) in self
+    /// Verifies the whole transcript from scratch, as a third party reading
+    /// it would: the powers and witness of every sub-ceremony, and the BLS
+    /// and ECDSA signatures of all participants. Unlike [`Self::verify_add`]
+    /// this does not trust any part of the transcript, and it is a lot
+    /// slower.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the checks fails.
+    #[instrument(level = "info", skip_all, fields(n=self.num_participants()))]
+    pub fn verify_self<E: Engine>(&self) -> Result<(), CeremoniesError> {
+        let num_entries = self.participant_ids.len();
+        let lengths = self
             .transcripts
-            .iter_mut()
-            .zip(contribution.contributions.into_iter())
-        {
-            transcript.add(contribution);
+            .iter()
+            .map(|transcript| transcript.witness.pubkeys.len())
+            .chain([self.participant_ecdsa_signatures.len()])
+            .chain(
+                (!self.participant_entropy_attestations.is_empty())
+                    .then_some(self.participant_entropy_attestations.len()),
+            );
+        for length in lengths {
+            if length != num_entries {
+                return Err(CeremoniesError::UnexpectedNumParticipants(
+                    num_entries,
+                    length,
+                ));
+            }
+        }
+        for attestation in &self.participant_entropy_attestations {
+            validate_entropy_attestation(attestation.as_deref())?;
         }
 
-        self.participant_ids.push(identity);
+        self.transcripts
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(i, transcript)| {
+                transcript
+                    .verify_self::<E>(&self.participant_ids)
+                    .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
+            })?;
+
+        (1..num_entries).into_par_iter().try_for_each(|i| {
+            let signature = &self.participant_ecdsa_signatures[i];
+            if signature.0.is_none() {
+                return Ok(());
+            }
+            let data = ContributionTypedData::from_transcript(self, i);
+            if signature.prune(&self.participant_ids[i], &data) == *signature {
+                Ok(())
+            } else {
+                Err(CeremoniesError::InvalidParticipantEcdsaSignature(i))
+            }
+        })
+    }
 
-        Ok(())
+    /// Verifies the witness chains of all sub-ceremonies from contribution
+    /// `from + 1` up to contribution `up_to`: that each contribution builds
+    /// on the running product of the one before, and the BLS signatures. The
+    /// chains up to `from` are trusted, e.g. because they were verified
+    /// before. Unlike [`Self::verify_self`] this does not check the powers,
+    /// so it takes time in the number of contributions checked only.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the checks fails, or if the transcript has
+    /// fewer than `up_to` contributions.
+    #[instrument(level = "info", skip_all, fields(from, up_to))]
+    pub fn verify_witness_chain<E: Engine>(
+        &self,
+        from: usize,
+        up_to: usize,
+    ) -> Result<(), CeremoniesError> {
+        let num_entries = self.participant_ids.len();
+        if up_to >= num_entries {
+            return Err(CeremoniesError::UnexpectedNumParticipants(
+                up_to + 1,
+                num_entries,
+            ));
+        }
+        for transcript in &self.transcripts {
+            let length = transcript.witness.pubkeys.len();
+            if length != num_entries {
+                return Err(CeremoniesError::UnexpectedNumParticipants(
+                    num_entries,
+                    length,
+                ));
+            }
+        }
+        self.transcripts
+            .par_iter()
+            .enumerate()
+            .try_for_each(|(i, transcript)| {
+                transcript
+                    .verify_witness_chain::<E>(&self.participant_ids, from, up_to)
+                    .map_err(|e| CeremoniesError::InvalidCeremony(i, e))
+            })
     }
 }
 
 #[cfg(test)]
 pub mod tests {
     use crate::{
-        BatchTranscript, CeremoniesError::UnexpectedNumContributions, DefaultEngine, Identity,
+        BatchTranscript,
+        CeremoniesError::{InvalidCeremony, UnexpectedNumContributions, UnexpectedNumParticipants},
+        CeremonyError::{InvalidG1Power, UnexpectedNumG2Powers, WitnessPairingFailed},
+        Check, DefaultEngine, Identity, G1,
     };
+    use secrecy::Secret;
+    use std::sync::Mutex;
 
     #[test]
     fn test_verify_add() {
@@ -120,6 +461,228 @@ pub mod tests {
             .unwrap();
         assert_eq!(result, UnexpectedNumContributions(2, 1));
     }
+
+    #[test]
+    fn test_verify_add_observed() {
+        let mut transcript = BatchTranscript::new([(2, 2), (3, 3)].iter());
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<DefaultEngine>(&Secret::new([1; 32]), &Identity::None)
+            .unwrap();
+        let checks = Mutex::new(Vec::new());
+        transcript
+            .verify_add_observed::<DefaultEngine>(contribution, Identity::None, &|i, check| {
+                checks.lock().unwrap().push((i, check));
+            })
+            .unwrap();
+        let mut checks = checks.into_inner().unwrap();
+        checks.sort_by_key(|&(i, check)| (i, check == Check::Pairings));
+        assert_eq!(checks, [
+            (0, Check::Points),
+            (0, Check::Pairings),
+            (1, Check::Points),
+            (1, Check::Pairings),
+        ]);
+    }
+
+    #[test]
+    fn test_apply_checks_transcript_is_unchanged() {
+        let mut transcript = BatchTranscript::new([(2, 2), (3, 3)].iter());
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<DefaultEngine>(&Secret::new([1; 32]), &Identity::None)
+            .unwrap();
+        let verified = transcript
+            .verify_observed::<DefaultEngine>(contribution.clone(), Identity::None, &|_, _| ())
+            .unwrap();
+        let verified_again = verified.clone();
+
+        let mut other = transcript.clone();
+        other
+            .verify_add::<DefaultEngine>(contribution, Identity::None)
+            .unwrap();
+        transcript.apply(verified).unwrap();
+        assert_eq!(transcript, other);
+        assert_eq!(
+            transcript.apply(verified_again).unwrap_err(),
+            CeremoniesError::UnexpectedNumParticipants(0, 1)
+        );
+        assert_eq!(transcript.num_participants(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        let transcript = BatchTranscript::new([(2, 2), (3, 3)].iter());
+        let contribution = transcript.contribution();
+        transcript.validate::<DefaultEngine>(&contribution).unwrap();
+
+        let mut short = contribution.clone();
+        short.contributions[1].powers.g2.pop();
+        assert_eq!(
+            transcript.validate::<DefaultEngine>(&short),
+            Err(InvalidCeremony(1, UnexpectedNumG2Powers(3, 2)))
+        );
+
+        let mut invalid = contribution;
+        invalid.contributions[0].powers.g1[1] = G1([0xff; 48]);
+        assert!(matches!(
+            transcript.validate::<DefaultEngine>(&invalid),
+            Err(InvalidCeremony(0, InvalidG1Power(1, _)))
+        ));
+    }
+
+    #[test]
+    fn test_verify_self() {
+        let mut transcript = BatchTranscript::new([(4, 2), (8, 3)].iter());
+        transcript.verify_self::<DefaultEngine>().unwrap();
+        for no in 1..=2 {
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_entropy::<DefaultEngine>(&Secret::new([no; 32]), &Identity::None)
+                .unwrap();
+            transcript
+                .verify_add::<DefaultEngine>(contribution, Identity::None)
+                .unwrap();
+        }
+        transcript.verify_self::<DefaultEngine>().unwrap();
+
+        let mut reordered = transcript.clone();
+        reordered.transcripts[1].witness.pubkeys.swap(1, 2);
+        assert!(matches!(
+            reordered.verify_self::<DefaultEngine>(),
+            Err(InvalidCeremony(1, WitnessPairingFailed(_)))
+        ));
+
+        let mut truncated = transcript;
+        truncated.participant_ids.pop();
+        assert_eq!(
+            truncated.verify_self::<DefaultEngine>(),
+            Err(UnexpectedNumParticipants(2, 3))
+        );
+    }
+
+    #[test]
+    fn test_verify_on_base() {
+        let mut transcript = BatchTranscript::new([(4, 2), (8, 3)].iter());
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<DefaultEngine>(&Secret::new([1; 32]), &Identity::None)
+            .unwrap();
+        transcript
+            .verify_add::<DefaultEngine>(contribution, Identity::None)
+            .unwrap();
+
+        let mut contribution = transcript.contribution();
+        contribution
+            .add_entropy::<DefaultEngine>(&Secret::new([2; 32]), &Identity::None)
+            .unwrap();
+        let mut on_base = transcript.clone();
+        let verified = BatchTranscript::verify_on_base::<DefaultEngine>(
+            transcript.contribution(),
+            transcript.num_participants(),
+            contribution.clone(),
+            Identity::None,
+            &|_, _| (),
+        )
+        .unwrap();
+        on_base.apply(verified).unwrap();
+        transcript
+            .verify_add::<DefaultEngine>(contribution.clone(), Identity::None)
+            .unwrap();
+        assert_eq!(on_base, transcript);
+
+        // The base of another transcript does not verify it.
+        assert!(matches!(
+            BatchTranscript::verify_on_base::<DefaultEngine>(
+                transcript.contribution(),
+                transcript.num_participants(),
+                contribution,
+                Identity::None,
+                &|_, _| (),
+            ),
+            Err(InvalidCeremony(..))
+        ));
+    }
+
+    #[test]
+    fn test_verify_witness_chain() {
+        let mut transcript = BatchTranscript::new([(4, 2), (8, 3)].iter());
+        transcript
+            .verify_witness_chain::<DefaultEngine>(0, 0)
+            .unwrap();
+        for no in 1..=3 {
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_entropy::<DefaultEngine>(&Secret::new([no; 32]), &Identity::None)
+                .unwrap();
+            transcript
+                .verify_add::<DefaultEngine>(contribution, Identity::None)
+                .unwrap();
+        }
+        transcript
+            .verify_witness_chain::<DefaultEngine>(0, 3)
+            .unwrap();
+        transcript
+            .verify_witness_chain::<DefaultEngine>(2, 3)
+            .unwrap();
+        assert_eq!(
+            transcript.verify_witness_chain::<DefaultEngine>(0, 4),
+            Err(UnexpectedNumParticipants(5, 4))
+        );
+
+        let mut broken = transcript;
+        broken.transcripts[1].witness.pubkeys.swap(2, 3);
+        broken.verify_witness_chain::<DefaultEngine>(0, 1).unwrap();
+        assert!(matches!(
+            broken.verify_witness_chain::<DefaultEngine>(0, 2),
+            Err(InvalidCeremony(1, WitnessPairingFailed(2)))
+        ));
+        // Links up to `from` are trusted.
+        assert!(matches!(
+            broken.verify_witness_chain::<DefaultEngine>(2, 3),
+            Err(InvalidCeremony(1, WitnessPairingFailed(3)))
+        ));
+    }
+
+    #[test]
+    fn test_entropy_attestations() {
+        let mut transcript = BatchTranscript::new([(4, 2)].iter());
+        let contribute = |transcript: &mut BatchTranscript, no, attestation: Option<&str>| {
+            let mut contribution = transcript.contribution();
+            contribution
+                .add_entropy::<DefaultEngine>(&Secret::new([no; 32]), &Identity::None)
+                .unwrap();
+            contribution.entropy_attestation = attestation.map(str::to_string);
+            transcript
+                .verify_add::<DefaultEngine>(contribution, Identity::None)
+                .unwrap();
+        };
+
+        // Transcripts without attestations keep their format.
+        contribute(&mut transcript, 1, None);
+        let json = serde_json::to_string(&transcript).unwrap();
+        assert!(!json.contains("participantEntropyAttestations"));
+
+        contribute(&mut transcript, 2, Some("drand round 2409021"));
+        assert_eq!(transcript.participant_entropy_attestations, vec![
+            None,
+            None,
+            Some("drand round 2409021".to_string())
+        ]);
+        assert_eq!(transcript.entropy_attestation(1), None);
+        assert_eq!(
+            transcript.entropy_attestation(2),
+            Some("drand round 2409021")
+        );
+        transcript.verify_self::<DefaultEngine>().unwrap();
+
+        let mut truncated = transcript;
+        truncated.participant_entropy_attestations.pop();
+        assert_eq!(
+            truncated.verify_self::<DefaultEngine>(),
+            Err(UnexpectedNumParticipants(3, 2))
+        );
+    }
 }
 
 #[cfg(feature = "bench")]
@@ -131,9 +694,10 @@ pub mod bench {
         bench::{rand_entropy, BATCH_SIZE},
         Arkworks, Both, BLST,
     };
-    use criterion::{BatchSize, Criterion};
+    use criterion::{black_box, BatchSize, Criterion};
 
     pub fn group(criterion: &mut Criterion) {
+        bench_serialize(criterion);
         #[cfg(feature = "arkworks")]
         bench_verify_add::<Arkworks>(criterion, "arkworks");
         #[cfg(feature = "blst")]
@@ -142,6 +706,21 @@ pub mod bench {
         bench_verify_add::<Both<Arkworks, BLST>>(criterion, "both");
     }
 
+    /// Writing and reading the transcript file, as after every contribution
+    /// and on startup.
+    fn bench_serialize(criterion: &mut Criterion) {
+        let transcript = BatchTranscript::new(BATCH_SIZE.iter());
+        let json = serde_json::to_vec(&transcript).unwrap();
+        criterion.bench_function("batch_transcript/serialize", move |bencher| {
+            bencher.iter(|| black_box(serde_json::to_vec(black_box(&transcript)).unwrap()));
+        });
+        criterion.bench_function("batch_transcript/deserialize", move |bencher| {
+            bencher.iter(|| {
+                black_box(serde_json::from_slice::<BatchTranscript>(black_box(&json)).unwrap())
+            });
+        });
+    }
+
     fn bench_verify_add<E: Engine>(criterion: &mut Criterion, name: &str) {
         // Create a non-trivial transcript
         let transcript = {
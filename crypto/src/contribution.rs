@@ -64,8 +64,8 @@ pub mod test {
     pub fn valid_contribution() -> Contribution {
         Contribution {
             powers:        Powers {
-                g1: vec![G1::one()],
-                g2: vec![G2::one()],
+                g1: vec![G1::one()].into(),
+                g2: vec![G2::one()].into(),
             },
             pot_pubkey:    G2::one(),
             bls_signature: BlsSignature::empty(),
@@ -75,8 +75,8 @@ pub mod test {
     pub fn invalid_g1_contribution() -> Contribution {
         Contribution {
             powers:        Powers {
-                g1: vec![invalid_g1()],
-                g2: vec![G2::one()],
+                g1: vec![invalid_g1()].into(),
+                g2: vec![G2::one()].into(),
             },
             pot_pubkey:    G2::one(),
             bls_signature: BlsSignature::empty(),
@@ -86,8 +86,8 @@ pub mod test {
     pub fn invalid_g2_contribution() -> Contribution {
         Contribution {
             powers:        Powers {
-                g1: vec![G1::one()],
-                g2: vec![invalid_g2()],
+                g1: vec![G1::one()].into(),
+                g2: vec![invalid_g2()].into(),
             },
             pot_pubkey:    G2::one(),
             bls_signature: BlsSignature::empty(),
@@ -97,8 +97,8 @@ pub mod test {
     pub fn invalid_pot_pubkey_contribution() -> Contribution {
         Contribution {
             powers:        Powers {
-                g1: vec![G1::one()],
-                g2: vec![G2::one()],
+                g1: vec![G1::one()].into(),
+                g2: vec![G2::one()].into(),
             },
             pot_pubkey:    invalid_g2(),
             bls_signature: BlsSignature::empty(),
@@ -0,0 +1,82 @@
+//! The curve the ceremony runs on.
+//!
+//! BLS12-381, as used by EIP-4844, is the default. A sequencer for a Groth16
+//! or Plonk project selects BN254 instead with [`select_curve`], before any
+//! point is parsed or created: the curve is fixed for the whole process, like
+//! the directory of [`crate::map_points_in`], so that [`crate::G1`] and
+//! [`crate::G2`] stay plain byte arrays. Processes select the curve even for
+//! BLS12-381, handling a point before is a bug that [`curve`] panics on.
+//!
+//! BN254 points are encoded in the canonical compressed format of arkworks:
+//! the little-endian x coordinate, with the top bit of the last byte set for
+//! the larger of the two y coordinates and the bit below it for the point at
+//! infinity. They are 32 bytes in G1 and 64 in G2, and take the first bytes of
+//! the arrays, the rest is zero.
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Display, EnumString, Serialize, Deserialize,
+)]
+pub enum Curve {
+    #[default]
+    #[strum(serialize = "bls12-381")]
+    #[serde(rename = "bls12-381")]
+    Bls12_381,
+    #[strum(serialize = "bn254")]
+    #[serde(rename = "bn254")]
+    Bn254,
+}
+
+impl Curve {
+    /// Length of the encoding of a G1 point.
+    #[must_use]
+    pub const fn g1_size(self) -> usize {
+        match self {
+            Self::Bls12_381 => 48,
+            Self::Bn254 => 32,
+        }
+    }
+
+    /// Length of the encoding of a G2 point.
+    #[must_use]
+    pub const fn g2_size(self) -> usize {
+        match self {
+            Self::Bls12_381 => 96,
+            Self::Bn254 => 64,
+        }
+    }
+}
+
+static CURVE: OnceCell<Curve> = OnceCell::new();
+
+/// Runs the ceremonies of this process on `curve`.
+///
+/// # Errors
+///
+/// Returns the curve in use if another one was selected already.
+pub fn select_curve(curve: Curve) -> Result<(), Curve> {
+    let current = *CURVE.get_or_init(|| curve);
+    if current == curve {
+        Ok(())
+    } else {
+        Err(current)
+    }
+}
+
+/// The curve of this process, see the [module documentation](self).
+///
+/// # Panics
+///
+/// Panics if no curve was selected. Tests, and builds with the `test_utils`
+/// feature, run on the default curve instead.
+#[must_use]
+pub fn curve() -> Curve {
+    match CURVE.get() {
+        Some(curve) => *curve,
+        None if cfg!(any(test, feature = "test_utils")) => *CURVE.get_or_init(Curve::default),
+        None => panic!("points are handled before the curve is selected with `select_curve`"),
+    }
+}
@@ -0,0 +1,188 @@
+//! BN254 operations of [`Arkworks`](super::Arkworks), see the
+//! [curve](crate::curve) for the encoding of the points.
+
+use super::{powers_of_tau, powers_pairing, pubkey_pairing, same_powers_pairing, tau_from_entropy};
+use crate::{CeremonyError, Curve, Entropy, ParseError, Tau, G1, G2};
+use ark_bn254::{Bn254, Fr, G1Affine, G2Affine};
+use ark_ec::{
+    models::SWModelParameters, short_weierstrass_jacobian::GroupAffine, wnaf::WnafContext,
+    AffineCurve, ProjectiveCurve,
+};
+use ark_ff::Zero;
+use ark_serialize::{CanonicalDeserializeWithFlags, CanonicalSerialize, SWFlags};
+use rayon::prelude::*;
+use secrecy::ExposeSecret;
+
+const G1_SIZE: usize = Curve::Bn254.g1_size();
+const G2_SIZE: usize = Curve::Bn254.g2_size();
+
+impl TryFrom<G1> for G1Affine {
+    type Error = ParseError;
+
+    fn try_from(g1: G1) -> Result<Self, Self::Error> {
+        parse(&g1.0, G1_SIZE)
+    }
+}
+
+impl TryFrom<G2> for G2Affine {
+    type Error = ParseError;
+
+    fn try_from(g2: G2) -> Result<Self, Self::Error> {
+        parse(&g2.0, G2_SIZE)
+    }
+}
+
+impl From<G1Affine> for G1 {
+    fn from(g1: G1Affine) -> Self {
+        Self(write(&g1))
+    }
+}
+
+impl From<G2Affine> for G2 {
+    fn from(g2: G2Affine) -> Self {
+        Self(write(&g2))
+    }
+}
+
+/// Serializes a point in the canonical compressed encoding, padded with
+/// zeros to `N` bytes.
+fn write<P: SWModelParameters, const N: usize>(point: &GroupAffine<P>) -> [u8; N] {
+    let mut encoded = Vec::with_capacity(N);
+    point
+        .serialize(&mut encoded)
+        .expect("writing to a vector does not fail");
+    let mut bytes = [0; N];
+    bytes[..encoded.len()].copy_from_slice(&encoded);
+    bytes
+}
+
+/// Parses a point from the first `size` bytes, without the subgroup check.
+///
+/// # Errors
+///
+/// Returns a [`ParseError`] if the bytes are not the unique encoding of a
+/// point on the curve.
+fn parse<P: SWModelParameters, const N: usize>(
+    bytes: &[u8; N],
+    size: usize,
+) -> Result<GroupAffine<P>, ParseError> {
+    let (encoded, padding) = bytes.split_at(size);
+    if padding.iter().any(|byte| *byte != 0) {
+        return Err(ParseError::InvalidCompression);
+    }
+    let (x, flags): (P::BaseField, SWFlags) =
+        CanonicalDeserializeWithFlags::deserialize_with_flags(encoded)
+            .map_err(|_| ParseError::BigIntError)?;
+    let point = match flags.is_positive() {
+        None => GroupAffine::zero(),
+        Some(greatest) => {
+            GroupAffine::get_point_from_x(x, greatest).ok_or(ParseError::InvalidXCoordinate)?
+        }
+    };
+    // The infinity flag makes arkworks ignore the x coordinate.
+    if write::<P, N>(&point) != *bytes {
+        return Err(ParseError::InvalidInfinity);
+    }
+    Ok(point)
+}
+
+pub(super) fn validate_g1(points: &[G1]) -> Result<(), CeremonyError> {
+    // The cofactor of G1 is one, so every point on the curve is in the group.
+    points.into_par_iter().enumerate().try_for_each(|(i, p)| {
+        G1Affine::try_from(*p)
+            .map(|_| ())
+            .map_err(|e| CeremonyError::InvalidG1Power(i, e))
+    })
+}
+
+pub(super) fn validate_g2(points: &[G2]) -> Result<(), CeremonyError> {
+    points.into_par_iter().enumerate().try_for_each(|(i, p)| {
+        let p = G2Affine::try_from(*p).map_err(|e| CeremonyError::InvalidG2Power(i, e))?;
+        if !p.is_in_correct_subgroup_assuming_on_curve() {
+            return Err(CeremonyError::InvalidG2Power(
+                i,
+                ParseError::InvalidSubgroup,
+            ));
+        }
+        Ok(())
+    })
+}
+
+pub(super) fn verify_pubkey(tau: G1, previous: G1, pubkey: G2) -> Result<(), CeremonyError> {
+    let tau = G1Affine::try_from(tau)?;
+    let previous = G1Affine::try_from(previous)?;
+    let pubkey = G2Affine::try_from(pubkey)?;
+    if !pubkey_pairing::<Bn254>(tau, previous, pubkey) {
+        return Err(CeremonyError::PubKeyPairingFailed);
+    }
+    Ok(())
+}
+
+pub(super) fn verify_g1(powers: &[G1], tau: G2) -> Result<(), CeremonyError> {
+    let powers = powers
+        .into_par_iter()
+        .map(|p| G1Affine::try_from(*p))
+        .collect::<Result<Vec<_>, _>>()?;
+    let tau = G2Affine::try_from(tau)?;
+    if !powers_pairing::<Bn254>(&powers, tau) {
+        return Err(CeremonyError::G1PairingFailed);
+    }
+    Ok(())
+}
+
+pub(super) fn verify_g2(g1: &[G1], g2: &[G2]) -> Result<(), CeremonyError> {
+    assert!(g1.len() == g2.len());
+    let g1 = g1
+        .into_par_iter()
+        .map(|p| G1Affine::try_from(*p))
+        .collect::<Result<Vec<_>, _>>()?;
+    let g2 = g2
+        .into_par_iter()
+        .map(|p| G2Affine::try_from(*p))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !same_powers_pairing::<Bn254>(&g1, &g2) {
+        return Err(CeremonyError::G2PairingFailed);
+    }
+    Ok(())
+}
+
+pub(super) fn generate_tau(entropy: &Entropy) -> Tau {
+    tau_from_entropy::<Fr>(entropy)
+}
+
+pub(super) fn add_tau_g1(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError> {
+    let points = powers
+        .par_iter()
+        .map(|p| G1Affine::try_from(*p))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (p, a) in powers.iter_mut().zip(mul_powers(tau, &points)) {
+        *p = a.into();
+    }
+    Ok(())
+}
+
+pub(super) fn add_tau_g2(tau: &Tau, powers: &mut [G2]) -> Result<(), CeremonyError> {
+    let points = powers
+        .par_iter()
+        .map(|p| G2Affine::try_from(*p))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (p, a) in powers.iter_mut().zip(mul_powers(tau, &points)) {
+        *p = a.into();
+    }
+    Ok(())
+}
+
+/// Multiplies the points by successive powers of $τ$.
+fn mul_powers<C: AffineCurve<ScalarField = Fr>>(tau: &Tau, points: &[C]) -> Vec<C> {
+    let taus = powers_of_tau::<Fr>(tau, points.len());
+    let mut projective = points
+        .par_iter()
+        .zip(taus.expose_secret())
+        .map(|(p, tau)| WnafContext::new(5).mul(p.into_projective(), tau))
+        .collect::<Vec<_>>();
+    C::Projective::batch_normalization(&mut projective);
+    projective
+        .iter()
+        .map(ProjectiveCurve::into_affine)
+        .collect()
+}
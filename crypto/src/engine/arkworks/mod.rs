@@ -2,6 +2,7 @@
 
 #![cfg(feature = "arkworks")]
 
+mod bn254;
 mod endomorphism;
 mod ext_field;
 mod hashing;
@@ -10,11 +11,12 @@ mod zcash_format;
 use self::endomorphism::{g1_mul_glv, g1_subgroup_check, g2_subgroup_check};
 use super::Engine;
 use crate::{
+    curve,
     engine::arkworks::hashing::{
         hash_to_curve::{HashToCurve, MapToCurveBasedHasher, WBMap},
         hash_to_field::DefaultFieldHasher,
     },
-    CeremonyError, Entropy, ParseError, Tau, F, G1, G2,
+    CeremonyError, Curve, Entropy, ParseError, Tau, F, G1, G2,
 };
 use ark_bls12_381::{
     g1::Parameters as G1Parameters, Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective,
@@ -34,12 +36,19 @@ use std::iter;
 use tracing::instrument;
 
 /// Arkworks implementation of [`Engine`] with additional endomorphism
-/// optimizations.
+/// optimizations. It also runs BN254 ceremonies.
 pub struct Arkworks;
 
 impl Engine for Arkworks {
+    fn supports(_curve: Curve) -> bool {
+        true
+    }
+
     #[instrument(level = "info", skip_all, fields(n=points.len()))]
     fn validate_g1(points: &[G1]) -> Result<(), CeremonyError> {
+        if curve() == Curve::Bn254 {
+            return bn254::validate_g1(points);
+        }
         points.into_par_iter().enumerate().try_for_each(|(i, p)| {
             let p = G1Affine::try_from(*p).map_err(|e| CeremonyError::InvalidG1Power(i, e))?;
             if !g1_subgroup_check(&p) {
@@ -54,6 +63,9 @@ impl Engine for Arkworks {
 
     #[instrument(level = "info", skip_all, fields(n=points.len()))]
     fn validate_g2(points: &[G2]) -> Result<(), CeremonyError> {
+        if curve() == Curve::Bn254 {
+            return bn254::validate_g2(points);
+        }
         points.into_par_iter().enumerate().try_for_each(|(i, p)| {
             let p = G2Affine::try_from(*p).map_err(|e| CeremonyError::InvalidG2Power(i, e))?;
             if !g2_subgroup_check(&p) {
@@ -68,12 +80,13 @@ impl Engine for Arkworks {
 
     #[instrument(level = "info", skip_all)]
     fn verify_pubkey(tau: G1, previous: G1, pubkey: G2) -> Result<(), CeremonyError> {
+        if curve() == Curve::Bn254 {
+            return bn254::verify_pubkey(tau, previous, pubkey);
+        }
         let tau = G1Affine::try_from(tau)?;
         let previous = G1Affine::try_from(previous)?;
         let pubkey = G2Affine::try_from(pubkey)?;
-        if Bls12_381::pairing(tau, G2Affine::prime_subgroup_generator())
-            != Bls12_381::pairing(previous, pubkey)
-        {
+        if !pubkey_pairing::<Bls12_381>(tau, previous, pubkey) {
             return Err(CeremonyError::PubKeyPairingFailed);
         }
         Ok(())
@@ -81,6 +94,9 @@ impl Engine for Arkworks {
 
     #[instrument(level = "info", skip_all, fields(n=powers.len()))]
     fn verify_g1(powers: &[G1], tau: G2) -> Result<(), CeremonyError> {
+        if curve() == Curve::Bn254 {
+            return bn254::verify_g1(powers, tau);
+        }
         // Parse ZCash format
         let powers = powers
             .into_par_iter()
@@ -88,15 +104,7 @@ impl Engine for Arkworks {
             .collect::<Result<Vec<_>, _>>()?;
         let tau = G2Affine::try_from(tau)?;
 
-        // Compute random linear combination
-        let (factors, sum) = random_factors(powers.len() - 1);
-        let lhs_g1 = VariableBaseMSM::multi_scalar_mul(&powers[1..], &factors[..]);
-        let lhs_g2 = G2Affine::prime_subgroup_generator().mul(sum);
-        let rhs_g1 = VariableBaseMSM::multi_scalar_mul(&powers[..factors.len()], &factors[..]);
-        let rhs_g2 = tau.mul(sum);
-
-        // Check pairing
-        if Bls12_381::pairing(lhs_g1, lhs_g2) != Bls12_381::pairing(rhs_g1, rhs_g2) {
+        if !powers_pairing::<Bls12_381>(&powers, tau) {
             return Err(CeremonyError::G1PairingFailed);
         }
         Ok(())
@@ -104,6 +112,9 @@ impl Engine for Arkworks {
 
     #[instrument(level = "info", skip_all, fields(n1=g1.len(), n2=g2.len()))]
     fn verify_g2(g1: &[G1], g2: &[G2]) -> Result<(), CeremonyError> {
+        if curve() == Curve::Bn254 {
+            return bn254::verify_g2(g1, g2);
+        }
         assert!(g1.len() == g2.len());
 
         // Parse ZCash format
@@ -116,15 +127,7 @@ impl Engine for Arkworks {
             .map(|p| G2Affine::try_from(*p))
             .collect::<Result<Vec<_>, _>>()?;
 
-        // Compute random linear combination
-        let (factors, sum) = random_factors(g2.len());
-        let lhs_g1 = VariableBaseMSM::multi_scalar_mul(&g1, &factors[..]);
-        let lhs_g2 = G2Affine::prime_subgroup_generator().mul(sum);
-        let rhs_g1 = G1Affine::prime_subgroup_generator().mul(sum);
-        let rhs_g2 = VariableBaseMSM::multi_scalar_mul(&g2, &factors[..]);
-
-        // Check pairing
-        if Bls12_381::pairing(lhs_g1, lhs_g2) != Bls12_381::pairing(rhs_g1, rhs_g2) {
+        if !same_powers_pairing::<Bls12_381>(&g1, &g2) {
             return Err(CeremonyError::G2PairingFailed);
         }
         Ok(())
@@ -132,26 +135,18 @@ impl Engine for Arkworks {
 
     #[instrument(level = "info", skip_all)]
     fn generate_tau(entropy: &Entropy) -> Tau {
-        // Use ChaCha20 CPRNG
-        let mut rng = ChaCha20Rng::from_seed(*entropy.expose_secret());
-
-        // Generate tau by reducing 512 bits of entropy modulo prime.
-        let mut large = [0_u8; 64];
-        rng.fill(&mut large);
-
-        let fr = bls_keygen(large);
-
-        // Convert to Tau
-        let le_bytes = fr.into_repr().to_bytes_le();
-        assert!(le_bytes.len() == 32);
-        let mut tau = [0u8; 32];
-        tau.copy_from_slice(&le_bytes[..]);
-        Secret::new(F(tau))
+        if curve() == Curve::Bn254 {
+            return bn254::generate_tau(entropy);
+        }
+        tau_from_entropy::<Fr>(entropy)
     }
 
     #[instrument(level = "info", skip_all, fields(n=powers.len()))]
     fn add_tau_g1(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError> {
-        let taus = powers_of_tau(tau, powers.len());
+        if curve() == Curve::Bn254 {
+            return bn254::add_tau_g1(tau, powers);
+        }
+        let taus = powers_of_tau::<Fr>(tau, powers.len());
         let mut projective = powers
             .par_iter()
             .zip(taus.expose_secret())
@@ -166,7 +161,10 @@ impl Engine for Arkworks {
 
     #[instrument(level = "info", skip_all, fields(n=powers.len()))]
     fn add_tau_g2(tau: &Tau, powers: &mut [G2]) -> Result<(), CeremonyError> {
-        let taus = powers_of_tau(tau, powers.len());
+        if curve() == Curve::Bn254 {
+            return bn254::add_tau_g2(tau, powers);
+        }
+        let taus = powers_of_tau::<Fr>(tau, powers.len());
         let mut projective = powers
             .par_iter()
             .zip(taus.expose_secret())
@@ -185,6 +183,9 @@ impl Engine for Arkworks {
     }
 
     fn sign_message(tau: &Tau, message: &[u8]) -> Option<G1> {
+        if curve() == Curve::Bn254 {
+            return None;
+        }
         let mapper = MapToCurveBasedHasher::<
             G1Parameters,
             DefaultFieldHasher<Sha256, 128>,
@@ -197,6 +198,9 @@ impl Engine for Arkworks {
     }
 
     fn verify_signature(sig: G1, message: &[u8], pk: G2) -> bool {
+        if curve() == Curve::Bn254 {
+            return false;
+        }
         let sig = match G1Affine::try_from(sig) {
             Ok(sig) => sig,
             _ => return false,
@@ -237,9 +241,28 @@ impl Engine for Arkworks {
     }
 }
 
+/// Derives $τ$ in the scalar field `Fr` from the entropy.
+fn tau_from_entropy<Fr: PrimeField>(entropy: &Entropy) -> Tau {
+    // Use ChaCha20 CPRNG
+    let mut rng = ChaCha20Rng::from_seed(*entropy.expose_secret());
+
+    // Generate tau by reducing 512 bits of entropy modulo prime.
+    let mut large = [0_u8; 64];
+    rng.fill(&mut large);
+
+    let fr = bls_keygen::<Fr>(large);
+
+    // Convert to Tau
+    let le_bytes = fr.into_repr().to_bytes_le();
+    assert!(le_bytes.len() == 32);
+    let mut tau = [0u8; 32];
+    tau.copy_from_slice(&le_bytes[..]);
+    Secret::new(F(tau))
+}
+
 // Implementation of the KeyGen function as specified in
 // https://datatracker.ietf.org/doc/draft-irtf-cfrg-bls-signature/
-fn bls_keygen(ikm: [u8; 64]) -> Fr {
+fn bls_keygen<Fr: PrimeField>(ikm: [u8; 64]) -> Fr {
     // the `L` value, precomputed from the formula given in the spec
     const L: u8 = 48;
     let mut full_ikm = [0u8; 65];
@@ -265,9 +288,9 @@ fn bls_keygen(ikm: [u8; 64]) -> Fr {
     }
 }
 
-pub fn powers_of_tau(tau: &Tau, n: usize) -> SecretVec<Fr> {
+pub fn powers_of_tau<Fr: PrimeField>(tau: &Tau, n: usize) -> SecretVec<Fr> {
     // Convert tau
-    let tau = Secret::new(Fr::from(tau.expose_secret()));
+    let tau = Secret::new(Fr::from_le_bytes_mod_order(&tau.expose_secret().0[..]));
 
     // Compute powers
     Secret::new(
@@ -277,19 +300,60 @@ pub fn powers_of_tau(tau: &Tau, n: usize) -> SecretVec<Fr> {
     )
 }
 
-fn random_factors(n: usize) -> (Vec<<Fr as PrimeField>::BigInt>, Fr) {
+fn random_factors<Fr: PrimeField>(n: usize) -> (Vec<Fr::BigInt>, Fr) {
     let mut rng = rand::thread_rng();
     let mut sum = Fr::zero();
     let factors = iter::from_fn(|| {
         let r = Fr::rand(&mut rng);
         sum += r;
-        Some(r.0)
+        Some(r.into_repr())
     })
     .take(n)
     .collect::<Vec<_>>();
     (factors, sum)
 }
 
+/// Whether `pubkey` moves `previous` to `tau`.
+fn pubkey_pairing<P: PairingEngine>(
+    tau: P::G1Affine,
+    previous: P::G1Affine,
+    pubkey: P::G2Affine,
+) -> bool {
+    P::pairing(tau, P::G2Affine::prime_subgroup_generator()) == P::pairing(previous, pubkey)
+}
+
+/// Whether `powers` is a sequence of powers of the exponent of `tau`.
+fn powers_pairing<P: PairingEngine>(powers: &[P::G1Affine], tau: P::G2Affine) -> bool {
+    // Compute random linear combination
+    let (factors, sum) = random_factors::<P::Fr>(powers.len() - 1);
+    let (lhs_g1, rhs_g1) = rayon::join(
+        || VariableBaseMSM::multi_scalar_mul(&powers[1..], &factors[..]),
+        || VariableBaseMSM::multi_scalar_mul(&powers[..factors.len()], &factors[..]),
+    );
+    let lhs_g2 = P::G2Affine::prime_subgroup_generator().mul(sum);
+    let rhs_g2 = tau.mul(sum);
+
+    // Check pairing
+    let (lhs, rhs) = rayon::join(|| P::pairing(lhs_g1, lhs_g2), || P::pairing(rhs_g1, rhs_g2));
+    lhs == rhs
+}
+
+/// Whether `g1` and `g2` have the same exponents.
+fn same_powers_pairing<P: PairingEngine>(g1: &[P::G1Affine], g2: &[P::G2Affine]) -> bool {
+    // Compute random linear combination
+    let (factors, sum) = random_factors::<P::Fr>(g2.len());
+    let (lhs_g1, rhs_g2) = rayon::join(
+        || VariableBaseMSM::multi_scalar_mul(g1, &factors[..]),
+        || VariableBaseMSM::multi_scalar_mul(g2, &factors[..]),
+    );
+    let lhs_g2 = P::G2Affine::prime_subgroup_generator().mul(sum);
+    let rhs_g1 = P::G1Affine::prime_subgroup_generator().mul(sum);
+
+    // Check pairing
+    let (lhs, rhs) = rayon::join(|| P::pairing(lhs_g1, lhs_g2), || P::pairing(rhs_g1, rhs_g2));
+    lhs == rhs
+}
+
 impl From<&F> for Fr {
     fn from(f: &F) -> Self {
         Self::from_le_bytes_mod_order(&f.0[..])
@@ -322,7 +386,9 @@ pub mod test {
     #[allow(clippy::missing_panics_doc)]
     pub fn arb_fr() -> impl Strategy<Value = Fr> {
         any::<U256>().prop_map(|mut n| {
-            n %= uint!(52435875175126190479447740508185965837690552500527637822603658699938581184513_U256);
+            n %= uint!(
+                52435875175126190479447740508185965837690552500527637822603658699938581184513_U256
+            );
             Fr::from_repr(BigInteger256::from(n)).expect("n is smaller than modulus")
         })
     }
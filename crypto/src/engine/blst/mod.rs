@@ -138,14 +138,16 @@ impl Engine for BLST {
         let (factors, sum) = random_factors(powers.len() - 1);
         let g2 = unsafe { *blst_p2_generator() };
 
-        let lhs_g1 = p1s_mult_pippenger(&powers[1..], &factors[..]);
+        let (lhs_g1, rhs_g1) = rayon::join(
+            || p1s_mult_pippenger(&powers[1..], &factors[..]),
+            || p1s_mult_pippenger(&powers[..factors.len()], &factors[..]),
+        );
         let lhs_g2 = p2_to_affine(&p2_mult(&g2, &sum));
-
-        let rhs_g1 = p1s_mult_pippenger(&powers[..factors.len()], &factors[..]);
         let rhs_g2 = p2_to_affine(&p2_mult(&tau, &sum));
 
         // Check pairing
-        if pairing(&lhs_g1, &lhs_g2) != pairing(&rhs_g1, &rhs_g2) {
+        let (lhs, rhs) = rayon::join(|| pairing(&lhs_g1, &lhs_g2), || pairing(&rhs_g1, &rhs_g2));
+        if lhs != rhs {
             return Err(CeremonyError::G1PairingFailed);
         }
 
@@ -171,14 +173,16 @@ impl Engine for BLST {
         let g1_generator = unsafe { *blst_p1_generator() };
         let g2_generator = unsafe { *blst_p2_generator() };
 
-        let lhs_g1 = p1s_mult_pippenger(&g1, &factors[..]);
+        let (lhs_g1, rhs_g2) = rayon::join(
+            || p1s_mult_pippenger(&g1, &factors[..]),
+            || p2s_mult_pippenger(&g2, &factors[..]),
+        );
         let lhs_g2 = p2_to_affine(&p2_mult(&g2_generator, &sum));
-
         let rhs_g1 = p1_to_affine(&p1_mult(&g1_generator, &sum));
-        let rhs_g2 = p2s_mult_pippenger(&g2, &factors[..]);
 
         // Check pairing
-        if pairing(&lhs_g1, &lhs_g2) != pairing(&rhs_g1, &rhs_g2) {
+        let (lhs, rhs) = rayon::join(|| pairing(&lhs_g1, &lhs_g2), || pairing(&rhs_g1, &rhs_g2));
+        if lhs != rhs {
             return Err(CeremonyError::G1PairingFailed);
         }
 
@@ -1,18 +1,37 @@
 use super::Engine;
-use crate::{CeremonyError, Entropy, Tau, G1, G2};
+use crate::{curve, CeremonyError, Curve, Entropy, Tau, G1, G2};
 use rayon::join;
 use secrecy::ExposeSecret;
 use std::marker::PhantomData;
 
 /// Implementation of [`Engine`] that combines two existing engines for
-/// redundancy.
+/// redundancy. On curves that `B` does not support, `A` runs alone, without
+/// the cross-check, see [`Engine::cross_checks`].
 pub struct Both<A: Engine, B: Engine> {
     _a: PhantomData<A>,
     _b: PhantomData<B>,
 }
 
+impl<A: Engine, B: Engine> Both<A, B> {
+    /// Whether `A` runs without `B`, on a curve `B` does not support.
+    fn alone() -> bool {
+        !B::supports(curve())
+    }
+}
+
 impl<A: Engine, B: Engine> Engine for Both<A, B> {
+    fn supports(curve: Curve) -> bool {
+        A::supports(curve)
+    }
+
+    fn cross_checks(curve: Curve) -> bool {
+        B::supports(curve)
+    }
+
     fn validate_g1(points: &[G1]) -> Result<(), CeremonyError> {
+        if Self::alone() {
+            return A::validate_g1(points);
+        }
         let (a, b) = join(|| A::validate_g1(points), || B::validate_g1(points));
         a?;
         b?;
@@ -20,6 +39,9 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
     }
 
     fn validate_g2(points: &[G2]) -> Result<(), CeremonyError> {
+        if Self::alone() {
+            return A::validate_g2(points);
+        }
         let (a, b) = join(|| A::validate_g2(points), || B::validate_g2(points));
         a?;
         b?;
@@ -27,6 +49,9 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
     }
 
     fn verify_pubkey(tau: G1, previous: G1, pubkey: G2) -> Result<(), CeremonyError> {
+        if Self::alone() {
+            return A::verify_pubkey(tau, previous, pubkey);
+        }
         let (a, b) = join(
             || A::verify_pubkey(tau, previous, pubkey),
             || B::verify_pubkey(tau, previous, pubkey),
@@ -37,6 +62,9 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
     }
 
     fn verify_g1(powers: &[G1], tau: G2) -> Result<(), CeremonyError> {
+        if Self::alone() {
+            return A::verify_g1(powers, tau);
+        }
         let (a, b) = join(|| A::verify_g1(powers, tau), || B::verify_g1(powers, tau));
         a?;
         b?;
@@ -44,6 +72,9 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
     }
 
     fn verify_g2(g1: &[G1], g2: &[G2]) -> Result<(), CeremonyError> {
+        if Self::alone() {
+            return A::verify_g2(g1, g2);
+        }
         let (a, b) = join(|| A::verify_g2(g1, g2), || B::verify_g2(g1, g2));
         a?;
         b?;
@@ -51,12 +82,18 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
     }
 
     fn generate_tau(entropy: &Entropy) -> Tau {
+        if Self::alone() {
+            return A::generate_tau(entropy);
+        }
         let (a, b) = join(|| A::generate_tau(entropy), || B::generate_tau(entropy));
         assert_eq!(a.expose_secret(), b.expose_secret());
         a
     }
 
     fn add_tau_g1(tau: &Tau, powers: &mut [G1]) -> Result<(), CeremonyError> {
+        if Self::alone() {
+            return A::add_tau_g1(tau, powers);
+        }
         let mut b = powers.to_vec();
         let (ra, rb) = join(|| A::add_tau_g1(tau, powers), || B::add_tau_g1(tau, &mut b));
         ra?;
@@ -66,6 +103,9 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
     }
 
     fn add_tau_g2(tau: &Tau, powers: &mut [G2]) -> Result<(), CeremonyError> {
+        if Self::alone() {
+            return A::add_tau_g2(tau, powers);
+        }
         let mut b = powers.to_vec();
         let (ra, rb) = join(|| A::add_tau_g2(tau, powers), || B::add_tau_g2(tau, &mut b));
         ra?;
@@ -75,6 +115,9 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
     }
 
     fn sign_message(tau: &Tau, message: &[u8]) -> Option<G1> {
+        if Self::alone() {
+            return A::sign_message(tau, message);
+        }
         let (a, b) = join(
             || A::sign_message(tau, message),
             || B::sign_message(tau, message),
@@ -84,6 +127,9 @@ impl<A: Engine, B: Engine> Engine for Both<A, B> {
     }
 
     fn verify_signature(sig: G1, message: &[u8], pk: G2) -> bool {
+        if Self::alone() {
+            return A::verify_signature(sig, message, pk);
+        }
         let (a, b) = join(
             || A::verify_signature(sig, message, pk),
             || B::verify_signature(sig, message, pk),
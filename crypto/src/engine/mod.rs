@@ -11,7 +11,7 @@ mod arkworks;
 mod blst;
 mod both;
 
-use crate::{CeremonyError, F, G1, G2};
+use crate::{CeremonyError, Curve, F, G1, G2};
 pub use secrecy::Secret;
 
 #[cfg(feature = "arkworks")]
@@ -26,6 +26,18 @@ pub type Tau = Secret<F>;
 pub trait Engine {
     const CYPHER_SUITE: &'static str = "BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_POP_";
 
+    /// Whether the engine implements the operations on `curve`. Engines only
+    /// support BLS12-381 unless they say otherwise.
+    fn supports(curve: Curve) -> bool {
+        curve == Curve::Bls12_381
+    }
+
+    /// Whether a second implementation checks the results of the operations
+    /// on `curve`, see [`Both`].
+    fn cross_checks(_curve: Curve) -> bool {
+        false
+    }
+
     /// Verifies that the given G1 points are valid.
     ///
     /// Valid mean that they are uniquely encoded in compressed ZCash format and
@@ -51,6 +63,12 @@ pub trait Engine {
     /// Verify that the pubkey contains the contribution added
     /// from `previous` to `tau`.
     ///
+    /// The points are decoded but not checked to be in the prime order
+    /// subgroups, so the outcome only holds once [`Self::validate_g1`] and
+    /// [`Self::validate_g2`] accepted them. The two may run in parallel, as
+    /// in [`Transcript::verify`](crate::Transcript::verify), but the outcome
+    /// of the pairing check must be discarded if the validation fails.
+    ///
     /// # Errors
     /// Returns an error if any of the points is invalid, or if the pairing
     /// check fails.
@@ -58,6 +76,8 @@ pub trait Engine {
 
     /// Verify that `powers` contains a sequence of powers of `tau`.
     ///
+    /// Requires the points to be validated, see [`Self::verify_pubkey`].
+    ///
     /// # Errors
     /// Returns an error if any of the points is invalid, or if the points are
     /// not a valid sequence of powers.
@@ -65,6 +85,8 @@ pub trait Engine {
 
     /// Verify that `g1` and `g2` contain the same values.
     ///
+    /// Requires the points to be validated, see [`Self::verify_pubkey`].
+    ///
     /// # Errors
     /// Returns an error if any of the points is invalid, if `g2` is not a valid
     /// sequence of powers, or if `g1` and `g2` are sequences with different
@@ -87,9 +109,13 @@ pub trait Engine {
     fn add_tau_g2(tau: &Tau, powers: &mut [G2]) -> Result<(), CeremonyError>;
 
     /// Sign a message with `CYPHER_SUITE`, using $τ$ as the secret key.
+    ///
+    /// There are no signatures on curves other than BLS12-381.
     fn sign_message(tau: &Tau, message: &[u8]) -> Option<G1>;
 
     /// Verify a `CYPHER_SUITE` signature.
+    ///
+    /// Fails on curves other than BLS12-381, so that signatures are pruned.
     fn verify_signature(sig: G1, message: &[u8], pk: G2) -> bool;
 }
 
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use strum::IntoStaticStr;
 use thiserror::Error;
 
@@ -5,12 +6,25 @@ pub trait ErrorCode {
     fn to_error_code(&self) -> String;
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Error, IntoStaticStr)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error, IntoStaticStr, Serialize, Deserialize)]
 pub enum CeremoniesError {
     #[error("Unexpected number of contributions: expected {0}, got {1}")]
     UnexpectedNumContributions(usize, usize),
     #[error("Error in contribution {0}: {1}")]
     InvalidCeremony(usize, #[source] CeremonyError),
+    #[error("ECDSA signature does not match the participant address")]
+    InvalidEcdsaSignature,
+    #[error("Contribution has no ECDSA signature")]
+    MissingEcdsaSignature,
+    #[error("Unexpected number of participants: expected {0}, got {1}")]
+    UnexpectedNumParticipants(usize, usize),
+    #[error("ECDSA signature of participant {0} does not match their address")]
+    InvalidParticipantEcdsaSignature(usize),
+    #[error(
+        "Entropy attestation is longer than {} bytes or contains control characters",
+        crate::MAX_ENTROPY_ATTESTATION_LENGTH
+    )]
+    InvalidEntropyAttestation,
 }
 
 impl ErrorCode for CeremoniesError {
@@ -23,7 +37,7 @@ impl ErrorCode for CeremoniesError {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Error, IntoStaticStr)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error, IntoStaticStr, Serialize, Deserialize)]
 pub enum CeremonyError {
     #[error("Unsupported number of G1 powers: {0}")]
     UnsupportedNumG1Powers(usize),
@@ -81,6 +95,16 @@ pub enum CeremonyError {
     ContributionNoEntropy,
     #[error("Mismatch in witness length: {0} products and {1} pubkeys")]
     WitnessLengthMismatch(usize, usize),
+    #[error("Mismatch in witness length: {0} pubkeys and {1} BLS signatures")]
+    WitnessSignaturesMismatch(usize, usize),
+    #[error("Witness must start with the generators")]
+    InvalidWitnessGenerators,
+    #[error("Witness pairing check failed for contribution {0}")]
+    WitnessPairingFailed(usize),
+    #[error("Last running product does not match g1[1]")]
+    WitnessProductMismatch,
+    #[error("Invalid BLS signature of contribution {0}")]
+    InvalidBlsSignature(usize),
 }
 
 impl ErrorCode for CeremonyError {
@@ -89,7 +113,7 @@ impl ErrorCode for CeremonyError {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Error, IntoStaticStr)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error, IntoStaticStr, Serialize, Deserialize)]
 pub enum ParseError {
     #[error("Invalid x coordinate")]
     BigIntError,
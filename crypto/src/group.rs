@@ -1,6 +1,10 @@
-//! BLS12-381 group elements in ZCash encoding.
+//! Group elements of the [curve](crate::curve) of the ceremony, BLS12-381 in
+//! ZCash encoding by default.
 
-use crate::hex_format::{bytes_to_hex, hex_to_bytes};
+use crate::{
+    curve::{curve, Curve},
+    hex_format::{bytes_to_hex, hex_to_bytes},
+};
 use hex_literal::hex;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use zeroize::Zeroize;
@@ -11,13 +15,15 @@ use zeroize::Zeroize;
 pub struct F(pub [u8; 32]);
 
 /// A G1 curve point.
-/// Encoded in compressed ZCash format.
+/// Encoded in compressed ZCash format, or padded with zeros on BN254.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Zeroize)]
+#[repr(transparent)]
 pub struct G1(pub [u8; 48]);
 
 /// A G2 curve point.
-/// Encoded in compressed ZCash format.
+/// Encoded in compressed ZCash format, or padded with zeros on BN254.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Zeroize)]
+#[repr(transparent)]
 pub struct G2(pub [u8; 96]);
 
 impl F {
@@ -41,31 +47,91 @@ impl F {
 impl G1 {
     /// The zero element of the group.
     #[must_use]
-    pub const fn zero() -> Self {
-        Self(hex!("c00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000"))
+    pub fn zero() -> Self {
+        match curve() {
+            Curve::Bls12_381 => Self(hex!("c00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")),
+            Curve::Bn254 => Self(padded(hex!("0000000000000000000000000000000000000000000000000000000000000040"))),
+        }
     }
 
     /// The default generator for the group.
     #[must_use]
-    pub const fn one() -> Self {
-        Self(hex!("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb"))
+    pub fn one() -> Self {
+        match curve() {
+            Curve::Bls12_381 => Self(hex!("97f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb")),
+            Curve::Bn254 => Self(padded(hex!("0100000000000000000000000000000000000000000000000000000000000000"))),
+        }
+    }
+
+    /// The encoding of the point on the curve of the ceremony.
+    #[must_use]
+    pub fn encoded(&self) -> &[u8] {
+        &self.0[..curve().g1_size()]
+    }
+
+    /// The point with the encoding `bytes`, if that has the length of a G1
+    /// point on the curve of the ceremony.
+    #[must_use]
+    pub fn from_encoded(bytes: &[u8]) -> Option<Self> {
+        let mut point = [0; 48];
+        (bytes.len() == curve().g1_size()).then(|| {
+            point[..bytes.len()].copy_from_slice(bytes);
+            Self(point)
+        })
     }
 }
 
 impl G2 {
     /// The zero element of the group.
     #[must_use]
-    pub const fn zero() -> Self {
-        Self(hex!("c00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000"))
+    pub fn zero() -> Self {
+        match curve() {
+            Curve::Bls12_381 => Self(hex!("c00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")),
+            Curve::Bn254 => Self(padded(hex!("00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000040"))),
+        }
     }
 
     /// The default generator for the group.
     #[must_use]
-    pub const fn one() -> Self {
-        Self(hex!("93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8"))
+    pub fn one() -> Self {
+        match curve() {
+            Curve::Bls12_381 => Self(hex!("93e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8")),
+            Curve::Bn254 => Self(padded(hex!("edf692d95cbdde46ddda5ef7d422436779445c5e66006a42761e1f12efde0018c212f3aeb785e49712e7a9353349aaf1255dfb31b7bf60723a480d9293938e19"))),
+        }
+    }
+
+    /// The encoding of the point on the curve of the ceremony.
+    #[must_use]
+    pub fn encoded(&self) -> &[u8] {
+        &self.0[..curve().g2_size()]
+    }
+
+    /// The point with the encoding `bytes`, if that has the length of a G2
+    /// point on the curve of the ceremony.
+    #[must_use]
+    pub fn from_encoded(bytes: &[u8]) -> Option<Self> {
+        let mut point = [0; 96];
+        (bytes.len() == curve().g2_size()).then(|| {
+            point[..bytes.len()].copy_from_slice(bytes);
+            Self(point)
+        })
     }
 }
 
+/// Pads a shorter encoding with zeros.
+pub(crate) fn padded<const N: usize, const M: usize>(bytes: [u8; N]) -> [u8; M] {
+    let mut padded = [0; M];
+    padded[..N].copy_from_slice(&bytes);
+    padded
+}
+
+/// The first `N` bytes of an encoding.
+fn truncated<const N: usize, const M: usize>(bytes: [u8; M]) -> [u8; N] {
+    bytes[..N]
+        .try_into()
+        .expect("curve encodings fit the arrays")
+}
+
 impl Serialize for F {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         bytes_to_hex::<_, 32, 66>(serializer, self.0)
@@ -80,25 +146,37 @@ impl<'de> Deserialize<'de> for F {
 
 impl Serialize for G1 {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        bytes_to_hex::<_, 48, 98>(serializer, self.0)
+        match curve() {
+            Curve::Bls12_381 => bytes_to_hex::<_, 48, 98>(serializer, self.0),
+            Curve::Bn254 => bytes_to_hex::<_, 32, 66>(serializer, truncated(self.0)),
+        }
     }
 }
 
 impl<'de> Deserialize<'de> for G1 {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        hex_to_bytes(deserializer).map(Self)
+        match curve() {
+            Curve::Bls12_381 => hex_to_bytes(deserializer).map(Self),
+            Curve::Bn254 => hex_to_bytes::<_, 32>(deserializer).map(|bytes| Self(padded(bytes))),
+        }
     }
 }
 
 impl Serialize for G2 {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        bytes_to_hex::<_, 96, 194>(serializer, self.0)
+        match curve() {
+            Curve::Bls12_381 => bytes_to_hex::<_, 96, 194>(serializer, self.0),
+            Curve::Bn254 => bytes_to_hex::<_, 64, 130>(serializer, truncated(self.0)),
+        }
     }
 }
 
 impl<'de> Deserialize<'de> for G2 {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        hex_to_bytes(deserializer).map(Self)
+        match curve() {
+            Curve::Bls12_381 => hex_to_bytes(deserializer).map(Self),
+            Curve::Bn254 => hex_to_bytes::<_, 64>(deserializer).map(|bytes| Self(padded(bytes))),
+        }
     }
 }
 
@@ -0,0 +1,123 @@
+//! Lagrange form of the powers of tau.
+
+#![cfg(feature = "arkworks")]
+
+use crate::{curve, CeremonyError, Curve, ParseError, G1};
+use ark_bls12_381::G1Affine;
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_poly::{domain::DomainCoeff, EvaluationDomain, Radix2EvaluationDomain};
+use rayon::prelude::*;
+use tracing::instrument;
+
+/// Converts the G1 powers `[τ^i]₁` into the Lagrange basis `[L_i(τ)]₁` over
+/// the roots of unity of order `powers.len()`.
+///
+/// The points are returned in bit-reversal permutation order, as in the
+/// EIP-4844 trusted setup.
+///
+/// # Errors
+///
+/// Returns an error if the number of powers is not a power of two or if a
+/// point can not be decoded.
+#[instrument(level = "info", skip_all, fields(n=powers.len()))]
+pub fn lagrange_g1(powers: &[G1]) -> Result<Vec<G1>, CeremonyError> {
+    match curve() {
+        Curve::Bls12_381 => lagrange::<G1Affine>(powers),
+        Curve::Bn254 => lagrange::<ark_bn254::G1Affine>(powers),
+    }
+}
+
+fn lagrange<C>(powers: &[G1]) -> Result<Vec<G1>, CeremonyError>
+where
+    C: AffineCurve + TryFrom<G1, Error = ParseError>,
+    C::Projective: DomainCoeff<C::ScalarField>,
+    G1: From<C>,
+{
+    let n = powers.len();
+    let domain = match Radix2EvaluationDomain::<C::ScalarField>::new(n) {
+        Some(domain) if n.is_power_of_two() => domain,
+        _ => return Err(CeremonyError::UnsupportedNumG1Powers(n)),
+    };
+    let mut points = powers
+        .par_iter()
+        .enumerate()
+        .map(|(i, point)| {
+            C::try_from(*point)
+                .map(|point| point.into_projective())
+                .map_err(|e| CeremonyError::InvalidG1Power(i, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // L_i(τ) = 1/n Σ_j ω^{-ij} τ^j, which is the inverse FFT of the powers.
+    domain.ifft_in_place(&mut points);
+    let mut points = C::Projective::batch_normalization_into_affine(&points)
+        .into_iter()
+        .map(G1::from)
+        .collect::<Vec<_>>();
+    bit_reverse(&mut points);
+    Ok(points)
+}
+
+fn bit_reverse<T>(values: &mut [T]) {
+    let bits = values.len().trailing_zeros();
+    if bits == 0 {
+        return;
+    }
+    for i in 0..values.len() {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arkworks, Engine, F};
+    use ark_bls12_381::{Fr, G1Projective};
+    use ark_ff::Field;
+    use secrecy::Secret;
+
+    fn to_projective(point: G1) -> G1Projective {
+        G1Affine::try_from(point).unwrap().into_projective()
+    }
+
+    #[test]
+    fn test_bit_reverse() {
+        let mut values = [0, 1, 2, 3, 4, 5, 6, 7];
+        bit_reverse(&mut values);
+        assert_eq!(values, [0, 4, 2, 6, 1, 5, 3, 7]);
+    }
+
+    #[test]
+    fn test_lagrange_g1() {
+        let tau = Secret::new(F::from(Fr::from(5_u64)));
+        let mut powers = vec![G1::one(); 4];
+        Arkworks::add_tau_g1(&tau, &mut powers).unwrap();
+        let lagrange = lagrange_g1(&powers).unwrap();
+        assert_eq!(lagrange.len(), 4);
+
+        // The constant polynomial 1 is 1 at every root of unity.
+        let sum: G1Projective = lagrange.iter().copied().map(to_projective).sum();
+        assert_eq!(sum, G1Projective::prime_subgroup_generator());
+
+        // The polynomial x is ω^i at ω^i.
+        let omega = Radix2EvaluationDomain::<Fr>::new(4).unwrap().group_gen;
+        let x: G1Projective = [0_u64, 2, 1, 3]
+            .iter()
+            .zip(&lagrange)
+            .map(|(i, point)| {
+                let mut point = to_projective(*point);
+                point *= omega.pow([*i]);
+                point
+            })
+            .sum();
+        assert_eq!(x, to_projective(powers[1]));
+
+        assert_eq!(
+            lagrange_g1(&powers[..3]),
+            Err(CeremonyError::UnsupportedNumG1Powers(3))
+        );
+    }
+}
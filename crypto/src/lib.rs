@@ -6,30 +6,39 @@
 mod batch_contribution;
 mod batch_transcript;
 mod contribution;
+mod curve;
 mod engine;
 mod error;
 mod group;
 mod hex_format;
+mod lagrange;
+mod points;
 mod powers;
 pub mod signature;
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
 mod transcript;
 
 pub use crate::{
-    batch_contribution::{get_pot_pubkeys, BatchContribution},
-    batch_transcript::BatchTranscript,
+    batch_contribution::{
+        derive_pot_pubkeys, get_pot_pubkeys, BatchContribution, MAX_ENTROPY_ATTESTATION_LENGTH,
+    },
+    batch_transcript::{BatchTranscript, VerifiedContribution},
     contribution::Contribution,
+    curve::{curve, select_curve, Curve},
     engine::{Engine, Entropy, Secret, Tau},
     error::{CeremoniesError, CeremonyError, ErrorCode, ParseError},
     group::{F, G1, G2},
+    points::{map_points_in, Point, Points},
     powers::Powers,
     signature::identity::Identity,
-    transcript::Transcript,
+    transcript::{Check, Transcript, VerifyTimings},
 };
 
 pub use crate::engine::Both;
 
 #[cfg(feature = "arkworks")]
-pub use crate::engine::Arkworks;
+pub use crate::{engine::Arkworks, lagrange::lagrange_g1};
 
 #[cfg(feature = "blst")]
 pub use crate::engine::BLST;
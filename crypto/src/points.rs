@@ -0,0 +1,394 @@
+//! Storage for the points of transcripts and contributions.
+//!
+//! Points are kept on the heap, or, once [`map_points_in`] was called, in
+//! files that are mapped into memory. Mapped points count towards the page
+//! cache instead of the memory of the process, so the OS can write them back
+//! and evict them when memory runs short, and a sequencer with a large
+//! transcript and a contribution under verification fits on a small host.
+//! The files are unnamed temporary files, deleted once the points are dropped
+//! or the process exits.
+
+use crate::{G1, G2};
+use memmap2::MmapMut;
+use once_cell::sync::OnceCell;
+use serde::{
+    de::{self, DeserializeOwned, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    marker::PhantomData,
+    mem::size_of,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    slice,
+};
+use tracing::warn;
+
+/// Points a mapping has room for at least, so that it is never empty.
+const MIN_CAPACITY: usize = 64;
+
+static MAP_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// Keeps the points deserialized or created with [`Points::new`] from now on
+/// in files in `dir`.
+///
+/// # Errors
+///
+/// Returns an error if no file can be created in `dir`, or if points are
+/// already mapped in another directory.
+pub fn map_points_in(dir: PathBuf) -> io::Result<()> {
+    tempfile::tempfile_in(&dir)?;
+    let current = MAP_DIR.get_or_init(|| dir.clone());
+    if *current != dir {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("points are already mapped in {}", current.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// A point stored as its encoding.
+///
+/// # Safety
+///
+/// The type must be `repr(transparent)` over a byte array, so that any
+/// `size_of::<Self>()` bytes are a valid value of it.
+pub unsafe trait Point: Copy + Serialize + DeserializeOwned {
+    fn as_bytes(&self) -> &[u8];
+}
+
+// SAFETY: `G1` is `repr(transparent)` over `[u8; 48]`.
+unsafe impl Point for G1 {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// SAFETY: `G2` is `repr(transparent)` over `[u8; 96]`.
+unsafe impl Point for G2 {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A list of points that derefs to a slice, on the heap or mapped from a
+/// file, see the [module documentation](self).
+pub struct Points<T: Point> {
+    storage: Storage<T>,
+}
+
+enum Storage<T> {
+    Heap(Vec<T>),
+    Mapped(Mapped<T>),
+}
+
+struct Mapped<T> {
+    /// Where copies of the points are mapped.
+    dir:    PathBuf,
+    file:   File,
+    map:    MmapMut,
+    len:    usize,
+    marker: PhantomData<T>,
+}
+
+impl<T: Point> Mapped<T> {
+    /// Maps `file`, which holds the first `len` points, with room for at
+    /// least `capacity`.
+    fn open(dir: PathBuf, file: File, len: usize, capacity: usize) -> io::Result<Self> {
+        let capacity = capacity.max(len).max(MIN_CAPACITY);
+        file.set_len(u64::try_from(capacity * size_of::<T>()).expect("usize fits in u64"))?;
+        // SAFETY: The file is unnamed, so nothing else changes or truncates
+        // it while it is mapped.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(Self {
+            dir,
+            file,
+            map,
+            len,
+            marker: PhantomData,
+        })
+    }
+
+    fn copy_of(dir: &Path, points: &[T]) -> io::Result<Self> {
+        let file = tempfile::tempfile_in(dir)?;
+        let mut mapped = Self::open(dir.to_path_buf(), file, 0, points.len())?;
+        mapped.len = points.len();
+        mapped.as_mut_slice().copy_from_slice(points);
+        Ok(mapped)
+    }
+
+    fn capacity(&self) -> usize {
+        self.map.len() / size_of::<T>()
+    }
+
+    fn as_slice(&self) -> &[T] {
+        // SAFETY: The map holds at least `len` points, a `Point` is valid for
+        // any bytes and has an alignment of one.
+        unsafe { slice::from_raw_parts(self.map.as_ptr().cast::<T>(), self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: As in `as_slice`, and the map is borrowed mutably.
+        unsafe { slice::from_raw_parts_mut(self.map.as_mut_ptr().cast::<T>(), self.len) }
+    }
+
+    fn push(&mut self, point: T) -> io::Result<()> {
+        if self.len == self.capacity() {
+            let grown = Self::open(
+                self.dir.clone(),
+                self.file.try_clone()?,
+                self.len,
+                self.len * 2,
+            )?;
+            *self = grown;
+        }
+        self.len += 1;
+        let last = self.len - 1;
+        self.as_mut_slice()[last] = point;
+        Ok(())
+    }
+}
+
+impl<T: Point> Points<T> {
+    /// Keeps `points` in a file if [`map_points_in`] was called, on the heap
+    /// otherwise. Unlike this, [`From<Vec<T>>`] always keeps them on the
+    /// heap.
+    #[must_use]
+    pub fn new(points: Vec<T>) -> Self {
+        match MAP_DIR.get() {
+            Some(dir) => Self::mapped_in(dir, points),
+            None => points.into(),
+        }
+    }
+
+    /// Keeps `points` in a file in `dir`, or on the heap if the file can not
+    /// be created.
+    #[must_use]
+    pub fn mapped_in(dir: &Path, points: Vec<T>) -> Self {
+        match Mapped::copy_of(dir, &points) {
+            Ok(mapped) => Self {
+                storage: Storage::Mapped(mapped),
+            },
+            Err(error) => {
+                warn!(?error, dir = %dir.display(), "failed to map points, keeping them in memory");
+                points.into()
+            }
+        }
+    }
+
+    /// Whether the points are kept in a file.
+    #[must_use]
+    pub const fn is_mapped(&self) -> bool {
+        matches!(self.storage, Storage::Mapped(_))
+    }
+
+    /// Appends `point`. Mapped points that can not grow their file move to
+    /// the heap.
+    pub fn push(&mut self, point: T) {
+        match &mut self.storage {
+            Storage::Heap(points) => points.push(point),
+            Storage::Mapped(mapped) => {
+                if let Err(error) = mapped.push(point) {
+                    warn!(
+                        ?error,
+                        "failed to grow mapped points, moving them to memory"
+                    );
+                    let mut points = mapped.as_slice().to_vec();
+                    points.push(point);
+                    self.storage = Storage::Heap(points);
+                }
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        match &mut self.storage {
+            Storage::Heap(points) => points.pop(),
+            Storage::Mapped(mapped) => {
+                let last = mapped.as_slice().last().copied()?;
+                mapped.len -= 1;
+                Some(last)
+            }
+        }
+    }
+
+    /// Replaces the points with `other`. Points of the same length are
+    /// copied, so that mapped points stay mapped.
+    pub fn assign(&mut self, other: Self) {
+        if self.is_mapped() && !other.is_mapped() && self.len() == other.len() {
+            self.copy_from_slice(&other);
+        } else {
+            *self = other;
+        }
+    }
+}
+
+impl<T: Point> Deref for Points<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match &self.storage {
+            Storage::Heap(points) => points,
+            Storage::Mapped(mapped) => mapped.as_slice(),
+        }
+    }
+}
+
+impl<T: Point> DerefMut for Points<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        match &mut self.storage {
+            Storage::Heap(points) => points,
+            Storage::Mapped(mapped) => mapped.as_mut_slice(),
+        }
+    }
+}
+
+impl<T: Point> From<Vec<T>> for Points<T> {
+    fn from(points: Vec<T>) -> Self {
+        Self {
+            storage: Storage::Heap(points),
+        }
+    }
+}
+
+impl<T: Point> FromIterator<T> for Points<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(points: I) -> Self {
+        points.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl<'a, T: Point> IntoIterator for &'a Points<T> {
+    type IntoIter = slice::Iter<'a, T>;
+    type Item = &'a T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Copies of mapped points are mapped too.
+impl<T: Point> Clone for Points<T> {
+    fn clone(&self) -> Self {
+        match &self.storage {
+            Storage::Heap(points) => points.clone().into(),
+            Storage::Mapped(mapped) => Self::mapped_in(&mapped.dir, mapped.as_slice().to_vec()),
+        }
+    }
+}
+
+impl<T: Point + PartialEq> PartialEq for Points<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Point + Eq> Eq for Points<T> {}
+
+impl<T: Point + fmt::Debug> fmt::Debug for Points<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Point> Serialize for Points<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Point> Deserialize<'de> for Points<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(PointsVisitor {
+            dir:    MAP_DIR.get().map(PathBuf::as_path),
+            marker: PhantomData,
+        })
+    }
+}
+
+/// Deserializes points into a file in `dir`, one at a time, or onto the heap
+/// without a `dir`.
+struct PointsVisitor<'a, T> {
+    dir:    Option<&'a Path>,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T: Point> Visitor<'de> for PointsVisitor<'_, T> {
+    type Value = Points<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a sequence of points")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let dir = if let Some(dir) = self.dir {
+            dir
+        } else {
+            let mut points = Vec::new();
+            while let Some(point) = seq.next_element()? {
+                points.push(point);
+            }
+            return Ok(points.into());
+        };
+        let file = tempfile::tempfile_in(dir).map_err(de::Error::custom)?;
+        let mut writer = BufWriter::new(file);
+        let mut len = 0;
+        while let Some(point) = seq.next_element::<T>()? {
+            writer
+                .write_all(point.as_bytes())
+                .map_err(de::Error::custom)?;
+            len += 1;
+        }
+        let file = writer
+            .into_inner()
+            .map_err(|error| de::Error::custom(error.into_error()))?;
+        let mapped = Mapped::open(dir.to_path_buf(), file, len, len).map_err(de::Error::custom)?;
+        Ok(Points {
+            storage: Storage::Mapped(mapped),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapped_points() {
+        let dir = tempfile::tempdir().unwrap();
+        let points = (0..100_u8).map(|i| G1([i; 48])).collect::<Vec<_>>();
+
+        let json = serde_json::to_vec(&points).unwrap();
+        let mut mapped = serde_json::Deserializer::from_slice(&json)
+            .deserialize_seq(PointsVisitor {
+                dir:    Some(dir.path()),
+                marker: PhantomData,
+            })
+            .unwrap();
+        assert!(mapped.is_mapped());
+        assert_eq!(&*mapped, &points[..]);
+
+        mapped[1] = G1::one();
+        mapped.push(G1::zero());
+        assert_eq!(mapped.len(), 101);
+        assert_eq!(mapped.pop(), Some(G1::zero()));
+        assert_eq!(mapped.pop(), Some(G1([99; 48])));
+        let copy = mapped.clone();
+        assert!(copy.is_mapped());
+        assert_eq!(copy, mapped);
+        assert_eq!(copy[1], G1::one());
+
+        mapped.assign(Points::from(points[..99].to_vec()));
+        assert!(mapped.is_mapped());
+        assert_eq!(&*mapped, &points[..99]);
+        mapped.assign(Points::from(points.clone()));
+        assert!(!mapped.is_mapped());
+
+        let empty = Points::<G2>::mapped_in(dir.path(), Vec::new());
+        assert!(empty.is_mapped());
+        assert!(empty.is_empty());
+    }
+}
@@ -1,45 +1,49 @@
-use super::{CeremonyError, G1, G2};
-use serde::{Deserialize, Serialize};
+use super::{CeremonyError, Points, G1, G2};
+use serde::{Deserialize, Serialize, Serializer};
 
-#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
-#[serde(try_from = "PowersJson", into = "PowersJson")]
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(try_from = "OwnedPowersJson")]
 pub struct Powers {
-    pub g1: Vec<G1>,
-    pub g2: Vec<G2>,
+    pub g1: Points<G1>,
+    pub g2: Points<G2>,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
-struct PowersJson {
+struct PowersJson<P> {
     num_g1_powers: usize,
     num_g2_powers: usize,
-    powers_of_tau: PowersOfTau,
+    powers_of_tau: P,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase", deny_unknown_fields)]
-struct PowersOfTau {
-    g1_powers: Vec<G1>,
-    g2_powers: Vec<G2>,
+struct PowersOfTau<G1s, G2s> {
+    g1_powers: G1s,
+    g2_powers: G2s,
 }
 
-impl From<Powers> for PowersJson {
-    fn from(powers: Powers) -> Self {
-        Self {
-            num_g1_powers: powers.g1.len(),
-            num_g2_powers: powers.g2.len(),
+type OwnedPowersJson = PowersJson<PowersOfTau<Points<G1>, Points<G2>>>;
+
+/// Serializes the points in place, mapped points are not copied.
+impl Serialize for Powers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PowersJson {
+            num_g1_powers: self.g1.len(),
+            num_g2_powers: self.g2.len(),
             powers_of_tau: PowersOfTau {
-                g1_powers: powers.g1,
-                g2_powers: powers.g2,
+                g1_powers: &*self.g1,
+                g2_powers: &*self.g2,
             },
         }
+        .serialize(serializer)
     }
 }
 
-impl TryFrom<PowersJson> for Powers {
+impl TryFrom<OwnedPowersJson> for Powers {
     type Error = CeremonyError;
 
-    fn try_from(value: PowersJson) -> Result<Self, Self::Error> {
+    fn try_from(value: OwnedPowersJson) -> Result<Self, Self::Error> {
         if value.powers_of_tau.g1_powers.len() != value.num_g1_powers {
             return Err(CeremonyError::InconsistentNumG1Powers(
                 value.num_g1_powers,
@@ -64,10 +68,16 @@ impl Powers {
     #[must_use]
     pub fn new(num_g1: usize, num_g2: usize) -> Self {
         Self {
-            g1: vec![G1::one(); num_g1],
-            g2: vec![G2::one(); num_g2],
+            g1: Points::new(vec![G1::one(); num_g1]),
+            g2: Points::new(vec![G2::one(); num_g2]),
         }
     }
+
+    /// Replaces the powers with `other`, see [`Points::assign`].
+    pub fn assign(&mut self, other: Self) {
+        self.g1.assign(other.g1);
+        self.g2.assign(other.g2);
+    }
 }
 
 #[cfg(test)]
@@ -5,8 +5,23 @@ use thiserror::Error;
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Identity {
     None,
-    Ethereum { address: [u8; 20] },
-    Github { id: u64, username: String },
+    Ethereum {
+        address: [u8; 20],
+    },
+    Github {
+        id:       u64,
+        username: String,
+    },
+    Discord {
+        id:       u64,
+        username: String,
+    },
+    /// An account of an OpenID Connect issuer. `subject` is the claim the
+    /// sequencer is configured to identify accounts by.
+    Oidc {
+        issuer:  String,
+        subject: String,
+    },
 }
 
 impl Identity {
@@ -36,7 +51,8 @@ impl Identity {
     pub fn nickname(&self) -> String {
         match self {
             Self::Ethereum { address } => format!("0x{}", hex::encode(address)),
-            Self::Github { username, .. } => username.to_string(),
+            Self::Github { username, .. } | Self::Discord { username, .. } => username.to_string(),
+            Self::Oidc { subject, .. } => subject.to_string(),
             Self::None => "<<unauthorized>>".to_string(),
         }
     }
@@ -46,6 +62,8 @@ impl Identity {
         match self {
             Self::Ethereum { .. } => "Ethereum",
             Self::Github { .. } => "Github",
+            Self::Discord { .. } => "Discord",
+            Self::Oidc { .. } => "OIDC",
             Self::None => "None",
         }
         .to_string()
@@ -64,6 +82,8 @@ pub enum IdentityError {
     InvalidEthereumAddress,
     #[error("Invalid Github ID")]
     InvalidGithubId,
+    #[error("Invalid Discord ID")]
+    InvalidDiscordId,
 }
 
 impl Display for Identity {
@@ -72,6 +92,8 @@ impl Display for Identity {
             Self::None => write!(f, ""),
             Self::Ethereum { address } => write!(f, "eth|0x{}", hex::encode(address)),
             Self::Github { id, username } => write!(f, "git|{id}|{username}"),
+            Self::Discord { id, username } => write!(f, "dsc|{id}|{username}"),
+            Self::Oidc { issuer, subject } => write!(f, "oidc|{issuer}|{subject}"),
         }
     }
 }
@@ -110,6 +132,33 @@ impl FromStr for Identity {
 
                 Ok(Self::Github { id, username })
             }
+            Some("dsc") => {
+                let id = parts.next().ok_or(IdentityError::MissingField)?;
+                let username = parts.next().ok_or(IdentityError::MissingField)?;
+                if parts.next().is_some() {
+                    return Err(IdentityError::TooManyFields);
+                }
+
+                let id = id.parse().map_err(|_| IdentityError::InvalidDiscordId)?;
+                let username = username.to_string();
+
+                Ok(Self::Discord { id, username })
+            }
+            Some("oidc") => {
+                let issuer = parts.next().ok_or(IdentityError::MissingField)?;
+                let subject = parts.next().ok_or(IdentityError::MissingField)?;
+                if parts.next().is_some() {
+                    return Err(IdentityError::TooManyFields);
+                }
+                if issuer.is_empty() || subject.is_empty() {
+                    return Err(IdentityError::MissingField);
+                }
+
+                Ok(Self::Oidc {
+                    issuer:  issuer.to_string(),
+                    subject: subject.to_string(),
+                })
+            }
             Some("") => {
                 if parts.next().is_some() {
                     return Err(IdentityError::TooManyFields);
@@ -197,6 +246,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_discord() {
+        let identity = Identity::Discord {
+            id:       80_351_110_224_678_912,
+            username: "username".to_string(),
+        };
+        assert_eq!(identity.to_string(), "dsc|80351110224678912|username");
+        assert_eq!(identity, "dsc|80351110224678912|username".parse().unwrap());
+        assert_eq!(identity.provider_name(), "Discord");
+        assert_eq!(identity.nickname(), "username");
+        assert_eq!(
+            "dsc|abc|username".parse::<Identity>().err().unwrap(),
+            IdentityError::InvalidDiscordId
+        );
+    }
+
+    #[test]
+    fn test_oidc() {
+        let identity = Identity::Oidc {
+            issuer:  "https://id.example.org/realms/dao".to_string(),
+            subject: "alice".to_string(),
+        };
+        assert_eq!(
+            identity.to_string(),
+            "oidc|https://id.example.org/realms/dao|alice"
+        );
+        assert_eq!(
+            identity,
+            "oidc|https://id.example.org/realms/dao|alice"
+                .parse()
+                .unwrap()
+        );
+        assert_eq!(identity.provider_name(), "OIDC");
+        assert_eq!(identity.nickname(), "alice");
+        assert_eq!(
+            "oidc|https://id.example.org|"
+                .parse::<Identity>()
+                .err()
+                .unwrap(),
+            IdentityError::MissingField
+        );
+    }
+
     #[test]
     fn test_invalid() {
         assert_eq!(
@@ -5,9 +5,11 @@
 pub mod identity;
 
 use crate::{
+    curve::{curve, Curve},
+    group::padded,
     hex_format::{bytes_to_hex, optional_hex_to_bytes},
     signature::identity::Identity,
-    BatchContribution, Engine, Tau, G1, G2,
+    BatchContribution, BatchTranscript, Engine, Tau, G1, G2,
 };
 use ethers_core::types::{
     transaction::eip712::{EIP712Domain, Eip712, Eip712Error, TypedData},
@@ -59,7 +61,12 @@ impl<'de> Deserialize<'de> for BlsSignature {
     where
         D: Deserializer<'de>,
     {
-        optional_hex_to_bytes::<_, 48>(deserializer).map(|bytes_opt| Self(bytes_opt.map(G1)))
+        match curve() {
+            Curve::Bls12_381 => optional_hex_to_bytes::<_, 48>(deserializer)
+                .map(|bytes_opt| Self(bytes_opt.map(G1))),
+            Curve::Bn254 => optional_hex_to_bytes::<_, 32>(deserializer)
+                .map(|bytes_opt| Self(bytes_opt.map(|bytes| G1(padded(bytes))))),
+        }
     }
 }
 
@@ -143,6 +150,25 @@ impl From<&BatchContribution> for ContributionTypedData {
     }
 }
 
+impl ContributionTypedData {
+    /// The data participant `index` of the transcript signed, reconstructed
+    /// from the witness.
+    #[must_use]
+    pub fn from_transcript(transcript: &BatchTranscript, index: usize) -> Self {
+        Self {
+            pot_pubkeys: transcript
+                .transcripts
+                .iter()
+                .map(|t| PubkeyTypedData {
+                    num_g1_powers: t.powers.g1.len(),
+                    num_g2_powers: t.powers.g2.len(),
+                    pot_pubkey:    t.witness.pubkeys[index],
+                })
+                .collect(),
+        }
+    }
+}
+
 impl From<ContributionTypedData> for TypedData {
     fn from(contrib: ContributionTypedData) -> Self {
         let json = json!({
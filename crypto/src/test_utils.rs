@@ -0,0 +1,195 @@
+//! Builders for valid and deliberately invalid contributions, to test
+//! verification without hand-crafting JSON. Enabled by the `test_utils`
+//! feature.
+//!
+//! ```
+//! # use kzg_ceremony_crypto::{
+//! #     test_utils::{ContributionBuilder, Defect},
+//! #     BatchTranscript, CeremoniesError, DefaultEngine, Identity,
+//! # };
+//! let mut transcript = BatchTranscript::new(&[(4, 2)]);
+//! let invalid = ContributionBuilder::from_transcript(&transcript)
+//!     .defect(Defect::ZeroTau)
+//!     .build::<DefaultEngine>();
+//! assert_eq!(
+//!     transcript.verify_add::<DefaultEngine>(invalid, Identity::None),
+//!     Err(CeremoniesError::InvalidCeremony(
+//!         0,
+//!         Defect::ZeroTau.expected_error()
+//!     ))
+//! );
+//! ```
+
+use crate::{
+    signature::identity::Identity, BatchContribution, BatchTranscript, CeremonyError, Engine,
+    Entropy, ParseError, G1, G2,
+};
+use hex_literal::hex;
+
+/// A point on the G1 curve that is not in the prime order subgroup.
+const NOT_IN_SUBGROUP_G1: G1 = G1(hex!("800000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000"));
+
+/// How a built contribution is invalid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Defect {
+    /// The first G1 power of tau is on the curve but not in the subgroup.
+    /// BLS12-381 only, every BN254 G1 point is in the group.
+    WrongSubgroup,
+    /// The last two G1 powers are swapped. Needs at least four G1 powers, so
+    /// that the pubkey check, which uses the second, still passes.
+    ShuffledPowers,
+    /// Tau is zero: all powers but the zeroth and the pubkey are the point
+    /// at infinity.
+    ZeroTau,
+    /// The pubkey is that of other entropy than the powers.
+    MismatchedPubkey,
+}
+
+impl Defect {
+    /// The error verifying a contribution with this defect fails with, in
+    /// the sub-ceremony that has it.
+    #[must_use]
+    pub const fn expected_error(self) -> CeremonyError {
+        match self {
+            Self::WrongSubgroup => CeremonyError::InvalidG1Power(1, ParseError::InvalidSubgroup),
+            Self::ShuffledPowers => CeremonyError::G1PairingFailed,
+            Self::ZeroTau => CeremonyError::ZeroPubkey,
+            Self::MismatchedPubkey => CeremonyError::PubKeyPairingFailed,
+        }
+    }
+}
+
+/// Builds a contribution on top of a start, as `/lobby/try_contribute` hands
+/// it out or [`BatchTranscript::contribution`] makes it.
+#[derive(Clone, Debug)]
+pub struct ContributionBuilder {
+    start:    BatchContribution,
+    entropy:  [u8; 32],
+    identity: Identity,
+    /// The defect and the index of the sub-ceremony that has it.
+    defect:   Option<(Defect, usize)>,
+}
+
+impl ContributionBuilder {
+    #[must_use]
+    pub const fn new(start: BatchContribution) -> Self {
+        Self {
+            start,
+            entropy: [1; 32],
+            identity: Identity::None,
+            defect: None,
+        }
+    }
+
+    #[must_use]
+    pub fn from_transcript(transcript: &BatchTranscript) -> Self {
+        Self::new(transcript.contribution())
+    }
+
+    /// Entropy the powers are derived from. Defaults to all ones.
+    #[must_use]
+    pub const fn entropy(mut self, entropy: [u8; 32]) -> Self {
+        self.entropy = entropy;
+        self
+    }
+
+    /// Identity the BLS signatures are made for. Defaults to none.
+    #[must_use]
+    pub fn identity(mut self, identity: Identity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Gives the first sub-ceremony `defect`.
+    #[must_use]
+    pub const fn defect(self, defect: Defect) -> Self {
+        self.defect_in(0, defect)
+    }
+
+    /// Gives sub-ceremony `ceremony` `defect`.
+    #[must_use]
+    pub const fn defect_in(mut self, ceremony: usize, defect: Defect) -> Self {
+        self.defect = Some((defect, ceremony));
+        self
+    }
+
+    /// The contribution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start is invalid, the sub-ceremony with the defect does
+    /// not exist, or it has too few powers for the defect.
+    #[must_use]
+    pub fn build<E: Engine>(self) -> BatchContribution {
+        let mut contribution = self.start.clone();
+        contribution
+            .add_entropy::<E>(&Entropy::new(self.entropy), &self.identity)
+            .expect("the start must be valid");
+        let (defect, ceremony) = match self.defect {
+            Some(defect) => defect,
+            None => return contribution,
+        };
+        let target = contribution
+            .contributions
+            .get_mut(ceremony)
+            .expect("the sub-ceremony must exist");
+        match defect {
+            Defect::WrongSubgroup => target.powers.g1[1] = NOT_IN_SUBGROUP_G1,
+            Defect::ShuffledPowers => {
+                let n = target.powers.g1.len();
+                assert!(n >= 4, "shuffling needs at least four G1 powers");
+                target.powers.g1.swap(n - 2, n - 1);
+            }
+            Defect::ZeroTau => {
+                target.powers.g1[1..].fill(G1::zero());
+                target.powers.g2[1..].fill(G2::zero());
+                target.pot_pubkey = G2::zero();
+            }
+            Defect::MismatchedPubkey => {
+                let mut other = self.start;
+                let mut entropy = self.entropy;
+                entropy[0] ^= 1;
+                other
+                    .add_entropy::<E>(&Entropy::new(entropy), &self.identity)
+                    .expect("the start must be valid");
+                target.pot_pubkey = other.contributions[ceremony].pot_pubkey;
+            }
+        }
+        contribution
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CeremoniesError, DefaultEngine};
+
+    #[test]
+    fn builds_contributions_with_defects() {
+        let transcript = BatchTranscript::new(&[(4, 2), (8, 3)]);
+        let valid = ContributionBuilder::from_transcript(&transcript).build::<DefaultEngine>();
+        transcript
+            .clone()
+            .verify_add::<DefaultEngine>(valid, Identity::None)
+            .unwrap();
+
+        for defect in [
+            Defect::WrongSubgroup,
+            Defect::ShuffledPowers,
+            Defect::ZeroTau,
+            Defect::MismatchedPubkey,
+        ] {
+            let invalid = ContributionBuilder::from_transcript(&transcript)
+                .entropy([7; 32])
+                .defect_in(1, defect)
+                .build::<DefaultEngine>();
+            assert_eq!(
+                transcript
+                    .clone()
+                    .verify_add::<DefaultEngine>(invalid, Identity::None),
+                Err(CeremoniesError::InvalidCeremony(1, defect.expected_error())),
+                "{defect:?}"
+            );
+        }
+    }
+}
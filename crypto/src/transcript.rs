@@ -1,8 +1,46 @@
-use super::{CeremonyError, Contribution, Powers, G1, G2};
-use crate::{engine::Engine, signature::BlsSignature};
+use super::{CeremonyError, Contribution, Points, Powers, G1, G2};
+use crate::{
+    engine::Engine,
+    signature::{identity::Identity, BlsSignature},
+};
+use rayon::{join, prelude::*};
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 use tracing::instrument;
 
+/// How long the checks of [`Transcript::verify_timed`] took. They run in
+/// parallel, so the durations overlap.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct VerifyTimings {
+    /// The point encoding and subgroup checks.
+    pub points:   Duration,
+    pub pairings: Duration,
+}
+
+impl VerifyTimings {
+    /// The longer of each check, for checks that ran in parallel.
+    #[must_use]
+    pub fn max(self, other: Self) -> Self {
+        Self {
+            points:   self.points.max(other.points),
+            pairings: self.pairings.max(other.pairings),
+        }
+    }
+}
+
+/// A check of [`Transcript::verify_observed`], reported when it is done.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Check {
+    /// The point encoding and subgroup checks.
+    Points,
+    Pairings,
+}
+
+fn timed<T>(check: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    (check(), start.elapsed())
+}
+
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Transcript {
     #[serde(flatten)]
@@ -14,10 +52,10 @@ pub struct Transcript {
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Witness {
     #[serde(rename = "runningProducts")]
-    pub products: Vec<G1>,
+    pub products: Points<G1>,
 
     #[serde(rename = "potPubkeys")]
-    pub pubkeys: Vec<G2>,
+    pub pubkeys: Points<G2>,
 
     #[serde(rename = "blsSignatures")]
     pub signatures: Vec<BlsSignature>,
@@ -38,8 +76,8 @@ impl Transcript {
         Self {
             powers:  Powers::new(num_g1, num_g2),
             witness: Witness {
-                products:   vec![G1::one()],
-                pubkeys:    vec![G2::one()],
+                products:   Points::new(vec![G1::one()]),
+                pubkeys:    Points::new(vec![G2::one()]),
                 signatures: vec![BlsSignature::empty()],
             },
         }
@@ -68,9 +106,85 @@ impl Transcript {
     }
 
     /// Verifies a contribution.
-    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
     pub fn verify<E: Engine>(&self, contribution: &Contribution) -> Result<(), CeremonyError> {
-        // Compatibility checks
+        self.verify_timed::<E>(contribution).map(|_| ())
+    }
+
+    /// Verifies a contribution like [`Self::verify`], and reports how long
+    /// the checks took.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contribution is invalid.
+    pub fn verify_timed<E: Engine>(
+        &self,
+        contribution: &Contribution,
+    ) -> Result<VerifyTimings, CeremonyError> {
+        self.verify_observed::<E>(contribution, &|_| ())
+    }
+
+    /// Verifies a contribution like [`Self::verify_timed`], and calls
+    /// `observe` with each check once it is done, passed or not.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contribution is invalid.
+    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
+    pub fn verify_observed<E: Engine>(
+        &self,
+        contribution: &Contribution,
+        observe: &(dyn Fn(Check) + Sync),
+    ) -> Result<VerifyTimings, CeremonyError> {
+        self.check_sizes(contribution)?;
+
+        // The point validation (encoding and subgroup checks) and the pairing
+        // checks are independent, so they run in parallel. Errors are still
+        // reported in the order of the checks, and the pairings are only
+        // looked at once the points are known to be in the subgroups.
+        let ((validation, points), (pairings, pairings_time)) = join(
+            || {
+                let result = timed(|| self.validate_points::<E>(contribution));
+                observe(Check::Points);
+                result
+            },
+            || {
+                let result = timed(|| self.verify_pairings::<E>(contribution));
+                observe(Check::Pairings);
+                result
+            },
+        );
+        validation?;
+
+        // Non-zero check
+        if contribution.pot_pubkey == G2::zero() {
+            return Err(CeremonyError::ZeroPubkey);
+        }
+
+        pairings?;
+
+        // Accept
+        Ok(VerifyTimings {
+            points,
+            pairings: pairings_time,
+        })
+    }
+
+    /// Checks only the structure of a contribution: the number of powers, the
+    /// point encodings and subgroup membership, and that the pubkey is not
+    /// zero. Unlike [`Self::verify`] this does not check that the powers
+    /// extend the transcript.
+    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
+    pub fn validate<E: Engine>(&self, contribution: &Contribution) -> Result<(), CeremonyError> {
+        self.check_sizes(contribution)?;
+        self.validate_points::<E>(contribution)?;
+        if contribution.pot_pubkey == G2::zero() {
+            return Err(CeremonyError::ZeroPubkey);
+        }
+        Ok(())
+    }
+
+    /// Checks that the contribution has as many powers as the transcript.
+    fn check_sizes(&self, contribution: &Contribution) -> Result<(), CeremonyError> {
         if self.powers.g1.len() != contribution.powers.g1.len() {
             return Err(CeremonyError::UnexpectedNumG1Powers(
                 self.powers.g1.len(),
@@ -83,40 +197,211 @@ impl Transcript {
                 contribution.powers.g2.len(),
             ));
         }
+        Ok(())
+    }
 
-        // Verify the contribution points (encoding and subgroup checks).
-        E::validate_g1(&contribution.powers.g1)?;
-        E::validate_g2(&contribution.powers.g2)?;
-        E::validate_g2(&[contribution.pot_pubkey])?;
+    /// Verifies the contribution points (encoding and subgroup checks).
+    fn validate_points<E: Engine>(&self, contribution: &Contribution) -> Result<(), CeremonyError> {
+        let (g1, g2) = join(
+            || E::validate_g1(&contribution.powers.g1),
+            || E::validate_g2(&contribution.powers.g2),
+        );
+        g1?;
+        g2?;
+        E::validate_g2(&[contribution.pot_pubkey])
+    }
 
-        // Non-zero check
-        if contribution.pot_pubkey == G2::zero() {
-            return Err(CeremonyError::ZeroPubkey);
+    /// Verifies the pubkey and the powers of tau using pairings. The points
+    /// are not subgroup checked here, so the result only counts if
+    /// [`Self::validate_points`] accepted the contribution.
+    fn verify_pairings<E: Engine>(&self, contribution: &Contribution) -> Result<(), CeremonyError> {
+        let (pubkey, (g1, g2)) = join(
+            || {
+                E::verify_pubkey(
+                    contribution.powers.g1[1],
+                    self.powers.g1[1],
+                    contribution.pot_pubkey,
+                )
+            },
+            || {
+                join(
+                    || E::verify_g1(&contribution.powers.g1, contribution.powers.g2[1]),
+                    || {
+                        E::verify_g2(
+                            &contribution.powers.g1[..contribution.powers.g2.len()],
+                            &contribution.powers.g2,
+                        )
+                    },
+                )
+            },
+        );
+        pubkey?;
+        g1?;
+        g2
+    }
+
+    /// Verifies the transcript from scratch: the points, that the powers are
+    /// powers of a single tau, and every link of the chain of contributions
+    /// recorded in the witness. BLS signatures are checked against
+    /// `participant_ids`, which must have an entry for every witness entry.
+    #[instrument(level = "info", skip_all, fields(n1=self.powers.g1.len(), n2=self.powers.g2.len()))]
+    pub(crate) fn verify_self<E: Engine>(
+        &self,
+        participant_ids: &[Identity],
+    ) -> Result<(), CeremonyError> {
+        self.verify_witness_shape()?;
+        let witness = &self.witness;
+        if witness.products.last() != self.powers.g1.get(1) {
+            return Err(CeremonyError::WitnessProductMismatch);
         }
 
-        // Verify pairings.
-        E::verify_pubkey(
-            contribution.powers.g1[1],
-            self.powers.g1[1],
-            contribution.pot_pubkey,
-        )?;
-        E::verify_g1(&contribution.powers.g1, contribution.powers.g2[1])?;
-        E::verify_g2(
-            &contribution.powers.g1[..contribution.powers.g2.len()],
-            &contribution.powers.g2,
-        )?;
+        let (points, (powers, chain)) = join(
+            || self.validate_all_points::<E>(),
+            || {
+                join(
+                    || self.verify_powers::<E>(),
+                    || self.verify_witness::<E>(participant_ids, 1, witness.pubkeys.len()),
+                )
+            },
+        );
+        points?;
+        powers?;
+        chain
+    }
 
-        // Accept
+    /// Verifies the links of the witness chain from the one of contribution
+    /// `from + 1` up to the one of contribution `up_to`, and their points,
+    /// without looking at the powers. The links before are trusted. If
+    /// `up_to` is the last contribution, the chain must also end at the
+    /// current powers. `participant_ids` must have an entry for every
+    /// witness entry up to `up_to`.
+    pub(crate) fn verify_witness_chain<E: Engine>(
+        &self,
+        participant_ids: &[Identity],
+        from: usize,
+        up_to: usize,
+    ) -> Result<(), CeremonyError> {
+        self.verify_witness_shape()?;
+        let witness = &self.witness;
+        if up_to >= witness.pubkeys.len() {
+            return Err(CeremonyError::WitnessLengthMismatch(
+                up_to + 1,
+                witness.pubkeys.len(),
+            ));
+        }
+        if up_to + 1 == witness.products.len() && witness.products.last() != self.powers.g1.get(1) {
+            return Err(CeremonyError::WitnessProductMismatch);
+        }
+        let from = from.min(up_to);
+        E::validate_g1(&witness.products[from..=up_to]).map_err(|e| match e {
+            CeremonyError::InvalidG1Power(i, e) => {
+                CeremonyError::InvalidWitnessProduct(from + i, e)
+            }
+            e => e,
+        })?;
+        E::validate_g2(&witness.pubkeys[from..=up_to]).map_err(|e| match e {
+            CeremonyError::InvalidG2Power(i, e) => CeremonyError::InvalidWitnessPubKey(from + i, e),
+            e => e,
+        })?;
+        self.verify_witness::<E>(participant_ids, from + 1, up_to + 1)
+    }
+
+    /// Verifies that the parts of the witness have the same length and start
+    /// at the generators.
+    fn verify_witness_shape(&self) -> Result<(), CeremonyError> {
+        let witness = &self.witness;
+        if witness.products.len() != witness.pubkeys.len() {
+            return Err(CeremonyError::WitnessLengthMismatch(
+                witness.products.len(),
+                witness.pubkeys.len(),
+            ));
+        }
+        if witness.pubkeys.len() != witness.signatures.len() {
+            return Err(CeremonyError::WitnessSignaturesMismatch(
+                witness.pubkeys.len(),
+                witness.signatures.len(),
+            ));
+        }
+        if witness.products.first() != Some(&G1::one())
+            || witness.pubkeys.first() != Some(&G2::one())
+        {
+            return Err(CeremonyError::InvalidWitnessGenerators);
+        }
         Ok(())
     }
 
+    /// Verifies the powers and the witness points (encoding and subgroup
+    /// checks).
+    fn validate_all_points<E: Engine>(&self) -> Result<(), CeremonyError> {
+        E::validate_g1(&self.powers.g1)?;
+        E::validate_g2(&self.powers.g2)?;
+        E::validate_g1(&self.witness.products).map_err(|e| match e {
+            CeremonyError::InvalidG1Power(i, e) => CeremonyError::InvalidWitnessProduct(i, e),
+            e => e,
+        })?;
+        E::validate_g2(&self.witness.pubkeys).map_err(|e| match e {
+            CeremonyError::InvalidG2Power(i, e) => CeremonyError::InvalidWitnessPubKey(i, e),
+            e => e,
+        })
+    }
+
+    /// Verifies that the powers start at the generators and are powers of the
+    /// same tau. The subgroup checks are left to
+    /// [`Self::validate_all_points`], which runs next to it and whose outcome
+    /// comes first.
+    fn verify_powers<E: Engine>(&self) -> Result<(), CeremonyError> {
+        let g1 = &self.powers.g1;
+        let g2 = &self.powers.g2;
+        if g1.first() != Some(&G1::one()) {
+            return Err(CeremonyError::InvalidG1FirstValue);
+        }
+        if g2.first() != Some(&G2::one()) {
+            return Err(CeremonyError::InvalidG2FirstValue);
+        }
+        let tau = *g2
+            .get(1)
+            .ok_or(CeremonyError::UnsupportedNumG2Powers(g2.len()))?;
+        if g1.len() < g2.len() {
+            return Err(CeremonyError::UnsupportedMoreG2Powers(g1.len(), g2.len()));
+        }
+        E::verify_g1(g1, tau)?;
+        E::verify_g2(&g1[..g2.len()], g2)
+    }
+
+    /// Verifies that each contribution in the witness from `start` to before
+    /// `end` builds on the previous one, and the BLS signatures of their
+    /// contributors.
+    fn verify_witness<E: Engine>(
+        &self,
+        participant_ids: &[Identity],
+        start: usize,
+        end: usize,
+    ) -> Result<(), CeremonyError> {
+        let witness = &self.witness;
+        (start..end).into_par_iter().try_for_each(|i| {
+            let pubkey = witness.pubkeys[i];
+            if pubkey == G2::zero() {
+                return Err(CeremonyError::ZeroPubkey);
+            }
+            E::verify_pubkey(witness.products[i], witness.products[i - 1], pubkey)
+                .map_err(|_| CeremonyError::WitnessPairingFailed(i))?;
+            if let Some(signature) = witness.signatures[i].0 {
+                let message = participant_ids[i].to_string();
+                if !E::verify_signature(signature, message.as_bytes(), pubkey) {
+                    return Err(CeremonyError::InvalidBlsSignature(i));
+                }
+            }
+            Ok(())
+        })
+    }
+
     /// Adds a contribution to the transcript. The contribution must be
     /// verified.
     pub fn add(&mut self, contribution: Contribution) {
         self.witness.products.push(contribution.powers.g1[1]);
         self.witness.pubkeys.push(contribution.pot_pubkey);
         self.witness.signatures.push(contribution.bls_signature);
-        self.powers = contribution.powers;
+        self.powers.assign(contribution.powers);
     }
 }
 
@@ -321,7 +606,7 @@ mod test {
     fn test_verify_wrong_g1_point_count() {
         let transcript = Transcript::new(3, 3);
         let mut contribution = transcript.contribution();
-        contribution.powers.g1 = contribution.powers.g1[0..2].to_vec();
+        contribution.powers.g1 = contribution.powers.g1[0..2].to_vec().into();
         let result = transcript
             .verify::<DefaultEngine>(&contribution)
             .err()
@@ -333,7 +618,7 @@ mod test {
     fn test_verify_wrong_g2_point_count() {
         let transcript = Transcript::new(3, 3);
         let mut contribution = transcript.contribution();
-        contribution.powers.g2 = contribution.powers.g2[0..2].to_vec();
+        contribution.powers.g2 = contribution.powers.g2[0..2].to_vec().into();
         let result = transcript
             .verify::<DefaultEngine>(&contribution)
             .err()
@@ -0,0 +1,121 @@
+//! Ceremonies on BN254. The curve is fixed for the whole process, so these
+//! tests have a test binary of their own.
+
+#![cfg(feature = "arkworks")]
+
+use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
+use ark_ec::{AffineCurve, ProjectiveCurve};
+use ark_ff::{One, Zero};
+use kzg_ceremony_crypto::{
+    lagrange_g1, select_curve, Arkworks, BatchTranscript, CeremonyError, Curve, DefaultEngine,
+    Engine, Identity, ParseError, Secret, G1, G2,
+};
+
+fn bn254() {
+    select_curve(Curve::Bn254).unwrap();
+}
+
+#[test]
+fn encodes_points() {
+    bn254();
+    assert_eq!(G1::from(G1Affine::prime_subgroup_generator()), G1::one());
+    assert_eq!(G2::from(G2Affine::prime_subgroup_generator()), G2::one());
+    assert_eq!(G1::from(G1Affine::zero()), G1::zero());
+    assert_eq!(G2::from(G2Affine::zero()), G2::zero());
+    assert_eq!(G1::one().encoded().len(), 32);
+    assert_eq!(G2::one().encoded().len(), 64);
+    assert_eq!(G1::from_encoded(G1::one().encoded()), Some(G1::one()));
+    assert_eq!(G1::from_encoded(&[0; 48]), None);
+
+    let json = serde_json::to_string(&G2::one()).unwrap();
+    assert_eq!(json.len(), 2 + 2 + 2 * 64);
+    assert_eq!(serde_json::from_str::<G2>(&json).unwrap(), G2::one());
+    let bls_json = format!("\"0x{}\"", "00".repeat(48));
+    assert!(serde_json::from_str::<G1>(&bls_json).is_err());
+}
+
+#[test]
+fn rejects_invalid_points() {
+    bn254();
+    let mut padded = G1::one();
+    padded.0[40] = 1;
+    assert_eq!(
+        Arkworks::validate_g1(&[padded]),
+        Err(CeremonyError::InvalidG1Power(
+            0,
+            ParseError::InvalidCompression
+        ))
+    );
+    let mut infinity = G1::one();
+    infinity.0[31] |= 0x40;
+    assert_eq!(
+        Arkworks::validate_g1(&[infinity]),
+        Err(CeremonyError::InvalidG1Power(
+            0,
+            ParseError::InvalidInfinity
+        ))
+    );
+
+    // Most points on the twist are not in G2.
+    let mut x = Fq::one();
+    let outside = loop {
+        if let Some(p) = G2Affine::get_point_from_x(Fq2::new(x, Fq::zero()), false) {
+            if !p.is_in_correct_subgroup_assuming_on_curve() {
+                break G2::from(p);
+            }
+        }
+        x += Fq::one();
+    };
+    assert_eq!(
+        DefaultEngine::validate_g2(&[G2::one(), outside]),
+        Err(CeremonyError::InvalidG2Power(
+            1,
+            ParseError::InvalidSubgroup
+        ))
+    );
+}
+
+#[test]
+fn runs_ceremony() {
+    bn254();
+    assert!(DefaultEngine::supports(Curve::Bn254));
+    let mut transcript = BatchTranscript::new(&[(8, 3), (4, 2)]);
+    let identity = Identity::Github {
+        id:       1234,
+        username: "test_user".to_string(),
+    };
+    let mut contribution = transcript.contribution();
+    contribution
+        .add_entropy::<DefaultEngine>(&Secret::new([7; 32]), &identity)
+        .unwrap();
+    transcript
+        .verify_add::<DefaultEngine>(contribution, identity)
+        .unwrap();
+    transcript.verify_self::<DefaultEngine>().unwrap();
+    // There are no BLS signatures on BN254.
+    assert_eq!(transcript.transcripts[0].witness.signatures[1].0, None);
+
+    let mut shuffled = transcript.contribution();
+    shuffled
+        .add_entropy::<DefaultEngine>(&Secret::new([8; 32]), &Identity::None)
+        .unwrap();
+    shuffled.contributions[0].powers.g1.swap(6, 7);
+    assert!(transcript
+        .clone()
+        .verify_add::<DefaultEngine>(shuffled, Identity::None)
+        .is_err());
+
+    let json = serde_json::to_string(&transcript).unwrap();
+    assert!(json.contains(&format!("\"0x01{}\"", "00".repeat(31))));
+    let read = serde_json::from_str::<BatchTranscript>(&json).unwrap();
+    assert_eq!(read, transcript);
+    read.verify_self::<DefaultEngine>().unwrap();
+
+    // The Lagrange basis sums to the generator.
+    let lagrange = lagrange_g1(&transcript.transcripts[0].powers.g1).unwrap();
+    let sum = lagrange
+        .iter()
+        .map(|point| G1Affine::try_from(*point).unwrap().into_projective())
+        .sum::<<G1Affine as AffineCurve>::Projective>();
+    assert_eq!(G1::from(sum.into_affine()), G1::one());
+}
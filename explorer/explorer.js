@@ -0,0 +1,126 @@
+'use strict';
+
+// The explorer is served at `<ceremony>/explorer/`, the API one level up.
+const api = (path) => new URL(`../${path}`, window.location.href);
+
+const RECENT_CONTRIBUTIONS = 20;
+
+const $ = (id) => document.getElementById(id);
+
+async function getJson(path) {
+  const response = await fetch(api(path));
+  if (!response.ok) {
+    const body = await response.json().catch(() => ({}));
+    throw new Error(body.error || `${response.status} ${response.statusText}`);
+  }
+  return response.json();
+}
+
+async function refreshStatus() {
+  const status = await getJson('info/status');
+  $('lobby-size').textContent = status.lobby_size;
+  $('num-contributions').textContent = status.num_contributions;
+  $('sequencer-address').textContent = status.sequencer_address;
+}
+
+async function refreshContributions() {
+  const page = await getJson(`info/contributions?limit=${RECENT_CONTRIBUTIONS}`);
+  $('num-contributions').textContent = page.num_contributions;
+  const rows = page.contributions.reverse().map((contribution) => {
+    const row = document.createElement('tr');
+    for (const value of [contribution.index, contribution.participant]) {
+      const cell = document.createElement('td');
+      cell.textContent = value;
+      row.appendChild(cell);
+    }
+    return row;
+  });
+  $('contributions').replaceChildren(...rows);
+}
+
+function handleEvent(event) {
+  switch (event.event) {
+    case 'lobby_size':
+      $('lobby-size').textContent = event.lobby_size;
+      break;
+    case 'contribution_started':
+      $('slot').textContent = 'contributing';
+      break;
+    case 'slot_opened':
+      $('slot').textContent = 'open';
+      break;
+    case 'contribution_verified':
+      $('num-contributions').textContent = event.num_contributions;
+      refreshContributions().catch(console.error);
+      break;
+    default:
+      break;
+  }
+}
+
+function subscribe() {
+  const url = api('ws/lobby');
+  url.protocol = url.protocol === 'https:' ? 'wss:' : 'ws:';
+  const socket = new WebSocket(url);
+  socket.onopen = () => {
+    $('connection').textContent = 'Live';
+  };
+  socket.onmessage = (message) => handleEvent(JSON.parse(message.data));
+  socket.onclose = () => {
+    $('connection').textContent = 'Disconnected, reconnecting…';
+    setTimeout(subscribe, 5000);
+  };
+}
+
+function showResult(message, ok) {
+  const result = $('result');
+  result.textContent = message;
+  result.className = ok ? 'ok' : 'error';
+}
+
+// Checks that the transcript holds the receipt's witness, for the receipt's
+// participant, at the receipt's position.
+async function verifyReceipt(text) {
+  const receipt = JSON.parse(text);
+  const index = receipt.contribution_index;
+  const page = await getJson(`info/contributions?start=${index}&limit=1`);
+  const entry = page.contributions[0];
+  if (!entry || entry.index !== index) {
+    throw new Error(`the transcript has no contribution ${index}`);
+  }
+  if (entry.participant !== receipt.identity) {
+    throw new Error(`contribution ${index} is by ${entry.participant}`);
+  }
+  const matches = receipt.witness.length === entry.pot_pubkeys.length
+    && receipt.witness.every((pubkey, i) => pubkey === entry.pot_pubkeys[i]);
+  if (!matches) {
+    throw new Error(`the witness does not match contribution ${index}`);
+  }
+  return index;
+}
+
+$('lookup').addEventListener('submit', async (event) => {
+  event.preventDefault();
+  try {
+    const uid = encodeURIComponent($('uid').value.trim());
+    const stored = await getJson(`contribution/receipt/${uid}`);
+    $('receipt').value = stored.receipt;
+    showResult('Receipt fetched, verify it against the transcript.', true);
+  } catch (error) {
+    showResult(`Could not fetch the receipt: ${error.message}`, false);
+  }
+});
+
+$('verify').addEventListener('submit', async (event) => {
+  event.preventDefault();
+  try {
+    const index = await verifyReceipt($('receipt').value);
+    showResult(`Contribution ${index} is part of the transcript.`, true);
+  } catch (error) {
+    showResult(`Verification failed: ${error.message}`, false);
+  }
+});
+
+refreshStatus().catch(console.error);
+refreshContributions().catch(console.error);
+subscribe();
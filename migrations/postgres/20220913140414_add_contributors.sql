@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS contributors (
+    uid           TEXT        PRIMARY KEY NOT NULL,
+    started_at    TIMESTAMPTZ             NOT NULL,
+    finished_at   TIMESTAMPTZ,
+    expired_at    TIMESTAMPTZ
+);
\ No newline at end of file
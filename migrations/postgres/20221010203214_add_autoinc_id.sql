@@ -0,0 +1,3 @@
+ALTER TABLE contributors DROP CONSTRAINT contributors_pkey;
+
+ALTER TABLE contributors ADD COLUMN id BIGSERIAL PRIMARY KEY;
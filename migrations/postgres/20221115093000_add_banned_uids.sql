@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS banned_uids (
+    uid        TEXT         PRIMARY KEY NOT NULL,
+    banned_at  TIMESTAMPTZ              NOT NULL
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS receipts (
+    position   BIGINT       PRIMARY KEY NOT NULL,
+    uid        TEXT         NOT NULL,
+    receipt    TEXT         NOT NULL,
+    signature  TEXT         NOT NULL,
+    created_at TIMESTAMPTZ  NOT NULL
+);
+CREATE INDEX IF NOT EXISTS receipts_uid ON receipts (uid);
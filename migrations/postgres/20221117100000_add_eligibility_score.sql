@@ -0,0 +1 @@
+ALTER TABLE contributors ADD COLUMN eligibility_score INTEGER;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+    id          BIGSERIAL    PRIMARY KEY,
+    created_at  TIMESTAMPTZ  NOT NULL,
+    action      TEXT         NOT NULL,
+    uid         TEXT,
+    ip          TEXT,
+    outcome     TEXT         NOT NULL
+);
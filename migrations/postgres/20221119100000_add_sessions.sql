@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS sessions (
+    token_hash        TEXT         PRIMARY KEY NOT NULL,
+    uid               TEXT         NOT NULL,
+    expires_at        TIMESTAMPTZ  NOT NULL,
+    lobby_entered_at  TIMESTAMPTZ,
+    eligibility_score INTEGER
+);
+CREATE INDEX IF NOT EXISTS sessions_uid ON sessions (uid);
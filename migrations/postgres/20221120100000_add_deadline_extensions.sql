@@ -0,0 +1 @@
+ALTER TABLE contributors ADD COLUMN deadline_extensions INTEGER NOT NULL DEFAULT 0;
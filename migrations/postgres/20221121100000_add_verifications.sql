@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS verifications (
+    payload_hash    TEXT         PRIMARY KEY NOT NULL,
+    uid             TEXT         NOT NULL,
+    verification_id TEXT         NOT NULL,
+    status          TEXT,
+    created_at      TIMESTAMPTZ  NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS verifications_id ON verifications (verification_id);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS auth_nonces (
+    nonce      TEXT         PRIMARY KEY NOT NULL,
+    expires_at TIMESTAMPTZ  NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE transcript_entries ADD COLUMN entropy_attestation TEXT;
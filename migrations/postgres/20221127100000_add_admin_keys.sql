@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS admin_keys (
+    name       TEXT         PRIMARY KEY NOT NULL,
+    key_hash   TEXT         UNIQUE      NOT NULL,
+    role       TEXT                     NOT NULL,
+    created_at TIMESTAMPTZ              NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS lobby_waits (
+    id           BIGSERIAL    PRIMARY KEY,
+    session_hash TEXT         NOT NULL,
+    provider     TEXT         NOT NULL,
+    joined_at    TIMESTAMPTZ  NOT NULL,
+    polls        BIGINT       NOT NULL,
+    outcome      TEXT,
+    finished_at  TIMESTAMPTZ
+);
+CREATE INDEX IF NOT EXISTS lobby_waits_session_hash ON lobby_waits (session_hash);
@@ -0,0 +1 @@
+ALTER TABLE contributors ADD COLUMN client_version TEXT;
@@ -0,0 +1 @@
+ALTER TABLE contributors ADD COLUMN ens_name TEXT;
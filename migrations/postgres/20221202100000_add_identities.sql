@@ -0,0 +1,45 @@
+CREATE TABLE IF NOT EXISTS identities (
+    id                BIGSERIAL    PRIMARY KEY,
+    provider          TEXT         NOT NULL,
+    provider_id       TEXT         NOT NULL,
+    handle            TEXT,
+    created_at        TIMESTAMPTZ  NOT NULL,
+    eligibility_score INTEGER,
+    UNIQUE (provider, provider_id)
+);
+
+ALTER TABLE contributors ADD COLUMN identity_id BIGINT REFERENCES identities (id);
+CREATE INDEX IF NOT EXISTS contributors_identity_id ON contributors (identity_id);
+
+-- Backfills the identities of the existing uids, `<provider>|<id>|<handle>`
+-- for git and dsc, `eth|<address>` and `oidc|<issuer>|<subject>`, with the
+-- handle and score of their latest attempt. Redacted uids have none.
+INSERT INTO identities (provider, provider_id, handle, created_at, eligibility_score)
+SELECT split_part(uid, '|', 1),
+       CASE split_part(uid, '|', 1)
+           WHEN 'oidc' THEN split_part(uid, '|', 2) || '|' || split_part(uid, '|', 3)
+           ELSE split_part(uid, '|', 2)
+       END,
+       CASE split_part(uid, '|', 1)
+           WHEN 'eth' THEN split_part(uid, '|', 2)
+           ELSE split_part(uid, '|', 3)
+       END,
+       started_at,
+       eligibility_score
+FROM contributors
+WHERE split_part(uid, '|', 1) IN ('eth', 'git', 'dsc', 'oidc')
+  AND split_part(uid, '|', 2) <> 'redacted'
+ORDER BY started_at DESC
+ON CONFLICT (provider, provider_id) DO NOTHING;
+
+UPDATE contributors SET identity_id = identities.id
+FROM identities
+WHERE identities.provider = split_part(contributors.uid, '|', 1)
+  AND identities.provider_id = CASE split_part(contributors.uid, '|', 1)
+      WHEN 'oidc' THEN split_part(contributors.uid, '|', 2) || '|' || split_part(contributors.uid, '|', 3)
+      ELSE split_part(contributors.uid, '|', 2)
+  END;
+
+UPDATE identities SET created_at = (
+    SELECT MIN(started_at) FROM contributors WHERE identity_id = identities.id
+);
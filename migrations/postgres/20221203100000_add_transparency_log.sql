@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS transparency_log (
+    leaf_index BIGINT  PRIMARY KEY NOT NULL,
+    entry      TEXT                NOT NULL
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS contribution_timings (
+    id                  BIGSERIAL    PRIMARY KEY,
+    uid                 TEXT         NOT NULL,
+    contribution_index  BIGINT       NOT NULL,
+    receive_us          BIGINT       NOT NULL,
+    deserialize_us      BIGINT       NOT NULL,
+    subgroup_check_us   BIGINT       NOT NULL,
+    pairing_us          BIGINT       NOT NULL,
+    transcript_write_us BIGINT       NOT NULL,
+    respond_us          BIGINT       NOT NULL,
+    recorded_at         TIMESTAMPTZ  NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE contributors ADD COLUMN attempts INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE contributors ADD COLUMN requeued_at TIMESTAMPTZ;
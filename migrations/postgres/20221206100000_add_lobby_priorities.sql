@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS lobby_priorities (
+    uid        TEXT         PRIMARY KEY NOT NULL,
+    priority   BIGINT                   NOT NULL,
+    updated_at TIMESTAMPTZ              NOT NULL
+);
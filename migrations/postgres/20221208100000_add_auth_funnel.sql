@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS auth_funnel (
+    provider TEXT   NOT NULL,
+    stage    TEXT   NOT NULL,
+    rule     TEXT   NOT NULL,
+    count    BIGINT NOT NULL,
+    PRIMARY KEY (provider, stage, rule)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE contributors ADD COLUMN fingerprint_hash TEXT;
+ALTER TABLE contributors ADD COLUMN subnet TEXT;
@@ -0,0 +1,4 @@
+-- Contributions finished before have none.
+ALTER TABLE contributors ADD COLUMN contribution_index BIGINT;
+CREATE UNIQUE INDEX IF NOT EXISTS contributors_finished_contribution_index
+    ON contributors (contribution_index) WHERE finished_at IS NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE contributors ADD COLUMN rejection_reason TEXT;
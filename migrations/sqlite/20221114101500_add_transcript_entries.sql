@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS transcript_entries (
+    position        INTEGER  PRIMARY KEY NOT NULL,
+    participant_id  TEXT     NOT NULL,
+    ecdsa_signature TEXT     NOT NULL,
+    witness         TEXT     NOT NULL,
+    powers          TEXT,
+    created_at      INTEGER  NOT NULL
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+    id          INTEGER  PRIMARY KEY AUTOINCREMENT,
+    created_at  INTEGER  NOT NULL,
+    action      TEXT     NOT NULL,
+    uid         TEXT,
+    ip          TEXT,
+    outcome     TEXT     NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS waiting_room (
+    uid          TEXT     PRIMARY KEY NOT NULL,
+    joined_at    INTEGER  NOT NULL,
+    email        TEXT,
+    announced_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS waiting_room_joined_at ON waiting_room (joined_at);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS jobs (
+    kind       TEXT     NOT NULL,
+    key        TEXT     NOT NULL,
+    payload    TEXT     NOT NULL,
+    run_at     INTEGER  NOT NULL,
+    attempts   INTEGER  NOT NULL DEFAULT 0,
+    last_error TEXT,
+    PRIMARY KEY (kind, key)
+);
+CREATE INDEX IF NOT EXISTS jobs_run_at ON jobs (run_at);
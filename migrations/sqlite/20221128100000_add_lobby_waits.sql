@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS lobby_waits (
+    id           INTEGER  PRIMARY KEY AUTOINCREMENT,
+    session_hash TEXT     NOT NULL,
+    provider     TEXT     NOT NULL,
+    joined_at    INTEGER  NOT NULL,
+    polls        INTEGER  NOT NULL,
+    outcome      TEXT,
+    finished_at  INTEGER
+);
+CREATE INDEX IF NOT EXISTS lobby_waits_session_hash ON lobby_waits (session_hash);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS ceremony_state (
+    id                 INTEGER  PRIMARY KEY CHECK (id = 1),
+    slot_holder        TEXT,
+    slot_expires_at    INTEGER,
+    contribution_index INTEGER  NOT NULL DEFAULT 0
+);
+INSERT INTO ceremony_state (id) VALUES (1) ON CONFLICT (id) DO NOTHING;
@@ -0,0 +1,47 @@
+CREATE TABLE IF NOT EXISTS identities (
+    id                INTEGER  PRIMARY KEY AUTOINCREMENT,
+    provider          TEXT     NOT NULL,
+    provider_id       TEXT     NOT NULL,
+    handle            TEXT,
+    created_at        INTEGER  NOT NULL,
+    eligibility_score INTEGER,
+    UNIQUE (provider, provider_id)
+);
+
+ALTER TABLE contributors ADD COLUMN identity_id INTEGER REFERENCES identities (id);
+CREATE INDEX IF NOT EXISTS contributors_identity_id ON contributors (identity_id);
+
+-- Backfills the identities of the existing uids, `<provider>|<id>|<handle>`
+-- for git and dsc, `eth|<address>` and `oidc|<issuer>|<subject>`, with the
+-- handle and score of their latest attempt. Redacted uids have none.
+INSERT INTO identities (provider, provider_id, handle, created_at, eligibility_score)
+SELECT provider,
+       CASE WHEN provider IN ('git', 'dsc') THEN substr(rest, 1, instr(rest, '|') - 1) ELSE rest END,
+       CASE WHEN provider = 'eth' THEN rest ELSE substr(rest, instr(rest, '|') + 1) END,
+       started_at,
+       eligibility_score
+FROM (
+    SELECT substr(uid, 1, instr(uid, '|') - 1) AS provider,
+           substr(uid, instr(uid, '|') + 1)    AS rest,
+           started_at,
+           eligibility_score
+    FROM contributors
+) AS parsed
+WHERE provider IN ('eth', 'git', 'dsc', 'oidc') AND rest NOT LIKE 'redacted|%'
+ORDER BY started_at DESC
+ON CONFLICT (provider, provider_id) DO NOTHING;
+
+UPDATE contributors SET identity_id = (
+    SELECT id FROM identities
+    WHERE provider = substr(contributors.uid, 1, instr(contributors.uid, '|') - 1)
+      AND provider_id = CASE
+          WHEN provider IN ('git', 'dsc') THEN substr(
+              substr(contributors.uid, instr(contributors.uid, '|') + 1), 1,
+              instr(substr(contributors.uid, instr(contributors.uid, '|') + 1), '|') - 1)
+          ELSE substr(contributors.uid, instr(contributors.uid, '|') + 1)
+      END
+);
+
+UPDATE identities SET created_at = (
+    SELECT MIN(started_at) FROM contributors WHERE identity_id = identities.id
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS contribution_timings (
+    id                  INTEGER  PRIMARY KEY AUTOINCREMENT,
+    uid                 TEXT     NOT NULL,
+    contribution_index  INTEGER  NOT NULL,
+    receive_us          INTEGER  NOT NULL,
+    deserialize_us      INTEGER  NOT NULL,
+    subgroup_check_us   INTEGER  NOT NULL,
+    pairing_us          INTEGER  NOT NULL,
+    transcript_write_us INTEGER  NOT NULL,
+    respond_us          INTEGER  NOT NULL,
+    recorded_at         INTEGER  NOT NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS lobby_priorities (
+    uid        TEXT     PRIMARY KEY NOT NULL,
+    priority   INTEGER              NOT NULL,
+    updated_at INTEGER              NOT NULL
+);
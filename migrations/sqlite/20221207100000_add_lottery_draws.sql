@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS lottery_draws (
+    epoch        INTEGER PRIMARY KEY NOT NULL,
+    block_number INTEGER             NOT NULL,
+    block_hash   TEXT                NOT NULL,
+    draw         TEXT                NOT NULL,
+    drawn_at     INTEGER             NOT NULL
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS lobby_notifications (
+    token_hash TEXT     PRIMARY KEY NOT NULL,
+    uid        TEXT     NOT NULL,
+    channel    TEXT     NOT NULL,
+    target     TEXT     NOT NULL,
+    status     TEXT     NOT NULL,
+    attempts   INTEGER  NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at INTEGER  NOT NULL,
+    sent_at    INTEGER
+);
+CREATE INDEX IF NOT EXISTS lobby_notifications_status ON lobby_notifications (status);
@@ -0,0 +1,8 @@
+-- Uids name the account, `<provider>|<provider id>#<checksum>`, see
+-- src/identity.rs. Identities backfilled from legacy uids get theirs when the
+-- account signs in or contributes next.
+ALTER TABLE identities ADD COLUMN uid TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS identities_uid ON identities (uid);
+
+-- The participant id the session signed in with, as uids leave out usernames.
+ALTER TABLE sessions ADD COLUMN identity TEXT;
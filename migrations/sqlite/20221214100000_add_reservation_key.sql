@@ -0,0 +1,3 @@
+-- Hex encoded HMAC key of the reservation tokens, shared by all sequencers
+-- using the database, see src/reservation.rs. Generated by the first one.
+ALTER TABLE ceremony_state ADD COLUMN reservation_key TEXT;
@@ -0,0 +1,185 @@
+use crate::{
+    audit::{outcome, Audit, AuditAction},
+    eligibility::SharedScorer,
+    lobby::{LobbyPosition, SessionPlace, SharedLobbyState},
+    metrics::CONTRIBUTIONS_EXPIRED,
+    oauth::SharedAuthState,
+    storage::{PersistentStorage, StorageError, StoredAttempt, StoredReceipt},
+    webhook::WebhookEvent,
+    SessionId,
+};
+use axum::{Extension, Json};
+use kzg_ceremony_crypto::{signature::identity::Identity, ErrorCode};
+use serde::Serialize;
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::task::JoinError;
+use tracing::warn;
+use utoipa::ToSchema;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum AccountError {
+    #[error("unknown session id")]
+    UnknownSessionId,
+    #[error("the contribution is being verified")]
+    ContributionInProgress,
+    #[error("storage error: {0}")]
+    StorageError(#[from] StorageError),
+    #[error("background task error: {0}")]
+    TaskError(#[from] JoinError),
+}
+
+impl ErrorCode for AccountError {
+    fn to_error_code(&self) -> String {
+        format!("AccountError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EligibilityStatus {
+    /// `None` for sessions from before a restart.
+    score:        Option<u32>,
+    min_score:    u32,
+    /// Rules that awarded no points or denied the participant, `None` for
+    /// sessions from before a restart.
+    failed_rules: Option<Vec<&'static str>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LobbyStatus {
+    place:    SessionPlace,
+    /// `None` outside of the lobby, or if the lobby of every replica can not
+    /// be read.
+    position: Option<LobbyPosition>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MeResponse {
+    uid:         String,
+    /// E.g. `git|1234|alice` or `eth|0x…`.
+    #[schema(value_type = String)]
+    identity:    Identity,
+    eligibility: EligibilityStatus,
+    lobby:       LobbyStatus,
+    /// Oldest first.
+    attempts:    Vec<StoredAttempt>,
+    /// Of the latest finished contribution.
+    receipt:     Option<StoredReceipt>,
+}
+
+/// Everything about the session of the participant, for their status page.
+#[utoipa::path(
+    get,
+    path = "/me",
+    tag = "account",
+    responses(
+        (status = 200, description = "Eligibility, place in the lobby, attempts and receipt", body = MeResponse),
+        (status = 401, description = "Unknown session", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+pub async fn me(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(scorer): Extension<SharedScorer>,
+) -> Result<Json<MeResponse>, AccountError> {
+    let (info, place) = lobby_state
+        .session_place(&session_id)
+        .await
+        .ok_or(AccountError::UnknownSessionId)?;
+    let uid = info.token.unique_identifier();
+    let position = match place {
+        SessionPlace::SignedIn => None,
+        _ => lobby_state.lobby_position(&session_id).await.ok(),
+    };
+    Ok(Json(MeResponse {
+        eligibility: EligibilityStatus {
+            score:        info.eligibility_score,
+            min_score:    scorer.current().min_score(),
+            failed_rules: info.failed_rules,
+        },
+        lobby: LobbyStatus { place, position },
+        attempts: storage.contribution_attempts(&uid).await?,
+        receipt: storage.get_receipt(&uid).await?,
+        identity: info.token.identity,
+        uid,
+    }))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeleteAccountResponse {
+    /// The uid the contributor is now recorded as.
+    uid:              String,
+    removed_sessions: usize,
+    kicked:           bool,
+}
+
+/// Forgets the identity of the participant: signs them out everywhere, gives
+/// up their slot and replaces their uid by a pseudonym in the database, see
+/// [`PersistentStorage::redact_contributor`]. Their entry in the transcript
+/// stays as it is.
+#[utoipa::path(
+    delete,
+    path = "/me",
+    tag = "account",
+    responses(
+        (status = 200, description = "The identity is forgotten", body = DeleteAccountResponse),
+        (status = 401, description = "Unknown session", body = ErrorBody),
+        (status = 404, description = "No `--pseudonym-key` configured", body = ErrorBody),
+        (status = 409, description = "The contribution is being verified", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+pub async fn delete_account(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(auth_state): Extension<SharedAuthState>,
+    Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<DeleteAccountResponse>, AccountError> {
+    let (uid, contributing) = lobby_state
+        .session_uid(&session_id)
+        .await
+        .ok_or(AccountError::UnknownSessionId)?;
+    if contributing {
+        return Err(AccountError::ContributionInProgress);
+    }
+    // Like the admin ban, in the background so that request cancelation
+    // doesn't leave the account half deleted.
+    tokio::spawn(async move {
+        let result = async {
+            let (removed_sessions, kicked) = lobby_state.remove_uid(&uid).await;
+            auth_state.write().await.unique_id_session.remove(&uid);
+            if kicked {
+                storage.expire_contribution(&uid).await?;
+            }
+            let pseudonym = storage.redact_contributor(&uid).await?;
+            if kicked {
+                CONTRIBUTIONS_EXPIRED.with_label_values(&["deleted"]).inc();
+                lobby_state.notify(WebhookEvent::ContributionExpired {
+                    uid:    pseudonym.clone(),
+                    reason: "deleted",
+                });
+            }
+            Ok::<_, AccountError>((pseudonym, removed_sessions, kicked))
+        }
+        .await;
+        let recorded_uid = result
+            .as_ref()
+            .ok()
+            .map(|(pseudonym, ..)| pseudonym.clone());
+        audit
+            .record(AuditAction::AccountDeleted, recorded_uid, outcome(&result))
+            .await;
+        let (uid, removed_sessions, kicked) = result?;
+        warn!(%uid, removed_sessions, kicked, "account deleted by its owner");
+        Ok(Json(DeleteAccountResponse {
+            uid,
+            removed_sessions,
+            kicked,
+        }))
+    })
+    .await
+    .unwrap_or_else(|e| Err(AccountError::TaskError(e)))
+}
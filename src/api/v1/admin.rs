@@ -0,0 +1,1518 @@
+use crate::{
+    audit::{outcome, Audit, AuditAction},
+    beacon::{self, Beacon, BeaconError},
+    checkpoint::SharedCheckpointer,
+    db_backup,
+    error_rates::{ErrorRates, SharedResponseCounter},
+    event_tail::{EventFilter, SharedEventTail, TailEvent},
+    feature_flags::{FeatureFlag, FeatureFlagError, Flags, SharedFeatureFlags},
+    fingerprint::SybilReport,
+    io::{write_json_file, TranscriptIoError},
+    keys::{KeyRotationError, Rotation, SharedKeys, Signature, SignatureError},
+    lobby::{
+        ActiveContributorError, ActiveContributorSnapshot, LobbyEvent, LobbySnapshot,
+        SharedLobbyState,
+    },
+    metrics::CONTRIBUTIONS_EXPIRED,
+    oauth::SharedAuthState,
+    replication::SharedReplica,
+    request_signing::SignedBy,
+    storage::{PersistentStorage, StorageError, StoredAdminKey, StoredContributionTimings},
+    transcript_format::{TranscriptFormat, TranscriptFormatError, TrustedSetup},
+    util::{last_event_id, Secret},
+    verification::SharedVerificationQueue,
+    webhook::WebhookEvent,
+    Engine, Options, SharedCeremonyStatus, SharedTranscript,
+};
+use axum::{
+    async_trait,
+    extract::{FromRequest, Path, Query, RequestParts},
+    response::sse::{Event, KeepAlive, Sse},
+    Extension, Json, TypedHeader,
+};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use futures::{stream, Stream, StreamExt};
+use headers::{authorization::Bearer, Authorization};
+use http::HeaderMap;
+use kzg_ceremony_crypto::{signature::identity::Identity, CeremoniesError, ErrorCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Path as FilePath, PathBuf},
+    str::FromStr,
+    sync::atomic::Ordering,
+    time::Duration,
+};
+use strum::{EnumString, IntoStaticStr};
+use thiserror::Error;
+use tokio::{sync::broadcast::error::RecvError, task::JoinError};
+use tracing::warn;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct AdminOptions {
+    /// Bearer token granting access to the `/admin` endpoints. The admin API
+    /// is disabled when neither a token nor `--signing-keys-file` is set.
+    #[clap(long, env)]
+    pub admin_token: Option<Secret>,
+
+    /// Directory `/admin/finalize` writes the final trusted setups to.
+    #[clap(long, env, default_value = "./final_setup")]
+    pub final_setup_dir: PathBuf,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum AdminError {
+    #[error("invalid admin token")]
+    Unauthorized,
+    #[error("the admin key may not do this")]
+    Forbidden,
+    #[error("unknown admin key")]
+    UnknownAdminKey,
+    #[error("an admin key of that name exists already")]
+    AdminKeyExists,
+    #[error("no active contributor")]
+    NoActiveContributor,
+    #[error("contribution is already being verified")]
+    ContributionInProgress,
+    #[error("a contributor still holds the slot")]
+    ContributorActive,
+    #[error("the sequencer is not a standby")]
+    NotStandby,
+    #[error("the sequencer is a standby, promote it first")]
+    Standby,
+    #[error("a beacon was mixed into the transcript already")]
+    BeaconApplied,
+    #[error("invalid beacon: {0}")]
+    InvalidBeacon(#[from] BeaconError),
+    #[error("transcript is invalid: {0}")]
+    InvalidTranscript(#[from] CeremoniesError),
+    #[error("beacon entry is invalid: {0}")]
+    InvalidBeaconEntry(BeaconError),
+    #[error("failed to export trusted setup: {0}")]
+    Export(#[from] TranscriptFormatError),
+    #[error("failed to write trusted setup: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to sign trusted setup: {0}")]
+    Signature(#[from] SignatureError),
+    #[error("failed to rotate the signing key: {0}")]
+    KeyRotation(#[from] KeyRotationError),
+    #[error("failed to change the feature flag: {0}")]
+    FeatureFlag(#[from] FeatureFlagError),
+    #[error("failed to write transcript: {0}")]
+    TranscriptIo(#[from] TranscriptIoError),
+    #[error("storage error: {0}")]
+    StorageError(#[from] StorageError),
+    #[error("background task error: {0}")]
+    TaskError(#[from] JoinError),
+}
+
+impl ErrorCode for AdminError {
+    fn to_error_code(&self) -> String {
+        format!("AdminError::{}", <&str>::from(self))
+    }
+}
+
+/// What the holder of an admin key may do. Each role may do everything the
+/// roles before it may. Signed requests and the admin token act as owners.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    IntoStaticStr,
+    EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AdminRole {
+    /// Inspects the lobby.
+    Viewer,
+    /// Pauses and resumes the lobby, kicks and bans.
+    Operator,
+    /// Finalizes the ceremony, promotes a standby and manages admin keys.
+    Owner,
+}
+
+/// Hex encoded SHA-256 of an admin key, under which it is stored.
+fn admin_key_hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Extractor guarding the admin endpoints. Succeeds only if the request was
+/// signed with one of the signing keys, carries the configured admin token as
+/// a bearer token, or carries an admin key of the `admin_keys` table whose
+/// role is at least the one the route requires. Routes name their role as an
+/// [`AdminRole`] extension; routes without one require an owner.
+pub struct AdminAuth;
+
+impl AdminAuth {
+    async fn role<B: Send>(req: &mut RequestParts<B>) -> Result<AdminRole, AdminError> {
+        if req.extensions().get::<SignedBy>().is_some() {
+            return Ok(AdminRole::Owner);
+        }
+        let Extension(options) = Extension::<Options>::from_request(req)
+            .await
+            .map_err(|_| AdminError::Unauthorized)?;
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request(req)
+                .await
+                .map_err(|_| AdminError::Unauthorized)?;
+        if let Some(expected) = options.admin.admin_token {
+            if constant_time_eq(bearer.token().as_bytes(), expected.get_secret().as_bytes()) {
+                return Ok(AdminRole::Owner);
+            }
+        }
+
+        let Extension(storage) = Extension::<PersistentStorage>::from_request(req)
+            .await
+            .map_err(|_| AdminError::Unauthorized)?;
+        let key = storage
+            .admin_key_by_hash(&admin_key_hash(bearer.token()))
+            .await?;
+        match key.and_then(|key| AdminRole::from_str(&key.role).ok()) {
+            Some(role) => Ok(role),
+            None => {
+                warn!("rejected request with invalid admin token");
+                Err(AdminError::Unauthorized)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for AdminAuth
+where
+    B: Send,
+{
+    type Rejection = AdminError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let required = req
+            .extensions()
+            .get::<AdminRole>()
+            .copied()
+            .unwrap_or(AdminRole::Owner);
+        let role = Self::role(req).await?;
+        if role < required {
+            warn!(?role, ?required, path = %req.uri().path(), "rejected admin request of a lesser role");
+            return Err(AdminError::Forbidden);
+        }
+        Ok(Self)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Debug, Serialize)]
+pub struct PausedResponse {
+    paused: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromoteResponse {
+    num_contributions: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KickResponse {
+    uid: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BanRequest {
+    uid: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PriorityRequest {
+    uid:      String,
+    priority: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BanResponse {
+    uid:              String,
+    removed_sessions: usize,
+    kicked:           bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminKeyRequest {
+    name: String,
+    role: AdminRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminRoleRequest {
+    role: AdminRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminKeyInfo {
+    name:       String,
+    role:       String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminKeyResponse {
+    name: String,
+    role: AdminRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminKeyName {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedAdminKey {
+    name: String,
+    role: AdminRole,
+    /// The bearer token of the key. Only its hash is stored, so it is not
+    /// shown again.
+    key:  String,
+}
+
+/// Largest number of contributions `/admin/timings` returns at once.
+const MAX_TIMINGS: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct TimingsQuery {
+    #[serde(default = "default_timings_limit")]
+    limit: usize,
+}
+
+const fn default_timings_limit() -> usize {
+    100
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TailQuery {
+    #[serde(default = "default_tail_last")]
+    last:  usize,
+    /// Comma separated types, e.g. `admin_ban,contribution_verified`.
+    #[serde(rename = "type")]
+    kinds: Option<String>,
+    uid:   Option<String>,
+}
+
+const fn default_tail_last() -> usize {
+    100
+}
+
+/// How long the phases of handling a contribution took, in microseconds.
+#[derive(Debug, Serialize)]
+pub struct TimingsEntry {
+    uid:                 String,
+    contribution_index:  usize,
+    receive_us:          u64,
+    deserialize_us:      u64,
+    subgroup_check_us:   u64,
+    pairing_us:          u64,
+    transcript_write_us: u64,
+    respond_us:          u64,
+    recorded_at:         DateTime<Utc>,
+}
+
+impl From<StoredContributionTimings> for TimingsEntry {
+    fn from(timings: StoredContributionTimings) -> Self {
+        let micros = |duration: Duration| u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        Self {
+            receive_us:          micros(timings.receive),
+            deserialize_us:      micros(timings.deserialize),
+            subgroup_check_us:   micros(timings.subgroup_check),
+            pairing_us:          micros(timings.pairing),
+            transcript_write_us: micros(timings.transcript_write),
+            respond_us:          micros(timings.respond),
+            uid:                 timings.uid,
+            contribution_index:  timings.contribution_index,
+            recorded_at:         timings.recorded_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BeaconResponse {
+    num_contributions: usize,
+    /// Recorded as the entropy attestation of the beacon's entry.
+    provenance:        String,
+}
+
+#[derive(Serialize)]
+pub struct FinalArtifact {
+    ceremony:  usize,
+    path:      String,
+    /// Hex encoded SHA-256 of the file.
+    sha256:    String,
+    /// Signature of the sequencer key over `sha256`.
+    signature: Signature,
+}
+
+#[derive(Serialize)]
+pub struct FinalizeResponse {
+    num_contributions: usize,
+    artifacts:         Vec<FinalArtifact>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseHealth {
+    /// `"ok"` or the error of pinging the database.
+    status:   String,
+    /// Whether too many connection failures put it in degraded mode.
+    degraded: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Overview {
+    paused:               bool,
+    lobby_size:           usize,
+    /// The lobby size in effect, see [`crate::lobby_tuning`].
+    max_lobby_size:       usize,
+    session_count:        usize,
+    active_contributor:   Option<ActiveContributorSnapshot>,
+    num_contributions:    usize,
+    /// Contributions submitted and not verified yet.
+    verification_backlog: usize,
+    database:             DatabaseHealth,
+    /// Time of the newest backup in `--db-backup-dir`.
+    last_backup_at:       Option<DateTime<Utc>>,
+    /// Responses of the last minute.
+    responses:            ErrorRates,
+}
+
+pub async fn lobby(
+    _: AdminAuth,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+) -> Json<LobbySnapshot> {
+    Json(lobby_state.snapshot().await)
+}
+
+/// The state of the ceremony at a glance, for dashboards.
+pub async fn overview(
+    _: AdminAuth,
+    Extension(options): Extension<Options>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(queue): Extension<SharedVerificationQueue>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(responses): Extension<SharedResponseCounter>,
+) -> Json<Overview> {
+    let snapshot = lobby_state.snapshot().await;
+    let num_contributions = transcript.read().await.num_participants();
+    let status = storage
+        .ping()
+        .await
+        .map_or_else(|err| err.to_string(), |()| "ok".to_string());
+    Json(Overview {
+        paused: snapshot.paused,
+        lobby_size: snapshot.lobby.len(),
+        max_lobby_size: lobby_state.options().max_lobby_size,
+        session_count: snapshot.session_count,
+        active_contributor: snapshot.active_contributor,
+        num_contributions,
+        verification_backlog: queue.backlog(),
+        database: DatabaseHealth {
+            status,
+            degraded: storage.is_degraded(),
+        },
+        last_backup_at: db_backup::latest_backup(&options.db_backup.db_backup_dir),
+        responses: responses.rates(),
+    })
+}
+
+pub async fn pause(
+    _: AdminAuth,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    audit: Audit,
+) -> Json<PausedResponse> {
+    warn!("lobby paused by admin");
+    lobby_state.set_paused(true).await;
+    audit
+        .record(AuditAction::AdminPause, None, "ok".to_string())
+        .await;
+    Json(PausedResponse { paused: true })
+}
+
+/// Fails on a standby, whose lobby stays paused until it is promoted.
+pub async fn resume(
+    _: AdminAuth,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(replica): Extension<SharedReplica>,
+    audit: Audit,
+) -> Result<Json<PausedResponse>, AdminError> {
+    let result = if replica.is_standby() {
+        Err(AdminError::Standby)
+    } else {
+        warn!("lobby resumed by admin");
+        lobby_state.set_paused(false).await;
+        Ok(Json(PausedResponse { paused: false }))
+    };
+    audit
+        .record(AuditAction::AdminResume, None, outcome(&result))
+        .await;
+    result
+}
+
+/// Stops following the primary and opens the lobby of a standby, see
+/// [`crate::replication`].
+pub async fn promote(
+    _: AdminAuth,
+    Extension(replica): Extension<SharedReplica>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(ceremony_status): Extension<SharedCeremonyStatus>,
+    audit: Audit,
+) -> Result<Json<PromoteResponse>, AdminError> {
+    let result = if replica.promote().await {
+        let num_contributions = ceremony_status.load(Ordering::Relaxed);
+        warn!(num_contributions, "standby promoted by admin");
+        lobby_state.set_paused(false).await;
+        Ok(Json(PromoteResponse { num_contributions }))
+    } else {
+        Err(AdminError::NotStandby)
+    };
+    audit
+        .record(AuditAction::AdminPromote, None, outcome(&result))
+        .await;
+    result
+}
+
+pub async fn kick(
+    _: AdminAuth,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<KickResponse>, AdminError> {
+    // Kick in the background, so that request cancelation doesn't interrupt it
+    // inbetween the lobby_state and storage calls.
+    tokio::spawn(async move {
+        let token = lobby_state
+            .kick_current_contributor()
+            .await
+            .map_err(|e| match e {
+                ActiveContributorError::AnotherContributionInProgress => {
+                    AdminError::ContributionInProgress
+                }
+                _ => AdminError::NoActiveContributor,
+            })?;
+        let uid = token.unique_identifier();
+        warn!(%uid, "active contributor kicked by admin");
+        CONTRIBUTIONS_EXPIRED.with_label_values(&["kicked"]).inc();
+        lobby_state.notify(WebhookEvent::ContributionExpired {
+            uid:    uid.clone(),
+            reason: "kicked",
+        });
+        let result = storage
+            .expire_contribution(&uid)
+            .await
+            .map_err(AdminError::from);
+        audit
+            .record(AuditAction::AdminKick, Some(uid.clone()), outcome(&result))
+            .await;
+        result?;
+        Ok(Json(KickResponse { uid }))
+    })
+    .await
+    .unwrap_or_else(|e| Err(AdminError::TaskError(e)))
+}
+
+pub async fn ban(
+    _: AdminAuth,
+    Json(request): Json<BanRequest>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(auth_state): Extension<SharedAuthState>,
+    Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<BanResponse>, AdminError> {
+    tokio::spawn(async move {
+        let uid = request.uid;
+        let result = async {
+            storage.ban_uid(&uid).await?;
+            let (removed_sessions, kicked) = lobby_state.remove_uid(&uid).await;
+            auth_state.write().await.unique_id_session.remove(&uid);
+            storage.delete_sessions_of(&uid).await?;
+            if kicked {
+                CONTRIBUTIONS_EXPIRED.with_label_values(&["banned"]).inc();
+                lobby_state.notify(WebhookEvent::ContributionExpired {
+                    uid:    uid.clone(),
+                    reason: "banned",
+                });
+                storage.expire_contribution(&uid).await?;
+            }
+            Ok::<_, AdminError>((removed_sessions, kicked))
+        }
+        .await;
+        audit
+            .record(AuditAction::AdminBan, Some(uid.clone()), outcome(&result))
+            .await;
+        let (removed_sessions, kicked) = result?;
+        warn!(%uid, removed_sessions, kicked, "uid banned by admin");
+        Ok(Json(BanResponse {
+            uid,
+            removed_sessions,
+            kicked,
+        }))
+    })
+    .await
+    .unwrap_or_else(|e| Err(AdminError::TaskError(e)))
+}
+
+pub async fn unban(
+    auth: AdminAuth,
+    Json(request): Json<BanRequest>,
+    storage: Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<KickResponse>, AdminError> {
+    delete_ban(auth, Path(request.uid), storage, audit).await
+}
+
+pub async fn delete_ban(
+    _: AdminAuth,
+    Path(uid): Path<String>,
+    Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<KickResponse>, AdminError> {
+    let result = storage.unban_uid(&uid).await.map_err(AdminError::from);
+    audit
+        .record(AuditAction::AdminUnban, Some(uid.clone()), outcome(&result))
+        .await;
+    result?;
+    warn!(%uid, "uid unbanned by admin");
+    Ok(Json(KickResponse { uid }))
+}
+
+/// Sets the lobby priority of a uid, e.g. to fast-track contributors who lost
+/// their slot to a crash. It applies whether or not they are in the lobby,
+/// and is kept across restarts. 0 removes it.
+pub async fn set_priority(
+    _: AdminAuth,
+    Json(request): Json<PriorityRequest>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<PriorityRequest>, AdminError> {
+    let result = storage
+        .set_priority(&request.uid, request.priority)
+        .await
+        .map_err(AdminError::from);
+    if result.is_ok() {
+        lobby_state
+            .set_priority(&request.uid, request.priority)
+            .await;
+    }
+    audit
+        .record(
+            AuditAction::AdminPriority,
+            Some(request.uid.clone()),
+            outcome(&result),
+        )
+        .await;
+    result?;
+    warn!(uid = %request.uid, priority = request.priority, "lobby priority set by admin");
+    Ok(Json(request))
+}
+
+/// The timings of the last `limit` contributions, newest first.
+pub async fn timings(
+    _: AdminAuth,
+    Query(query): Query<TimingsQuery>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Vec<TimingsEntry>>, AdminError> {
+    let timings = storage
+        .contribution_timings(query.limit.min(MAX_TIMINGS))
+        .await?;
+    Ok(Json(timings.into_iter().map(TimingsEntry::from).collect()))
+}
+
+pub async fn sybil_report(
+    _: AdminAuth,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<SybilReport>, AdminError> {
+    let rows = storage.contributor_fingerprints().await?;
+    Ok(Json(SybilReport::new(rows)))
+}
+
+/// Streams the last `last` matching events, then those that follow as they
+/// happen. A client that reconnects with `Last-Event-ID` gets every kept event
+/// it missed instead.
+pub async fn tail_events(
+    _: AdminAuth,
+    Query(query): Query<TailQuery>,
+    headers: HeaderMap,
+    Extension(tail): Extension<SharedEventTail>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let filter = EventFilter {
+        kinds: query
+            .kinds
+            .iter()
+            .flat_map(|kinds| kinds.split(','))
+            .map(str::trim)
+            .filter(|kind| !kind.is_empty())
+            .map(str::to_string)
+            .collect(),
+        uid:   query.uid,
+    };
+    let after = last_event_id(&headers);
+    let last = if after.is_some() {
+        usize::MAX
+    } else {
+        query.last
+    };
+    let (backlog, receiver) = tail.subscribe(&filter, last, after);
+    let live = stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if filter.matches(&event) => return Some((event, (receiver, filter))),
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(missed, "event tail subscriber fell behind");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    let events = stream::iter(backlog)
+        .chain(live)
+        .map(|event: TailEvent| Event::default().id(event.id.to_string()).json_data(&event));
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+pub async fn admin_keys(
+    _: AdminAuth,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Vec<AdminKeyInfo>>, AdminError> {
+    let keys = storage.admin_keys().await?;
+    Ok(Json(
+        keys.into_iter()
+            .map(|key| AdminKeyInfo {
+                name:       key.name,
+                role:       key.role,
+                created_at: key.created_at,
+            })
+            .collect(),
+    ))
+}
+
+pub async fn create_admin_key(
+    _: AdminAuth,
+    Json(request): Json<AdminKeyRequest>,
+    Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<CreatedAdminKey>, AdminError> {
+    let key = hex::encode(rand::random::<[u8; 32]>());
+    let stored = StoredAdminKey {
+        name:       request.name.clone(),
+        key_hash:   admin_key_hash(&key),
+        role:       <&str>::from(request.role).to_string(),
+        created_at: Utc::now(),
+    };
+    let result = match storage.insert_admin_key(&stored).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AdminError::AdminKeyExists),
+        Err(e) => Err(AdminError::from(e)),
+    };
+    audit
+        .record(AuditAction::AdminKeyCreated, None, outcome(&result))
+        .await;
+    result?;
+    warn!(name = %request.name, role = ?request.role, "admin key created");
+    Ok(Json(CreatedAdminKey {
+        name: request.name,
+        role: request.role,
+        key,
+    }))
+}
+
+pub async fn update_admin_key(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Json(request): Json<AdminRoleRequest>,
+    Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<AdminKeyResponse>, AdminError> {
+    let result = match storage
+        .update_admin_key_role(&name, request.role.into())
+        .await
+    {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AdminError::UnknownAdminKey),
+        Err(e) => Err(AdminError::from(e)),
+    };
+    audit
+        .record(AuditAction::AdminKeyUpdated, None, outcome(&result))
+        .await;
+    result?;
+    warn!(%name, role = ?request.role, "admin key role changed");
+    Ok(Json(AdminKeyResponse {
+        name,
+        role: request.role,
+    }))
+}
+
+pub async fn delete_admin_key(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
+) -> Result<Json<AdminKeyName>, AdminError> {
+    let result = match storage.delete_admin_key(&name).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AdminError::UnknownAdminKey),
+        Err(e) => Err(AdminError::from(e)),
+    };
+    audit
+        .record(AuditAction::AdminKeyDeleted, None, outcome(&result))
+        .await;
+    result?;
+    warn!(%name, "admin key deleted");
+    Ok(Json(AdminKeyName { name }))
+}
+
+/// Replaces the sequencer's signing key with a new one, see
+/// [`crate::keys::Keys::rotate`].
+pub async fn rotate_signing_key(
+    _: AdminAuth,
+    Extension(keys): Extension<SharedKeys>,
+    audit: Audit,
+) -> Result<Json<Rotation>, AdminError> {
+    let result = keys.rotate().map_err(AdminError::from);
+    audit
+        .record(AuditAction::AdminSigningKeyRotated, None, outcome(&result))
+        .await;
+    let rotation = result?;
+    warn!(address = %rotation.address, previous = %rotation.previous_address, "signing key rotated by admin");
+    Ok(Json(rotation))
+}
+
+pub async fn feature_flags(
+    _: AdminAuth,
+    Extension(flags): Extension<SharedFeatureFlags>,
+) -> Json<Flags> {
+    Json(Flags::clone(&flags.current()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeatureFlagRequest {
+    enabled: bool,
+    #[serde(default = "all_sessions")]
+    percent: u8,
+}
+
+const fn all_sessions() -> u8 {
+    100
+}
+
+/// Changes a feature flag until the flags file is read again, see
+/// [`crate::feature_flags`].
+pub async fn set_feature_flag(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Json(request): Json<FeatureFlagRequest>,
+    Extension(flags): Extension<SharedFeatureFlags>,
+    audit: Audit,
+) -> Result<Json<FeatureFlag>, AdminError> {
+    let result = flags
+        .set(&name, request.enabled, request.percent)
+        .map_err(AdminError::from);
+    audit
+        .record(AuditAction::AdminFeatureFlag, None, outcome(&result))
+        .await;
+    let flag = result?;
+    warn!(%name, enabled = flag.enabled, percent = flag.percent, "feature flag changed by admin");
+    Ok(Json(flag))
+}
+
+/// Closes the lobby and adds a last contribution derived from a public
+/// randomness beacon, see [`crate::beacon`]. The beacon can be mixed in once.
+///
+/// Fails like [`finalize`] if a contributor still holds the slot.
+#[allow(clippy::too_many_arguments)]
+pub async fn apply_beacon(
+    _: AdminAuth,
+    Json(request): Json<Beacon>,
+    Extension(options): Extension<Options>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(checkpointer): Extension<SharedCheckpointer>,
+    Extension(ceremony_status): Extension<SharedCeremonyStatus>,
+    Extension(replica): Extension<SharedReplica>,
+    audit: Audit,
+) -> Result<Json<BeaconResponse>, AdminError> {
+    // Mix in the background, so that request cancelation doesn't leave the
+    // entry out of the database or the transcript file.
+    tokio::spawn(async move {
+        let result = async {
+            if replica.is_standby() {
+                return Err(AdminError::Standby);
+            }
+            let provenance = request.provenance()?;
+            if !lobby_state
+                .close(lobby_state.options().shutdown_deadline)
+                .await
+            {
+                return Err(AdminError::ContributorActive);
+            }
+            let mut mixed = transcript.clone().write_owned().await;
+            let entropy_provenance = provenance.clone();
+            let num_contributions = tokio::task::spawn_blocking(move || {
+                if beacon::applied(&mixed) {
+                    return Err(AdminError::BeaconApplied);
+                }
+                let contribution = beacon::contribution(&mixed, &entropy_provenance)?;
+                mixed.verify_add::<Engine>(contribution, Identity::None)?;
+                Ok(mixed.num_participants())
+            })
+            .await??;
+
+            storage
+                .append_transcript_entry(&*transcript.read().await)
+                .await?;
+            checkpointer
+                .checkpoint(num_contributions, &transcript)
+                .await;
+            write_json_file(
+                options.transcript_file.clone(),
+                options.transcript_in_progress_file.clone(),
+                transcript.clone(),
+            )
+            .await?;
+            ceremony_status.store(num_contributions, Ordering::Relaxed);
+            lobby_state.publish(LobbyEvent::ContributionVerified { num_contributions });
+            Ok(BeaconResponse {
+                num_contributions,
+                provenance,
+            })
+        }
+        .await;
+        audit
+            .record(AuditAction::AdminBeacon, None, outcome(&result))
+            .await;
+        let response = result?;
+        warn!(
+            num_contributions = response.num_contributions,
+            provenance = %response.provenance,
+            "beacon mixed into the transcript"
+        );
+        Ok(Json(response))
+    })
+    .await
+    .unwrap_or_else(|e| Err(AdminError::TaskError(e)))
+}
+
+/// Closes the lobby, re-verifies the whole transcript and writes the trusted
+/// setup of every sub-ceremony, signed by the sequencer key.
+///
+/// Fails if a contributor still holds the slot once
+/// `--shutdown-deadline` has passed. The lobby stays closed, so the
+/// contributor can be kicked and the request retried.
+pub async fn finalize(
+    _: AdminAuth,
+    Extension(options): Extension<Options>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(keys): Extension<SharedKeys>,
+    audit: Audit,
+) -> Result<Json<FinalizeResponse>, AdminError> {
+    warn!("ceremony finalization started by admin");
+    // Finalize in the background, so that request cancelation doesn't leave
+    // the artifacts half written.
+    tokio::spawn(async move {
+        let result = async {
+            if !lobby_state
+                .close(lobby_state.options().shutdown_deadline)
+                .await
+            {
+                return Err(AdminError::ContributorActive);
+            }
+            let transcript = transcript.read_owned().await;
+            let num_contributions = transcript.num_participants();
+            let setups = tokio::task::spawn_blocking(move || {
+                transcript.verify_self::<Engine>()?;
+                beacon::verify(&transcript).map_err(AdminError::InvalidBeaconEntry)?;
+                (0..transcript.transcripts.len())
+                    .map(|ceremony| {
+                        TrustedSetup { ceremony }
+                            .export(&transcript)
+                            .map_err(AdminError::from)
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .await??;
+            let artifacts =
+                write_final_setups(&options.admin.final_setup_dir, setups, &keys).await?;
+            Ok(FinalizeResponse {
+                num_contributions,
+                artifacts,
+            })
+        }
+        .await;
+        audit
+            .record(AuditAction::AdminFinalize, None, outcome(&result))
+            .await;
+        let response = result?;
+        warn!(
+            num_contributions = response.num_contributions,
+            dir = %options.admin.final_setup_dir.display(),
+            "ceremony finalized"
+        );
+        Ok(Json(response))
+    })
+    .await
+    .unwrap_or_else(|e| Err(AdminError::TaskError(e)))
+}
+
+/// Writes each setup to `trusted_setup_<ceremony>.json` and the artifact list
+/// to `manifest.json`.
+async fn write_final_setups(
+    dir: &FilePath,
+    setups: Vec<Vec<u8>>,
+    keys: &SharedKeys,
+) -> Result<Vec<FinalArtifact>, AdminError> {
+    tokio::fs::create_dir_all(dir).await?;
+    let mut artifacts = Vec::with_capacity(setups.len());
+    for (ceremony, setup) in setups.into_iter().enumerate() {
+        let path = dir.join(format!("trusted_setup_{ceremony}.json"));
+        let sha256 = hex::encode(Sha256::digest(&setup));
+        tokio::fs::write(&path, setup).await?;
+        artifacts.push(FinalArtifact {
+            ceremony,
+            path: path.display().to_string(),
+            signature: keys.sign(&sha256).await?,
+            sha256,
+        });
+    }
+    let manifest = serde_json::to_vec_pretty(&artifacts).map_err(TranscriptFormatError::from)?;
+    tokio::fs::write(dir.join("manifest.json"), manifest).await?;
+    Ok(artifacts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::v1::lobby::{try_contribute, TryContributeError},
+        checkpoint::Checkpointer,
+        client_version::ClientHeader,
+        contribution_format::ContributionEncoding,
+        handoff::SharedHandoff,
+        keys::{self, Keys},
+        phases::SharedSchedule,
+        proof_of_work::PowSolution,
+        quotas::SharedRuleSet,
+        replication::Replica,
+        reservation::ReservationSigner,
+        spam_filter::SpamGuard,
+        storage::storage_client,
+        test_util::{create_test_session_info, test_options},
+        tests::test_transcript,
+        verification::VerificationQueue,
+        SessionId,
+    };
+    use http::{header::AUTHORIZATION, HeaderMap, Request};
+    use std::{
+        sync::{atomic::AtomicUsize, Arc},
+        time::Duration,
+    };
+    use tempfile::tempdir;
+    use tokio::sync::RwLock;
+
+    #[test]
+    fn compares_tokens() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+
+    #[tokio::test]
+    async fn checks_admin_roles() {
+        let mut opts = test_options();
+        opts.admin.admin_token = Some("owner-token".parse().unwrap());
+        let db = storage_client(&opts.storage).await.unwrap();
+        let authorize = |token: &str, required: Option<AdminRole>| {
+            let mut request = Request::builder()
+                .uri("/admin/lobby")
+                .header(AUTHORIZATION, format!("Bearer {token}"))
+                .body(())
+                .unwrap();
+            request.extensions_mut().insert(opts.clone());
+            request.extensions_mut().insert(db.clone());
+            if let Some(required) = required {
+                request.extensions_mut().insert(required);
+            }
+            async move { AdminAuth::from_request(&mut RequestParts::new(request)).await }
+        };
+        let create = |role| {
+            create_admin_key(
+                AdminAuth,
+                Json(AdminKeyRequest {
+                    name: "on-call".to_string(),
+                    role,
+                }),
+                Extension(db.clone()),
+                Audit::default(),
+            )
+        };
+
+        let Json(created) = create(AdminRole::Viewer).await.unwrap();
+        assert!(matches!(
+            create(AdminRole::Owner).await,
+            Err(AdminError::AdminKeyExists)
+        ));
+        assert!(authorize(&created.key, Some(AdminRole::Viewer))
+            .await
+            .is_ok());
+        assert!(matches!(
+            authorize(&created.key, Some(AdminRole::Operator)).await,
+            Err(AdminError::Forbidden)
+        ));
+        assert!(matches!(
+            authorize(&created.key, None).await,
+            Err(AdminError::Forbidden)
+        ));
+        assert!(authorize("owner-token", None).await.is_ok());
+        assert!(matches!(
+            authorize("guessed", Some(AdminRole::Viewer)).await,
+            Err(AdminError::Unauthorized)
+        ));
+
+        update_admin_key(
+            AdminAuth,
+            Path("on-call".to_string()),
+            Json(AdminRoleRequest {
+                role: AdminRole::Operator,
+            }),
+            Extension(db.clone()),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
+        assert!(authorize(&created.key, Some(AdminRole::Operator))
+            .await
+            .is_ok());
+        let Json(keys) = admin_keys(AdminAuth, Extension(db.clone())).await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].role, "operator");
+
+        let delete = || {
+            delete_admin_key(
+                AdminAuth,
+                Path("on-call".to_string()),
+                Extension(db.clone()),
+                Audit::default(),
+            )
+        };
+        delete().await.unwrap();
+        assert!(matches!(delete().await, Err(AdminError::UnknownAdminKey)));
+        assert!(matches!(
+            authorize(&created.key, Some(AdminRole::Viewer)).await,
+            Err(AdminError::Unauthorized)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_shared_fingerprints_of_finished_contributions() {
+        let db = storage_client(&test_options().storage).await.unwrap();
+        for uid in ["git|1|a", "git|2|b", "git|3|unfinished"] {
+            db.insert_contributor(uid, None).await.unwrap();
+            db.set_contributor_fingerprint(uid, Some("f1"), Some("10.0.0.0/24"))
+                .await
+                .unwrap();
+        }
+        db.finish_contribution("git|1|a").await.unwrap();
+        let Json(report) = sybil_report(AdminAuth, Extension(db.clone()))
+            .await
+            .unwrap();
+        assert_eq!(report, SybilReport::default());
+
+        db.finish_contribution("git|2|b").await.unwrap();
+        let Json(report) = sybil_report(AdminAuth, Extension(db)).await.unwrap();
+        assert_eq!(report.fingerprints.len(), 1);
+        assert_eq!(report.fingerprints[0].uids, vec!["git|1|a", "git|2|b"]);
+        assert_eq!(report.subnets[0].key, "10.0.0.0/24");
+    }
+
+    #[tokio::test]
+    async fn promote_opens_standby_lobby() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let replica = Arc::new(Replica::new(true));
+        lobby_state.set_paused(true).await;
+
+        let resumed = resume(
+            AdminAuth,
+            Extension(lobby_state.clone()),
+            Extension(replica.clone()),
+            Audit::default(),
+        )
+        .await;
+        assert!(matches!(resumed, Err(AdminError::Standby)));
+        assert!(lobby_state.snapshot().await.paused);
+
+        let promote = || {
+            promote(
+                AdminAuth,
+                Extension(replica.clone()),
+                Extension(lobby_state.clone()),
+                Extension(Arc::new(AtomicUsize::new(3))),
+                Audit::default(),
+            )
+        };
+        assert_eq!(promote().await.unwrap().num_contributions, 3);
+        assert!(!replica.is_standby());
+        assert!(!lobby_state.snapshot().await.paused);
+        assert!(matches!(promote().await, Err(AdminError::NotStandby)));
+    }
+
+    #[tokio::test]
+    async fn pause_blocks_new_contributors() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+
+        pause(AdminAuth, Extension(lobby_state.clone()), Audit::default()).await;
+        let paused_response = try_contribute(
+            session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Extension(transcript.clone()),
+            Extension(opts.clone()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
+        )
+        .await;
+        assert!(matches!(
+            paused_response,
+            Err(TryContributeError::LobbyPaused)
+        ));
+        assert!(lobby_state.snapshot().await.paused);
+        assert_eq!(lobby_state.snapshot().await.lobby.len(), 1);
+
+        resume(
+            AdminAuth,
+            Extension(lobby_state.clone()),
+            Extension(SharedReplica::default()),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
+        tokio::time::pause();
+        tokio::time::advance(opts.lobby.min_checkin_delay()).await;
+        tokio::time::resume();
+        try_contribute(
+            session_id,
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
+            Extension(lobby_state.clone()),
+            Extension(db),
+            Extension(transcript),
+            Extension(opts),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
+        )
+        .await
+        .unwrap();
+        let snapshot = lobby_state.snapshot().await;
+        assert!(!snapshot.paused);
+        let active = snapshot.active_contributor.unwrap();
+        assert!(!active.contributing);
+        assert!(active.seconds_remaining.unwrap() <= opts.lobby.compute_deadline.as_secs());
+    }
+
+    #[tokio::test]
+    async fn overview_sums_up_the_ceremony() {
+        let dir = tempdir().unwrap();
+        let mut opts = test_options();
+        opts.db_backup.db_backup_dir = dir.path().to_path_buf();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        lobby_state
+            .insert_session(SessionId::new(), create_test_session_info(100))
+            .await
+            .unwrap();
+        let db = storage_client(&opts.storage).await.unwrap();
+        let responses = SharedResponseCounter::default();
+        responses.record(http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        let Json(overview) = overview(
+            AdminAuth,
+            Extension(opts.clone()),
+            Extension(lobby_state),
+            Extension(Arc::new(RwLock::new(test_transcript()))),
+            Extension(Arc::new(VerificationQueue::new(&opts.verification))),
+            Extension(db),
+            Extension(responses),
+        )
+        .await;
+        assert_eq!(overview.lobby_size, 0);
+        assert_eq!(overview.max_lobby_size, opts.lobby.max_lobby_size);
+        assert_eq!(overview.session_count, 1);
+        assert!(overview.active_contributor.is_none());
+        assert_eq!(overview.num_contributions, 0);
+        assert_eq!(overview.verification_backlog, 0);
+        assert_eq!(overview.database.status, "ok");
+        assert!(!overview.database.degraded);
+        assert_eq!(overview.last_backup_at, None);
+        assert_eq!(overview.responses, ErrorRates {
+            requests:      1.0,
+            client_errors: 0.0,
+            server_errors: 1.0,
+        });
+    }
+
+    #[tokio::test]
+    async fn kicks_and_bans() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let auth_state = SharedAuthState::default();
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let result = kick(
+            AdminAuth,
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Audit::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(AdminError::NoActiveContributor)));
+
+        let session_id = SessionId::new();
+        let session_info = create_test_session_info(100);
+        let uid = session_info.token.unique_identifier();
+        lobby_state
+            .insert_session(session_id.clone(), session_info)
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&session_id).await.unwrap();
+        lobby_state
+            .set_current_contributor(&session_id, 1, String::new(), opts.lobby.compute_deadline)
+            .await
+            .unwrap();
+
+        let Json(kicked) = kick(
+            AdminAuth,
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(kicked.uid, uid);
+        assert!(lobby_state.snapshot().await.active_contributor.is_none());
+
+        lobby_state
+            .insert_session(session_id, create_test_session_info(100))
+            .await
+            .unwrap();
+        let Json(banned) = ban(
+            AdminAuth,
+            Json(BanRequest { uid: uid.clone() }),
+            Extension(lobby_state.clone()),
+            Extension(auth_state),
+            Extension(db.clone()),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(banned.removed_sessions, 1);
+        assert!(!banned.kicked);
+        assert!(db.is_banned(&uid).await.unwrap());
+        assert_eq!(lobby_state.get_session_count().await, 0);
+
+        // A session that survived the ban can not join the lobby.
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        let banned_response = try_contribute(
+            session_id,
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+            Extension(Arc::new(RwLock::new(test_transcript()))),
+            Extension(opts.clone()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
+        )
+        .await;
+        assert!(matches!(
+            banned_response,
+            Err(TryContributeError::UserBanned)
+        ));
+        assert_eq!(lobby_state.get_lobby_size().await, 0);
+
+        let Json(unbanned) = delete_ban(
+            AdminAuth,
+            Path(uid.clone()),
+            Extension(db.clone()),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(unbanned.uid, uid);
+        assert!(!db.is_banned(&uid).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn finalize_writes_signed_setups() {
+        let dir = tempdir().unwrap();
+        let mut opts = test_options();
+        opts.admin.final_setup_dir = dir.path().join("final");
+        opts.lobby.shutdown_deadline = Duration::from_millis(10);
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let keys = Arc::new(Keys::new(&keys::Options::parse_from(Vec::<&str>::new())).unwrap());
+        let contributor = SessionId::new();
+        lobby_state
+            .insert_session(contributor.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&contributor).await.unwrap();
+        lobby_state
+            .set_current_contributor(&contributor, 1, String::new(), opts.lobby.compute_deadline)
+            .await
+            .unwrap();
+        let result = finalize(
+            AdminAuth,
+            Extension(opts.clone()),
+            Extension(lobby_state.clone()),
+            Extension(transcript.clone()),
+            Extension(keys.clone()),
+            Audit::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(AdminError::ContributorActive)));
+
+        lobby_state.clear_current_contributor().await;
+        let Json(finalized) = finalize(
+            AdminAuth,
+            Extension(opts.clone()),
+            Extension(lobby_state.clone()),
+            Extension(transcript),
+            Extension(keys.clone()),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(finalized.num_contributions, 0);
+        assert_eq!(finalized.artifacts.len(), 1);
+        let artifact = &finalized.artifacts[0];
+        let setup = std::fs::read(&artifact.path).unwrap();
+        assert_eq!(artifact.sha256, hex::encode(Sha256::digest(&setup)));
+        keys.verify(&artifact.sha256, &artifact.signature).unwrap();
+        let setup: serde_json::Value = serde_json::from_slice(&setup).unwrap();
+        assert_eq!(setup["g1_lagrange"].as_array().unwrap().len(), 4);
+        assert!(opts.admin.final_setup_dir.join("manifest.json").exists());
+
+        let latecomer = SessionId::new();
+        lobby_state
+            .insert_session(latecomer.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        assert!(matches!(
+            lobby_state.enter_lobby(&latecomer).await,
+            Err(ActiveContributorError::CeremonyClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn applies_beacon_once() {
+        let dir = tempdir().unwrap();
+        let mut opts = test_options();
+        opts.transcript_file = dir.path().join("transcript.json");
+        opts.transcript_in_progress_file = dir.path().join("transcript.json.next");
+        opts.admin.final_setup_dir = dir.path().join("final");
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+        let checkpointer = Arc::new(Checkpointer::new(&opts.checkpoint).unwrap());
+        let ceremony_status = Arc::new(AtomicUsize::new(0));
+        let apply = |request| {
+            apply_beacon(
+                AdminAuth,
+                Json(request),
+                Extension(opts.clone()),
+                Extension(lobby_state.clone()),
+                Extension(transcript.clone()),
+                Extension(db.clone()),
+                Extension(checkpointer.clone()),
+                Extension(ceremony_status.clone()),
+                Extension(SharedReplica::default()),
+                Audit::default(),
+            )
+        };
+        let drand = Beacon::Drand {
+            chain_hash: "ab".repeat(32),
+            round:      2_409_021,
+            randomness: "cd".repeat(32),
+        };
+
+        let Json(mixed) = apply(drand.clone()).await.unwrap();
+        assert_eq!(mixed.num_contributions, 1);
+        assert!(mixed.provenance.starts_with("beacon:drand:"));
+        assert_eq!(ceremony_status.load(Ordering::Relaxed), 1);
+        assert!(opts.transcript_file.exists());
+        assert_eq!(
+            beacon::provenance(&*transcript.read().await, 1),
+            Some(mixed.provenance.as_str())
+        );
+        assert!(matches!(apply(drand).await, Err(AdminError::BeaconApplied)));
+        assert!(matches!(
+            apply(Beacon::Ethereum {
+                block_number: 16_000_000,
+                block_hash:   "0x1234".to_string(),
+            })
+            .await,
+            Err(AdminError::InvalidBeacon(_))
+        ));
+
+        let keys = Arc::new(Keys::new(&keys::Options::parse_from(Vec::<&str>::new())).unwrap());
+        let Json(finalized) = finalize(
+            AdminAuth,
+            Extension(opts.clone()),
+            Extension(lobby_state),
+            Extension(transcript),
+            Extension(keys),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(finalized.num_contributions, 1);
+    }
+}
@@ -1,31 +1,38 @@
 use crate::{
+    audit::{outcome, Audit, AuditAction},
+    auth_funnel::{self, Stage},
+    callback_coalescing::SharedCallbackCoalescer,
+    ceremony::CeremonyId,
+    eligibility::{Eligibility, SharedScorer},
+    identity,
     lobby::SharedLobbyState,
-    oauth::{EthOAuthClient, GithubOAuthClient, SharedAuthState},
+    oauth::{AuthProviders, SharedAuthState},
+    phases::{PhaseViolation, SharedSchedule},
+    quotas::{RuleViolation, SharedRuleSet},
     sessions::IdToken,
-    storage::{PersistentStorage, StorageError},
-    EthAuthOptions, Options, SessionId, SessionInfo,
+    spam_filter::{SpamFilterError, SpamGuard, SpamStage},
+    storage::{PersistentStorage, StorageError, StoredSession},
+    Options, SessionId, SessionInfo,
 };
 use axum::{
     async_trait,
-    extract::{FromRequest, Query, RequestParts},
+    extract::{FromRequest, Path, Query, RequestParts},
     response::{IntoResponse, Redirect, Response},
     Extension, Json,
 };
-use chrono::DateTime;
-use eyre::eyre;
-use http::StatusCode;
+use chrono::Utc;
 use kzg_ceremony_crypto::{signature::identity::Identity, ErrorCode};
-use oauth2::{
-    reqwest::async_http_client, AuthorizationCode, CsrfToken, RequestTokenError, Scope,
-    TokenResponse,
-};
+use oauth2::CsrfToken;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Map, Value};
+use serde_json::json;
+use std::collections::BTreeMap;
 use strum::IntoStaticStr;
 use thiserror::Error;
 use tokio::time::Instant;
-use tracing::{log::error, warn};
+use tracing::{instrument, warn};
 use url::Url;
+use utoipa::IntoParams;
+use uuid::Uuid;
 
 #[derive(Debug, Error)]
 #[error("{payload}")]
@@ -42,12 +49,36 @@ pub enum AuthErrorPayload {
     UserAlreadyContributed,
     #[error("invalid authorization code")]
     InvalidAuthCode,
+    #[error("invalid base64 data in state parameter")]
+    InvalidStateEncoding,
+    #[error("invalid json in state parameter")]
+    InvalidStateJson,
+    #[error("sign-in nonce is unknown, expired or already used")]
+    InvalidNonce,
     #[error("could not fetch user data from auth server")]
     FetchUserDataError,
+    #[error("auth provider unavailable")]
+    ProviderUnavailable,
     #[error("could not extract user data from auth server")]
     CouldNotExtractUserData,
     #[error("user created after deadline")]
     UserCreatedAfterDeadline,
+    #[error("user sent too few transactions")]
+    TooFewTransactions,
+    #[error("user is banned from the ceremony")]
+    UserBanned,
+    #[error("unknown auth provider")]
+    UnknownProvider,
+    #[error("user is not eligible to participate")]
+    NotEligible,
+    #[error("the contribution quota of the auth provider is used up")]
+    ProviderQuotaReached,
+    #[error("no phase of the ceremony is open")]
+    PhaseClosed,
+    #[error("user is not eligible for the open phase of the ceremony")]
+    NotEligibleInPhase,
+    #[error(transparent)]
+    SpamFilter(#[from] SpamFilterError),
     #[error("storage error: {0}")]
     Storage(#[from] StorageError),
 }
@@ -58,24 +89,65 @@ impl ErrorCode for AuthErrorPayload {
     }
 }
 
-pub struct UserVerifiedResponse {
-    id_token:       IdToken,
-    session_id:     String,
-    as_redirect_to: Option<String>,
+impl AuthErrorPayload {
+    /// The rule that turned the participant away, for the auth funnel.
+    /// `None` if the sign-in failed instead.
+    const fn rejection_rule(&self) -> Option<&'static str> {
+        match self {
+            Self::NotEligible => Some("eligibility_score"),
+            Self::UserCreatedAfterDeadline => Some("created_before"),
+            Self::TooFewTransactions => Some("min_nonce"),
+            Self::ProviderQuotaReached => Some("max_contributions"),
+            Self::PhaseClosed => Some("phase_closed"),
+            Self::NotEligibleInPhase => Some("phase"),
+            Self::UserBanned => Some("banned"),
+            Self::UserAlreadyContributed => Some("already_contributed"),
+            Self::LobbyIsFull => Some("lobby_full"),
+            Self::SpamFilter(SpamFilterError::Vetoed { .. }) => Some("spam_filter"),
+            _ => None,
+        }
+    }
+
+    /// A copy of the error, for coalesced callbacks. `None` for storage
+    /// errors, which cannot be copied.
+    pub(crate) fn duplicate(&self) -> Option<Self> {
+        Some(match self {
+            Self::LobbyIsFull => Self::LobbyIsFull,
+            Self::UserAlreadyContributed => Self::UserAlreadyContributed,
+            Self::InvalidAuthCode => Self::InvalidAuthCode,
+            Self::InvalidStateEncoding => Self::InvalidStateEncoding,
+            Self::InvalidStateJson => Self::InvalidStateJson,
+            Self::InvalidNonce => Self::InvalidNonce,
+            Self::FetchUserDataError => Self::FetchUserDataError,
+            Self::ProviderUnavailable => Self::ProviderUnavailable,
+            Self::CouldNotExtractUserData => Self::CouldNotExtractUserData,
+            Self::UserCreatedAfterDeadline => Self::UserCreatedAfterDeadline,
+            Self::TooFewTransactions => Self::TooFewTransactions,
+            Self::UserBanned => Self::UserBanned,
+            Self::UnknownProvider => Self::UnknownProvider,
+            Self::NotEligible => Self::NotEligible,
+            Self::ProviderQuotaReached => Self::ProviderQuotaReached,
+            Self::PhaseClosed => Self::PhaseClosed,
+            Self::NotEligibleInPhase => Self::NotEligibleInPhase,
+            Self::SpamFilter(err) => Self::SpamFilter(err.clone()),
+            Self::Storage(_) => return None,
+        })
+    }
 }
 
-pub struct AuthUrl {
-    eth_auth_url:    String,
-    github_auth_url: String,
+#[derive(Clone)]
+pub struct UserVerifiedResponse {
+    id_token:              IdToken,
+    pub(crate) session_id: String,
+    as_redirect_to:        Option<String>,
 }
 
+/// Authorization urls keyed by `<provider>_auth_url`.
+pub struct AuthUrl(BTreeMap<String, String>);
+
 impl IntoResponse for AuthUrl {
     fn into_response(self) -> Response {
-        Json(json!({
-            "eth_auth_url": self.eth_auth_url,
-            "github_auth_url": self.github_auth_url,
-        }))
-        .into_response()
+        Json(self.0).into_response()
     }
 }
 
@@ -89,7 +161,7 @@ impl IntoResponse for UserVerifiedResponse {
                 redirect_url
                     .query_pairs_mut()
                     .append_pair("session_id", &self.session_id)
-                    .append_pair("sub", &self.id_token.identity.unique_id())
+                    .append_pair("sub", &self.id_token.unique_identifier())
                     .append_pair("nickname", &self.id_token.identity.nickname())
                     .append_pair("provider", &self.id_token.identity.provider_name())
                     .append_pair("exp", &self.id_token.exp.to_string());
@@ -97,7 +169,7 @@ impl IntoResponse for UserVerifiedResponse {
             }
             None => Json(json!({
                 "id_token" : {
-                    "sub": &self.id_token.identity.unique_id(),
+                    "sub": &self.id_token.unique_identifier(),
                     "nickname": &self.id_token.identity.nickname(),
                     "provider": &self.id_token.identity.provider_name(),
                     "exp": &self.id_token.exp,
@@ -109,14 +181,26 @@ impl IntoResponse for UserVerifiedResponse {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct AuthClientLinkQueryParams {
+    /// Where the callback redirects to with the session, instead of answering
+    /// with it.
     redirect_to: Option<String>,
+    /// Only hand out the link of this provider, so that the sign-in counts as
+    /// started for it alone in the auth funnel.
+    provider:    Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CsrfWithRedirect {
     redirect: Option<String>,
+    /// The ceremony that handed out the link. Identity providers always
+    /// call back the default ceremony, which forwards the callback.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ceremony: Option<String>,
+    /// Single use nonce for providers that require one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce:    Option<String>,
 }
 
 impl CsrfWithRedirect {
@@ -129,38 +213,80 @@ impl CsrfWithRedirect {
 
 // Returns the url that the user needs to call
 // in order to get an authorisation code
+#[utoipa::path(
+    get,
+    path = "/auth/request_link",
+    tag = "auth",
+    params(AuthClientLinkQueryParams),
+    responses(
+        (status = 200, description = "Sign-in url of each provider, keyed by `<provider>_auth_url`", body = BTreeMap<String, String>),
+        (status = 503, description = "Too many sessions", body = ErrorBody),
+    )
+)]
 pub async fn auth_client_link(
     Query(params): Query<AuthClientLinkQueryParams>,
     Extension(options): Extension<Options>,
     Extension(lobby_state): Extension<SharedLobbyState>,
-    Extension(eth_client): Extension<EthOAuthClient>,
-    Extension(gh_client): Extension<GithubOAuthClient>,
+    Extension(providers): Extension<AuthProviders>,
+    Extension(ceremony): Extension<CeremonyId>,
+    Extension(storage): Extension<PersistentStorage>,
+    spam_guard: SpamGuard,
 ) -> Result<AuthUrl, AuthErrorPayload> {
     let session_count = lobby_state.get_session_count().await;
 
-    if session_count >= options.lobby.max_sessions_count {
+    if session_count >= lobby_state.options().max_sessions_count {
         return Err(AuthErrorPayload::LobbyIsFull);
     }
-
+    spam_guard.check(SpamStage::RequestLink, None, None).await?;
+
+    let nonce = if providers.iter().any(|provider| provider.requires_nonce()) {
+        let nonce = Uuid::new_v4().simple().to_string();
+        let expires_at = Utc::now()
+            + chrono::Duration::from_std(options.ethereum.eth_auth_nonce_ttl)
+                .unwrap_or_else(|_| chrono::Duration::max_value());
+        storage.insert_auth_nonce(&nonce, expires_at).await?;
+        Some(nonce)
+    } else {
+        None
+    };
     let csrf_with_redirect = CsrfWithRedirect {
         redirect: params.redirect_to,
+        ceremony: ceremony.0,
+        nonce:    nonce.clone(),
     }
     .encode_into_csrf();
 
-    let eth_auth_request = eth_client
-        .authorize_url(|| csrf_with_redirect)
-        .add_scope(Scope::new("openid".to_string()));
-
-    let (auth_url, csrf_with_redirect) = eth_auth_request.url();
-
-    let gh_auth_request = gh_client.client.authorize_url(|| csrf_with_redirect);
-
-    let (gh_url, _) = gh_auth_request.url();
+    if let Some(provider) = &params.provider {
+        if providers.get(provider).is_none() {
+            return Err(AuthErrorPayload::UnknownProvider);
+        }
+    }
+    let providers = providers
+        .iter()
+        .filter(|provider| {
+            params
+                .provider
+                .as_deref()
+                .map_or(true, |name| name == provider.name())
+        })
+        .collect::<Vec<_>>();
+    for provider in &providers {
+        auth_funnel::record(&storage, provider.name(), Stage::Started).await;
+    }
+    let urls = providers
+        .iter()
+        .map(|provider| {
+            (
+                format!("{}_auth_url", provider.name()),
+                provider.authorize_url(
+                    csrf_with_redirect.clone(),
+                    nonce.as_deref().filter(|_| provider.requires_nonce()),
+                ),
+            )
+        })
+        .collect();
 
-    Ok(AuthUrl {
-        eth_auth_url:    auth_url.to_string(),
-        github_auth_url: gh_url.to_string(),
-    })
+    Ok(AuthUrl(urls))
 }
 
 // This is the payload that the client will send
@@ -178,6 +304,10 @@ pub struct RawAuthPayload {
 pub struct AuthPayload {
     code:        String,
     redirect_to: Option<String>,
+    ceremony:    CeremonyId,
+    nonce:       Option<String>,
+    /// The original query, for forwarding the callback to another ceremony.
+    query:       String,
 }
 
 #[async_trait]
@@ -188,282 +318,210 @@ where
     type Rejection = Response;
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let query = req.uri().query().unwrap_or_default().to_string();
         let Query(raw): Query<RawAuthPayload> = Query::from_request(req)
             .await
             .map_err(IntoResponse::into_response)?;
-        let decoded_state =
-            base64::decode_config(raw.state, base64::URL_SAFE_NO_PAD).map_err(|_| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(Value::Object(Map::from_iter([(
-                        "message".to_string(),
-                        Value::String("invalid base64 data in state parameter".to_string()),
-                    )]))),
-                )
-                    .into_response()
-            })?;
+        let decoded_state = base64::decode_config(raw.state, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| AuthErrorPayload::InvalidStateEncoding.into_response())?;
         let json_decoded_state =
-            serde_json::from_slice::<CsrfWithRedirect>(decoded_state.as_slice()).map_err(|_| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(Value::Object(Map::from_iter([(
-                        "message".to_string(),
-                        Value::String("invalid json in state parameter".to_string()),
-                    )]))),
-                )
-                    .into_response()
-            })?;
+            serde_json::from_slice::<CsrfWithRedirect>(decoded_state.as_slice())
+                .map_err(|_| AuthErrorPayload::InvalidStateJson.into_response())?;
         Ok(Self {
-            code:        raw.code,
+            code: raw.code,
             redirect_to: json_decoded_state.redirect,
+            ceremony: CeremonyId(json_decoded_state.ceremony),
+            nonce: json_decoded_state.nonce,
+            query,
         })
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct GhUserInfo {
-    id:         u64,
-    login:      String,
-    created_at: String,
-}
-
+// This endpoint allows one to consume an oAUTH authorisation code
+//  and produce a JWT token
 #[allow(clippy::too_many_arguments)]
-pub async fn github_callback(
+#[utoipa::path(
+    get,
+    path = "/auth/callback/{provider}",
+    tag = "auth",
+    params(
+        ("provider" = String, Path, description = "Name of the identity provider"),
+        ("code" = String, Query, description = "Authorization code of the provider"),
+        ("state" = String, Query, description = "State of the link from `/auth/request_link`"),
+    ),
+    responses(
+        (status = 200, description = "The new session", body = UserVerified),
+        (status = 303, description = "Redirect to `redirect_to` with the session as query parameters"),
+        (status = 400, description = "Invalid code or state, or already contributed", body = ErrorBody),
+        (status = 403, description = "Not eligible or banned", body = ErrorBody),
+    )
+)]
+#[instrument(level = "info", skip_all, fields(%provider))]
+pub async fn auth_callback(
+    Path(provider): Path<String>,
     payload: AuthPayload,
+    audit: Audit,
     Extension(options): Extension<Options>,
     Extension(auth_state): Extension<SharedAuthState>,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(storage): Extension<PersistentStorage>,
-    Extension(gh_oauth_client): Extension<GithubOAuthClient>,
+    Extension(providers): Extension<AuthProviders>,
     Extension(http_client): Extension<reqwest::Client>,
-) -> Result<UserVerifiedResponse, AuthError> {
-    let token = gh_oauth_client
-        .exchange_code(AuthorizationCode::new(payload.code))
-        .request_async(async_http_client)
-        .await
-        .map_err(|e| {
-            if let RequestTokenError::Parse(_, bytes) = e {
-                let response_str = String::from_utf8(bytes);
-                warn!("Unexpected Github Token Exchange response: {response_str:?}");
-            } else {
-                warn!("Github Token Exchange Error: {e}");
+    Extension(scorer): Extension<SharedScorer>,
+    Extension(provider_rules): Extension<SharedRuleSet>,
+    Extension(phases): Extension<SharedSchedule>,
+    Extension(ceremony): Extension<CeremonyId>,
+    Extension(callbacks): Extension<SharedCallbackCoalescer>,
+    spam_guard: SpamGuard,
+) -> Result<Response, AuthError> {
+    if payload.ceremony != ceremony {
+        let path = format!(
+            "{}{}/auth/callback/{provider}?{}",
+            options.server.path().trim_end_matches('/'),
+            payload.ceremony.path_prefix(),
+            payload.query
+        );
+        return Ok(Redirect::temporary(&path).into_response());
+    }
+    let redirect = payload.redirect_to.clone();
+    // Double-clicked sign-ins share the session of the first callback.
+    let code = payload.code.clone();
+    let callback = async {
+        let mut uid = None;
+        let mut funnel_provider = None;
+        let result = async {
+            let provider = providers
+                .get(&provider)
+                .ok_or(AuthErrorPayload::UnknownProvider)?;
+            funnel_provider = Some(provider.name());
+            auth_funnel::record(&storage, provider.name(), Stage::Callback).await;
+            let nonce = payload
+                .nonce
+                .as_deref()
+                .filter(|_| provider.requires_nonce());
+            if provider.requires_nonce() {
+                let consumed = match nonce {
+                    Some(nonce) => storage.consume_auth_nonce(nonce).await?,
+                    None => false,
+                };
+                if !consumed {
+                    return Err(AuthErrorPayload::InvalidNonce);
+                }
             }
-            AuthError {
-                redirect: payload.redirect_to.clone(),
-                payload:  AuthErrorPayload::InvalidAuthCode,
+            let (user, evidence) = provider
+                .authenticate(payload.code, nonce, &http_client)
+                .await?;
+            uid = Some(identity::uid(&user));
+            spam_guard
+                .check(SpamStage::AuthCallback, Some(&user), Some(&evidence))
+                .await?;
+            let eligibility = scorer.current().evaluate(&user, &evidence);
+            if !eligibility.eligible {
+                warn!(uid = %user, score = eligibility.score, "User is not eligible.");
+                return Err(AuthErrorPayload::NotEligible);
             }
-        })?;
-
-    let response = http_client
-        .get(options.github.gh_userinfo_url)
-        .bearer_auth(token.access_token().secret())
-        .header("User-Agent", "ethereum-kzg-ceremony-sequencer")
-        .send()
-        .await
-        .map_err(|_| AuthError {
-            redirect: payload.redirect_to.clone(),
-            payload:  AuthErrorPayload::FetchUserDataError,
-        })?;
-    let gh_user_info = response.json::<GhUserInfo>().await.map_err(|_| AuthError {
-        redirect: payload.redirect_to.clone(),
-        payload:  AuthErrorPayload::CouldNotExtractUserData,
-    })?;
-    let creation_time =
-        DateTime::parse_from_rfc3339(&gh_user_info.created_at).map_err(|_| AuthError {
-            redirect: payload.redirect_to.clone(),
-            payload:  AuthErrorPayload::CouldNotExtractUserData,
-        })?;
-    if creation_time > options.github.gh_max_account_creation_time {
-        return Err(AuthError {
-            redirect: payload.redirect_to.clone(),
-            payload:  AuthErrorPayload::UserCreatedAfterDeadline,
-        });
-    }
-    let user = Identity::Github {
-        id:       gh_user_info.id,
-        username: gh_user_info.login.clone(),
+            let rules = provider_rules.current();
+            match rules.check(&user, &evidence) {
+                Ok(()) => {}
+                Err(RuleViolation::CreatedAfterDeadline) => {
+                    return Err(AuthErrorPayload::UserCreatedAfterDeadline)
+                }
+                Err(RuleViolation::TooFewTransactions) => {
+                    return Err(AuthErrorPayload::TooFewTransactions)
+                }
+            }
+            if let Some(quota) = rules.quota(&identity::uid(&user)) {
+                if storage.count_contributions_of(quota.uid_prefix).await?
+                    >= quota.max_contributions
+                {
+                    return Err(AuthErrorPayload::ProviderQuotaReached);
+                }
+            }
+            match phases.current().check(&user, Utc::now()) {
+                Ok(()) => {}
+                Err(PhaseViolation::Closed) => return Err(AuthErrorPayload::PhaseClosed),
+                Err(PhaseViolation::NotEligible) => {
+                    return Err(AuthErrorPayload::NotEligibleInPhase)
+                }
+            }
+            let session = provider.session_id(&user);
+            post_authenticate(
+                auth_state,
+                lobby_state,
+                storage.clone(),
+                user,
+                eligibility,
+                payload.redirect_to,
+                &options,
+                session,
+            )
+            .await
+        }
+        .await;
+        if let Some(provider) = funnel_provider {
+            let stage = match &result {
+                Ok(_) => Stage::Session,
+                Err(payload) => payload
+                    .rejection_rule()
+                    .map_or(Stage::Failed, Stage::Rejected),
+            };
+            auth_funnel::record(&storage, provider, stage).await;
+        }
+        audit.record(AuditAction::Auth, uid, outcome(&result)).await;
+        result
     };
-    post_authenticate(
-        auth_state,
-        lobby_state,
-        storage,
-        user,
-        payload.redirect_to,
-        options.multi_contribution,
-    )
-    .await
-}
-
-#[derive(Debug, Deserialize)]
-struct EthUserInfo {
-    sub: String,
-}
-
-// This endpoint allows one to consume an oAUTH authorisation code
-//  and produce a JWT token
-// So Sequencer could give out fake identities, we are trusting the sequencer
-// to not do that.
-//
-// Now this is catchable by the client. They will clearly see that the sequencer
-// was malicious. What can happen is sequencer can claim that someone
-// participated when they did not. Is this Okay? Maybe that person can then just
-// say they did not
-#[allow(clippy::too_many_arguments)]
-pub async fn eth_callback(
-    payload: AuthPayload,
-    Extension(options): Extension<Options>,
-    Extension(auth_state): Extension<SharedAuthState>,
-    Extension(lobby_state): Extension<SharedLobbyState>,
-    Extension(storage): Extension<PersistentStorage>,
-    Extension(oauth_client): Extension<EthOAuthClient>,
-    Extension(http_client): Extension<reqwest::Client>,
-) -> Result<UserVerifiedResponse, AuthError> {
-    let token = oauth_client
-        .exchange_code(AuthorizationCode::new(payload.code))
-        .request_async(async_http_client)
-        .await
-        .map_err(|_| AuthError {
-            redirect: payload.redirect_to.clone(),
-            payload:  AuthErrorPayload::InvalidAuthCode,
-        })?;
-
-    let response = http_client
-        .get(&options.ethereum.eth_userinfo_url)
-        .bearer_auth(token.access_token().secret())
-        .send()
-        .await
-        .map_err(|_| AuthError {
-            redirect: payload.redirect_to.clone(),
-            payload:  AuthErrorPayload::FetchUserDataError,
-        })?;
-
-    let eth_user = response
-        .json::<EthUserInfo>()
+    callbacks
+        .run(&provider, &code, callback)
         .await
-        .map_err(|_| AuthError {
-            redirect: payload.redirect_to.clone(),
-            payload:  AuthErrorPayload::CouldNotExtractUserData,
-        })?;
-
-    let addr_parts: Vec<_> = eth_user.sub.split(':').collect();
-    let address = (*addr_parts.get(2).ok_or(AuthError {
-        redirect: payload.redirect_to.clone(),
-        payload:  AuthErrorPayload::CouldNotExtractUserData,
-    })?)
-    .to_string();
-
-    let tx_count = get_tx_count(
-        &address,
-        &options.ethereum.eth_nonce_verification_block,
-        &http_client,
-        &options.ethereum,
-    )
-    .await
-    .map_err(|e| {
-        error!("Could not get tx count for {address}: {e}");
-        AuthError {
-            redirect: payload.redirect_to.clone(),
-            payload:  AuthErrorPayload::CouldNotExtractUserData,
-        }
-    })?;
-
-    if tx_count < options.ethereum.eth_min_nonce {
-        return Err(AuthError {
-            redirect: payload.redirect_to.clone(),
-            payload:  AuthErrorPayload::UserCreatedAfterDeadline,
-        });
-    }
-
-    let user_data = Identity::eth_from_str(&address).map_err(|_| AuthError {
-        redirect: payload.redirect_to.clone(),
-        payload:  AuthErrorPayload::CouldNotExtractUserData,
-    })?;
-
-    post_authenticate(
-        auth_state,
-        lobby_state,
-        storage,
-        user_data,
-        payload.redirect_to,
-        options.multi_contribution,
-    )
-    .await
+        .map(IntoResponse::into_response)
+        .map_err(|payload| AuthError { redirect, payload })
 }
 
-// TODO: This has many failure modes and should return and eyre::Result.
-async fn get_tx_count(
-    address: &str,
-    at_block: &str,
-    client: &reqwest::Client,
-    options: &EthAuthOptions,
-) -> eyre::Result<u64> {
-    let rpc_payload = json!({
-        "id": 1,
-        "jsonrpc": "2.0",
-        "params": [&address, &at_block],
-        "method": "eth_getTransactionCount"
-    });
-
-    let rpc_response = client
-        .post(options.eth_rpc_url.get_secret())
-        .json(&rpc_payload)
-        .send()
-        .await?;
-
-    let rpc_response_json = rpc_response.json::<serde_json::Value>().await?;
-
-    let rpc_result = rpc_response_json
-        .get("result")
-        .ok_or(eyre!("malformed response JSON"))?
-        .as_str()
-        .ok_or(eyre!("malformed response JSON"))?;
-
-    let result = u64::from_str_radix(rpc_result.trim_start_matches("0x"), 16)?;
-    Ok(result)
-}
-
-async fn post_authenticate(
+/// Signs `user_data` in, with `new_session` unless they have a session
+/// already, or a random one.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn post_authenticate(
     auth_state: SharedAuthState,
     lobby_state: SharedLobbyState,
     storage: PersistentStorage,
     user_data: Identity,
+    eligibility: Eligibility,
     redirect_to: Option<String>,
-    multi_contribution: bool,
-) -> Result<UserVerifiedResponse, AuthError> {
+    options: &Options,
+    new_session: Option<SessionId>,
+) -> Result<UserVerifiedResponse, AuthErrorPayload> {
+    let uid = identity::uid(&user_data);
+    // Bans from before uids left out usernames are of legacy uids.
+    if storage.is_banned(&uid).await?
+        || storage.is_banned(&identity::legacy_uid(&user_data)).await?
+    {
+        return Err(AuthErrorPayload::UserBanned);
+    }
+
     // Check if they have already contributed
-    match storage.has_contributed(&user_data.unique_id()).await {
-        Err(error) => {
-            return Err(AuthError {
-                redirect: redirect_to.clone(),
-                payload:  AuthErrorPayload::Storage(error),
-            })
-        }
-        Ok(true) => {
-            if multi_contribution {
-                warn!(uid = %user_data, "User has already contributed, accepting multiple.");
-            } else {
-                return Err(AuthError {
-                    redirect: redirect_to.clone(),
-                    payload:  AuthErrorPayload::UserAlreadyContributed,
-                });
-            }
+    if storage.has_contributed(&uid).await? {
+        if options.multi_contribution {
+            warn!(uid = %user_data, "User has already contributed, accepting multiple.");
+        } else {
+            return Err(AuthErrorPayload::UserAlreadyContributed);
         }
-        Ok(false) => (),
     }
 
+    // Keeps the handle of renamed accounts current, and fails if another
+    // account is known by the uid.
+    storage.register_identity(&user_data).await?;
+
     // Check if this user is already in the lobby
     // If so, we send them back their session id
     let session_id = {
         let mut state = auth_state.write().await;
 
         #[allow(clippy::option_if_let_else)]
-        if let Some(session_id) = state.unique_id_session.get(&user_data.unique_id()) {
+        if let Some(session_id) = state.unique_id_session.get(&uid) {
             session_id.clone()
         } else {
-            let id = SessionId::new();
-            state
-                .unique_id_session
-                .insert(user_data.unique_id(), id.clone());
+            let id = new_session.unwrap_or_default();
+            state.unique_id_session.insert(uid.clone(), id.clone());
             id
         }
     };
@@ -478,12 +536,26 @@ async fn post_authenticate(
             token:                 id_token.clone(),
             last_ping_time:        Instant::now(),
             is_first_ping_attempt: true,
+            lobby_entered_at:      None,
+            eligibility_score:     Some(eligibility.score),
+            failed_rules:          Some(eligibility.failed_rules),
+            checkin_challenge:     None,
+            min_poll_interval:     None,
         })
         .await
-        .map_err(|_| AuthError {
-            redirect: redirect_to.clone(),
-            payload:  AuthErrorPayload::LobbyIsFull,
-        })?;
+        .map_err(|_| AuthErrorPayload::LobbyIsFull)?;
+    storage
+        .save_session(&StoredSession {
+            token_hash:        session_id.hash(),
+            uid:               id_token.unique_identifier(),
+            identity:          Some(id_token.identity.to_string()),
+            expires_at:        Utc::now()
+                + chrono::Duration::from_std(lobby_state.options().session_expiration)
+                    .unwrap_or_else(|_| chrono::Duration::max_value()),
+            lobby_entered_at:  None,
+            eligibility_score: Some(eligibility.score),
+        })
+        .await?;
 
     Ok(UserVerifiedResponse {
         id_token,
@@ -1,28 +1,74 @@
 use crate::{
-    io::{write_json_file, TranscriptIoError},
+    api::v1::error_response::ToApiError,
+    audit::{outcome, Audit, AuditAction},
+    auth_funnel::{self, Stage},
+    checkpoint::SharedCheckpointer,
+    chunked_upload::{ChunkedUploadError, SharedUploads, UploadPart, UploadStatus},
+    client_version::ClientHeader,
+    ens::SharedEnsResolver,
+    fingerprint::{self, FingerprintHeader},
+    geoip::SharedGeoIp,
+    handoff::SharedHandoff,
+    io::{sync_transcript, write_json_file, TranscriptIoError},
     keys::{SharedKeys, Signature, SignatureError},
-    lobby::SharedLobbyState,
+    lobby::{ActiveContributorError, LobbyEvent, SharedLobbyState},
+    metrics::{CONTRIBUTIONS_EXPIRED, CONTRIBUTIONS_FINISHED, VERIFICATION_LATENCY},
+    quarantine::{Rejection, SharedQuarantine},
     receipt::Receipt,
-    storage::{PersistentStorage, StorageError},
+    rejection::RejectionReason,
+    replication::{ReplicatedContribution, SharedReplicationLog},
+    reservation::{
+        Reservation, ReservationError, ReservationToken, SharedReservationSigner,
+        RESERVATION_HEADER,
+    },
+    sandbox::{self, SandboxError},
+    sessions::IdToken,
+    storage::{
+        PersistentStorage, StorageError, StoredContributionTimings, StoredReceipt,
+        StoredVerification,
+    },
+    transparency_log::{LogEntry, SharedTransparencyLog},
+    upload::{ContributionBody, Upload},
+    verification::{
+        self, ProgressReporter, SharedVerificationQueue, VerificationError, VerificationPool,
+        VerificationProgress, VerificationStage, VerificationStatus,
+    },
+    webhook::WebhookEvent,
     Engine, Options, SessionId, SharedCeremonyStatus, SharedTranscript,
 };
 use axum::{
-    response::{IntoResponse, Response},
+    extract::{Path, Query},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Extension, Json,
 };
 use axum_extra::response::ErasedJson;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures::Stream;
 use http::StatusCode;
-use kzg_ceremony_crypto::{BatchContribution, CeremoniesError, ErrorCode};
-use serde::Serialize;
-use std::sync::atomic::Ordering;
+use kzg_ceremony_crypto::{
+    signature::identity::Identity, BatchContribution, BatchTranscript, CeremoniesError, ErrorCode,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
 use strum::IntoStaticStr;
 use thiserror::Error;
 use tokio::task::JoinError;
-use tracing::error;
+use tracing::{error, field, instrument, Instrument, Span};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ContributeReceipt {
+    /// JSON encoded receipt, signed as is.
     receipt:   String,
+    #[schema(value_type = String)]
     signature: Signature,
 }
 
@@ -32,10 +78,48 @@ impl IntoResponse for ContributeReceipt {
     }
 }
 
+/// How the ECDSA signatures of Ethereum participants are checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EcdsaSignaturePolicy {
+    /// Drop invalid signatures from the transcript, as the spec requires.
+    Prune,
+    /// Reject contributions with an invalid signature.
+    RejectInvalid,
+    /// Reject contributions without a valid signature.
+    Require,
+}
+
+impl EcdsaSignaturePolicy {
+    /// Checks the signature of `contribution` made by `identity`. Signatures
+    /// of participants without an Ethereum address are always pruned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the policy rejects the contribution.
+    pub fn check(
+        self,
+        contribution: &BatchContribution,
+        identity: &Identity,
+    ) -> Result<(), CeremoniesError> {
+        if self == Self::Prune {
+            return Ok(());
+        }
+        let signed = contribution.verify_ecdsa_signature(identity)?;
+        if self == Self::Require && matches!(identity, Identity::Ethereum { .. }) && !signed {
+            return Err(CeremoniesError::MissingEcdsaSignature);
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Error, IntoStaticStr)]
 pub enum ContributeError {
     #[error("not your turn to participate")]
     NotUsersTurn,
+    #[error("no deadline extensions left")]
+    NoExtensionsLeft,
+    #[error("invalid reservation: {0}")]
+    InvalidReservation(#[from] ReservationError),
     #[error("contribution invalid: {0}")]
     InvalidContribution(#[from] CeremoniesError),
     #[error("receipt signing error: {0}")]
@@ -46,6 +130,8 @@ pub enum ContributeError {
     TranscriptIOError(#[from] TranscriptIoError),
     #[error("background task error: {0}")]
     TaskError(#[from] JoinError),
+    #[error("contribution not verified: {0}")]
+    Sandbox(#[from] SandboxError),
 }
 
 impl ErrorCode for ContributeError {
@@ -54,104 +140,938 @@ impl ErrorCode for ContributeError {
     }
 }
 
+impl ContributeError {
+    /// Why the contribution was rejected, if it was verified and failed.
+    #[must_use]
+    pub fn rejection_reason(&self) -> Option<RejectionReason> {
+        match self {
+            Self::InvalidContribution(error) => Some(error.into()),
+            _ => None,
+        }
+    }
+}
+
+/// Response of `/contribute`: the contribution is queued for verification.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ContributeAccepted {
+    pub verification_id: String,
+}
+
+impl IntoResponse for ContributeAccepted {
+    fn into_response(self) -> Response {
+        (StatusCode::ACCEPTED, Json(self)).into_response()
+    }
+}
+
+/// Where contributions and their contributors are recorded besides the
+/// transcript and the database. A single extension, as axum handlers take at
+/// most 16 arguments.
+#[derive(Clone)]
+pub struct ContributionRecorders {
+    pub checkpointer:     SharedCheckpointer,
+    pub geoip:            SharedGeoIp,
+    pub ens:              SharedEnsResolver,
+    pub quarantine:       SharedQuarantine,
+    pub replication:      SharedReplicationLog,
+    pub transparency_log: SharedTransparencyLog,
+    pub handoff:          SharedHandoff,
+}
+
 #[allow(clippy::too_many_arguments)]
+#[utoipa::path(
+    post,
+    path = "/contribute",
+    tag = "contribute",
+    request_body(content = BatchContribution, description = "The contribution, JSON or binary encoded"),
+    params(("X-Reservation-Token" = String, Header, description = "Reservation handed out with the slot")),
+    responses(
+        (status = 200, description = "The signed receipt", body = ContributeReceipt),
+        (status = 202, description = "Queued for verification, see `/contribute/status/{id}`", body = ContributeAccepted),
+        (status = 400, description = "Invalid contribution or reservation, or not the active contributor", body = ErrorBody),
+        (status = 413, description = "The body is too large", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+#[instrument(level = "info", skip_all, fields(uid = field::Empty))]
 pub async fn contribute(
     session_id: SessionId,
-    Json(contribution): Json<BatchContribution>,
+    ReservationToken(reservation): ReservationToken,
+    ClientHeader(client): ClientHeader,
+    FingerprintHeader(fingerprint_hash): FingerprintHeader,
+    ContributionBody(contribution): ContributionBody,
+    upload: Upload,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(options): Extension<Options>,
     Extension(shared_transcript): Extension<SharedTranscript>,
     Extension(storage): Extension<PersistentStorage>,
     Extension(num_contributions): Extension<SharedCeremonyStatus>,
     Extension(keys): Extension<SharedKeys>,
-) -> Result<ContributeReceipt, ContributeError> {
-    // Handle the contribution in the background, so that request cancelation
-    // doesn't interrupt it.
-    let res = tokio::spawn(async move {
-        let id_token = lobby_state
-            .begin_contributing(&session_id)
+    Extension(signer): Extension<SharedReservationSigner>,
+    Extension(queue): Extension<SharedVerificationQueue>,
+    Extension(recorders): Extension<ContributionRecorders>,
+    audit: Audit,
+) -> Result<ContributeAccepted, ContributeError> {
+    let ContributionRecorders {
+        checkpointer,
+        geoip,
+        ens,
+        quarantine,
+        replication,
+        transparency_log,
+        handoff,
+    } = recorders;
+    let reservation = signer.verify(&reservation)?;
+    let (contribution, payload_hash) = tokio::task::spawn_blocking(move || {
+        let hash = payload_hash(&contribution);
+        (contribution, hash)
+    })
+    .await?;
+    if let Some(verification_id) =
+        resubmitted_verification(&storage, &queue, &payload_hash, &reservation.uid).await?
+    {
+        return Ok(ContributeAccepted { verification_id });
+    }
+    // A contribution another sequencer sharing the database recorded since
+    // the slot was handed out makes the slot stale.
+    sync_transcript(
+        &options,
+        &storage,
+        &shared_transcript,
+        &num_contributions,
+        &lobby_state,
+    )
+    .await?;
+    if shared_transcript.read().await.num_participants() + 1 != reservation.slot {
+        return Err(ReservationError::WrongSlot.into());
+    }
+    let id_token = lobby_state
+        .begin_contributing(&session_id, &reservation.uid)
+        .await
+        .map_err(|_| ContributeError::NotUsersTurn)?
+        .token;
+    let uid = id_token.unique_identifier();
+    Span::current().record("uid", uid.as_str());
+    // Only the country is kept, the address is dropped with the request.
+    if let Some(country) = audit.ip.and_then(|ip| geoip.lookup(ip)) {
+        if let Err(err) = storage.set_contributor_country(&uid, country).await {
+            error!(?err, "failed to record contributor country");
+        }
+    }
+    if let Some(client) = client {
+        if let Err(err) = storage
+            .set_contributor_client_version(&uid, &client.to_string())
             .await
-            .map_err(|_| ContributeError::NotUsersTurn)?
-            .token;
+        {
+            error!(?err, "failed to record contributor client version");
+        }
+    }
+    let subnet = audit.ip.map(fingerprint::subnet);
+    if fingerprint_hash.is_some() || subnet.is_some() {
+        if let Err(err) = storage
+            .set_contributor_fingerprint(&uid, fingerprint_hash.as_deref(), subnet.as_deref())
+            .await
+        {
+            error!(?err, "failed to record contributor fingerprint");
+        }
+    }
+    ens.record(storage.clone(), uid.clone(), &id_token.identity);
 
-        let result = {
-            let mut transcript = shared_transcript.write().await;
-            transcript
-                .verify_add::<Engine>(contribution.clone(), id_token.identity.clone())
-                .map_err(ContributeError::InvalidContribution)
+    let mut record = StoredVerification {
+        payload_hash,
+        uid: uid.clone(),
+        verification_id: verification::new_id(),
+        status: None,
+    };
+    // Without the record a resubmission is rejected instead of answered with
+    // the original outcome, which is no reason to reject this submission.
+    if let Err(err) = storage.save_verification(&record).await {
+        error!(?err, "failed to record submitted contribution");
+    }
+    let verification_id = record.verification_id.clone();
+    let record_storage = storage.clone();
+    let captured = quarantine.is_enabled().then(|| contribution.clone());
+    let pool = queue.pool();
+    queue.submit(verification_id.clone(), |progress| async move {
+        let result = verify_contribution(
+            contribution,
+            upload,
+            progress,
+            id_token,
+            lobby_state,
+            options,
+            shared_transcript,
+            storage,
+            num_contributions,
+            keys,
+            checkpointer,
+            replication,
+            transparency_log,
+            handoff,
+            pool,
+        )
+        .await;
+        audit
+            .record(
+                AuditAction::ContributionSubmitted,
+                Some(uid.clone()),
+                outcome(&result),
+            )
+            .await;
+        let status = match result {
+            Ok(receipt) => VerificationStatus::Valid {
+                receipt:   receipt.receipt,
+                signature: receipt.signature.as_str().to_string(),
+            },
+            Err(err) => {
+                if matches!(
+                    err,
+                    ContributeError::ReceiptSigning(_)
+                        | ContributeError::StorageError(_)
+                        | ContributeError::TaskError(_)
+                ) {
+                    error!(?err, "unexpected error recording contribution");
+                }
+                let kind = match &err {
+                    ContributeError::InvalidContribution(e) => e.to_error_code(),
+                    err => err.to_error_code(),
+                };
+                let code = err.to_api_error().code().to_string();
+                if let (ContributeError::InvalidContribution(_), Some(contribution)) =
+                    (&err, captured)
+                {
+                    quarantine.capture(
+                        Rejection {
+                            uid,
+                            payload_hash: record.payload_hash.clone(),
+                            code: code.clone(),
+                            kind: kind.clone(),
+                            error: err.to_string(),
+                        },
+                        contribution,
+                    );
+                }
+                VerificationStatus::Invalid {
+                    code,
+                    kind,
+                    error: err.to_string(),
+                    reason: err.rejection_reason(),
+                }
+            }
         };
+        record.status = serde_json::to_string(&status).ok();
+        if let Err(err) = record_storage.save_verification(&record).await {
+            error!(?err, "failed to record verification outcome");
+        }
+        status
+    });
+    Ok(ContributeAccepted { verification_id })
+}
 
-        if let Err(e) = result {
+/// Hex encoded SHA-256 hash identifying a contribution payload.
+fn payload_hash(contribution: &BatchContribution) -> String {
+    let json = serde_json::to_vec(contribution).expect("contributions serialize");
+    hex::encode(Sha256::digest(json))
+}
+
+/// Id of the verification of an identical contribution submitted before by
+/// `uid`, so that clients retrying after a dropped response get the original
+/// outcome. Submissions whose verification was lost, e.g. in a restart, are
+/// not answered this way.
+async fn resubmitted_verification(
+    storage: &PersistentStorage,
+    queue: &SharedVerificationQueue,
+    payload_hash: &str,
+    uid: &str,
+) -> Result<Option<String>, StorageError> {
+    let stored = match storage.get_verification_by_hash(payload_hash).await? {
+        Some(stored) if stored.uid == uid => stored,
+        _ => return Ok(None),
+    };
+    if stored.status.is_some() || queue.status(&stored.verification_id).is_ok() {
+        Ok(Some(stored.verification_id))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Adds the contribution to the transcript and records it, or expires the
+/// contributor if the contribution is invalid.
+#[allow(clippy::too_many_arguments)]
+async fn verify_contribution(
+    contribution: BatchContribution,
+    upload: Upload,
+    progress: ProgressReporter,
+    id_token: IdToken,
+    lobby_state: SharedLobbyState,
+    options: Options,
+    shared_transcript: SharedTranscript,
+    storage: PersistentStorage,
+    num_contributions: SharedCeremonyStatus,
+    keys: SharedKeys,
+    checkpointer: SharedCheckpointer,
+    replication: SharedReplicationLog,
+    transparency_log: SharedTransparencyLog,
+    handoff: SharedHandoff,
+    pool: VerificationPool,
+) -> Result<ContributeReceipt, ContributeError> {
+    let result = {
+        // Run the pairing checks on the blocking pool, where they fan out to
+        // the rayon pool of the queue, one task per sub-ceremony, so they
+        // don't stall the runtime.
+        // The transcript is only read meanwhile, and swapped in one step
+        // after all sub-ceremonies passed.
+        let transcript = shared_transcript.clone().read_owned().await;
+        let policy = options.ecdsa_signature;
+        let contribution = contribution.clone();
+        let identity = id_token.identity.clone();
+        let verified = if options.sandbox.verification_sandbox {
+            let _timer = VERIFICATION_LATENCY.start_timer();
+            progress.verifying(contribution.contributions.len());
+            let sandboxed = match policy.check(&contribution, &identity) {
+                Ok(()) => {
+                    sandbox::verify(
+                        &options.sandbox,
+                        &transcript,
+                        &contribution,
+                        &identity,
+                        pool.threads(),
+                        |_, check| progress.checked(check),
+                    )
+                    .await
+                }
+                Err(e) => Ok(Err(e)),
+            };
+            // Released before the transcript is written below.
+            drop(transcript);
+            match sandboxed {
+                Ok(verified) => verified,
+                Err(err) => {
+                    // The contribution is not rejected, the worker may have
+                    // failed for reasons of its own, so the contributor can
+                    // rejoin the lobby.
+                    error!(?err, "failed to verify contribution in the sandbox");
+                    CONTRIBUTIONS_EXPIRED
+                        .with_label_values(&["unverified"])
+                        .inc();
+                    lobby_state.notify(WebhookEvent::ContributionExpired {
+                        uid:    id_token.unique_identifier(),
+                        reason: "unverified",
+                    });
+                    lobby_state.clear_current_contributor().await;
+                    storage
+                        .expire_contribution(&id_token.unique_identifier())
+                        .await?;
+                    return Err(ContributeError::Sandbox(err));
+                }
+            }
+        } else {
+            let progress = progress.clone();
+            tokio::task::spawn_blocking(move || {
+                let _timer = VERIFICATION_LATENCY.start_timer();
+                progress.verifying(contribution.contributions.len());
+                pool.install(|| {
+                    policy.check(&contribution, &identity).and_then(|()| {
+                        transcript.verify_observed::<Engine>(contribution, identity, &|_, check| {
+                            progress.checked(check);
+                        })
+                    })
+                })
+            })
+            .await?
+        };
+        match verified {
+            Ok(verified) => {
+                let mut transcript = shared_transcript.write().await;
+                progress.writing_transcript();
+                let transcript_write = Instant::now();
+                // Recorded before it joins the transcript, so that a failed
+                // write leaves the transcript as the database has it.
+                // Readers of the transcript wait for the write meanwhile.
+                let stored = if verified.num_participants() == transcript.num_participants() {
+                    storage.append_verified_entry(&verified).await
+                } else {
+                    Ok(())
+                };
+                stored.map(|()| {
+                    transcript
+                        .apply(verified)
+                        .map(|timings| (timings, transcript_write))
+                })
+            }
+            Err(e) => Ok(Err(e)),
+        }
+    };
+
+    let (verify_timings, transcript_write) = match result {
+        Err(e) => {
+            // The contribution is valid, but lost. It is not rejected, so the
+            // contributor can rejoin the lobby.
+            error!(?e, "failed to record contribution");
+            CONTRIBUTIONS_EXPIRED
+                .with_label_values(&["unrecorded"])
+                .inc();
+            lobby_state.notify(WebhookEvent::ContributionExpired {
+                uid:    id_token.unique_identifier(),
+                reason: "unrecorded",
+            });
             lobby_state.clear_current_contributor().await;
-            storage
+            if let Err(e) = storage
                 .expire_contribution(&id_token.unique_identifier())
+                .await
+            {
+                error!(?e, "failed to expire unrecorded contribution");
+            }
+            return Err(ContributeError::StorageError(e));
+        }
+        Ok(Ok(timings)) => {
+            // The next contributor gets the slot once this contribution is
+            // recorded, the base they start from is ready by then.
+            let transcript = shared_transcript.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handoff.prepare(&transcript).await {
+                    error!(?err, "failed to prepare the next contribution base");
+                }
+            });
+            timings
+        }
+        Ok(Err(e)) => {
+            CONTRIBUTIONS_EXPIRED.with_label_values(&["invalid"]).inc();
+            lobby_state.notify(WebhookEvent::ContributionExpired {
+                uid:    id_token.unique_identifier(),
+                reason: "invalid",
+            });
+            lobby_state.clear_current_contributor().await;
+            storage
+                .reject_contribution(&id_token.unique_identifier(), &RejectionReason::from(&e))
                 .await?;
-            return Err(e);
+            return Err(ContributeError::InvalidContribution(e));
         }
+    };
 
-        let result = write_json_file(
-            options.transcript_file,
-            options.transcript_in_progress_file,
-            shared_transcript,
-        )
+    let contribution_index = shared_transcript.read().await.num_participants();
+    checkpointer
+        .checkpoint(contribution_index, &shared_transcript)
         .await;
 
-        lobby_state.clear_current_contributor().await;
-        storage.finish_contribution(&session_id.0).await?;
+    let result = write_json_file(
+        options.transcript_file,
+        options.transcript_in_progress_file,
+        shared_transcript,
+    )
+    .await;
+
+    lobby_state.clear_current_contributor().await;
+    let reserved_index = storage
+        .finish_contribution(&id_token.unique_identifier())
+        .await?;
+    // Attempts that got the slot before the index was reserved in storage
+    // have none.
+    let contribution_index = match reserved_index {
+        Some(reserved_index) if reserved_index != contribution_index => {
+            error!(
+                reserved_index,
+                contribution_index, "reserved contribution index differs from the transcript"
+            );
+            reserved_index
+        }
+        Some(reserved_index) => reserved_index,
+        None => contribution_index,
+    };
+
+    if let Err(e) = result {
+        error!("failed to write transcript: {}", e);
+        return Err(ContributeError::TranscriptIOError(e));
+    }
+    let respond = Instant::now();
 
-        if let Err(e) = result {
-            error!("failed to write transcript: {}", e);
-            return Err(ContributeError::TranscriptIOError(e));
+    let num_contributions = num_contributions.fetch_add(1, Ordering::Relaxed) + 1;
+    CONTRIBUTIONS_FINISHED.inc();
+    auth_funnel::record(
+        &storage,
+        auth_funnel::provider_of(&id_token.identity),
+        Stage::Contributed,
+    )
+    .await;
+    lobby_state.publish(LobbyEvent::ContributionVerified { num_contributions });
+
+    let uid = id_token.unique_identifier();
+    lobby_state.notify(WebhookEvent::ContributionVerified {
+        uid: uid.clone(),
+        num_contributions,
+    });
+    let receipt = Receipt::new(id_token.identity.clone(), contribution_index, &contribution)
+        .map(|receipt| receipt.with_content_digest(upload.content_digest));
+    if let Ok(receipt) = &receipt {
+        if let Err(e) = transparency_log.append(&LogEntry::new(&uid, receipt)).await {
+            error!(%uid, "failed to append to the transparency log: {}", e);
         }
+    }
+    let signed = match receipt {
+        Ok(receipt) => receipt.sign(&keys).await,
+        Err(e) => Err(e),
+    }
+    .map_err(ContributeError::ReceiptSigning);
+    let stored_receipt = signed
+        .as_ref()
+        .ok()
+        .map(|(signed_msg, signature)| StoredReceipt {
+            receipt:   signed_msg.clone(),
+            signature: signature.as_str().to_string(),
+        });
 
-        num_contributions.fetch_add(1, Ordering::Relaxed);
+    // The contribution is already part of the transcript, so hand out the
+    // receipt even if it can't be stored for later download.
+    if let Some(stored_receipt) = &stored_receipt {
+        if let Err(e) = storage
+            .insert_receipt(&uid, contribution_index, stored_receipt)
+            .await
+        {
+            error!(%uid, "failed to store receipt: {}", e);
+        }
+    }
 
-        let receipt = Receipt {
-            identity: id_token.identity,
-            witness:  contribution.receipt(),
+    if replication.is_enabled() {
+        let started_at = storage
+            .get_contributor_by_uid(&uid)
+            .await
+            .map(|contributor| contributor.map(|contributor| contributor.started_at))
+            .unwrap_or_else(|e| {
+                error!(%uid, "failed to read contribution start: {}", e);
+                None
+            });
+        let replicated = ReplicatedContribution {
+            num_contributions,
+            uid: uid.clone(),
+            identity: Some(id_token.identity),
+            contribution,
+            started_at,
+            finished_at: Utc::now(),
+            receipt: stored_receipt,
         };
+        if let Err(e) = replication.record(&keys, &replicated).await {
+            error!(%uid, "failed to record contribution for replication: {}", e);
+        }
+    }
 
-        let (signed_msg, signature) = receipt
-            .sign(&keys)
-            .await
-            .map_err(ContributeError::ReceiptSigning)?;
+    let timings = StoredContributionTimings {
+        uid: uid.clone(),
+        contribution_index,
+        receive: upload.timings.receive,
+        deserialize: upload.timings.deserialize,
+        subgroup_check: verify_timings.points,
+        pairing: verify_timings.pairings,
+        transcript_write: respond - transcript_write,
+        respond: respond.elapsed(),
+        recorded_at: Utc::now(),
+    };
+    if let Err(e) = storage.insert_contribution_timings(&timings).await {
+        error!(%uid, "failed to store contribution timings: {}", e);
+    }
+
+    let (signed_msg, signature) = signed?;
+    Ok(ContributeReceipt {
+        receipt: signed_msg,
+        signature,
+    })
+}
+
+/// Reports the outcome of a queued contribution. Outcomes dropped from the
+/// queue's history are read from the database.
+#[utoipa::path(
+    get,
+    path = "/contribute/status/{id}",
+    tag = "contribute",
+    params(("id" = String, Path, description = "`verification_id` from `/contribute`")),
+    responses(
+        (status = 200, description = "Outcome of the verification", body = VerificationStatus),
+        (status = 404, description = "Unknown verification", body = ErrorBody),
+    )
+)]
+pub async fn contribute_status(
+    Path(id): Path<String>,
+    Extension(queue): Extension<SharedVerificationQueue>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<VerificationStatus>, VerificationError> {
+    if let Ok(status) = queue.status(&id) {
+        return Ok(Json(status));
+    }
+    storage
+        .get_verification(&id)
+        .await?
+        .and_then(|stored| stored.status)
+        .and_then(|status| serde_json::from_str(&status).ok())
+        .map(Json)
+        .ok_or(VerificationError::UnknownId)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ProgressQueryParams {
+    /// `verification_id` from `/contribute`.
+    pub id: String,
+}
+
+/// Streams the progress of a queued contribution as server-sent events: a
+/// `progress` event whenever a check finishes, and a final `status` event
+/// with the outcome, like `/contribute/status/{id}`, after which the stream
+/// ends.
+#[utoipa::path(
+    get,
+    path = "/contribute/progress",
+    tag = "contribute",
+    params(ProgressQueryParams),
+    responses(
+        (status = 200, description = "Stream of `progress` events, then one `status` event", content_type = "text/event-stream", body = VerificationProgress),
+        (status = 404, description = "Unknown or finished verification, see `/contribute/status/{id}`", body = ErrorBody),
+    )
+)]
+pub async fn contribute_progress(
+    Query(params): Query<ProgressQueryParams>,
+    Extension(queue): Extension<SharedVerificationQueue>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, VerificationError> {
+    let (progress, status) = queue.subscribe(&params.id)?;
+    let events = futures::stream::unfold(Some((progress, status, false)), |state| async move {
+        let (mut progress, status, seen) = state?;
+        // The job was dropped from the history.
+        if seen && progress.changed().await.is_err() {
+            return None;
+        }
+        let current = progress.borrow_and_update().clone();
+        if current.stage == VerificationStage::Finished {
+            let event = Event::default()
+                .event("status")
+                .json_data(&*status.borrow());
+            return Some((event, None));
+        }
+        let event = Event::default().event("progress").json_data(&current);
+        Some((event, Some((progress, status, true))))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ReceiptError {
+    #[error("no receipt found for this participant")]
+    NotFound,
+    #[error("storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
 
-        Ok(ContributeReceipt {
-            receipt: signed_msg,
-            signature,
-        })
+impl ErrorCode for ReceiptError {
+    fn to_error_code(&self) -> String {
+        format!("ReceiptError::{}", <&str>::from(self))
+    }
+}
+
+/// Returns the signed receipt of the latest contribution made by `uid`.
+#[utoipa::path(
+    get,
+    path = "/contribution/receipt/{uid}",
+    tag = "contribute",
+    params(("uid" = String, Path, description = "Unique id of the participant, e.g. `git|1234#acde033f`")),
+    responses(
+        (status = 200, description = "The signed receipt", body = ContributeReceipt),
+        (status = 404, description = "No receipt", body = ErrorBody),
+    )
+)]
+pub async fn receipt(
+    Path(uid): Path<String>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<ContributeReceipt, ReceiptError> {
+    let stored = storage
+        .get_receipt(&uid)
+        .await?
+        .ok_or(ReceiptError::NotFound)?;
+    Ok(ContributeReceipt {
+        receipt:   stored.receipt,
+        signature: Signature::from(stored.signature),
     })
+}
+
+/// Response of `/contribute/extend`. The new reservation is sent in the
+/// `X-Reservation-Token` header, as by `/lobby/try_contribute`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeadlineExtended {
+    /// Unix timestamp (in seconds) of the new deadline.
+    pub expires_at:      i64,
+    pub extensions_left: usize,
+    #[serde(skip)]
+    reservation:         String,
+}
+
+impl IntoResponse for DeadlineExtended {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::OK,
+            [(RESERVATION_HEADER, self.reservation.clone())],
+            Json(self),
+        )
+            .into_response()
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/contribute/extend",
+    tag = "contribute",
+    responses(
+        (status = 200, description = "The new deadline", body = DeadlineExtended,
+            headers(("X-Reservation-Token" = String, description = "Reservation that replaces the old one"))),
+        (status = 400, description = "Not the active contributor", body = ErrorBody),
+        (status = 409, description = "No extensions left", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+#[instrument(level = "info", skip_all)]
+pub async fn contribute_extend(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(signer): Extension<SharedReservationSigner>,
+    audit: Audit,
+) -> Result<DeadlineExtended, ContributeError> {
+    // Extend in the background, so that request cancelation doesn't interrupt
+    // it inbetween the lobby_state and storage calls.
+    tokio::spawn(
+        async move {
+            let slot = transcript.read().await.num_participants() + 1;
+            let mut extended = None;
+            let (token, extensions_left) = lobby_state
+                .extend_deadline(&session_id, |uid, valid_for| {
+                    let reservation = Reservation::new(uid.to_string(), slot, valid_for);
+                    let token = signer.sign(&reservation);
+                    extended = Some(reservation);
+                    token
+                })
+                .await
+                .map_err(|e| match e {
+                    ActiveContributorError::NoExtensionsLeft => ContributeError::NoExtensionsLeft,
+                    _ => ContributeError::NotUsersTurn,
+                })?;
+            let reservation = extended.expect("signed when extended");
+            let result = storage
+                .record_deadline_extension(&reservation.uid)
+                .await
+                .map_err(ContributeError::from);
+            audit
+                .record(
+                    AuditAction::DeadlineExtended,
+                    Some(reservation.uid),
+                    outcome(&result),
+                )
+                .await;
+            result?;
+            Ok(DeadlineExtended {
+                expires_at: reservation.expires_at,
+                extensions_left,
+                reservation: token,
+            })
+        }
+        .in_current_span(),
+    )
     .await
-    .unwrap_or_else(|e| Err(ContributeError::TaskError(e)));
-    if let Err(err) = &res {
-        if matches!(
-            err,
-            ContributeError::ReceiptSigning(_)
-                | ContributeError::StorageError(_)
-                | ContributeError::TaskError(_)
-        ) {
-            error!(?err, "unexpected error recording contribution");
+    .unwrap_or_else(|e| Err(ContributeError::TaskError(e)))
+}
+
+/// Response of `/contribute/deadline`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ContributionDeadline {
+    /// When the slot expires unless the contribution arrives, by the
+    /// sequencer's clock.
+    #[schema(value_type = String)]
+    pub deadline:     DateTime<Utc>,
+    pub remaining_ms: u64,
+}
+
+impl ContributionDeadline {
+    fn from_time_left(time_left: Duration) -> Self {
+        let remaining_ms = u64::try_from(time_left.as_millis()).unwrap_or(u64::MAX);
+        let deadline = Utc::now()
+            + chrono::Duration::from_std(time_left)
+                .unwrap_or_else(|_| chrono::Duration::max_value());
+        Self {
+            deadline,
+            remaining_ms,
         }
     }
-    res
 }
 
+#[utoipa::path(
+    get,
+    path = "/contribute/deadline",
+    tag = "contribute",
+    responses(
+        (status = 200, description = "Time left to contribute", body = ContributionDeadline),
+        (status = 400, description = "Not the active contributor", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+#[instrument(level = "info", skip_all)]
+pub async fn contribute_deadline(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+) -> Result<Json<ContributionDeadline>, ContributeError> {
+    let time_left = lobby_state
+        .time_left(&session_id)
+        .await
+        .map_err(|_| ContributeError::NotUsersTurn)?;
+    Ok(Json(ContributionDeadline::from_time_left(time_left)))
+}
+
+/// Shows that the active contributor is still computing, see
+/// `--contributor-heartbeat-timeout`. Answers like `/contribute/deadline`.
+#[utoipa::path(
+    post,
+    path = "/contribute/heartbeat",
+    tag = "contribute",
+    responses(
+        (status = 200, description = "Time left to contribute", body = ContributionDeadline),
+        (status = 400, description = "Not the active contributor", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+#[instrument(level = "info", skip_all)]
+pub async fn contribute_heartbeat(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+) -> Result<Json<ContributionDeadline>, ContributeError> {
+    let time_left = lobby_state
+        .heartbeat(&session_id)
+        .await
+        .map_err(|_| ContributeError::NotUsersTurn)?;
+    Ok(Json(ContributionDeadline::from_time_left(time_left)))
+}
+
+/// Stores a part of a contribution uploaded in parts, see
+/// [`crate::chunked_upload`]. Counts as a heartbeat.
+#[utoipa::path(
+    post,
+    path = "/contribute/upload/{part}",
+    tag = "contribute",
+    request_body(content = String, description = "The bytes of the part of the body of `/contribute`", content_type = "application/octet-stream"),
+    params(("part" = u32, Path, description = "Number of the part, from 0")),
+    responses(
+        (status = 200, description = "The parts received so far", body = UploadStatus),
+        (status = 400, description = "Invalid part number, or not the active contributor", body = ErrorBody),
+        (status = 413, description = "The part or the upload is too large", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+#[instrument(level = "info", skip_all)]
+pub async fn contribute_upload_part(
+    session_id: SessionId,
+    Path(part): Path<u32>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(uploads): Extension<SharedUploads>,
+    Extension(options): Extension<Options>,
+    UploadPart(bytes): UploadPart,
+) -> Result<Json<UploadStatus>, ChunkedUploadError> {
+    lobby_state
+        .heartbeat(&session_id)
+        .await
+        .map_err(|_| ChunkedUploadError::NotUsersTurn)?;
+    uploads
+        .put(&session_id, part, bytes, options.upload.max_body_size)
+        .map(Json)
+}
+
+/// The parts of the contribution uploaded so far.
+#[utoipa::path(
+    get,
+    path = "/contribute/upload",
+    tag = "contribute",
+    responses(
+        (status = 200, description = "The parts received so far", body = UploadStatus),
+        (status = 404, description = "No upload of this session", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+#[instrument(level = "info", skip_all)]
+pub async fn contribute_upload_status(
+    session_id: SessionId,
+    Extension(uploads): Extension<SharedUploads>,
+) -> Result<Json<UploadStatus>, ChunkedUploadError> {
+    uploads
+        .status(&session_id)
+        .map(Json)
+        .ok_or(ChunkedUploadError::UnknownUpload)
+}
+
+/// Checks the structure of a contribution (the number of powers, point
+/// encodings and subgroup membership) without a session or the contribution
+/// slot, so that clients can test their serialization.
+#[utoipa::path(
+    post,
+    path = "/contribute/validate",
+    tag = "contribute",
+    request_body(content = BatchContribution, description = "The contribution, JSON or binary encoded"),
+    responses(
+        (status = 200, description = "The contribution is well formed"),
+        (status = 400, description = "Invalid contribution", body = ErrorBody),
+        (status = 413, description = "The body is too large", body = ErrorBody),
+    )
+)]
+#[instrument(level = "info", skip_all)]
+pub async fn contribute_validate(
+    ContributionBody(contribution): ContributionBody,
+    Extension(options): Extension<Options>,
+) -> Result<(), ContributeError> {
+    tokio::task::spawn_blocking(move || {
+        // Only the sizes of the transcript matter, so validating against a
+        // fresh one does not hold up contributions waiting for the lock.
+        BatchTranscript::new(options.ceremony_sizes.sizes()).validate::<Engine>(&contribution)
+    })
+    .await?
+    .map_err(ContributeError::InvalidContribution)
+}
+
+#[utoipa::path(
+    post,
+    path = "/contribute/abort",
+    tag = "contribute",
+    responses(
+        (status = 200, description = "The slot is free"),
+        (status = 400, description = "Not the active contributor", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+#[instrument(level = "info", skip_all)]
 pub async fn contribute_abort(
     session_id: SessionId,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(storage): Extension<PersistentStorage>,
+    audit: Audit,
 ) -> Result<(), ContributeError> {
     // Abort the contribution in the background,
     // so that request cancelation doesn't interrupt it inbetween the lobby_state
     // and storage calls.
-    tokio::spawn(async move {
-        lobby_state
-            .abort_contribution(&session_id)
-            .await
-            .map_err(|_| ContributeError::NotUsersTurn)?;
-        storage.expire_contribution(&session_id.0).await?;
-        Ok(())
-    })
+    tokio::spawn(
+        async move {
+            let token = lobby_state
+                .abort_contribution(&session_id)
+                .await
+                .map_err(|_| ContributeError::NotUsersTurn)?;
+            CONTRIBUTIONS_EXPIRED.with_label_values(&["aborted"]).inc();
+            let uid = token.unique_identifier();
+            lobby_state.notify(WebhookEvent::ContributionExpired {
+                uid:    uid.clone(),
+                reason: "aborted",
+            });
+            let result = storage
+                .expire_contribution(&uid)
+                .await
+                .map_err(ContributeError::from);
+            audit
+                .record(
+                    AuditAction::ContributionAborted,
+                    Some(uid),
+                    outcome(&result),
+                )
+                .await;
+            result
+        }
+        .in_current_span(),
+    )
     .await
     .unwrap_or_else(|e| Err(ContributeError::TaskError(e)))
 }
@@ -164,19 +1084,33 @@ mod tests {
             contribute::ContributeError,
             lobby::{try_contribute, TryContributeError, TryContributeResponse},
         },
+        checkpoint::Checkpointer,
         contribute,
-        io::read_json_file,
+        contribution_format::ContributionEncoding,
+        io::{read_json_file, CeremonySizes},
         keys,
         keys::SharedKeys,
         lobby::SharedLobbyState,
+        phases::SharedSchedule,
+        proof_of_work::PowSolution,
+        quotas::SharedRuleSet,
+        reservation::{Reservation, ReservationSigner},
+        spam_filter::SpamGuard,
         storage::storage_client,
-        test_util::{create_test_session_info, test_options},
+        test_util::{create_test_session_info, test_jwt, test_options},
         tests::{invalid_contribution, test_transcript, valid_contribution},
+        transparency_log::TransparencyLog,
+        verification::VerificationQueue,
         Keys, SessionId,
     };
-    use axum::{Extension, Json};
+    use axum::Extension;
     use clap::Parser;
-    use kzg_ceremony_crypto::{signature::identity::Identity, BatchTranscript};
+    use ethers_signers::{LocalWallet, Signer};
+    use http::HeaderMap;
+    use kzg_ceremony_crypto::{
+        signature::{identity::Identity, ContributionTypedData, EcdsaSignature},
+        CeremonyError,
+    };
     use std::{
         sync::{atomic::AtomicUsize, Arc},
         time::Duration,
@@ -188,6 +1122,41 @@ mod tests {
         Arc::new(Keys::new(&options).unwrap())
     }
 
+    fn checkpointer(options: &Options) -> SharedCheckpointer {
+        Arc::new(Checkpointer::new(&options.checkpoint).unwrap())
+    }
+
+    fn verification_queue(options: &Options) -> SharedVerificationQueue {
+        Arc::new(VerificationQueue::new(&options.verification))
+    }
+
+    async fn transparency_log(db: &PersistentStorage) -> SharedTransparencyLog {
+        Arc::new(TransparencyLog::load(db.clone()).await.unwrap())
+    }
+
+    fn recorders(
+        options: &Options,
+        transparency_log: SharedTransparencyLog,
+    ) -> ContributionRecorders {
+        ContributionRecorders {
+            checkpointer: checkpointer(options),
+            geoip: SharedGeoIp::default(),
+            ens: SharedEnsResolver::default(),
+            quarantine: SharedQuarantine::default(),
+            replication: SharedReplicationLog::default(),
+            transparency_log,
+            handoff: SharedHandoff::default(),
+        }
+    }
+
+    fn reservation(signer: &ReservationSigner, slot: usize) -> ReservationToken {
+        ReservationToken(signer.sign(&Reservation::new(
+            test_jwt(0).unique_identifier(),
+            slot,
+            Duration::from_secs(60),
+        )))
+    }
+
     #[tokio::test]
     async fn rejects_out_of_turn_contribution() {
         let opts = test_options();
@@ -195,15 +1164,26 @@ mod tests {
         let lobby_state = SharedLobbyState::new(opts.lobby.clone());
         let transcript = test_transcript();
         let contrbution = valid_contribution(&transcript, 1);
+        let signer = Arc::new(ReservationSigner::default());
+        let queue = verification_queue(&opts);
+        let log = transparency_log(&db).await;
         let result = contribute(
             SessionId::new(),
-            Json(contrbution),
+            reservation(&signer, 1),
+            ClientHeader::default(),
+            FingerprintHeader::default(),
+            ContributionBody(contrbution),
+            Upload::default(),
             Extension(lobby_state),
-            Extension(opts),
+            Extension(opts.clone()),
             Extension(Arc::new(RwLock::new(transcript))),
             Extension(db),
             Extension(Arc::new(AtomicUsize::new(0))),
             Extension(shared_keys()),
+            Extension(signer.clone()),
+            Extension(queue.clone()),
+            Extension(recorders(&opts, log)),
+            Audit::default(),
         )
         .await;
         assert!(matches!(result, Err(ContributeError::NotUsersTurn)));
@@ -221,25 +1201,41 @@ mod tests {
             .unwrap();
         lobby_state.enter_lobby(&participant).await.unwrap();
         lobby_state
-            .set_current_contributor(&participant, opts.lobby.compute_deadline, db.clone())
+            .set_current_contributor(&participant, 1, String::new(), opts.lobby.compute_deadline)
             .await
             .unwrap();
         let transcript = test_transcript();
         let contribution = invalid_contribution(&transcript, 1);
+        let signer = Arc::new(ReservationSigner::default());
+        let queue = verification_queue(&opts);
+        let log = transparency_log(&db).await;
         let result = contribute(
             participant,
-            Json(contribution),
+            reservation(&signer, 1),
+            ClientHeader::default(),
+            FingerprintHeader::default(),
+            ContributionBody(contribution),
+            Upload::default(),
             Extension(lobby_state),
-            Extension(opts),
+            Extension(opts.clone()),
             Extension(Arc::new(RwLock::new(transcript))),
             Extension(db),
             Extension(Arc::new(AtomicUsize::new(0))),
             Extension(shared_keys()),
+            Extension(signer.clone()),
+            Extension(queue.clone()),
+            Extension(recorders(&opts, log)),
+            Audit::default(),
         )
-        .await;
+        .await
+        .unwrap();
+        let status = queue.wait(&result.verification_id).await.unwrap();
         assert!(matches!(
-            result,
-            Err(ContributeError::InvalidContribution(_))
+            status,
+            VerificationStatus::Invalid { code, kind, reason, .. }
+                if code == "SEQ-CONTRIB-002"
+                    && kind.starts_with("CeremonyError::")
+                    && reason == Some(RejectionReason::Malformed)
         ));
     }
 
@@ -274,6 +1270,9 @@ mod tests {
             transcript
         };
         let shared_transcript = Arc::new(RwLock::new(transcript));
+        let signer = Arc::new(ReservationSigner::default());
+        let queue = verification_queue(&cfg);
+        let log = transparency_log(&db).await;
 
         lobby_state
             .insert_session(participant.clone(), create_test_session_info(100))
@@ -282,22 +1281,32 @@ mod tests {
         lobby_state.enter_lobby(&participant).await.unwrap();
 
         lobby_state
-            .set_current_contributor(&participant, cfg.lobby.compute_deadline, db.clone())
+            .set_current_contributor(&participant, 1, String::new(), cfg.lobby.compute_deadline)
             .await
             .unwrap();
         let result = contribute(
             participant.clone(),
-            Json(contribution_1),
+            reservation(&signer, 1),
+            ClientHeader::default(),
+            FingerprintHeader::default(),
+            ContributionBody(contribution_1),
+            Upload::default(),
             Extension(lobby_state.clone()),
             Extension(cfg.clone()),
             Extension(shared_transcript.clone()),
             Extension(db.clone()),
             Extension(Arc::new(AtomicUsize::new(0))),
             Extension(keys.clone()),
+            Extension(signer.clone()),
+            Extension(queue.clone()),
+            Extension(recorders(&cfg, log.clone())),
+            Audit::default(),
         )
-        .await;
+        .await
+        .unwrap();
 
-        assert!(matches!(result, Ok(_)));
+        let status = queue.wait(&result.verification_id).await.unwrap();
+        assert!(matches!(status, VerificationStatus::Valid { .. }));
         let transcript = read_json_file::<BatchTranscript>(cfg.transcript_file.clone())
             .await
             .unwrap();
@@ -308,26 +1317,112 @@ mod tests {
             .unwrap();
         lobby_state.enter_lobby(&participant).await.unwrap();
         lobby_state
-            .set_current_contributor(&participant, cfg.lobby.compute_deadline, db.clone())
+            .set_current_contributor(&participant, 1, String::new(), cfg.lobby.compute_deadline)
             .await
             .unwrap();
+        // The reservation for the previous slot can not be used again.
         let result = contribute(
             participant.clone(),
-            Json(contribution_2),
-            Extension(lobby_state),
+            reservation(&signer, 1),
+            ClientHeader::default(),
+            FingerprintHeader::default(),
+            ContributionBody(contribution_2.clone()),
+            Upload::default(),
+            Extension(lobby_state.clone()),
             Extension(cfg.clone()),
             Extension(shared_transcript.clone()),
             Extension(db.clone()),
             Extension(Arc::new(AtomicUsize::new(0))),
             Extension(keys.clone()),
+            Extension(signer.clone()),
+            Extension(queue.clone()),
+            Extension(recorders(&cfg, log.clone())),
+            Audit::default(),
         )
         .await;
+        assert!(matches!(
+            result,
+            Err(ContributeError::InvalidReservation(
+                ReservationError::WrongSlot
+            ))
+        ));
+        let result = contribute(
+            participant.clone(),
+            reservation(&signer, 2),
+            ClientHeader::default(),
+            FingerprintHeader::default(),
+            ContributionBody(contribution_2.clone()),
+            Upload::default(),
+            Extension(lobby_state.clone()),
+            Extension(cfg.clone()),
+            Extension(shared_transcript.clone()),
+            Extension(db.clone()),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(keys.clone()),
+            Extension(signer.clone()),
+            Extension(queue.clone()),
+            Extension(recorders(&cfg, log.clone())),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
 
-        assert!(matches!(result, Ok(_)));
+        let status = queue.wait(&result.verification_id).await.unwrap();
+        let receipt_2 = match &status {
+            VerificationStatus::Valid { receipt, .. } => receipt.clone(),
+            status => panic!("unexpected status {status:?}"),
+        };
         let transcript = read_json_file::<BatchTranscript>(cfg.transcript_file.clone())
             .await
             .unwrap();
         assert_eq!(transcript, transcript_2);
+        assert_eq!(db.read_transcript().await.unwrap(), Some(transcript_2));
+        assert_eq!(log.tree_head(None).await.tree_size, 2);
+        let timings = db.contribution_timings(10).await.unwrap();
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].contribution_index, 2);
+
+        // Resubmitting the same contribution returns the original outcome, also
+        // once the queue forgot about it.
+        let resubmitted = contribute(
+            participant.clone(),
+            reservation(&signer, 2),
+            ClientHeader::default(),
+            FingerprintHeader::default(),
+            ContributionBody(contribution_2),
+            Upload::default(),
+            Extension(lobby_state),
+            Extension(cfg.clone()),
+            Extension(shared_transcript.clone()),
+            Extension(db.clone()),
+            Extension(Arc::new(AtomicUsize::new(0))),
+            Extension(keys.clone()),
+            Extension(signer.clone()),
+            Extension(queue.clone()),
+            Extension(recorders(&cfg, log.clone())),
+            Audit::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resubmitted.verification_id, result.verification_id);
+        let Json(restored) = contribute_status(
+            Path(result.verification_id),
+            Extension(verification_queue(&cfg)),
+            Extension(db.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(restored, status);
+
+        let stored = receipt(Path(test_jwt(0).unique_identifier()), Extension(db.clone()))
+            .await
+            .unwrap();
+        assert_eq!(stored.receipt, receipt_2);
+        assert!(stored.receipt.contains("\"contribution_index\":2"));
+        keys.verify(&stored.receipt, &stored.signature).unwrap();
+
+        let missing = receipt(Path("git|1|nobody".to_string()), Extension(db)).await;
+        assert!(matches!(missing, Err(ReceiptError::NotFound)));
     }
 
     #[tokio::test]
@@ -352,16 +1447,27 @@ mod tests {
         lobby_state.enter_lobby(&other_session_id).await.unwrap();
 
         lobby_state
-            .set_current_contributor(&session_id, opts.lobby.compute_deadline, db.clone())
+            .set_current_contributor(&session_id, 1, String::new(), opts.lobby.compute_deadline)
             .await
             .unwrap();
 
         let contribution_in_progress_response = try_contribute(
             other_session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await;
 
@@ -374,6 +1480,7 @@ mod tests {
             session_id,
             Extension(lobby_state.clone()),
             Extension(db.clone()),
+            Audit::default(),
         )
         .await
         .unwrap();
@@ -383,13 +1490,187 @@ mod tests {
 
         let success_response = try_contribute(
             other_session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await;
 
         assert!(matches!(success_response, Ok(TryContributeResponse { .. })));
     }
+
+    #[tokio::test]
+    async fn extends_deadline_once() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+        let signer = Arc::new(ReservationSigner::default());
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&session_id).await.unwrap();
+        lobby_state
+            .set_current_contributor(&session_id, 1, String::new(), opts.lobby.compute_deadline)
+            .await
+            .unwrap();
+        let extend = |session_id: SessionId| {
+            contribute_extend(
+                session_id,
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(signer.clone()),
+                Audit::default(),
+            )
+        };
+
+        let before = contribute_deadline(session_id.clone(), Extension(lobby_state.clone()))
+            .await
+            .unwrap();
+        let extended = extend(session_id.clone()).await.unwrap();
+        assert_eq!(extended.extensions_left, 0);
+        let reservation = signer.verify(&extended.reservation).unwrap();
+        assert_eq!(reservation.slot, 1);
+        assert_eq!(reservation.expires_at, extended.expires_at);
+        let after = contribute_deadline(session_id.clone(), Extension(lobby_state.clone()))
+            .await
+            .unwrap();
+        assert!(after.remaining_ms > before.remaining_ms);
+        assert!(after.deadline > before.deadline);
+
+        assert!(matches!(
+            extend(session_id).await,
+            Err(ContributeError::NoExtensionsLeft)
+        ));
+        assert!(matches!(
+            extend(SessionId::new()).await,
+            Err(ContributeError::NotUsersTurn)
+        ));
+        assert!(matches!(
+            contribute_deadline(SessionId::new(), Extension(lobby_state.clone())).await,
+            Err(ContributeError::NotUsersTurn)
+        ));
+    }
+
+    #[tokio::test]
+    async fn validates_contribution_format() {
+        let mut opts = test_options();
+        opts.ceremony_sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+        let validate = |contribution| {
+            contribute_validate(ContributionBody(contribution), Extension(opts.clone()))
+        };
+
+        let transcript = test_transcript();
+        validate(valid_contribution(&transcript, 1)).await.unwrap();
+        assert!(matches!(
+            validate(invalid_contribution(&transcript, 1)).await,
+            Err(ContributeError::InvalidContribution(
+                CeremoniesError::InvalidCeremony(0, CeremonyError::ZeroPubkey)
+            ))
+        ));
+        let other_size = BatchTranscript::new(&[(8, 2)]);
+        assert!(matches!(
+            validate(valid_contribution(&other_size, 1)).await,
+            Err(ContributeError::InvalidContribution(
+                CeremoniesError::InvalidCeremony(0, CeremonyError::UnexpectedNumG1Powers(4, 8))
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn checks_ecdsa_signatures() {
+        let wallet = LocalWallet::new(&mut rand::thread_rng());
+        let identity = Identity::Ethereum {
+            address: wallet.address().0,
+        };
+        let mut contribution = valid_contribution(&test_transcript(), 1);
+        let unsigned = contribution.clone();
+        contribution.ecdsa_signature = EcdsaSignature(Some(
+            wallet
+                .sign_typed_data(&ContributionTypedData::from(&contribution))
+                .await
+                .unwrap(),
+        ));
+        let other = Identity::Ethereum { address: [1; 20] };
+
+        for policy in [
+            EcdsaSignaturePolicy::Prune,
+            EcdsaSignaturePolicy::RejectInvalid,
+            EcdsaSignaturePolicy::Require,
+        ] {
+            assert!(policy.check(&contribution, &identity).is_ok());
+        }
+        assert!(EcdsaSignaturePolicy::Prune
+            .check(&contribution, &other)
+            .is_ok());
+        assert!(matches!(
+            EcdsaSignaturePolicy::RejectInvalid.check(&contribution, &other),
+            Err(CeremoniesError::InvalidEcdsaSignature)
+        ));
+        assert!(EcdsaSignaturePolicy::RejectInvalid
+            .check(&unsigned, &identity)
+            .is_ok());
+        assert!(matches!(
+            EcdsaSignaturePolicy::Require.check(&unsigned, &identity),
+            Err(CeremoniesError::MissingEcdsaSignature)
+        ));
+        assert!(EcdsaSignaturePolicy::Require
+            .check(&unsigned, &Identity::None)
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn streams_verification_progress() {
+        let queue = verification_queue(&test_options());
+        let id = verification::new_id();
+        queue.submit(id.clone(), |progress| async move {
+            progress.verifying(1);
+            VerificationStatus::Invalid {
+                code:   "code".to_string(),
+                kind:   "kind".to_string(),
+                error:  "error".to_string(),
+                reason: None,
+            }
+        });
+        queue.wait(&id).await.unwrap();
+        let response =
+            contribute_progress(Query(ProgressQueryParams { id }), Extension(queue.clone()))
+                .await
+                .unwrap()
+                .into_response();
+        assert_eq!(
+            response.headers()[http::header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        // A progress event may come first, the outcome is always last.
+        assert!(body.contains("event: status\ndata: {\"status\":\"invalid\""));
+
+        assert!(matches!(
+            contribute_progress(
+                Query(ProgressQueryParams {
+                    id: "unknown".to_string(),
+                }),
+                Extension(queue),
+            )
+            .await,
+            Err(VerificationError::UnknownId)
+        ));
+    }
 }
@@ -1,58 +1,433 @@
+//! Error responses of the API.
+//!
+//! Every error is answered with a JSON body of the form
+//!
+//! ```json
+//! {
+//!   "code": "SEQ-LOBBY-002",
+//!   "kind": "TryContributeError::RateLimited",
+//!   "error": "call came too early. rate limited",
+//!   "request_id": "5f0c9a4e-8d7b-4c3e-9a51-2b7e04d3c6f1",
+//!   "retry_after_ms": 18000
+//! }
+//! ```
+//!
+//! `code` is one of the stable codes listed in [`ApiError`] and is what
+//! clients should branch on. `kind` names the error in the code base and
+//! `error` is a message for humans, both may change between releases.
+//! `request_id` is the [correlation id](crate::request_id) of the request.
+//! Errors of the lobby that tell the client when to try again have
+//! `retry_after_ms`, and the same in seconds, rounded up, in `Retry-After`.
+
 use super::{
+    account::AccountError,
+    admin::AdminError,
     auth::{AuthError, AuthErrorPayload},
-    contribute::ContributeError,
-    lobby::TryContributeError,
+    contribute::{ContributeError, ReceiptError},
+    info::{ContributorError, LotteryError, TranscriptDiffError},
+    lobby::{NotificationTargetError, TryContributeError, WaitingRoomError},
+    transcript::TranscriptPageError,
+};
+use crate::{
+    checkin_proof::{CheckinProofError, CHECKIN_CHALLENGE_HEADER},
+    chunked_upload::ChunkedUploadError,
+    client_version::ClientVersionError,
+    content_digest::{WANTED_DIGESTS, WANT_CONTENT_DIGEST, WANT_REPR_DIGEST},
+    feature_flags::FeatureFlagError,
+    keys::{KeyRotationError, SignatureError},
+    load_shedding::LoadShedError,
+    metrics::AUTH_FAILURES,
+    mirror::MirrorError,
+    proof_of_work::{ProofOfWorkError, POW_CHALLENGE_HEADER},
+    rate_limit::RateLimitError,
+    replication::ReplicationLogError,
+    request_id,
+    request_limits::RequestLimitError,
+    request_signing::RequestSignatureError,
+    reservation::ReservationError,
+    sandbox::SandboxError,
+    sessions::SessionError,
+    spam_filter::SpamFilterError,
+    storage::StorageError,
+    transcript_format::TranscriptFormatError,
+    transparency_log::TransparencyLogError,
+    upload::UploadError,
+    verification::VerificationError,
+    witness_chain::ChainReportError,
 };
-use crate::{keys::SignatureError, sessions::SessionError};
 use axum::{
     response::{IntoResponse, Redirect, Response},
     Json,
 };
 use http::StatusCode;
 use kzg_ceremony_crypto::{CeremoniesError, ErrorCode};
-use serde_json::json;
+use serde::Serialize;
 use std::fmt::Display;
+use strum::EnumIter;
 use url::Url;
+use utoipa::ToSchema;
 
-fn error_to_json<Err: Display + ErrorCode>(error: &Err) -> Json<serde_json::Value> {
-    Json(json!({
-        "code": error.to_error_code(),
-        "error": error.to_string()
-    }))
+/// The errors the API can respond with, each with a stable code and status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum ApiError {
+    UnknownSession,
+    InvalidAuthCode,
+    InvalidAuthState,
+    UnknownProvider,
+    IdentityProviderFailed,
+    AccountTooNew,
+    NotEligible,
+    UserBanned,
+    AlreadyContributed,
+    ProviderQuotaReached,
+    PhaseClosed,
+    NotEligibleInPhase,
+    AuthProviderUnavailable,
+    SpamVetoed,
+    SpamFilterUnavailable,
+    LobbyFull,
+    LobbyRateLimited,
+    /// Not an error for clients waiting in the lobby, so it is sent with
+    /// status 200. They keep pinging until they get the slot.
+    AnotherContributionInProgress,
+    /// Sent with status 200, like [`Self::AnotherContributionInProgress`].
+    LobbyPaused,
+    ShuttingDown,
+    LobbyStoreUnavailable,
+    CeremonyClosed,
+    /// Sent with status 200, like [`Self::AnotherContributionInProgress`].
+    /// The participant may stop pinging and wait to be told.
+    InWaitingRoom,
+    InvalidEmail,
+    NotInWaitingRoom,
+    ProofOfWorkRequired,
+    InvalidProofOfWork,
+    /// Sent with status 200, like [`Self::AnotherContributionInProgress`].
+    Requeued,
+    /// Sent with status 200, like [`Self::AnotherContributionInProgress`].
+    NotAdmitted,
+    LotteryDrawNotFound,
+    NotificationsDisabled,
+    InvalidNotificationTarget,
+    NotificationChannelUnavailable,
+    NoNotificationTarget,
+    CheckinProofRequired,
+    InvalidCheckinProof,
+    NotUsersTurn,
+    InvalidContribution,
+    MissingReservation,
+    InvalidReservation,
+    ReservationExpired,
+    WrongSlot,
+    UnknownVerification,
+    ReceiptNotFound,
+    NoExtensionsLeft,
+    ContributorNotFound,
+    ContributionNotVerified,
+    InvalidSignatureEncoding,
+    InvalidSignature,
+    SigningFailed,
+    UnknownCeremony,
+    TranscriptSerialization,
+    TranscriptIo,
+    InvalidTranscriptRange,
+    InvalidLogRange,
+    AdminUnauthorized,
+    NoActiveContributor,
+    ContributionBeingVerified,
+    ContributorActive,
+    InvalidTranscript,
+    InvalidRequestSignature,
+    NotStandby,
+    Standby,
+    InvalidBeacon,
+    BeaconApplied,
+    AdminForbidden,
+    UnknownAdminKey,
+    AdminKeyExists,
+    KeyRotationUnsupported,
+    FeatureDisabled,
+    UnknownFeatureFlag,
+    InvalidFeatureFlag,
+    AccountContributionInProgress,
+    ReplicationEvicted,
+    ReplicationDisabled,
+    MirrorNotSynced,
+    TooManyRequests,
+    Overloaded,
+    RequestTimedOut,
+    LoadShedding,
+    BodyTooLarge,
+    UnsupportedContentType,
+    InvalidJson,
+    BodyReadFailed,
+    InvalidBinaryContribution,
+    UnsupportedContentEncoding,
+    InvalidUploadPart,
+    UnknownUpload,
+    IncompleteUpload,
+    UploadDigestMismatch,
+    ContentDigestMismatch,
+    InvalidContentDigest,
+    InvalidClientVersion,
+    ClientTooOld,
+    Database,
+    CorruptData,
+    DatabaseClosed,
+    DatabaseDegraded,
+    UidCollision,
+    Internal,
 }
 
-impl IntoResponse for SignatureError {
-    fn into_response(self) -> Response {
+impl ApiError {
+    #[must_use]
+    pub const fn code(self) -> &'static str {
+        self.spec().0
+    }
+
+    #[must_use]
+    pub const fn status(self) -> StatusCode {
+        self.spec().1
+    }
+
+    const fn spec(self) -> (&'static str, StatusCode) {
         match self {
-            Self::SignatureCreation => {
-                (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self)).into_response()
+            Self::UnknownSession => ("SEQ-AUTH-001", StatusCode::UNAUTHORIZED),
+            Self::InvalidAuthCode => ("SEQ-AUTH-002", StatusCode::BAD_REQUEST),
+            Self::InvalidAuthState => ("SEQ-AUTH-003", StatusCode::BAD_REQUEST),
+            Self::UnknownProvider => ("SEQ-AUTH-004", StatusCode::NOT_FOUND),
+            Self::IdentityProviderFailed => ("SEQ-AUTH-005", StatusCode::INTERNAL_SERVER_ERROR),
+            Self::AccountTooNew => ("SEQ-AUTH-006", StatusCode::UNAUTHORIZED),
+            Self::NotEligible => ("SEQ-AUTH-007", StatusCode::FORBIDDEN),
+            Self::UserBanned => ("SEQ-AUTH-008", StatusCode::FORBIDDEN),
+            Self::AlreadyContributed => ("SEQ-AUTH-009", StatusCode::BAD_REQUEST),
+            Self::ProviderQuotaReached => ("SEQ-AUTH-010", StatusCode::FORBIDDEN),
+            Self::PhaseClosed => ("SEQ-AUTH-011", StatusCode::FORBIDDEN),
+            Self::NotEligibleInPhase => ("SEQ-AUTH-012", StatusCode::FORBIDDEN),
+            Self::AuthProviderUnavailable => ("SEQ-AUTH-013", StatusCode::SERVICE_UNAVAILABLE),
+            Self::SpamVetoed => ("SEQ-AUTH-014", StatusCode::FORBIDDEN),
+            Self::SpamFilterUnavailable => ("SEQ-AUTH-015", StatusCode::SERVICE_UNAVAILABLE),
+            Self::LobbyFull => ("SEQ-LOBBY-001", StatusCode::SERVICE_UNAVAILABLE),
+            Self::LobbyRateLimited => ("SEQ-LOBBY-002", StatusCode::TOO_MANY_REQUESTS),
+            Self::AnotherContributionInProgress => ("SEQ-LOBBY-003", StatusCode::OK),
+            Self::LobbyPaused => ("SEQ-LOBBY-004", StatusCode::OK),
+            Self::ShuttingDown => ("SEQ-LOBBY-005", StatusCode::SERVICE_UNAVAILABLE),
+            Self::LobbyStoreUnavailable => ("SEQ-LOBBY-006", StatusCode::SERVICE_UNAVAILABLE),
+            Self::CeremonyClosed => ("SEQ-LOBBY-007", StatusCode::GONE),
+            Self::InWaitingRoom => ("SEQ-LOBBY-008", StatusCode::OK),
+            Self::InvalidEmail => ("SEQ-LOBBY-009", StatusCode::BAD_REQUEST),
+            Self::NotInWaitingRoom => ("SEQ-LOBBY-010", StatusCode::CONFLICT),
+            Self::ProofOfWorkRequired => ("SEQ-LOBBY-011", StatusCode::PRECONDITION_REQUIRED),
+            Self::InvalidProofOfWork => ("SEQ-LOBBY-012", StatusCode::BAD_REQUEST),
+            Self::Requeued => ("SEQ-LOBBY-013", StatusCode::OK),
+            Self::NotAdmitted => ("SEQ-LOBBY-014", StatusCode::OK),
+            Self::LotteryDrawNotFound => ("SEQ-LOBBY-015", StatusCode::NOT_FOUND),
+            Self::NotificationsDisabled => ("SEQ-LOBBY-016", StatusCode::NOT_FOUND),
+            Self::InvalidNotificationTarget => ("SEQ-LOBBY-017", StatusCode::BAD_REQUEST),
+            Self::NotificationChannelUnavailable => ("SEQ-LOBBY-018", StatusCode::BAD_REQUEST),
+            Self::NoNotificationTarget => ("SEQ-LOBBY-019", StatusCode::NOT_FOUND),
+            Self::CheckinProofRequired => ("SEQ-LOBBY-020", StatusCode::PRECONDITION_REQUIRED),
+            Self::InvalidCheckinProof => ("SEQ-LOBBY-021", StatusCode::BAD_REQUEST),
+            Self::NotUsersTurn => ("SEQ-CONTRIB-001", StatusCode::BAD_REQUEST),
+            Self::InvalidContribution => ("SEQ-CONTRIB-002", StatusCode::BAD_REQUEST),
+            Self::MissingReservation => ("SEQ-CONTRIB-003", StatusCode::BAD_REQUEST),
+            Self::InvalidReservation => ("SEQ-CONTRIB-004", StatusCode::BAD_REQUEST),
+            Self::ReservationExpired => ("SEQ-CONTRIB-005", StatusCode::BAD_REQUEST),
+            Self::WrongSlot => ("SEQ-CONTRIB-006", StatusCode::BAD_REQUEST),
+            Self::UnknownVerification => ("SEQ-CONTRIB-007", StatusCode::NOT_FOUND),
+            Self::ReceiptNotFound => ("SEQ-CONTRIB-008", StatusCode::NOT_FOUND),
+            Self::NoExtensionsLeft => ("SEQ-CONTRIB-009", StatusCode::CONFLICT),
+            Self::ContributorNotFound => ("SEQ-CONTRIB-010", StatusCode::NOT_FOUND),
+            Self::ContributionNotVerified => ("SEQ-CONTRIB-011", StatusCode::BAD_REQUEST),
+            Self::InvalidSignatureEncoding => ("SEQ-SIG-001", StatusCode::BAD_REQUEST),
+            Self::InvalidSignature => ("SEQ-SIG-002", StatusCode::BAD_REQUEST),
+            Self::SigningFailed => ("SEQ-SIG-003", StatusCode::INTERNAL_SERVER_ERROR),
+            Self::UnknownCeremony => ("SEQ-TRANSCRIPT-001", StatusCode::BAD_REQUEST),
+            Self::TranscriptSerialization => {
+                ("SEQ-TRANSCRIPT-002", StatusCode::INTERNAL_SERVER_ERROR)
             }
-            Self::InvalidToken => (StatusCode::BAD_REQUEST, error_to_json(&self)).into_response(),
-            Self::InvalidSignature => {
-                (StatusCode::BAD_REQUEST, error_to_json(&self)).into_response()
+            Self::TranscriptIo => ("SEQ-TRANSCRIPT-003", StatusCode::INTERNAL_SERVER_ERROR),
+            Self::InvalidTranscriptRange => ("SEQ-TRANSCRIPT-004", StatusCode::BAD_REQUEST),
+            Self::InvalidLogRange => ("SEQ-TRANSCRIPT-005", StatusCode::BAD_REQUEST),
+            Self::AdminUnauthorized => ("SEQ-ADMIN-001", StatusCode::UNAUTHORIZED),
+            Self::NoActiveContributor => ("SEQ-ADMIN-002", StatusCode::CONFLICT),
+            Self::ContributionBeingVerified => ("SEQ-ADMIN-003", StatusCode::CONFLICT),
+            Self::ContributorActive => ("SEQ-ADMIN-004", StatusCode::CONFLICT),
+            Self::InvalidTranscript => ("SEQ-ADMIN-005", StatusCode::INTERNAL_SERVER_ERROR),
+            Self::InvalidRequestSignature => ("SEQ-ADMIN-006", StatusCode::UNAUTHORIZED),
+            Self::NotStandby => ("SEQ-ADMIN-007", StatusCode::CONFLICT),
+            Self::Standby => ("SEQ-ADMIN-008", StatusCode::CONFLICT),
+            Self::InvalidBeacon => ("SEQ-ADMIN-009", StatusCode::BAD_REQUEST),
+            Self::BeaconApplied => ("SEQ-ADMIN-010", StatusCode::CONFLICT),
+            Self::AdminForbidden => ("SEQ-ADMIN-011", StatusCode::FORBIDDEN),
+            Self::UnknownAdminKey => ("SEQ-ADMIN-012", StatusCode::NOT_FOUND),
+            Self::AdminKeyExists => ("SEQ-ADMIN-013", StatusCode::CONFLICT),
+            Self::KeyRotationUnsupported => ("SEQ-ADMIN-014", StatusCode::CONFLICT),
+            Self::FeatureDisabled => ("SEQ-FEATURE-001", StatusCode::NOT_FOUND),
+            Self::UnknownFeatureFlag => ("SEQ-FEATURE-002", StatusCode::NOT_FOUND),
+            Self::InvalidFeatureFlag => ("SEQ-FEATURE-003", StatusCode::BAD_REQUEST),
+            Self::AccountContributionInProgress => ("SEQ-ACCOUNT-001", StatusCode::CONFLICT),
+            Self::ReplicationEvicted => ("SEQ-REPL-001", StatusCode::GONE),
+            Self::ReplicationDisabled => ("SEQ-REPL-002", StatusCode::NOT_FOUND),
+            Self::MirrorNotSynced => ("SEQ-MIRROR-001", StatusCode::SERVICE_UNAVAILABLE),
+            Self::TooManyRequests => ("SEQ-RATE-001", StatusCode::TOO_MANY_REQUESTS),
+            Self::Overloaded => ("SEQ-LIMIT-001", StatusCode::SERVICE_UNAVAILABLE),
+            Self::RequestTimedOut => ("SEQ-LIMIT-002", StatusCode::GATEWAY_TIMEOUT),
+            Self::LoadShedding => ("SEQ-LIMIT-003", StatusCode::TOO_MANY_REQUESTS),
+            Self::BodyTooLarge => ("SEQ-UPLOAD-001", StatusCode::PAYLOAD_TOO_LARGE),
+            Self::UnsupportedContentType => ("SEQ-UPLOAD-002", StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            Self::InvalidJson => ("SEQ-UPLOAD-003", StatusCode::BAD_REQUEST),
+            Self::BodyReadFailed => ("SEQ-UPLOAD-004", StatusCode::BAD_REQUEST),
+            Self::InvalidBinaryContribution => ("SEQ-UPLOAD-005", StatusCode::BAD_REQUEST),
+            Self::UnsupportedContentEncoding => {
+                ("SEQ-UPLOAD-006", StatusCode::UNSUPPORTED_MEDIA_TYPE)
             }
+            Self::InvalidUploadPart => ("SEQ-UPLOAD-007", StatusCode::BAD_REQUEST),
+            Self::UnknownUpload => ("SEQ-UPLOAD-008", StatusCode::NOT_FOUND),
+            Self::IncompleteUpload => ("SEQ-UPLOAD-009", StatusCode::CONFLICT),
+            Self::UploadDigestMismatch => ("SEQ-UPLOAD-010", StatusCode::BAD_REQUEST),
+            Self::ContentDigestMismatch => ("SEQ-UPLOAD-011", StatusCode::BAD_REQUEST),
+            Self::InvalidContentDigest => ("SEQ-UPLOAD-012", StatusCode::BAD_REQUEST),
+            Self::InvalidClientVersion => ("SEQ-CLIENT-001", StatusCode::BAD_REQUEST),
+            Self::ClientTooOld => ("SEQ-CLIENT-002", StatusCode::UPGRADE_REQUIRED),
+            Self::Database => ("SEQ-DB-001", StatusCode::INTERNAL_SERVER_ERROR),
+            Self::CorruptData => ("SEQ-DB-002", StatusCode::INTERNAL_SERVER_ERROR),
+            Self::DatabaseClosed => ("SEQ-DB-003", StatusCode::SERVICE_UNAVAILABLE),
+            Self::DatabaseDegraded => ("SEQ-DB-004", StatusCode::SERVICE_UNAVAILABLE),
+            Self::UidCollision => ("SEQ-DB-005", StatusCode::CONFLICT),
+            Self::Internal => ("SEQ-INTERNAL-001", StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+}
+
+/// Maps an error to the [`ApiError`] it is reported as.
+pub trait ToApiError: Display + ErrorCode {
+    fn to_api_error(&self) -> ApiError;
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ErrorBody {
+    /// Stable code of the error, see the table in the Readme.
+    #[schema(value_type = String, example = "SEQ-LOBBY-003")]
+    code:           &'static str,
+    kind:           String,
+    error:          String,
+    /// To match the error against the server logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id:     Option<String>,
+    /// Milliseconds to wait before trying again.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_ms: Option<u64>,
+}
+
+fn error_body(api_error: ApiError, kind: String, error: String) -> Json<ErrorBody> {
+    Json(ErrorBody {
+        code: api_error.code(),
+        kind,
+        error,
+        request_id: request_id::current(),
+        retry_after_ms: None,
+    })
+}
+
+/// [`error_response`] asking to try again after `retry_after_ms`, in the
+/// body and in `Retry-After`.
+fn retry_response<Err: ToApiError>(error: &Err, retry_after_ms: u64) -> Response {
+    let api_error = error.to_api_error();
+    let Json(mut body) = error_body(api_error, error.to_error_code(), error.to_string());
+    body.retry_after_ms = Some(retry_after_ms);
+    let retry_after_secs = retry_after_ms / 1000 + u64::from(retry_after_ms % 1000 > 0);
+    (
+        api_error.status(),
+        [(http::header::RETRY_AFTER, retry_after_secs.to_string())],
+        Json(body),
+    )
+        .into_response()
+}
+
+fn error_response<Err: ToApiError>(error: &Err) -> Response {
+    let api_error = error.to_api_error();
+    (
+        api_error.status(),
+        error_body(api_error, error.to_error_code(), error.to_string()),
+    )
+        .into_response()
+}
+
+impl ToApiError for SignatureError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::SignatureCreation => ApiError::SigningFailed,
+            Self::InvalidToken => ApiError::InvalidSignatureEncoding,
+            Self::InvalidSignature => ApiError::InvalidSignature,
+        }
+    }
+}
+
+impl ToApiError for KeyRotationError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Unsupported => ApiError::KeyRotationUnsupported,
+            Self::InvalidKeyFile(_) | Self::Io(_) => ApiError::Internal,
+        }
+    }
+}
+
+impl IntoResponse for SignatureError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for SessionError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::InvalidSessionId => ApiError::UnknownSession,
         }
     }
 }
 
 impl IntoResponse for SessionError {
     fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for StorageError {
+    fn to_api_error(&self) -> ApiError {
         match self {
-            Self::InvalidSessionId => {
-                (StatusCode::BAD_REQUEST, error_to_json(&self)).into_response()
-            }
+            Self::DatabaseError(_) => ApiError::Database,
+            Self::SerializationError(_)
+            | Self::InvalidIdentity(_)
+            | Self::CorruptTranscript(_)
+            | Self::CorruptRow(_) => ApiError::CorruptData,
+            Self::Closed => ApiError::DatabaseClosed,
+            Self::Degraded => ApiError::DatabaseDegraded,
+            Self::UidCollision(_) => ApiError::UidCollision,
+            Self::NoPseudonymKey => ApiError::FeatureDisabled,
         }
     }
 }
 
+impl IntoResponse for StorageError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
         let redirect_url = self.redirect.and_then(|r| Url::parse(&r).ok());
         match redirect_url {
             Some(mut redirect_url) => {
+                AUTH_FAILURES
+                    .with_label_values(&[&self.payload.to_error_code()])
+                    .inc();
                 redirect_url
                     .query_pairs_mut()
-                    .append_pair("code", &self.payload.to_error_code())
+                    .append_pair("code", self.payload.to_api_error().code())
+                    .append_pair("kind", &self.payload.to_error_code())
                     .append_pair("error", &format!("{}", self.payload));
 
                 Redirect::to(redirect_url.as_str()).into_response()
@@ -62,52 +437,640 @@ impl IntoResponse for AuthError {
     }
 }
 
-impl IntoResponse for AuthErrorPayload {
-    fn into_response(self) -> Response {
-        let (status, body) = match self {
+impl ToApiError for AuthErrorPayload {
+    fn to_api_error(&self) -> ApiError {
+        match self {
             Self::FetchUserDataError | Self::CouldNotExtractUserData => {
-                (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self))
+                ApiError::IdentityProviderFailed
             }
-            Self::LobbyIsFull => (StatusCode::SERVICE_UNAVAILABLE, error_to_json(&self)),
-            Self::InvalidAuthCode | Self::UserAlreadyContributed => {
-                (StatusCode::BAD_REQUEST, error_to_json(&self))
+            Self::LobbyIsFull => ApiError::LobbyFull,
+            Self::InvalidAuthCode => ApiError::InvalidAuthCode,
+            Self::InvalidStateEncoding | Self::InvalidStateJson | Self::InvalidNonce => {
+                ApiError::InvalidAuthState
             }
-            Self::UserCreatedAfterDeadline => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
-            Self::Storage(storage_error) => return storage_error.into_response(),
-        };
-        (status, body).into_response()
+            Self::UserAlreadyContributed => ApiError::AlreadyContributed,
+            Self::UserCreatedAfterDeadline => ApiError::AccountTooNew,
+            Self::UserBanned => ApiError::UserBanned,
+            Self::NotEligible | Self::TooFewTransactions => ApiError::NotEligible,
+            Self::ProviderQuotaReached => ApiError::ProviderQuotaReached,
+            Self::PhaseClosed => ApiError::PhaseClosed,
+            Self::NotEligibleInPhase => ApiError::NotEligibleInPhase,
+            Self::UnknownProvider => ApiError::UnknownProvider,
+            Self::ProviderUnavailable => ApiError::AuthProviderUnavailable,
+            Self::SpamFilter(err) => err.to_api_error(),
+            Self::Storage(err) => err.to_api_error(),
+        }
+    }
+}
+
+impl IntoResponse for AuthErrorPayload {
+    fn into_response(self) -> Response {
+        AUTH_FAILURES
+            .with_label_values(&[&self.to_error_code()])
+            .inc();
+        match self {
+            Self::Storage(storage_error) => storage_error.into_response(),
+            _ => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for ContributeError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::NotUsersTurn => ApiError::NotUsersTurn,
+            Self::NoExtensionsLeft => ApiError::NoExtensionsLeft,
+            Self::InvalidReservation(err) => err.to_api_error(),
+            Self::InvalidContribution(_) => ApiError::InvalidContribution,
+            Self::ReceiptSigning(err) => err.to_api_error(),
+            Self::StorageError(err) => err.to_api_error(),
+            Self::TranscriptIOError(_) => ApiError::TranscriptIo,
+            Self::TaskError(_) => ApiError::Internal,
+            Self::Sandbox(err) => err.to_api_error(),
+        }
     }
 }
 
 impl IntoResponse for ContributeError {
     fn into_response(self) -> Response {
-        let (status, body) = match self {
-            Self::NotUsersTurn => (StatusCode::BAD_REQUEST, error_to_json(&self)),
-            Self::InvalidContribution(e) => return CeremoniesErrorFormatter(e).into_response(),
-            Self::ReceiptSigning(err) => return err.into_response(),
-            Self::StorageError(err) => return err.into_response(),
-            Self::TaskError(_) | Self::TranscriptIOError(_) => {
-                (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self))
+        match self {
+            Self::InvalidReservation(err) => err.into_response(),
+            Self::InvalidContribution(e) => CeremoniesErrorFormatter(e).into_response(),
+            Self::ReceiptSigning(err) => err.into_response(),
+            Self::StorageError(err) => err.into_response(),
+            Self::NotUsersTurn
+            | Self::NoExtensionsLeft
+            | Self::TaskError(_)
+            | Self::TranscriptIOError(_)
+            | Self::Sandbox(_) => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for SandboxError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Crashed(_) | Self::NoOutcome | Self::TimedOut | Self::Failed(_) => {
+                ApiError::ContributionNotVerified
+            }
+            Self::Spawn(_) | Self::Io(_) | Self::Protocol(_) => ApiError::Internal,
+        }
+    }
+}
+
+impl ToApiError for VerificationError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::UnknownId => ApiError::UnknownVerification,
+            Self::Storage(err) => err.to_api_error(),
+        }
+    }
+}
+
+impl IntoResponse for VerificationError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for TranscriptFormatError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::UnknownCeremony(_) => ApiError::UnknownCeremony,
+            Self::Serialization(_) | Self::Lagrange(_) => ApiError::TranscriptSerialization,
+        }
+    }
+}
+
+impl IntoResponse for TranscriptFormatError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for TranscriptDiffError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::InvalidRange { .. } | Self::RangeTooLarge => ApiError::InvalidTranscriptRange,
+        }
+    }
+}
+
+impl IntoResponse for TranscriptDiffError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for TranscriptPageError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::UnknownCeremony(_) => ApiError::UnknownCeremony,
+            Self::InvalidOffset { .. } | Self::InvalidChunk { .. } => {
+                ApiError::InvalidTranscriptRange
             }
-        };
+        }
+    }
+}
+
+impl IntoResponse for TranscriptPageError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for ChainReportError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::InvalidUpTo { .. } => ApiError::InvalidTranscriptRange,
+            Self::TaskError(_) => ApiError::Internal,
+            Self::Signature(err) => err.to_api_error(),
+        }
+    }
+}
+
+impl IntoResponse for ChainReportError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for TransparencyLogError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::InvalidLeaf { .. } | Self::InvalidTreeSize { .. } => ApiError::InvalidLogRange,
+            Self::StorageError(err) => err.to_api_error(),
+            Self::TaskError(_) => ApiError::Internal,
+        }
+    }
+}
+
+impl IntoResponse for TransparencyLogError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::StorageError(err) => err.into_response(),
+            _ => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for LotteryError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::NotFound => ApiError::LotteryDrawNotFound,
+            Self::StorageError(err) => err.to_api_error(),
+        }
+    }
+}
+
+impl IntoResponse for LotteryError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::NotFound => error_response(&self),
+            Self::StorageError(err) => err.into_response(),
+        }
+    }
+}
+
+impl ToApiError for ContributorError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::NotFound => ApiError::ContributorNotFound,
+            Self::StorageError(err) => err.to_api_error(),
+        }
+    }
+}
+
+impl IntoResponse for ContributorError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::NotFound => error_response(&self),
+            Self::StorageError(err) => err.into_response(),
+        }
+    }
+}
+
+impl ToApiError for RequestSignatureError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Malformed
+            | Self::Expired
+            | Self::DigestMismatch
+            | Self::InvalidSignature
+            | Self::Replayed => ApiError::InvalidRequestSignature,
+            Self::LobbyStoreError(_) => ApiError::LobbyStoreUnavailable,
+            Self::BodyTooLarge => ApiError::BodyTooLarge,
+            Self::ReadFailed(_) => ApiError::BodyReadFailed,
+        }
+    }
+}
+
+impl IntoResponse for RequestSignatureError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for ReservationError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Missing => ApiError::MissingReservation,
+            Self::Malformed | Self::InvalidSignature => ApiError::InvalidReservation,
+            Self::Expired => ApiError::ReservationExpired,
+            Self::WrongSlot => ApiError::WrongSlot,
+        }
+    }
+}
+
+impl IntoResponse for ReservationError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
 
-        (status, body).into_response()
+impl ToApiError for ReceiptError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::NotFound => ApiError::ReceiptNotFound,
+            Self::StorageError(err) => err.to_api_error(),
+        }
+    }
+}
+
+impl IntoResponse for ReceiptError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::NotFound => error_response(&self),
+            Self::StorageError(err) => err.into_response(),
+        }
+    }
+}
+
+impl ToApiError for TryContributeError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::UnknownSessionId => ApiError::UnknownSession,
+            Self::UserBanned => ApiError::UserBanned,
+            Self::RateLimited { .. } => ApiError::LobbyRateLimited,
+            Self::LobbyIsFull { .. } => ApiError::LobbyFull,
+            Self::InWaitingRoom { .. } => ApiError::InWaitingRoom,
+            Self::AnotherContributionInProgress => ApiError::AnotherContributionInProgress,
+            Self::Requeued { .. } => ApiError::Requeued,
+            Self::NotAdmitted { .. } => ApiError::NotAdmitted,
+            Self::LobbyPaused => ApiError::LobbyPaused,
+            Self::ShuttingDown => ApiError::ShuttingDown,
+            Self::CeremonyClosed => ApiError::CeremonyClosed,
+            Self::ProviderQuotaReached => ApiError::ProviderQuotaReached,
+            Self::PhaseClosed => ApiError::PhaseClosed,
+            Self::NotEligibleInPhase => ApiError::NotEligibleInPhase,
+            Self::ProofOfWork(err) => err.to_api_error(),
+            Self::SpamFilter(err) => err.to_api_error(),
+            Self::StorageError(err) => err.to_api_error(),
+            Self::LobbyStoreError(_) => ApiError::LobbyStoreUnavailable,
+            Self::TaskError(_) => ApiError::Internal,
+        }
     }
 }
 
 impl IntoResponse for TryContributeError {
     fn into_response(self) -> Response {
-        let (status, body) = match self {
-            Self::UnknownSessionId => (StatusCode::UNAUTHORIZED, error_to_json(&self)),
-            Self::RateLimited | Self::LobbyIsFull => {
-                (StatusCode::BAD_REQUEST, error_to_json(&self))
+        match self {
+            Self::Requeued { retry_after } | Self::NotAdmitted { retry_after } => (
+                [(http::header::RETRY_AFTER, retry_after.to_string())],
+                error_response(&self),
+            )
+                .into_response(),
+            Self::RateLimited { retry_after_ms }
+            | Self::LobbyIsFull {
+                retry_after_ms: Some(retry_after_ms),
+            } => retry_response(&self, retry_after_ms),
+            Self::ProofOfWork(err) => err.into_response(),
+            Self::StorageError(err) => err.into_response(),
+            _ => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for ProofOfWorkError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Required { .. } => ApiError::ProofOfWorkRequired,
+            Self::Invalid { .. } => ApiError::InvalidProofOfWork,
+        }
+    }
+}
+
+impl IntoResponse for ProofOfWorkError {
+    fn into_response(self) -> Response {
+        (
+            [(POW_CHALLENGE_HEADER, self.challenge().to_string())],
+            error_response(&self),
+        )
+            .into_response()
+    }
+}
+
+impl ToApiError for CheckinProofError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Missing { .. } => ApiError::CheckinProofRequired,
+            Self::Invalid { .. } => ApiError::InvalidCheckinProof,
+        }
+    }
+}
+
+impl IntoResponse for CheckinProofError {
+    fn into_response(self) -> Response {
+        (
+            [(CHECKIN_CHALLENGE_HEADER, self.challenge().to_string())],
+            error_response(&self),
+        )
+            .into_response()
+    }
+}
+
+impl ToApiError for SpamFilterError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Vetoed { .. } => ApiError::SpamVetoed,
+            Self::Unavailable { .. } => ApiError::SpamFilterUnavailable,
+        }
+    }
+}
+
+impl IntoResponse for SpamFilterError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for ChunkedUploadError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::NotUsersTurn => ApiError::NotUsersTurn,
+            Self::InvalidPart => ApiError::InvalidUploadPart,
+            Self::PartTooLarge(_) | Self::TooLarge(_) => ApiError::BodyTooLarge,
+            Self::UnknownUpload => ApiError::UnknownUpload,
+            Self::MissingPart(_) => ApiError::IncompleteUpload,
+            Self::MissingDigest | Self::DigestMismatch(_) => ApiError::UploadDigestMismatch,
+            Self::ReadFailed(_) => ApiError::BodyReadFailed,
+            Self::TaskError(_) => ApiError::Internal,
+        }
+    }
+}
+
+impl IntoResponse for ChunkedUploadError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for WaitingRoomError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::UnknownSessionId => ApiError::UnknownSession,
+            Self::InvalidEmail => ApiError::InvalidEmail,
+            Self::NotWaiting => ApiError::NotInWaitingRoom,
+            Self::StorageError(err) => err.to_api_error(),
+        }
+    }
+}
+
+impl IntoResponse for WaitingRoomError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::StorageError(err) => err.into_response(),
+            _ => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for NotificationTargetError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::UnknownSessionId => ApiError::UnknownSession,
+            Self::Disabled => ApiError::NotificationsDisabled,
+            Self::InvalidTarget => ApiError::InvalidNotificationTarget,
+            Self::ChannelUnavailable(_) => ApiError::NotificationChannelUnavailable,
+            Self::NotRegistered => ApiError::NoNotificationTarget,
+            Self::StorageError(err) => err.to_api_error(),
+        }
+    }
+}
+
+impl IntoResponse for NotificationTargetError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::StorageError(err) => err.into_response(),
+            _ => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for AdminError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Unauthorized => ApiError::AdminUnauthorized,
+            Self::Forbidden => ApiError::AdminForbidden,
+            Self::UnknownAdminKey => ApiError::UnknownAdminKey,
+            Self::AdminKeyExists => ApiError::AdminKeyExists,
+            Self::NoActiveContributor => ApiError::NoActiveContributor,
+            Self::ContributionInProgress => ApiError::ContributionBeingVerified,
+            Self::ContributorActive => ApiError::ContributorActive,
+            Self::NotStandby => ApiError::NotStandby,
+            Self::Standby => ApiError::Standby,
+            Self::BeaconApplied => ApiError::BeaconApplied,
+            Self::InvalidBeacon(_) => ApiError::InvalidBeacon,
+            Self::InvalidTranscript(_) | Self::InvalidBeaconEntry(_) => ApiError::InvalidTranscript,
+            Self::Export(err) => err.to_api_error(),
+            Self::Io(_) | Self::TranscriptIo(_) => ApiError::TranscriptIo,
+            Self::Signature(err) => err.to_api_error(),
+            Self::KeyRotation(err) => err.to_api_error(),
+            Self::FeatureFlag(err) => err.to_api_error(),
+            Self::StorageError(err) => err.to_api_error(),
+            Self::TaskError(_) => ApiError::Internal,
+        }
+    }
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::StorageError(err) => err.into_response(),
+            _ => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for AccountError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::UnknownSessionId => ApiError::UnknownSession,
+            Self::ContributionInProgress => ApiError::AccountContributionInProgress,
+            Self::StorageError(err) => err.to_api_error(),
+            Self::TaskError(_) => ApiError::Internal,
+        }
+    }
+}
+
+impl IntoResponse for AccountError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::StorageError(err) => err.into_response(),
+            _ => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for ReplicationLogError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Disabled => ApiError::ReplicationDisabled,
+            Self::Evicted => ApiError::ReplicationEvicted,
+        }
+    }
+}
+
+impl IntoResponse for ReplicationLogError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for MirrorError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::NotSynced => ApiError::MirrorNotSynced,
+        }
+    }
+}
+
+impl IntoResponse for MirrorError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for RateLimitError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::TooManyRequests(_) => ApiError::TooManyRequests,
+        }
+    }
+}
+
+impl IntoResponse for RateLimitError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::TooManyRequests(retry_after) => (
+                [(http::header::RETRY_AFTER, retry_after.to_string())],
+                error_response(&self),
+            )
+                .into_response(),
+        }
+    }
+}
+
+impl ToApiError for RequestLimitError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Overloaded => ApiError::Overloaded,
+            Self::TimedOut(_) => ApiError::RequestTimedOut,
+        }
+    }
+}
+
+impl IntoResponse for RequestLimitError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Overloaded => {
+                ([(http::header::RETRY_AFTER, "1")], error_response(&self)).into_response()
             }
-            Self::AnotherContributionInProgress => (StatusCode::OK, error_to_json(&self)),
-            Self::StorageError(err) => return err.into_response(),
-            Self::TaskError(_) => (StatusCode::INTERNAL_SERVER_ERROR, error_to_json(&self)),
-        };
+            Self::TimedOut(_) => error_response(&self),
+        }
+    }
+}
 
-        (status, body).into_response()
+impl ToApiError for FeatureFlagError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Disabled => ApiError::FeatureDisabled,
+            Self::Unknown => ApiError::UnknownFeatureFlag,
+            Self::InvalidPercent(_) => ApiError::InvalidFeatureFlag,
+        }
+    }
+}
+
+impl IntoResponse for FeatureFlagError {
+    fn into_response(self) -> Response {
+        error_response(&self)
+    }
+}
+
+impl ToApiError for LoadShedError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Shedding(_) => ApiError::LoadShedding,
+        }
+    }
+}
+
+impl IntoResponse for LoadShedError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Shedding(retry_after) => (
+                [(http::header::RETRY_AFTER, retry_after.to_string())],
+                error_response(&self),
+            )
+                .into_response(),
+        }
+    }
+}
+
+impl ToApiError for UploadError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::TooLarge(_) | Self::DecompressedTooLarge(_) => ApiError::BodyTooLarge,
+            Self::UnsupportedContentEncoding => ApiError::UnsupportedContentEncoding,
+            Self::UnsupportedContentType => ApiError::UnsupportedContentType,
+            Self::InvalidJson(_) => ApiError::InvalidJson,
+            Self::InvalidBinary(_) => ApiError::InvalidBinaryContribution,
+            Self::ReadFailed(_) => ApiError::BodyReadFailed,
+            Self::DigestMismatch(_) => ApiError::ContentDigestMismatch,
+            Self::InvalidDigest(_) => ApiError::InvalidContentDigest,
+            Self::TaskError(_) => ApiError::Internal,
+        }
+    }
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::DigestMismatch(_) | Self::InvalidDigest(_) => (
+                [
+                    (WANT_CONTENT_DIGEST, WANTED_DIGESTS),
+                    (WANT_REPR_DIGEST, WANTED_DIGESTS),
+                ],
+                error_response(&self),
+            )
+                .into_response(),
+            _ => error_response(&self),
+        }
+    }
+}
+
+impl ToApiError for ClientVersionError {
+    fn to_api_error(&self) -> ApiError {
+        match self {
+            Self::Invalid(_) => ApiError::InvalidClientVersion,
+            Self::TooOld { .. } => ApiError::ClientTooOld,
+        }
+    }
+}
+
+impl IntoResponse for ClientVersionError {
+    fn into_response(self) -> Response {
+        error_response(&self)
     }
 }
 
@@ -115,11 +1078,58 @@ struct CeremoniesErrorFormatter(CeremoniesError);
 
 impl IntoResponse for CeremoniesErrorFormatter {
     fn into_response(self) -> Response {
-        let body = Json(json!({
-            "code": self.0.to_error_code(),
-            "error" : format!("contribution invalid: {}", self.0)
-        }));
+        let api_error = ApiError::InvalidContribution;
+        let body = error_body(
+            api_error,
+            self.0.to_error_code(),
+            format!("contribution invalid: {}", self.0),
+        );
+        (api_error.status(), body).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn codes_are_unique() {
+        let codes = ApiError::iter().map(ApiError::code).collect::<Vec<_>>();
+        assert_eq!(codes.iter().collect::<HashSet<_>>().len(), codes.len());
+        assert!(codes.iter().all(|code| code.starts_with("SEQ-")));
+    }
+
+    #[tokio::test]
+    async fn responds_with_code_and_status() {
+        let response = TryContributeError::RateLimited {
+            retry_after_ms: 1500,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[http::header::RETRY_AFTER], "2");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "SEQ-LOBBY-002");
+        assert_eq!(body["kind"], "TryContributeError::RateLimited");
+        assert_eq!(body["error"], "call came too early. rate limited");
+        assert_eq!(body["retry_after_ms"], 1500);
+
+        let response = TryContributeError::LobbyIsFull {
+            retry_after_ms: None,
+        }
+        .into_response();
+        assert!(!response.headers().contains_key(http::header::RETRY_AFTER));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.get("retry_after_ms"), None);
 
-        (StatusCode::BAD_REQUEST, body).into_response()
+        let response = AdminError::StorageError(StorageError::Closed).into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "SEQ-DB-003");
+        assert_eq!(body["kind"], "StorageError::Closed");
     }
 }
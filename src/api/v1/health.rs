@@ -0,0 +1,147 @@
+use crate::{oauth::AuthProviders, storage::PersistentStorage, Options};
+use axum::{
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use http::StatusCode;
+use serde::Serialize;
+use std::{collections::BTreeMap, time::Duration};
+use tokio::fs::{remove_file, OpenOptions};
+use tracing::warn;
+
+/// Timeout for reaching an auth provider during the readiness check.
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    ready:  bool,
+    /// Result of each dependency check, `"ok"` or an error message.
+    checks: BTreeMap<String, String>,
+}
+
+impl ReadinessResponse {
+    #[must_use]
+    pub const fn new(ready: bool, checks: BTreeMap<String, String>) -> Self {
+        Self { ready, checks }
+    }
+}
+
+impl IntoResponse for ReadinessResponse {
+    fn into_response(self) -> Response {
+        let status = if self.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        (status, Json(self)).into_response()
+    }
+}
+
+/// Liveness probe. Succeeds as long as the server is serving requests.
+#[allow(clippy::unused_async)] // Required for axum function signature
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe. Checks the database connection, that the transcript can be
+/// written and that the auth providers are reachable. Unreachable providers
+/// are reported but leave the sequencer ready, as the lobby can still be
+/// served while sign-ins fail with `SEQ-AUTH-013`.
+pub async fn readyz(
+    Extension(options): Extension<Options>,
+    Extension(storage): Extension<PersistentStorage>,
+    Extension(providers): Extension<AuthProviders>,
+    Extension(http_client): Extension<reqwest::Client>,
+) -> ReadinessResponse {
+    let mut checks = BTreeMap::new();
+    checks.insert(
+        "database".to_string(),
+        storage.ping().await.map_err(|e| e.to_string()),
+    );
+    checks.insert(
+        "transcript".to_string(),
+        check_transcript_writable(&options).await,
+    );
+
+    for provider in providers.iter() {
+        // Any response means the provider is reachable.
+        let result = http_client
+            .get(provider.health_url())
+            .timeout(PROVIDER_TIMEOUT)
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        checks.insert(format!("auth_{}", provider.name()), result);
+    }
+
+    let ready = checks
+        .iter()
+        .filter(|(name, _)| !name.starts_with("auth_"))
+        .all(|(_, result)| result.is_ok());
+    if !ready {
+        warn!(?checks, "readiness check failed");
+    }
+    ReadinessResponse {
+        ready,
+        checks: checks
+            .into_iter()
+            .map(|(name, result)| (name, result.err().unwrap_or_else(|| "ok".to_string())))
+            .collect(),
+    }
+}
+
+/// Creates and removes a probe file next to the transcript.
+async fn check_transcript_writable(options: &Options) -> Result<(), String> {
+    let probe = options.transcript_file.with_extension("readyz");
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(&probe)
+        .await
+        .map_err(|e| format!("{}: {e}", probe.display()))?;
+    remove_file(&probe)
+        .await
+        .map_err(|e| format!("{}: {e}", probe.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::storage_client, test_util::test_options};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn reports_readiness() {
+        let dir = tempdir().unwrap();
+        let mut options = test_options();
+        options.transcript_file = dir.path().join("transcript.json");
+        options.auth_providers = vec![];
+        let storage = storage_client(&options.storage).await.unwrap();
+        let providers = AuthProviders::new(&options).unwrap();
+
+        let response = readyz(
+            Extension(options.clone()),
+            Extension(storage.clone()),
+            Extension(providers.clone()),
+            Extension(reqwest::Client::new()),
+        )
+        .await;
+        assert!(response.ready);
+        assert_eq!(response.checks["database"], "ok");
+        assert_eq!(response.checks["transcript"], "ok");
+
+        options.transcript_file = dir.path().join("missing").join("transcript.json");
+        storage.close().await.unwrap();
+        let response = readyz(
+            Extension(options),
+            Extension(storage),
+            Extension(providers),
+            Extension(reqwest::Client::new()),
+        )
+        .await;
+        assert!(!response.ready);
+        assert_ne!(response.checks["database"], "ok");
+        assert_ne!(response.checks["transcript"], "ok");
+    }
+}
@@ -1,52 +1,675 @@
 use crate::{
-    keys::{Address, SharedKeys},
+    attestation::Attestation,
+    auth_funnel::{self, ProviderFunnel},
+    ceremony::CeremonyId,
+    compression::{Encoding, SharedTranscriptCache},
+    etag,
+    fairness::{self, FairnessReport},
+    identity,
+    keys::{Address, SharedKeys, Signature, SignatureError, VerifyingKey},
     lobby::SharedLobbyState,
-    Options, SharedCeremonyStatus,
+    lottery::Draw,
+    statistics::SharedStatistics,
+    status_cache::{CachedResponse, SharedStatusCache},
+    storage::{ContributorOrder, PersistentStorage, StorageError, StoredContributor},
+    transcript_format::{TranscriptFormatError, TranscriptFormatKind},
+    transparency_log::{InclusionProof, SharedTransparencyLog, TransparencyLogError},
+    Options, SharedCeremonyStatus, SharedTranscript,
 };
 use axum::{
     body::StreamBody,
+    extract::{Path, Query},
     response::{IntoResponse, Response},
     Extension, Json,
 };
-use http::StatusCode;
-use serde::Serialize;
-use std::sync::atomic::Ordering;
+use chrono::{DateTime, Utc};
+use http::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE, ETAG, VARY},
+    HeaderMap, HeaderValue, StatusCode,
+};
+use kzg_ceremony_crypto::{signature::identity::Identity, ErrorCode, G1, G2};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::Range, sync::atomic::Ordering};
+use strum::IntoStaticStr;
+use thiserror::Error;
 use tokio::fs::File;
 use tokio_util::io::ReaderStream;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, PartialEq, Eq, ToSchema)]
 pub struct StatusResponse {
     lobby_size:        usize,
     num_contributions: usize,
+    #[schema(value_type = String)]
+    sequencer_address: Address,
+}
+
+/// Polled by every waiting client, so it is tagged for conditional GETs.
+#[utoipa::path(
+    get,
+    path = "/info/status",
+    tag = "info",
+    responses(
+        (status = 200, description = "Lobby size and number of contributions", body = StatusResponse,
+            headers(("ETag" = String))),
+        (status = 304, description = "Unchanged since `If-None-Match`"),
+    )
+)]
+pub async fn status(
+    headers: HeaderMap,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(ceremony_status): Extension<SharedCeremonyStatus>,
+    Extension(keys): Extension<SharedKeys>,
+    Extension(cache): Extension<SharedStatusCache>,
+) -> Response {
+    let response = cache
+        .get_or_build(lobby_state.generation(), async {
+            let lobby_size = lobby_state.get_lobby_size().await;
+            let num_contributions = ceremony_status.load(Ordering::Relaxed);
+            let sequencer_address = keys.address();
+            let json = serde_json::to_vec(&StatusResponse {
+                lobby_size,
+                num_contributions,
+                sequencer_address,
+            })
+            .ok()?;
+            let tag = etag::tag(&json);
+            Some(CachedResponse {
+                json: json.into(),
+                tag,
+            })
+        })
+        .await;
+    match response {
+        Some(response) => etag::respond(&headers, response.json, response.tag),
+        None => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IdentityResponse {
+    #[schema(value_type = String)]
     sequencer_address: Address,
+    public_key:        String,
+    /// JSON encoded [`Attestation`], signed as is.
+    attestation:       String,
+    #[schema(value_type = String)]
+    signature:         Signature,
 }
 
-impl IntoResponse for StatusResponse {
+impl IntoResponse for IdentityResponse {
     fn into_response(self) -> Response {
-        let status = StatusCode::OK;
-        (status, Json(self)).into_response()
+        (StatusCode::OK, Json(self)).into_response()
     }
 }
 
-pub async fn status(
-    Extension(lobby_state): Extension<SharedLobbyState>,
-    Extension(ceremony_status): Extension<SharedCeremonyStatus>,
+/// The sequencer's signing keys as a JSON Web Key Set, see RFC 7517.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Jwks {
+    /// The current key first, then the rotated out keys that still verify
+    /// signatures.
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Jwk {
+    /// `EC`.
+    kty:        &'static str,
+    /// `secp256k1`.
+    crv:        &'static str,
+    /// `ES256K`.
+    alg:        &'static str,
+    /// `sig`.
+    #[serde(rename = "use")]
+    usage:      &'static str,
+    /// The address of the key, which receipts are checked against.
+    #[schema(value_type = String)]
+    kid:        Address,
+    /// Base64url encoded coordinates of the public key.
+    x:          String,
+    y:          String,
+    /// When a rotated out key stops verifying signatures. Missing for the
+    /// current key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<VerifyingKey> for Jwk {
+    fn from(key: VerifyingKey) -> Self {
+        // An uncompressed key is 0x04 followed by the coordinates.
+        let point = hex::decode(key.public_key.trim_start_matches("0x")).unwrap_or_default();
+        let coordinate = |range: Range<usize>| {
+            point
+                .get(range)
+                .map(|bytes| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD))
+                .unwrap_or_default()
+        };
+        Self {
+            kty:        "EC",
+            crv:        "secp256k1",
+            alg:        "ES256K",
+            usage:      "sig",
+            kid:        key.address,
+            x:          coordinate(1..33),
+            y:          coordinate(33..65),
+            expires_at: key.expires_at,
+        }
+    }
+}
+
+/// The keys receipts and attestations of the sequencer are checked against.
+/// After a key rotation the previous key is listed until it expires.
+#[utoipa::path(
+    get,
+    path = "/info/jwks",
+    tag = "info",
+    responses((status = 200, description = "The signing keys", body = Jwks))
+)]
+pub async fn jwks(Extension(keys): Extension<SharedKeys>) -> Json<Jwks> {
+    Json(Jwks {
+        keys: keys.verifying_keys().into_iter().map(Jwk::from).collect(),
+    })
+}
+
+/// The sequencer's public key and a fresh signature over the transcript it
+/// serves, so clients can detect a swapped sequencer.
+#[utoipa::path(
+    get,
+    path = "/info/identity",
+    tag = "info",
+    responses(
+        (status = 200, description = "Public key and signed attestation", body = IdentityResponse),
+        (status = 500, description = "Signing failed", body = ErrorBody),
+    )
+)]
+pub async fn identity(
+    Extension(options): Extension<Options>,
+    Extension(ceremony): Extension<CeremonyId>,
     Extension(keys): Extension<SharedKeys>,
-) -> StatusResponse {
-    let lobby_size = lobby_state.get_lobby_size().await;
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Result<IdentityResponse, SignatureError> {
+    let transcript = transcript.read_owned().await;
+    // Hashing the transcript takes a while for the full ceremony.
+    let attestation = tokio::task::spawn_blocking(move || {
+        Attestation::new(ceremony.0, &options.ceremony_sizes, &transcript)
+    })
+    .await
+    .map_err(|_| SignatureError::SignatureCreation)??;
+    let (attestation, signature) = attestation.sign(&keys).await?;
+    Ok(IdentityResponse {
+        sequencer_address: keys.address(),
+        public_key: keys.public_key(),
+        attestation,
+        signature,
+    })
+}
 
-    let num_contributions = ceremony_status.load(Ordering::Relaxed);
-    let sequencer_address = keys.address();
+#[derive(Debug, Serialize, PartialEq)]
+pub struct StatisticsResponse {
+    /// When the statistics were aggregated, see [`crate::statistics`].
+    refreshed_at: DateTime<Utc>,
+    attempts: usize,
+    contributions: usize,
+    expired: usize,
+    /// Fraction of finished attempts that expired instead of contributing.
+    expiry_rate: f64,
+    median_contribution_duration_secs: Option<f64>,
+    contributions_per_hour: Vec<HourlyContributions>,
+    contributions_by_provider: BTreeMap<String, usize>,
+    /// Finished contributions by two letter country code, for those whose
+    /// country is known.
+    contributions_by_country: BTreeMap<String, usize>,
+    /// Number of distinct countries contributions came from.
+    countries: usize,
+    /// Finished contributions by `<name>/<version>` of the client, for those
+    /// that sent `X-Client-Version`.
+    contributions_by_client: BTreeMap<String, usize>,
+    /// Sign-ins by auth provider and stage, see [`crate::auth_funnel`].
+    auth_funnel: BTreeMap<String, ProviderFunnel>,
+}
 
-    StatusResponse {
-        lobby_size,
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct HourlyContributions {
+    hour:          DateTime<Utc>,
+    contributions: usize,
+}
+
+impl IntoResponse for StatisticsResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+pub async fn statistics(Extension(snapshot): Extension<SharedStatistics>) -> StatisticsResponse {
+    let latest = snapshot.latest();
+    let statistics = latest.contributions.clone();
+    let auth_funnel = auth_funnel::funnels(latest.auth_funnel.clone());
+    let ended = statistics.contributions + statistics.expired;
+    #[allow(clippy::cast_precision_loss)]
+    let expiry_rate = if ended == 0 {
+        0.0
+    } else {
+        statistics.expired as f64 / ended as f64
+    };
+    StatisticsResponse {
+        refreshed_at: latest.refreshed_at,
+        attempts: statistics.attempts,
+        contributions: statistics.contributions,
+        expired: statistics.expired,
+        expiry_rate,
+        median_contribution_duration_secs: statistics
+            .median_contribution_duration
+            .map(|duration| duration.as_secs_f64()),
+        contributions_per_hour: statistics
+            .contributions_per_hour
+            .into_iter()
+            .map(|(hour, contributions)| HourlyContributions {
+                hour,
+                contributions,
+            })
+            .collect(),
+        contributions_by_provider: statistics.contributions_by_provider,
+        countries: statistics.contributions_by_country.len(),
+        contributions_by_country: statistics.contributions_by_country,
+        contributions_by_client: statistics.contributions_by_client,
+        auth_funnel,
+    }
+}
+
+/// How long participants waited in the lobby, see [`crate::fairness`].
+pub async fn lobby_fairness(
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<FairnessReport>, StorageError> {
+    let waits = storage.lobby_waits().await?;
+    Ok(Json(fairness::report(&waits)))
+}
+
+/// Largest number of contributions `/info/contributions` returns at once.
+const MAX_CONTRIBUTIONS_PAGE: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ContributionsQuery {
+    /// Index of the first contribution. Defaults to the last `limit` ones.
+    start: Option<usize>,
+    #[serde(default = "default_contributions_limit")]
+    limit: usize,
+}
+
+const fn default_contributions_limit() -> usize {
+    20
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ContributionsResponse {
+    num_contributions: usize,
+    contributions:     Vec<ContributionEntry>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ContributionEntry {
+    /// Position in the transcript, as in the receipt's `contribution_index`.
+    index:       usize,
+    participant: Identity,
+    /// One per sub-ceremony, as in the receipt's `witness`.
+    pot_pubkeys: Vec<G2>,
+}
+
+/// A page of the contributions in the transcript, without the powers.
+pub async fn contributions(
+    Query(query): Query<ContributionsQuery>,
+    headers: HeaderMap,
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Response {
+    let transcript = transcript.read().await;
+    let num_contributions = transcript.num_participants();
+    let limit = query.limit.min(MAX_CONTRIBUTIONS_PAGE);
+    // Index 0 is the initial transcript, not a contribution.
+    let start = query
+        .start
+        .unwrap_or_else(|| num_contributions.saturating_sub(limit) + 1)
+        .max(1);
+    let end = start.saturating_add(limit).min(num_contributions + 1);
+    let contributions = (start..end)
+        .map(|index| ContributionEntry {
+            index,
+            participant: transcript.participant_ids[index].clone(),
+            pot_pubkeys: transcript
+                .transcripts
+                .iter()
+                .map(|transcript| transcript.witness.pubkeys[index])
+                .collect(),
+        })
+        .collect();
+    etag::json(&headers, &ContributionsResponse {
         num_contributions,
-        sequencer_address,
+        contributions,
+    })
+}
+
+/// Number of contributors on a page of `/info/contributors`.
+const CONTRIBUTORS_PAGE_SIZE: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct ContributorsQuery {
+    /// Zero based.
+    #[serde(default)]
+    page:  usize,
+    #[serde(default)]
+    order: ContributorOrder,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ContributorsResponse {
+    page:              usize,
+    page_size:         usize,
+    /// Contributions with a receipt, across all pages.
+    num_contributions: usize,
+    contributors:      Vec<ContributorEntry>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ContributorEntry {
+    /// Position in the transcript, as in the receipt's `contribution_index`.
+    index:         usize,
+    uid:           String,
+    provider:      String,
+    /// From getting the slot to the verified contribution. `None` if the
+    /// attempt is no longer recorded.
+    duration_secs: Option<f64>,
+    receipt:       String,
+    signature:     Signature,
+    /// Verified ENS name of Ethereum contributors, with `--ens-rpc-url`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ens_name:      Option<String>,
+}
+
+impl From<StoredContributor> for ContributorEntry {
+    fn from(contributor: StoredContributor) -> Self {
+        let provider = identity::provider_name(&contributor.uid);
+        Self {
+            index: contributor.position,
+            duration_secs: contributor.duration().map(|d| d.as_secs_f64()),
+            provider,
+            uid: contributor.uid,
+            receipt: contributor.receipt.receipt,
+            signature: Signature::from(contributor.receipt.signature),
+            ens_name: contributor.ens_name,
+        }
+    }
+}
+
+impl IntoResponse for ContributorsResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
     }
 }
 
-pub async fn current_state(Extension(options): Extension<Options>) -> impl IntoResponse {
-    let f = match File::open(options.transcript_file).await {
+impl IntoResponse for ContributorEntry {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ContributorError {
+    #[error("no contribution with a receipt at this index")]
+    NotFound,
+    #[error("storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+impl ErrorCode for ContributorError {
+    fn to_error_code(&self) -> String {
+        format!("ContributorError::{}", <&str>::from(self))
+    }
+}
+
+/// A page of the contributors, by index or fastest first, with their
+/// receipts.
+pub async fn contributors(
+    Query(query): Query<ContributorsQuery>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<ContributorsResponse, StorageError> {
+    let num_contributions = storage.count_receipts().await?;
+    let contributors = storage
+        .list_contributors(
+            query.order,
+            query.page.saturating_mul(CONTRIBUTORS_PAGE_SIZE),
+            CONTRIBUTORS_PAGE_SIZE,
+        )
+        .await?
+        .into_iter()
+        .map(ContributorEntry::from)
+        .collect();
+    Ok(ContributorsResponse {
+        page: query.page,
+        page_size: CONTRIBUTORS_PAGE_SIZE,
+        num_contributions,
+        contributors,
+    })
+}
+
+/// The contribution at `index` in the transcript, with its receipt.
+pub async fn contributor(
+    Path(index): Path<usize>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<ContributorEntry, ContributorError> {
+    storage
+        .get_contributor(index)
+        .await?
+        .map(ContributorEntry::from)
+        .ok_or(ContributorError::NotFound)
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum LotteryError {
+    #[error("no lottery draw for this epoch")]
+    NotFound,
+    #[error("storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+impl ErrorCode for LotteryError {
+    fn to_error_code(&self) -> String {
+        format!("LotteryError::{}", <&str>::from(self))
+    }
+}
+
+/// The latest draw of the admission lottery, see [`crate::lottery`].
+pub async fn lottery_latest(
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Draw>, LotteryError> {
+    storage
+        .lottery_draw(None)
+        .await?
+        .map(Json)
+        .ok_or(LotteryError::NotFound)
+}
+
+/// The draw of the admission lottery for `epoch`.
+pub async fn lottery_epoch(
+    Path(epoch): Path<u64>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<Draw>, LotteryError> {
+    storage
+        .lottery_draw(Some(epoch))
+        .await?
+        .map(Json)
+        .ok_or(LotteryError::NotFound)
+}
+
+/// Largest number of contributions `/transcript/diff` spans at once.
+const MAX_DIFF_RANGE: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    from: usize,
+    to:   usize,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum TranscriptDiffError {
+    #[error("invalid range {from}..{to}, the transcript has {num_contributions} contributions")]
+    InvalidRange {
+        from:              usize,
+        to:                usize,
+        num_contributions: usize,
+    },
+    #[error("ranges can span at most {MAX_DIFF_RANGE} contributions")]
+    RangeTooLarge,
+}
+
+impl ErrorCode for TranscriptDiffError {
+    fn to_error_code(&self) -> String {
+        format!("TranscriptDiffError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct TranscriptDiff {
+    from:              usize,
+    to:                usize,
+    num_contributions: usize,
+    /// Participants of the contributions `from + 1..=to`.
+    participants:      Vec<Identity>,
+    /// One per sub-ceremony.
+    ceremonies:        Vec<CeremonyDiff>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct CeremonyDiff {
+    /// Every contribution multiplies all powers but the generator at index 0
+    /// with powers of its secret, so these are the powers that differ between
+    /// `from` and `to`. Empty if the range is.
+    changed_g1_powers: Range<usize>,
+    changed_g2_powers: Range<usize>,
+    /// The running products after contributions `from..=to`. Each is the one
+    /// before multiplied by the contribution's secret, which can be checked
+    /// as `e(running_products[k + 1], g2) = e(running_products[k],
+    /// pot_pubkeys[k])`.
+    running_products:  Vec<G1>,
+    /// The pubkeys of the contributions `from + 1..=to`.
+    pot_pubkeys:       Vec<G2>,
+}
+
+/// What changed in the transcript between contributions `from` and `to`, so
+/// that the witness of a range can be audited without the full transcript.
+/// Index 0 is the initial transcript.
+pub async fn transcript_diff(
+    Query(query): Query<DiffQuery>,
+    headers: HeaderMap,
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Result<Response, TranscriptDiffError> {
+    let transcript = transcript.read().await;
+    let num_contributions = transcript.num_participants();
+    let DiffQuery { from, to } = query;
+    if from > to || to > num_contributions {
+        return Err(TranscriptDiffError::InvalidRange {
+            from,
+            to,
+            num_contributions,
+        });
+    }
+    if to - from > MAX_DIFF_RANGE {
+        return Err(TranscriptDiffError::RangeTooLarge);
+    }
+    let changed = |num_powers: usize| if from == to { 1..1 } else { 1..num_powers };
+    Ok(etag::json(&headers, &TranscriptDiff {
+        from,
+        to,
+        num_contributions,
+        participants: transcript.participant_ids[from + 1..=to].to_vec(),
+        ceremonies: transcript
+            .transcripts
+            .iter()
+            .map(|transcript| CeremonyDiff {
+                changed_g1_powers: changed(transcript.powers.g1.len()),
+                changed_g2_powers: changed(transcript.powers.g2.len()),
+                running_products:  transcript.witness.products[from..=to].to_vec(),
+                pot_pubkeys:       transcript.witness.pubkeys[from + 1..=to].to_vec(),
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CurrentStateQuery {
+    #[serde(default)]
+    format:   TranscriptFormatKind,
+    /// Sub-ceremony to export in formats holding a single one.
+    #[serde(default)]
+    ceremony: usize,
+}
+
+/// The transcript, as the transcript file or exported to `query.format`.
+/// Tagged for conditional GETs once the compressed copies of the latest
+/// transcript are ready, see [`crate::compression`].
+pub async fn current_state(
+    Query(query): Query<CurrentStateQuery>,
+    headers: HeaderMap,
+    Extension(options): Extension<Options>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(ceremony_status): Extension<SharedCeremonyStatus>,
+    Extension(transcript_cache): Extension<SharedTranscriptCache>,
+) -> Response {
+    let num_contributions = ceremony_status.load(Ordering::Relaxed);
+    // Exports are derived from the transcript, and so are their tags.
+    let tag = transcript_cache
+        .etag(num_contributions)
+        .map(|tag| match query.format {
+            TranscriptFormatKind::Json => tag,
+            format => etag::tag(format!("{tag} {format:?} {}", query.ceremony).as_bytes()),
+        });
+    if let Some(tag) = &tag {
+        if etag::matches(&headers, tag) {
+            return etag::not_modified(tag.clone());
+        }
+    }
+
+    let mut response = match query.format.exporter(query.ceremony) {
+        Some(exporter) => {
+            let exported = exporter.export(&*transcript.read().await);
+            match exported {
+                Ok(body) => (
+                    StatusCode::OK,
+                    [(CONTENT_TYPE, exporter.content_type())],
+                    body,
+                )
+                    .into_response(),
+                Err(error) => error.into_response(),
+            }
+        }
+        None => {
+            let cached = Encoding::preferred(&headers).and_then(|encoding| {
+                Some((encoding, transcript_cache.get(num_contributions, encoding)?))
+            });
+            match cached {
+                Some((encoding, body)) => (
+                    StatusCode::OK,
+                    [
+                        (CONTENT_TYPE, "application/json"),
+                        (CONTENT_ENCODING, encoding.as_str()),
+                        (VARY, "accept-encoding"),
+                    ],
+                    body,
+                )
+                    .into_response(),
+                None => transcript_file(&options).await.into_response(),
+            }
+        }
+    };
+    // The transcript only grows, so a body newer than the tag is no harm.
+    if let Some(tag) = tag.and_then(|tag| HeaderValue::from_str(&tag).ok()) {
+        if response.status() == StatusCode::OK {
+            response.headers_mut().insert(ETAG, tag);
+        }
+    }
+    response
+}
+
+async fn transcript_file(options: &Options) -> impl IntoResponse {
+    let f = match File::open(&options.transcript_file).await {
         Ok(file) => file,
         Err(_) => {
             return Err((
@@ -59,3 +682,48 @@ pub async fn current_state(Extension(options): Extension<Options>) -> impl IntoR
     let body = StreamBody::new(stream);
     Ok((StatusCode::OK, body))
 }
+
+#[derive(Serialize)]
+pub struct SthResponse {
+    sequencer_address: Address,
+    /// JSON encoded [`TreeHead`](crate::transparency_log::TreeHead), signed
+    /// as is.
+    tree_head:         String,
+    signature:         Signature,
+}
+
+impl IntoResponse for SthResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+/// The signed head of the transparency log, see [`crate::transparency_log`].
+pub async fn sth(
+    Extension(ceremony): Extension<CeremonyId>,
+    Extension(keys): Extension<SharedKeys>,
+    Extension(log): Extension<SharedTransparencyLog>,
+) -> Result<SthResponse, SignatureError> {
+    let (tree_head, signature) = log.tree_head(ceremony.0).await.sign(&keys).await?;
+    Ok(SthResponse {
+        sequencer_address: keys.address(),
+        tree_head,
+        signature,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InclusionProofQuery {
+    index:     usize,
+    tree_size: Option<usize>,
+}
+
+/// Proves that entry `index` of the transparency log is part of the tree of
+/// its first `tree_size` entries.
+pub async fn inclusion_proof(
+    Query(query): Query<InclusionProofQuery>,
+    Extension(log): Extension<SharedTransparencyLog>,
+) -> Result<Json<InclusionProof>, TransparencyLogError> {
+    let proof = log.inclusion_proof(query.index, query.tree_size).await?;
+    Ok(Json(proof))
+}
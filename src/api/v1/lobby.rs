@@ -1,31 +1,83 @@
 use crate::{
-    lobby::{ActiveContributorError, SharedLobbyState},
+    audit::{outcome, Audit, AuditAction},
+    client_version::ClientHeader,
+    compression::Encoding,
+    contribution_format::ContributionEncoding,
+    etag,
+    fairness::{self, WaitOutcome},
+    handoff::{PreparedBase, SharedHandoff},
+    io::sync_transcript,
+    lobby::{ActiveContributorError, LobbyEvent, LobbyPosition, SharedLobbyState},
+    lobby_store::LobbyStoreError,
+    metrics::CONTRIBUTIONS_STARTED,
+    notifications::{
+        is_valid_matrix_id, NotificationStatus, NotificationTarget, SharedNotifier,
+        StoredNotification,
+    },
+    phases::{PhaseViolation, SharedSchedule},
+    proof_of_work::{PowSolution, ProofOfWorkError},
+    quotas::SharedRuleSet,
+    reservation::{SharedReservationSigner, RESERVATION_HEADER},
+    spam_filter::{SpamFilterError, SpamGuard, SpamStage},
     storage::{PersistentStorage, StorageError},
-    SessionId, SharedTranscript,
+    SessionId, SharedCeremonyStatus, SharedTranscript,
 };
 use axum::{
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
     response::{IntoResponse, Response},
     Extension, Json,
 };
-use http::StatusCode;
-use kzg_ceremony_crypto::{BatchContribution, ErrorCode};
-use serde::Serialize;
+use chrono::{DateTime, Utc};
+use http::{header::ETAG, HeaderMap, StatusCode};
+use kzg_ceremony_crypto::{BatchTranscript, ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
 use strum::IntoStaticStr;
 use thiserror::Error;
-use tokio::{task::JoinError, time::Instant};
+use tokio::{sync::broadcast::error::RecvError, task::JoinError, time::Instant};
+use tracing::{debug, instrument, Instrument};
+use utoipa::ToSchema;
 
 #[derive(Debug, Error, IntoStaticStr)]
 pub enum TryContributeError {
     #[error("unknown session id")]
     UnknownSessionId,
     #[error("call came too early. rate limited")]
-    RateLimited,
+    RateLimited { retry_after_ms: u64 },
     #[error("another contribution in progress")]
     AnotherContributionInProgress,
+    #[error("the slot expired, it is handed out again in {retry_after} seconds")]
+    Requeued { retry_after: u64 },
+    #[error("not admitted by the lottery, the next draw is in {retry_after} seconds")]
+    NotAdmitted { retry_after: u64 },
+    /// `retry_after_ms` is `None` for the session count limit, which only
+    /// sign-ins run into.
     #[error("lobby is full")]
-    LobbyIsFull,
+    LobbyIsFull { retry_after_ms: Option<u64> },
+    #[error("lobby is full, waiting room position {position}")]
+    InWaitingRoom { position: usize },
+    #[error("user is banned")]
+    UserBanned,
+    #[error("lobby is paused")]
+    LobbyPaused,
+    #[error("sequencer is shutting down")]
+    ShuttingDown,
+    #[error("the ceremony is closed")]
+    CeremonyClosed,
+    #[error("the contribution quota of the auth provider is used up")]
+    ProviderQuotaReached,
+    #[error("no phase of the ceremony is open")]
+    PhaseClosed,
+    #[error("user is not eligible for the open phase of the ceremony")]
+    NotEligibleInPhase,
+    #[error(transparent)]
+    ProofOfWork(#[from] ProofOfWorkError),
+    #[error(transparent)]
+    SpamFilter(#[from] SpamFilterError),
     #[error("error in storage layer: {0}")]
     StorageError(#[from] StorageError),
+    #[error("error in lobby store: {0}")]
+    LobbyStoreError(#[from] LobbyStoreError),
     #[error("background task error: {0}")]
     TaskError(#[from] JoinError),
 }
@@ -42,93 +94,682 @@ impl From<ActiveContributorError> for TryContributeError {
             ActiveContributorError::AnotherContributionInProgress
             | ActiveContributorError::NotUsersTurn => Self::AnotherContributionInProgress,
             ActiveContributorError::UserNotInLobby
-            | ActiveContributorError::NotActiveContributor => Self::UnknownSessionId,
-            ActiveContributorError::SessionCountLimitExceeded
-            | ActiveContributorError::LobbySizeLimitExceeded => Self::LobbyIsFull,
-            ActiveContributorError::RateLimited => Self::RateLimited,
+            | ActiveContributorError::NotActiveContributor
+            | ActiveContributorError::NoExtensionsLeft => Self::UnknownSessionId,
+            ActiveContributorError::SessionCountLimitExceeded => Self::LobbyIsFull {
+                retry_after_ms: None,
+            },
+            ActiveContributorError::LobbySizeLimitExceeded(retry_after) => Self::LobbyIsFull {
+                retry_after_ms: Some(millis_rounded_up(retry_after)),
+            },
+            ActiveContributorError::RateLimited(wait) => Self::RateLimited {
+                retry_after_ms: millis_rounded_up(wait),
+            },
+            ActiveContributorError::Requeued(penalty) => Self::Requeued {
+                // Rounded up, so that clients do not ask too early.
+                retry_after: penalty.as_secs() + u64::from(penalty.subsec_nanos() > 0),
+            },
+            ActiveContributorError::NotAdmitted(next_draw) => Self::NotAdmitted {
+                retry_after: next_draw.as_secs() + u64::from(next_draw.subsec_nanos() > 0),
+            },
+            ActiveContributorError::LobbyPaused => Self::LobbyPaused,
+            ActiveContributorError::ShuttingDown => Self::ShuttingDown,
+            ActiveContributorError::CeremonyClosed => Self::CeremonyClosed,
+            ActiveContributorError::Store(err) => Self::LobbyStoreError(err),
+            ActiveContributorError::Storage(err) => Self::StorageError(err),
         }
     }
 }
 
+/// `duration` in milliseconds, rounded up, so that clients do not ask too
+/// early.
+fn millis_rounded_up(duration: Duration) -> u64 {
+    u64::try_from((duration.as_nanos() + 999_999) / 1_000_000).unwrap_or(u64::MAX)
+}
+
+/// Tag of the powers the next contribution builds on, sent as `ETag` with
+/// the contribution base. The powers only change with a contribution, so
+/// the tag is taken from the running products, not the powers themselves.
+#[must_use]
+pub fn powers_tag(transcript: &BatchTranscript) -> String {
+    let mut bytes = transcript.num_participants().to_be_bytes().to_vec();
+    for sub_ceremony in &transcript.transcripts {
+        if let Some(product) = sub_ceremony.witness.products.last() {
+            bytes.extend_from_slice(&product.0);
+        }
+    }
+    etag::tag(&bytes)
+}
+
+/// The contribution base, unless the client prefetched the current powers.
+fn contribution_base(
+    base: Arc<PreparedBase>,
+    headers: &HeaderMap,
+) -> (Option<Arc<PreparedBase>>, String) {
+    let tag = base.powers_tag.clone();
+    ((!etag::matches(headers, &tag)).then(|| base), tag)
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct TryContributeResponse<C> {
-    contribution: C,
+    /// `None` if the client has the current powers already.
+    contribution: Option<C>,
+    powers_tag:   String,
+    reservation:  String,
+    encoding:     ContributionEncoding,
+    /// Compression the client accepts for the contribution base.
+    compression:  Option<Encoding>,
 }
 
-impl<C: Serialize> IntoResponse for TryContributeResponse<C> {
+impl IntoResponse for TryContributeResponse<Arc<PreparedBase>> {
     fn into_response(self) -> Response {
-        (StatusCode::OK, Json(self.contribution)).into_response()
+        let headers = [(RESERVATION_HEADER, self.reservation)];
+        let contribution = match self.contribution {
+            Some(contribution) => contribution,
+            None => {
+                return (StatusCode::NO_CONTENT, headers, [(ETAG, self.powers_tag)]).into_response()
+            }
+        };
+        (
+            headers,
+            contribution.respond(self.encoding, self.compression),
+        )
+            .into_response()
     }
 }
 
+/// Checks in at the lobby and takes the contribution slot once it is the
+/// participant's turn. Those still waiting get an error body with status 200
+/// and one of the codes `SEQ-LOBBY-003`, `004`, `008` or `013`.
+#[allow(clippy::too_many_arguments)]
+#[utoipa::path(
+    post,
+    path = "/lobby/try_contribute",
+    tag = "lobby",
+    params(("If-None-Match" = Option<String>, Header, description = "`ETag` of the powers prefetched from `/lobby/powers`")),
+    responses(
+        (status = 200, description = "The slot and the contribution base, or a wait", body = BatchContribution,
+            headers(
+                ("X-Reservation-Token" = String, description = "Reservation to send with the contribution"),
+                ("ETag" = String, description = "Tag of the powers"),
+            )),
+        (status = 204, description = "The slot, the prefetched powers are current",
+            headers(("X-Reservation-Token" = String, description = "Reservation to send with the contribution"))),
+        (status = 401, description = "Unknown session", body = ErrorBody),
+        (status = 428, description = "Joining the lobby takes a proof of work", body = ErrorBody),
+        (status = 429, description = "Pinged too early", body = ErrorBody),
+        (status = 503, description = "The lobby is full", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+#[instrument(level = "info", skip_all)]
 pub async fn try_contribute(
     session_id: SessionId,
+    encoding: ContributionEncoding,
+    // Names the powers prefetched from `/lobby/powers` in `If-None-Match`.
+    headers: HeaderMap,
+    // Rejects outdated clients before they get the slot.
+    _client: ClientHeader,
+    pow: PowSolution,
     Extension(lobby_state): Extension<SharedLobbyState>,
     Extension(storage): Extension<PersistentStorage>,
     Extension(transcript): Extension<SharedTranscript>,
+    Extension(num_contributions): Extension<SharedCeremonyStatus>,
     Extension(options): Extension<crate::Options>,
-) -> Result<TryContributeResponse<BatchContribution>, TryContributeError> {
+    Extension(signer): Extension<SharedReservationSigner>,
+    Extension(provider_rules): Extension<SharedRuleSet>,
+    Extension(phases): Extension<SharedSchedule>,
+    Extension(handoff): Extension<SharedHandoff>,
+    audit: Audit,
+    spam_guard: SpamGuard,
+) -> Result<TryContributeResponse<Arc<PreparedBase>>, TryContributeError> {
+    let compression = Encoding::preferred(&headers);
+    let lobby_options = lobby_state.options();
     let res = lobby_state
         .modify_participant(&session_id, |mut info| {
             let now = Instant::now();
-            if !info.is_first_ping_attempt
-                && now < info.last_ping_time + options.lobby.min_checkin_delay()
-            {
-                return Err(TryContributeError::RateLimited);
+            // Those turned away from the full lobby were told to wait longer.
+            let min_delay = info
+                .min_poll_interval
+                .unwrap_or_else(|| lobby_options.min_checkin_delay());
+            let wait = (info.last_ping_time + min_delay).saturating_duration_since(now);
+            if !info.is_first_ping_attempt && !wait.is_zero() {
+                return Err(TryContributeError::RateLimited {
+                    retry_after_ms: millis_rounded_up(wait),
+                });
             }
             info.is_first_ping_attempt = false;
             info.last_ping_time = now;
-            Ok(info.token.unique_identifier())
+            Ok((
+                info.token.unique_identifier(),
+                info.token.identity.clone(),
+                info.eligibility_score,
+            ))
         })
         .await;
 
-    let uid = if let Some(inner) = res {
+    let (uid, identity, eligibility_score) = if let Some(inner) = res {
         inner?
     } else {
         // Session not found. Check if they're the active contributor, and
         // if so, if we can give them back the contribution base they need.
-        lobby_state
+        let reservation = lobby_state
             .request_contribution_file_again(&session_id)
             .await?;
 
-        let transcript = transcript.read().await;
+        let (contribution, powers_tag) =
+            contribution_base(handoff.prepare(&transcript).await?, &headers);
         return Ok(TryContributeResponse {
-            contribution: transcript.contribution(),
+            contribution,
+            powers_tag,
+            reservation,
+            encoding,
+            compression,
         });
     };
 
     // Attempt to set ourselves as the current contributor in the background,
     // so that request cancelation doesn't interrupt it inbetween the lobby_state
     // and storage calls.
-    tokio::spawn(async move {
-        lobby_state.enter_lobby(&session_id).await?;
+    tokio::spawn(
+        async move {
+            // Without a healthy database the contributor could not be
+            // recorded.
+            storage.check_healthy().await?;
+            // Bans also drop the sessions, this catches those created in a race
+            // with the ban.
+            if !lobby_state.is_in_lobby(&session_id).await {
+                // Checked first, it spares the database during spam waves.
+                pow.check(&session_id)?;
+                if storage.is_banned(&uid).await? {
+                    return Err(TryContributeError::UserBanned);
+                }
+                spam_guard
+                    .check(SpamStage::LobbyJoin, Some(&identity), None)
+                    .await?;
+                // Those in the lobby keep their place when their phase ends.
+                match phases.current().check(&identity, Utc::now()) {
+                    Ok(()) => {}
+                    Err(PhaseViolation::Closed) => return Err(TryContributeError::PhaseClosed),
+                    Err(PhaseViolation::NotEligible) => {
+                        return Err(TryContributeError::NotEligibleInPhase)
+                    }
+                }
+            }
+            let entered = lobby_state
+                .enter_lobby(&session_id)
+                .await
+                .map_err(TryContributeError::from);
+            // Only joining the lobby changes state, staying in it does not.
+            if !matches!(entered, Ok(false)) {
+                audit
+                    .record(AuditAction::LobbyJoin, Some(uid.clone()), outcome(&entered))
+                    .await;
+            }
+            let entered = match entered {
+                Err(TryContributeError::LobbyIsFull { .. })
+                    if options.waiting_room.waiting_room =>
+                {
+                    let position = storage.join_waiting_room(&uid).await?;
+                    return Err(TryContributeError::InWaitingRoom { position });
+                }
+                Err(TryContributeError::LobbyIsFull {
+                    retry_after_ms: Some(retry_after_ms),
+                }) => {
+                    // Polls before the hint are turned away without asking the
+                    // lobby store again.
+                    lobby_state
+                        .modify_participant(&session_id, |info| {
+                            info.min_poll_interval = Some(Duration::from_millis(retry_after_ms));
+                        })
+                        .await;
+                    return Err(TryContributeError::LobbyIsFull {
+                        retry_after_ms: Some(retry_after_ms),
+                    });
+                }
+                entered => entered?,
+            };
+            if entered {
+                storage
+                    .update_session_lobby(&session_id.hash(), Some(Utc::now()))
+                    .await?;
+                if options.waiting_room.waiting_room {
+                    storage.leave_waiting_room(&uid).await?;
+                }
+            }
 
-        lobby_state
-            .set_current_contributor(&session_id, options.lobby.compute_deadline, storage.clone())
-            .await
-            .map_err(TryContributeError::from)?;
+            fairness::record_poll(&storage, &session_id, &identity, entered).await;
 
-        storage.insert_contributor(&uid).await?;
-        let transcript = transcript.read().await;
+            // The base is only synced, prepared and signed for whoever gets
+            // the slot, the others are turned away without it.
+            let (reservation, base) = lobby_state
+                .grant_slot(&session_id, lobby_options.compute_deadline, || async {
+                    // Other sequencers sharing the database may have recorded
+                    // contributions since.
+                    sync_transcript(
+                        &options,
+                        &storage,
+                        &transcript,
+                        &num_contributions,
+                        &lobby_state,
+                    )
+                    .await?;
+                    // Prepared while the previous contribution was recorded.
+                    let base = handoff.prepare(&transcript).await?;
+                    let reservation =
+                        signer.sign(&base.reservation(uid.clone(), lobby_options.compute_deadline));
+                    Ok::<_, TryContributeError>((
+                        base.slot,
+                        reservation.clone(),
+                        (reservation, base),
+                    ))
+                })
+                .await?;
+            let slot = base.slot;
+            CONTRIBUTIONS_STARTED.inc();
+            fairness::finish_wait(&storage, &session_id, WaitOutcome::Slot).await;
 
-        Ok(TryContributeResponse {
-            contribution: transcript.contribution(),
-        })
-    })
+            // The session ends with taking the slot.
+            storage.delete_session(&session_id.hash()).await?;
+            let quota = provider_rules.current().quota(&uid);
+            // Requeued contributors go on with their earlier attempt.
+            if !storage.retry_contribution(&uid).await?
+                && !storage
+                    .insert_contributor_within_quota(&uid, eligibility_score, quota)
+                    .await?
+            {
+                // Contributors admitted since the participant signed in used up
+                // the quota.
+                lobby_state.abort_contribution(&session_id).await.ok();
+                return Err(TryContributeError::ProviderQuotaReached);
+            }
+            if !storage.reserve_contribution_index(&uid, slot).await? {
+                // Another sequencer sharing the database finished the
+                // contribution already, the next check-in builds on it.
+                lobby_state.abort_contribution(&session_id).await.ok();
+                sync_transcript(
+                    &options,
+                    &storage,
+                    &transcript,
+                    &num_contributions,
+                    &lobby_state,
+                )
+                .await?;
+                return Err(TryContributeError::AnotherContributionInProgress);
+            }
+            audit
+                .record(
+                    AuditAction::ContributionStarted,
+                    Some(uid),
+                    "ok".to_string(),
+                )
+                .await;
+            let (contribution, powers_tag) = contribution_base(base, &headers);
+
+            Ok(TryContributeResponse {
+                contribution,
+                powers_tag,
+                reservation,
+                encoding,
+                compression,
+            })
+        }
+        .in_current_span(),
+    )
     .await
     .unwrap_or_else(|e| Err(TryContributeError::TaskError(e)))
 }
 
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum WaitingRoomError {
+    #[error("unknown session id")]
+    UnknownSessionId,
+    #[error("invalid email address")]
+    InvalidEmail,
+    #[error("not in the waiting room")]
+    NotWaiting,
+    #[error("error in storage layer: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+impl ErrorCode for WaitingRoomError {
+    fn to_error_code(&self) -> String {
+        format!("WaitingRoomError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WaitingRoomRequest {
+    email: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WaitingRoomResponse {
+    /// Starting at 1.
+    position: usize,
+}
+
+/// Loosely checks an email address. Whether it is reachable is up to the
+/// mailer.
+fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            email.len() <= 254
+                && !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+/// Asks to be told at `email` when a slot frees up, for participants in the
+/// waiting room.
+#[utoipa::path(
+    post,
+    path = "/lobby/waiting_room",
+    tag = "lobby",
+    request_body = WaitingRoomRequest,
+    responses(
+        (status = 200, description = "Position in the waiting room", body = WaitingRoomResponse),
+        (status = 400, description = "Invalid email address", body = ErrorBody),
+        (status = 409, description = "Not in the waiting room", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+pub async fn waiting_room_notify(
+    session_id: SessionId,
+    Json(request): Json<WaitingRoomRequest>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<WaitingRoomResponse>, WaitingRoomError> {
+    let uid = lobby_state
+        .modify_participant(&session_id, |info| info.token.unique_identifier())
+        .await
+        .ok_or(WaitingRoomError::UnknownSessionId)?;
+    let email = request.email.trim();
+    if !is_valid_email(email) {
+        return Err(WaitingRoomError::InvalidEmail);
+    }
+    if !storage.set_waiting_room_email(&uid, email).await? {
+        return Err(WaitingRoomError::NotWaiting);
+    }
+    let position = storage
+        .waiting_room_position(&uid)
+        .await?
+        .ok_or(WaitingRoomError::NotWaiting)?;
+    Ok(Json(WaitingRoomResponse { position }))
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum NotificationTargetError {
+    #[error("unknown session id")]
+    UnknownSessionId,
+    #[error("notifications are disabled")]
+    Disabled,
+    #[error("invalid notification target")]
+    InvalidTarget,
+    #[error("notifications over {0} are not configured")]
+    ChannelUnavailable(&'static str),
+    #[error("no notification target registered")]
+    NotRegistered,
+    #[error("error in storage layer: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+impl ErrorCode for NotificationTargetError {
+    fn to_error_code(&self) -> String {
+        format!("NotificationTargetError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NotificationTargetResponse {
+    target:     NotificationTarget,
+    status:     NotificationStatus,
+    /// Deliveries attempted so far.
+    attempts:   u32,
+    /// When the notification was sent.
+    #[schema(value_type = Option<String>)]
+    sent_at:    Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+impl From<StoredNotification> for NotificationTargetResponse {
+    fn from(notification: StoredNotification) -> Self {
+        Self {
+            target:     notification.target,
+            status:     notification.status,
+            attempts:   notification.attempts,
+            sent_at:    notification.sent_at,
+            last_error: notification.last_error,
+        }
+    }
+}
+
+/// Asks to be told at `target` when the session is among the first
+/// `--notify-top` of the lobby. Replaces a target registered before.
+#[utoipa::path(
+    post,
+    path = "/lobby/notification_target",
+    tag = "lobby",
+    request_body = NotificationTarget,
+    responses(
+        (status = 200, description = "Registered notification target", body = NotificationTargetResponse),
+        (status = 400, description = "Invalid or unsupported target", body = ErrorBody),
+        (status = 404, description = "Notifications are disabled", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+pub async fn register_notification_target(
+    session_id: SessionId,
+    Json(target): Json<NotificationTarget>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(notifier): Extension<SharedNotifier>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<NotificationTargetResponse>, NotificationTargetError> {
+    if !notifier.is_enabled() {
+        return Err(NotificationTargetError::Disabled);
+    }
+    let uid = lobby_state
+        .modify_participant(&session_id, |info| info.token.unique_identifier())
+        .await
+        .ok_or(NotificationTargetError::UnknownSessionId)?;
+    let target = match target {
+        NotificationTarget::Email(email) if is_valid_email(email.trim()) => {
+            NotificationTarget::Email(email.trim().to_string())
+        }
+        NotificationTarget::Matrix(user_id) if is_valid_matrix_id(user_id.trim()) => {
+            NotificationTarget::Matrix(user_id.trim().to_string())
+        }
+        _ => return Err(NotificationTargetError::InvalidTarget),
+    };
+    if !notifier.supports(&target) {
+        return Err(NotificationTargetError::ChannelUnavailable(
+            target.channel(),
+        ));
+    }
+    let token_hash = session_id.hash();
+    storage
+        .register_notification(&token_hash, &uid, &target)
+        .await?;
+    let notification = storage
+        .notification(&token_hash)
+        .await?
+        .ok_or(NotificationTargetError::NotRegistered)?;
+    Ok(Json(notification.into()))
+}
+
+/// The notification target of the session and the state of its delivery.
+#[utoipa::path(
+    get,
+    path = "/lobby/notification_target",
+    tag = "lobby",
+    responses(
+        (status = 200, description = "Registered notification target", body = NotificationTargetResponse),
+        (status = 404, description = "No target registered, or notifications are disabled", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+pub async fn notification_target(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(notifier): Extension<SharedNotifier>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<NotificationTargetResponse>, NotificationTargetError> {
+    if !notifier.is_enabled() {
+        return Err(NotificationTargetError::Disabled);
+    }
+    if lobby_state.session_uid(&session_id).await.is_none() {
+        return Err(NotificationTargetError::UnknownSessionId);
+    }
+    let notification = storage
+        .notification(&session_id.hash())
+        .await?
+        .ok_or(NotificationTargetError::NotRegistered)?;
+    Ok(Json(notification.into()))
+}
+
+/// Number of recent contributions the wait time estimate is based on.
+const RECENT_CONTRIBUTIONS: usize = 20;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LobbyPositionResponse {
+    #[serde(flatten)]
+    position:                     LobbyPosition,
+    /// Average duration of recent contributions, if there are any.
+    average_contribution_seconds: Option<u64>,
+    /// Estimated time until the participant gets to contribute.
+    estimated_wait_seconds:       Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/lobby/position",
+    tag = "lobby",
+    responses(
+        (status = 200, description = "Position in the lobby", body = LobbyPositionResponse),
+        (status = 401, description = "Unknown session", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+pub async fn lobby_position(
+    session_id: SessionId,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Json<LobbyPositionResponse>, TryContributeError> {
+    let position = lobby_state.lobby_position(&session_id).await?;
+    let average = storage
+        .average_contribution_duration(RECENT_CONTRIBUTIONS)
+        .await?;
+    // Everyone ahead in the lobby, plus the contribution currently running.
+    let contributions_ahead = match position.position {
+        0 => 0,
+        n => n - 1 + usize::from(position.contribution_in_progress),
+    };
+    let estimated_wait_seconds = average
+        .map(|average| average.as_secs() * u64::try_from(contributions_ahead).unwrap_or(u64::MAX));
+    Ok(Json(LobbyPositionResponse {
+        position,
+        average_contribution_seconds: average.map(|average| average.as_secs()),
+        estimated_wait_seconds,
+    }))
+}
+
+/// Answers participants waiting in the lobby with the contribution base
+/// they would get now, tagged like the one of `/lobby/try_contribute`, so
+/// the powers are downloaded before the compute deadline starts. Refetched
+/// with `If-None-Match` once a `contribution_verified` event arrives.
+#[utoipa::path(
+    get,
+    path = "/lobby/powers",
+    tag = "lobby",
+    params(("If-None-Match" = Option<String>, Header, description = "`ETag` of the powers the client has")),
+    responses(
+        (status = 200, description = "The contribution base", body = BatchContribution,
+            headers(("ETag" = String, description = "Tag of the powers"))),
+        (status = 304, description = "The powers of the client are current"),
+        (status = 401, description = "Not waiting in the lobby", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+pub async fn lobby_powers(
+    session_id: SessionId,
+    encoding: ContributionEncoding,
+    headers: HeaderMap,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(handoff): Extension<SharedHandoff>,
+) -> Result<Response, TryContributeError> {
+    if !lobby_state.is_in_lobby(&session_id).await {
+        return Err(TryContributeError::UnknownSessionId);
+    }
+    let (contribution, powers_tag) =
+        contribution_base(handoff.prepare(&transcript).await?, &headers);
+    Ok(match contribution {
+        Some(contribution) => contribution.respond(encoding, Encoding::preferred(&headers)),
+        None => etag::not_modified(powers_tag),
+    })
+}
+
+/// Upgrades to a websocket that receives [`LobbyEvent`]s as JSON text messages,
+/// starting with the current lobby size.
+pub async fn lobby_events(
+    ws: WebSocketUpgrade,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+) -> Response {
+    ws.on_upgrade(|socket| stream_lobby_events(socket, lobby_state))
+}
+
+async fn stream_lobby_events(mut socket: WebSocket, lobby_state: SharedLobbyState) {
+    let mut events = lobby_state.subscribe();
+    let initial = LobbyEvent::LobbySize {
+        lobby_size: lobby_state.get_lobby_size().await,
+    };
+    if send_event(&mut socket, &initial).await.is_err() {
+        return;
+    }
+    loop {
+        let event = tokio::select! {
+            received = events.recv() => match received {
+                Ok(event) => event,
+                // Subscriber fell behind, resynchronize with the current size.
+                Err(RecvError::Lagged(_)) => LobbyEvent::LobbySize {
+                    lobby_size: lobby_state.get_lobby_size().await,
+                },
+                Err(RecvError::Closed) => break,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                // Clients have nothing to say, ignore anything but close.
+                Some(Ok(_)) => continue,
+            },
+        };
+        if send_event(&mut socket, &event).await.is_err() {
+            break;
+        }
+    }
+    debug!("lobby event subscriber disconnected");
+}
+
+async fn send_event(socket: &mut WebSocket, event: &LobbyEvent) -> Result<(), axum::Error> {
+    let message = serde_json::to_string(event).expect("lobby events serialize");
+    socket.send(Message::Text(message)).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         api::v1::lobby::TryContributeError,
+        reservation::ReservationSigner,
         storage::storage_client,
         test_util::{create_test_session_info, test_options},
         tests::test_transcript,
     };
-    use std::{sync::Arc, time::Duration};
     use tokio::sync::RwLock;
 
     #[tokio::test]
@@ -145,10 +786,21 @@ mod tests {
         // no users in lobby
         let unknown_session_response = try_contribute(
             session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(opts),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await;
         assert!(matches!(
@@ -167,19 +819,41 @@ mod tests {
         // "other participant" is contributing
         try_contribute(
             other_session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await
         .unwrap();
         let contribution_in_progress_response = try_contribute(
             session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await;
 
@@ -194,15 +868,30 @@ mod tests {
         tokio::time::advance(Duration::from_secs(5)).await;
         let too_soon_response = try_contribute(
             session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await;
 
         assert!(
-            matches!(too_soon_response, Err(TryContributeError::RateLimited),),
+            matches!(
+                too_soon_response,
+                Err(TryContributeError::RateLimited { retry_after_ms })
+                    if retry_after_ms > 0 && retry_after_ms <= 23_000
+            ),
             "response expected: Err(TryContributeError::RateLimited) actual: {:?}",
             too_soon_response
         );
@@ -214,15 +903,26 @@ mod tests {
         tokio::time::advance(Duration::from_secs(5)).await;
         let too_soon_response = try_contribute(
             session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await;
         assert!(matches!(
             too_soon_response,
-            Err(TryContributeError::RateLimited)
+            Err(TryContributeError::RateLimited { .. })
         ));
 
         // wait enough time to be able to contribute
@@ -231,10 +931,21 @@ mod tests {
         tokio::time::resume();
         let success_response = try_contribute(
             session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await
         .expect("try_contribute that should succeed failed");
@@ -242,13 +953,27 @@ mod tests {
         // if a user attempts to try_contribute again they should get rate limited
         let check_again = try_contribute(
             session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await;
-        assert!(matches!(check_again, Err(TryContributeError::RateLimited)));
+        assert!(matches!(
+            check_again,
+            Err(TryContributeError::RateLimited { .. })
+        ));
 
         tokio::time::pause();
         tokio::time::advance(test_options().lobby.min_checkin_delay()).await;
@@ -257,13 +982,162 @@ mod tests {
         // but after waiting a bit they should be able to re-fetch their transcript
         let refetch_transcript = try_contribute(
             session_id.clone(),
+            ContributionEncoding::Json,
+            HeaderMap::new(),
+            ClientHeader::default(),
+            PowSolution::default(),
             Extension(lobby_state.clone()),
             Extension(db.clone()),
             Extension(transcript.clone()),
+            Extension(SharedCeremonyStatus::default()),
             Extension(test_options()),
+            Extension(Arc::new(ReservationSigner::default())),
+            Extension(SharedRuleSet::default()),
+            Extension(SharedSchedule::default()),
+            Extension(SharedHandoff::default()),
+            Audit::default(),
+            SpamGuard::default(),
         )
         .await
         .expect("re-fetching the transcript with try_contribute failed");
         assert_eq!(success_response, refetch_transcript);
     }
+
+    #[tokio::test]
+    async fn prefetches_powers() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let db = storage_client(&opts.storage).await.unwrap();
+        let session_id = SessionId::new();
+        let other_session_id = SessionId::new();
+        for id in [&session_id, &other_session_id] {
+            lobby_state
+                .insert_session(id.clone(), create_test_session_info(100))
+                .await
+                .unwrap();
+        }
+        let handoff = SharedHandoff::default();
+        let take_slot = |session_id: SessionId, headers: HeaderMap| {
+            try_contribute(
+                session_id,
+                ContributionEncoding::Json,
+                headers,
+                ClientHeader::default(),
+                PowSolution::default(),
+                Extension(lobby_state.clone()),
+                Extension(db.clone()),
+                Extension(transcript.clone()),
+                Extension(SharedCeremonyStatus::default()),
+                Extension(test_options()),
+                Extension(Arc::new(ReservationSigner::default())),
+                Extension(SharedRuleSet::default()),
+                Extension(SharedSchedule::default()),
+                Extension(handoff.clone()),
+                Audit::default(),
+                SpamGuard::default(),
+            )
+        };
+        let prefetch = |session_id: SessionId, headers: HeaderMap| {
+            lobby_powers(
+                session_id,
+                ContributionEncoding::Json,
+                headers,
+                Extension(lobby_state.clone()),
+                Extension(transcript.clone()),
+                Extension(handoff.clone()),
+            )
+        };
+
+        take_slot(other_session_id, HeaderMap::new()).await.unwrap();
+        // Joins the lobby.
+        assert!(matches!(
+            take_slot(session_id.clone(), HeaderMap::new()).await,
+            Err(TryContributeError::AnotherContributionInProgress)
+        ));
+        assert!(matches!(
+            prefetch(SessionId::new(), HeaderMap::new()).await,
+            Err(TryContributeError::UnknownSessionId)
+        ));
+        let prefetched = prefetch(session_id.clone(), HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(prefetched.status(), StatusCode::OK);
+        let tag = prefetched.headers()[ETAG].clone();
+        assert_eq!(tag, powers_tag(&*transcript.read().await));
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::IF_NONE_MATCH, tag.clone());
+        let unchanged = prefetch(session_id.clone(), headers.clone()).await.unwrap();
+        assert_eq!(unchanged.status(), StatusCode::NOT_MODIFIED);
+
+        // The slot comes without the powers already downloaded.
+        lobby_state.clear_current_contributor().await;
+        tokio::time::pause();
+        tokio::time::advance(test_options().lobby.min_checkin_delay()).await;
+        tokio::time::resume();
+        let response = take_slot(session_id, headers)
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[ETAG], tag);
+        assert!(response.headers().contains_key(RESERVATION_HEADER));
+    }
+
+    #[tokio::test]
+    async fn reports_lobby_position() {
+        let opts = test_options();
+        let lobby_state = SharedLobbyState::new(opts.lobby.clone());
+        let db = storage_client(&opts.storage).await.unwrap();
+
+        let sessions = [SessionId::new(), SessionId::new(), SessionId::new()];
+        for session_id in &sessions {
+            lobby_state
+                .insert_session(session_id.clone(), create_test_session_info(100))
+                .await
+                .unwrap();
+        }
+        let not_in_lobby = lobby_position(
+            sessions[0].clone(),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+        )
+        .await;
+        assert!(matches!(
+            not_in_lobby,
+            Err(TryContributeError::UnknownSessionId)
+        ));
+
+        for session_id in &sessions {
+            lobby_state.enter_lobby(session_id).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        lobby_state
+            .set_current_contributor(&sessions[0], 1, String::new(), opts.lobby.compute_deadline)
+            .await
+            .unwrap();
+
+        let Json(active) = lobby_position(
+            sessions[0].clone(),
+            Extension(lobby_state.clone()),
+            Extension(db.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(active.position.position, 0);
+        assert_eq!(active.estimated_wait_seconds, None);
+
+        let Json(last) = lobby_position(
+            sessions[2].clone(),
+            Extension(lobby_state.clone()),
+            Extension(db),
+        )
+        .await
+        .unwrap();
+        assert_eq!(last.position, LobbyPosition {
+            position:                 2,
+            lobby_size:               2,
+            contribution_in_progress: true,
+        });
+    }
 }
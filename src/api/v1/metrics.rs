@@ -0,0 +1,46 @@
+use crate::{
+    lobby::SharedLobbyState,
+    metrics::{LOBBY_SIZE, SESSION_COUNT},
+};
+use axum::{
+    response::{IntoResponse, Response},
+    Extension,
+};
+use http::{header, StatusCode};
+use prometheus::{Encoder, TextEncoder};
+
+pub async fn metrics(Extension(lobby_state): Extension<SharedLobbyState>) -> Response {
+    // Gauges are sampled at scrape time
+    LOBBY_SIZE.set(i64::try_from(lobby_state.get_lobby_size().await).unwrap_or(i64::MAX));
+    SESSION_COUNT.set(i64::try_from(lobby_state.get_session_count().await).unwrap_or(i64::MAX));
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+    }
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metrics::CONTRIBUTIONS_STARTED, test_util::test_options};
+
+    #[tokio::test]
+    async fn exports_metrics() {
+        CONTRIBUTIONS_STARTED.inc();
+        let lobby_state = SharedLobbyState::new(test_options().lobby);
+        let response = metrics(Extension(lobby_state)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("sequencer_contributions_started"));
+        assert!(body.contains("sequencer_lobby_size 0"));
+    }
+}
@@ -1,5 +1,12 @@
+pub mod account;
+pub mod admin;
 pub mod auth;
 pub mod contribute;
 pub mod error_response;
+pub mod health;
 pub mod info;
 pub mod lobby;
+pub mod metrics;
+pub mod openapi;
+pub mod replication;
+pub mod transcript;
@@ -0,0 +1,332 @@
+//! OpenAPI 3 description of the participant API, served at
+//! `/api-docs/openapi.json` and, with the `swagger-ui` feature, browsable
+//! under `/swagger-ui/`.
+//!
+//! Paths and most schemas are derived from the handlers and their request
+//! and response types, so the document follows them. Bodies whose shape is
+//! not defined by a type of the sequencer, like the contribution from the
+//! crypto crate, are described by the types below. A test checks them
+//! against the real serialization, and another that every participant route
+//! of the router is documented.
+
+use super::{account, auth, contribute, error_response::ErrorBody, info, lobby};
+use crate::verification::{VerificationProgress, VerificationStage, VerificationStatus};
+use axum::Json;
+use serde::Serialize;
+use utoipa::{
+    openapi::{
+        security::{Http, HttpAuthScheme, SecurityScheme},
+        Server,
+    },
+    Modify, OpenApi, ToSchema,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        auth::auth_client_link,
+        auth::auth_callback,
+        lobby::try_contribute,
+        lobby::lobby_position,
+        lobby::lobby_powers,
+        lobby::waiting_room_notify,
+        lobby::register_notification_target,
+        lobby::notification_target,
+        contribute::contribute,
+        contribute::contribute_abort,
+        contribute::contribute_extend,
+        contribute::contribute_deadline,
+        contribute::contribute_heartbeat,
+        contribute::contribute_upload_part,
+        contribute::contribute_upload_status,
+        contribute_upload_commit,
+        contribute::contribute_validate,
+        contribute::contribute_status,
+        contribute::contribute_progress,
+        contribute::receipt,
+        info::status,
+        info::identity,
+        info::jwks,
+        account::me,
+        account::delete_account,
+    ),
+    components(schemas(
+        ErrorBody,
+        BatchContribution,
+        Contribution,
+        PowersOfTau,
+        UserVerified,
+        IdToken,
+        lobby::LobbyPositionResponse,
+        lobby::WaitingRoomRequest,
+        lobby::WaitingRoomResponse,
+        lobby::NotificationTargetResponse,
+        crate::notifications::NotificationTarget,
+        crate::notifications::NotificationStatus,
+        crate::lobby::LobbyPosition,
+        contribute::ContributeReceipt,
+        contribute::ContributeAccepted,
+        contribute::DeadlineExtended,
+        contribute::ContributionDeadline,
+        crate::chunked_upload::UploadStatus,
+        crate::chunked_upload::PartStatus,
+        VerificationStatus,
+        VerificationProgress,
+        VerificationStage,
+        crate::rejection::RejectionReason,
+        crate::rejection::PointGroup,
+        info::StatusResponse,
+        info::IdentityResponse,
+        info::Jwks,
+        info::Jwk,
+        account::MeResponse,
+        account::EligibilityStatus,
+        account::LobbyStatus,
+        crate::lobby::SessionPlace,
+        crate::storage::StoredAttempt,
+        crate::storage::StoredReceipt,
+        account::DeleteAccountResponse,
+    )),
+    modifiers(&Servers, &SessionAuth),
+    tags(
+        (name = "auth", description = "Signing in with an identity provider"),
+        (name = "lobby", description = "Waiting for and taking the contribution slot"),
+        (name = "contribute", description = "Submitting a contribution"),
+        (name = "info", description = "Public ceremony information"),
+        (name = "account", description = "The participant's own data"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Paths are relative to the ceremony, which is wherever the document is
+/// served from, so the server is given relative to it.
+struct Servers;
+
+impl Modify for Servers {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi.servers = Some(vec![Server::new("..")]);
+    }
+}
+
+/// The session id handed out by `/auth/callback/{provider}`, sent as bearer
+/// token.
+struct SessionAuth;
+
+impl Modify for SessionAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "session",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+    }
+}
+
+#[allow(clippy::unused_async)] // Required for axum function signature
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI under `/swagger-ui/`. It loads the document relative to its own
+/// path, so that it works below the server prefix and in every ceremony.
+#[cfg(feature = "swagger-ui")]
+pub fn swagger_ui() -> utoipa_swagger_ui::SwaggerUi {
+    utoipa_swagger_ui::SwaggerUi::new("/swagger-ui/*tail")
+        .config(utoipa_swagger_ui::Config::new(["../api-docs/openapi.json"]))
+}
+
+/// `POST /contribute/upload` is `/contribute` with the body joined from the
+/// parts, by [`crate::chunked_upload::commit`] in front of the handler.
+#[allow(dead_code)] // Never called, it only describes the route.
+#[utoipa::path(
+    post,
+    path = "/contribute/upload",
+    tag = "contribute",
+    params(
+        ("X-Upload-Sha256" = String, Header, description = "Hex encoded SHA-256 of the joined parts"),
+        ("X-Reservation-Token" = String, Header, description = "Reservation handed out with the slot"),
+    ),
+    responses(
+        (status = 200, description = "The signed receipt", body = ContributeReceipt),
+        (status = 202, description = "Queued for verification, see `/contribute/status/{id}`", body = ContributeAccepted),
+        (status = 400, description = "Digest mismatch, invalid contribution or reservation, or not the active contributor", body = ErrorBody),
+        (status = 404, description = "No upload of this session", body = ErrorBody),
+        (status = 409, description = "A part is missing", body = ErrorBody),
+    ),
+    security(("session" = []))
+)]
+fn contribute_upload_commit() {}
+
+/// JSON encoding of a batch contribution, as sent by `/lobby/try_contribute`
+/// and to `/contribute`. `/lobby/try_contribute` leaves the signatures empty.
+/// Clients that send or accept `application/octet-stream` use the binary
+/// encoding instead, see the Readme.
+#[allow(dead_code)] // Never built, it only describes the body.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchContribution {
+    /// One per sub-ceremony, in the order of `/info/current_state`.
+    contributions:       Vec<Contribution>,
+    /// Hex encoded EIP-712 signature of the pot pubkeys by the participant's
+    /// Ethereum address, or `null`.
+    ecdsa_signature:     Option<String>,
+    /// Free text about the sources of the entropy, at most 256 bytes. Not
+    /// covered by the signatures.
+    entropy_attestation: Option<String>,
+}
+
+/// The contribution to one sub-ceremony.
+#[allow(dead_code)] // Never built, it only describes the body.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Contribution {
+    num_g1_powers: usize,
+    num_g2_powers: usize,
+    powers_of_tau: PowersOfTau,
+    /// Hex encoded compressed G2 point.
+    pot_pubkey:    String,
+    /// Hex encoded compressed G1 point, or `""`.
+    bls_signature: String,
+}
+
+#[allow(dead_code)] // Never built, it only describes the body.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct PowersOfTau {
+    /// Hex encoded compressed G1 points.
+    g1_powers: Vec<String>,
+    /// Hex encoded compressed G2 points.
+    g2_powers: Vec<String>,
+}
+
+/// Response of `/auth/callback/{provider}` without `redirect_to`. With it,
+/// the same fields are appended to the redirect as query parameters.
+#[allow(dead_code)] // Never built, it only describes the body.
+#[derive(Serialize, ToSchema)]
+pub struct UserVerified {
+    id_token:   IdToken,
+    /// Bearer token of the session.
+    session_id: String,
+}
+
+#[allow(dead_code)] // Never built, it only describes the body.
+#[derive(Serialize, ToSchema)]
+pub struct IdToken {
+    /// Unique id of the participant, e.g. `git|1234#acde033f`.
+    sub:      String,
+    nickname: String,
+    provider: String,
+    /// Unix timestamp (in seconds) when the session expires.
+    exp:      u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kzg_ceremony_crypto::BatchTranscript;
+    use serde_json::Value;
+
+    fn properties<'a>(doc: &'a Value, schema: &str) -> Vec<&'a String> {
+        doc["components"]["schemas"][schema]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect()
+    }
+
+    #[test]
+    fn documents_participant_api() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        for path in [
+            "/auth/request_link",
+            "/auth/callback/{provider}",
+            "/lobby/try_contribute",
+            "/contribute",
+            "/contribution/receipt/{uid}",
+            "/info/status",
+        ] {
+            assert!(doc["paths"][path].is_object(), "{path} is not documented");
+        }
+        assert!(doc["paths"]["/me"]["get"].is_object());
+        assert!(doc["paths"]["/me"]["delete"].is_object());
+        assert!(doc["components"]["securitySchemes"]["session"].is_object());
+    }
+
+    /// Routes of the router that are not part of the participant API.
+    const UNDOCUMENTED: &[&str] = &[
+        "/info/current_state",
+        "/info/statistics",
+        "/info/fairness",
+        "/info/lottery",
+        "/info/lottery/{epoch}",
+        "/info/contributions",
+        "/info/contributors",
+        "/info/contributor/{index}",
+        "/info/sth",
+        "/info/inclusion_proof",
+        "/replication/contributions/{num}",
+        "/transcript/diff",
+        "/transcript/verify_chain",
+        "/transcript/stream",
+        "/transcript/contributions",
+        "/transcript/powers/{sub_ceremony}",
+        "/healthz",
+        "/readyz",
+        "/metrics",
+        "/ws/lobby",
+        "/api-docs/openapi.json",
+    ];
+
+    /// The paths passed to `.route` in `lib.rs`, in OpenAPI syntax.
+    fn router_paths() -> Vec<String> {
+        include_str!("../../lib.rs")
+            .split(".route(")
+            .skip(1)
+            .filter_map(|call| call.trim_start().strip_prefix('"')?.split('"').next())
+            .map(|path| {
+                path.split('/')
+                    .map(|segment| {
+                        segment
+                            .strip_prefix(':')
+                            .map_or_else(|| segment.to_string(), |name| format!("{{{name}}}"))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn documents_every_participant_route() {
+        let paths = ApiDoc::openapi().paths.paths;
+        let routes = router_paths();
+        assert!(routes.contains(&"/contribute/upload/{part}".to_string()));
+        for route in routes {
+            if route.starts_with("/admin") || route.starts_with("/explorer") {
+                continue;
+            }
+            if UNDOCUMENTED.contains(&route.as_str()) {
+                continue;
+            }
+            assert!(paths.contains_key(&route), "{route} is not documented");
+        }
+    }
+
+    #[test]
+    fn describes_contributions() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let mut contribution = BatchTranscript::new(&[(4, 2)]).contribution();
+        contribution.entropy_attestation = Some("test".to_string());
+        let json = serde_json::to_value(&contribution).unwrap();
+        let keys = |value: &Value| value.as_object().unwrap().keys().collect::<Vec<_>>();
+
+        assert_eq!(keys(&json), properties(&doc, "BatchContribution"));
+        let sub_ceremony = &json["contributions"][0];
+        assert_eq!(keys(sub_ceremony), properties(&doc, "Contribution"));
+        assert_eq!(
+            keys(&sub_ceremony["powersOfTau"]),
+            properties(&doc, "PowersOfTau")
+        );
+    }
+}
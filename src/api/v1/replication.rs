@@ -0,0 +1,20 @@
+use crate::replication::{ReplicationLogError, SharedReplicationLog, POLL_WAIT};
+use axum::{
+    extract::Path,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use http::StatusCode;
+
+/// The signed contribution that made the transcript `num` contributions long,
+/// for standby sequencers. Waits for it if it is not verified yet, see
+/// [`crate::replication`].
+pub async fn replicated_contribution(
+    Path(num): Path<usize>,
+    Extension(log): Extension<SharedReplicationLog>,
+) -> Result<Response, ReplicationLogError> {
+    Ok(match log.wait_for(num, POLL_WAIT).await? {
+        Some(signed) => Json(&*signed).into_response(),
+        None => StatusCode::NO_CONTENT.into_response(),
+    })
+}
@@ -0,0 +1,529 @@
+use crate::{
+    ceremony::CeremonyId,
+    etag,
+    keys::{Address, SharedKeys, Signature},
+    lobby::{LobbyEvent, SharedLobbyState},
+    storage::{PersistentStorage, StorageError, StoredReceipt},
+    util::last_event_id,
+    witness_chain::{ChainReportError, SharedChainVerifier},
+    SharedTranscript,
+};
+use axum::{
+    extract::{Path, Query},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Extension, Json,
+};
+use futures::{stream, Stream};
+use http::{HeaderMap, StatusCode};
+use kzg_ceremony_crypto::{
+    signature::{identity::Identity, BlsSignature, EcdsaSignature},
+    BatchTranscript, ErrorCode, G1, G2,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::Instant,
+};
+
+/// Largest number of entries `/transcript/contributions` returns at once.
+const MAX_ENTRIES_PAGE: usize = 1000;
+
+/// Number of powers of each group in a chunk of `/transcript/powers`.
+pub const POWERS_CHUNK_SIZE: usize = 4096;
+
+/// How long `/transcript/stream` waits for the receipt of a new contribution
+/// to be stored, which happens after it is added to the transcript.
+const RECEIPT_WAIT: Duration = Duration::from_secs(2);
+
+/// How often `/transcript/stream` looks for a receipt while it waits.
+const RECEIPT_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum TranscriptPageError {
+    #[error("unknown sub-ceremony {0}")]
+    UnknownCeremony(usize),
+    #[error("offset {offset} is past the {num_entries} entries of the transcript")]
+    InvalidOffset {
+        offset:      usize,
+        num_entries: usize,
+    },
+    #[error("chunk {chunk} is past the {num_chunks} chunks of the powers")]
+    InvalidChunk {
+        chunk:      usize,
+        num_chunks: usize,
+    },
+}
+
+impl ErrorCode for TranscriptPageError {
+    fn to_error_code(&self) -> String {
+        format!("TranscriptPageError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntriesQuery {
+    #[serde(default)]
+    offset: usize,
+    #[serde(default = "default_entries_limit")]
+    limit:  usize,
+}
+
+const fn default_entries_limit() -> usize {
+    100
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntriesPage {
+    offset:      usize,
+    /// Entries of the transcript, including the initial one at index 0.
+    num_entries: usize,
+    entries:     Vec<ParticipantEntry>,
+}
+
+/// Everything the transcript records about one participant.
+#[derive(Debug, Serialize)]
+pub struct ParticipantEntry {
+    index:               usize,
+    participant:         Identity,
+    ecdsa_signature:     EcdsaSignature,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entropy_attestation: Option<String>,
+    /// One per sub-ceremony.
+    witnesses:           Vec<WitnessEntry>,
+}
+
+impl ParticipantEntry {
+    fn new(transcript: &BatchTranscript, index: usize) -> Self {
+        Self {
+            index,
+            participant: transcript.participant_ids[index].clone(),
+            ecdsa_signature: transcript.participant_ecdsa_signatures[index].clone(),
+            entropy_attestation: transcript.entropy_attestation(index).map(str::to_string),
+            witnesses: transcript
+                .transcripts
+                .iter()
+                .map(|transcript| WitnessEntry {
+                    running_product: transcript.witness.products[index],
+                    pot_pubkey:      transcript.witness.pubkeys[index],
+                    bls_signature:   transcript.witness.signatures[index].clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WitnessEntry {
+    running_product: G1,
+    pot_pubkey:      G2,
+    bls_signature:   BlsSignature,
+}
+
+/// A page of the participant entries of the transcript, with their witness.
+/// Entries never change once added, so the pages of a growing transcript can
+/// be fetched once each.
+pub async fn transcript_contributions(
+    Query(query): Query<EntriesQuery>,
+    headers: HeaderMap,
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Result<Response, TranscriptPageError> {
+    let transcript = transcript.read().await;
+    let num_entries = transcript.participant_ids.len();
+    let EntriesQuery { offset, limit } = query;
+    if offset > num_entries {
+        return Err(TranscriptPageError::InvalidOffset {
+            offset,
+            num_entries,
+        });
+    }
+    let end = offset
+        .saturating_add(limit.min(MAX_ENTRIES_PAGE))
+        .min(num_entries);
+    let entries = (offset..end)
+        .map(|index| ParticipantEntry::new(&transcript, index))
+        .collect();
+    drop(transcript);
+    Ok(etag::json(&headers, &EntriesPage {
+        offset,
+        num_entries,
+        entries,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Index of the last entry the client has, 0 for the initial one only.
+    #[serde(default)]
+    since: usize,
+}
+
+/// A participant entry as sent by `/transcript/stream`.
+#[derive(Debug, Serialize)]
+pub struct StreamedEntry {
+    #[serde(flatten)]
+    entry:   ParticipantEntry,
+    /// `None` for the beacon and for contributions whose receipt was not
+    /// stored.
+    receipt: Option<StoredReceipt>,
+}
+
+struct StreamState {
+    /// Index of the next entry to send.
+    next:       usize,
+    /// Number of entries when the stream started. Receipts of later ones may
+    /// not be stored yet.
+    backlog:    usize,
+    events:     broadcast::Receiver<LobbyEvent>,
+    transcript: SharedTranscript,
+    storage:    PersistentStorage,
+}
+
+/// The participant entries after `since`, then every new one as it is added,
+/// with their receipts, as server-sent events with the index of the entry as
+/// their id. A client that reconnects with `Last-Event-ID` continues after
+/// that entry instead.
+pub async fn transcript_stream(
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(lobby_state): Extension<SharedLobbyState>,
+    Extension(storage): Extension<PersistentStorage>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, TranscriptPageError> {
+    let since = last_event_id(&headers)
+        .and_then(|id| usize::try_from(id).ok())
+        .unwrap_or(query.since);
+    // Subscribed first, so that no entry added meanwhile is missed.
+    let events = lobby_state.subscribe();
+    let num_entries = transcript.read().await.participant_ids.len();
+    if since >= num_entries {
+        return Err(TranscriptPageError::InvalidOffset {
+            offset: since,
+            num_entries,
+        });
+    }
+    let state = StreamState {
+        next: since + 1,
+        backlog: num_entries,
+        events,
+        transcript,
+        storage,
+    };
+    let entries = stream::unfold(state, |mut state| async move {
+        loop {
+            let entry = {
+                let transcript = state.transcript.read().await;
+                (state.next < transcript.participant_ids.len())
+                    .then(|| ParticipantEntry::new(&transcript, state.next))
+            };
+            if let Some(entry) = entry {
+                let index = state.next;
+                let wait = if index < state.backlog {
+                    Duration::ZERO
+                } else {
+                    RECEIPT_WAIT
+                };
+                state.next += 1;
+                let event = match stored_receipt(&state.storage, index, wait).await {
+                    Ok(receipt) => Event::default()
+                        .id(index.to_string())
+                        .json_data(StreamedEntry { entry, receipt }),
+                    Err(e) => Err(axum::Error::new(e)),
+                };
+                return Some((event, state));
+            }
+            // Entries are added before `ContributionVerified` is published.
+            if matches!(state.events.recv().await, Err(RecvError::Closed)) {
+                return None;
+            }
+        }
+    });
+    Ok(Sse::new(entries).keep_alive(KeepAlive::default()))
+}
+
+/// The receipt of the contribution at `index`, waiting up to `wait` for it
+/// to be stored.
+async fn stored_receipt(
+    storage: &PersistentStorage,
+    index: usize,
+    wait: Duration,
+) -> Result<Option<StoredReceipt>, StorageError> {
+    let deadline = Instant::now() + wait;
+    loop {
+        if let Some(contributor) = storage.get_contributor(index).await? {
+            return Ok(Some(contributor.receipt));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        tokio::time::sleep(RECEIPT_POLL).await;
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PowersQuery {
+    #[serde(default)]
+    chunk: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PowersChunk {
+    /// Powers change with every contribution, chunks only fit together if
+    /// they were taken at the same number of contributions.
+    num_contributions: usize,
+    chunk:             usize,
+    num_chunks:        usize,
+    /// Index of the first power of each group in the chunk.
+    start:             usize,
+    num_g1_powers:     usize,
+    num_g2_powers:     usize,
+    g1_powers:         Vec<G1>,
+    g2_powers:         Vec<G2>,
+}
+
+/// A chunk of [`POWERS_CHUNK_SIZE`] powers of each group of a sub-ceremony.
+pub async fn transcript_powers(
+    Path(sub_ceremony): Path<usize>,
+    Query(query): Query<PowersQuery>,
+    headers: HeaderMap,
+    Extension(transcript): Extension<SharedTranscript>,
+) -> Result<Response, TranscriptPageError> {
+    let transcript = transcript.read().await;
+    let num_contributions = transcript.num_participants();
+    let powers = &transcript
+        .transcripts
+        .get(sub_ceremony)
+        .ok_or(TranscriptPageError::UnknownCeremony(sub_ceremony))?
+        .powers;
+    let (num_g1_powers, num_g2_powers) = (powers.g1.len(), powers.g2.len());
+    let num_chunks =
+        ((num_g1_powers.max(num_g2_powers) + POWERS_CHUNK_SIZE - 1) / POWERS_CHUNK_SIZE).max(1);
+    let chunk = query.chunk;
+    if chunk >= num_chunks {
+        return Err(TranscriptPageError::InvalidChunk { chunk, num_chunks });
+    }
+    let start = chunk * POWERS_CHUNK_SIZE;
+    let range = |len: usize| start.min(len)..(start + POWERS_CHUNK_SIZE).min(len);
+    let g1_powers = powers.g1[range(num_g1_powers)].to_vec();
+    let g2_powers = powers.g2[range(num_g2_powers)].to_vec();
+    drop(transcript);
+    Ok(etag::json(&headers, &PowersChunk {
+        num_contributions,
+        chunk,
+        num_chunks,
+        start,
+        num_g1_powers,
+        num_g2_powers,
+        g1_powers,
+        g2_powers,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyChainQuery {
+    up_to: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct VerifyChainResponse {
+    sequencer_address: Address,
+    /// JSON encoded [`ChainReport`](crate::witness_chain::ChainReport),
+    /// signed as is.
+    report:            String,
+    signature:         Signature,
+}
+
+impl IntoResponse for VerifyChainResponse {
+    fn into_response(self) -> Response {
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+/// Checks the witness chain up to contribution `up_to` and answers with a
+/// signed report, see [`crate::witness_chain`].
+pub async fn verify_chain(
+    Query(query): Query<VerifyChainQuery>,
+    Extension(ceremony): Extension<CeremonyId>,
+    Extension(keys): Extension<SharedKeys>,
+    Extension(transcript): Extension<SharedTranscript>,
+    Extension(verifier): Extension<SharedChainVerifier>,
+) -> Result<VerifyChainResponse, ChainReportError> {
+    // Copied, so that contributions are not held up while verifying.
+    let transcript = transcript.read().await.clone();
+    let report = verifier.report(ceremony.0, transcript, query.up_to).await?;
+    let (report, signature) = report.sign(&keys).await?;
+    Ok(VerifyChainResponse {
+        sequencer_address: keys.address(),
+        report,
+        signature,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        storage::storage_client,
+        test_util::test_options,
+        tests::{test_transcript, valid_contribution},
+        Engine,
+    };
+    use axum::body::BoxBody;
+    use http::{
+        header::{ETAG, IF_NONE_MATCH},
+        HeaderValue, StatusCode,
+    };
+    use hyper::body::HttpBody;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn pages_the_transcript() {
+        let mut transcript = test_transcript();
+        for no in 1..=3 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        let transcript = Arc::new(RwLock::new(transcript));
+        let page = |offset, limit, headers| {
+            transcript_contributions(
+                Query(EntriesQuery { offset, limit }),
+                headers,
+                Extension(transcript.clone()),
+            )
+        };
+
+        let response = page(2, 10, HeaderMap::new()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[ETAG].clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["num_entries"], 4);
+        assert_eq!(body["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(body["entries"][0]["index"], 2);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, etag);
+        let response = page(2, 10, headers.clone()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        let response = page(1, 10, headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(matches!(
+            page(5, 10, HeaderMap::new()).await,
+            Err(TranscriptPageError::InvalidOffset { .. })
+        ));
+
+        let powers = |sub_ceremony, chunk| {
+            transcript_powers(
+                Path(sub_ceremony),
+                Query(PowersQuery { chunk }),
+                HeaderMap::new(),
+                Extension(transcript.clone()),
+            )
+        };
+        let response = powers(0, 0).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["num_chunks"], 1);
+        assert_eq!(body["g1_powers"].as_array().unwrap().len(), 4);
+        assert!(matches!(
+            powers(0, 1).await,
+            Err(TranscriptPageError::InvalidChunk { .. })
+        ));
+        assert!(matches!(
+            powers(1, 0).await,
+            Err(TranscriptPageError::UnknownCeremony(1))
+        ));
+    }
+
+    /// The id and data of the next event of an SSE body.
+    async fn next_event(body: &mut BoxBody) -> (String, serde_json::Value) {
+        let chunk = body.data().await.unwrap().unwrap();
+        let event = String::from_utf8(chunk.to_vec()).unwrap();
+        let field = |name: &str| {
+            event
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .unwrap()
+                .trim()
+                .to_string()
+        };
+        (field("id:"), serde_json::from_str(&field("data:")).unwrap())
+    }
+
+    #[tokio::test]
+    async fn streams_new_entries() {
+        let mut transcript = test_transcript();
+        for no in 1..=2 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        let transcript = Arc::new(RwLock::new(transcript));
+        let options = test_options();
+        let lobby_state = SharedLobbyState::new(options.lobby.clone());
+        let storage = storage_client(&options.storage).await.unwrap();
+        storage
+            .insert_receipt("git|1234|test_user", 3, &StoredReceipt {
+                receipt:   "receipt 3".to_string(),
+                signature: "signature 3".to_string(),
+            })
+            .await
+            .unwrap();
+        let stream = |since, headers| {
+            transcript_stream(
+                Query(StreamQuery { since }),
+                headers,
+                Extension(transcript.clone()),
+                Extension(lobby_state.clone()),
+                Extension(storage.clone()),
+            )
+        };
+        assert!(matches!(
+            stream(3, HeaderMap::new()).await,
+            Err(TranscriptPageError::InvalidOffset { .. })
+        ));
+
+        let mut body = stream(1, HeaderMap::new())
+            .await
+            .unwrap()
+            .into_response()
+            .into_body();
+        let (id, data) = next_event(&mut body).await;
+        assert_eq!(id, "2");
+        assert_eq!(data["index"], 2);
+        assert_eq!(data["witnesses"].as_array().unwrap().len(), 1);
+        assert!(data["receipt"].is_null());
+
+        {
+            let mut transcript = transcript.write().await;
+            let contribution = valid_contribution(&transcript, 3);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        lobby_state.publish(LobbyEvent::ContributionVerified {
+            num_contributions: 3,
+        });
+        let (id, data) = next_event(&mut body).await;
+        assert_eq!(id, "3");
+        assert_eq!(data["receipt"]["receipt"], "receipt 3");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("last-event-id", HeaderValue::from_static("2"));
+        let mut body = stream(0, headers)
+            .await
+            .unwrap()
+            .into_response()
+            .into_body();
+        assert_eq!(next_event(&mut body).await.0, "3");
+    }
+}
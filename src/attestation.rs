@@ -0,0 +1,87 @@
+use crate::{
+    io::CeremonySizes,
+    keys::{Keys, Signature, SignatureError},
+};
+use chrono::Utc;
+use ethers_core::utils::keccak256;
+use kzg_ceremony_crypto::BatchTranscript;
+use serde::Serialize;
+
+// Statement by the sequencer about the transcript it currently serves, so
+// clients can tell they are talking to the sequencer they expect.
+#[derive(Serialize)]
+pub struct Attestation {
+    /// Id of the ceremony, `None` for the default one.
+    pub ceremony:          Option<String>,
+    /// Number of G1 and G2 powers of each sub-ceremony.
+    pub ceremony_sizes:    Vec<(usize, usize)>,
+    pub num_contributions: usize,
+    /// Keccak256 hash of the JSON encoded transcript.
+    pub transcript_hash:   String,
+    /// Unix timestamp (in seconds) at which the attestation was made.
+    pub timestamp:         i64,
+}
+
+impl Attestation {
+    pub fn new(
+        ceremony: Option<String>,
+        ceremony_sizes: &CeremonySizes,
+        transcript: &BatchTranscript,
+    ) -> Result<Self, SignatureError> {
+        let transcript_json =
+            serde_json::to_vec(transcript).map_err(|_| SignatureError::SignatureCreation)?;
+        Ok(Self {
+            ceremony,
+            ceremony_sizes: ceremony_sizes.sizes().to_vec(),
+            num_contributions: transcript.num_participants(),
+            transcript_hash: format!("0x{}", hex::encode(keccak256(transcript_json))),
+            timestamp: Utc::now().timestamp(),
+        })
+    }
+
+    pub async fn sign(&self, keys: &Keys) -> Result<(String, Signature), SignatureError> {
+        sign_statement(self, keys).await
+    }
+}
+
+/// Signs the JSON encoding of `statement`, and returns it with the signature.
+pub async fn sign_statement<T: Serialize + Sync>(
+    statement: &T,
+    keys: &Keys,
+) -> Result<(String, Signature), SignatureError> {
+    let message =
+        serde_json::to_string(statement).map_err(|_| SignatureError::SignatureCreation)?;
+    keys.sign(&message).await.map(|sig| (message, sig))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_transcript;
+
+    #[tokio::test]
+    async fn signs_attestation() {
+        let keys = Keys::new(&crate::keys::Options {
+            signing_key:         None,
+            signing_key_file:    None,
+            signing_kms_key_id:  None,
+            signing_kms_region:  None,
+            signing_key_overlap: std::time::Duration::from_secs(3600),
+        })
+        .unwrap();
+        let transcript = test_transcript();
+        let sizes = CeremonySizes::parse_from_cmd("4,2:8,2").unwrap();
+        let attestation = Attestation::new(None, &sizes, &transcript).unwrap();
+        assert_eq!(attestation.transcript_hash.len(), 66);
+        assert_eq!(
+            attestation.transcript_hash,
+            Attestation::new(None, &sizes, &transcript)
+                .unwrap()
+                .transcript_hash
+        );
+
+        let (message, signature) = attestation.sign(&keys).await.unwrap();
+        assert!(message.contains("\"ceremony_sizes\":[[4,2],[8,2]]"));
+        keys.verify(&message, &signature).unwrap();
+    }
+}
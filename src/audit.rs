@@ -0,0 +1,274 @@
+//! Append-only audit log of all state-changing requests.
+//!
+//! Every record is written as one JSON line to `--audit-log-path` and, with
+//! `--audit-log-database`, to the `audit_log` table. Failing to record is
+//! logged but never fails the request. Records are also kept in the event
+//! tail, for `/admin/events/tail`.
+
+use crate::{
+    client_ip::client_ip, event_tail::SharedEventTail, storage::PersistentStorage,
+    Options as AppOptions,
+};
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use eyre::{Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::ErrorCode;
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, net::IpAddr, path::PathBuf, sync::Arc};
+use strum::IntoStaticStr;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+use tracing::error;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// JSONL file every state-changing request is appended to. The audit log
+    /// is disabled when neither this nor `--audit-log-database` is set.
+    #[clap(long, env)]
+    pub audit_log_path: Option<PathBuf>,
+
+    /// Also record the audit log in the `audit_log` database table.
+    #[clap(long, env, default_value = "false")]
+    pub audit_log_database: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, IntoStaticStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditAction {
+    Auth,
+    LobbyJoin,
+    ContributionStarted,
+    ContributionSubmitted,
+    ContributionAborted,
+    DeadlineExtended,
+    ContributionExpired,
+    AdminPause,
+    AdminResume,
+    AdminKick,
+    AdminBan,
+    AdminUnban,
+    AdminPriority,
+    AdminBeacon,
+    AdminFinalize,
+    AdminPromote,
+    AdminKeyCreated,
+    AdminKeyUpdated,
+    AdminKeyDeleted,
+    AdminSigningKeyRotated,
+    AdminFeatureFlag,
+    AccountDeleted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub action:    AuditAction,
+    /// The participant the action concerns, if known.
+    pub uid:       Option<String>,
+    /// Address of the client that sent the request. Missing for actions the
+    /// sequencer takes on its own, like expiring a contribution.
+    pub ip:        Option<IpAddr>,
+    /// `ok`, or the error code the request failed with.
+    pub outcome:   String,
+}
+
+/// Outcome of a request as stored in the audit log.
+#[must_use]
+pub fn outcome<T, E: ErrorCode>(result: &Result<T, E>) -> String {
+    match result {
+        Ok(_) => "ok".to_string(),
+        Err(error) => error.to_error_code(),
+    }
+}
+
+/// Handle to the audit log. The default handle records nothing.
+#[derive(Clone, Default)]
+pub struct AuditLog(Option<Arc<AuditSinks>>);
+
+struct AuditSinks {
+    file:    Option<Mutex<File>>,
+    storage: Option<PersistentStorage>,
+    tail:    Option<SharedEventTail>,
+}
+
+impl AuditLog {
+    /// Opens the audit log file for appending. Records are kept in `tail`
+    /// even if the audit log is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the audit log file can not be opened.
+    pub async fn new(
+        options: &Options,
+        storage: &PersistentStorage,
+        tail: Option<SharedEventTail>,
+    ) -> EyreResult<Self> {
+        let file = match &options.audit_log_path {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .wrap_err_with(|| format!("failed to open audit log {}", path.display()))?,
+            )),
+            None => None,
+        };
+        let storage = options.audit_log_database.then(|| storage.clone());
+        if file.is_none() && storage.is_none() && tail.is_none() {
+            return Ok(Self::default());
+        }
+        Ok(Self(Some(Arc::new(AuditSinks {
+            file,
+            storage,
+            tail,
+        }))))
+    }
+
+    pub async fn record(
+        &self,
+        action: AuditAction,
+        uid: Option<String>,
+        ip: Option<IpAddr>,
+        outcome: String,
+    ) {
+        let sinks = match &self.0 {
+            Some(sinks) => sinks,
+            None => return,
+        };
+        let record = AuditRecord {
+            timestamp: Utc::now(),
+            action,
+            uid,
+            ip,
+            outcome,
+        };
+        if let Some(tail) = &sinks.tail {
+            tail.push_audit(&record);
+        }
+        if let Some(file) = &sinks.file {
+            let mut line = serde_json::to_vec(&record).unwrap_or_default();
+            line.push(b'\n');
+            let mut file = file.lock().await;
+            let written = match file.write_all(&line).await {
+                Ok(()) => file.flush().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                error!(?record, "failed to write audit log: {}", e);
+            }
+        }
+        if let Some(storage) = &sinks.storage {
+            if let Err(e) = storage.insert_audit_record(&record).await {
+                error!(?record, "failed to store audit record: {}", e);
+            }
+        }
+    }
+}
+
+/// Extractor for the audit log together with the address of the client.
+#[derive(Clone, Default)]
+pub struct Audit {
+    pub log: AuditLog,
+    pub ip:  Option<IpAddr>,
+}
+
+impl Audit {
+    pub async fn record(&self, action: AuditAction, uid: Option<String>, outcome: String) {
+        self.log.record(action, uid, self.ip, outcome).await;
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for Audit
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let options = req
+            .extensions()
+            .get::<AppOptions>()
+            .map(|options| options.rate_limit.clone());
+        let ip = match &options {
+            Some(options) => client_ip(
+                req.headers(),
+                req.extensions(),
+                options.rate_limit_ip_header.as_deref(),
+                &options.client_ip,
+            ),
+            None => client_ip(req.headers(), req.extensions(), None, &Default::default()),
+        };
+        Ok(Self {
+            log: req
+                .extensions()
+                .get::<AuditLog>()
+                .cloned()
+                .unwrap_or_default(),
+            ip,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::storage_client, test_util::test_options};
+    use std::net::Ipv4Addr;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn appends_records() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let options = test_options();
+        let storage = storage_client(&options.storage).await.unwrap();
+        let log = AuditLog::new(
+            &Options {
+                audit_log_path:     Some(path.clone()),
+                audit_log_database: true,
+            },
+            &storage,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let ip = IpAddr::from(Ipv4Addr::LOCALHOST);
+        log.record(
+            AuditAction::Auth,
+            Some("git|1234|test_user".to_string()),
+            Some(ip),
+            "ok".to_string(),
+        )
+        .await;
+        log.record(
+            AuditAction::ContributionExpired,
+            Some("git|1234|test_user".to_string()),
+            None,
+            "timeout".to_string(),
+        )
+        .await;
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records = contents
+            .lines()
+            .map(|line| serde_json::from_str::<AuditRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].action, AuditAction::Auth);
+        assert_eq!(records[0].ip, Some(ip));
+        assert_eq!(records[1].outcome, "timeout");
+        assert_eq!(storage.count_audit_records().await.unwrap(), 2);
+    }
+}
@@ -0,0 +1,146 @@
+//! The sign-in funnel of each auth provider.
+//!
+//! Each sign-in passes the stages `started`, when `/auth/request_link` hands
+//! out the link, and `callback`, when the provider calls back. It then ends
+//! `rejected` by a rule, `failed`, e.g. because the code could not be
+//! exchanged, or as a `session`, which may end up `contributed`. A provider
+//! whose callbacks stop turning into sessions, without rejections to explain
+//! it, likely has a broken integration.
+//!
+//! The stages are counted by `sequencer_auth_funnel` and in the
+//! `auth_funnel` table, which `/info/statistics` reads, so the funnel
+//! survives restarts and adds up across sequencers sharing a database.
+
+use crate::{metrics::AUTH_FUNNEL, storage::PersistentStorage};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tracing::warn;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    Started,
+    Callback,
+    /// Turned away by the rule.
+    Rejected(&'static str),
+    Failed,
+    Session,
+    Contributed,
+}
+
+impl Stage {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Started => "started",
+            Self::Callback => "callback",
+            Self::Rejected(_) => "rejected",
+            Self::Failed => "failed",
+            Self::Session => "session",
+            Self::Contributed => "contributed",
+        }
+    }
+
+    const fn rule(self) -> &'static str {
+        match self {
+            Self::Rejected(rule) => rule,
+            _ => "",
+        }
+    }
+}
+
+/// Counts a sign-in with `provider` reaching `stage`. Failures to store it
+/// are only logged.
+pub async fn record(storage: &PersistentStorage, provider: &str, stage: Stage) {
+    AUTH_FUNNEL
+        .with_label_values(&[provider, stage.name(), stage.rule()])
+        .inc();
+    if let Err(error) = storage
+        .count_auth_funnel(provider, stage.name(), stage.rule())
+        .await
+    {
+        warn!(?error, "failed to record auth funnel");
+    }
+}
+
+/// Name of the auth provider that signs in `identity`, as in
+/// `/auth/callback/<provider>`.
+#[must_use]
+pub const fn provider_of(identity: &Identity) -> &'static str {
+    match identity {
+        Identity::Github { .. } => "github",
+        Identity::Ethereum { .. } => "eth",
+        Identity::Discord { .. } => "discord",
+        Identity::Oidc { .. } => "oidc",
+        Identity::None => "none",
+    }
+}
+
+/// The funnel of one provider, as shown by `/info/statistics`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ProviderFunnel {
+    pub started:       u64,
+    pub callbacks:     u64,
+    /// Rejections by rule.
+    pub rejected:      BTreeMap<String, u64>,
+    pub failed:        u64,
+    pub sessions:      u64,
+    pub contributions: u64,
+}
+
+/// Funnels by provider from the rows of the `auth_funnel` table, as
+/// `(provider, stage, rule, count)`.
+#[must_use]
+pub fn funnels(
+    rows: impl IntoIterator<Item = (String, String, String, u64)>,
+) -> BTreeMap<String, ProviderFunnel> {
+    let mut funnels = BTreeMap::<String, ProviderFunnel>::new();
+    for (provider, stage, rule, count) in rows {
+        let funnel = funnels.entry(provider).or_default();
+        match stage.as_str() {
+            "started" => funnel.started += count,
+            "callback" => funnel.callbacks += count,
+            "rejected" => *funnel.rejected.entry(rule).or_default() += count,
+            "failed" => funnel.failed += count,
+            "session" => funnel.sessions += count,
+            "contributed" => funnel.contributions += count,
+            _ => {}
+        }
+    }
+    funnels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_stages_by_provider() {
+        let row = |provider: &str, stage: Stage, count| {
+            (
+                provider.to_string(),
+                stage.name().to_string(),
+                stage.rule().to_string(),
+                count,
+            )
+        };
+        let funnels = funnels([
+            row("github", Stage::Started, 10),
+            row("github", Stage::Callback, 8),
+            row("github", Stage::Rejected("created_before"), 2),
+            row("github", Stage::Failed, 1),
+            row("github", Stage::Session, 5),
+            row("github", Stage::Contributed, 3),
+            row("eth", Stage::Rejected("min_nonce"), 4),
+        ]);
+        assert_eq!(funnels["github"], ProviderFunnel {
+            started:       10,
+            callbacks:     8,
+            rejected:      BTreeMap::from([("created_before".to_string(), 2)]),
+            failed:        1,
+            sessions:      5,
+            contributions: 3,
+        });
+        assert_eq!(funnels["eth"].rejected["min_nonce"], 4);
+        assert_eq!(funnels["eth"].started, 0);
+    }
+}
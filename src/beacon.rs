@@ -0,0 +1,194 @@
+//! Public randomness mixed into the transcript once the participants are done.
+//!
+//! `POST /admin/beacon` takes a drand round or an Ethereum block that was
+//! produced after the lobby closed, and adds a last contribution whose entropy
+//! is the SHA-256 of the beacon's provenance, e.g.
+//! `beacon:ethereum:16000000:0x<block hash>`. The provenance is recorded as
+//! the entropy attestation of the entry, without a participant, so anyone can
+//! derive the same entropy and check the `pot_pubkey`s of the entry. The entry
+//! is verified like any other contribution when it is added and when the
+//! transcript is finalized.
+
+use crate::Engine;
+use kzg_ceremony_crypto::{
+    derive_pot_pubkeys, signature::identity::Identity, BatchContribution, BatchTranscript,
+    CeremoniesError, Entropy, ErrorCode, Secret,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+/// Start of the entropy attestation of beacon entries.
+const PROVENANCE_PREFIX: &str = "beacon:";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Beacon {
+    /// A round of the drand chain with the given hash.
+    Drand {
+        chain_hash: String,
+        round:      u64,
+        randomness: String,
+    },
+    /// A block of Ethereum mainnet.
+    Ethereum {
+        block_number: u64,
+        block_hash:   String,
+    },
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum BeaconError {
+    #[error("{0} must be 32 hex encoded bytes")]
+    InvalidHex(&'static str),
+    #[error("the pot_pubkeys of entry {0} do not match its beacon")]
+    Mismatch(usize),
+}
+
+impl ErrorCode for BeaconError {
+    fn to_error_code(&self) -> String {
+        format!("BeaconError::{}", <&str>::from(self))
+    }
+}
+
+impl Beacon {
+    /// The canonical description of the beacon that its entropy is derived
+    /// from.
+    pub fn provenance(&self) -> Result<String, BeaconError> {
+        match self {
+            Self::Drand {
+                chain_hash,
+                round,
+                randomness,
+            } => Ok(format!(
+                "{PROVENANCE_PREFIX}drand:{}:{round}:{}",
+                hash(chain_hash, "chain_hash")?,
+                hash(randomness, "randomness")?
+            )),
+            Self::Ethereum {
+                block_number,
+                block_hash,
+            } => Ok(format!(
+                "{PROVENANCE_PREFIX}ethereum:{block_number}:0x{}",
+                hash(block_hash, "block_hash")?
+            )),
+        }
+    }
+}
+
+/// Lower case hex of a 32 byte value, with or without `0x`.
+fn hash(value: &str, field: &'static str) -> Result<String, BeaconError> {
+    let bytes =
+        hex::decode(value.trim_start_matches("0x")).map_err(|_| BeaconError::InvalidHex(field))?;
+    if bytes.len() != 32 {
+        return Err(BeaconError::InvalidHex(field));
+    }
+    Ok(hex::encode(bytes))
+}
+
+fn entropy(provenance: &str) -> Entropy {
+    Secret::new(Sha256::digest(provenance.as_bytes()).into())
+}
+
+/// The provenance of the entry at `index`, if it is a beacon entry.
+#[must_use]
+pub fn provenance(transcript: &BatchTranscript, index: usize) -> Option<&str> {
+    transcript
+        .entropy_attestation(index)
+        .filter(|attestation| attestation.starts_with(PROVENANCE_PREFIX))
+        .filter(|_| transcript.participant_ids[index] == Identity::None)
+}
+
+/// Whether a beacon was mixed into the transcript already.
+#[must_use]
+pub fn applied(transcript: &BatchTranscript) -> bool {
+    (0..transcript.participant_ids.len()).any(|index| provenance(transcript, index).is_some())
+}
+
+/// The contribution of the beacon with `provenance` to `transcript`.
+pub fn contribution(
+    transcript: &BatchTranscript,
+    provenance: &str,
+) -> Result<BatchContribution, CeremoniesError> {
+    let mut contribution = transcript.contribution();
+    contribution.add_entropy::<Engine>(&entropy(provenance), &Identity::None)?;
+    contribution.entropy_attestation = Some(provenance.to_string());
+    Ok(contribution)
+}
+
+/// Checks that the `pot_pubkey`s of the beacon entries of `transcript` were
+/// derived from their provenance.
+pub fn verify(transcript: &BatchTranscript) -> Result<(), BeaconError> {
+    for index in 0..transcript.participant_ids.len() {
+        let provenance = match provenance(transcript, index) {
+            Some(provenance) => provenance,
+            None => continue,
+        };
+        let expected =
+            derive_pot_pubkeys::<Engine>(&entropy(provenance), transcript.transcripts.len());
+        let matches = transcript
+            .transcripts
+            .iter()
+            .zip(expected)
+            .all(|(transcript, pubkey)| transcript.witness.pubkeys[index] == pubkey);
+        if !matches {
+            return Err(BeaconError::Mismatch(index));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+
+    #[test]
+    fn mixes_in_beacons() {
+        let block_hash = format!("0x{}", "AB".repeat(32));
+        let beacon = Beacon::Ethereum {
+            block_number: 16_000_000,
+            block_hash,
+        };
+        let expected = beacon.provenance().unwrap();
+        assert_eq!(
+            expected,
+            format!("beacon:ethereum:16000000:0x{}", "ab".repeat(32))
+        );
+        assert!(matches!(
+            Beacon::Drand {
+                chain_hash: "00".repeat(32),
+                round:      1,
+                randomness: "00".repeat(31),
+            }
+            .provenance(),
+            Err(BeaconError::InvalidHex("randomness"))
+        ));
+
+        let mut transcript = test_transcript();
+        let first = valid_contribution(&transcript, 1);
+        transcript
+            .verify_add::<Engine>(first, Identity::None)
+            .unwrap();
+        assert!(!applied(&transcript));
+
+        let mut mixed = transcript.clone();
+        let beacon_contribution = contribution(&mixed, &expected).unwrap();
+        mixed
+            .verify_add::<Engine>(beacon_contribution, Identity::None)
+            .unwrap();
+        assert!(applied(&mixed));
+        assert_eq!(provenance(&mixed, 2), Some(expected.as_str()));
+        verify(&mixed).unwrap();
+
+        // A valid contribution whose entropy is not the beacon's.
+        let mut forged = transcript;
+        let mut contribution = valid_contribution(&forged, 2);
+        contribution.entropy_attestation = Some(expected);
+        forged
+            .verify_add::<Engine>(contribution, Identity::None)
+            .unwrap();
+        assert!(matches!(verify(&forged), Err(BeaconError::Mismatch(2))));
+    }
+}
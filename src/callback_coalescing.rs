@@ -0,0 +1,247 @@
+//! Coalescing of concurrent auth callbacks with the same code.
+//!
+//! Participants who double-click at their identity provider send two
+//! callbacks with the same authorization code at once. Handled one by one,
+//! both race to consume the sign-in nonce and to create a session, and the
+//! browser shows the response of the second one, which lost. Instead, the
+//! first callback with a code handles it and every callback with the code
+//! arriving while it does gets the same session, or the same error. Only
+//! callbacks in flight are coalesced: a code used again later is rejected by
+//! the provider as before.
+//!
+//! Storage errors are not shared, as they cannot be copied. The waiting
+//! callbacks then handle the code themselves, as they do when the first
+//! request is dropped.
+
+use crate::api::v1::auth::{AuthErrorPayload, UserVerifiedResponse};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::OnceCell;
+
+type Outcome = Result<UserVerifiedResponse, AuthErrorPayload>;
+
+#[derive(Default)]
+pub struct CallbackCoalescer {
+    /// Outcomes of the callbacks in flight, by the hash of provider and code.
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<Outcome>>>>,
+}
+
+pub type SharedCallbackCoalescer = Arc<CallbackCoalescer>;
+
+/// Hash of the callback, so that codes are not kept in memory.
+fn key(provider: &str, code: &str) -> String {
+    let hash = Sha256::new()
+        .chain_update(provider)
+        .chain_update([0])
+        .chain_update(code)
+        .finalize();
+    hex::encode(hash)
+}
+
+/// A copy of the outcome for another callback.
+fn copy(outcome: &Outcome) -> Option<Outcome> {
+    match outcome {
+        Ok(response) => Some(Ok(response.clone())),
+        Err(err) => err.duplicate().map(Err),
+    }
+}
+
+impl CallbackCoalescer {
+    /// Runs `callback` for the `code` of `provider`, unless a callback with
+    /// the code is in flight already, whose outcome is returned instead.
+    pub async fn run<F>(&self, provider: &str, code: &str, callback: F) -> Outcome
+    where
+        F: Future<Output = Outcome> + Send,
+    {
+        let key = key(provider, code);
+        let cell = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let outcome = cell
+            .get_or_try_init(|| async {
+                let outcome = callback.await;
+                match copy(&outcome) {
+                    Some(_) => Ok(outcome),
+                    None => Err(outcome),
+                }
+            })
+            .await
+            .map_or_else(Some, copy)
+            .expect("only outcomes that can be copied are kept");
+        let mut in_flight = self.in_flight.lock().unwrap();
+        // The entry is still needed by callbacks waiting for a failed one.
+        let done = cell.initialized() || Arc::strong_count(&cell) <= 2;
+        if done
+            && in_flight
+                .get(&key)
+                .map_or(false, |kept| Arc::ptr_eq(kept, &cell))
+        {
+            in_flight.remove(&key);
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::v1::auth::post_authenticate,
+        eligibility::Eligibility,
+        lobby::SharedLobbyState,
+        oauth::SharedAuthState,
+        storage::{storage_client, StorageError},
+        test_util::test_options,
+        SessionId,
+    };
+    use kzg_ceremony_crypto::signature::identity::Identity;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::oneshot;
+
+    fn user() -> Identity {
+        Identity::Github {
+            id:       1234,
+            username: "test_user".to_string(),
+        }
+    }
+
+    fn eligibility() -> Eligibility {
+        Eligibility {
+            score:        1,
+            eligible:     true,
+            failed_rules: Vec::new(),
+        }
+    }
+
+    /// Lets the tasks of the single threaded test runtime run up to their
+    /// next wait.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_callbacks_share_one_session() {
+        let options = test_options();
+        let storage = storage_client(&options.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(options.lobby.clone());
+        let auth_state = SharedAuthState::default();
+        let coalescer = SharedCallbackCoalescer::default();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let (release, released) = oneshot::channel::<()>();
+        let mut released = Some(released);
+
+        let mut callback = || {
+            let (options, storage, lobby_state, auth_state, coalescer, runs) = (
+                options.clone(),
+                storage.clone(),
+                lobby_state.clone(),
+                auth_state.clone(),
+                coalescer.clone(),
+                runs.clone(),
+            );
+            let released = released.take();
+            tokio::spawn(async move {
+                coalescer
+                    .run("github", "code", async {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        if let Some(released) = released {
+                            released.await.unwrap();
+                        }
+                        post_authenticate(
+                            auth_state,
+                            lobby_state,
+                            storage,
+                            user(),
+                            eligibility(),
+                            None,
+                            &options,
+                            Some(SessionId::new()),
+                        )
+                        .await
+                    })
+                    .await
+            })
+        };
+        let first = callback();
+        settle().await;
+        let second = callback();
+        settle().await;
+        release.send(()).unwrap();
+        let first = first.await.unwrap().unwrap();
+        let second = second.await.unwrap().unwrap();
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(first.session_id, second.session_id);
+        assert_eq!(storage.load_sessions().await.unwrap().len(), 1);
+        assert!(coalescer.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn storage_errors_are_not_shared() {
+        let coalescer = SharedCallbackCoalescer::default();
+        let (release, released) = oneshot::channel::<()>();
+        let first = tokio::spawn({
+            let coalescer = coalescer.clone();
+            async move {
+                coalescer
+                    .run("github", "code", async {
+                        released.await.unwrap();
+                        Err(AuthErrorPayload::Storage(StorageError::Degraded))
+                    })
+                    .await
+            }
+        });
+        settle().await;
+        let second = tokio::spawn({
+            let coalescer = coalescer.clone();
+            async move {
+                coalescer
+                    .run("github", "code", async {
+                        Err(AuthErrorPayload::InvalidNonce)
+                    })
+                    .await
+            }
+        });
+        settle().await;
+        release.send(()).unwrap();
+
+        assert!(matches!(
+            first.await.unwrap(),
+            Err(AuthErrorPayload::Storage(StorageError::Degraded))
+        ));
+        assert!(matches!(
+            second.await.unwrap(),
+            Err(AuthErrorPayload::InvalidNonce)
+        ));
+        assert!(coalescer.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn later_callbacks_and_other_codes_run_again() {
+        let coalescer = CallbackCoalescer::default();
+        let first = coalescer
+            .run("github", "code", async {
+                Err(AuthErrorPayload::InvalidAuthCode)
+            })
+            .await;
+        assert!(matches!(first, Err(AuthErrorPayload::InvalidAuthCode)));
+        let again = coalescer
+            .run("github", "code", async {
+                Err(AuthErrorPayload::UserBanned)
+            })
+            .await;
+        assert!(matches!(again, Err(AuthErrorPayload::UserBanned)));
+        assert_ne!(key("github", "code"), key("eth", "code"));
+        assert!(coalescer.in_flight.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! Hosting additional, independent ceremonies next to the default one.
+//!
+//! Every ceremony listed in `--ceremonies-file` is served under
+//! `/ceremony/<id>/` with the full API. It has its own transcript, lobby,
+//! sessions, checkpoints and database, so its contributors are tracked
+//! separately from those of the default ceremony. Identity providers, keys
+//! and rate limits are shared.
+
+use crate::{
+    io::{read_json_file, CeremonySizes},
+    Options as AppOptions,
+};
+use clap::Parser;
+use eyre::{eyre, Result as EyreResult, WrapErr};
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// JSON file listing additional ceremonies to host, each with an `id`,
+    /// `ceremony_sizes` and `database_url`, and optionally a
+    /// `read_database_url` and a `transcript_file`.
+    #[clap(long, env)]
+    pub ceremonies_file: Option<PathBuf>,
+}
+
+/// Id of the ceremony a router serves. `None` for the default ceremony.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CeremonyId(pub Option<String>);
+
+impl CeremonyId {
+    /// Path below the server url the ceremony's routes are nested at.
+    #[must_use]
+    pub fn path_prefix(&self) -> String {
+        self.0
+            .as_ref()
+            .map_or_else(String::new, |id| format!("/ceremony/{id}"))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CeremonyConfig {
+    /// Used in the routes, and to derive file names. Must consist of ASCII
+    /// letters, digits, `-` and `_`.
+    pub id:                String,
+    /// Same format as `--ceremony-sizes`.
+    pub ceremony_sizes:    String,
+    /// Must not be shared with any other ceremony.
+    pub database_url:      String,
+    /// Read replica of `database_url`. Not taken over from the default
+    /// ceremony.
+    pub read_database_url: Option<String>,
+    /// Defaults to `<id>.transcript.json` next to `--transcript-file`.
+    pub transcript_file:   Option<PathBuf>,
+}
+
+impl CeremonyConfig {
+    /// Derives the options of this ceremony from those of the default
+    /// ceremony.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ceremony sizes can not be parsed.
+    pub fn apply(&self, options: &AppOptions) -> EyreResult<AppOptions> {
+        let mut options = options.clone();
+        options.ceremony_sizes = CeremonySizes::parse_from_cmd(&self.ceremony_sizes)
+            .wrap_err_with(|| format!("invalid sizes for ceremony {}", self.id))?;
+        options.transcript_file = self.transcript_file.clone().unwrap_or_else(|| {
+            options
+                .transcript_file
+                .with_file_name(format!("{}.transcript.json", self.id))
+        });
+        options.transcript_in_progress_file = with_suffix(&options.transcript_file, ".next");
+        options.storage.database_url = self.database_url.clone();
+        options.storage.read_database_url = self.read_database_url.clone();
+        options.checkpoint.checkpoint_dir = options.checkpoint.checkpoint_dir.join(&self.id);
+        options.db_backup.db_backup_dir = options.db_backup.db_backup_dir.join(&self.id);
+        options.quarantine.capture_rejections = options
+            .quarantine
+            .capture_rejections
+            .map(|dir| dir.join(&self.id));
+        options.admin.final_setup_dir = options.admin.final_setup_dir.join(&self.id);
+        options.audit.audit_log_path = options
+            .audit
+            .audit_log_path
+            .map(|path| with_suffix(&path, &format!(".{}", self.id)));
+        options.ceremonies.ceremonies_file = None;
+        Ok(options)
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+/// Reads and validates the additional ceremonies.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read, or if ids or database urls
+/// are invalid or not unique.
+pub async fn load_ceremonies(options: &AppOptions) -> EyreResult<Vec<CeremonyConfig>> {
+    let ceremonies = match &options.ceremonies.ceremonies_file {
+        Some(path) => read_json_file::<Vec<CeremonyConfig>>(path.clone())
+            .await
+            .wrap_err_with(|| format!("failed to read ceremonies from {}", path.display()))?,
+        None => return Ok(Vec::new()),
+    };
+    validate_ceremonies(options, &ceremonies)?;
+    Ok(ceremonies)
+}
+
+fn validate_ceremonies(options: &AppOptions, ceremonies: &[CeremonyConfig]) -> EyreResult<()> {
+    let mut ids = HashSet::new();
+    let mut database_urls = HashSet::from([options.storage.database_url.as_str()]);
+    for ceremony in ceremonies {
+        let valid_id = !ceremony.id.is_empty()
+            && ceremony
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_id {
+            return Err(eyre!("invalid ceremony id {:?}", ceremony.id));
+        }
+        if !ids.insert(ceremony.id.as_str()) {
+            return Err(eyre!("duplicate ceremony id {}", ceremony.id));
+        }
+        if !database_urls.insert(ceremony.database_url.as_str()) {
+            return Err(eyre!(
+                "ceremony {} shares its database with another ceremony",
+                ceremony.id
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+
+    fn config(id: &str, database_url: &str) -> CeremonyConfig {
+        CeremonyConfig {
+            id:                id.to_string(),
+            ceremony_sizes:    "4,2".to_string(),
+            database_url:      database_url.to_string(),
+            read_database_url: None,
+            transcript_file:   None,
+        }
+    }
+
+    #[test]
+    fn derives_ceremony_options() {
+        let mut options = test_options();
+        options.audit.audit_log_path = Some(PathBuf::from("./audit.jsonl"));
+        options.quarantine.capture_rejections = Some(PathBuf::from("./rejected"));
+        let derived = config("small", "sqlite://small.db")
+            .apply(&options)
+            .unwrap();
+        assert_eq!(
+            derived.transcript_file,
+            Path::new("./small.transcript.json")
+        );
+        assert_eq!(
+            derived.transcript_in_progress_file,
+            Path::new("./small.transcript.json.next")
+        );
+        assert_eq!(derived.storage.database_url, "sqlite://small.db");
+        assert_eq!(
+            derived.checkpoint.checkpoint_dir,
+            Path::new("./checkpoints/small")
+        );
+        assert_eq!(
+            derived.audit.audit_log_path.as_deref(),
+            Some(Path::new("./audit.jsonl.small"))
+        );
+        assert_eq!(
+            derived.quarantine.capture_rejections.as_deref(),
+            Some(Path::new("./rejected/small"))
+        );
+        assert_eq!(
+            derived.ceremony_sizes,
+            CeremonySizes::parse_from_cmd("4,2").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_ceremonies() {
+        let options = test_options();
+        let valid = config("small", "sqlite://small.db");
+        assert!(validate_ceremonies(&options, &[valid.clone()]).is_ok());
+        assert!(validate_ceremonies(&options, &[config("a/b", "sqlite://other.db")]).is_err());
+        assert!(validate_ceremonies(&options, &[
+            valid.clone(),
+            config("small", "sqlite://other.db")
+        ])
+        .is_err());
+        assert!(
+            validate_ceremonies(&options, &[valid, config("other", "sqlite://small.db")]).is_err()
+        );
+        assert!(validate_ceremonies(&options, &[config("other", "sqlite://:memory:")]).is_err());
+    }
+}
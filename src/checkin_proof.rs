@@ -0,0 +1,201 @@
+//! Proof that a client in the lobby is still computing something, to keep
+//! scripted keep-alive bots from holding places in the lobby.
+//!
+//! With `--checkin-proof`, every `/lobby/try_contribute` response to a
+//! signed in session carries a fresh challenge `<difficulty>.<random>` in the
+//! `X-Checkin-Challenge` header. The next ping of the session has to answer
+//! it with a nonce in the `X-Checkin-Proof` header, for which the SHA-256 of
+//! `<challenge>:<nonce>` starts with `<difficulty>` zero bits. Each challenge
+//! is answered once: a ping that passes, even one rate limited afterwards,
+//! gets the next one. The first ping after signing in, or after a restart of
+//! the sequencer, needs no proof.
+
+use crate::{lobby::SharedLobbyState, proof_of_work::leading_zeros, SessionId};
+use axum::{
+    body::Body,
+    extract::{FromRequest, RequestParts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use clap::Parser;
+use http::{HeaderValue, Request};
+use kzg_ceremony_crypto::ErrorCode;
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+pub const CHECKIN_CHALLENGE_HEADER: &str = "x-checkin-challenge";
+pub const CHECKIN_PROOF_HEADER: &str = "x-checkin-proof";
+
+/// Longest nonce that is checked.
+const MAX_NONCE_LENGTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Require lobby pings to answer the challenge of the previous ping.
+    #[clap(long, env)]
+    pub checkin_proof: bool,
+
+    /// Leading zero bits the hash of a check-in proof needs. Each bit doubles
+    /// the expected work.
+    #[clap(long, env, default_value = "12")]
+    pub checkin_proof_difficulty: u32,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum CheckinProofError {
+    #[error("the ping must answer the challenge sent in X-Checkin-Challenge")]
+    Missing { challenge: String },
+    #[error("invalid check-in proof, answer the challenge sent in X-Checkin-Challenge")]
+    Invalid { challenge: String },
+}
+
+impl ErrorCode for CheckinProofError {
+    fn to_error_code(&self) -> String {
+        format!("CheckinProofError::{}", <&str>::from(self))
+    }
+}
+
+impl CheckinProofError {
+    /// The challenge that is still to be answered.
+    #[must_use]
+    pub fn challenge(&self) -> &str {
+        match self {
+            Self::Missing { challenge } | Self::Invalid { challenge } => challenge,
+        }
+    }
+}
+
+fn challenge(difficulty: u32) -> String {
+    let mut random = [0; 16];
+    thread_rng().fill_bytes(&mut random);
+    format!("{difficulty}.{}", hex::encode(random))
+}
+
+/// Whether `nonce` answers `challenge`.
+fn verify(challenge: &str, nonce: &str) -> bool {
+    let difficulty = challenge
+        .split_once('.')
+        .and_then(|(difficulty, _)| difficulty.parse::<u32>().ok());
+    match difficulty {
+        Some(difficulty) if nonce.len() <= MAX_NONCE_LENGTH => {
+            let hash = Sha256::new()
+                .chain_update(challenge)
+                .chain_update(":")
+                .chain_update(nonce)
+                .finalize();
+            leading_zeros(&hash) >= difficulty
+        }
+        _ => false,
+    }
+}
+
+/// Checks `proof` against the `pending` challenge of a session, if it has
+/// one, and replaces it with a new challenge that is returned.
+fn check_and_rotate(
+    options: &Options,
+    pending: &mut Option<String>,
+    proof: Option<&str>,
+) -> Result<String, CheckinProofError> {
+    if let Some(challenge) = pending.as_deref() {
+        match proof {
+            None => {
+                return Err(CheckinProofError::Missing {
+                    challenge: challenge.to_string(),
+                })
+            }
+            Some(nonce) if !verify(challenge, nonce) => {
+                return Err(CheckinProofError::Invalid {
+                    challenge: challenge.to_string(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    let next = challenge(options.checkin_proof_difficulty);
+    *pending = Some(next.clone());
+    Ok(next)
+}
+
+/// Middleware of `/lobby/try_contribute` checking the proof of the ping and
+/// sending the next challenge. Requests of sessions not signed in to this
+/// sequencer, and of the active contributor, pass unchecked.
+pub async fn check(options: Options, request: Request<Body>, next: Next<Body>) -> Response {
+    let lobby_state = request.extensions().get::<SharedLobbyState>().cloned();
+    let mut parts = RequestParts::new(request);
+    let session_id = SessionId::from_request(&mut parts).await.ok();
+    let request = match parts.try_into_request() {
+        Ok(request) => request,
+        Err(err) => return err.into_response(),
+    };
+    let (lobby_state, session_id) = match (lobby_state, session_id) {
+        (Some(lobby_state), Some(session_id)) => (lobby_state, session_id),
+        _ => return next.run(request).await,
+    };
+    let proof = request
+        .headers()
+        .get(CHECKIN_PROOF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToString::to_string);
+    let checked = lobby_state
+        .modify_participant(&session_id, |info| {
+            check_and_rotate(&options, &mut info.checkin_challenge, proof.as_deref())
+        })
+        .await;
+    let challenge = match checked {
+        None => return next.run(request).await,
+        Some(Ok(challenge)) => challenge,
+        Some(Err(err)) => return err.into_response(),
+    };
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&challenge) {
+        response
+            .headers_mut()
+            .insert(CHECKIN_CHALLENGE_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(challenge: &str) -> String {
+        (0_u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| verify(challenge, nonce))
+            .unwrap()
+    }
+
+    #[test]
+    fn rotates_challenges() {
+        let options = Options {
+            checkin_proof:            true,
+            checkin_proof_difficulty: 4,
+        };
+        let mut pending = None;
+        let first = check_and_rotate(&options, &mut pending, None).unwrap();
+        assert_eq!(pending.as_deref(), Some(first.as_str()));
+
+        assert!(matches!(
+            check_and_rotate(&options, &mut pending, None),
+            Err(CheckinProofError::Missing { challenge }) if challenge == first
+        ));
+        let unsolved = (0_u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| !verify(&first, nonce))
+            .unwrap();
+        assert!(matches!(
+            check_and_rotate(&options, &mut pending, Some(&unsolved)),
+            Err(CheckinProofError::Invalid { challenge }) if challenge == first
+        ));
+
+        let proof = solve(&first);
+        let second = check_and_rotate(&options, &mut pending, Some(&proof)).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(pending, Some(second));
+        assert!(!verify("garbage", "0"));
+    }
+}
@@ -0,0 +1,698 @@
+//! Periodic transcript checkpoints with optional upload to an object store.
+//!
+//! The checkpoint itself is written right away, as its contents only exist
+//! while the transcript is locked. With a job queue, see [`crate::scheduler`],
+//! the upload and the removal of the checkpoint that left the retention
+//! window are `backup` and `prune_checkpoint` jobs, so that they are retried
+//! and survive a restart.
+
+use crate::{
+    io::{read_json_file, CeremonySizes},
+    scheduler::JobKind,
+    storage::PersistentStorage,
+    util::Secret,
+    SharedTranscript,
+};
+use chrono::Utc;
+use clap::Parser;
+use eyre::{bail, ensure, eyre, Result as EyreResult};
+use hmac::{Hmac, Mac};
+use kzg_ceremony_crypto::BatchTranscript;
+use sha2::{Digest, Sha256};
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::fs;
+use tracing::{error, info, warn};
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Write a checkpoint of the transcript after every N verified
+    /// contributions. 0 disables checkpoints.
+    #[clap(long, env, default_value = "0")]
+    pub checkpoint_interval: usize,
+
+    /// Directory the checkpoints are written to.
+    #[clap(long, env, default_value = "./checkpoints")]
+    pub checkpoint_dir: PathBuf,
+
+    /// Number of checkpoints to keep, both locally and in the object store.
+    #[clap(long, env, default_value = "10")]
+    pub checkpoint_retention: usize,
+
+    /// Object store to upload checkpoints to, either `s3://bucket/prefix` or
+    /// `gs://bucket/prefix`.
+    #[clap(long, env)]
+    pub backup_url: Option<Url>,
+
+    /// Region of the S3 bucket.
+    #[clap(long, env, default_value = "us-east-1")]
+    pub backup_s3_region: String,
+
+    /// Endpoint of an S3 compatible store. Defaults to AWS.
+    #[clap(long, env)]
+    pub backup_s3_endpoint: Option<Url>,
+
+    #[clap(long, env)]
+    pub aws_access_key_id: Option<Secret>,
+
+    #[clap(long, env)]
+    pub aws_secret_access_key: Option<Secret>,
+
+    /// OAuth2 access token for Google Cloud Storage.
+    #[clap(long, env)]
+    pub backup_gcs_token: Option<Secret>,
+
+    /// On startup, restore the latest valid checkpoint if the transcript file
+    /// is torn or behind the contributors table.
+    #[clap(long, env, default_value = "true")]
+    pub recover_from_checkpoint: bool,
+}
+
+pub type SharedCheckpointer = Arc<Checkpointer>;
+
+pub struct Checkpointer {
+    interval:  usize,
+    retention: usize,
+    dir:       PathBuf,
+    store:     Option<ObjectStore>,
+    client:    reqwest::Client,
+    /// Where uploads and removals are scheduled. Without it they run right
+    /// after the checkpoint is written.
+    jobs:      Option<PersistentStorage>,
+}
+
+impl Checkpointer {
+    /// Builds the checkpointer from the command line options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `--backup-url` is invalid or its credentials are
+    /// missing.
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        let store = options
+            .backup_url
+            .as_ref()
+            .map(|url| ObjectStore::new(url, options))
+            .transpose()?;
+        Ok(Self {
+            interval: options.checkpoint_interval,
+            retention: options.checkpoint_retention,
+            dir: options.checkpoint_dir.clone(),
+            store,
+            client: reqwest::Client::new(),
+            jobs: None,
+        })
+    }
+
+    #[must_use]
+    pub fn with_jobs(mut self, storage: PersistentStorage) -> Self {
+        self.jobs = Some(storage);
+        self
+    }
+
+    /// Writes a checkpoint in the background if `num_participants` is a
+    /// multiple of the interval.
+    ///
+    /// Must be called while the transcript can not change, so that the
+    /// checkpoint contains exactly `num_participants` contributions.
+    pub async fn checkpoint(
+        self: &Arc<Self>,
+        num_participants: usize,
+        transcript: &SharedTranscript,
+    ) {
+        if self.interval == 0 || num_participants % self.interval != 0 {
+            return;
+        }
+        let contents = match serde_json::to_vec(&*transcript.read().await) {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!("failed to serialize checkpoint: {}", e);
+                return;
+            }
+        };
+        let checkpointer = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = checkpointer.save(num_participants, contents).await {
+                error!(num_participants, "failed to save checkpoint: {}", e);
+            }
+        });
+    }
+
+    async fn save(&self, num_participants: usize, contents: Vec<u8>) -> EyreResult<()> {
+        let name = checkpoint_name(num_participants);
+        fs::create_dir_all(&self.dir).await?;
+        let path = self.dir.join(&name);
+        let work_path = path.with_extension("json.next");
+        fs::write(&work_path, &contents).await?;
+        fs::rename(&work_path, &path).await?;
+        info!(path = %path.display(), "Wrote checkpoint");
+
+        // Checkpoints are taken at fixed positions, so the one that falls out of
+        // the retention window is known without listing the store.
+        let expired = self
+            .retention
+            .checked_mul(self.interval)
+            .and_then(|window| num_participants.checked_sub(window))
+            .filter(|&expired| expired > 0);
+
+        if let Some(jobs) = &self.jobs {
+            let now = Utc::now();
+            if self.store.is_some() {
+                let key = num_participants.to_string();
+                jobs.schedule_job(JobKind::Backup.into(), &key, "", now)
+                    .await?;
+            }
+            if let Some(expired) = expired {
+                let key = expired.to_string();
+                jobs.schedule_job(JobKind::PruneCheckpoint.into(), &key, "", now)
+                    .await?;
+            }
+            return Ok(());
+        }
+        if let Some(store) = &self.store {
+            store.put(&self.client, &name, contents).await?;
+            info!(name, "Uploaded checkpoint");
+        }
+        if let Some(expired) = expired {
+            self.prune(expired).await?;
+        }
+        Ok(())
+    }
+
+    /// Uploads the checkpoint with `num_participants` contributions from the
+    /// directory to the object store, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the checkpoint can not be read or uploaded.
+    pub async fn upload(&self, num_participants: usize) -> EyreResult<()> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let name = checkpoint_name(num_participants);
+        let contents = fs::read(self.dir.join(&name)).await?;
+        store.put(&self.client, &name, contents).await?;
+        info!(name, "Uploaded checkpoint");
+        Ok(())
+    }
+
+    /// Removes the checkpoint with `num_participants` contributions from the
+    /// directory and the object store.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the checkpoint can not be removed.
+    pub async fn prune(&self, num_participants: usize) -> EyreResult<()> {
+        let name = checkpoint_name(num_participants);
+        if let Err(e) = fs::remove_file(self.dir.join(&name)).await {
+            if e.kind() != ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+        if let Some(store) = &self.store {
+            store.delete(&self.client, &name).await?;
+        }
+        Ok(())
+    }
+}
+
+fn checkpoint_name(num_participants: usize) -> String {
+    format!("transcript-{num_participants:08}.json")
+}
+
+fn parse_checkpoint_name(name: &str) -> Option<usize> {
+    name.strip_prefix("transcript-")?
+        .strip_suffix(".json")?
+        .parse()
+        .ok()
+}
+
+/// State of the transcript sources found during startup.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReconciliationReport {
+    /// Contributions marked as finished in the contributors table.
+    pub finished_contributions:       usize,
+    /// Contributions stored in the transcript entries table.
+    pub database_entries:             usize,
+    /// Contributions in the transcript file, if it could be read.
+    pub transcript_file_participants: Option<usize>,
+    /// Why the transcript file could not be used.
+    pub transcript_file_error:        Option<String>,
+    /// Checkpoint copied over the transcript file.
+    pub restored_checkpoint:          Option<PathBuf>,
+}
+
+impl ReconciliationReport {
+    /// Finished contributions that are in none of the transcript sources.
+    #[must_use]
+    pub fn lost_contributions(&self) -> usize {
+        let restored = self
+            .restored_checkpoint
+            .as_deref()
+            .and_then(Path::file_name)
+            .and_then(|name| parse_checkpoint_name(&name.to_string_lossy()));
+        let recovered = self
+            .database_entries
+            .max(self.transcript_file_participants.unwrap_or_default())
+            .max(restored.unwrap_or_default());
+        self.finished_contributions.saturating_sub(recovered)
+    }
+}
+
+/// Checks the transcript file against the database before the transcript is
+/// loaded.
+///
+/// The database is the source of truth whenever it has transcript entries.
+/// Otherwise the transcript file is used, and if it can not be read or holds
+/// fewer contributions than the contributors table says were finished, it is
+/// replaced by the most recent valid checkpoint.
+///
+/// # Errors
+///
+/// Returns an error if the database can not be queried or a checkpoint can
+/// not be copied.
+pub async fn recover_transcript(
+    options: &crate::Options,
+    storage: &PersistentStorage,
+) -> EyreResult<ReconciliationReport> {
+    let mut report = ReconciliationReport {
+        finished_contributions: storage.count_finished_contributions().await?,
+        database_entries: storage.count_transcript_entries().await?,
+        ..ReconciliationReport::default()
+    };
+
+    if options.transcript_file.exists() {
+        match read_transcript(&options.transcript_file, &options.ceremony_sizes).await {
+            Ok(transcript) => {
+                report.transcript_file_participants = Some(transcript.num_participants());
+            }
+            Err(e) => report.transcript_file_error = Some(e.to_string()),
+        }
+    }
+
+    let file_participants = report.transcript_file_participants.unwrap_or_default();
+    let torn =
+        report.transcript_file_error.is_some() || file_participants < report.finished_contributions;
+    if report.database_entries == 0 && torn && options.checkpoint.recover_from_checkpoint {
+        match latest_checkpoint(&options.checkpoint.checkpoint_dir, &options.ceremony_sizes).await {
+            Some((path, num_participants))
+                if report.transcript_file_error.is_some()
+                    || num_participants > file_participants =>
+            {
+                let work_path = &options.transcript_in_progress_file;
+                fs::copy(&path, work_path).await?;
+                fs::rename(work_path, &options.transcript_file).await?;
+                report.restored_checkpoint = Some(path);
+            }
+            _ => warn!("No newer checkpoint found to restore the transcript from"),
+        }
+    }
+
+    if report.lost_contributions() > 0 || report.transcript_file_error.is_some() {
+        warn!(
+            ?report,
+            lost_contributions = report.lost_contributions(),
+            "Transcript reconciliation found inconsistencies"
+        );
+    } else {
+        info!(?report, "Transcript reconciliation complete");
+    }
+    Ok(report)
+}
+
+async fn read_transcript(path: &Path, sizes: &CeremonySizes) -> EyreResult<BatchTranscript> {
+    let transcript = read_json_file::<BatchTranscript>(path.to_path_buf()).await?;
+    sizes.validate_batch_transcript(&transcript)?;
+    Ok(transcript)
+}
+
+/// Finds the checkpoint with the most contributions that can be read and
+/// matches the ceremony sizes.
+async fn latest_checkpoint(dir: &Path, sizes: &CeremonySizes) -> Option<(PathBuf, usize)> {
+    let mut entries = fs::read_dir(dir).await.ok()?;
+    let mut checkpoints = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Some(num_participants) = parse_checkpoint_name(&entry.file_name().to_string_lossy())
+        {
+            checkpoints.push((entry.path(), num_participants));
+        }
+    }
+    checkpoints.sort_unstable_by_key(|(_, num_participants)| *num_participants);
+    for (path, num_participants) in checkpoints.into_iter().rev() {
+        match read_transcript(&path, sizes).await {
+            Ok(transcript) if transcript.num_participants() == num_participants => {
+                return Some((path, num_participants));
+            }
+            Ok(_) => {
+                warn!(path = %path.display(), "Checkpoint has the wrong number of contributions");
+            }
+            Err(e) => warn!(path = %path.display(), "Skipping invalid checkpoint: {}", e),
+        }
+    }
+    None
+}
+
+enum ObjectStore {
+    S3 {
+        endpoint:   Url,
+        bucket:     String,
+        prefix:     String,
+        region:     String,
+        access_key: Secret,
+        secret_key: Secret,
+    },
+    Gcs {
+        bucket: String,
+        prefix: String,
+        token:  Secret,
+    },
+}
+
+impl ObjectStore {
+    fn new(url: &Url, options: &Options) -> EyreResult<Self> {
+        let bucket = url
+            .host_str()
+            .ok_or_else(|| eyre!("Missing bucket in {}", url))?
+            .to_string();
+        let prefix = url.path().trim_matches('/').to_string();
+        match url.scheme() {
+            "s3" => {
+                let endpoint = match &options.backup_s3_endpoint {
+                    Some(endpoint) => endpoint.clone(),
+                    None => {
+                        format!("https://s3.{}.amazonaws.com", options.backup_s3_region).parse()?
+                    }
+                };
+                let (access_key, secret_key) =
+                    match (&options.aws_access_key_id, &options.aws_secret_access_key) {
+                        (Some(access_key), Some(secret_key)) => {
+                            (access_key.clone(), secret_key.clone())
+                        }
+                        _ => bail!(
+                            "--aws-access-key-id and --aws-secret-access-key are required for S3 \
+                             backups"
+                        ),
+                    };
+                Ok(Self::S3 {
+                    endpoint,
+                    bucket,
+                    prefix,
+                    region: options.backup_s3_region.clone(),
+                    access_key,
+                    secret_key,
+                })
+            }
+            "gs" => {
+                let token = options
+                    .backup_gcs_token
+                    .clone()
+                    .ok_or_else(|| eyre!("--backup-gcs-token is required for GCS backups"))?;
+                Ok(Self::Gcs {
+                    bucket,
+                    prefix,
+                    token,
+                })
+            }
+            scheme => bail!("Unsupported backup url scheme {}", scheme),
+        }
+    }
+
+    async fn put(&self, client: &reqwest::Client, name: &str, contents: Vec<u8>) -> EyreResult<()> {
+        self.send(client, reqwest::Method::PUT, name, contents)
+            .await
+    }
+
+    async fn delete(&self, client: &reqwest::Client, name: &str) -> EyreResult<()> {
+        self.send(client, reqwest::Method::DELETE, name, Vec::new())
+            .await
+    }
+
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+        name: &str,
+        body: Vec<u8>,
+    ) -> EyreResult<()> {
+        let request = match self {
+            Self::S3 {
+                endpoint,
+                bucket,
+                prefix,
+                region,
+                access_key,
+                secret_key,
+            } => {
+                let path = uri_encode_path(&format!("/{bucket}/{}", object_key(prefix, name)));
+                let url = endpoint.join(&path)?;
+                let host = match (url.host_str(), url.port()) {
+                    (Some(host), Some(port)) => format!("{host}:{port}"),
+                    (Some(host), None) => host.to_string(),
+                    _ => bail!("Invalid S3 endpoint {}", endpoint),
+                };
+                let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+                let signer = SigV4 {
+                    access_key: access_key.get_secret(),
+                    secret_key: secret_key.get_secret(),
+                    region,
+                    amz_date: &amz_date,
+                };
+                let payload_hash = hex::encode(Sha256::digest(&body));
+                let authorization =
+                    signer.authorization(method.as_str(), &path, &host, &payload_hash);
+                client
+                    .request(method, url)
+                    .header("x-amz-date", amz_date)
+                    .header("x-amz-content-sha256", payload_hash)
+                    .header("authorization", authorization)
+            }
+            Self::Gcs {
+                bucket,
+                prefix,
+                token,
+            } => {
+                let url = format!(
+                    "https://storage.googleapis.com{}",
+                    uri_encode_path(&format!("/{bucket}/{}", object_key(prefix, name)))
+                );
+                client.request(method, url).bearer_auth(token.get_secret())
+            }
+        };
+        let response = request.body(body).send().await?;
+        let status = response.status();
+        ensure!(
+            status.is_success() || status == reqwest::StatusCode::NOT_FOUND,
+            "Object store responded with {}: {}",
+            status,
+            response.text().await.unwrap_or_default()
+        );
+        Ok(())
+    }
+}
+
+fn object_key(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// Percent-encodes everything but unreserved characters and `/`.
+fn uri_encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// AWS Signature Version 4 for unsigned-query S3 requests.
+struct SigV4<'a> {
+    access_key: &'a str,
+    secret_key: &'a str,
+    region:     &'a str,
+    amz_date:   &'a str,
+}
+
+impl SigV4<'_> {
+    const SIGNED_HEADERS: &'static str = "host;x-amz-content-sha256;x-amz-date";
+
+    fn authorization(&self, method: &str, path: &str, host: &str, payload_hash: &str) -> String {
+        let date = &self.amz_date[..8];
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let canonical_request = [
+            method,
+            path,
+            "", // No query string
+            &format!("host:{host}"),
+            &format!("x-amz-content-sha256:{payload_hash}"),
+            &format!("x-amz-date:{}", self.amz_date),
+            "",
+            Self::SIGNED_HEADERS,
+            payload_hash,
+        ]
+        .join("\n");
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{scope}\n{}",
+            self.amz_date,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = signing_key(self.secret_key, date, self.region, "s3");
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={}, Signature={signature}",
+            self.access_key,
+            Self::SIGNED_HEADERS,
+        )
+    }
+}
+
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{secret_key}").as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        storage::storage_client,
+        test_util::test_options,
+        tests::{test_transcript, valid_contribution},
+        Engine,
+    };
+    use kzg_ceremony_crypto::signature::identity::Identity;
+    use tempfile::tempdir;
+
+    #[test]
+    fn derives_signing_key() {
+        // Example from the AWS Signature Version 4 documentation.
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn parses_backup_url() {
+        let mut options = test_options().checkpoint;
+        options.backup_url = Some("s3://bucket/".parse().unwrap());
+        assert!(Checkpointer::new(&options).is_err());
+
+        options.backup_url = Some("gs://bucket/ceremony".parse().unwrap());
+        options.backup_gcs_token = Some("token".parse().unwrap());
+        let checkpointer = Checkpointer::new(&options).unwrap();
+        assert!(matches!(
+            checkpointer.store,
+            Some(ObjectStore::Gcs { ref bucket, ref prefix, .. })
+                if bucket == "bucket" && prefix == "ceremony"
+        ));
+
+        options.backup_url = Some("ftp://bucket/".parse().unwrap());
+        assert!(Checkpointer::new(&options).is_err());
+    }
+
+    #[tokio::test]
+    async fn keeps_latest_checkpoints() {
+        let dir = tempdir().unwrap();
+        let mut options = test_options().checkpoint;
+        options.checkpoint_interval = 2;
+        options.checkpoint_retention = 2;
+        options.checkpoint_dir = dir.path().join("checkpoints");
+        let checkpointer = Checkpointer::new(&options).unwrap();
+
+        for num_participants in [2, 4, 6] {
+            checkpointer
+                .save(num_participants, b"{}".to_vec())
+                .await
+                .unwrap();
+        }
+        let mut names = std::fs::read_dir(&options.checkpoint_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec![
+            "transcript-00000004.json",
+            "transcript-00000006.json"
+        ]);
+    }
+
+    #[tokio::test]
+    async fn restores_torn_transcript() {
+        let dir = tempdir().unwrap();
+        let mut options = test_options();
+        options.ceremony_sizes = CeremonySizes::parse_from_cmd("4,2").unwrap();
+        options.transcript_file = dir.path().join("transcript.json");
+        options.transcript_in_progress_file = dir.path().join("transcript.json.next");
+        options.checkpoint.checkpoint_interval = 1;
+        options.checkpoint.checkpoint_dir = dir.path().join("checkpoints");
+        let storage = storage_client(&options.storage).await.unwrap();
+        let checkpointer = Checkpointer::new(&options.checkpoint).unwrap();
+
+        let mut transcript = test_transcript();
+        for no in 1..=2 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+            let uid = format!("git|{no}|user");
+            storage.insert_contributor(&uid, None).await.unwrap();
+            storage.finish_contribution(&uid).await.unwrap();
+            checkpointer
+                .save(no.into(), serde_json::to_vec(&transcript).unwrap())
+                .await
+                .unwrap();
+        }
+        // A torn write of the second contribution.
+        std::fs::write(
+            options.checkpoint.checkpoint_dir.join(checkpoint_name(2)),
+            "{",
+        )
+        .unwrap();
+        std::fs::write(&options.transcript_file, "{\"transcripts\": [").unwrap();
+
+        let report = recover_transcript(&options, &storage).await.unwrap();
+        assert_eq!(report.finished_contributions, 2);
+        assert_eq!(report.database_entries, 0);
+        assert!(report.transcript_file_error.is_some());
+        assert_eq!(
+            report.restored_checkpoint,
+            Some(options.checkpoint.checkpoint_dir.join(checkpoint_name(1)))
+        );
+        assert_eq!(report.lost_contributions(), 1);
+        let restored = read_json_file::<BatchTranscript>(options.transcript_file.clone())
+            .await
+            .unwrap();
+        assert_eq!(restored.num_participants(), 1);
+
+        // Nothing to do once the transcript file is intact.
+        let report = recover_transcript(&options, &storage).await.unwrap();
+        assert_eq!(report.transcript_file_participants, Some(1));
+        assert_eq!(report.restored_checkpoint, None);
+    }
+}
@@ -0,0 +1,358 @@
+//! Contributions uploaded in parts.
+//!
+//! On a flaky connection a single large `POST /contribute` may fail near the
+//! end and take the slot with it. Instead, the active contributor can send the
+//! body of the request in parts, `POST /contribute/upload/<n>` for `n` from 0,
+//! each at most `--max-upload-part-size` bytes. A part that failed is simply
+//! sent again. `GET /contribute/upload` lists the parts received so far, with
+//! their SHA-256, so that a client that lost track knows where to resume.
+//! `POST /contribute/upload` with the SHA-256 of the whole body in
+//! `X-Upload-Sha256` commits the upload: the parts are joined and handled as
+//! the body of `/contribute`, with the `Content-Type` and `Content-Encoding`
+//! of the commit request. The parts are kept after the commit, so that a
+//! commit whose response was lost can be repeated.
+//!
+//! Only the upload of the active contributor is kept, in memory. Each part
+//! counts as a heartbeat.
+
+use crate::{Options as AppOptions, SessionId};
+use axum::{
+    async_trait,
+    body::{Body, Bytes, HttpBody},
+    extract::{FromRequest, RequestParts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use http::{header::CONTENT_LENGTH, HeaderValue, Request};
+use kzg_ceremony_crypto::ErrorCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::task::JoinError;
+use utoipa::ToSchema;
+
+pub const UPLOAD_DIGEST_HEADER: &str = "x-upload-sha256";
+
+/// Parts are numbered from 0 to below this.
+const MAX_PARTS: u32 = 10_000;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ChunkedUploadError {
+    #[error("not the participant's turn")]
+    NotUsersTurn,
+    #[error("parts are numbered from 0 to {}, and must not be empty", MAX_PARTS - 1)]
+    InvalidPart,
+    #[error("part is larger than {0} bytes")]
+    PartTooLarge(usize),
+    #[error("upload is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("no upload of this session")]
+    UnknownUpload,
+    #[error("part {0} is missing")]
+    MissingPart(u32),
+    #[error("missing or malformed X-Upload-Sha256 header")]
+    MissingDigest,
+    #[error("the parts have SHA-256 {0}")]
+    DigestMismatch(String),
+    #[error("failed to read request body: {0}")]
+    ReadFailed(String),
+    #[error("background task error: {0}")]
+    TaskError(#[from] JoinError),
+}
+
+impl ErrorCode for ChunkedUploadError {
+    fn to_error_code(&self) -> String {
+        format!("ChunkedUploadError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct UploadStatus {
+    /// Total size of the parts in bytes.
+    size:  usize,
+    parts: Vec<PartStatus>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PartStatus {
+    part:   u32,
+    size:   usize,
+    sha256: String,
+}
+
+struct Part {
+    bytes:  Bytes,
+    sha256: String,
+}
+
+struct Upload {
+    session_hash: String,
+    parts:        BTreeMap<u32, Part>,
+}
+
+impl Upload {
+    fn size(&self) -> usize {
+        self.parts.values().map(|part| part.bytes.len()).sum()
+    }
+
+    fn status(&self) -> UploadStatus {
+        UploadStatus {
+            size:  self.size(),
+            parts: self
+                .parts
+                .iter()
+                .map(|(part, stored)| PartStatus {
+                    part:   *part,
+                    size:   stored.bytes.len(),
+                    sha256: stored.sha256.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+pub type SharedUploads = Arc<Uploads>;
+
+/// The upload of the active contributor. An upload of another session
+/// replaces it.
+#[derive(Default)]
+pub struct Uploads {
+    upload: Mutex<Option<Upload>>,
+}
+
+impl Uploads {
+    /// Stores `part` of the upload of `session_id`, replacing a part with the
+    /// same number.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the part number is out of range, the part is empty
+    /// or the parts would add up to more than `max_size` bytes.
+    pub fn put(
+        &self,
+        session_id: &SessionId,
+        part: u32,
+        bytes: Bytes,
+        max_size: usize,
+    ) -> Result<UploadStatus, ChunkedUploadError> {
+        if part >= MAX_PARTS || bytes.is_empty() {
+            return Err(ChunkedUploadError::InvalidPart);
+        }
+        let session_hash = session_id.hash();
+        let sha256 = hex::encode(Sha256::digest(&bytes));
+        let mut upload = self.upload.lock().unwrap();
+        let upload = match &mut *upload {
+            Some(upload) if upload.session_hash == session_hash => upload,
+            other => other.insert(Upload {
+                session_hash,
+                parts: BTreeMap::new(),
+            }),
+        };
+        let replaced = upload.parts.get(&part).map_or(0, |part| part.bytes.len());
+        if upload.size() - replaced + bytes.len() > max_size {
+            return Err(ChunkedUploadError::TooLarge(max_size));
+        }
+        upload.parts.insert(part, Part { bytes, sha256 });
+        Ok(upload.status())
+    }
+
+    /// The parts received from `session_id`.
+    #[must_use]
+    pub fn status(&self, session_id: &SessionId) -> Option<UploadStatus> {
+        let session_hash = session_id.hash();
+        self.upload
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|upload| upload.session_hash == session_hash)
+            .map(Upload::status)
+    }
+
+    /// The parts of `session_id` joined together.
+    fn join(&self, session_id: &SessionId) -> Result<Vec<u8>, ChunkedUploadError> {
+        let session_hash = session_id.hash();
+        let upload = self.upload.lock().unwrap();
+        let upload = upload
+            .as_ref()
+            .filter(|upload| upload.session_hash == session_hash)
+            .ok_or(ChunkedUploadError::UnknownUpload)?;
+        let mut joined = Vec::with_capacity(upload.size());
+        for (expected, (part, stored)) in (0..).zip(&upload.parts) {
+            if *part != expected {
+                return Err(ChunkedUploadError::MissingPart(expected));
+            }
+            joined.extend_from_slice(&stored.bytes);
+        }
+        Ok(joined)
+    }
+}
+
+/// Extractor for the body of a part, of at most `--max-upload-part-size`
+/// bytes.
+pub struct UploadPart(pub Bytes);
+
+#[async_trait]
+impl<B> FromRequest<B> for UploadPart
+where
+    B: HttpBody<Data = Bytes> + Send + Unpin,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = ChunkedUploadError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let max_size = req
+            .extensions()
+            .get::<AppOptions>()
+            .map_or(usize::MAX, |options| options.upload.max_upload_part_size);
+        let mut body = req
+            .take_body()
+            .ok_or_else(|| ChunkedUploadError::ReadFailed("body already taken".to_string()))?;
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk =
+                chunk.map_err(|error| ChunkedUploadError::ReadFailed(error.into().to_string()))?;
+            if bytes.len() + chunk.len() > max_size {
+                return Err(ChunkedUploadError::PartTooLarge(max_size));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(Self(bytes.into()))
+    }
+}
+
+/// Middleware of `POST /contribute/upload`, which replaces the body of the
+/// request by the joined parts once they match `X-Upload-Sha256`, and passes
+/// it on to the `/contribute` handler.
+pub async fn commit(request: Request<Body>, next: Next<Body>) -> Response {
+    let mut parts = RequestParts::new(request);
+    let session_id = match SessionId::from_request(&mut parts).await {
+        Ok(session_id) => session_id,
+        Err(err) => return err.into_response(),
+    };
+    let mut request = match parts.try_into_request() {
+        Ok(request) => request,
+        Err(err) => return ChunkedUploadError::ReadFailed(err.to_string()).into_response(),
+    };
+    match joined_body(&request, &session_id).await {
+        Ok(body) => {
+            request
+                .headers_mut()
+                .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+            *request.body_mut() = Body::from(body);
+            next.run(request).await
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+async fn joined_body(
+    request: &Request<Body>,
+    session_id: &SessionId,
+) -> Result<Vec<u8>, ChunkedUploadError> {
+    let expected = request
+        .headers()
+        .get(UPLOAD_DIGEST_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| hex::decode(value.trim()).ok())
+        .ok_or(ChunkedUploadError::MissingDigest)?;
+    let uploads = request
+        .extensions()
+        .get::<SharedUploads>()
+        .cloned()
+        .ok_or(ChunkedUploadError::UnknownUpload)?;
+    let session_id = session_id.clone();
+    tokio::task::spawn_blocking(move || {
+        let joined = uploads.join(&session_id)?;
+        let digest = Sha256::digest(&joined);
+        if digest.as_slice() != expected.as_slice() {
+            return Err(ChunkedUploadError::DigestMismatch(hex::encode(digest)));
+        }
+        Ok(joined)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware::from_fn, routing::post, Extension, Router};
+    use http::{header::AUTHORIZATION, StatusCode};
+    use tower::ServiceExt;
+
+    #[test]
+    fn stores_parts() {
+        let uploads = Uploads::default();
+        let session_id = SessionId::new();
+        let put = |part, bytes: &'static [u8]| {
+            uploads.put(&session_id, part, Bytes::from_static(bytes), 8)
+        };
+
+        put(1, b"def").unwrap();
+        assert!(matches!(
+            uploads.join(&session_id),
+            Err(ChunkedUploadError::MissingPart(0))
+        ));
+        put(0, b"xyz").unwrap();
+        // Sent again after a failure.
+        let status = put(0, b"abc").unwrap();
+        assert_eq!(status.size, 6);
+        assert_eq!(status.parts[0].sha256, hex::encode(Sha256::digest(b"abc")));
+        assert_eq!(uploads.join(&session_id).unwrap(), b"abcdef");
+        assert!(matches!(
+            put(2, b"ghi"),
+            Err(ChunkedUploadError::TooLarge(8))
+        ));
+        assert!(matches!(put(2, b""), Err(ChunkedUploadError::InvalidPart)));
+        assert!(matches!(
+            put(MAX_PARTS, b"g"),
+            Err(ChunkedUploadError::InvalidPart)
+        ));
+
+        let other = SessionId::new();
+        assert_eq!(uploads.status(&other), None);
+        uploads
+            .put(&other, 0, Bytes::from_static(b"new"), 8)
+            .unwrap();
+        assert_eq!(uploads.status(&session_id), None);
+    }
+
+    #[tokio::test]
+    async fn commits_joined_parts() {
+        let uploads = SharedUploads::default();
+        let session_id = SessionId::new();
+        uploads
+            .put(&session_id, 0, Bytes::from_static(b"{\"a\":"), 100)
+            .unwrap();
+        uploads
+            .put(&session_id, 1, Bytes::from_static(b"1}"), 100)
+            .unwrap();
+        let app = Router::new()
+            .route(
+                "/contribute/upload",
+                post(|body: String| async move { body }).layer(from_fn(commit)),
+            )
+            .layer(Extension(uploads));
+        let commit = |digest: &str| {
+            Request::post("/contribute/upload")
+                .header(AUTHORIZATION, format!("Bearer {}", session_id.0))
+                .header(UPLOAD_DIGEST_HEADER, digest)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let digest = hex::encode(Sha256::digest(b"{\"a\":1}"));
+        let response = app.clone().oneshot(commit(&digest)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"{\"a\":1}");
+
+        let response = app.oneshot(commit(&"00".repeat(32))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
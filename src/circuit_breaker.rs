@@ -0,0 +1,112 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{error, info};
+
+/// Stops calls to the database after `threshold` consecutive failures.
+///
+/// While open, calls are rejected for `cooldown`. After that a single call is
+/// let through to probe the database: success closes the breaker, failure
+/// keeps it open for another `cooldown`. A threshold of 0 never opens it.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: usize,
+    cooldown:  Duration,
+    state:     Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    consecutive_failures: usize,
+    /// When the breaker opened, or when the last probe was let through.
+    opened_at:            Option<Instant>,
+}
+
+impl CircuitBreaker {
+    #[must_use]
+    pub fn new(threshold: usize, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().opened_at.is_some()
+    }
+
+    /// Whether a call may go through.
+    #[must_use]
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() >= self.cooldown => {
+                state.opened_at = Some(Instant::now());
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        if state.opened_at.take().is_some() {
+            info!("Database recovered, leaving degraded mode");
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if self.threshold == 0 || state.consecutive_failures < self.threshold {
+            return;
+        }
+        if state.opened_at.is_none() {
+            error!(
+                failures = state.consecutive_failures,
+                "Database is unhealthy, entering degraded mode"
+            );
+        }
+        state.opened_at = Some(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(50));
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        assert!(!breaker.allow());
+
+        // One probe per cooldown.
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+        breaker.record_failure();
+        assert!(breaker.is_open());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.allow());
+
+        let disabled = CircuitBreaker::new(0, Duration::from_millis(50));
+        disabled.record_failure();
+        assert!(!disabled.is_open());
+    }
+}
@@ -0,0 +1,310 @@
+//! The address of the client behind trusted reverse proxies.
+//!
+//! Behind a proxy like Cloudflare every request comes from one of the
+//! proxy's addresses, and the client's is in a forwarding header, which
+//! anyone can set. With `--trusted-proxies`, the headers are only believed
+//! when the peer is one of the listed proxies: `--rate-limit-ip-header` if
+//! set, e.g. `CF-Connecting-IP`, otherwise the RFC 7239 `Forwarded` header
+//! or else `X-Forwarded-For`. The hops the headers list are walked from the
+//! nearest proxy back, and the first address that is not a trusted proxy is
+//! the client's. Without `--trusted-proxies`, `--rate-limit-ip-header` is
+//! believed from any peer, as before.
+//!
+//! IPv4 addresses that arrive as IPv4-mapped IPv6 addresses on a dual-stack
+//! socket are turned back into IPv4 addresses, so that a client has the same
+//! address either way.
+
+use axum::extract::ConnectInfo;
+use clap::Parser;
+use http::{Extensions, HeaderMap};
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Addresses or CIDR ranges of the reverse proxies in front of the
+    /// sequencer, comma separated. Forwarding headers are only believed from
+    /// them.
+    #[clap(long, env, value_delimiter = ',')]
+    pub trusted_proxies: Vec<IpNetwork>,
+}
+
+/// An address range like `173.245.48.0/20`. A bare address is a range of one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpNetwork {
+    /// The address as IPv6, IPv4 addresses mapped.
+    network: u128,
+    /// Prefix length in IPv6 bits.
+    prefix:  u32,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid address range {0}")]
+pub struct InvalidIpNetwork(String);
+
+impl FromStr for IpNetwork {
+    type Err = InvalidIpNetwork;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidIpNetwork(value.to_string());
+        let (address, prefix) = match value.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let address = canonical(address.parse().map_err(|_| invalid())?);
+        let bits = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u32>().map_err(|_| invalid())?,
+            None => bits,
+        };
+        if prefix > bits {
+            return Err(invalid());
+        }
+        let prefix = prefix + 128 - bits;
+        Ok(Self {
+            network: as_u128(address) & mask(prefix),
+            prefix,
+        })
+    }
+}
+
+impl IpNetwork {
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        as_u128(canonical(ip)) & mask(self.prefix) == self.network
+    }
+}
+
+fn as_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u128::from(ip.to_ipv6_mapped()),
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+const fn mask(prefix: u32) -> u128 {
+    match prefix {
+        0 => 0,
+        prefix => u128::MAX << (128 - prefix),
+    }
+}
+
+/// `ip`, as IPv4 address if it is an IPv4-mapped IPv6 address.
+#[must_use]
+pub fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// `ip` with all but the first `prefix` bits cleared if it is an IPv6
+/// address, since a single client usually has a whole /64.
+#[must_use]
+pub fn ipv6_prefix(ip: IpAddr, prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(
+            u128::from(v6) & mask(u32::from(prefix.min(128))),
+        )),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// Address of the client, see the module documentation.
+#[must_use]
+pub fn client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    ip_header: Option<&str>,
+    options: &Options,
+) -> Option<IpAddr> {
+    let peer = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| canonical(addr.ip()));
+    let trusted = |ip: IpAddr| {
+        options
+            .trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip))
+    };
+    if options.trusted_proxies.is_empty() {
+        return match ip_header {
+            Some(header) => header_values(headers, header).next().and_then(parse_node),
+            None => peer,
+        };
+    }
+    let peer = peer?;
+    if !trusted(peer) {
+        return Some(peer);
+    }
+    if let Some(header) = ip_header {
+        return header_values(headers, header)
+            .next()
+            .and_then(parse_node)
+            .or(Some(peer));
+    }
+    let hops = if headers.contains_key(http::header::FORWARDED) {
+        header_values(headers, http::header::FORWARDED.as_str())
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(node))
+            })
+            .collect::<Vec<_>>()
+    } else {
+        header_values(headers, "x-forwarded-for")
+            .map(parse_node)
+            .collect()
+    };
+    // Nearest hop first. A hop that can not be parsed, e.g. an obfuscated
+    // one, ends the walk at the proxy that added it.
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        match hop {
+            Some(hop) if trusted(client) => client = hop,
+            _ => break,
+        }
+    }
+    Some(client)
+}
+
+/// The comma separated values of all `name` headers, in order.
+fn header_values<'a>(headers: &'a HeaderMap, name: &str) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+}
+
+/// An address as forwarding headers give it: possibly quoted, IPv6 possibly
+/// in brackets and either with a port.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    let address = match node.strip_prefix('[') {
+        Some(rest) => rest.split(']').next()?,
+        None => match node.split_once(':') {
+            // One colon separates an IPv4 address from the port, IPv6
+            // addresses have more.
+            Some((address, port)) if !port.contains(':') => address,
+            _ => node,
+        },
+    };
+    address.parse().ok().map(canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+    use std::net::Ipv4Addr;
+
+    fn request(peer: &str, headers: &[(&'static str, &'static str)]) -> (HeaderMap, Extensions) {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 443)));
+        (map, extensions)
+    }
+
+    fn trusting(ranges: &str) -> Options {
+        Options {
+            trusted_proxies: ranges
+                .split(',')
+                .map(|range| range.parse().unwrap())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn parses_networks() {
+        let network: IpNetwork = "173.245.48.0/20".parse().unwrap();
+        assert!(network.contains("173.245.63.1".parse().unwrap()));
+        assert!(network.contains("::ffff:173.245.48.1".parse().unwrap()));
+        assert!(!network.contains("173.245.64.1".parse().unwrap()));
+        let network: IpNetwork = "2400:cb00::/32".parse().unwrap();
+        assert!(network.contains("2400:cb00:1::1".parse().unwrap()));
+        assert!(!network.contains("2400:cb01::1".parse().unwrap()));
+        let single: IpNetwork = "10.0.0.1".parse().unwrap();
+        assert!(single.contains("10.0.0.1".parse().unwrap()));
+        assert!(!single.contains("10.0.0.2".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("proxy".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn believes_only_trusted_proxies() {
+        let options = trusting("10.0.0.0/8");
+        let client = |peer, headers: &[(&'static str, &'static str)]| {
+            let (headers, extensions) = request(peer, headers);
+            client_ip(&headers, &extensions, None, &options)
+        };
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+
+        let forwarded = [("x-forwarded-for", "203.0.113.9")];
+        assert_eq!(client("198.51.100.1", &forwarded), ip("198.51.100.1"));
+        assert_eq!(client("10.0.0.1", &forwarded), ip("203.0.113.9"));
+        // A client that forges the header is the first untrusted hop.
+        assert_eq!(
+            client("10.0.0.1", &[(
+                "x-forwarded-for",
+                "1.2.3.4, 203.0.113.9, 10.0.0.2"
+            )]),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            client("10.0.0.1", &[
+                ("forwarded", "for=1.2.3.4"),
+                ("forwarded", "for=\"[2001:db8::17]:4711\";proto=https"),
+                ("x-forwarded-for", "203.0.113.9"),
+            ]),
+            ip("2001:db8::17")
+        );
+        assert_eq!(
+            client("10.0.0.1", &[("forwarded", "for=_hidden, for=10.0.0.2")]),
+            ip("10.0.0.2")
+        );
+        assert_eq!(client("::ffff:10.0.0.1", &forwarded), ip("203.0.113.9"));
+        assert_eq!(client("::ffff:203.0.113.9", &[]), ip("203.0.113.9"));
+
+        let (headers, extensions) = request("10.0.0.1", &[("cf-connecting-ip", "203.0.113.7")]);
+        assert_eq!(
+            client_ip(&headers, &extensions, Some("cf-connecting-ip"), &options),
+            ip("203.0.113.7")
+        );
+        let (headers, extensions) = request("198.51.100.1", &[("cf-connecting-ip", "203.0.113.7")]);
+        assert_eq!(
+            client_ip(&headers, &extensions, Some("cf-connecting-ip"), &options),
+            ip("198.51.100.1")
+        );
+        // Without trusted proxies the header is believed from anyone.
+        assert_eq!(
+            client_ip(
+                &headers,
+                &extensions,
+                Some("cf-connecting-ip"),
+                &Options::default()
+            ),
+            ip("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn groups_ipv6_by_prefix() {
+        assert_eq!(
+            ipv6_prefix("2001:db8:1:2:3:4:5:6".parse().unwrap(), 64),
+            "2001:db8:1:2::".parse::<IpAddr>().unwrap()
+        );
+        let v4 = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 9));
+        assert_eq!(ipv6_prefix(v4, 64), v4);
+    }
+}
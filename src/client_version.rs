@@ -0,0 +1,276 @@
+//! Versions of the contribution clients.
+//!
+//! Clients name themselves in the `X-Client-Version` header as
+//! `<name>/<version>`, e.g. `powers-of-tau-cli/1.2.0`. The version is
+//! recorded with each contribution, so that contributions of a client found
+//! to be buggy later can be told apart. `--min-client-version
+//! powers-of-tau-cli=1.2.0` rejects older versions of that client in
+//! `/lobby/try_contribute` and `/contribute` with `SEQ-CLIENT-002`, before
+//! they get the slot. Clients without the header, or without a configured
+//! minimum, are accepted.
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    Extension,
+};
+use clap::Parser;
+use eyre::{eyre, Result as EyreResult};
+use kzg_ceremony_crypto::ErrorCode;
+use std::{cmp::Ordering, fmt, str::FromStr};
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+pub const CLIENT_VERSION_HEADER: &str = "x-client-version";
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Oldest accepted version of a client, as `<name>=<version>`. Can be
+    /// given once per client, or as a comma separated list.
+    #[clap(long, env, value_parser = MinClientVersion::from_str, value_delimiter = ',')]
+    pub min_client_version: Vec<MinClientVersion>,
+}
+
+impl Options {
+    fn minimum(&self, name: &str) -> Option<&Version> {
+        self.min_client_version
+            .iter()
+            .find(|minimum| minimum.name == name)
+            .map(|minimum| &minimum.version)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MinClientVersion {
+    pub name:    String,
+    pub version: Version,
+}
+
+impl FromStr for MinClientVersion {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> EyreResult<Self> {
+        let (name, version) = value
+            .split_once('=')
+            .ok_or_else(|| eyre!("expected <name>=<version>, got {value}"))?;
+        Ok(Self {
+            name:    name.trim().to_string(),
+            version: version.trim().parse()?,
+        })
+    }
+}
+
+/// A `major.minor.patch` version with an optional pre-release, which comes
+/// before the release. Build metadata is ignored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Version {
+    core:        [u64; 3],
+    pre_release: Option<String>,
+}
+
+impl FromStr for Version {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> EyreResult<Self> {
+        let version = value.split('+').next().unwrap_or_default();
+        let (core, pre_release) = match version.split_once('-') {
+            Some((core, pre_release)) => (core, Some(pre_release.to_string())),
+            None => (version, None),
+        };
+        let parts = core
+            .split('.')
+            .map(u64::from_str)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| eyre!("invalid version {value}"))?;
+        // `1.2` is read as `1.2.0`.
+        if parts.is_empty() || parts.len() > 3 {
+            return Err(eyre!("invalid version {value}"));
+        }
+        let mut numbers = [0; 3];
+        numbers[..parts.len()].copy_from_slice(&parts);
+        Ok(Self {
+            core: numbers,
+            pre_release,
+        })
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.core
+            .cmp(&other.core)
+            .then_with(|| match (&self.pre_release, &other.pre_release) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(ours), Some(theirs)) => ours.cmp(theirs),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [major, minor, patch] = self.core;
+        write!(f, "{major}.{minor}.{patch}")?;
+        if let Some(pre_release) = &self.pre_release {
+            write!(f, "-{pre_release}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientVersion {
+    pub name:    String,
+    pub version: Version,
+}
+
+impl FromStr for ClientVersion {
+    type Err = eyre::Report;
+
+    /// Parses `<name>/<version>`. Further product tokens, as in a
+    /// `User-Agent`, are ignored.
+    fn from_str(value: &str) -> EyreResult<Self> {
+        let product = value.split_whitespace().next().unwrap_or_default();
+        let (name, version) = product
+            .split_once('/')
+            .ok_or_else(|| eyre!("expected <name>/<version>, got {value}"))?;
+        if name.is_empty() {
+            return Err(eyre!("missing client name in {value}"));
+        }
+        Ok(Self {
+            name:    name.to_string(),
+            version: version.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for ClientVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.name, self.version)
+    }
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ClientVersionError {
+    #[error("invalid X-Client-Version header: {0}")]
+    Invalid(String),
+    #[error("{name} {version} is no longer supported, upgrade to {minimum} or later")]
+    TooOld {
+        name:    String,
+        version: Version,
+        minimum: Version,
+    },
+}
+
+impl ErrorCode for ClientVersionError {
+    fn to_error_code(&self) -> String {
+        format!("ClientVersionError::{}", <&str>::from(self))
+    }
+}
+
+/// Checks `client` against the configured minimum of its name.
+pub fn check(options: &Options, client: &ClientVersion) -> Result<(), ClientVersionError> {
+    match options.minimum(&client.name) {
+        Some(minimum) if client.version < *minimum => Err(ClientVersionError::TooOld {
+            name:    client.name.clone(),
+            version: client.version.clone(),
+            minimum: minimum.clone(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Extractor for the `X-Client-Version` header, which rejects clients older
+/// than their minimum.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientHeader(pub Option<ClientVersion>);
+
+#[async_trait]
+impl<B> FromRequest<B> for ClientHeader
+where
+    B: Send,
+{
+    type Rejection = ClientVersionError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let value = match req.headers().get(CLIENT_VERSION_HEADER) {
+            Some(value) => value
+                .to_str()
+                .map_err(|_| ClientVersionError::Invalid("not visible ASCII".to_string()))?,
+            None => return Ok(Self(None)),
+        };
+        let client = value
+            .parse::<ClientVersion>()
+            .map_err(|err| ClientVersionError::Invalid(err.to_string()))?;
+        if let Ok(Extension(options)) = Extension::<crate::Options>::from_request(req).await {
+            check(&options.client_version, &client)?;
+        }
+        Ok(Self(Some(client)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use http::Request;
+
+    fn version(value: &str) -> Version {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn compares_versions() {
+        assert!(version("1.2.0") < version("1.10.0"));
+        assert!(version("1.2") == version("1.2.0"));
+        assert!(version("1.2.0-rc.1") < version("1.2.0"));
+        assert!(version("1.2.0+build.7") == version("1.2.0"));
+        assert!("1.x".parse::<Version>().is_err());
+        assert!("1.2.3.4".parse::<Version>().is_err());
+
+        let client: ClientVersion = "powers-of-tau-cli/1.2.0-rc.1 (linux)".parse().unwrap();
+        assert_eq!(client.name, "powers-of-tau-cli");
+        assert_eq!(client.to_string(), "powers-of-tau-cli/1.2.0-rc.1");
+        assert!("powers-of-tau-cli".parse::<ClientVersion>().is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_old_clients() {
+        let mut options = crate::test_util::test_options();
+        options.client_version.min_client_version =
+            vec!["powers-of-tau-cli=1.2.0".parse().unwrap()];
+        let extract = |header: Option<&str>| {
+            let mut request = Request::builder();
+            if let Some(header) = header {
+                request = request.header(CLIENT_VERSION_HEADER, header);
+            }
+            let mut request = RequestParts::new(
+                request
+                    .extension(options.clone())
+                    .body(Body::empty())
+                    .unwrap(),
+            );
+            async move { ClientHeader::from_request(&mut request).await }
+        };
+
+        assert_eq!(extract(None).await.unwrap(), ClientHeader(None));
+        assert!(extract(Some("powers-of-tau-cli/1.2.0")).await.is_ok());
+        assert!(extract(Some("other-client/0.1.0")).await.is_ok());
+        assert!(matches!(
+            extract(Some("powers-of-tau-cli/1.1.9")).await,
+            Err(ClientVersionError::TooOld { .. })
+        ));
+        assert!(matches!(
+            extract(Some("powers-of-tau-cli")).await,
+            Err(ClientVersionError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,312 @@
+//! Subcommands that work on a transcript or database without starting the
+//! HTTP server.
+
+use crate::{
+    identity,
+    io::{read_json_file, write_json_file, CeremonySizes},
+    run_on_curve,
+    storage::{self, storage_client},
+    transcript_format::TranscriptFormatKind,
+    Engine, DEFAULT_CEREMONY_SIZES,
+};
+use chrono::Utc;
+use clap::Parser;
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{BatchTranscript, Curve};
+use std::{io::Write, path::PathBuf, sync::Arc, time::Instant};
+use tokio::sync::RwLock;
+use tracing::info;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct VerifyTranscriptOptions {
+    /// Transcript file to verify.
+    pub transcript: PathBuf,
+
+    /// Also check that the transcript has these sizes, in the format of
+    /// `serve --ceremony-sizes`.
+    #[clap(long, value_parser=CeremonySizes::parse_from_cmd)]
+    pub ceremony_sizes: Option<CeremonySizes>,
+
+    /// Curve of the transcript, as for `serve --curve`.
+    #[clap(long, env, default_value = "bls12-381")]
+    pub curve: Curve,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct ExportOptions {
+    /// Transcript file to export.
+    pub transcript: PathBuf,
+
+    /// Format to export to. `json` writes the transcript file as is.
+    #[clap(long, value_enum, default_value = "kzg-json")]
+    pub format: TranscriptFormatKind,
+
+    /// Sub-ceremony to export in formats holding a single one.
+    #[clap(long, default_value = "0")]
+    pub ceremony: usize,
+
+    /// File to write the export to. Defaults to standard output.
+    #[clap(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Curve of the transcript, as for `serve --curve`.
+    #[clap(long, env, default_value = "bls12-381")]
+    pub curve: Curve,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct InitOptions {
+    /// Transcript file of a previous ceremony to continue, e.g. the output of
+    /// the Ethereum KZG ceremony.
+    #[clap(long)]
+    pub from_transcript: PathBuf,
+
+    /// The transcript file of `serve` to create.
+    #[clap(long, env, default_value = "./transcript.json")]
+    pub transcript_file: PathBuf,
+
+    /// Temporary storage location for transcript writing, as for `serve`.
+    #[clap(long, env, default_value = "./transcript.json.next")]
+    pub transcript_in_progress_file: PathBuf,
+
+    /// The sizes the transcript must have, in the format of `serve
+    /// --ceremony-sizes`.
+    #[clap(long, env, value_parser=CeremonySizes::parse_from_cmd, default_value=DEFAULT_CEREMONY_SIZES)]
+    pub ceremony_sizes: CeremonySizes,
+
+    /// Curve of the transcript, as for `serve --curve`.
+    #[clap(long, env, default_value = "bls12-381")]
+    pub curve: Curve,
+
+    #[clap(flatten)]
+    pub storage: storage::Options,
+}
+
+async fn read_transcript(path: PathBuf) -> EyreResult<BatchTranscript> {
+    read_json_file::<BatchTranscript>(path.clone())
+        .await
+        .wrap_err_with(|| format!("failed to read transcript from {}", path.display()))
+}
+
+/// Re-verifies every contribution in a transcript file.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read or the transcript is invalid.
+pub async fn verify_transcript(options: VerifyTranscriptOptions) -> EyreResult<()> {
+    run_on_curve(options.curve)?;
+    let transcript = read_transcript(options.transcript).await?;
+    if let Some(sizes) = &options.ceremony_sizes {
+        sizes.validate_batch_transcript(&transcript)?;
+    }
+
+    info!(
+        num_participants = transcript.num_participants(),
+        "Verifying transcript"
+    );
+    let start = Instant::now();
+    tokio::task::spawn_blocking(move || transcript.verify_self::<Engine>())
+        .await?
+        .wrap_err("transcript is invalid")?;
+    info!(elapsed = ?start.elapsed(), "Transcript is valid");
+    Ok(())
+}
+
+/// Verifies the transcript of a previous ceremony and seeds a new sequencer
+/// with it: the database gets its contributions and contributors, and the
+/// transcript file is written, so that `serve` adds new contributions on top.
+///
+/// # Errors
+///
+/// Returns an error if the transcript can not be read or is invalid, or the
+/// sequencer has a transcript already.
+pub async fn init(options: InitOptions) -> EyreResult<()> {
+    run_on_curve(options.curve)?;
+    let storage = storage_client(&options.storage).await?;
+    ensure!(
+        storage.read_transcript().await?.is_none(),
+        "the database holds a transcript already"
+    );
+    ensure!(
+        !options.transcript_file.exists(),
+        "{} exists already",
+        options.transcript_file.display()
+    );
+    let transcript = read_transcript(options.from_transcript).await?;
+    options
+        .ceremony_sizes
+        .validate_batch_transcript(&transcript)?;
+
+    info!(
+        num_participants = transcript.num_participants(),
+        "Verifying transcript"
+    );
+    let start = Instant::now();
+    let transcript = tokio::task::spawn_blocking(move || {
+        transcript.verify_self::<Engine>().map(|()| transcript)
+    })
+    .await?
+    .wrap_err("transcript is invalid")?;
+    info!(elapsed = ?start.elapsed(), "Transcript is valid, importing it");
+
+    storage.import_transcript(&transcript).await?;
+    // When the contributions were made is not part of the transcript.
+    let imported_at = Utc::now();
+    for (position, participant) in transcript.participant_ids.iter().enumerate().skip(1) {
+        let uid = identity::uid(participant);
+        if uid.is_empty() {
+            continue;
+        }
+        storage.register_identity(participant).await?;
+        storage
+            .insert_replicated_contributor(&uid, position, imported_at, imported_at)
+            .await?;
+    }
+    let num_participants = transcript.num_participants();
+    write_json_file(
+        options.transcript_file,
+        options.transcript_in_progress_file,
+        Arc::new(RwLock::new(transcript)),
+    )
+    .await?;
+    storage.close().await?;
+    info!(num_participants, "Sequencer initialized");
+    Ok(())
+}
+
+/// Converts a transcript file into one of the export formats.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read, or the export can not be
+/// created or written.
+pub async fn export(options: ExportOptions) -> EyreResult<()> {
+    run_on_curve(options.curve)?;
+    let transcript = read_transcript(options.transcript).await?;
+    let exported = match options.format.exporter(options.ceremony) {
+        Some(exporter) => exporter.export(&transcript)?,
+        None => serde_json::to_vec_pretty(&transcript)?,
+    };
+    match options.output {
+        Some(path) => tokio::fs::write(&path, exported)
+            .await
+            .wrap_err_with(|| format!("failed to write {}", path.display()))?,
+        None => std::io::stdout().lock().write_all(&exported)?,
+    }
+    Ok(())
+}
+
+/// Creates the database if needed and runs all pending migrations.
+///
+/// # Errors
+///
+/// Returns an error if the database can not be reached or migrated.
+pub async fn migrate(mut options: storage::Options) -> EyreResult<()> {
+    options.database_migrate = true;
+    let storage = storage_client(&options).await?;
+    storage.close().await?;
+    info!("Database is up to date");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+    use kzg_ceremony_crypto::signature::identity::Identity;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn verifies_and_exports_transcripts() {
+        let dir = tempdir().unwrap();
+        let mut transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        transcript
+            .verify_add::<Engine>(contribution, Identity::None)
+            .unwrap();
+        let path = dir.path().join("transcript.json");
+        std::fs::write(&path, serde_json::to_vec(&transcript).unwrap()).unwrap();
+
+        verify_transcript(VerifyTranscriptOptions {
+            transcript:     path.clone(),
+            ceremony_sizes: Some(CeremonySizes::parse_from_cmd("4,2").unwrap()),
+            curve:          Curve::Bls12_381,
+        })
+        .await
+        .unwrap();
+        assert!(verify_transcript(VerifyTranscriptOptions {
+            transcript:     path.clone(),
+            ceremony_sizes: Some(CeremonySizes::parse_from_cmd("8,2").unwrap()),
+            curve:          Curve::Bls12_381,
+        })
+        .await
+        .is_err());
+
+        transcript.transcripts[0].witness.pubkeys[1] = transcript.transcripts[0].powers.g2[0];
+        let tampered = dir.path().join("tampered.json");
+        std::fs::write(&tampered, serde_json::to_vec(&transcript).unwrap()).unwrap();
+        assert!(verify_transcript(VerifyTranscriptOptions {
+            transcript:     tampered,
+            ceremony_sizes: None,
+            curve:          Curve::Bls12_381,
+        })
+        .await
+        .is_err());
+
+        let output = dir.path().join("transcript.bin");
+        export(ExportOptions {
+            transcript: path,
+            format:     TranscriptFormatKind::Binary,
+            ceremony:   0,
+            output:     Some(output.clone()),
+            curve:      Curve::Bls12_381,
+        })
+        .await
+        .unwrap();
+        assert!(std::fs::read(&output)
+            .unwrap()
+            .starts_with(crate::transcript_format::BINARY_MAGIC));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn initializes_from_transcript() {
+        let dir = tempdir().unwrap();
+        let mut transcript = test_transcript();
+        let participant = Identity::Github {
+            id:       1234,
+            username: "test_user".to_string(),
+        };
+        let contribution = valid_contribution(&transcript, 1);
+        transcript
+            .verify_add::<Engine>(contribution, participant.clone())
+            .unwrap();
+        let previous = dir.path().join("previous.json");
+        std::fs::write(&previous, serde_json::to_vec(&transcript).unwrap()).unwrap();
+
+        let mut storage = crate::test_util::test_options().storage;
+        storage.database_url = format!("sqlite://{}", dir.path().join("storage.db").display());
+        let options = InitOptions {
+            from_transcript: previous,
+            transcript_file: dir.path().join("transcript.json"),
+            transcript_in_progress_file: dir.path().join("transcript.json.next"),
+            ceremony_sizes: CeremonySizes::parse_from_cmd("4,2").unwrap(),
+            curve: Curve::Bls12_381,
+            storage: storage.clone(),
+        };
+        init(options.clone()).await.unwrap();
+        assert!(init(options.clone()).await.is_err());
+
+        let written = read_transcript(options.transcript_file).await.unwrap();
+        assert_eq!(written, transcript);
+        let storage = storage_client(&storage).await.unwrap();
+        assert_eq!(storage.read_transcript().await.unwrap(), Some(transcript));
+        assert!(storage
+            .has_contributed(&identity::uid(&participant))
+            .await
+            .unwrap());
+    }
+}
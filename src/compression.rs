@@ -0,0 +1,268 @@
+//! Compressed transcripts and contribution uploads.
+//!
+//! `/info/current_state` and `/lobby/try_contribute` are compressed with
+//! brotli or gzip for clients that send `Accept-Encoding`. The transcript file
+//! is the largest response of the sequencer, so compressed copies of it are
+//! kept in memory and regenerated in the background after every contribution.
+//! Requests that come in before the copies of the latest transcript are ready
+//! are compressed on the fly. The contribution base is compressed ahead of
+//! time as well, see [`crate::handoff`]. Contributions may be uploaded with
+//! `Content-Encoding: gzip` or `br`, see [`crate::upload`].
+
+use crate::{
+    etag,
+    lobby::{LobbyEvent, SharedLobbyState},
+    SharedCeremonyStatus,
+};
+use axum::body::Bytes;
+use flate2::{write::GzEncoder, Compression};
+use http::{header::ACCEPT_ENCODING, HeaderMap};
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc, RwLock},
+};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// Brotli quality of the transcript copies, a compromise between their size
+/// and the time to regenerate them.
+const BROTLI_QUALITY: u32 = 6;
+const BROTLI_WINDOW: u32 = 22;
+const BROTLI_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// The encoding of a `Content-Encoding` header, if it is supported.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "br" => Some(Self::Brotli),
+            _ => None,
+        }
+    }
+
+    /// The encoding to answer with, preferring brotli.
+    #[must_use]
+    pub fn preferred(headers: &HeaderMap) -> Option<Self> {
+        let accepted = headers
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|coding| {
+                let mut params = coding.split(';').map(str::trim);
+                let name = params.next()?;
+                let refused = params.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .map_or(false, |q| q <= 0.0)
+                });
+                (!refused).then_some(name)
+            })
+            .collect::<Vec<_>>();
+        if accepted.contains(&"br") {
+            Some(Self::Brotli)
+        } else if accepted.contains(&"gzip") {
+            Some(Self::Gzip)
+        } else {
+            None
+        }
+    }
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Brotli => "br",
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if compressing fails.
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Self::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(
+                    Vec::new(),
+                    BROTLI_BUFFER_SIZE,
+                    BROTLI_QUALITY,
+                    BROTLI_WINDOW,
+                );
+                encoder.write_all(data)?;
+                Ok(encoder.into_inner())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Copies {
+    num_contributions: usize,
+    /// Tag of the uncompressed transcript, see [`crate::etag`].
+    etag:              String,
+    gzip:              Bytes,
+    brotli:            Bytes,
+}
+
+pub type SharedTranscriptCache = Arc<TranscriptCache>;
+
+/// Compressed copies of the transcript file.
+#[derive(Debug, Default)]
+pub struct TranscriptCache {
+    copies: RwLock<Option<Arc<Copies>>>,
+}
+
+impl TranscriptCache {
+    /// The copy of the transcript after `num_contributions` contributions, if
+    /// it is ready.
+    #[must_use]
+    pub fn get(&self, num_contributions: usize, encoding: Encoding) -> Option<Bytes> {
+        let copies = self.copies.read().unwrap().clone()?;
+        if copies.num_contributions != num_contributions {
+            return None;
+        }
+        Some(match encoding {
+            Encoding::Gzip => copies.gzip.clone(),
+            Encoding::Brotli => copies.brotli.clone(),
+        })
+    }
+
+    /// The tag of the transcript after `num_contributions` contributions, if
+    /// its copies are ready.
+    #[must_use]
+    pub fn etag(&self, num_contributions: usize) -> Option<String> {
+        let copies = self.copies.read().unwrap().clone()?;
+        (copies.num_contributions == num_contributions).then(|| copies.etag.clone())
+    }
+
+    /// Compresses the transcript file, which holds `num_contributions`
+    /// contributions. Copies of older transcripts do not replace newer ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or compressed.
+    pub async fn refresh(&self, path: PathBuf, num_contributions: usize) -> io::Result<()> {
+        let copies = tokio::task::spawn_blocking(move || {
+            let json = std::fs::read(&path)?;
+            let gzip = Encoding::Gzip.compress(&json)?.into();
+            let brotli = Encoding::Brotli.compress(&json)?.into();
+            Ok::<_, io::Error>(Copies {
+                num_contributions,
+                etag: etag::tag(&json),
+                gzip,
+                brotli,
+            })
+        })
+        .await
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))??;
+        let mut current = self.copies.write().unwrap();
+        if current.as_ref().map_or(true, |current| {
+            current.num_contributions <= num_contributions
+        }) {
+            *current = Some(Arc::new(copies));
+        }
+        Ok(())
+    }
+}
+
+/// Compresses the transcript file on startup and after every contribution.
+pub async fn refresh_on_contributions(
+    cache: SharedTranscriptCache,
+    transcript_file: PathBuf,
+    lobby_state: SharedLobbyState,
+    ceremony_status: SharedCeremonyStatus,
+) {
+    let mut events = lobby_state.subscribe();
+    let mut num_contributions = ceremony_status.load(Ordering::Relaxed);
+    loop {
+        match cache
+            .refresh(transcript_file.clone(), num_contributions)
+            .await
+        {
+            Ok(()) => info!(num_contributions, "Compressed the transcript"),
+            Err(error) => warn!(?error, "failed to compress the transcript"),
+        }
+        num_contributions = loop {
+            match events.recv().await {
+                Ok(LobbyEvent::ContributionVerified { num_contributions }) => {
+                    break num_contributions
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => break ceremony_status.load(Ordering::Relaxed),
+                Err(RecvError::Closed) => return,
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use http::HeaderValue;
+    use std::io::Read;
+    use tempfile::TempDir;
+
+    fn accepting(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn negotiates_encoding() {
+        assert_eq!(Encoding::preferred(&HeaderMap::new()), None);
+        assert_eq!(
+            Encoding::preferred(&accepting("gzip, deflate, br")),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(
+            Encoding::preferred(&accepting("br;q=0, gzip;q=0.5")),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(Encoding::preferred(&accepting("deflate")), None);
+        assert_eq!(Encoding::from_name("br"), Some(Encoding::Brotli));
+        assert_eq!(Encoding::from_name("compress"), None);
+    }
+
+    #[tokio::test]
+    async fn caches_compressed_transcripts() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("transcript.json");
+        let cache = TranscriptCache::default();
+        std::fs::write(&path, "[1]").unwrap();
+        cache.refresh(path.clone(), 1).await.unwrap();
+        std::fs::write(&path, "[1, 2]").unwrap();
+        cache.refresh(path.clone(), 2).await.unwrap();
+        std::fs::write(&path, "[]").unwrap();
+        cache.refresh(path.clone(), 0).await.unwrap();
+
+        assert_eq!(cache.get(1, Encoding::Gzip), None);
+        assert_eq!(cache.etag(1), None);
+        assert_eq!(cache.etag(2), Some(etag::tag(b"[1, 2]")));
+        let mut json = String::new();
+        GzDecoder::new(&cache.get(2, Encoding::Gzip).unwrap()[..])
+            .read_to_string(&mut json)
+            .unwrap();
+        assert_eq!(json, "[1, 2]");
+        let mut json = String::new();
+        brotli::Decompressor::new(&cache.get(2, Encoding::Brotli).unwrap()[..], 4096)
+            .read_to_string(&mut json)
+            .unwrap();
+        assert_eq!(json, "[1, 2]");
+    }
+}
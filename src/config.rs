@@ -0,0 +1,296 @@
+//! Options from a configuration file.
+//!
+//! With `--config sequencer.toml`, the options of `serve` are also read from
+//! a TOML file. Its keys are the names of the command line options, with
+//! either `-` or `_`, and may be grouped in tables, e.g. `[lobby]`; the tables
+//! are only for readability. Values are strings, numbers or booleans, lists
+//! are arrays. Options given on the command line or in the environment take
+//! precedence over the file.
+//!
+//! On SIGHUP the file is read again. The lobby options except
+//! `--lobby-strategy`, the rate limits and the eligibility options take effect
+//! right away, without dropping the lobby. The other options are only read on
+//! startup; changes to them are logged and wait for a restart. A file that
+//! fails to parse keeps the current options.
+
+use crate::{
+    eligibility::SharedScorer,
+    lobby::SharedLobbyState,
+    rate_limit::SharedRateLimiter,
+    reload_signal::{self, ReloadSignal},
+    Options as AppOptions,
+};
+use clap::{parser::ValueSource, ArgAction, CommandFactory, FromArgMatches, Parser};
+use eyre::{bail, eyre, Result as EyreResult, WrapErr};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+use toml::{value::Table, Value};
+use tracing::{error, info, warn};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// TOML file with further options of `serve`, read again on SIGHUP.
+    #[clap(long, env)]
+    pub config: Option<PathBuf>,
+}
+
+/// Services whose settings are replaced when the configuration is reloaded.
+pub struct LiveSettings {
+    pub lobbies:      Vec<SharedLobbyState>,
+    pub rate_limiter: SharedRateLimiter,
+    pub scorer:       SharedScorer,
+}
+
+/// Parses the command line of `serve` again together with `--config`, if it
+/// is set.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read or its options are invalid.
+pub fn load(options: AppOptions) -> EyreResult<AppOptions> {
+    match &options.config.config {
+        Some(path) => parse(&serve_args(), path),
+        None => Ok(options),
+    }
+}
+
+/// Parses `args`, starting with the binary name, together with the options in
+/// the file at `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file can not be read, names unknown options or the
+/// options are invalid.
+pub fn parse(args: &[OsString], path: &Path) -> EyreResult<AppOptions> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("failed to read config file {}", path.display()))?;
+    let table: Table = toml::from_str(&contents)
+        .wrap_err_with(|| format!("invalid config file {}", path.display()))?;
+    let command = AppOptions::command();
+    let given = command.clone().try_get_matches_from(args)?;
+
+    let mut entries = Vec::new();
+    flatten(table, &mut entries);
+    let mut file_args = Vec::new();
+    for (key, value) in entries {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .ok_or_else(|| eyre!("unknown option `{key}` in {}", path.display()))?;
+        if matches!(
+            given.value_source(arg.get_id().as_str()),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+        match (arg.get_action(), value) {
+            (ArgAction::SetTrue, Value::Boolean(true)) => file_args.push(format!("--{long}")),
+            (ArgAction::SetTrue, Value::Boolean(false)) => {}
+            (ArgAction::SetTrue, _) => bail!("`{key}` must be true or false"),
+            (_, Value::Array(values)) => {
+                let values = values
+                    .iter()
+                    .map(|value| scalar(&key, value))
+                    .collect::<EyreResult<Vec<_>>>()?;
+                file_args.push(format!("--{long}={}", values.join(",")));
+            }
+            (_, value) => file_args.push(format!("--{long}={}", scalar(&key, &value)?)),
+        }
+    }
+
+    let args = args[..1]
+        .iter()
+        .cloned()
+        .chain(file_args.into_iter().map(OsString::from))
+        .chain(args[1..].iter().cloned());
+    let matches = command
+        .try_get_matches_from(args)
+        .wrap_err_with(|| format!("invalid options in {}", path.display()))?;
+    Ok(AppOptions::from_arg_matches(&matches)?)
+}
+
+/// Reads the configuration file again on every reload signal. `running` are
+/// the options the server was started with.
+pub async fn reload_on_signal(running: AppOptions, live: LiveSettings) {
+    let path = match &running.config.config {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    let args = serve_args();
+    let mut signals = match ReloadSignal::new() {
+        Ok(signals) => signals,
+        Err(error) => {
+            error!(
+                ?error,
+                "Failed to listen for {}, the configuration will not be reloaded",
+                reload_signal::NAME
+            );
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        match reload(&running, &args, &path, &live) {
+            Ok(()) => info!(path = %path.display(), "Reloaded configuration"),
+            Err(error) => error!(?error, "Failed to reload configuration"),
+        }
+    }
+}
+
+/// Applies the options that can change without a restart, and warns about
+/// changes to the others.
+fn reload(
+    running: &AppOptions,
+    args: &[OsString],
+    path: &Path,
+    live: &LiveSettings,
+) -> EyreResult<()> {
+    let options = parse(args, path)?;
+    let options = options.mode.apply(options)?;
+    live.scorer.reload(&options.eligibility)?;
+    live.rate_limiter.set_options(options.rate_limit.clone());
+    for lobby in &live.lobbies {
+        lobby.set_options(options.lobby.clone());
+    }
+    let restart_only = AppOptions {
+        lobby: running.lobby.clone(),
+        rate_limit: running.rate_limit.clone(),
+        eligibility: running.eligibility.clone(),
+        ..options
+    };
+    if &restart_only != running {
+        warn!(
+            "Options other than the lobby, rate limit and eligibility options changed, they take \
+             effect after a restart"
+        );
+    }
+    Ok(())
+}
+
+/// The command line arguments after `serve`, preceded by the binary name.
+fn serve_args() -> Vec<OsString> {
+    let mut args = std::env::args_os();
+    let binary = args.next().unwrap_or_default();
+    let rest = args.collect::<Vec<_>>();
+    let start = rest
+        .iter()
+        .position(|arg| arg == "serve")
+        .map_or(0, |index| index + 1);
+    std::iter::once(binary)
+        .chain(rest[start..].iter().cloned())
+        .collect()
+}
+
+fn flatten(table: Table, entries: &mut Vec<(String, Value)>) {
+    for (key, value) in table {
+        match value {
+            Value::Table(table) => flatten(table, entries),
+            value => entries.push((key, value)),
+        }
+    }
+}
+
+fn scalar(key: &str, value: &Value) -> EyreResult<String> {
+    Ok(match value {
+        Value::String(value) => value.clone(),
+        Value::Integer(value) => value.to_string(),
+        Value::Float(value) => value.to_string(),
+        Value::Boolean(value) => value.to_string(),
+        Value::Datetime(value) => value.to_string(),
+        Value::Array(_) | Value::Table(_) => bail!("`{key}` must be a string, number or boolean"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eligibility::ScorerHandle, oauth::AuthProviderKind, rate_limit::RateLimiter};
+    use std::{io::Write, sync::Arc, time::Duration};
+    use tempfile::NamedTempFile;
+
+    fn args(extra: &[&str]) -> Vec<OsString> {
+        [
+            "kzg-ceremony-sequencer",
+            "--gh-client-secret",
+            "INVALID",
+            "--gh-client-id",
+            "INVALID",
+            "--eth-rpc-url",
+            "INVALID",
+            "--eth-client-secret",
+            "INVALID",
+            "--eth-client-id",
+            "INVALID",
+            "--database-url",
+            "sqlite://:memory:",
+        ]
+        .iter()
+        .chain(extra)
+        .map(OsString::from)
+        .collect()
+    }
+
+    fn config_file(contents: &str) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn reads_options_from_file() {
+        let file = config_file(
+            r#"
+            multi-contribution = true
+            auth_providers = ["github"]
+
+            [lobby]
+            compute_deadline = 300
+            max_lobby_size = 5
+
+            [rate_limit]
+            rate_limit_auth = 3
+            "#,
+        );
+        let options = parse(&args(&["--max-lobby-size", "7"]), file.path()).unwrap();
+        assert_eq!(options.lobby.compute_deadline, Duration::from_secs(300));
+        // The command line takes precedence.
+        assert_eq!(options.lobby.max_lobby_size, 7);
+        assert_eq!(options.rate_limit.rate_limit_auth, 3);
+        assert!(options.multi_contribution);
+        assert_eq!(options.auth_providers, vec![AuthProviderKind::Github]);
+
+        let file = config_file("max_lobby_sizes = 5");
+        assert!(parse(&args(&[]), file.path()).is_err());
+        let file = config_file("multi_contribution = \"yes\"");
+        assert!(parse(&args(&[]), file.path()).is_err());
+    }
+
+    #[test]
+    fn reloads_live_settings() {
+        let file = config_file("compute_deadline = 300\n");
+        let running = parse(&args(&[]), file.path()).unwrap();
+        let live = LiveSettings {
+            lobbies:      vec![SharedLobbyState::new(running.lobby.clone())],
+            rate_limiter: Arc::new(RateLimiter::new(running.rate_limit.clone())),
+            scorer:       Arc::new(ScorerHandle::new(&running.eligibility).unwrap()),
+        };
+
+        std::fs::write(
+            file.path(),
+            "compute_deadline = 300\nmax_lobby_size = 5\ntranscript_file = \"other.json\"\n",
+        )
+        .unwrap();
+        reload(&running, &args(&[]), file.path(), &live).unwrap();
+        let lobby = live.lobbies[0].options();
+        assert_eq!(lobby.compute_deadline, Duration::from_secs(300));
+        assert_eq!(lobby.max_lobby_size, 5);
+
+        // Invalid files keep the current options.
+        std::fs::write(file.path(), "max_lobby_size = \"many\"\n").unwrap();
+        assert!(reload(&running, &args(&[]), file.path(), &live).is_err());
+        assert_eq!(live.lobbies[0].options().max_lobby_size, 5);
+    }
+}
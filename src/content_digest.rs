@@ -0,0 +1,212 @@
+//! Integrity fields of contribution uploads, see RFC 9530.
+//!
+//! A client may send `Content-Digest` or `Repr-Digest` with the contribution,
+//! e.g. `Content-Digest: sha-256=:<base64>:`, to protect it against
+//! corruption on the way. Contributions are posted whole, so both fields are
+//! over the body as it is sent, compressed if it has a `Content-Encoding`.
+//! `sha-256` and `sha-512` are supported and other algorithms are ignored.
+//! The body is hashed while it is read, and a submission whose body does not
+//! match every supported digest it declares is rejected before it is
+//! verified, as is one whose fields declare no supported digest at all. Those
+//! rejections carry `Want-Content-Digest` and `Want-Repr-Digest` with the
+//! algorithms the sequencer prefers. The checked digest is echoed in the
+//! `content_digest` of the receipt, so that it is signed along with it.
+
+use base64::{decode as base64_decode, encode as base64_encode};
+use http::HeaderMap;
+use sha2::{Digest, Sha256, Sha512};
+
+pub const CONTENT_DIGEST: &str = "content-digest";
+pub const REPR_DIGEST: &str = "repr-digest";
+pub const WANT_CONTENT_DIGEST: &str = "want-content-digest";
+pub const WANT_REPR_DIGEST: &str = "want-repr-digest";
+
+/// The preferences sent in `Want-Content-Digest` and `Want-Repr-Digest`.
+pub const WANTED_DIGESTS: &str = "sha-256=10, sha-512=3";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "sha-256" => Some(Self::Sha256),
+            "sha-512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    const fn key(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha-256",
+            Self::Sha512 => "sha-512",
+        }
+    }
+}
+
+/// The digests a request declares, by algorithm.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeclaredDigests(Vec<(Algorithm, Vec<u8>)>);
+
+impl DeclaredDigests {
+    /// Parses the `Content-Digest` and `Repr-Digest` fields of `headers`.
+    /// `None` if neither is sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a field is not a dictionary of byte sequences, or
+    /// declares no supported algorithm.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Option<Self>, String> {
+        let mut declared = Vec::new();
+        let mut sent = false;
+        for name in [CONTENT_DIGEST, REPR_DIGEST] {
+            for value in headers.get_all(name) {
+                sent = true;
+                let value = value
+                    .to_str()
+                    .map_err(|_| format!("`{name}` is not ASCII"))?;
+                for member in value.split(',') {
+                    let (key, digest) = parse_member(member)
+                        .ok_or_else(|| format!("`{name}` is not a dictionary of digests"))?;
+                    if let Some(algorithm) = Algorithm::from_key(key) {
+                        declared.push((algorithm, digest));
+                    }
+                }
+            }
+        }
+        if !sent {
+            return Ok(None);
+        }
+        if declared.is_empty() {
+            return Err("no supported digest algorithm, use sha-256 or sha-512".to_string());
+        }
+        Ok(Some(Self(declared)))
+    }
+
+    /// A hasher for the declared algorithms.
+    #[must_use]
+    pub fn hasher(&self) -> BodyHasher {
+        let has = |algorithm| self.0.iter().any(|(declared, _)| *declared == algorithm);
+        BodyHasher {
+            sha256: has(Algorithm::Sha256).then(Sha256::new),
+            sha512: has(Algorithm::Sha512).then(Sha512::new),
+        }
+    }
+
+    /// Checks the declared digests against those of the body.
+    ///
+    /// # Errors
+    ///
+    /// Returns the algorithm of the first digest that does not match.
+    pub fn verify(&self, hasher: BodyHasher) -> Result<ContentDigest, &'static str> {
+        let sha256 = hasher.sha256.map(|hasher| hasher.finalize().to_vec());
+        let sha512 = hasher.sha512.map(|hasher| hasher.finalize().to_vec());
+        for (algorithm, declared) in &self.0 {
+            let actual = match algorithm {
+                Algorithm::Sha256 => sha256.as_ref(),
+                Algorithm::Sha512 => sha512.as_ref(),
+            };
+            if actual != Some(declared) {
+                return Err(algorithm.key());
+            }
+        }
+        let (algorithm, digest) = sha256
+            .map(|digest| (Algorithm::Sha256, digest))
+            .or_else(|| sha512.map(|digest| (Algorithm::Sha512, digest)))
+            .expect("at least one digest is declared");
+        Ok(ContentDigest(format!(
+            "{}=:{}:",
+            algorithm.key(),
+            base64_encode(digest)
+        )))
+    }
+}
+
+/// Parses a dictionary member `key=:base64:`, ignoring its parameters.
+fn parse_member(member: &str) -> Option<(&str, Vec<u8>)> {
+    let (key, value) = member.trim().split_once('=')?;
+    let value = value.split(';').next()?.trim();
+    let encoded = value.strip_prefix(':')?.strip_suffix(':')?;
+    Some((key.trim(), base64_decode(encoded).ok()?))
+}
+
+/// Hashes a body as it is read, with the algorithms it declares.
+#[derive(Clone, Default)]
+pub struct BodyHasher {
+    sha256: Option<Sha256>,
+    sha512: Option<Sha512>,
+}
+
+impl BodyHasher {
+    pub fn update(&mut self, chunk: &[u8]) {
+        if let Some(hasher) = &mut self.sha256 {
+            hasher.update(chunk);
+        }
+        if let Some(hasher) = &mut self.sha512 {
+            hasher.update(chunk);
+        }
+    }
+}
+
+/// The checked digest of an upload, as a `Content-Digest` field value. The
+/// SHA-256 if it was declared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentDigest(pub String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    fn field(algorithm: &str, digest: &[u8]) -> String {
+        format!("{algorithm}=:{}:", base64_encode(digest))
+    }
+
+    #[test]
+    fn verifies_declared_digests() {
+        let body = b"{\"contributions\": []}";
+        let sha256 = Sha256::digest(body);
+        let sha512 = Sha512::digest(body);
+        let check = |headers: &HeaderMap| {
+            let declared = DeclaredDigests::from_headers(headers).unwrap().unwrap();
+            let mut hasher = declared.hasher();
+            for chunk in body.chunks(5) {
+                hasher.update(chunk);
+            }
+            declared.verify(hasher)
+        };
+
+        let value = format!("{}, md5=:AAAA:", field("sha-512", &sha512));
+        assert_eq!(
+            check(&headers(CONTENT_DIGEST, &value)),
+            Ok(ContentDigest(field("sha-512", &sha512)))
+        );
+        let value = format!(
+            "{};p=1, {}",
+            field("sha-256", &sha256),
+            field("sha-512", &sha512)
+        );
+        assert_eq!(
+            check(&headers(REPR_DIGEST, &value)),
+            Ok(ContentDigest(field("sha-256", &sha256)))
+        );
+        let value = field("sha-256", &Sha256::digest(b"corrupted"));
+        assert_eq!(check(&headers(CONTENT_DIGEST, &value)), Err("sha-256"));
+    }
+
+    #[test]
+    fn rejects_unusable_fields() {
+        assert_eq!(DeclaredDigests::from_headers(&HeaderMap::new()), Ok(None));
+        assert!(DeclaredDigests::from_headers(&headers(CONTENT_DIGEST, "md5=:AAAA:")).is_err());
+        assert!(DeclaredDigests::from_headers(&headers(CONTENT_DIGEST, "sha-256=abc")).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! Compact binary encoding of batch contributions.
+//!
+//! The JSON encoding spells every point out in hex, which doubles its size. A
+//! contribution sent with `Content-Type: application/octet-stream` and a
+//! contribution base requested with `Accept: application/octet-stream` use
+//! this encoding instead: the magic `KZGC`, a version byte and the number of
+//! contributions, followed for each contribution by the number of G1 and G2
+//! powers, the compressed powers, the compressed `pot_pubkey` and the BLS
+//! signature, and finally the ECDSA signature. Signatures are preceded by a
+//! byte that is 1 if the signature is present and 0 if it is not. All integers
+//! are little endian `u32`. Points have the size of the `--curve`, and are
+//! checked when the contribution is validated, like those of JSON
+//! contributions.
+//!
+//! Version 2 appends the entropy attestation: a presence byte, followed by the
+//! `u32` length and the UTF-8 bytes of the attestation. Contributions without
+//! one are encoded as version 1.
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+};
+use ethers_core::types::Signature as EthSignature;
+use http::header::ACCEPT;
+use kzg_ceremony_crypto::{
+    curve,
+    signature::{BlsSignature, EcdsaSignature},
+    BatchContribution, Contribution, Powers, G1, G2,
+};
+use std::convert::Infallible;
+use thiserror::Error;
+
+/// Magic bytes starting a contribution in the binary encoding.
+pub const CONTRIBUTION_MAGIC: &[u8; 4] = b"KZGC";
+pub const CONTRIBUTION_VERSION: u8 = 1;
+/// Version of contributions ending with an entropy attestation.
+pub const ATTESTED_CONTRIBUTION_VERSION: u8 = 2;
+
+pub const BINARY_CONTENT_TYPE: &str = "application/octet-stream";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("not a binary contribution")]
+    InvalidMagic,
+    #[error("unsupported encoding version {0}")]
+    UnsupportedVersion(u8),
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("invalid presence flag {0}")]
+    InvalidFlag(u8),
+    #[error("invalid ecdsa signature")]
+    InvalidEcdsaSignature,
+    #[error("entropy attestation is not valid UTF-8")]
+    InvalidEntropyAttestation,
+    #[error("{0} bytes after the end of the contribution")]
+    TrailingBytes(usize),
+}
+
+/// Encoding of the contribution base sent to the participant, from the
+/// `Accept` header of the request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ContributionEncoding {
+    #[default]
+    Json,
+    Binary,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for ContributionEncoding {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let accepts_binary = req
+            .headers()
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|range| range.split(';').next().map(str::trim) == Some(BINARY_CONTENT_TYPE));
+        Ok(if accepts_binary {
+            Self::Binary
+        } else {
+            Self::Json
+        })
+    }
+}
+
+#[must_use]
+pub fn encode(contribution: &BatchContribution) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(CONTRIBUTION_MAGIC);
+    out.push(if contribution.entropy_attestation.is_some() {
+        ATTESTED_CONTRIBUTION_VERSION
+    } else {
+        CONTRIBUTION_VERSION
+    });
+    push_u32(&mut out, contribution.contributions.len());
+    for contribution in &contribution.contributions {
+        push_u32(&mut out, contribution.powers.g1.len());
+        push_u32(&mut out, contribution.powers.g2.len());
+        for point in &contribution.powers.g1 {
+            out.extend_from_slice(point.encoded());
+        }
+        for point in &contribution.powers.g2 {
+            out.extend_from_slice(point.encoded());
+        }
+        out.extend_from_slice(contribution.pot_pubkey.encoded());
+        match &contribution.bls_signature.0 {
+            Some(signature) => {
+                out.push(1);
+                out.extend_from_slice(signature.encoded());
+            }
+            None => out.push(0),
+        }
+    }
+    match contribution.ecdsa_signature.0 {
+        Some(signature) => {
+            out.push(1);
+            out.extend_from_slice(&<[u8; 65]>::from(signature));
+        }
+        None => out.push(0),
+    }
+    if let Some(attestation) = &contribution.entropy_attestation {
+        out.push(1);
+        push_u32(&mut out, attestation.len());
+        out.extend_from_slice(attestation.as_bytes());
+    }
+    out
+}
+
+/// # Errors
+///
+/// Returns an error if `bytes` is not a contribution in the binary encoding.
+pub fn decode(bytes: &[u8]) -> Result<BatchContribution, DecodeError> {
+    let mut reader = Reader(bytes);
+    if reader.take(CONTRIBUTION_MAGIC.len())? != CONTRIBUTION_MAGIC {
+        return Err(DecodeError::InvalidMagic);
+    }
+    let version = reader.u8()?;
+    if version != CONTRIBUTION_VERSION && version != ATTESTED_CONTRIBUTION_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let (g1_size, g2_size) = (curve().g1_size(), curve().g2_size());
+    let count = reader.u32()?;
+    let mut contributions = Vec::new();
+    for _ in 0..count {
+        let num_g1 = reader.u32()?;
+        let num_g2 = reader.u32()?;
+        let g1 = reader.points(num_g1, g1_size, G1::from_encoded)?;
+        let g2 = reader.points(num_g2, g2_size, G2::from_encoded)?;
+        let pot_pubkey = reader.point(g2_size, G2::from_encoded)?;
+        let bls_signature =
+            BlsSignature(reader.optional(|reader| reader.point(g1_size, G1::from_encoded))?);
+        contributions.push(Contribution {
+            powers: Powers {
+                g1: g1.into(),
+                g2: g2.into(),
+            },
+            pot_pubkey,
+            bls_signature,
+        });
+    }
+    let ecdsa_signature = reader.optional(|reader| {
+        let bytes: [u8; 65] = reader.array()?;
+        EthSignature::try_from(&bytes[..]).map_err(|_| DecodeError::InvalidEcdsaSignature)
+    })?;
+    let entropy_attestation = if version == ATTESTED_CONTRIBUTION_VERSION {
+        reader.optional(|reader| {
+            let len = reader.u32()?;
+            String::from_utf8(reader.take(len)?.to_vec())
+                .map_err(|_| DecodeError::InvalidEntropyAttestation)
+        })?
+    } else {
+        None
+    };
+    if !reader.0.is_empty() {
+        return Err(DecodeError::TrailingBytes(reader.0.len()));
+    }
+    Ok(BatchContribution {
+        contributions,
+        ecdsa_signature: EcdsaSignature(ecdsa_signature),
+        entropy_attestation,
+    })
+}
+
+fn push_u32(out: &mut Vec<u8>, value: usize) {
+    let value = u32::try_from(value).expect("contribution sizes fit in u32");
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn point<T>(&mut self, size: usize, point: fn(&[u8]) -> Option<T>) -> Result<T, DecodeError> {
+        Ok(point(self.take(size)?).expect("took a point"))
+    }
+
+    fn points<T>(
+        &mut self,
+        count: usize,
+        size: usize,
+        point: fn(&[u8]) -> Option<T>,
+    ) -> Result<Vec<T>, DecodeError> {
+        let len = count.checked_mul(size).ok_or(DecodeError::UnexpectedEnd)?;
+        Ok(self
+            .take(len)?
+            .chunks_exact(size)
+            .map(|chunk| point(chunk).expect("chunks of a point"))
+            .collect())
+    }
+
+    fn optional<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Option<T>, DecodeError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            flag => Err(DecodeError::InvalidFlag(flag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+    use http::Request;
+
+    #[test]
+    fn round_trips_contributions() {
+        let mut attested = valid_contribution(&test_transcript(), 2);
+        attested.entropy_attestation = Some("drand round 2409021".to_string());
+        for contribution in [
+            test_transcript().contribution(),
+            valid_contribution(&test_transcript(), 1),
+            attested,
+        ] {
+            let bytes = encode(&contribution);
+            let json = serde_json::to_vec(&contribution).unwrap();
+            assert!(bytes.len() < json.len() / 2);
+            assert_eq!(decode(&bytes), Ok(contribution));
+
+            assert_eq!(
+                decode(&bytes[..bytes.len() - 1]),
+                Err(DecodeError::UnexpectedEnd)
+            );
+            let mut trailing = bytes.clone();
+            trailing.push(0);
+            assert_eq!(decode(&trailing), Err(DecodeError::TrailingBytes(1)));
+        }
+        assert_eq!(decode(b"KZGT\x01"), Err(DecodeError::InvalidMagic));
+        assert_eq!(decode(b"KZGC\x03"), Err(DecodeError::UnsupportedVersion(3)));
+        assert_eq!(
+            decode(b"KZGC\x01\xff\xff\xff\xff\xff\xff\xff\xff"),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+
+    #[tokio::test]
+    async fn negotiates_encoding() {
+        let encoding = |accept: Option<&str>| async move {
+            let mut builder = Request::builder();
+            if let Some(accept) = accept {
+                builder = builder.header(ACCEPT, accept);
+            }
+            let mut req = RequestParts::new(builder.body(()).unwrap());
+            ContributionEncoding::from_request(&mut req).await.unwrap()
+        };
+        assert_eq!(encoding(None).await, ContributionEncoding::Json);
+        assert_eq!(
+            encoding(Some("application/json")).await,
+            ContributionEncoding::Json
+        );
+        assert_eq!(
+            encoding(Some("application/octet-stream;q=1, */*")).await,
+            ContributionEncoding::Binary
+        );
+    }
+}
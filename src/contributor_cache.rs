@@ -0,0 +1,86 @@
+use crate::metrics::CONTRIBUTOR_CACHE_LOOKUPS;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use tokio::time::Instant;
+
+/// Number of cached answers above which expired ones are dropped. If all of
+/// them are still fresh, the whole cache is.
+const MAX_ENTRIES: usize = 100_000;
+
+/// Remembers whether uids have contributed, so that sign-in spikes do not
+/// each cost a database query.
+///
+/// Answers are kept for `ttl`. Contributions recorded through this sequencer
+/// update the cache right away, so `ttl` only bounds how long a contribution
+/// recorded by another instance on the same database goes unnoticed. A `ttl`
+/// of zero disables the cache.
+#[derive(Debug)]
+pub struct ContributorCache {
+    ttl:     Duration,
+    entries: Mutex<HashMap<String, (bool, Instant)>>,
+}
+
+impl ContributorCache {
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// The cached answer for `uid`, if there is a fresh one.
+    #[must_use]
+    pub fn get(&self, uid: &str) -> Option<bool> {
+        if self.ttl.is_zero() {
+            return None;
+        }
+        let cached = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(uid)
+            .filter(|(_, cached_at)| cached_at.elapsed() < self.ttl)
+            .map(|(contributed, _)| *contributed);
+        CONTRIBUTOR_CACHE_LOOKUPS
+            .with_label_values(&[if cached.is_some() { "hit" } else { "miss" }])
+            .inc();
+        cached
+    }
+
+    pub fn insert(&self, uid: &str, contributed: bool) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(uid) {
+            entries.retain(|_, (_, cached_at)| cached_at.elapsed() < self.ttl);
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+        }
+        entries.insert(uid.to_string(), (contributed, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn expires_answers() {
+        tokio::time::pause();
+        let cache = ContributorCache::new(Duration::from_secs(10));
+        assert_eq!(cache.get("git|1|alice"), None);
+        cache.insert("git|1|alice", false);
+        assert_eq!(cache.get("git|1|alice"), Some(false));
+        cache.insert("git|1|alice", true);
+        assert_eq!(cache.get("git|1|alice"), Some(true));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(cache.get("git|1|alice"), None);
+
+        let disabled = ContributorCache::new(Duration::ZERO);
+        disabled.insert("git|1|alice", true);
+        assert_eq!(disabled.get("git|1|alice"), None);
+    }
+}
@@ -0,0 +1,494 @@
+//! Which browser origins may call the API.
+//!
+//! `--cors-origins` lists the allowed origins. `*` allows any origin, and a
+//! pattern like `https://*.example.org` any subdomain of `example.org`, but
+//! not `example.org` itself. Allowed origins may use `--cors-methods` and send
+//! `--cors-headers`. `--cors-rules-file` is a TOML file of origins with their
+//! own methods and headers, which are checked before `--cors-origins`:
+//!
+//! ```toml
+//! [[origin]]
+//! origin = "https://admin.example.org"
+//! methods = ["GET", "POST", "PUT", "DELETE"]
+//! headers = ["authorization", "content-type", "x-signature", "x-signature-timestamp", "x-signature-nonce", "x-content-sha256"]
+//!
+//! [[origin]]
+//! origin = "https://*.ceremony.example.org"
+//! methods = ["GET", "POST"]
+//! ```
+//!
+//! Methods and headers left out of a rule are those of `--cors-methods` and
+//! `--cors-headers`. Preflight requests of other origins, or for other
+//! methods or headers, are answered with `403 Forbidden`. Other requests are
+//! handled either way, only without the headers that let a browser read the
+//! response.
+
+use axum::{
+    http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+            ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+            ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+        },
+        HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use clap::Parser;
+use eyre::{bail, eyre, Result as EyreResult, WrapErr};
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Origins allowed to call the API from a browser, separated by commas.
+    /// `*` allows any origin, `https://*.example.org` any subdomain of
+    /// `example.org`.
+    #[clap(long, env, value_delimiter = ',', default_value = "*")]
+    pub cors_origins: Vec<String>,
+
+    /// Methods allowed origins may use, separated by commas, or `*` for any.
+    #[clap(long, env, value_delimiter = ',', default_value = "*")]
+    pub cors_methods: Vec<String>,
+
+    /// Request headers allowed origins may send, separated by commas, or `*`
+    /// for any.
+    #[clap(long, env, value_delimiter = ',', default_value = "*")]
+    pub cors_headers: Vec<String>,
+
+    /// Seconds browsers may cache the answer to a preflight request. 0 leaves
+    /// it to the browser.
+    #[clap(long, env, default_value = "0")]
+    pub cors_max_age: u64,
+
+    /// TOML file of origins with their own methods and headers.
+    #[clap(long, env)]
+    pub cors_rules_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesConfig {
+    #[serde(default)]
+    origin: Vec<RuleConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    origin:  String,
+    methods: Option<Vec<String>>,
+    headers: Option<Vec<String>>,
+}
+
+/// Origins a rule applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum OriginPattern {
+    Any,
+    Exact(String),
+    /// Any origin `<scheme>://<subdomain><suffix>`, where `suffix` starts
+    /// with a dot.
+    Subdomains {
+        scheme: String,
+        suffix: String,
+    },
+}
+
+impl OriginPattern {
+    fn parse(pattern: &str) -> EyreResult<Self> {
+        let pattern = pattern.trim().trim_end_matches('/').to_ascii_lowercase();
+        if pattern == "*" {
+            return Ok(Self::Any);
+        }
+        let (scheme, host) = pattern
+            .split_once("://")
+            .ok_or_else(|| eyre!("origin {pattern} has no scheme, like https://"))?;
+        if let Some(domain) = host.strip_prefix('*') {
+            if !domain.starts_with('.') || domain.contains('*') {
+                bail!("origin {pattern} may only start with *.");
+            }
+            return Ok(Self::Subdomains {
+                scheme: format!("{scheme}://"),
+                suffix: domain.to_string(),
+            });
+        }
+        if pattern.contains('*') || host.contains('/') {
+            bail!("invalid origin {pattern}");
+        }
+        Ok(Self::Exact(pattern))
+    }
+
+    /// Whether `origin`, in lower case, is one of the pattern's.
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(exact) => exact == origin,
+            Self::Subdomains { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .map_or(false, |subdomain| {
+                    !subdomain.is_empty()
+                        && !subdomain.starts_with('.')
+                        && subdomain
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                }),
+        }
+    }
+}
+
+/// Methods or headers of a rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Allowed<T> {
+    Any,
+    List(Vec<T>),
+}
+
+impl<T: PartialEq> Allowed<T> {
+    fn parse(values: &[String], parse: impl Fn(&str) -> EyreResult<T>) -> EyreResult<Self> {
+        let values = values
+            .iter()
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>();
+        if values.contains(&"*") {
+            return Ok(Self::Any);
+        }
+        values
+            .into_iter()
+            .map(parse)
+            .collect::<EyreResult<_>>()
+            .map(Self::List)
+    }
+
+    fn allows(&self, value: &T) -> bool {
+        match self {
+            Self::Any => true,
+            Self::List(values) => values.contains(value),
+        }
+    }
+}
+
+fn parse_method(method: &str) -> EyreResult<Method> {
+    Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+        .map_err(|_| eyre!("invalid method {method}"))
+}
+
+fn parse_header(header: &str) -> EyreResult<HeaderName> {
+    HeaderName::from_bytes(header.to_ascii_lowercase().as_bytes())
+        .map_err(|_| eyre!("invalid header {header}"))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Rule {
+    origin:  OriginPattern,
+    methods: Allowed<Method>,
+    headers: Allowed<HeaderName>,
+}
+
+pub type SharedCors = Arc<Cors>;
+
+pub struct Cors {
+    /// Those of `--cors-rules-file` first.
+    rules:   Vec<Rule>,
+    max_age: Option<HeaderValue>,
+}
+
+impl Cors {
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        let methods = Allowed::parse(&options.cors_methods, parse_method)
+            .wrap_err("invalid --cors-methods")?;
+        let headers = Allowed::parse(&options.cors_headers, parse_header)
+            .wrap_err("invalid --cors-headers")?;
+        let mut rules = match &options.cors_rules_file {
+            Some(path) => Self::rules_from_file(path, &methods, &headers)?,
+            None => Vec::new(),
+        };
+        for origin in &options.cors_origins {
+            if origin.trim().is_empty() {
+                continue;
+            }
+            rules.push(Rule {
+                origin:  OriginPattern::parse(origin).wrap_err("invalid --cors-origins")?,
+                methods: methods.clone(),
+                headers: headers.clone(),
+            });
+        }
+        Ok(Self {
+            rules,
+            max_age: (options.cors_max_age > 0).then(|| HeaderValue::from(options.cors_max_age)),
+        })
+    }
+
+    fn rules_from_file(
+        path: &Path,
+        methods: &Allowed<Method>,
+        headers: &Allowed<HeaderName>,
+    ) -> EyreResult<Vec<Rule>> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read CORS rules {}", path.display()))?;
+        let config: RulesConfig = toml::from_str(&contents)
+            .wrap_err_with(|| format!("invalid CORS rules in {}", path.display()))?;
+        config
+            .origin
+            .into_iter()
+            .map(|rule| {
+                Ok(Rule {
+                    origin:  OriginPattern::parse(&rule.origin)?,
+                    methods: match rule.methods {
+                        Some(list) => Allowed::parse(&list, parse_method)?,
+                        None => methods.clone(),
+                    },
+                    headers: match rule.headers {
+                        Some(list) => Allowed::parse(&list, parse_header)?,
+                        None => headers.clone(),
+                    },
+                })
+            })
+            .collect::<EyreResult<_>>()
+            .wrap_err_with(|| format!("invalid CORS rules in {}", path.display()))
+    }
+
+    /// The first rule allowing `origin`.
+    fn rule(&self, origin: &str) -> Option<&Rule> {
+        let origin = origin.to_ascii_lowercase();
+        self.rules.iter().find(|rule| rule.origin.matches(&origin))
+    }
+
+    /// The answer to a preflight request from `origin`.
+    fn preflight(&self, origin: &HeaderValue, headers: &HeaderMap) -> Response {
+        let rule = match origin.to_str().ok().and_then(|origin| self.rule(origin)) {
+            Some(rule) => rule,
+            None => return StatusCode::FORBIDDEN.into_response(),
+        };
+        let method = match headers
+            .get(ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok())
+        {
+            Some(method) if rule.methods.allows(&method) => method,
+            _ => return StatusCode::FORBIDDEN.into_response(),
+        };
+        let requested = headers
+            .get_all(ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .map(|header| parse_header(header).ok())
+            .collect::<Option<Vec<_>>>();
+        let requested = match requested {
+            Some(requested) if requested.iter().all(|header| rule.headers.allows(header)) => {
+                requested
+            }
+            _ => return StatusCode::FORBIDDEN.into_response(),
+        };
+
+        let mut response = StatusCode::OK.into_response();
+        let response_headers = response.headers_mut();
+        allow_origin(response_headers, origin);
+        let methods = match &rule.methods {
+            Allowed::Any => method.to_string(),
+            Allowed::List(methods) => join(methods.iter().map(Method::as_str)),
+        };
+        let allowed_headers = match &rule.headers {
+            Allowed::Any => join(requested.iter().map(HeaderName::as_str)),
+            Allowed::List(headers) => join(headers.iter().map(HeaderName::as_str)),
+        };
+        for (name, value) in [
+            (ACCESS_CONTROL_ALLOW_METHODS, methods),
+            (ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers),
+        ] {
+            if value.is_empty() {
+                continue;
+            }
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                response_headers.insert(name, value);
+            }
+        }
+        if let Some(max_age) = &self.max_age {
+            response_headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.clone());
+        }
+        response
+    }
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    values.collect::<Vec<_>>().join(",")
+}
+
+fn allow_origin(headers: &mut HeaderMap, origin: &HeaderValue) {
+    headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+    headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from_static("*"));
+    headers.append(VARY, HeaderValue::from_static("origin"));
+}
+
+/// Middleware answering preflight requests and adding the CORS headers to
+/// the responses to allowed origins.
+pub async fn handle_cors<B>(cors: SharedCors, request: Request<B>, next: Next<B>) -> Response {
+    let origin = match request.headers().get(ORIGIN) {
+        Some(origin) => origin.clone(),
+        None => return next.run(request).await,
+    };
+    if request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+    {
+        return cors.preflight(&origin, request.headers());
+    }
+    let rule = origin.to_str().ok().and_then(|origin| cors.rule(origin));
+    let allowed = rule.map_or(false, |rule| rule.methods.allows(request.method()));
+    let mut response = next.run(request).await;
+    if allowed {
+        allow_origin(response.headers_mut(), &origin);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware::from_fn, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn matches_origins() {
+        let subdomains = OriginPattern::parse("https://*.example.org").unwrap();
+        assert!(subdomains.matches("https://app.example.org"));
+        assert!(subdomains.matches("https://a.b.example.org"));
+        assert!(!subdomains.matches("https://example.org"));
+        assert!(!subdomains.matches("https://evil-example.org"));
+        assert!(!subdomains.matches("http://app.example.org"));
+        assert!(!subdomains.matches("https://app.example.org.evil.com"));
+        assert!(!subdomains.matches("https://a/b.example.org"));
+
+        let exact = OriginPattern::parse("https://Ceremony.example.org/").unwrap();
+        assert!(exact.matches("https://ceremony.example.org"));
+        assert!(!exact.matches("https://ceremony.example.org:8443"));
+
+        assert_eq!(OriginPattern::parse("*").unwrap(), OriginPattern::Any);
+        for invalid in [
+            "example.org",
+            "https://a.*.example.org",
+            "https://*example.org",
+        ] {
+            assert!(OriginPattern::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[tokio::test]
+    async fn applies_rules_per_origin() {
+        let dir = tempfile::tempdir().unwrap();
+        let rules = dir.path().join("cors.toml");
+        std::fs::write(
+            &rules,
+            r#"
+            [[origin]]
+            origin = "https://admin.example.org"
+            methods = ["GET", "DELETE"]
+            headers = ["authorization"]
+            "#,
+        )
+        .unwrap();
+        let options = Options::parse_from([
+            "cors",
+            "--cors-origins",
+            "https://*.example.org",
+            "--cors-methods",
+            "GET,POST",
+            "--cors-max-age",
+            "600",
+            "--cors-rules-file",
+            rules.to_str().unwrap(),
+        ]);
+        let cors = Arc::new(Cors::new(&options).unwrap());
+        let app = Router::new()
+            .route("/info/status", get(|| async { "ok" }))
+            .layer(from_fn(move |request: Request<Body>, next: Next<Body>| {
+                handle_cors(cors.clone(), request, next)
+            }));
+        let preflight = |origin: &str, method: &str, headers: &str| {
+            Request::options("/info/status")
+                .header(ORIGIN, origin)
+                .header(ACCESS_CONTROL_REQUEST_METHOD, method)
+                .header(ACCESS_CONTROL_REQUEST_HEADERS, headers)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(preflight(
+                "https://app.example.org",
+                "POST",
+                "content-type, x-custom",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.org"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET,POST");
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type,x-custom"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+
+        for (origin, method, headers) in [
+            ("https://admin.example.org", "POST", ""),
+            ("https://admin.example.org", "DELETE", "content-type"),
+            ("https://app.example.org", "DELETE", ""),
+            ("https://example.com", "GET", ""),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(preflight(origin, method, headers))
+                .await
+                .unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{origin} {method}"
+            );
+        }
+        let response = app
+            .clone()
+            .oneshot(preflight(
+                "https://admin.example.org",
+                "DELETE",
+                "Authorization",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = |origin: &str| {
+            Request::get("/info/status")
+                .header(ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app
+            .clone()
+            .oneshot(request("https://app.example.org"))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.org"
+        );
+        let response = app.oneshot(request("https://example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
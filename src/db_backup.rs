@@ -0,0 +1,329 @@
+//! Backups of the database while the sequencer uses it.
+//!
+//! Copying the Sqlite file of a running sequencer can catch it halfway
+//! through a write, and misses what is still in the write-ahead log. Backups
+//! are therefore made with `VACUUM INTO`, which writes a consistent snapshot
+//! of the database to a new file without blocking writers for long. Postgres
+//! databases are dumped with `pg_dump --format=custom`, which must be on the
+//! `PATH`, and restored with `pg_restore`.
+//!
+//! `db backup --out <file>` makes one backup. With `--db-backup-interval` the
+//! sequencer makes them itself, into `--db-backup-dir`, and then prunes the
+//! backups there by `--db-backup-retention` and `--db-backup-max-age-days`.
+//! `db prune` does only the latter, e.g. for backups made by cron.
+
+use crate::{
+    metrics::DB_BACKUPS,
+    storage::{self, storage_client, PersistentStorage},
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::{Parser, Subcommand};
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use std::{
+    fs,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use tracing::{error, info};
+
+/// Names of backups in `--db-backup-dir` are this prefix, the time of the
+/// backup in this format and the extension of the backend.
+const PREFIX: &str = "storage-";
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const SQLITE_EXTENSION: &str = "db";
+const POSTGRES_EXTENSION: &str = "dump";
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Seconds between backups of the database into `--db-backup-dir`. 0
+    /// disables them.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "0")]
+    pub db_backup_interval: Duration,
+
+    /// Directory the backups are written to.
+    #[clap(long, env, default_value = "./db_backups")]
+    pub db_backup_dir: PathBuf,
+
+    #[clap(flatten)]
+    pub retention: RetentionOptions,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct RetentionOptions {
+    /// Number of the newest backups that are kept. 0 keeps all.
+    #[clap(long, env, default_value = "7")]
+    pub db_backup_retention: usize,
+
+    /// Age in days after which backups are removed, even among the newest
+    /// `--db-backup-retention`. 0 keeps them regardless of their age.
+    #[clap(long, env, default_value = "0")]
+    pub db_backup_max_age_days: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Write a backup of the database, which may be in use.
+    Backup(Box<BackupOptions>),
+
+    /// Remove the backups in `--db-backup-dir` that the retention options do
+    /// not keep.
+    Prune(PruneOptions),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct BackupOptions {
+    /// File to write the backup to. Must not exist.
+    #[clap(long)]
+    pub out: PathBuf,
+
+    #[clap(flatten)]
+    pub storage: storage::Options,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct PruneOptions {
+    /// Directory with the backups.
+    #[clap(long, env, default_value = "./db_backups")]
+    pub db_backup_dir: PathBuf,
+
+    #[clap(flatten)]
+    pub retention: RetentionOptions,
+}
+
+/// Runs a `db` subcommand.
+///
+/// # Errors
+///
+/// Returns an error if the backup can not be made, or the backups can not be
+/// pruned.
+pub async fn run(command: Command) -> EyreResult<()> {
+    match command {
+        Command::Backup(options) => {
+            let mut storage_options = options.storage.clone();
+            // Backing up does not migrate.
+            storage_options.database_migrate = false;
+            let storage = match storage_options.sqlite_path() {
+                Some(_) => Some(storage_client(&storage_options).await?),
+                None => None,
+            };
+            let result = backup(&storage_options, storage.as_ref(), &options.out).await;
+            if let Some(storage) = storage {
+                storage.close().await?;
+            }
+            result?;
+            info!(out = %options.out.display(), "Database backed up");
+            Ok(())
+        }
+        Command::Prune(options) => {
+            let removed = prune(&options.db_backup_dir, &options.retention, Utc::now())?;
+            info!(removed, dir = %options.db_backup_dir.display(), "Pruned database backups");
+            Ok(())
+        }
+    }
+}
+
+/// Writes a backup of the database of `options` to `out`, through `storage`
+/// for Sqlite. The backup only appears at `out` once it is complete.
+///
+/// # Errors
+///
+/// Returns an error if `out` exists, the database is in memory, or the backup
+/// fails.
+pub async fn backup(
+    options: &storage::Options,
+    storage: Option<&PersistentStorage>,
+    out: &Path,
+) -> EyreResult<()> {
+    ensure!(!out.exists(), "{} exists already", out.display());
+    let partial = out.with_extension("partial");
+    remove_if_exists(&partial)?;
+    if options.database_url.starts_with("sqlite:") {
+        ensure!(
+            options.sqlite_path().is_some(),
+            "in-memory databases can not be backed up"
+        );
+        let storage = storage.ok_or_else(|| eyre::eyre!("no connection to the database"))?;
+        storage.vacuum_into(&partial).await?;
+    } else {
+        let status = tokio::process::Command::new("pg_dump")
+            .arg("--format=custom")
+            .arg("--file")
+            .arg(&partial)
+            .arg(&options.database_url)
+            .status()
+            .await
+            .wrap_err("failed to run pg_dump")?;
+        if !status.success() {
+            remove_if_exists(&partial)?;
+            eyre::bail!("pg_dump failed with {status}");
+        }
+    }
+    fs::rename(&partial, out).wrap_err_with(|| format!("failed to write {}", out.display()))?;
+    Ok(())
+}
+
+/// Backs the database up into `--db-backup-dir` every `--db-backup-interval`
+/// and prunes the old backups. Failures are logged and counted, the next
+/// round tries again.
+pub async fn backup_on_interval(
+    options: Options,
+    storage_options: storage::Options,
+    storage: PersistentStorage,
+) {
+    let extension = if storage_options.database_url.starts_with("sqlite:") {
+        SQLITE_EXTENSION
+    } else {
+        POSTGRES_EXTENSION
+    };
+    loop {
+        tokio::time::sleep(options.db_backup_interval).await;
+        let now = Utc::now();
+        let out = options.db_backup_dir.join(backup_name(now, extension));
+        let result = async {
+            fs::create_dir_all(&options.db_backup_dir)?;
+            backup(&storage_options, Some(&storage), &out).await?;
+            prune(&options.db_backup_dir, &options.retention, now)
+        }
+        .await;
+        match result {
+            Ok(removed) => {
+                DB_BACKUPS.with_label_values(&["ok"]).inc();
+                info!(out = %out.display(), removed, "Database backed up");
+            }
+            Err(error) => {
+                DB_BACKUPS.with_label_values(&["failed"]).inc();
+                error!(?error, out = %out.display(), "Database backup failed");
+            }
+        }
+    }
+}
+
+fn backup_name(time: DateTime<Utc>, extension: &str) -> String {
+    format!("{PREFIX}{}.{extension}", time.format(TIME_FORMAT))
+}
+
+/// Time of the backup named `name`, if it is one.
+fn backup_time(name: &str) -> Option<DateTime<Utc>> {
+    let (stem, extension) = name.strip_prefix(PREFIX)?.rsplit_once('.')?;
+    if extension != SQLITE_EXTENSION && extension != POSTGRES_EXTENSION {
+        return None;
+    }
+    let time = NaiveDateTime::parse_from_str(stem, TIME_FORMAT).ok()?;
+    Some(DateTime::from_utc(time, Utc))
+}
+
+/// Time of the newest backup in `dir`, `None` if there is none or it can not
+/// be read.
+#[must_use]
+pub fn latest_backup(dir: &Path) -> Option<DateTime<Utc>> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str().and_then(backup_time))
+        .max()
+}
+
+/// Removes the backups in `dir` that `retention` does not keep at `now`.
+/// Other files are left alone. Returns the number of removed backups.
+///
+/// # Errors
+///
+/// Returns an error if the directory can not be read or a backup can not be
+/// removed.
+pub fn prune(dir: &Path, retention: &RetentionOptions, now: DateTime<Utc>) -> EyreResult<usize> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir).wrap_err_with(|| format!("failed to read {}", dir.display()))? {
+        let entry = entry?;
+        if let Some(time) = entry.file_name().to_str().and_then(backup_time) {
+            backups.push((time, entry.path()));
+        }
+    }
+    // Newest first.
+    backups.sort_by(|a, b| b.cmp(a));
+    let max_age = chrono::Duration::days(retention.db_backup_max_age_days.into());
+    let mut removed = 0;
+    for (index, (time, path)) in backups.iter().enumerate() {
+        let surplus = retention.db_backup_retention > 0 && index >= retention.db_backup_retention;
+        let expired = retention.db_backup_max_age_days > 0 && now - *time > max_age;
+        if surplus || expired {
+            fs::remove_file(path)
+                .wrap_err_with(|| format!("failed to remove {}", path.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn remove_if_exists(path: &Path) -> EyreResult<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+            Err(error).wrap_err_with(|| format!("failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn backs_up_sqlite_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = test_options().storage;
+        options.database_url = format!("sqlite://{}", dir.path().join("storage.db").display());
+        let storage = storage_client(&options).await.unwrap();
+        storage.ban_uid("git|1234|banned").await.unwrap();
+
+        let out = dir.path().join("backup.db");
+        backup(&options, Some(&storage), &out).await.unwrap();
+        assert!(backup(&options, Some(&storage), &out).await.is_err());
+
+        let mut restored = options.clone();
+        restored.database_url = format!("sqlite://{}", out.display());
+        restored.database_migrate = false;
+        let restored = storage_client(&restored).await.unwrap();
+        assert!(restored.is_banned("git|1234|banned").await.unwrap());
+    }
+
+    #[test]
+    fn prunes_by_count_and_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = "2022-12-06T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let names = (0..4)
+            .map(|days| backup_name(now - chrono::Duration::days(days), SQLITE_EXTENSION))
+            .collect::<Vec<_>>();
+        for name in names.iter().chain([&"unrelated.db".to_string()]) {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let exists = |name: &str| dir.path().join(name).exists();
+        assert_eq!(latest_backup(dir.path()), Some(now));
+
+        let retention = RetentionOptions {
+            db_backup_retention:    3,
+            db_backup_max_age_days: 0,
+        };
+        assert_eq!(prune(dir.path(), &retention, now).unwrap(), 1);
+        assert!(!exists(&names[3]));
+
+        let retention = RetentionOptions {
+            db_backup_retention:    0,
+            db_backup_max_age_days: 1,
+        };
+        assert_eq!(prune(dir.path(), &retention, now).unwrap(), 1);
+        assert!(exists(&names[0]) && exists(&names[1]) && !exists(&names[2]));
+        assert!(exists("unrelated.db"));
+        assert_eq!(latest_backup(&dir.path().join("missing")), None);
+    }
+}
@@ -0,0 +1,375 @@
+//! Moving the database to another one, e.g. from Sqlite to Postgres.
+//!
+//! `migrate-db --from sqlite://storage.db --to postgres://...` migrates the
+//! target, checks that it holds no rows yet and copies the contributors, with
+//! the identities they link to, the sessions, the banned uids and the
+//! transcript entries, table by table in the order of their keys. Each table
+//! is then read back from both databases and compared by its number of rows
+//! and a SHA-256 over the sorted hashes of its rows, as the backends may
+//! order text differently, so a copy that lost or changed a row fails.
+//! Timestamps are compared to the microsecond, the precision of Postgres.
+//!
+//! The other tables, like the audit log and the receipts, start empty. The
+//! sequencer should be stopped or paused during the copy, as rows written
+//! meanwhile may be missed.
+
+use crate::storage::{
+    self, storage_client, ColumnKind,
+    ColumnKind::{Integer, Text, Timestamp},
+    ColumnValue, PersistentStorage,
+};
+use clap::Parser;
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+/// Rows read and inserted at a time.
+const BATCH_SIZE: usize = 1000;
+
+/// A copied table.
+struct Table {
+    name:     &'static str,
+    order_by: &'static str,
+    columns:  &'static [(&'static str, ColumnKind)],
+    /// Whether the table has a serial `id` column.
+    serial:   bool,
+}
+
+/// The copied tables. Identities come before the contributors that reference
+/// them.
+const TABLES: &[Table] = &[
+    Table {
+        name:     "identities",
+        order_by: "id",
+        columns:  &[
+            ("id", Integer),
+            ("provider", Text),
+            ("provider_id", Text),
+            ("handle", Text),
+            ("created_at", Timestamp),
+            ("eligibility_score", Integer),
+            ("uid", Text),
+        ],
+        serial:   true,
+    },
+    Table {
+        name:     "contributors",
+        order_by: "id",
+        columns:  &[
+            ("id", Integer),
+            ("uid", Text),
+            ("started_at", Timestamp),
+            ("finished_at", Timestamp),
+            ("expired_at", Timestamp),
+            ("eligibility_score", Integer),
+            ("deadline_extensions", Integer),
+            ("country", Text),
+            ("client_version", Text),
+            ("ens_name", Text),
+            ("identity_id", Integer),
+            ("attempts", Integer),
+            ("requeued_at", Timestamp),
+            ("fingerprint_hash", Text),
+            ("subnet", Text),
+            ("contribution_index", Integer),
+            ("rejection_reason", Text),
+        ],
+        serial:   true,
+    },
+    Table {
+        name:     "sessions",
+        order_by: "token_hash",
+        columns:  &[
+            ("token_hash", Text),
+            ("uid", Text),
+            ("expires_at", Timestamp),
+            ("lobby_entered_at", Timestamp),
+            ("eligibility_score", Integer),
+            ("identity", Text),
+        ],
+        serial:   false,
+    },
+    Table {
+        name:     "banned_uids",
+        order_by: "uid",
+        columns:  &[("uid", Text), ("banned_at", Timestamp)],
+        serial:   false,
+    },
+    Table {
+        name:     "transcript_entries",
+        order_by: "position",
+        columns:  &[
+            ("position", Integer),
+            ("participant_id", Text),
+            ("ecdsa_signature", Text),
+            ("witness", Text),
+            ("powers", Text),
+            ("created_at", Timestamp),
+            ("entropy_attestation", Text),
+        ],
+        serial:   false,
+    },
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Connection string of the database to copy from.
+    #[clap(long)]
+    pub from: String,
+
+    /// Connection string of the database to copy to. It is created and
+    /// migrated if needed, and must hold no rows in the copied tables.
+    #[clap(long)]
+    pub to: String,
+}
+
+/// What was copied of a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Copied {
+    pub table: &'static str,
+    pub rows:  u64,
+    /// Hex encoded SHA-256 over the sorted hashes of the rows.
+    pub hash:  String,
+}
+
+/// Copies the database at `--from` to `--to`.
+///
+/// # Errors
+///
+/// Returns an error if either database can not be reached, the target is not
+/// empty, or the copy does not match the source.
+pub async fn migrate_db(options: Options) -> EyreResult<()> {
+    let source = storage_client(&storage_options(&options.from, false))
+        .await
+        .wrap_err("failed to open the source database")?;
+    let target = storage_client(&storage_options(&options.to, true))
+        .await
+        .wrap_err("failed to open the target database")?;
+    let result = copy(&source, &target).await;
+    source.close().await?;
+    target.close().await?;
+    for Copied { table, rows, hash } in result? {
+        info!(table, rows, %hash, "Table copied");
+    }
+    info!("Database copied");
+    Ok(())
+}
+
+/// The storage options, from the environment, for the database at `url`.
+fn storage_options(url: &str, migrate: bool) -> storage::Options {
+    let mut options = storage::Options::parse_from(["migrate-db", "--database-url", url]);
+    options.database_migrate = migrate;
+    options
+}
+
+/// Copies the tables from `source` to `target` and verifies the copies.
+///
+/// # Errors
+///
+/// Returns an error if the target holds rows already, or a copy differs.
+pub async fn copy(
+    source: &PersistentStorage,
+    target: &PersistentStorage,
+) -> EyreResult<Vec<Copied>> {
+    for table in TABLES {
+        let rows = target.count_rows(table.name).await?;
+        ensure!(
+            rows == 0,
+            "target table {} holds {rows} rows already",
+            table.name
+        );
+    }
+    let mut copied = Vec::new();
+    for table in TABLES {
+        let mut offset = 0;
+        loop {
+            let rows = source
+                .read_rows(
+                    table.name,
+                    table.columns,
+                    table.order_by,
+                    offset,
+                    BATCH_SIZE,
+                )
+                .await?;
+            if rows.is_empty() {
+                break;
+            }
+            offset += rows.len();
+            target.insert_rows(table.name, table.columns, rows).await?;
+        }
+        if table.serial {
+            target.reset_id_sequence(table.name).await?;
+        }
+
+        let (source_rows, source_hash) = digest(source, table).await?;
+        let (target_rows, target_hash) = digest(target, table).await?;
+        ensure!(
+            source_rows == target_rows,
+            "copied {target_rows} of the {source_rows} rows of {}",
+            table.name
+        );
+        ensure!(
+            source_hash == target_hash,
+            "copy of {} differs from the source",
+            table.name
+        );
+        copied.push(Copied {
+            table: table.name,
+            rows:  source_rows,
+            hash:  source_hash,
+        });
+    }
+    Ok(copied)
+}
+
+/// Number of rows of `table` and the hex encoded SHA-256 over the sorted
+/// hashes of the rows.
+async fn digest(storage: &PersistentStorage, table: &Table) -> EyreResult<(u64, String)> {
+    let mut row_hashes = Vec::new();
+    loop {
+        let rows = storage
+            .read_rows(
+                table.name,
+                table.columns,
+                table.order_by,
+                row_hashes.len(),
+                BATCH_SIZE,
+            )
+            .await?;
+        if rows.is_empty() {
+            break;
+        }
+        row_hashes.extend(rows.iter().map(|row| {
+            let mut hasher = Sha256::new();
+            for value in row {
+                hash_value(&mut hasher, value);
+            }
+            hasher.finalize()
+        }));
+    }
+    row_hashes.sort_unstable();
+    let mut hasher = Sha256::new();
+    for row_hash in &row_hashes {
+        hasher.update(row_hash);
+    }
+    Ok((
+        u64::try_from(row_hashes.len())?,
+        hex::encode(hasher.finalize()),
+    ))
+}
+
+/// Feeds `value` to `hasher`, tagged by its kind and length prefixed, so
+/// that different rows can not hash alike.
+fn hash_value(hasher: &mut Sha256, value: &ColumnValue) {
+    match value {
+        ColumnValue::Text(Some(text)) => {
+            hasher.update([1]);
+            hasher.update(u64::try_from(text.len()).unwrap_or(u64::MAX).to_be_bytes());
+            hasher.update(text.as_bytes());
+        }
+        ColumnValue::Integer(Some(integer)) => {
+            hasher.update([2]);
+            hasher.update(integer.to_be_bytes());
+        }
+        ColumnValue::Timestamp(Some(time)) => {
+            hasher.update([3]);
+            hasher.update(time.timestamp_micros().to_be_bytes());
+        }
+        ColumnValue::Text(None) | ColumnValue::Integer(None) | ColumnValue::Timestamp(None) => {
+            hasher.update([0]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::test_options,
+        tests::{test_transcript, valid_contribution},
+        Engine,
+    };
+    use chrono::{Duration, DurationRound, Utc};
+    use kzg_ceremony_crypto::signature::identity::Identity;
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn copies_and_verifies_tables() {
+        let source = storage_client(&test_options().storage).await.unwrap();
+        let target = storage_client(&test_options().storage).await.unwrap();
+
+        let mut transcript = test_transcript();
+        for no in 1..=2 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        source.import_transcript(&transcript).await.unwrap();
+        source
+            .insert_contributor("git|1|alice", Some(7))
+            .await
+            .unwrap();
+        source.finish_contribution("git|1|alice").await.unwrap();
+        source.ban_uid("git|2|banned").await.unwrap();
+        let sessions = [("a", "git|1|alice"), ("b", "eth|0x12")];
+        for (token_hash, uid) in sessions {
+            source
+                .save_session(&storage::StoredSession {
+                    token_hash:        token_hash.to_string(),
+                    uid:               uid.to_string(),
+                    identity:          None,
+                    expires_at:        Utc::now() + Duration::hours(1),
+                    lobby_entered_at:  None,
+                    eligibility_score: None,
+                })
+                .await
+                .unwrap();
+        }
+
+        let copied = copy(&source, &target).await.unwrap();
+        let rows = |table| {
+            copied
+                .iter()
+                .find(|copied| copied.table == table)
+                .unwrap()
+                .rows
+        };
+        assert_eq!(rows("contributors"), 1);
+        assert_eq!(rows("sessions"), 2);
+        assert_eq!(rows("banned_uids"), 1);
+        assert_eq!(rows("transcript_entries"), 2);
+        assert_eq!(target.read_transcript().await.unwrap(), Some(transcript));
+        assert!(target.is_banned("git|2|banned").await.unwrap());
+        assert!(target.has_contributed("git|1|alice").await.unwrap());
+
+        // The target holds rows now.
+        assert!(copy(&source, &target).await.is_err());
+    }
+
+    #[test]
+    fn hashes_values_unambiguously() {
+        let hash = |values: &[ColumnValue]| {
+            let mut hasher = Sha256::new();
+            for value in values {
+                hash_value(&mut hasher, value);
+            }
+            hasher.finalize()
+        };
+        let text = |text: &str| ColumnValue::Text(Some(text.to_string()));
+        assert_ne!(
+            hash(&[text("ab"), text("c")]),
+            hash(&[text("a"), text("bc")])
+        );
+        assert_ne!(hash(&[ColumnValue::Text(None)]), hash(&[text("")]));
+        let now = Utc::now();
+        assert_eq!(
+            hash(&[ColumnValue::Timestamp(Some(now))]),
+            hash(&[ColumnValue::Timestamp(Some(
+                now.duration_trunc(Duration::microseconds(1)).unwrap()
+            ))])
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Anti-sybil scoring of authenticated participants.
+//!
+//! Every [`EligibilityRule`] looks at the identity and at the [`Evidence`]
+//! gathered by the auth provider. Deny and allow lists decide on their own;
+//! otherwise the points of all rules are added up and compared against
+//! `--eligibility-min-score`.
+
+use crate::identity;
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use eyre::{Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Minimum score a participant needs to be allowed into the lobby.
+    #[clap(long, env, default_value = "0")]
+    pub eligibility_min_score: u32,
+
+    /// Minimum age in days a Github or Discord account needs to earn a point.
+    #[clap(long, env, default_value = "365")]
+    pub eligibility_min_account_age_days: i64,
+
+    /// Minimum Ethereum nonce an address needs to earn a point.
+    #[clap(long, env, default_value = "16")]
+    pub eligibility_min_nonce: u64,
+
+    /// File with one participant uid per line that is always allowed to
+    /// participate.
+    #[clap(long, env)]
+    pub eligibility_allowlist: Option<PathBuf>,
+
+    /// File with one participant uid per line that is never allowed to
+    /// participate.
+    #[clap(long, env)]
+    pub eligibility_denylist: Option<PathBuf>,
+}
+
+/// Facts about an account collected during authentication.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Evidence {
+    pub account_created_at: Option<DateTime<Utc>>,
+    pub nonce:              Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleScore {
+    Points(u32),
+    Allow,
+    Deny,
+    /// The rule does not apply to the participant.
+    Abstain,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eligibility {
+    pub score:        u32,
+    pub eligible:     bool,
+    /// Names of the rules that awarded no points or denied the participant.
+    pub failed_rules: Vec<&'static str>,
+}
+
+pub trait EligibilityRule: Send + Sync {
+    /// Name of the rule, as reported to the participant.
+    fn name(&self) -> &'static str;
+
+    fn score(&self, identity: &Identity, evidence: &Evidence) -> RuleScore;
+}
+
+/// Awards a point to accounts older than `min_age_days`.
+pub struct AccountAgeRule {
+    pub min_age_days: i64,
+}
+
+impl EligibilityRule for AccountAgeRule {
+    fn name(&self) -> &'static str {
+        "account_age"
+    }
+
+    fn score(&self, _identity: &Identity, evidence: &Evidence) -> RuleScore {
+        match evidence.account_created_at {
+            Some(created_at) if (Utc::now() - created_at).num_days() >= self.min_age_days => {
+                RuleScore::Points(1)
+            }
+            _ => RuleScore::Points(0),
+        }
+    }
+}
+
+/// Awards a point to Ethereum addresses with at least `min_nonce`
+/// transactions.
+pub struct NonceRule {
+    pub min_nonce: u64,
+}
+
+impl EligibilityRule for NonceRule {
+    fn name(&self) -> &'static str {
+        "nonce"
+    }
+
+    fn score(&self, _identity: &Identity, evidence: &Evidence) -> RuleScore {
+        match evidence.nonce {
+            Some(nonce) if nonce >= self.min_nonce => RuleScore::Points(1),
+            _ => RuleScore::Points(0),
+        }
+    }
+}
+
+/// Allows or denies the uids in a list.
+pub struct ListRule {
+    uids:    BTreeSet<String>,
+    outcome: RuleScore,
+}
+
+impl ListRule {
+    pub fn from_file(path: &Path, outcome: RuleScore) -> EyreResult<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read uid list {}", path.display()))?;
+        Ok(Self {
+            uids: contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(ToString::to_string)
+                .collect(),
+            outcome,
+        })
+    }
+}
+
+impl EligibilityRule for ListRule {
+    fn name(&self) -> &'static str {
+        if self.outcome == RuleScore::Deny {
+            "denylist"
+        } else {
+            "allowlist"
+        }
+    }
+
+    fn score(&self, identity: &Identity, _evidence: &Evidence) -> RuleScore {
+        if self.uids.contains(&identity::uid(identity))
+            || self.uids.contains(&identity::legacy_uid(identity))
+        {
+            self.outcome
+        } else {
+            RuleScore::Abstain
+        }
+    }
+}
+
+pub type SharedScorer = Arc<ScorerHandle>;
+
+/// The current scorer, replaced as a whole when the configuration is
+/// reloaded.
+pub struct ScorerHandle {
+    scorer: RwLock<Arc<Scorer>>,
+}
+
+impl ScorerHandle {
+    /// # Errors
+    ///
+    /// Returns an error if one of the uid lists can not be read.
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        Ok(Self {
+            scorer: RwLock::new(Arc::new(Scorer::new(options)?)),
+        })
+    }
+
+    #[must_use]
+    pub fn current(&self) -> Arc<Scorer> {
+        self.scorer.read().unwrap().clone()
+    }
+
+    /// Builds the scorer again, reading the uid lists again. The current
+    /// scorer is kept if that fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the uid lists can not be read.
+    pub fn reload(&self, options: &Options) -> EyreResult<()> {
+        let scorer = Scorer::new(options)?;
+        *self.scorer.write().unwrap() = Arc::new(scorer);
+        Ok(())
+    }
+}
+
+pub struct Scorer {
+    rules:     Vec<Box<dyn EligibilityRule>>,
+    min_score: u32,
+}
+
+impl Scorer {
+    /// Builds the scorer from the command line options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the uid lists can not be read.
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        let mut rules: Vec<Box<dyn EligibilityRule>> = vec![
+            Box::new(AccountAgeRule {
+                min_age_days: options.eligibility_min_account_age_days,
+            }),
+            Box::new(NonceRule {
+                min_nonce: options.eligibility_min_nonce,
+            }),
+        ];
+        if let Some(path) = &options.eligibility_denylist {
+            rules.push(Box::new(ListRule::from_file(path, RuleScore::Deny)?));
+        }
+        if let Some(path) = &options.eligibility_allowlist {
+            rules.push(Box::new(ListRule::from_file(path, RuleScore::Allow)?));
+        }
+        Ok(Self::with_rules(rules, options.eligibility_min_score))
+    }
+
+    #[must_use]
+    pub fn with_rules(rules: Vec<Box<dyn EligibilityRule>>, min_score: u32) -> Self {
+        Self { rules, min_score }
+    }
+
+    #[must_use]
+    pub const fn min_score(&self) -> u32 {
+        self.min_score
+    }
+
+    /// Scores a participant. A deny takes precedence over an allow.
+    #[must_use]
+    pub fn evaluate(&self, identity: &Identity, evidence: &Evidence) -> Eligibility {
+        let mut score = 0;
+        let mut allowed = false;
+        let mut failed_rules = Vec::new();
+        for rule in &self.rules {
+            match rule.score(identity, evidence) {
+                RuleScore::Deny => {
+                    failed_rules.push(rule.name());
+                    return Eligibility {
+                        score,
+                        eligible: false,
+                        failed_rules,
+                    };
+                }
+                RuleScore::Allow => allowed = true,
+                RuleScore::Points(0) => failed_rules.push(rule.name()),
+                RuleScore::Points(points) => score += points,
+                RuleScore::Abstain => {}
+            }
+        }
+        Eligibility {
+            score,
+            eligible: allowed || score >= self.min_score,
+            failed_rules,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn github(id: u64) -> Identity {
+        Identity::Github {
+            id,
+            username: "test_user".to_string(),
+        }
+    }
+
+    #[test]
+    fn scores_rules() {
+        let scorer = Scorer::with_rules(
+            vec![
+                Box::new(AccountAgeRule { min_age_days: 30 }),
+                Box::new(NonceRule { min_nonce: 4 }),
+            ],
+            1,
+        );
+        let old_account = Evidence {
+            account_created_at: Some(Utc::now() - Duration::days(31)),
+            nonce:              None,
+        };
+        assert_eq!(scorer.evaluate(&github(1), &old_account), Eligibility {
+            score:        1,
+            eligible:     true,
+            failed_rules: vec!["nonce"],
+        });
+        let new_account = Evidence {
+            account_created_at: Some(Utc::now() - Duration::days(1)),
+            nonce:              Some(3),
+        };
+        assert_eq!(scorer.evaluate(&github(1), &new_account), Eligibility {
+            score:        0,
+            eligible:     false,
+            failed_rules: vec!["account_age", "nonce"],
+        });
+    }
+
+    #[test]
+    fn applies_lists() {
+        let mut denylist = NamedTempFile::new().unwrap();
+        writeln!(denylist, "# banned\ngit|2|test_user").unwrap();
+        let mut allowlist = NamedTempFile::new().unwrap();
+        writeln!(allowlist, "git|1|test_user\ngit|2|test_user").unwrap();
+        writeln!(allowlist, "{}", identity::uid(&github(3))).unwrap();
+
+        let scorer = Scorer::new(&Options {
+            eligibility_min_score:            2,
+            eligibility_min_account_age_days: 0,
+            eligibility_min_nonce:            0,
+            eligibility_allowlist:            Some(allowlist.path().to_path_buf()),
+            eligibility_denylist:             Some(denylist.path().to_path_buf()),
+        })
+        .unwrap();
+        let evidence = Evidence::default();
+        assert!(scorer.evaluate(&github(1), &evidence).eligible);
+        assert!(!scorer.evaluate(&github(2), &evidence).eligible);
+        assert_eq!(scorer.evaluate(&github(2), &evidence).failed_rules, vec![
+            "denylist"
+        ]);
+        assert!(scorer.evaluate(&github(3), &evidence).eligible);
+        assert!(!scorer.evaluate(&github(4), &evidence).eligible);
+    }
+}
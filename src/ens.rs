@@ -0,0 +1,236 @@
+//! ENS names of contributors who signed in with Ethereum.
+//!
+//! With `--ens-rpc-url`, the primary name of the address is looked up when the
+//! contribution is submitted: the resolver of `<address>.addr.reverse` is read
+//! from the ENS registry and asked for the name. Anyone can claim any name in
+//! their reverse record, so the name is only kept if it resolves back to the
+//! address. It is stored in the `ens_name` column of `contributors` and shown
+//! by `/info/contributors`. Lookups run in the background and failures are
+//! only logged, they never hold up a contribution.
+
+use crate::{storage::PersistentStorage, util::Secret};
+use clap::Parser;
+use ethers_core::{
+    abi::{self, ParamType, Token},
+    utils::keccak256,
+};
+use eyre::{bail, eyre, Result as EyreResult};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{error, warn, Instrument};
+
+/// The ENS registry on mainnet.
+const MAINNET_REGISTRY: &str = "0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+
+/// Longest name that is recorded.
+const MAX_NAME_LENGTH: usize = 255;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Ethereum JSON-RPC endpoint the ENS names of contributors are looked up
+    /// with. No names are recorded without it.
+    #[clap(long, env)]
+    pub ens_rpc_url: Option<Secret>,
+
+    /// Address of the ENS registry.
+    #[clap(long, env, default_value = MAINNET_REGISTRY)]
+    pub ens_registry: String,
+}
+
+#[derive(Debug, Default)]
+pub struct EnsResolver {
+    rpc_url:  Option<String>,
+    registry: [u8; 20],
+    client:   reqwest::Client,
+}
+
+pub type SharedEnsResolver = Arc<EnsResolver>;
+
+impl EnsResolver {
+    pub fn new(options: &Options, client: reqwest::Client) -> EyreResult<Self> {
+        Ok(Self {
+            rpc_url: options
+                .ens_rpc_url
+                .as_ref()
+                .map(|url| url.get_secret().to_string()),
+            registry: parse_address(&options.ens_registry)?,
+            client,
+        })
+    }
+
+    /// Looks up the name of `identity` in the background and records it for
+    /// `uid`. Does nothing for other providers or without `--ens-rpc-url`.
+    pub fn record(self: &Arc<Self>, storage: PersistentStorage, uid: String, identity: &Identity) {
+        let address = match identity {
+            Identity::Ethereum { address } if self.rpc_url.is_some() => *address,
+            _ => return,
+        };
+        let resolver = self.clone();
+        tokio::spawn(
+            async move {
+                match resolver.lookup(&address).await {
+                    Ok(Some(name)) => {
+                        if let Err(err) = storage.set_contributor_ens_name(&uid, &name).await {
+                            error!(?err, "failed to record contributor ENS name");
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(?err, "ENS lookup failed"),
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// The primary name of `address`, if it has one that resolves back to it.
+    pub async fn lookup(&self, address: &[u8; 20]) -> EyreResult<Option<String>> {
+        let reverse_node = namehash(&format!("{}.addr.reverse", hex::encode(address)));
+        let reverse_resolver = match self.resolver(&reverse_node).await? {
+            Some(resolver) => resolver,
+            None => return Ok(None),
+        };
+        let name = match decode_name(
+            &self
+                .call(&reverse_resolver, "name(bytes32)", &reverse_node)
+                .await?,
+        )? {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let node = namehash(&name);
+        let resolver = match self.resolver(&node).await? {
+            Some(resolver) => resolver,
+            None => return Ok(None),
+        };
+        let resolved = decode_address(&self.call(&resolver, "addr(bytes32)", &node).await?);
+        if resolved == Some(*address) {
+            Ok(Some(name))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The resolver the registry has set for `node`.
+    async fn resolver(&self, node: &[u8; 32]) -> EyreResult<Option<[u8; 20]>> {
+        let result = self.call(&self.registry, "resolver(bytes32)", node).await?;
+        Ok(decode_address(&result).filter(|resolver| *resolver != [0; 20]))
+    }
+
+    /// Calls `function`, which takes a single node, of the contract at `to`.
+    async fn call(&self, to: &[u8; 20], function: &str, node: &[u8; 32]) -> EyreResult<Vec<u8>> {
+        let rpc_url = self
+            .rpc_url
+            .as_ref()
+            .ok_or_else(|| eyre!("ENS lookups are disabled"))?;
+        let mut data = keccak256(function.as_bytes())[..4].to_vec();
+        data.extend_from_slice(node);
+        let request = json!({
+            "id": 1,
+            "jsonrpc": "2.0",
+            "method": "eth_call",
+            "params": [
+                {
+                    "to": format!("0x{}", hex::encode(to)),
+                    "data": format!("0x{}", hex::encode(data)),
+                },
+                "latest"
+            ],
+        });
+        let response: Value = self
+            .client
+            .post(rpc_url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            bail!("eth_call failed: {error}");
+        }
+        let result = response
+            .get("result")
+            .and_then(Value::as_str)
+            .ok_or_else(|| eyre!("malformed eth_call response"))?;
+        Ok(hex::decode(result.trim_start_matches("0x"))?)
+    }
+}
+
+fn parse_address(address: &str) -> EyreResult<[u8; 20]> {
+    let bytes = hex::decode(address.trim_start_matches("0x"))?;
+    bytes
+        .try_into()
+        .map_err(|_| eyre!("invalid address {address}"))
+}
+
+/// The node of `name` as defined by EIP-137. `name` is expected to be
+/// normalized already, names that are not do not resolve back.
+fn namehash(name: &str) -> [u8; 32] {
+    name.rsplit('.')
+        .filter(|label| !label.is_empty())
+        .fold([0; 32], |node, label| {
+            let mut input = node.to_vec();
+            input.extend_from_slice(&keccak256(label.as_bytes()));
+            keccak256(input)
+        })
+}
+
+/// An address returned as a 32 byte word.
+fn decode_address(data: &[u8]) -> Option<[u8; 20]> {
+    data.get(12..32)?.try_into().ok()
+}
+
+/// A name returned by `name(bytes32)`, `None` if the resolver has none.
+fn decode_name(data: &[u8]) -> EyreResult<Option<String>> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let name = match abi::decode(&[ParamType::String], data)?.pop() {
+        Some(Token::String(name)) => name,
+        _ => bail!("malformed name"),
+    };
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH || name.chars().any(char::is_control) {
+        return Ok(None);
+    }
+    Ok(Some(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_names() {
+        assert_eq!(namehash(""), [0; 32]);
+        assert_eq!(
+            hex::encode(namehash("eth")),
+            "93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+        );
+        assert_eq!(
+            hex::encode(namehash("foo.eth")),
+            "de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+        );
+    }
+
+    #[test]
+    fn decodes_results() {
+        let encoded = abi::encode(&[Token::String("vitalik.eth".to_string())]);
+        assert_eq!(
+            decode_name(&encoded).unwrap().as_deref(),
+            Some("vitalik.eth")
+        );
+        assert_eq!(
+            decode_name(&abi::encode(&[Token::String(String::new())])).unwrap(),
+            None
+        );
+        assert_eq!(decode_name(&[]).unwrap(), None);
+        assert!(decode_name(&[1, 2, 3]).is_err());
+
+        let mut word = [0; 32];
+        word[12..].copy_from_slice(&[0xab; 20]);
+        assert_eq!(decode_address(&word), Some([0xab; 20]));
+        assert_eq!(decode_address(&word[..31]), None);
+    }
+}
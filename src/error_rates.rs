@@ -0,0 +1,155 @@
+//! Rates of error responses, for `/admin/overview`.
+//!
+//! Every response of a ceremony is counted by its status, those turned away
+//! by the rate limits and the load shedder included. The rates are per
+//! minute, estimated like the call rate of [`crate::proof_of_work`] from the
+//! current minute and the part of the previous one still within the last
+//! sixty seconds.
+
+use axum::{body::Body, middleware::Next, response::Response};
+use http::{Request, StatusCode};
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Responses of the last minute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ErrorRates {
+    pub requests:      f64,
+    /// Responses with a 4xx status.
+    pub client_errors: f64,
+    /// Responses with a 5xx status.
+    pub server_errors: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Counts {
+    requests:      u64,
+    client_errors: u64,
+    server_errors: u64,
+}
+
+/// Counts of the current and the previous window.
+struct Window {
+    started:  Instant,
+    current:  Counts,
+    previous: Counts,
+}
+
+pub type SharedResponseCounter = Arc<ResponseCounter>;
+
+pub struct ResponseCounter {
+    window: Mutex<Window>,
+}
+
+impl Default for ResponseCounter {
+    fn default() -> Self {
+        Self {
+            window: Mutex::new(Window {
+                started:  Instant::now(),
+                current:  Counts::default(),
+                previous: Counts::default(),
+            }),
+        }
+    }
+}
+
+impl Window {
+    /// Moves on to the window of now, and returns how far into it now is.
+    fn advance(&mut self) -> Duration {
+        let elapsed = self.started.elapsed();
+        if elapsed >= 2 * WINDOW {
+            *self = Self {
+                started:  Instant::now(),
+                current:  Counts::default(),
+                previous: Counts::default(),
+            };
+            Duration::ZERO
+        } else if elapsed >= WINDOW {
+            self.previous = self.current;
+            self.current = Counts::default();
+            self.started += WINDOW;
+            elapsed - WINDOW
+        } else {
+            elapsed
+        }
+    }
+}
+
+impl ResponseCounter {
+    pub fn record(&self, status: StatusCode) {
+        let mut window = self.window.lock().unwrap();
+        window.advance();
+        window.current.requests += 1;
+        if status.is_client_error() {
+            window.current.client_errors += 1;
+        } else if status.is_server_error() {
+            window.current.server_errors += 1;
+        }
+    }
+
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn rates(&self) -> ErrorRates {
+        let mut window = self.window.lock().unwrap();
+        let elapsed = window.advance();
+        // The previous window is weighted by how much of it is still within
+        // the last minute.
+        let remaining = 1.0 - elapsed.as_secs_f64() / WINDOW.as_secs_f64();
+        let rate =
+            |previous: u64, current: u64| (previous as f64).mul_add(remaining, current as f64);
+        ErrorRates {
+            requests:      rate(window.previous.requests, window.current.requests),
+            client_errors: rate(window.previous.client_errors, window.current.client_errors),
+            server_errors: rate(window.previous.server_errors, window.current.server_errors),
+        }
+    }
+}
+
+/// Middleware counting the responses of the ceremony.
+pub async fn count_responses(
+    counter: SharedResponseCounter,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let response = next.run(request).await;
+    counter.record(response.status());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rates_cover_the_last_minute() {
+        tokio::time::pause();
+        let counter = ResponseCounter::default();
+        counter.record(StatusCode::OK);
+        counter.record(StatusCode::NOT_FOUND);
+        counter.record(StatusCode::TOO_MANY_REQUESTS);
+        counter.record(StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(counter.rates(), ErrorRates {
+            requests:      4.0,
+            client_errors: 2.0,
+            server_errors: 1.0,
+        });
+
+        // Half of the previous minute is still within the last one.
+        tokio::time::advance(Duration::from_secs(90)).await;
+        counter.record(StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(counter.rates(), ErrorRates {
+            requests:      3.0,
+            client_errors: 1.0,
+            server_errors: 1.5,
+        });
+
+        tokio::time::advance(Duration::from_secs(120)).await;
+        assert_eq!(counter.rates(), ErrorRates::default());
+    }
+}
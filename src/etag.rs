@@ -0,0 +1,94 @@
+//! Entity tags for conditional GETs.
+//!
+//! Responses that clients poll are tagged with a hash of their content. A
+//! client that sends the tag back in `If-None-Match` gets `304 Not Modified`
+//! without a body as long as the content stays the same. Tags are weak, as
+//! the body may be compressed on the way out.
+
+use axum::{
+    body::Bytes,
+    response::{IntoResponse, Response},
+};
+use ethers_core::utils::keccak256;
+use http::{
+    header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    HeaderMap, StatusCode,
+};
+use serde::Serialize;
+
+/// The tag of `content`.
+#[must_use]
+pub fn tag(content: &[u8]) -> String {
+    format!("W/\"{}\"", hex::encode(&keccak256(content)[..16]))
+}
+
+/// Whether `If-None-Match` names `tag`, going by weak comparison.
+#[must_use]
+pub fn matches(headers: &HeaderMap, tag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    let tag = opaque(tag);
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim() == "*" || opaque(value) == tag)
+}
+
+#[must_use]
+pub fn not_modified(tag: String) -> Response {
+    (StatusCode::NOT_MODIFIED, [(ETAG, tag)]).into_response()
+}
+
+/// Answers with `body` as tagged JSON, or with `304 Not Modified` if the
+/// client already has it.
+pub fn json<T: Serialize>(headers: &HeaderMap, body: &T) -> Response {
+    let json = match serde_json::to_vec(body) {
+        Ok(json) => json,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let tag = tag(&json);
+    respond(headers, json.into(), tag)
+}
+
+/// Answers with `json`, tagged as `tag`, or with `304 Not Modified` if the
+/// client already has it.
+pub fn respond(headers: &HeaderMap, json: Bytes, tag: String) -> Response {
+    if matches(headers, &tag) {
+        return not_modified(tag);
+    }
+    (
+        StatusCode::OK,
+        [(ETAG, tag), (CONTENT_TYPE, "application/json".to_string())],
+        json,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn compares_tags_weakly() {
+        let tag = tag(b"{}");
+        assert!(tag.starts_with("W/\""));
+        let sending = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(IF_NONE_MATCH, HeaderValue::from_static(value));
+            headers
+        };
+        assert!(!matches(&HeaderMap::new(), &tag));
+        assert!(matches(&sending("*"), &tag));
+        assert!(!matches(&sending("\"other\""), &tag));
+
+        let strong = tag.trim_start_matches("W/").to_owned();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            IF_NONE_MATCH,
+            HeaderValue::from_str(&format!("\"other\", {strong}")).unwrap(),
+        );
+        assert!(matches(&headers, &tag));
+    }
+}
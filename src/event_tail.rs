@@ -0,0 +1,217 @@
+//! The recent audit records and ceremony events, for `/admin/events/tail`.
+//!
+//! The last `--event-tail-size` events are kept in memory, whether or not the
+//! audit log or the webhook are enabled. Every event gets an increasing `id`,
+//! so that a client that reconnects with `Last-Event-ID` only gets the events
+//! it missed, as far as they are still kept.
+
+use crate::{audit::AuditRecord, webhook::WebhookEvent};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+
+/// Events a subscriber may fall behind by before it misses some.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Number of recent audit records and ceremony events kept for
+    /// `/admin/events/tail`.
+    #[clap(long, env, default_value = "1000")]
+    pub event_tail_size: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    /// An [`AuditRecord`].
+    Audit,
+    /// A [`WebhookEvent`].
+    Ceremony,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TailEvent {
+    pub id:        u64,
+    pub timestamp: DateTime<Utc>,
+    pub source:    EventSource,
+    /// The audit action or the name of the ceremony event, e.g. `admin_ban`
+    /// or `contribution_verified`.
+    #[serde(rename = "type")]
+    pub kind:      String,
+    pub uid:       Option<String>,
+    /// The record or event as the audit log or the webhook has it.
+    pub data:      Value,
+}
+
+/// Which events a subscriber wants. The default matches all of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    /// Any of these types, or any type if empty.
+    pub kinds: BTreeSet<String>,
+    pub uid:   Option<String>,
+}
+
+impl EventFilter {
+    #[must_use]
+    pub fn matches(&self, event: &TailEvent) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+            && self
+                .uid
+                .as_ref()
+                .map_or(true, |uid| event.uid.as_ref() == Some(uid))
+    }
+}
+
+pub type SharedEventTail = Arc<EventTail>;
+
+#[derive(Debug)]
+pub struct EventTail {
+    capacity: usize,
+    recent:   Mutex<Recent>,
+    sender:   broadcast::Sender<TailEvent>,
+}
+
+#[derive(Debug)]
+struct Recent {
+    events:  VecDeque<TailEvent>,
+    next_id: u64,
+}
+
+impl EventTail {
+    #[must_use]
+    pub fn new(options: &Options) -> Self {
+        Self {
+            capacity: options.event_tail_size,
+            recent:   Mutex::new(Recent {
+                events:  VecDeque::new(),
+                next_id: 1,
+            }),
+            sender:   broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+
+    pub fn push_audit(&self, record: &AuditRecord) {
+        let kind = <&str>::from(record.action).to_string();
+        self.push(
+            EventSource::Audit,
+            kind,
+            record.uid.clone(),
+            serde_json::to_value(record).unwrap_or_default(),
+        );
+    }
+
+    pub fn push_ceremony(&self, event: &WebhookEvent) {
+        let data = serde_json::to_value(event).unwrap_or_default();
+        let kind = data["event"].as_str().unwrap_or_default().to_string();
+        let uid = data["uid"].as_str().map(str::to_string);
+        self.push(EventSource::Ceremony, kind, uid, data);
+    }
+
+    fn push(&self, source: EventSource, kind: String, uid: Option<String>, data: Value) {
+        let mut recent = self.recent.lock().expect("lock poisoned");
+        let event = TailEvent {
+            id: recent.next_id,
+            timestamp: Utc::now(),
+            source,
+            kind,
+            uid,
+            data,
+        };
+        recent.next_id += 1;
+        recent.events.push_back(event.clone());
+        while recent.events.len() > self.capacity {
+            recent.events.pop_front();
+        }
+        // Sending only fails if nobody is listening.
+        let _ = self.sender.send(event);
+    }
+
+    /// The last `last` kept events that match `filter` and came after the
+    /// event `after`, oldest first, and the events from then on.
+    #[must_use]
+    pub fn subscribe(
+        &self,
+        filter: &EventFilter,
+        last: usize,
+        after: Option<u64>,
+    ) -> (Vec<TailEvent>, broadcast::Receiver<TailEvent>) {
+        // Subscribing under the lock neither misses nor repeats an event.
+        let recent = self.recent.lock().expect("lock poisoned");
+        let mut backlog = recent
+            .events
+            .iter()
+            .rev()
+            .filter(|event| after.map_or(true, |after| event.id > after))
+            .filter(|event| filter.matches(event))
+            .take(last)
+            .cloned()
+            .collect::<Vec<_>>();
+        backlog.reverse();
+        (backlog, self.sender.subscribe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditAction;
+
+    fn ban(uid: &str) -> AuditRecord {
+        AuditRecord {
+            timestamp: Utc::now(),
+            action:    AuditAction::AdminBan,
+            uid:       Some(uid.to_string()),
+            ip:        None,
+            outcome:   "ok".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_and_streams_recent_events() {
+        let tail = EventTail::new(&Options { event_tail_size: 3 });
+        for uid in ["a", "b", "c", "d"] {
+            tail.push_audit(&ban(uid));
+        }
+        tail.push_ceremony(&WebhookEvent::LobbyFull { lobby_size: 10 });
+
+        let (backlog, _) = tail.subscribe(&EventFilter::default(), 10, None);
+        let ids = backlog.iter().map(|event| event.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![3, 4, 5]);
+        assert_eq!(backlog[2].kind, "lobby_full");
+        assert_eq!(backlog[2].source, EventSource::Ceremony);
+        assert_eq!(backlog[2].data["lobby_size"], 10);
+
+        let bans = EventFilter {
+            kinds: BTreeSet::from(["admin_ban".to_string()]),
+            uid:   None,
+        };
+        let (backlog, _) = tail.subscribe(&bans, 1, None);
+        assert_eq!(backlog.len(), 1);
+        assert_eq!(backlog[0].uid.as_deref(), Some("d"));
+        let (backlog, _) = tail.subscribe(&EventFilter::default(), 10, Some(4));
+        assert_eq!(backlog.len(), 1);
+
+        let of_e = EventFilter {
+            kinds: BTreeSet::new(),
+            uid:   Some("e".to_string()),
+        };
+        let (backlog, mut receiver) = tail.subscribe(&of_e, 10, None);
+        assert!(backlog.is_empty());
+        tail.push_ceremony(&WebhookEvent::ContributionVerified {
+            uid:               "e".to_string(),
+            num_contributions: 1,
+        });
+        let event = receiver.recv().await.unwrap();
+        assert!(of_e.matches(&event));
+        assert_eq!(event.id, 6);
+        assert_eq!(event.kind, "contribution_verified");
+    }
+}
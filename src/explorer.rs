@@ -0,0 +1,68 @@
+//! Static transcript explorer served under `/explorer/`.
+//!
+//! The assets in `explorer/` are embedded into the binary when the `explorer`
+//! feature is enabled, so the UI needs no separate web host. It only uses the
+//! public API of the ceremony it is nested in.
+
+use axum::{
+    body::Full,
+    extract::Path,
+    response::{IntoResponse, Redirect, Response},
+};
+use http::{header, StatusCode};
+use rust_embed::RustEmbed;
+
+#[derive(RustEmbed)]
+#[folder = "explorer/"]
+struct Assets;
+
+/// Redirects `/explorer` to `/explorer/`, so that relative links in the
+/// assets resolve below it.
+#[allow(clippy::unused_async)] // Required for axum function signature
+pub async fn explorer_root() -> Redirect {
+    Redirect::permanent("explorer/")
+}
+
+#[allow(clippy::unused_async)] // Required for axum function signature
+pub async fn explorer_asset(Path(path): Path<String>) -> Response {
+    match path.trim_start_matches('/') {
+        "" => asset("index.html"),
+        path => asset(path),
+    }
+}
+
+fn asset(path: &str) -> Response {
+    match Assets::get(path) {
+        Some(file) => {
+            let mime = mime_guess::from_path(path).first_or_octet_stream();
+            (
+                [(header::CONTENT_TYPE, mime.as_ref())],
+                Full::from(file.data),
+            )
+                .into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_embedded_assets() {
+        let response = explorer_asset(Path("/".to_string())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html");
+
+        let response = explorer_asset(Path("/explorer.js".to_string())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .ends_with("/javascript"));
+
+        let response = explorer_asset(Path("missing.txt".to_string())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,242 @@
+//! Evidence that the lobby handed out the contribution slot fairly.
+//!
+//! Each time a session joins the lobby a wait is recorded in the
+//! `lobby_waits` table, with the identity provider of the participant, when
+//! it joined and how often it called `/lobby/try_contribute`. The wait ends
+//! with the outcome `slot` when the session gets the contribution slot, or
+//! `timeout` when it is dropped from the lobby for missing its check-ins.
+//! `GET /info/fairness` summarizes the waits, so that anyone can compare the
+//! waits and the share of slots of each provider. Waits record no uid.
+
+use crate::{
+    metrics::LOBBY_WAIT_TIME,
+    storage::{PersistentStorage, StoredLobbyWait},
+    SessionId,
+};
+use chrono::Utc;
+use kzg_ceremony_crypto::signature::identity::Identity;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use strum::IntoStaticStr;
+use tracing::error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum WaitOutcome {
+    Slot,
+    Timeout,
+}
+
+/// Records a `/lobby/try_contribute` call of a session in the lobby, which
+/// starts a wait if the session `joined` with it. Failures are only logged,
+/// they must not keep the participant from the slot.
+pub async fn record_poll(
+    storage: &PersistentStorage,
+    session_id: &SessionId,
+    identity: &Identity,
+    joined: bool,
+) {
+    let session_hash = session_id.hash();
+    let result = if joined {
+        storage
+            .start_lobby_wait(&session_hash, &identity.provider_name(), Utc::now())
+            .await
+    } else {
+        storage.count_lobby_poll(&session_hash).await
+    };
+    if let Err(error) = result {
+        error!(?error, "failed to record lobby poll");
+    }
+}
+
+/// Ends the wait of the session, if it has one.
+pub async fn finish_wait(
+    storage: &PersistentStorage,
+    session_id: &SessionId,
+    outcome: WaitOutcome,
+) {
+    let finished_at = Utc::now();
+    let wait = match storage
+        .finish_lobby_wait(&session_id.hash(), outcome.into(), finished_at)
+        .await
+    {
+        Ok(Some(wait)) => wait,
+        Ok(None) => return,
+        Err(error) => {
+            error!(?error, "failed to record end of lobby wait");
+            return;
+        }
+    };
+    if outcome == WaitOutcome::Slot {
+        let waited = (finished_at - wait.joined_at).to_std().unwrap_or_default();
+        LOBBY_WAIT_TIME
+            .with_label_values(&[&wait.provider])
+            .observe(waited.as_secs_f64());
+    }
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct FairnessReport {
+    /// Waits that have not ended yet.
+    waiting:   usize,
+    #[serde(flatten)]
+    overall:   WaitSummary,
+    providers: BTreeMap<String, WaitSummary>,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct WaitSummary {
+    joins:      usize,
+    slots:      usize,
+    timeouts:   usize,
+    /// Fraction of all joins.
+    join_share: f64,
+    /// Fraction of all slots. Much more than `join_share` means the provider
+    /// got ahead in the queue.
+    slot_share: f64,
+    /// Seconds from joining the lobby to getting the slot.
+    wait_secs:  Percentiles,
+    /// `/lobby/try_contribute` calls of the waits that got the slot.
+    polls:      Percentiles,
+}
+
+/// Nearest rank percentiles, `None` without values.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Percentiles {
+    p50: Option<f64>,
+    p90: Option<f64>,
+    p99: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Percentiles {
+    fn of(mut values: Vec<f64>) -> Self {
+        values.sort_by(f64::total_cmp);
+        let at = |permille: usize| {
+            (!values.is_empty()).then(|| values[(permille * values.len() + 999) / 1000 - 1])
+        };
+        Self {
+            p50: at(500),
+            p90: at(900),
+            p99: at(990),
+            max: at(1000),
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn share(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn summarize(waits: &[&StoredLobbyWait], total_joins: usize, total_slots: usize) -> WaitSummary {
+    let slots = waits
+        .iter()
+        .filter(|wait| wait.outcome.as_deref() == Some(<&str>::from(WaitOutcome::Slot)))
+        .collect::<Vec<_>>();
+    let timeouts = waits
+        .iter()
+        .filter(|wait| wait.outcome.as_deref() == Some(<&str>::from(WaitOutcome::Timeout)))
+        .count();
+    WaitSummary {
+        joins: waits.len(),
+        slots: slots.len(),
+        timeouts,
+        join_share: share(waits.len(), total_joins),
+        slot_share: share(slots.len(), total_slots),
+        wait_secs: Percentiles::of(
+            slots
+                .iter()
+                .filter_map(|wait| {
+                    wait.finished_at
+                        .map(|finished_at| finished_at - wait.joined_at)
+                })
+                .map(|waited| waited.to_std().unwrap_or_default().as_secs_f64())
+                .collect(),
+        ),
+        polls: Percentiles::of(slots.iter().map(|wait| wait.polls as f64).collect()),
+    }
+}
+
+#[must_use]
+pub fn report(waits: &[StoredLobbyWait]) -> FairnessReport {
+    let all = waits.iter().collect::<Vec<_>>();
+    let total_joins = waits.len();
+    let total_slots = waits
+        .iter()
+        .filter(|wait| wait.outcome.as_deref() == Some(<&str>::from(WaitOutcome::Slot)))
+        .count();
+    let mut by_provider = BTreeMap::<&str, Vec<&StoredLobbyWait>>::new();
+    for wait in waits {
+        by_provider
+            .entry(wait.provider.as_str())
+            .or_default()
+            .push(wait);
+    }
+    FairnessReport {
+        waiting:   waits.iter().filter(|wait| wait.outcome.is_none()).count(),
+        overall:   summarize(&all, total_joins, total_slots),
+        providers: by_provider
+            .into_iter()
+            .map(|(provider, waits)| {
+                (
+                    provider.to_string(),
+                    summarize(&waits, total_joins, total_slots),
+                )
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn wait(provider: &str, waited_secs: Option<i64>, polls: u64) -> StoredLobbyWait {
+        let joined_at = Utc::now();
+        StoredLobbyWait {
+            provider: provider.to_string(),
+            joined_at,
+            polls,
+            outcome: waited_secs.map(|_| "slot".to_string()),
+            finished_at: waited_secs.map(|secs| joined_at + Duration::seconds(secs)),
+        }
+    }
+
+    #[test]
+    fn reports_waits_by_provider() {
+        let mut waits = (1..=10)
+            .map(|secs| wait("Github", Some(secs * 10), 2 * secs.unsigned_abs()))
+            .collect::<Vec<_>>();
+        waits.push(wait("Ethereum", Some(5), 1));
+        waits.push(wait("Ethereum", None, 3));
+        let mut timeout = wait("Ethereum", None, 4);
+        timeout.outcome = Some("timeout".to_string());
+        timeout.finished_at = Some(timeout.joined_at);
+        waits.push(timeout);
+
+        let report = report(&waits);
+        assert_eq!(report.waiting, 1);
+        assert_eq!(report.overall.joins, 13);
+        assert_eq!(report.overall.slots, 11);
+        assert_eq!(report.overall.timeouts, 1);
+        assert_eq!(report.overall.wait_secs.max, Some(100.0));
+
+        let github = &report.providers["Github"];
+        assert_eq!(github.slots, 10);
+        assert_eq!(github.wait_secs.p50, Some(50.0));
+        assert_eq!(github.wait_secs.p90, Some(90.0));
+        assert_eq!(github.polls.max, Some(20.0));
+        assert!((github.slot_share - 10.0 / 11.0).abs() < 1e-9);
+        let ethereum = &report.providers["Ethereum"];
+        assert_eq!(ethereum.joins, 3);
+        assert_eq!(ethereum.wait_secs.p99, Some(5.0));
+        assert!((ethereum.join_share - 3.0 / 13.0).abs() < 1e-9);
+    }
+}
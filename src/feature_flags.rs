@@ -0,0 +1,325 @@
+//! Feature flags that turn routes on and off at runtime, to roll out new
+//! routes during the ceremony.
+//!
+//! `--feature-flags-file` is a TOML file with a table per flag:
+//!
+//! ```toml
+//! [binary_upload]
+//! routes = ["/contribute/upload/:id", "/contribute/upload/:id/:part"]
+//! percent = 10
+//!
+//! [ws_lobby]
+//! routes = ["/ws/lobby"]
+//! enabled = false
+//! ```
+//!
+//! A route matches the paths with the same segments, where a `:name` segment
+//! matches any one segment and a trailing `*` any that follow. Requests to a
+//! route of a disabled flag are answered with `SEQ-FEATURE-001`. An enabled
+//! flag with a `percent` below 100 serves its routes to that share of the
+//! sessions only: the bearer token of the request, hashed with the name of the
+//! flag, decides, so a session gets the same answer on every request. Requests
+//! without a session are served with that probability. Routes of no flag, and
+//! the admin API, are always served.
+//!
+//! The admin API changes flags until the file is read again, on SIGHUP. A
+//! file that fails to parse keeps the previous flags.
+
+use crate::reload_signal::{self, ReloadSignal};
+use axum::{
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use clap::Parser;
+use eyre::{ensure, Result as EyreResult, WrapErr};
+use http::{header::AUTHORIZATION, Request};
+use kzg_ceremony_crypto::ErrorCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tracing::{error, info};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// TOML file with feature flags gating routes.
+    #[clap(long, env)]
+    pub feature_flags_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeatureFlag {
+    /// Route patterns the flag gates.
+    pub routes:  Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Share of the sessions, from 0 to 100, the routes are served to while
+    /// the flag is enabled.
+    #[serde(default = "default_percent")]
+    pub percent: u8,
+}
+
+const fn default_enabled() -> bool {
+    true
+}
+
+const fn default_percent() -> u8 {
+    100
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum FeatureFlagError {
+    #[error("this feature is disabled")]
+    Disabled,
+    #[error("unknown feature flag")]
+    Unknown,
+    #[error("percent must be at most 100, got {0}")]
+    InvalidPercent(u8),
+}
+
+impl ErrorCode for FeatureFlagError {
+    fn to_error_code(&self) -> String {
+        format!("FeatureFlagError::{}", <&str>::from(self))
+    }
+}
+
+pub type Flags = BTreeMap<String, FeatureFlag>;
+
+fn flags_from_file(path: &Path) -> EyreResult<Flags> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("failed to read feature flags {}", path.display()))?;
+    let flags: Flags = toml::from_str(&contents)
+        .wrap_err_with(|| format!("invalid feature flags in {}", path.display()))?;
+    for (name, flag) in &flags {
+        ensure!(
+            flag.percent <= 100,
+            "feature flag {name} in {}: {}",
+            path.display(),
+            FeatureFlagError::InvalidPercent(flag.percent)
+        );
+    }
+    Ok(flags)
+}
+
+/// Whether the route `pattern` matches `path`, see the [module
+/// documentation](self).
+fn matches(pattern: &str, path: &str) -> bool {
+    let mut pattern = pattern.trim_matches('/').split('/');
+    let mut path = path.trim_matches('/').split('/');
+    loop {
+        match (pattern.next(), path.next()) {
+            (Some("*"), _) => return true,
+            (Some(expected), Some(segment)) => {
+                if !expected.starts_with(':') && expected != segment {
+                    return false;
+                }
+            }
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Whether the session with the bearer token `key` is among the `percent`
+/// sessions served by the flag `name`.
+fn in_rollout(name: &str, key: Option<&str>, percent: u8) -> bool {
+    let bucket = key.map_or_else(rand::random::<u16>, |key| {
+        let hash = Sha256::new()
+            .chain_update(name)
+            .chain_update([0])
+            .chain_update(key)
+            .finalize();
+        u16::from_be_bytes([hash[0], hash[1]])
+    });
+    bucket % 100 < u16::from(percent)
+}
+
+pub type SharedFeatureFlags = Arc<FeatureFlags>;
+
+/// The current flags, replaced as a whole on reload.
+#[derive(Debug, Default)]
+pub struct FeatureFlags {
+    path:  Option<PathBuf>,
+    flags: RwLock<Arc<Flags>>,
+}
+
+impl FeatureFlags {
+    /// Reads `--feature-flags-file`, without it every route is served.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not valid.
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        let flags = match &options.feature_flags_file {
+            Some(path) => flags_from_file(path)?,
+            None => Flags::default(),
+        };
+        Ok(Self {
+            path:  options.feature_flags_file.clone(),
+            flags: RwLock::new(Arc::new(flags)),
+        })
+    }
+
+    #[must_use]
+    pub fn current(&self) -> Arc<Flags> {
+        self.flags.read().unwrap().clone()
+    }
+
+    /// Reads the flags file again. The current flags, including those changed
+    /// by [`Self::set`], are kept if that fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not valid.
+    pub fn reload(&self) -> EyreResult<()> {
+        if let Some(path) = &self.path {
+            let flags = flags_from_file(path)?;
+            *self.flags.write().unwrap() = Arc::new(flags);
+        }
+        Ok(())
+    }
+
+    /// Enables or disables the flag `name`, for `percent` of the sessions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such flag or `percent` is above 100.
+    pub fn set(
+        &self,
+        name: &str,
+        enabled: bool,
+        percent: u8,
+    ) -> Result<FeatureFlag, FeatureFlagError> {
+        if percent > 100 {
+            return Err(FeatureFlagError::InvalidPercent(percent));
+        }
+        let mut guard = self.flags.write().unwrap();
+        let mut flags = Flags::clone(&guard);
+        let flag = flags.get_mut(name).ok_or(FeatureFlagError::Unknown)?;
+        flag.enabled = enabled;
+        flag.percent = percent;
+        let flag = flag.clone();
+        *guard = Arc::new(flags);
+        Ok(flag)
+    }
+
+    /// Whether a request to `path` by the session with the bearer token `key`
+    /// is served.
+    #[must_use]
+    pub fn allows(&self, path: &str, key: Option<&str>) -> bool {
+        self.current()
+            .iter()
+            .filter(|(_, flag)| flag.routes.iter().any(|route| matches(route, path)))
+            .all(|(name, flag)| {
+                flag.enabled && (flag.percent >= 100 || in_rollout(name, key, flag.percent))
+            })
+    }
+}
+
+/// Reloads the feature flags on every reload signal.
+pub async fn reload_on_signal(flags: SharedFeatureFlags) {
+    if flags.path.is_none() {
+        return;
+    }
+    let mut signals = match ReloadSignal::new() {
+        Ok(signals) => signals,
+        Err(error) => {
+            error!(
+                ?error,
+                "Failed to listen for {}, feature flags will not be reloaded",
+                reload_signal::NAME
+            );
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        match flags.reload() {
+            Ok(()) => info!("Reloaded feature flags"),
+            Err(error) => error!(?error, "Failed to reload feature flags"),
+        }
+    }
+}
+
+/// Middleware answering requests to routes of disabled flags. Must be
+/// installed on the router without the server path prefix, so that routes
+/// match.
+pub async fn gate_features<B>(
+    flags: SharedFeatureFlags,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let key = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let path = request.uri().path();
+    // Flags can not lock operators out of changing them back.
+    if path.starts_with("/admin/") || flags.allows(path, key) {
+        next.run(request).await
+    } else {
+        FeatureFlagError::Disabled.into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn gates_routes() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "[upload]\nroutes = [\"/contribute/upload/:id/:part\"]\n\n[ws]\nroutes = \
+             [\"/ws/*\"]\nenabled = false\n\n[half]\nroutes = [\"/info/statistics\"]\npercent = 50"
+        )
+        .unwrap();
+        let flags = FeatureFlags::new(&Options {
+            feature_flags_file: Some(file.path().to_path_buf()),
+        })
+        .unwrap();
+        assert!(flags.allows("/contribute/upload/abc/1", None));
+        assert!(flags.allows("/contribute/upload/abc", None));
+        assert!(!flags.allows("/ws/lobby", None));
+        assert!(flags.allows("/info/status", None));
+
+        let served = (0..1000)
+            .filter(|session| flags.allows("/info/statistics", Some(&session.to_string())))
+            .count();
+        assert!((400..600).contains(&served), "{served}");
+        for session in ["a", "b", "c"] {
+            assert_eq!(
+                flags.allows("/info/statistics", Some(session)),
+                flags.allows("/info/statistics", Some(session))
+            );
+        }
+
+        assert!(matches!(
+            flags.set("upload", true, 101),
+            Err(FeatureFlagError::InvalidPercent(101))
+        ));
+        assert!(matches!(
+            flags.set("unknown", true, 100),
+            Err(FeatureFlagError::Unknown)
+        ));
+        flags.set("upload", false, 100).unwrap();
+        assert!(!flags.allows("/contribute/upload/abc/1", None));
+
+        // Reloading drops the changes, invalid flags keep the current ones.
+        flags.reload().unwrap();
+        assert!(flags.allows("/contribute/upload/abc/1", None));
+        std::fs::write(file.path(), "[upload]\nroutes = []\npercent = 200\n").unwrap();
+        assert!(flags.reload().is_err());
+        assert!(!flags.allows("/ws/lobby", None));
+    }
+}
@@ -0,0 +1,175 @@
+//! Clusters of contributions that may come from the same machine.
+//!
+//! Clients may send an opaque machine fingerprint in the
+//! `X-Client-Fingerprint` header on `/contribute`. Its SHA-256 is stored in
+//! the `fingerprint_hash` column of `contributors`, together with the /24
+//! subnet of the client's IPv4 address, or the /48 of its IPv6 address, in
+//! `subnet`. The address itself is dropped with the request.
+//!
+//! `GET /admin/sybil_report` lists the fingerprints and subnets that several
+//! finished contributions share. The report is meant for the analysis of
+//! sybil behaviour after the ceremony, nobody is turned away for sharing a
+//! machine or a network: households, universities and VPNs do so honestly.
+
+use crate::client_ip::ipv6_prefix;
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::Infallible,
+    net::IpAddr,
+};
+
+pub const FINGERPRINT_HEADER: &str = "x-client-fingerprint";
+
+/// Prefix length of the IPv6 subnets, a site rather than a single client.
+const IPV6_SUBNET_PREFIX: u8 = 48;
+
+/// Extractor for the hex encoded SHA-256 of the `X-Client-Fingerprint`
+/// header. Requests without it, or with an empty or not visible ASCII one,
+/// have none.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FingerprintHeader(pub Option<String>);
+
+#[async_trait]
+impl<B> FromRequest<B> for FingerprintHeader
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        Ok(Self(
+            req.headers()
+                .get(FINGERPRINT_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| hex::encode(Sha256::digest(value.as_bytes()))),
+        ))
+    }
+}
+
+/// The subnet `ip` is in, as `203.0.113.0/24` or `2001:db8:1::/48`.
+#[must_use]
+pub fn subnet(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            format!("{a}.{b}.{c}.0/24")
+        }
+        IpAddr::V6(_) => format!(
+            "{}/{IPV6_SUBNET_PREFIX}",
+            ipv6_prefix(ip, IPV6_SUBNET_PREFIX)
+        ),
+    }
+}
+
+/// Contributors sharing a fingerprint or subnet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Cluster {
+    /// The fingerprint hash or subnet.
+    pub key:  String,
+    pub uids: Vec<String>,
+}
+
+/// The response of `/admin/sybil_report`. Largest clusters first.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SybilReport {
+    pub fingerprints: Vec<Cluster>,
+    pub subnets:      Vec<Cluster>,
+}
+
+impl SybilReport {
+    /// The report over finished contributions, as `(uid, fingerprint_hash,
+    /// subnet)`.
+    #[must_use]
+    pub fn new(rows: impl IntoIterator<Item = (String, Option<String>, Option<String>)>) -> Self {
+        let mut fingerprints = BTreeMap::<String, BTreeSet<String>>::new();
+        let mut subnets = BTreeMap::<String, BTreeSet<String>>::new();
+        for (uid, fingerprint_hash, subnet) in rows {
+            if let Some(fingerprint_hash) = fingerprint_hash {
+                fingerprints
+                    .entry(fingerprint_hash)
+                    .or_default()
+                    .insert(uid.clone());
+            }
+            if let Some(subnet) = subnet {
+                subnets.entry(subnet).or_default().insert(uid);
+            }
+        }
+        Self {
+            fingerprints: clusters(fingerprints),
+            subnets:      clusters(subnets),
+        }
+    }
+}
+
+/// The groups of more than one contributor, largest first.
+fn clusters(groups: BTreeMap<String, BTreeSet<String>>) -> Vec<Cluster> {
+    let mut clusters = groups
+        .into_iter()
+        .filter(|(_, uids)| uids.len() > 1)
+        .map(|(key, uids)| Cluster {
+            key,
+            uids: uids.into_iter().collect(),
+        })
+        .collect::<Vec<_>>();
+    // Stable, so clusters of the same size stay ordered by key.
+    clusters.sort_by(|a, b| b.uids.len().cmp(&a.uids.len()));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_subnets() {
+        assert_eq!(subnet("203.0.113.9".parse().unwrap()), "203.0.113.0/24");
+        assert_eq!(
+            subnet("2001:db8:1:2::9".parse().unwrap()),
+            "2001:db8:1::/48"
+        );
+    }
+
+    #[test]
+    fn clusters_shared_fingerprints_and_subnets() {
+        let row = |uid: &str, fingerprint: Option<&str>, subnet: &str| {
+            (
+                uid.to_string(),
+                fingerprint.map(ToString::to_string),
+                Some(subnet.to_string()),
+            )
+        };
+        let report = SybilReport::new([
+            row("git|1|a", Some("f1"), "10.0.0.0/24"),
+            row("git|2|b", Some("f1"), "10.0.1.0/24"),
+            row("git|3|c", Some("f1"), "10.0.1.0/24"),
+            row("eth|0x4", Some("f2"), "10.0.2.0/24"),
+            row("eth|0x5", None, "10.0.0.0/24"),
+            // Another attempt of the same contributor.
+            row("eth|0x4", Some("f2"), "10.0.2.0/24"),
+        ]);
+        assert_eq!(report.fingerprints, vec![Cluster {
+            key:  "f1".to_string(),
+            uids: vec![
+                "git|1|a".to_string(),
+                "git|2|b".to_string(),
+                "git|3|c".to_string()
+            ],
+        }]);
+        assert_eq!(
+            report
+                .subnets
+                .iter()
+                .map(|cluster| cluster.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["10.0.0.0/24", "10.0.1.0/24"]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Pruning of what sessions leave behind.
+//!
+//! Every `--gc-interval` the sessions idle for longer than
+//! `--session-expiration` are dropped from memory, restored sessions that were
+//! never claimed included, along with their rows in the `sessions` table.
+//! Rows of sessions that expired elsewhere, e.g. on a replica that crashed,
+//! are deleted by their `expires_at`, as are sign-in nonces that were never
+//! used. Lobby waits of sessions that are gone are ended with the outcome
+//! `timeout`, so that `/info/fairness` does not count them as waiting forever.
+
+use crate::{
+    fairness::WaitOutcome, lobby::SharedLobbyState, metrics::GC_PRUNED,
+    power_saving::SharedPowerSaving, storage::PersistentStorage,
+};
+use chrono::Utc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{error, info};
+
+/// What one round of garbage collection pruned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pruned {
+    /// Sessions dropped from memory.
+    pub sessions:        usize,
+    /// Rows of the `sessions` table deleted for having expired.
+    pub stored_sessions: usize,
+    pub lobby_waits:     usize,
+    pub auth_nonces:     usize,
+}
+
+impl Pruned {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+pub async fn collect_on_interval(
+    state: SharedLobbyState,
+    storage: PersistentStorage,
+    power_saving: SharedPowerSaving,
+) {
+    loop {
+        // Read on every round, as the options may be reloaded.
+        let options = state.options();
+        tokio::time::sleep(power_saving.poll_interval(options.gc_interval)).await;
+        let pruned = collect(&state, &storage, options.session_expiration).await;
+        if !pruned.is_empty() {
+            info!(?pruned, "Pruned stale sessions");
+        }
+    }
+}
+
+/// Runs one round of garbage collection, dropping the sessions idle for
+/// longer than `max_idle`. Failures are only logged, the next round tries
+/// again.
+pub async fn collect(
+    state: &SharedLobbyState,
+    storage: &PersistentStorage,
+    max_idle: Duration,
+) -> Pruned {
+    let now = Instant::now();
+    let dropped = state
+        .clear_session(|info| now - info.last_ping_time > max_idle)
+        .await;
+    for hash in &dropped {
+        if let Err(error) = storage.delete_session(hash).await {
+            error!(?error, "failed to delete expired session");
+        }
+    }
+    let mut pruned = Pruned {
+        sessions: dropped.len(),
+        ..Pruned::default()
+    };
+
+    match storage.delete_expired_sessions(Utc::now()).await {
+        Ok(deleted) => pruned.stored_sessions = deleted,
+        Err(error) => error!(?error, "failed to delete expired sessions"),
+    }
+    match storage.delete_expired_auth_nonces(Utc::now()).await {
+        Ok(deleted) => pruned.auth_nonces = deleted,
+        Err(error) => error!(?error, "failed to delete sign-in nonces"),
+    }
+    match storage.abandoned_lobby_waits().await {
+        Ok(abandoned) => {
+            // Sessions whose row could not be stored are still alive.
+            let known = state.session_hashes().await;
+            for hash in abandoned.iter().filter(|hash| !known.contains(*hash)) {
+                match storage
+                    .finish_lobby_wait(hash, WaitOutcome::Timeout.into(), Utc::now())
+                    .await
+                {
+                    Ok(wait) => pruned.lobby_waits += usize::from(wait.is_some()),
+                    Err(error) => {
+                        error!(?error, "failed to end lobby wait");
+                    }
+                }
+            }
+        }
+        Err(error) => error!(?error, "failed to list lobby waits"),
+    }
+
+    for (kind, count) in [
+        ("session", pruned.sessions),
+        ("stored_session", pruned.stored_sessions),
+        ("lobby_wait", pruned.lobby_waits),
+        ("auth_nonce", pruned.auth_nonces),
+    ] {
+        GC_PRUNED
+            .with_label_values(&[kind])
+            .inc_by(u64::try_from(count).unwrap_or(u64::MAX));
+    }
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sessions::SessionId,
+        storage::{storage_client, StoredSession},
+        test_util::{create_test_session_info, test_options},
+    };
+
+    #[tokio::test]
+    async fn prunes_stale_sessions() {
+        let options = test_options();
+        let storage = storage_client(&options.storage).await.unwrap();
+        let state = SharedLobbyState::new(options.lobby.clone());
+        let idle = SessionId::new();
+        let active = SessionId::new();
+        for id in [&idle, &active] {
+            state
+                .insert_session(id.clone(), create_test_session_info(100))
+                .await
+                .unwrap();
+            storage
+                .save_session(&StoredSession {
+                    token_hash:        id.hash(),
+                    uid:               "git|1|alice".to_string(),
+                    identity:          None,
+                    expires_at:        Utc::now() + chrono::Duration::hours(1),
+                    lobby_entered_at:  None,
+                    eligibility_score: None,
+                })
+                .await
+                .unwrap();
+        }
+        state
+            .modify_participant(&idle, |info| {
+                info.last_ping_time = Instant::now() - Duration::from_secs(120);
+            })
+            .await
+            .unwrap();
+        // Left over by a crashed replica.
+        storage
+            .save_session(&StoredSession {
+                token_hash:        "expired".to_string(),
+                uid:               "git|2|bob".to_string(),
+                identity:          None,
+                expires_at:        Utc::now() - chrono::Duration::seconds(1),
+                lobby_entered_at:  None,
+                eligibility_score: None,
+            })
+            .await
+            .unwrap();
+        for hash in [idle.hash(), active.hash(), "gone".to_string()] {
+            storage
+                .start_lobby_wait(&hash, "Github", Utc::now())
+                .await
+                .unwrap();
+        }
+        storage
+            .insert_auth_nonce("unused", Utc::now() - chrono::Duration::seconds(1))
+            .await
+            .unwrap();
+
+        let pruned = collect(&state, &storage, Duration::from_secs(60)).await;
+        assert_eq!(pruned, Pruned {
+            sessions:        1,
+            stored_sessions: 1,
+            lobby_waits:     2,
+            auth_nonces:     1,
+        });
+        assert_eq!(state.session_hashes().await.len(), 1);
+        let waiting = storage
+            .lobby_waits()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|wait| wait.outcome.is_none())
+            .count();
+        assert_eq!(waiting, 1);
+        assert_eq!(
+            collect(&state, &storage, Duration::from_secs(60)).await,
+            Pruned::default()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Coarse location of contributors, for the country statistics of
+//! `/info/statistics`.
+//!
+//! `--geoip-database` is an IP to country CSV in the format of the free DB-IP
+//! "IP to Country Lite" database: one `first_ip,last_ip,country` range per
+//! line, IPv4 and IPv6 mixed. The database is not shipped with the sequencer,
+//! as it is updated monthly. Only the two letter country code of a contributor
+//! is stored: the address is looked up when the contribution is submitted and
+//! then dropped.
+
+use clap::Parser;
+use eyre::{bail, eyre, Result as EyreResult, WrapErr};
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::info;
+
+/// Country code DB-IP uses for unassigned ranges.
+const UNKNOWN_COUNTRY: &str = "ZZ";
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// IP to country CSV the contributor countries are looked up in. No
+    /// countries are recorded without it.
+    #[clap(long, env)]
+    pub geoip_database: Option<PathBuf>,
+}
+
+/// Sorted, non-overlapping address ranges. IPv4 addresses are stored as
+/// IPv4-mapped IPv6 addresses, so both share one table.
+#[derive(Debug, Default)]
+pub struct GeoIp {
+    ranges: Vec<(u128, u128, [u8; 2])>,
+}
+
+pub type SharedGeoIp = Arc<GeoIp>;
+
+impl GeoIp {
+    /// Loads `--geoip-database`, or an empty database if it is not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or parsed.
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        match &options.geoip_database {
+            Some(path) => Self::from_file(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn from_file(path: &Path) -> EyreResult<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read geoip database {}", path.display()))?;
+        let geoip = Self::parse(&contents)
+            .wrap_err_with(|| format!("invalid geoip database {}", path.display()))?;
+        info!(ranges = geoip.ranges.len(), "Loaded geoip database");
+        Ok(geoip)
+    }
+
+    fn parse(contents: &str) -> EyreResult<Self> {
+        let mut ranges = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let range = parse_range(line).wrap_err_with(|| format!("line {}", number + 1))?;
+            if let Some(range) = range {
+                ranges.push(range);
+            }
+        }
+        ranges.sort_unstable();
+        if let Some(pair) = ranges.windows(2).find(|pair| pair[0].1 >= pair[1].0) {
+            bail!("overlapping ranges starting at {}", to_ip(pair[1].0));
+        }
+        Ok(Self { ranges })
+    }
+
+    /// Two letter country code of `ip`, if it is in the database.
+    #[must_use]
+    pub fn lookup(&self, ip: IpAddr) -> Option<&str> {
+        let ip = to_u128(ip);
+        let index = self.ranges.partition_point(|(first, ..)| *first <= ip);
+        let (_, last, country) = self.ranges.get(index.checked_sub(1)?)?;
+        if ip > *last {
+            return None;
+        }
+        std::str::from_utf8(country).ok()
+    }
+}
+
+/// Parses a `first_ip,last_ip,country` line. Returns `None` for unassigned
+/// ranges.
+fn parse_range(line: &str) -> EyreResult<Option<(u128, u128, [u8; 2])>> {
+    let mut fields = line.split(',').map(|field| field.trim().trim_matches('"'));
+    let (first, last, country) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(first), Some(last), Some(country)) => (first, last, country),
+        _ => bail!("expected first_ip,last_ip,country"),
+    };
+    let first = to_u128(first.parse().wrap_err("invalid first ip")?);
+    let last = to_u128(last.parse().wrap_err("invalid last ip")?);
+    if first > last {
+        bail!("range ends before it starts");
+    }
+    if country == UNKNOWN_COUNTRY {
+        return Ok(None);
+    }
+    let country: [u8; 2] = country
+        .as_bytes()
+        .try_into()
+        .ok()
+        .filter(|code: &[u8; 2]| code.iter().all(u8::is_ascii_uppercase))
+        .ok_or_else(|| eyre!("invalid country code {country}"))?;
+    Ok(Some((first, last, country)))
+}
+
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().into(),
+        IpAddr::V6(ip) => ip.into(),
+    }
+}
+
+fn to_ip(ip: u128) -> IpAddr {
+    let ip = std::net::Ipv6Addr::from(ip);
+    ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_countries() {
+        let database = [
+            "1.0.0.0,1.0.0.255,AU",
+            "",
+            "\"2.0.0.0\",\"2.0.255.255\",\"FR\"",
+            "3.0.0.0,3.0.0.255,ZZ",
+            "2001:db8::,2001:db8::ffff,DE",
+        ];
+        let geoip = GeoIp::parse(&database.join("\n")).unwrap();
+        let country = |ip: &str| geoip.lookup(ip.parse().unwrap());
+        assert_eq!(country("1.0.0.7"), Some("AU"));
+        assert_eq!(country("2.0.255.255"), Some("FR"));
+        assert_eq!(country("2001:db8::1"), Some("DE"));
+        assert_eq!(country("1.0.1.0"), None);
+        assert_eq!(country("3.0.0.1"), None);
+        assert_eq!(country("0.0.0.1"), None);
+        assert_eq!(GeoIp::default().lookup("1.0.0.7".parse().unwrap()), None);
+
+        assert!(GeoIp::parse("1.0.0.0,1.0.0.255,australia").is_err());
+        assert!(GeoIp::parse("1.0.0.255,1.0.0.0,AU").is_err());
+        assert!(GeoIp::parse("1.0.0.0,1.0.0.255,AU\n1.0.0.128,1.0.1.0,NZ").is_err());
+    }
+}
@@ -0,0 +1,737 @@
+//! gRPC API, for clients that prefer strongly typed messages and streams to
+//! large JSON bodies. Only built with the `grpc` feature.
+//!
+//! With `--grpc-address`, the `kzg.sequencer.v1.Sequencer` service of
+//! `proto/sequencer.proto` is served next to the HTTP API. Every call is
+//! dispatched to the HTTP router, so both share the state of the ceremonies
+//! and each call goes through the checks and limits of its route. Metadata is
+//! passed on as headers. Errors are answered with the status closest to the
+//! HTTP one, the message of the error and its stable code (see
+//! [`ApiError`](crate::api::v1::error_response::ApiError)) in the
+//! `x-error-code` metadata.
+
+use crate::{
+    ceremony::CeremonyId,
+    contribution_format::{self, BINARY_CONTENT_TYPE},
+    lobby::SharedLobbyState,
+    reservation::RESERVATION_HEADER,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::ConnectInfo,
+    http::{
+        header::{ACCEPT, ACCEPT_ENCODING, CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, Method, Request, StatusCode,
+    },
+    Router,
+};
+use clap::Parser;
+use eyre::{Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{
+    curve,
+    signature::{BlsSignature, EcdsaSignature},
+    BatchContribution, Contribution, Powers, G1, G2,
+};
+use serde::Deserialize;
+use std::{net::SocketAddr, time::Duration};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{
+    async_trait,
+    metadata::{MetadataMap, MetadataValue},
+    Code, Request as GrpcRequest, Response as GrpcResponse, Status, Streaming,
+};
+use tower::ServiceExt;
+use tracing::{error, info};
+use url::form_urlencoded;
+
+#[allow(
+    clippy::all,
+    clippy::pedantic,
+    clippy::nursery,
+    missing_docs,
+    unreachable_pub
+)]
+pub mod proto {
+    tonic::include_proto!("kzg.sequencer.v1");
+}
+
+use proto::{
+    lobby_update::Update,
+    sequencer_server::{Sequencer, SequencerServer},
+};
+
+/// Metadata selecting the ceremony of a call, the default one if absent.
+const CEREMONY_METADATA: &str = "x-ceremony";
+
+/// Metadata of errors with their stable code.
+const ERROR_CODE_METADATA: &str = "x-error-code";
+
+/// Code of the error answered to check-ins that came too early.
+const RATE_LIMITED: &str = "SEQ-LOBBY-002";
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Address to serve the gRPC API on, e.g. `0.0.0.0:3001`. It is not
+    /// served unless set.
+    #[clap(long, env)]
+    pub grpc_address: Option<SocketAddr>,
+}
+
+/// An error answered by the HTTP API.
+#[derive(Debug)]
+struct ApiFailure {
+    status:      StatusCode,
+    code:        Option<String>,
+    message:     String,
+    retry_after: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    code:  String,
+    error: String,
+}
+
+impl ApiFailure {
+    fn new(status: StatusCode, headers: &HeaderMap, body: &[u8]) -> Self {
+        let (code, message) = match serde_json::from_slice::<ErrorBody>(body) {
+            Ok(body) => (Some(body.code), body.error),
+            Err(_) => (None, String::from_utf8_lossy(body).into_owned()),
+        };
+        Self {
+            status,
+            code,
+            message,
+            retry_after: headers
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string),
+        }
+    }
+}
+
+/// The gRPC status closest to an HTTP one. Successful statuses carry the
+/// errors of the lobby that mean to wait.
+const fn grpc_code(status: StatusCode) -> Code {
+    match status {
+        StatusCode::BAD_REQUEST
+        | StatusCode::PAYLOAD_TOO_LARGE
+        | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::CONFLICT => Code::Aborted,
+        StatusCode::GONE | StatusCode::PRECONDITION_FAILED | StatusCode::PRECONDITION_REQUIRED => {
+            Code::FailedPrecondition
+        }
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+        _ if status.is_success() => Code::FailedPrecondition,
+        _ => Code::Internal,
+    }
+}
+
+impl From<ApiFailure> for Status {
+    fn from(failure: ApiFailure) -> Self {
+        let mut metadata = MetadataMap::new();
+        let values = [
+            (ERROR_CODE_METADATA, failure.code),
+            ("retry-after", failure.retry_after),
+        ];
+        for (key, value) in values {
+            if let Some(value) = value.and_then(|value| MetadataValue::try_from(value).ok()) {
+                metadata.insert(key, value);
+            }
+        }
+        Self::with_metadata(grpc_code(failure.status), failure.message, metadata)
+    }
+}
+
+/// A successful answer of the HTTP API.
+struct Reply {
+    headers: HeaderMap,
+    body:    Bytes,
+}
+
+impl Reply {
+    fn json<T: for<'de> Deserialize<'de>>(&self) -> Result<T, Status> {
+        serde_json::from_slice(&self.body)
+            .map_err(|error| Status::internal(format!("unexpected response: {error}")))
+    }
+}
+
+#[derive(Deserialize)]
+struct ReceiptJson {
+    receipt:   String,
+    signature: String,
+}
+
+impl From<ReceiptJson> for proto::Receipt {
+    fn from(receipt: ReceiptJson) -> Self {
+        Self {
+            receipt:   receipt.receipt,
+            signature: receipt.signature,
+        }
+    }
+}
+
+/// The call being served, as far as it matters to the HTTP API.
+struct Call {
+    metadata: MetadataMap,
+    remote:   Option<SocketAddr>,
+}
+
+impl Call {
+    fn new<T>(request: &GrpcRequest<T>) -> Self {
+        Self {
+            metadata: request.metadata().clone(),
+            remote:   request.remote_addr(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SequencerService {
+    router:           Router,
+    /// Path of the server url, without the trailing slash.
+    prefix:           String,
+    /// The lobbies of the ceremonies, whose check-in frequency changes with
+    /// the tuning and with configuration reloads.
+    lobbies:          Vec<(CeremonyId, SharedLobbyState)>,
+    /// Check-in interval of ceremonies without a lobby.
+    checkin_interval: Duration,
+}
+
+impl SequencerService {
+    /// Serves calls with `router`, the complete router of the HTTP API, for
+    /// the ceremonies with `lobbies`.
+    #[must_use]
+    pub fn new(
+        router: Router,
+        lobbies: Vec<(CeremonyId, SharedLobbyState)>,
+        options: &crate::Options,
+    ) -> Self {
+        Self {
+            router,
+            prefix: options.server.path().trim_end_matches('/').to_string(),
+            lobbies,
+            checkin_interval: options.lobby.lobby_checkin_frequency,
+        }
+    }
+
+    /// The current check-in frequency of the lobby `call` is for.
+    fn checkin_interval(&self, call: &Call) -> Duration {
+        let ceremony = ceremony(call).unwrap_or_default();
+        self.lobbies
+            .iter()
+            .find(|(id, _)| *id == ceremony)
+            .map_or(self.checkin_interval, |(_, lobby_state)| {
+                lobby_state.options().lobby_checkin_frequency
+            })
+    }
+
+    async fn dispatch(
+        &self,
+        call: &Call,
+        method: Method,
+        route: &str,
+        body: Option<(&'static str, Vec<u8>)>,
+    ) -> Result<Result<Reply, ApiFailure>, Status> {
+        let ceremony = ceremony(call)?;
+        let uri = format!("{}{}{route}", self.prefix, ceremony.path_prefix());
+
+        let mut headers = call.metadata.clone().into_headers();
+        for name in [CONTENT_TYPE, ACCEPT, ACCEPT_ENCODING] {
+            headers.remove(name);
+        }
+        headers.remove("te");
+        // Contributions are exchanged in the binary encoding, the other
+        // routes ignore `Accept`.
+        headers.insert(ACCEPT, BINARY_CONTENT_TYPE.parse().expect("valid header"));
+        let body = match body {
+            Some((content_type, bytes)) => {
+                headers.insert(CONTENT_TYPE, content_type.parse().expect("valid header"));
+                Body::from(bytes)
+            }
+            None => Body::empty(),
+        };
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(body)
+            .map_err(|error| Status::invalid_argument(error.to_string()))?;
+        *request.headers_mut() = headers;
+        if let Some(remote) = call.remote {
+            request.extensions_mut().insert(ConnectInfo(remote));
+        }
+
+        let response = match self.router.clone().oneshot(request).await {
+            Ok(response) => response,
+            Err(infallible) => match infallible {},
+        };
+        let (parts, body) = response.into_parts();
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(|error| Status::internal(error.to_string()))?;
+        Ok(if parts.status.is_success() {
+            Ok(Reply {
+                headers: parts.headers,
+                body,
+            })
+        } else {
+            Err(ApiFailure::new(parts.status, &parts.headers, &body))
+        })
+    }
+
+    async fn call(
+        &self,
+        call: &Call,
+        method: Method,
+        route: &str,
+        body: Option<(&'static str, Vec<u8>)>,
+    ) -> Result<Reply, Status> {
+        Ok(self.dispatch(call, method, route, body).await??)
+    }
+
+    /// Checks in at the lobby until the slot is granted, see
+    /// `Sequencer::join_lobby`.
+    async fn check_in(
+        &self,
+        call: Call,
+        updates: mpsc::Sender<Result<proto::LobbyUpdate, Status>>,
+    ) {
+        loop {
+            let update = self.check_in_once(&call).await;
+            let done = !matches!(update, Ok(Update::Waiting(_)));
+            let update = update.map(|update| proto::LobbyUpdate {
+                update: Some(update),
+            });
+            if updates.send(update).await.is_err() || done {
+                return;
+            }
+            tokio::select! {
+                () = tokio::time::sleep(self.checkin_interval(&call)) => {}
+                // The client hung up.
+                () = updates.closed() => return,
+            }
+        }
+    }
+
+    async fn check_in_once(&self, call: &Call) -> Result<Update, Status> {
+        let reply = match self
+            .dispatch(call, Method::POST, "/lobby/try_contribute", None)
+            .await?
+        {
+            Ok(reply) => reply,
+            // The interval only is checked with a tolerance.
+            Err(failure) if failure.code.as_deref() == Some(RATE_LIMITED) => {
+                return self.waiting(call, RATE_LIMITED.to_string()).await;
+            }
+            Err(failure) => return Err(failure.into()),
+        };
+        match reply.headers.get(RESERVATION_HEADER) {
+            Some(token) => {
+                // Without a body if the client prefetched the powers.
+                let contribution = if reply.body.is_empty() {
+                    None
+                } else {
+                    let contribution = contribution_format::decode(&reply.body)
+                        .map_err(|error| Status::internal(error.to_string()))?;
+                    Some(contribution.into())
+                };
+                Ok(Update::SlotGranted(proto::SlotGranted {
+                    contribution,
+                    reservation_token: token.to_str().unwrap_or_default().to_string(),
+                }))
+            }
+            None => {
+                let body = reply.json::<ErrorBody>()?;
+                self.waiting(call, body.code).await
+            }
+        }
+    }
+
+    async fn waiting(&self, call: &Call, code: String) -> Result<Update, Status> {
+        #[derive(Deserialize)]
+        struct Position {
+            position:               u64,
+            lobby_size:             u64,
+            estimated_wait_seconds: Option<u64>,
+        }
+        let position = self
+            .call(call, Method::GET, "/lobby/position", None)
+            .await?
+            .json::<Position>()?;
+        Ok(Update::Waiting(proto::Waiting {
+            code,
+            position: position.position,
+            lobby_size: position.lobby_size,
+            estimated_wait_seconds: position.estimated_wait_seconds,
+        }))
+    }
+}
+
+#[async_trait]
+impl Sequencer for SequencerService {
+    type JoinLobbyStream = ReceiverStream<Result<proto::LobbyUpdate, Status>>;
+
+    async fn status(
+        &self,
+        request: GrpcRequest<proto::StatusRequest>,
+    ) -> Result<GrpcResponse<proto::StatusResponse>, Status> {
+        #[derive(Deserialize)]
+        struct StatusJson {
+            lobby_size:        u64,
+            num_contributions: u64,
+            sequencer_address: String,
+        }
+        let status = self
+            .call(&Call::new(&request), Method::GET, "/info/status", None)
+            .await?
+            .json::<StatusJson>()?;
+        Ok(GrpcResponse::new(proto::StatusResponse {
+            lobby_size:        status.lobby_size,
+            num_contributions: status.num_contributions,
+            sequencer_address: status.sequencer_address,
+        }))
+    }
+
+    async fn join_lobby(
+        &self,
+        request: GrpcRequest<proto::JoinLobbyRequest>,
+    ) -> Result<GrpcResponse<Self::JoinLobbyStream>, Status> {
+        let call = Call::new(&request);
+        let (sender, receiver) = mpsc::channel(1);
+        let service = self.clone();
+        tokio::spawn(async move { service.check_in(call, sender).await });
+        Ok(GrpcResponse::new(ReceiverStream::new(receiver)))
+    }
+
+    async fn ping(
+        &self,
+        request: GrpcRequest<proto::PingRequest>,
+    ) -> Result<GrpcResponse<proto::PingResponse>, Status> {
+        #[derive(Deserialize)]
+        struct Deadline {
+            deadline:     String,
+            remaining_ms: u64,
+        }
+        let deadline = self
+            .call(
+                &Call::new(&request),
+                Method::POST,
+                "/contribute/heartbeat",
+                None,
+            )
+            .await?
+            .json::<Deadline>()?;
+        Ok(GrpcResponse::new(proto::PingResponse {
+            deadline:     deadline.deadline,
+            remaining_ms: deadline.remaining_ms,
+        }))
+    }
+
+    async fn contribute(
+        &self,
+        request: GrpcRequest<Streaming<proto::ContributeRequest>>,
+    ) -> Result<GrpcResponse<proto::Receipt>, Status> {
+        let call = Call::new(&request);
+        let mut stream = request.into_inner();
+        let mut batch = proto::BatchContribution::default();
+        while let Some(message) = stream.message().await? {
+            batch.contributions.extend(message.contribution);
+            if !message.ecdsa_signature.is_empty() {
+                batch.ecdsa_signature = message.ecdsa_signature;
+            }
+            if message.entropy_attestation.is_some() {
+                batch.entropy_attestation = message.entropy_attestation;
+            }
+        }
+        let contribution = BatchContribution::try_from(batch)?;
+        let body = contribution_format::encode(&contribution);
+        drop(contribution);
+        let receipt = self
+            .call(
+                &call,
+                Method::POST,
+                "/contribute",
+                Some((BINARY_CONTENT_TYPE, body)),
+            )
+            .await?
+            .json::<ReceiptJson>()?;
+        Ok(GrpcResponse::new(receipt.into()))
+    }
+
+    async fn get_receipt(
+        &self,
+        request: GrpcRequest<proto::GetReceiptRequest>,
+    ) -> Result<GrpcResponse<proto::Receipt>, Status> {
+        let uid: String =
+            form_urlencoded::byte_serialize(request.get_ref().uid.as_bytes()).collect();
+        let receipt = self
+            .call(
+                &Call::new(&request),
+                Method::GET,
+                &format!("/contribution/receipt/{uid}"),
+                None,
+            )
+            .await?
+            .json::<ReceiptJson>()?;
+        Ok(GrpcResponse::new(receipt.into()))
+    }
+}
+
+impl From<Contribution> for proto::Contribution {
+    fn from(contribution: Contribution) -> Self {
+        Self {
+            g1_powers:     contribution
+                .powers
+                .g1
+                .iter()
+                .map(|p| p.encoded().to_vec())
+                .collect(),
+            g2_powers:     contribution
+                .powers
+                .g2
+                .iter()
+                .map(|p| p.encoded().to_vec())
+                .collect(),
+            pot_pubkey:    contribution.pot_pubkey.encoded().to_vec(),
+            bls_signature: contribution
+                .bls_signature
+                .0
+                .map_or_else(Vec::new, |signature| signature.encoded().to_vec()),
+        }
+    }
+}
+
+impl From<BatchContribution> for proto::BatchContribution {
+    fn from(contribution: BatchContribution) -> Self {
+        Self {
+            contributions:       contribution
+                .contributions
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            ecdsa_signature:     contribution
+                .ecdsa_signature
+                .0
+                .map_or_else(Vec::new, |signature| <[u8; 65]>::from(signature).to_vec()),
+            entropy_attestation: contribution.entropy_attestation,
+        }
+    }
+}
+
+fn point<T>(
+    bytes: &[u8],
+    size: usize,
+    point: fn(&[u8]) -> Option<T>,
+    what: &str,
+) -> Result<T, Status> {
+    point(bytes).ok_or_else(|| {
+        Status::invalid_argument(format!("{what} must be {size} bytes, not {}", bytes.len()))
+    })
+}
+
+fn g1(bytes: &[u8], what: &str) -> Result<G1, Status> {
+    point(bytes, curve().g1_size(), G1::from_encoded, what)
+}
+
+fn g2(bytes: &[u8], what: &str) -> Result<G2, Status> {
+    point(bytes, curve().g2_size(), G2::from_encoded, what)
+}
+
+impl TryFrom<proto::Contribution> for Contribution {
+    type Error = Status;
+
+    fn try_from(contribution: proto::Contribution) -> Result<Self, Status> {
+        let g1 = contribution
+            .g1_powers
+            .iter()
+            .map(|p| g1(p, "G1 powers"))
+            .collect::<Result<_, _>>()?;
+        let g2 = contribution
+            .g2_powers
+            .iter()
+            .map(|p| g2(p, "G2 powers"))
+            .collect::<Result<_, _>>()?;
+        let bls_signature = if contribution.bls_signature.is_empty() {
+            None
+        } else {
+            Some(g1(&contribution.bls_signature, "BLS signatures")?)
+        };
+        Ok(Self {
+            powers:        Powers { g1, g2 },
+            pot_pubkey:    g2(&contribution.pot_pubkey, "pot_pubkey")?,
+            bls_signature: BlsSignature(bls_signature),
+        })
+    }
+}
+
+impl TryFrom<proto::BatchContribution> for BatchContribution {
+    type Error = Status;
+
+    fn try_from(contribution: proto::BatchContribution) -> Result<Self, Status> {
+        let ecdsa_signature = if contribution.ecdsa_signature.is_empty() {
+            None
+        } else {
+            Some(
+                ethers_core::types::Signature::try_from(&contribution.ecdsa_signature[..])
+                    .map_err(|error| Status::invalid_argument(error.to_string()))?,
+            )
+        };
+        Ok(Self {
+            contributions:       contribution
+                .contributions
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            ecdsa_signature:     EcdsaSignature(ecdsa_signature),
+            entropy_attestation: contribution.entropy_attestation,
+        })
+    }
+}
+
+/// The ceremony `call` is for, from its `x-ceremony` metadata.
+fn ceremony(call: &Call) -> Result<CeremonyId, Status> {
+    Ok(call
+        .metadata
+        .get(CEREMONY_METADATA)
+        .map(|id| {
+            id.to_str()
+                .map(|id| CeremonyId(Some(id.to_string())))
+                .map_err(|_| Status::invalid_argument("invalid x-ceremony"))
+        })
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// The gRPC server, stopped with [`Self::stop`].
+pub struct GrpcServer {
+    shutdown: oneshot::Sender<()>,
+    server:   JoinHandle<()>,
+}
+
+impl GrpcServer {
+    /// Binds `--grpc-address`, if set, and serves the gRPC API with
+    /// `router`, for the ceremonies with `lobbies`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address can not be bound.
+    pub async fn start(
+        options: &crate::Options,
+        router: Router,
+        lobbies: Vec<(CeremonyId, SharedLobbyState)>,
+    ) -> EyreResult<Option<Self>> {
+        let addr = match options.grpc.grpc_address {
+            Some(addr) => addr,
+            None => return Ok(None),
+        };
+        let listener = TcpListener::bind(addr)
+            .await
+            .wrap_err_with(|| format!("failed to bind {addr}"))?;
+        info!("Serving gRPC on {}", listener.local_addr()?);
+        let service = SequencerServer::new(SequencerService::new(router, lobbies, options));
+        let (shutdown, stopped) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            let result = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async {
+                    stopped.await.ok();
+                })
+                .await;
+            if let Err(error) = result {
+                error!(?error, "gRPC server failed");
+            }
+        });
+        Ok(Some(Self { shutdown, server }))
+    }
+
+    /// Stops accepting calls and waits for those in progress.
+    pub async fn stop(self) {
+        self.shutdown.send(()).ok();
+        self.server.await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lobby_tuning::LobbyTuning,
+        test_util::test_options,
+        tests::{test_transcript, valid_contribution},
+    };
+
+    #[tokio::test]
+    async fn checks_in_at_the_tuned_frequency() {
+        let options = test_options();
+        let lobby_state = SharedLobbyState::new(options.lobby.clone());
+        let service = SequencerService::new(
+            Router::new(),
+            vec![(CeremonyId::default(), lobby_state.clone())],
+            &options,
+        );
+        let call = Call {
+            metadata: MetadataMap::new(),
+            remote:   None,
+        };
+        assert_eq!(
+            service.checkin_interval(&call),
+            options.lobby.lobby_checkin_frequency
+        );
+        lobby_state.set_tuning(Some(LobbyTuning {
+            max_lobby_size:          10,
+            lobby_checkin_frequency: Duration::from_secs(90),
+        }));
+        assert_eq!(service.checkin_interval(&call), Duration::from_secs(90));
+    }
+
+    #[test]
+    fn converts_contributions() {
+        let mut contribution = valid_contribution(&test_transcript(), 1);
+        contribution.entropy_attestation = Some("blst 0.3.10".to_string());
+        let message = proto::BatchContribution::from(contribution.clone());
+        assert_eq!(message.contributions[0].g1_powers[0].len(), 48);
+        assert_eq!(
+            BatchContribution::try_from(message.clone()).unwrap(),
+            contribution
+        );
+
+        let mut truncated = message;
+        truncated.contributions[0].pot_pubkey.pop();
+        assert_eq!(
+            BatchContribution::try_from(truncated).unwrap_err().code(),
+            Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn keeps_error_codes() {
+        let body = br#"{"code":"SEQ-LOBBY-001","kind":"TryContributeError::LobbyIsFull","error":"lobby is full"}"#;
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "5".parse().unwrap());
+        let status = Status::from(ApiFailure::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            &headers,
+            body,
+        ));
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "lobby is full");
+        assert_eq!(
+            status.metadata().get(ERROR_CODE_METADATA).unwrap(),
+            "SEQ-LOBBY-001"
+        );
+        assert_eq!(status.metadata().get("retry-after").unwrap(), "5");
+        assert_eq!(grpc_code(StatusCode::UNAUTHORIZED), Code::Unauthenticated);
+    }
+}
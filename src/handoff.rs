@@ -0,0 +1,248 @@
+//! The start of the next contribution, prepared before its slot is handed
+//! out.
+//!
+//! A verified contribution is stored first, with
+//! [`append_verified_entry`](crate::storage::PersistentStorage::append_verified_entry),
+//! and only then applied to the transcript, so that a failed write leaves the
+//! transcript as the database has it. It is then checkpointed and written to
+//! the transcript file before the slot is handed off. Meanwhile the
+//! contribution base of the next contributor is prepared on the blocking
+//! pool: serialized as JSON and in the binary encoding, the JSON compressed
+//! with gzip and brotli, together with its powers tag and the slot its
+//! reservation is for. `/lobby/try_contribute` and `/lobby/powers` hand it
+//! out as is, so that taking the slot does not wait for the powers to be
+//! copied out of the transcript, encoded and compressed. Bases are also
+//! prepared on startup and after contributions that come in otherwise, e.g.
+//! replicated ones. Requests that come in before the base is ready wait for
+//! it.
+
+use crate::{
+    api::v1::lobby::powers_tag,
+    compression::Encoding,
+    contribution_format::{self, ContributionEncoding, BINARY_CONTENT_TYPE},
+    lobby::{LobbyEvent, SharedLobbyState},
+    reservation::Reservation,
+    SharedTranscript,
+};
+use axum::{
+    body::Bytes,
+    response::{IntoResponse, Response},
+};
+use http::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE, ETAG, VARY},
+    StatusCode,
+};
+use kzg_ceremony_crypto::BatchTranscript;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast::error::RecvError, Mutex},
+    task::JoinError,
+};
+use tracing::{info, warn};
+
+/// The contribution base of a slot, encoded ahead of time.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PreparedBase {
+    /// The slot reservations handed out with the base are for, the number of
+    /// participants plus one.
+    pub slot:       usize,
+    /// See [`powers_tag`].
+    pub powers_tag: String,
+    json:           Bytes,
+    binary:         Bytes,
+    gzip:           Bytes,
+    brotli:         Bytes,
+}
+
+impl PreparedBase {
+    /// Encodes the contribution base of `transcript`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if compressing into memory fails, which it does not.
+    #[must_use]
+    pub fn new(transcript: &BatchTranscript) -> Self {
+        let contribution = transcript.contribution();
+        let json = serde_json::to_vec(&contribution).expect("contributions serialize");
+        let compress = |encoding: Encoding| -> Bytes {
+            encoding
+                .compress(&json)
+                .expect("compressing into memory does not fail")
+                .into()
+        };
+        Self {
+            slot:       transcript.num_participants() + 1,
+            powers_tag: powers_tag(transcript),
+            binary:     contribution_format::encode(&contribution).into(),
+            gzip:       compress(Encoding::Gzip),
+            brotli:     compress(Encoding::Brotli),
+            json:       json.into(),
+        }
+    }
+
+    /// The reservation of the slot for `uid`.
+    #[must_use]
+    pub fn reservation(&self, uid: String, valid_for: Duration) -> Reservation {
+        Reservation::new(uid, self.slot, valid_for)
+    }
+
+    /// The base in `encoding`. JSON is sent compressed if the client accepts
+    /// `compression`.
+    #[must_use]
+    pub fn respond(
+        &self,
+        encoding: ContributionEncoding,
+        compression: Option<Encoding>,
+    ) -> Response {
+        let tag = [(ETAG, self.powers_tag.clone())];
+        match (encoding, compression) {
+            (ContributionEncoding::Binary, _) => (
+                StatusCode::OK,
+                tag,
+                [(CONTENT_TYPE, BINARY_CONTENT_TYPE)],
+                self.binary.clone(),
+            )
+                .into_response(),
+            (ContributionEncoding::Json, None) => (
+                StatusCode::OK,
+                tag,
+                [(CONTENT_TYPE, "application/json")],
+                self.json.clone(),
+            )
+                .into_response(),
+            (ContributionEncoding::Json, Some(compression)) => (
+                StatusCode::OK,
+                tag,
+                [
+                    (CONTENT_TYPE, "application/json"),
+                    (CONTENT_ENCODING, compression.as_str()),
+                    (VARY, "accept-encoding"),
+                ],
+                match compression {
+                    Encoding::Gzip => self.gzip.clone(),
+                    Encoding::Brotli => self.brotli.clone(),
+                },
+            )
+                .into_response(),
+        }
+    }
+}
+
+pub type SharedHandoff = Arc<Handoff>;
+
+/// The prepared base of the latest transcript.
+#[derive(Debug, Default)]
+pub struct Handoff {
+    prepared:  RwLock<Option<Arc<PreparedBase>>>,
+    /// Held while a base is prepared, so that requests wait for it instead
+    /// of preparing it once more.
+    preparing: Mutex<()>,
+}
+
+impl Handoff {
+    /// The base of `slot`, if it is ready.
+    #[must_use]
+    pub fn get(&self, slot: usize) -> Option<Arc<PreparedBase>> {
+        let prepared = self.prepared.read().unwrap().clone()?;
+        (prepared.slot == slot).then(|| prepared)
+    }
+
+    /// The base of the current transcript, prepared unless it is ready.
+    /// Bases of older transcripts do not replace newer ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the blocking task preparing the base panics.
+    pub async fn prepare(
+        &self,
+        transcript: &SharedTranscript,
+    ) -> Result<Arc<PreparedBase>, JoinError> {
+        let _preparing = self.preparing.lock().await;
+        let transcript = transcript.clone().read_owned().await;
+        if let Some(prepared) = self.get(transcript.num_participants() + 1) {
+            return Ok(prepared);
+        }
+        let prepared =
+            Arc::new(tokio::task::spawn_blocking(move || PreparedBase::new(&transcript)).await?);
+        let mut current = self.prepared.write().unwrap();
+        if current
+            .as_ref()
+            .map_or(true, |current| current.slot <= prepared.slot)
+        {
+            *current = Some(prepared.clone());
+        }
+        Ok(prepared)
+    }
+}
+
+/// Prepares the base on startup and after every contribution.
+pub async fn prepare_on_contributions(
+    handoff: SharedHandoff,
+    transcript: SharedTranscript,
+    lobby_state: SharedLobbyState,
+) {
+    let mut events = lobby_state.subscribe();
+    loop {
+        match handoff.prepare(&transcript).await {
+            Ok(prepared) => info!(slot = prepared.slot, "Prepared the next contribution base"),
+            Err(error) => warn!(?error, "failed to prepare the next contribution base"),
+        }
+        loop {
+            match events.recv().await {
+                Ok(LobbyEvent::ContributionVerified { .. }) | Err(RecvError::Lagged(_)) => break,
+                Ok(_) => {}
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{test_transcript, valid_contribution},
+        Engine,
+    };
+    use flate2::read::GzDecoder;
+    use kzg_ceremony_crypto::signature::identity::Identity;
+    use std::io::Read;
+    use tokio::sync::RwLock as AsyncRwLock;
+
+    #[tokio::test]
+    async fn prepares_the_next_base() {
+        let transcript = Arc::new(AsyncRwLock::new(test_transcript()));
+        let handoff = Handoff::default();
+        let first = handoff.prepare(&transcript).await.unwrap();
+        assert_eq!(first.slot, 1);
+        assert!(Arc::ptr_eq(
+            &handoff.prepare(&transcript).await.unwrap(),
+            &first
+        ));
+
+        let contribution = valid_contribution(&*transcript.read().await, 1);
+        transcript
+            .write()
+            .await
+            .verify_add::<Engine>(contribution, Identity::None)
+            .unwrap();
+        assert_eq!(handoff.get(2), None);
+        let second = handoff.prepare(&transcript).await.unwrap();
+        assert_eq!(second.slot, 2);
+        assert_eq!(second.powers_tag, powers_tag(&*transcript.read().await));
+        assert_eq!(handoff.get(1), None);
+
+        let response = second.respond(ContributionEncoding::Json, Some(Encoding::Gzip));
+        assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let mut json = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut json).unwrap();
+        assert_eq!(
+            json,
+            serde_json::to_string(&transcript.read().await.contribution()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Uids of participants.
+//!
+//! A participant is known to the sequencer by a uid of the form
+//! `<provider>|<provider id>#<checksum>`, e.g. `git|1234#acde033f`. The
+//! provider id is what the provider never changes: the account id on GitHub
+//! and Discord, the address on Ethereum and `<issuer>|<subject>` on OIDC.
+//! Usernames are left out, so that an account renamed mid-ceremony keeps its
+//! uid. The checksum, the first 8 hex digits of the SHA-256 of the rest of the
+//! uid, catches uids that were mistyped or cut off, e.g. in an allowlist or an
+//! admin request.
+//!
+//! Uids used to be the participant ids of the transcript,
+//! `git|<id>|<username>`. Those legacy uids are still understood, and storage
+//! ties both to the same row of `identities`, so that an account that
+//! contributed under a legacy uid has contributed under its uid, too. The
+//! transcript keeps using participant ids.
+
+use kzg_ceremony_crypto::signature::identity::Identity;
+use sha2::{Digest, Sha256};
+
+/// Number of hex digits of the checksum.
+const CHECKSUM_LEN: usize = 8;
+
+const PROVIDERS: [&str; 4] = ["eth", "git", "dsc", "oidc"];
+
+/// The provider and the provider id of an account.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Account {
+    pub provider:    &'static str,
+    pub provider_id: String,
+}
+
+impl Account {
+    /// The account of `identity`, `None` for [`Identity::None`].
+    #[must_use]
+    pub fn of(identity: &Identity) -> Option<Self> {
+        let (provider, provider_id) = match identity {
+            Identity::Ethereum { address } => ("eth", format!("0x{}", hex::encode(address))),
+            Identity::Github { id, .. } => ("git", id.to_string()),
+            Identity::Discord { id, .. } => ("dsc", id.to_string()),
+            Identity::Oidc { issuer, subject } => ("oidc", format!("{issuer}|{subject}")),
+            Identity::None => return None,
+        };
+        Some(Self {
+            provider,
+            provider_id,
+        })
+    }
+
+    /// The account `uid` names, which may be a legacy uid. `None` if it names
+    /// none, like a [`pseudonym`](crate::storage::pseudonym), or its checksum
+    /// does not match.
+    #[must_use]
+    pub fn from_uid(uid: &str) -> Option<Self> {
+        match uid.rsplit_once('#') {
+            Some((named, checksum))
+                if checksum.len() == CHECKSUM_LEN
+                    && checksum.bytes().all(|byte| byte.is_ascii_hexdigit()) =>
+            {
+                Self::from_checked_uid(named, checksum)
+            }
+            _ => Self::of(&uid.parse().ok()?),
+        }
+    }
+
+    fn from_checked_uid(named: &str, checksum: &str) -> Option<Self> {
+        let (provider, provider_id) = named.split_once('|')?;
+        let provider = PROVIDERS.into_iter().find(|known| *known == provider)?;
+        if provider_id.is_empty() || checksum != self::checksum(named) {
+            return None;
+        }
+        Some(Self {
+            provider,
+            provider_id: provider_id.to_string(),
+        })
+    }
+
+    #[must_use]
+    pub fn uid(&self) -> String {
+        let named = format!("{}|{}", self.provider, self.provider_id);
+        let checksum = checksum(&named);
+        format!("{named}#{checksum}")
+    }
+}
+
+fn checksum(named: &str) -> String {
+    let mut checksum = hex::encode(Sha256::digest(named.as_bytes()));
+    checksum.truncate(CHECKSUM_LEN);
+    checksum
+}
+
+/// The uid of `identity`, empty for [`Identity::None`].
+#[must_use]
+pub fn uid(identity: &Identity) -> String {
+    Account::of(identity).map_or_else(String::new, |account| account.uid())
+}
+
+/// The uid `identity` had before uids left out usernames, which is its
+/// participant id. Bans and allowlists may still hold those.
+#[must_use]
+pub fn legacy_uid(identity: &Identity) -> String {
+    identity.to_string()
+}
+
+/// Name of the identity provider of `uid`, by its prefix, so that it is known
+/// for legacy uids and [`pseudonym`](crate::storage::pseudonym)s, too.
+#[must_use]
+pub fn provider_name(uid: &str) -> String {
+    match uid.split('|').next() {
+        Some("eth") => "Ethereum",
+        Some("git") => "Github",
+        Some("dsc") => "Discord",
+        Some("oidc") => "OIDC",
+        _ => "Unknown",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uids_leave_out_usernames() {
+        let github = Identity::Github {
+            id:       1234,
+            username: "alice".to_string(),
+        };
+        let renamed = Identity::Github {
+            id:       1234,
+            username: "alice2".to_string(),
+        };
+        let uid = uid(&github);
+        assert!(uid.starts_with("git|1234#"));
+        assert_eq!(uid.len(), "git|1234#".len() + CHECKSUM_LEN);
+        assert_eq!(uid, self::uid(&renamed));
+        assert_ne!(
+            uid,
+            self::uid(&Identity::Discord {
+                id:       1234,
+                username: "alice".to_string(),
+            })
+        );
+        assert_eq!(self::uid(&Identity::None), "");
+
+        let account = Account::of(&github).unwrap();
+        assert_eq!(Account::from_uid(&uid), Some(account.clone()));
+        assert_eq!(Account::from_uid("git|1234|alice"), Some(account));
+        assert_eq!(Account::from_uid(&uid[..uid.len() - 1]), None);
+    }
+
+    #[test]
+    fn checks_checksums() {
+        let oidc = Identity::Oidc {
+            issuer:  "https://id.example.com".to_string(),
+            subject: "a#b".to_string(),
+        };
+        let uid = uid(&oidc);
+        assert_eq!(Account::from_uid(&uid), Account::of(&oidc));
+        assert_eq!(Account::from_uid(&uid.replace("a#b", "a#c")), None);
+        assert_eq!(Account::from_uid("git|redacted|abcd"), None);
+        assert_eq!(provider_name(&uid), "OIDC");
+        assert_eq!(provider_name("git|redacted|abcd"), "Github");
+        assert_eq!(provider_name("invalid"), "Unknown");
+    }
+}
@@ -0,0 +1,319 @@
+//! Periodic integrity self-check of the stored transcript.
+//!
+//! The database is the source of truth for the transcript, see
+//! [`crate::io::read_or_create_transcript`], but it is only read in full on
+//! startup. Silent corruption of a stored contribution would therefore only
+//! show on the next restart, or when someone verifies the published
+//! transcript. Every `--integrity-check-interval` seconds the sequencer reads
+//! `--integrity-check-sample` randomly chosen contributions, and always the
+//! latest, back from the database and checks that each matches the
+//! transcript in memory, that its running product builds on the stored one
+//! before, by pairing, and its BLS signatures. The latest must also end at
+//! the current powers.
+//!
+//! Mismatches are logged, sent as `integrity_mismatch` webhook events and
+//! counted by `sequencer_integrity_mismatches`. The pairings run on the
+//! blocking pool, one check at a time, so the check competes little with
+//! the verification of contributions.
+
+use crate::{
+    metrics::{INTEGRITY_CHECKS, INTEGRITY_MISMATCHES},
+    storage::{PersistentStorage, WitnessEntry},
+    webhook::{Webhook, WebhookEvent},
+    Engine, SharedTranscript,
+};
+use clap::Parser;
+use eyre::Result as EyreResult;
+use kzg_ceremony_crypto::{signature::identity::Identity, Engine as _, G1, G2};
+use rand::{seq::index, thread_rng};
+use std::{collections::BTreeSet, num::ParseIntError, str::FromStr, time::Duration};
+use tracing::{error, info};
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Seconds between integrity self-checks of the stored transcript. 0
+    /// disables them.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "3600")]
+    pub integrity_check_interval: Duration,
+
+    /// Number of randomly chosen contributions each self-check verifies,
+    /// besides the latest.
+    #[clap(long, env, default_value = "16")]
+    pub integrity_check_sample: usize,
+}
+
+/// A stored contribution that did not pass the check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub position: usize,
+    pub reason:   String,
+}
+
+/// A sampled contribution, as stored, with what it is checked against.
+struct Link {
+    position:       usize,
+    participant_id: Identity,
+    witness:        Vec<WitnessEntry>,
+    /// Stored running products of the contribution before.
+    previous:       Vec<G1>,
+    /// The running products of the current powers, for the latest
+    /// contribution.
+    powers:         Option<Vec<G1>>,
+}
+
+impl Link {
+    fn verify(&self) -> Result<(), String> {
+        let message = self.participant_id.to_string();
+        for (i, (entry, previous)) in self.witness.iter().zip(&self.previous).enumerate() {
+            if entry.pot_pubkey == G2::zero() {
+                return Err(format!("sub-ceremony {i} has a zero pubkey"));
+            }
+            if Engine::verify_pubkey(entry.running_product, *previous, entry.pot_pubkey).is_err() {
+                return Err(format!(
+                    "running product of sub-ceremony {i} does not build on the one before"
+                ));
+            }
+            if let Some(signature) = entry.bls_signature.0 {
+                if !Engine::verify_signature(signature, message.as_bytes(), entry.pot_pubkey) {
+                    return Err(format!("BLS signature of sub-ceremony {i} does not verify"));
+                }
+            }
+            if let Some(powers) = &self.powers {
+                if powers.get(i) != Some(&entry.running_product) {
+                    return Err(format!(
+                        "running product of sub-ceremony {i} does not match the powers"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks `sample` random stored contributions and the latest against
+/// `transcript`.
+///
+/// # Errors
+///
+/// Returns an error if the contributions can not be read. Contributions that
+/// do not pass are not an error, but returned.
+pub async fn check(
+    transcript: &SharedTranscript,
+    storage: &PersistentStorage,
+    sample: usize,
+) -> EyreResult<Vec<Mismatch>> {
+    // What the transcript in memory holds for each sampled contribution.
+    let expected = {
+        let transcript = transcript.read().await;
+        let latest = transcript.num_participants();
+        let mut positions = index::sample(&mut thread_rng(), latest, sample.min(latest))
+            .into_iter()
+            .map(|index| index + 1)
+            .collect::<BTreeSet<_>>();
+        if latest > 0 {
+            positions.insert(latest);
+        }
+        let powers = transcript
+            .transcripts
+            .iter()
+            .map(|t| t.powers.g1[1])
+            .collect::<Vec<_>>();
+        positions
+            .into_iter()
+            .map(|position| {
+                let witness = transcript
+                    .transcripts
+                    .iter()
+                    .map(|t| WitnessEntry {
+                        running_product: t.witness.products[position],
+                        pot_pubkey:      t.witness.pubkeys[position],
+                        bls_signature:   t.witness.signatures[position].clone(),
+                    })
+                    .collect::<Vec<_>>();
+                let powers = (position == latest).then(|| powers.clone());
+                (
+                    position,
+                    transcript.participant_ids[position].clone(),
+                    witness,
+                    powers,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut mismatches = Vec::new();
+    let mut links = Vec::new();
+    for (position, participant_id, witness, powers) in expected {
+        let mismatch = |reason: &str| Mismatch {
+            position,
+            reason: reason.to_string(),
+        };
+        let stored = match storage.read_transcript_entry(position).await? {
+            Some(stored) => stored,
+            None => {
+                mismatches.push(mismatch("contribution is not stored"));
+                continue;
+            }
+        };
+        if stored.participant_id != participant_id || stored.witness != witness {
+            mismatches.push(mismatch(
+                "stored contribution differs from the transcript in memory",
+            ));
+            continue;
+        }
+        let previous = if position == 1 {
+            vec![G1::one(); witness.len()]
+        } else {
+            match storage.read_transcript_entry(position - 1).await? {
+                Some(previous) if previous.witness.len() == witness.len() => previous
+                    .witness
+                    .into_iter()
+                    .map(|entry| entry.running_product)
+                    .collect(),
+                _ => {
+                    mismatches.push(mismatch("contribution before is not stored"));
+                    continue;
+                }
+            }
+        };
+        links.push(Link {
+            position,
+            participant_id,
+            witness,
+            previous,
+            powers,
+        });
+    }
+
+    let failed = tokio::task::spawn_blocking(move || {
+        links
+            .iter()
+            .filter_map(|link| {
+                link.verify().err().map(|reason| Mismatch {
+                    position: link.position,
+                    reason,
+                })
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+    mismatches.extend(failed);
+    mismatches.sort_by_key(|mismatch| mismatch.position);
+    Ok(mismatches)
+}
+
+/// Runs [`check`] every `--integrity-check-interval` and raises the
+/// mismatches. A check that fails to run is retried at the next interval.
+pub async fn check_on_interval(
+    options: Options,
+    transcript: SharedTranscript,
+    storage: PersistentStorage,
+    webhook: Webhook,
+) {
+    loop {
+        tokio::time::sleep(options.integrity_check_interval).await;
+        match check(&transcript, &storage, options.integrity_check_sample).await {
+            Ok(mismatches) if mismatches.is_empty() => {
+                INTEGRITY_CHECKS.with_label_values(&["ok"]).inc();
+                INTEGRITY_MISMATCHES.set(0);
+                info!("Integrity self-check passed");
+            }
+            Ok(mismatches) => {
+                INTEGRITY_CHECKS.with_label_values(&["mismatch"]).inc();
+                INTEGRITY_MISMATCHES.set(i64::try_from(mismatches.len()).unwrap_or(i64::MAX));
+                for Mismatch { position, reason } in mismatches {
+                    error!(position, %reason, "Integrity self-check failed");
+                    webhook.notify(WebhookEvent::IntegrityMismatch { position, reason });
+                }
+            }
+            Err(error) => {
+                INTEGRITY_CHECKS.with_label_values(&["failed"]).inc();
+                error!(?error, "Integrity self-check could not run");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        storage::storage_client,
+        test_util::test_options,
+        tests::{test_transcript, valid_contribution},
+    };
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn finds_contributions_that_differ() {
+        let storage = storage_client(&test_options().storage).await.unwrap();
+        let mut transcript = test_transcript();
+        let shared = Arc::new(RwLock::new(transcript.clone()));
+        assert_eq!(check(&shared, &storage, 16).await.unwrap(), vec![]);
+
+        for no in 1..=4 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::Github {
+                    id:       u64::from(no),
+                    username: format!("user_{no}"),
+                })
+                .unwrap();
+        }
+        storage.import_transcript(&transcript).await.unwrap();
+        *shared.write().await = transcript.clone();
+        assert_eq!(check(&shared, &storage, 16).await.unwrap(), vec![]);
+
+        // What the database holds no longer matches memory.
+        let mut corrupted = transcript.clone();
+        corrupted.transcripts[0].witness.products[2] = G1::one();
+        *shared.write().await = corrupted;
+        assert_eq!(check(&shared, &storage, 16).await.unwrap(), vec![
+            Mismatch {
+                position: 2,
+                reason:   "stored contribution differs from the transcript in memory".to_string(),
+            }
+        ]);
+
+        // Nor with the powers.
+        let mut corrupted = transcript;
+        corrupted.transcripts[0].powers.g1[1] = G1::one();
+        *shared.write().await = corrupted;
+        let mismatches = check(&shared, &storage, 0).await.unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].position, 4);
+        assert!(mismatches[0].reason.contains("does not match the powers"));
+    }
+
+    #[test]
+    fn verifies_links() {
+        let mut transcript = test_transcript();
+        for no in 1..=2 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        let witness = &transcript.transcripts[0].witness;
+        let link = |position: usize, previous: G1| Link {
+            position,
+            participant_id: Identity::None,
+            witness: vec![WitnessEntry {
+                running_product: witness.products[position],
+                pot_pubkey:      witness.pubkeys[position],
+                bls_signature:   witness.signatures[position].clone(),
+            }],
+            previous: vec![previous],
+            powers: None,
+        };
+        assert_eq!(link(2, witness.products[1]).verify(), Ok(()));
+        assert!(link(2, witness.products[0]).verify().is_err());
+    }
+}
@@ -1,10 +1,17 @@
-use crate::SharedTranscript;
+use crate::{
+    lobby::{LobbyEvent, SharedLobbyState},
+    storage::{PersistentStorage, StorageError},
+    Options, SharedCeremonyStatus, SharedTranscript,
+};
 use eyre::eyre;
 use kzg_ceremony_crypto::BatchTranscript;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc},
+};
 use tokio::sync::RwLock;
-use tracing::{info, warn};
+use tracing::{error, info, instrument, warn};
 
 /// Represents a size constraint on a batch transcript
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -43,11 +50,20 @@ impl CeremonySizes {
         })
     }
 
+    /// Number of G1 and G2 points of each ceremony.
+    #[must_use]
+    pub fn sizes(&self) -> &[(usize, usize)] {
+        &self.sizes
+    }
+
     /// Validates a batch transcript against this shape description
     ///
     /// # Errors:
     /// - when the transcript does not conform to the required shape
-    fn validate_batch_transcript(&self, transcript: &BatchTranscript) -> eyre::Result<()> {
+    pub(crate) fn validate_batch_transcript(
+        &self,
+        transcript: &BatchTranscript,
+    ) -> eyre::Result<()> {
         let defined_ceremonies = transcript.transcripts.len();
         let expected_ceremonies = self.sizes.len();
         if defined_ceremonies != expected_ceremonies {
@@ -91,20 +107,44 @@ pub enum TranscriptIoError {
     TaskError(tokio::task::JoinError),
 }
 
-/// Reads a transcript file from disk, or creates it, if it doesn't exist.
+/// Restores the transcript from the database, or reads it from the transcript
+/// file if the database has no contributions yet. A new transcript file is
+/// created if neither exists.
+///
+/// The database is the source of truth: a transcript restored from it is
+/// written back to the transcript file, and the contributions in a legacy
+/// transcript file are imported into an empty database.
 ///
 /// # Errors
 ///
 /// - when the transcript exists, but does not conform to the required shape.
+/// - when the transcript can not be read from or written to the database.
 pub async fn read_or_create_transcript(
     path: PathBuf,
     work_path: PathBuf,
     ceremony_sizes: &CeremonySizes,
+    storage: &PersistentStorage,
 ) -> eyre::Result<SharedTranscript> {
-    if path.exists() {
+    if let Some(transcript) = storage.read_transcript().await? {
+        info!(
+            num_participants = transcript.num_participants(),
+            "Restoring transcript from database"
+        );
+        ceremony_sizes.validate_batch_transcript(&transcript)?;
+        let shared_transcript = Arc::new(RwLock::new(transcript));
+        write_json_file(path, work_path, shared_transcript.clone()).await?;
+        Ok(shared_transcript)
+    } else if path.exists() {
         info!(?path, "Opening transcript file");
         let transcript = read_json_file::<BatchTranscript>(path).await?;
         ceremony_sizes.validate_batch_transcript(&transcript)?;
+        if transcript.num_participants() > 0 {
+            warn!(
+                num_participants = transcript.num_participants(),
+                "No transcript in database, importing transcript file"
+            );
+            storage.import_transcript(&transcript).await?;
+        }
         Ok(Arc::new(RwLock::new(transcript)))
     } else {
         warn!(?path, "No transcript found, creating new transcript file");
@@ -115,11 +155,61 @@ pub async fn read_or_create_transcript(
     }
 }
 
+/// Adds the contributions that other sequencers sharing the database recorded
+/// since the last one of the transcript, and writes the transcript file if
+/// there were any. Returns the number of contributions added.
+///
+/// Called under the contribution slot, before the contribution base is handed
+/// out and before a contribution is verified, so that a sequencer never builds
+/// on a transcript the database has moved past.
+///
+/// # Errors
+///
+/// When the contributions can not be read from the database.
+pub async fn sync_transcript(
+    options: &Options,
+    storage: &PersistentStorage,
+    shared_transcript: &SharedTranscript,
+    num_contributions: &SharedCeremonyStatus,
+    lobby_state: &SharedLobbyState,
+) -> Result<usize, StorageError> {
+    let known = shared_transcript.read().await.num_participants();
+    if storage.count_transcript_entries().await? <= known {
+        return Ok(0);
+    }
+    let mut transcript = shared_transcript.write().await;
+    let added = storage.read_transcript_tail(&mut transcript).await?;
+    let num_participants = transcript.num_participants();
+    drop(transcript);
+    if added == 0 {
+        return Ok(0);
+    }
+    info!(
+        added,
+        num_participants, "Took contributions recorded by other sequencers"
+    );
+    if let Err(err) = write_json_file(
+        options.transcript_file.clone(),
+        options.transcript_in_progress_file.clone(),
+        shared_transcript.clone(),
+    )
+    .await
+    {
+        error!(?err, "failed to write transcript");
+    }
+    num_contributions.store(num_participants, Ordering::Relaxed);
+    lobby_state.publish(LobbyEvent::ContributionVerified {
+        num_contributions: num_participants,
+    });
+    Ok(added)
+}
+
 /// Asynchronously reads a JSON file from disk.
 ///
 /// # Errors
 /// If the file does not exist, or if it does not contain correct transcript
 /// data.
+#[instrument(level = "info", skip_all, fields(path = %path.display()))]
 pub async fn read_json_file<T: DeserializeOwned + Send + 'static>(
     path: PathBuf,
 ) -> Result<T, TranscriptIoError> {
@@ -135,6 +225,7 @@ pub async fn read_json_file<T: DeserializeOwned + Send + 'static>(
 ///
 /// # Errors
 /// If either file cannot be written.
+#[instrument(level = "info", skip_all, fields(path = %target_path.display()))]
 pub async fn write_json_file<T: Serialize + Send + Sync + 'static>(
     target_path: PathBuf,
     work_path: PathBuf,
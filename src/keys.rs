@@ -1,17 +1,46 @@
+//! The sequencer's signing key, which signs receipts and attestations.
+//!
+//! The key is held by a [`Signer`]: a [`LocalSigner`] for keys given on the
+//! command line or kept in a file, or, with the `aws-kms` feature, a
+//! [`KmsSigner`] for keys that never leave AWS KMS.
+//!
+//! A key kept in `--signing-key-file` can be rotated, see [`Keys::rotate`].
+//! The file then lists the keys oldest first, one per line. The last one is
+//! the current key and signs; each before it is followed by the time it was
+//! replaced, e.g. `<hex key> 2022-12-01T00:00:00Z`, and still verifies
+//! signatures for `--signing-key-overlap` hours after that. Sessions and
+//! reservations are not signed with these keys, so rotating them signs no
+//! participant out.
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
 use clap::Parser;
 use ethers_core::{
+    k256::ecdsa::SigningKey,
     rand::thread_rng,
     types::{RecoveryMessage, H160},
     utils::to_checksum,
 };
-use ethers_signers::{LocalWallet, Signer};
-use eyre::Result;
+use ethers_signers::{LocalWallet, Signer as _};
+use eyre::{Result, WrapErr};
 use kzg_ceremony_crypto::ErrorCode;
 use serde::Serialize;
-use std::{fmt, sync::Arc};
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 use strum::IntoStaticStr;
 use thiserror::Error;
-use tracing::{info, warn};
+use tracing::{info, instrument, warn};
+
+fn duration_from_hours(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)? * 3600))
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 #[group(skip)]
@@ -19,11 +48,47 @@ pub struct Options {
     /// Ethereum private key to use for signing receipts.
     #[clap(long, env)]
     pub signing_key: Option<String>,
+
+    /// File holding the hex encoded signing key, used if `--signing-key` is
+    /// not given. A new key is generated and written to it if it does not
+    /// exist, so the sequencer keeps its identity across restarts.
+    #[clap(long, env)]
+    pub signing_key_file: Option<PathBuf>,
+
+    /// Id, ARN or alias of an AWS KMS key of spec `ECC_SECG_P256K1` to sign
+    /// with instead of a local key. Takes precedence over the other key
+    /// options. Needs the `aws-kms` feature. Credentials are read from the
+    /// environment.
+    #[clap(long, env)]
+    pub signing_kms_key_id: Option<String>,
+
+    /// AWS region of `--signing-kms-key-id`. Defaults to the region of the
+    /// environment.
+    #[clap(long, env)]
+    pub signing_kms_region: Option<String>,
+
+    /// Hours a signing key still verifies signatures after it was rotated
+    /// out.
+    #[clap(long, env, value_parser = duration_from_hours, default_value = "168")]
+    pub signing_key_overlap: Duration,
 }
 
 #[derive(Serialize)]
 pub struct Signature(String);
 
+impl Signature {
+    /// Hex encoded signature bytes.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Signature {
+    fn from(hex: String) -> Self {
+        Self(hex)
+    }
+}
+
 #[derive(Debug, Error, IntoStaticStr)]
 pub enum SignatureError {
     #[error("couldn't sign the receipt")]
@@ -40,13 +105,169 @@ impl ErrorCode for SignatureError {
     }
 }
 
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum KeyRotationError {
+    #[error("only keys kept in --signing-key-file alone can be rotated")]
+    Unsupported,
+    #[error("invalid signing key file: {0}")]
+    InvalidKeyFile(String),
+    #[error("failed to write the signing key file: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl ErrorCode for KeyRotationError {
+    fn to_error_code(&self) -> String {
+        format!("KeyRotationError::{}", <&str>::from(self))
+    }
+}
+
+/// Holds the private key of the sequencer. Implementations that keep it
+/// remote only have to sign, the public key is known up front.
+#[async_trait]
+pub trait Signer: Send + Sync {
+    fn address(&self) -> H160;
+
+    /// Hex encoded uncompressed public key.
+    fn public_key(&self) -> String;
+
+    /// EIP-191 signature of `message`.
+    async fn sign_message(
+        &self,
+        message: &str,
+    ) -> Result<ethers_core::types::Signature, SignatureError>;
+}
+
+/// A key in memory, see [`Options::signing_key`] and
+/// [`Options::signing_key_file`].
+pub struct LocalSigner(LocalWallet);
+
+#[async_trait]
+impl Signer for LocalSigner {
+    fn address(&self) -> H160 {
+        self.0.address()
+    }
+
+    fn public_key(&self) -> String {
+        let point = self.0.signer().verifying_key().to_encoded_point(false);
+        format!("0x{}", hex::encode(point.as_bytes()))
+    }
+
+    async fn sign_message(
+        &self,
+        message: &str,
+    ) -> Result<ethers_core::types::Signature, SignatureError> {
+        self.0
+            .sign_message(message)
+            .await
+            .map_err(|_| SignatureError::SignatureCreation)
+    }
+}
+
+/// A key in AWS KMS, see [`Options::signing_kms_key_id`]. Every signature is
+/// a request to KMS.
+#[cfg(feature = "aws-kms")]
+pub struct KmsSigner {
+    signer:     ethers_signers::AwsSigner<'static>,
+    public_key: String,
+}
+
+#[cfg(feature = "aws-kms")]
+impl KmsSigner {
+    /// Looks up the public key of `key_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the region is unknown or KMS does not hand out the
+    /// public key.
+    pub async fn new(key_id: &str, region: Option<&str>) -> Result<Self> {
+        let region = match region {
+            Some(region) => region
+                .parse::<rusoto_core::Region>()
+                .wrap_err_with(|| format!("unknown AWS region {region}"))?,
+            None => rusoto_core::Region::default(),
+        };
+        // The signer borrows the client. There is one per process, so it is
+        // never freed.
+        let kms: &'static rusoto_kms::KmsClient =
+            Box::leak(Box::new(rusoto_kms::KmsClient::new(region)));
+        let signer = ethers_signers::AwsSigner::new(kms, key_id, 1)
+            .await
+            .wrap_err("failed to load the KMS signing key")?;
+        let point = signer
+            .get_pubkey()
+            .await
+            .wrap_err("failed to read the public key of the KMS signing key")?
+            .to_encoded_point(false);
+        Ok(Self {
+            signer,
+            public_key: format!("0x{}", hex::encode(point.as_bytes())),
+        })
+    }
+}
+
+#[cfg(feature = "aws-kms")]
+#[async_trait]
+impl Signer for KmsSigner {
+    fn address(&self) -> H160 {
+        self.signer.address()
+    }
+
+    fn public_key(&self) -> String {
+        self.public_key.clone()
+    }
+
+    async fn sign_message(
+        &self,
+        message: &str,
+    ) -> Result<ethers_core::types::Signature, SignatureError> {
+        self.signer.sign_message(message).await.map_err(|error| {
+            warn!(?error, "KMS failed to sign");
+            SignatureError::SignatureCreation
+        })
+    }
+}
+
 pub struct Keys {
-    wallet: LocalWallet,
+    /// Oldest first. The last one signs.
+    ring:    RwLock<Vec<RingKey>>,
+    /// The key file, if the keys can be rotated.
+    file:    Option<PathBuf>,
+    overlap: Duration,
+}
+
+#[derive(Clone)]
+struct RingKey {
+    signer:     Arc<dyn Signer>,
+    /// When the key stopped signing. `None` for the current key.
+    retired_at: Option<DateTime<Utc>>,
+}
+
+/// When a key replaced at `retired_at` stops verifying signatures. `None`
+/// for the current key.
+fn expiry(retired_at: Option<DateTime<Utc>>, overlap: Duration) -> Option<DateTime<Utc>> {
+    let overlap =
+        chrono::Duration::from_std(overlap).unwrap_or_else(|_| chrono::Duration::max_value());
+    retired_at.map(|retired_at| {
+        retired_at
+            .checked_add_signed(overlap)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    })
+}
+
+fn is_expired(retired_at: Option<DateTime<Utc>>, overlap: Duration) -> bool {
+    expiry(retired_at, overlap).map_or(false, |expires_at| expires_at <= Utc::now())
+}
+
+/// A key of the key file, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FileKey {
+    key:        String,
+    retired_at: Option<DateTime<Utc>>,
 }
 
 pub type SharedKeys = Arc<Keys>;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Address(H160);
 
 impl fmt::Display for Address {
@@ -64,47 +285,335 @@ impl Serialize for Address {
     }
 }
 
+/// A key that verifies signatures, as listed by `/info/jwks`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyingKey {
+    pub address:    Address,
+    /// Hex encoded uncompressed public key.
+    pub public_key: String,
+    /// When a rotated out key stops verifying. `None` for the current key.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// The outcome of [`Keys::rotate`].
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Rotation {
+    pub address:             Address,
+    pub previous_address:    Address,
+    pub previous_expires_at: DateTime<Utc>,
+}
+
 impl Keys {
+    /// Keys with a local key, see [`Self::load`] for KMS keys.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key is invalid, can not be read or written, or
+    /// is kept in KMS.
     pub fn new(options: &Options) -> Result<Self> {
-        match &options.signing_key {
-            Some(signing_key) => {
+        if options.signing_kms_key_id.is_some() {
+            eyre::bail!("KMS signing keys are loaded with Keys::load");
+        }
+        let wallet = match (&options.signing_key, &options.signing_key_file) {
+            (Some(signing_key), _) => {
                 let wallet = signing_key.parse::<LocalWallet>()?;
                 info!(address = ?wallet.address(), "Wallet created from the provided signing key");
-                Ok(Self { wallet })
+                wallet
             }
-            None => {
+            (None, Some(path)) => {
+                let ring = ring_of(&load_or_create_key_file(path)?)?;
+                return Ok(Self {
+                    ring:    RwLock::new(ring),
+                    file:    Some(path.clone()),
+                    overlap: options.signing_key_overlap,
+                });
+            }
+            (None, None) => {
                 let wallet = LocalWallet::new(&mut thread_rng());
                 warn!(address = ?wallet.address(), "Random wallet created. Make sure to provide a signing key in prod!");
-                Ok(Self { wallet })
+                wallet
             }
+        };
+        Ok(Self::with_signer(Box::new(LocalSigner(wallet))))
+    }
+
+    /// Keys with the key configured by `options`, which may be kept in KMS.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key can not be loaded.
+    pub async fn load(options: &Options) -> Result<Self> {
+        let key_id = match &options.signing_kms_key_id {
+            Some(key_id) => key_id,
+            None => return Self::new(options),
+        };
+        #[cfg(feature = "aws-kms")]
+        {
+            let signer = KmsSigner::new(key_id, options.signing_kms_region.as_deref()).await?;
+            info!(address = ?signer.address(), %key_id, "Using the KMS signing key");
+            Ok(Self::with_signer(Box::new(signer)))
+        }
+        #[cfg(not(feature = "aws-kms"))]
+        {
+            eyre::bail!("--signing-kms-key-id {key_id} needs the aws-kms feature")
+        }
+    }
+
+    #[must_use]
+    pub fn with_signer(signer: Box<dyn Signer>) -> Self {
+        Self {
+            ring:    RwLock::new(vec![RingKey {
+                signer:     Arc::from(signer),
+                retired_at: None,
+            }]),
+            file:    None,
+            overlap: Duration::ZERO,
         }
     }
 
+    /// The current key.
+    fn signer(&self) -> Arc<dyn Signer> {
+        let ring = self.ring.read().expect("lock poisoned");
+        ring.last().expect("there is a current key").signer.clone()
+    }
+
+    #[instrument(level = "info", skip_all)]
     pub async fn sign(&self, message: &str) -> Result<Signature, SignatureError> {
-        let signature = self
-            .wallet
-            .sign_message(message)
-            .await
-            .map_err(|_| SignatureError::SignatureCreation)?;
+        let signature = self.signer().sign_message(message).await?;
         Ok(Signature(hex::encode::<Vec<u8>>(signature.into())))
     }
 
+    /// Checks `signature` against the current key and the rotated out keys
+    /// that did not expire yet.
     #[allow(unused)]
     pub fn verify(&self, message: &str, signature: &Signature) -> Result<(), SignatureError> {
-        let h = hex::decode(&signature.0).map_err(|_| SignatureError::InvalidToken)?;
-        let signature = ethers_core::types::Signature::try_from(h.as_ref())
-            .map_err(|_| SignatureError::InvalidSignature)?;
-        signature
-            .verify(
-                RecoveryMessage::Data(message.as_bytes().to_owned()),
-                self.wallet.address(),
-            )
-            .map_err(|_| SignatureError::InvalidToken)
+        let ring = self.ring.read().expect("lock poisoned").clone();
+        let mut result = Err(SignatureError::InvalidToken);
+        for key in ring
+            .iter()
+            .rev()
+            .filter(|key| !is_expired(key.retired_at, self.overlap))
+        {
+            result = verify_signed_by(message, signature.as_str(), key.signer.address());
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
     }
 
     pub fn address(&self) -> Address {
-        Address(self.wallet.address())
+        Address(self.signer().address())
+    }
+
+    /// Hex encoded uncompressed public key.
+    pub fn public_key(&self) -> String {
+        self.signer().public_key()
+    }
+
+    /// The keys that verify signatures, the current one first.
+    pub fn verifying_keys(&self) -> Vec<VerifyingKey> {
+        let ring = self.ring.read().expect("lock poisoned");
+        ring.iter()
+            .rev()
+            .filter(|key| !is_expired(key.retired_at, self.overlap))
+            .map(|key| VerifyingKey {
+                address:    Address(key.signer.address()),
+                public_key: key.signer.public_key(),
+                expires_at: expiry(key.retired_at, self.overlap),
+            })
+            .collect()
+    }
+
+    /// Replaces the current key with a new one, which is written to the key
+    /// file. The replaced key verifies signatures for `--signing-key-overlap`
+    /// hours more, and keys whose overlap has passed are dropped from the
+    /// file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the keys are not kept in a key file alone, or it
+    /// can not be read or written.
+    pub fn rotate(&self) -> Result<Rotation, KeyRotationError> {
+        let path = self.file.as_ref().ok_or(KeyRotationError::Unsupported)?;
+        let mut ring = self.ring.write().expect("lock poisoned");
+        let contents = fs::read_to_string(path)?;
+        let mut keys = parse_key_file(&contents).map_err(KeyRotationError::InvalidKeyFile)?;
+        let now = Utc::now();
+        if let Some(current) = keys.last_mut() {
+            current.retired_at = Some(now);
+        }
+        keys.retain(|key| !is_expired(key.retired_at, self.overlap));
+        let new_key = hex::encode(SigningKey::random(&mut thread_rng()).to_bytes());
+        keys.push(FileKey {
+            key:        new_key,
+            retired_at: None,
+        });
+        let new_ring =
+            ring_of(&keys).map_err(|error| KeyRotationError::InvalidKeyFile(error.to_string()))?;
+        write_key_file(path, &keys)?;
+        let previous = ring
+            .last()
+            .expect("there is a current key")
+            .signer
+            .address();
+        *ring = new_ring;
+        let address = ring
+            .last()
+            .expect("there is a current key")
+            .signer
+            .address();
+        info!(?address, ?previous, path = %path.display(), "Signing key rotated");
+        Ok(Rotation {
+            address:             Address(address),
+            previous_address:    Address(previous),
+            previous_expires_at: expiry(Some(now), self.overlap).expect("the key is retired"),
+        })
+    }
+}
+
+/// Checks that the hex encoded `signature` over `message` was made by the
+/// key of `address`, e.g. that of another sequencer.
+///
+/// # Errors
+///
+/// Returns an error if the signature is malformed or made by another key.
+pub fn verify_signed_by(
+    message: &str,
+    signature: &str,
+    address: H160,
+) -> Result<(), SignatureError> {
+    let h = hex::decode(signature).map_err(|_| SignatureError::InvalidToken)?;
+    let signature = ethers_core::types::Signature::try_from(h.as_ref())
+        .map_err(|_| SignatureError::InvalidSignature)?;
+    signature
+        .verify(
+            RecoveryMessage::Data(message.as_bytes().to_owned()),
+            address,
+        )
+        .map_err(|_| SignatureError::InvalidToken)
+}
+
+/// The hex encoded signing key of `options`, if one is configured and, for
+/// `--signing-key-file`, already exists. Keys kept in KMS can not be read.
+///
+/// # Errors
+///
+/// Returns an error if the key file can not be read.
+pub fn configured_key(options: &Options) -> Result<Option<String>> {
+    if options.signing_kms_key_id.is_some() {
+        return Ok(None);
     }
+    match (&options.signing_key, &options.signing_key_file) {
+        (Some(signing_key), _) => Ok(Some(signing_key.clone())),
+        (None, Some(path)) if path.exists() => {
+            let contents = fs::read_to_string(path)
+                .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+            let keys = parse_key_file(&contents).map_err(|error| eyre::eyre!(error))?;
+            Ok(keys.last().map(|current| current.key.clone()))
+        }
+        (None, _) => Ok(None),
+    }
+}
+
+/// The address of the hex encoded signing key `key`.
+///
+/// # Errors
+///
+/// Returns an error if `key` is not a valid signing key.
+pub fn address_of(key: &str) -> Result<Address> {
+    Ok(Address(key.parse::<LocalWallet>()?.address()))
+}
+
+/// Parses the lines of a key file, oldest key first.
+fn parse_key_file(contents: &str) -> Result<Vec<FileKey>, String> {
+    let keys = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let key = fields.next().unwrap_or_default().to_string();
+            let retired_at = fields
+                .next()
+                .map(|retired_at| {
+                    DateTime::parse_from_rfc3339(retired_at)
+                        .map(|retired_at| retired_at.with_timezone(&Utc))
+                        .map_err(|_| format!("invalid replacement time {retired_at}"))
+                })
+                .transpose()?;
+            Ok(FileKey { key, retired_at })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    match keys.last() {
+        Some(current) if current.retired_at.is_none() => Ok(keys),
+        Some(_) => Err("the last key is replaced, there is no current key".to_string()),
+        None => Err("there is no key".to_string()),
+    }
+}
+
+fn ring_of(keys: &[FileKey]) -> Result<Vec<RingKey>> {
+    keys.iter()
+        .map(|key| {
+            let wallet = key.key.parse::<LocalWallet>()?;
+            Ok(RingKey {
+                signer:     Arc::new(LocalSigner(wallet)),
+                retired_at: key.retired_at,
+            })
+        })
+        .collect()
+}
+
+/// Writes `keys` to a new file next to `path` and moves it over `path`, so
+/// that the keys are never lost half written.
+fn write_key_file(path: &Path, keys: &[FileKey]) -> io::Result<()> {
+    let contents = keys
+        .iter()
+        .map(|key| match key.retired_at {
+            Some(retired_at) => format!("{} {}\n", key.key, retired_at.to_rfc3339()),
+            None => format!("{}\n", key.key),
+        })
+        .collect::<String>();
+    let mut next = path.as_os_str().to_owned();
+    next.push(".next");
+    let next = PathBuf::from(next);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&next)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&next, path)
+}
+
+fn load_or_create_key_file(path: &Path) -> Result<Vec<FileKey>> {
+    if path.exists() {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        let keys = parse_key_file(&contents)
+            .map_err(|error| eyre::eyre!("invalid signing key file {}: {error}", path.display()))?;
+        let current = keys.last().expect("there is a current key");
+        let wallet = current.key.parse::<LocalWallet>()?;
+        info!(address = ?wallet.address(), path = %path.display(), previous_keys = keys.len() - 1, "Wallet loaded from the signing key file");
+        return Ok(keys);
+    }
+    let key = SigningKey::random(&mut thread_rng());
+    let encoded = hex::encode(key.to_bytes());
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(encoded.as_bytes()))
+        .wrap_err_with(|| format!("failed to write {}", path.display()))?;
+    let wallet = LocalWallet::from(key);
+    info!(address = ?wallet.address(), path = %path.display(), "Wallet created and saved to the signing key file");
+    Ok(vec![FileKey {
+        key:        encoded,
+        retired_at: None,
+    }])
 }
 
 #[cfg(test)]
@@ -134,4 +643,79 @@ mod tests {
         let result = keys.verify(&message, &signature);
         println!("result {result:?}");
     }
+
+    #[test]
+    fn keeps_key_in_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options {
+            signing_key:         None,
+            signing_key_file:    Some(dir.path().join("signing.key")),
+            signing_kms_key_id:  None,
+            signing_kms_region:  None,
+            signing_key_overlap: Duration::from_secs(3600),
+        };
+        let created = Keys::new(&options).unwrap();
+        let loaded = Keys::new(&options).unwrap();
+        assert_eq!(created.address(), loaded.address());
+        assert_eq!(created.public_key(), loaded.public_key());
+        assert_eq!(created.public_key().len(), 2 + 130);
+    }
+
+    #[tokio::test]
+    async fn rotates_keys_in_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = Options::parse_from(Vec::<&str>::new());
+        options.signing_key_file = Some(dir.path().join("signing.key"));
+        let keys = Keys::new(&options).unwrap();
+        let old = keys.address();
+        let signature = keys.sign("receipt").await.unwrap();
+
+        let rotation = keys.rotate().unwrap();
+        assert_eq!(rotation.previous_address, old);
+        assert_eq!(rotation.address, keys.address());
+        assert_ne!(keys.address(), old);
+        keys.verify("receipt", &signature).unwrap();
+        let verifying = keys.verifying_keys();
+        assert_eq!(verifying.len(), 2);
+        assert_eq!(verifying[0].address, keys.address());
+        assert_eq!(verifying[0].expires_at, None);
+        assert_eq!(verifying[1].expires_at, Some(rotation.previous_expires_at));
+
+        let loaded = Keys::new(&options).unwrap();
+        assert_eq!(loaded.address(), keys.address());
+        loaded.verify("receipt", &signature).unwrap();
+        let current = configured_key(&options).unwrap().unwrap();
+        assert_eq!(address_of(&current).unwrap(), keys.address());
+
+        options.signing_key_overlap = Duration::ZERO;
+        let expired = Keys::new(&options).unwrap();
+        assert!(expired.verify("receipt", &signature).is_err());
+        expired.rotate().unwrap();
+        assert_eq!(Keys::new(&options).unwrap().verifying_keys().len(), 1);
+
+        let unrotatable = Keys::new(&Options::parse_from(Vec::<&str>::new())).unwrap();
+        assert!(matches!(
+            unrotatable.rotate(),
+            Err(KeyRotationError::Unsupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn loads_local_keys() {
+        let options = Options::parse_from([
+            "test",
+            "--signing-key",
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+        ]);
+        let keys = Keys::load(&options).await.unwrap();
+        assert_eq!(keys.address(), Keys::new(&options).unwrap().address());
+    }
+
+    #[cfg(not(feature = "aws-kms"))]
+    #[tokio::test]
+    async fn kms_needs_feature() {
+        let options = Options::parse_from(["test", "--signing-kms-key-id", "alias/sequencer"]);
+        assert!(Keys::load(&options).await.is_err());
+        assert!(Keys::new(&options).is_err());
+    }
 }
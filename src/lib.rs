@@ -5,65 +5,229 @@
 
 use crate::{
     api::v1::{
-        auth::{auth_client_link, eth_callback, github_callback},
-        contribute::{contribute, contribute_abort},
-        info::{current_state, status},
-        lobby::try_contribute,
+        account::{delete_account, me},
+        admin::{self, AdminOptions, AdminRole},
+        auth::{auth_callback, auth_client_link},
+        contribute::{
+            contribute, contribute_abort, contribute_deadline, contribute_extend,
+            contribute_heartbeat, contribute_progress, contribute_status, contribute_upload_part,
+            contribute_upload_status, contribute_validate, receipt, ContributionRecorders,
+            EcdsaSignaturePolicy,
+        },
+        health::{healthz, readyz},
+        info::{
+            contributions, contributor, contributors, current_state, identity, inclusion_proof,
+            jwks, lobby_fairness, lottery_epoch, lottery_latest, statistics, status, sth,
+            transcript_diff,
+        },
+        lobby::{
+            lobby_events, lobby_position, lobby_powers, notification_target,
+            register_notification_target, try_contribute, waiting_room_notify,
+        },
+        metrics::metrics,
+        openapi::openapi_json,
+        replication::replicated_contribution,
+        transcript::{
+            transcript_contributions, transcript_powers, transcript_stream, verify_chain,
+        },
     },
-    io::{read_or_create_transcript, CeremonySizes},
-    keys::Keys,
+    audit::AuditLog,
+    callback_coalescing::SharedCallbackCoalescer,
+    ceremony::{load_ceremonies, CeremonyId},
+    checkpoint::{recover_transcript, Checkpointer},
+    chunked_upload::SharedUploads,
+    compression::SharedTranscriptCache,
+    cors::{handle_cors, Cors, SharedCors},
+    eligibility::{ScorerHandle, SharedScorer},
+    ens::{EnsResolver, SharedEnsResolver},
+    error_rates::{count_responses, SharedResponseCounter},
+    event_tail::EventTail,
+    feature_flags::{gate_features, FeatureFlags, SharedFeatureFlags},
+    geoip::{GeoIp, SharedGeoIp},
+    handoff::SharedHandoff,
+    io::{read_or_create_transcript, write_json_file, CeremonySizes},
+    keys::{Keys, SharedKeys},
+    load_shedding::{shed_load, LoadShedder, SharedLoadShedder},
     lobby::{clear_lobby_on_interval, SharedLobbyState},
+    lobby_store::lobby_store,
+    lottery::Lottery,
+    notifications::Notifier,
     oauth::{
-        eth_oauth_client, github_oauth_client, EthAuthOptions, GithubAuthOptions, SharedAuthState,
+        AuthProviderKind, AuthProviders, DiscordAuthOptions, EthAuthOptions, GithubAuthOptions,
+        OidcAuthOptions, ProviderOptions, SharedAuthState,
     },
+    phases::{ScheduleHandle, SharedSchedule},
+    power_saving::PowerSaving,
+    proof_of_work::ProofOfWork,
+    quarantine::Quarantine,
+    quotas::{RuleSetHandle, SharedRuleSet},
+    rate_limit::{rate_limit, RateLimiter, SharedRateLimiter},
+    replication::{Follower, Replica, ReplicationLog},
+    request_limits::{limit_requests, RequestLimiter, SharedRequestLimiter},
+    request_signing::{verify_signed_request, KeyringHandle, SharedKeyring},
+    reservation::ReservationSigner,
+    scheduler::Scheduler,
     sessions::{SessionId, SessionInfo},
-    storage::storage_client,
+    spam_filter::SpamFilters,
+    statistics::StatisticsSnapshot,
+    status_cache::StatusCache,
+    storage::{storage_client, PersistentStorage},
+    test_mode::Mode,
+    transparency_log::TransparencyLog,
     util::parse_url,
+    verification::VerificationQueue,
+    webhook::{Webhook, WebhookEvent},
+    witness_chain::SharedChainVerifier,
 };
 use axum::{
-    extract::{DefaultBodyLimit, Extension},
+    body::Body,
+    extract::Extension,
     handler::Handler,
+    http::Request,
+    middleware::{self, Next},
     response::{Html, IntoResponse},
-    routing::{get, post, IntoMakeService},
+    routing::{delete, get, post, put},
     Router, Server,
 };
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use cli_batteries::await_shutdown;
-use eyre::Result as EyreResult;
+use eyre::{ensure, eyre, Result as EyreResult, WrapErr};
 use http::StatusCode;
-use hyper::server::conn::AddrIncoming;
-use kzg_ceremony_crypto::BatchTranscript;
+use kzg_ceremony_crypto::{map_points_in, select_curve, BatchTranscript, Curve};
 use std::{
+    future::Future,
+    net::{SocketAddr, TcpListener},
     path::PathBuf,
-    sync::{atomic::AtomicUsize, Arc},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 use tokio::sync::RwLock;
 use tower_http::{
-    cors::CorsLayer,
-    limit::RequestBodyLimitLayer,
-    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+    compression::CompressionLayer,
+    trace::{DefaultOnResponse, TraceLayer},
 };
-use tracing::{debug, info, Level};
+use tracing::{debug, info, warn, Level};
 use url::Url;
 
 mod api;
+mod attestation;
+mod audit;
+mod auth_funnel;
+mod beacon;
+mod callback_coalescing;
+mod ceremony;
+mod checkin_proof;
+mod checkpoint;
+mod chunked_upload;
+mod circuit_breaker;
+mod client_ip;
+mod client_version;
+mod commands;
+mod compression;
+mod config;
+mod content_digest;
+mod contribution_format;
+mod contributor_cache;
+mod cors;
+mod db_backup;
+mod db_migrate;
+mod eligibility;
+mod ens;
+mod error_rates;
+mod etag;
+mod event_tail;
+#[cfg(feature = "explorer")]
+mod explorer;
+mod fairness;
+mod feature_flags;
+mod fingerprint;
+mod gc;
+mod geoip;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod handoff;
+mod identity;
+mod integrity;
 pub mod io;
 mod keys;
+mod load_shedding;
 mod lobby;
+mod lobby_store;
+mod lobby_tuning;
+mod lottery;
+mod metrics;
+mod mirror;
+mod notifications;
 mod oauth;
+mod phases;
+mod power_saving;
+mod proof_of_work;
+mod quarantine;
+mod quotas;
+mod rate_limit;
 mod receipt;
+mod rejection;
+mod reload_signal;
+mod replication;
+mod request_id;
+mod request_limits;
+mod request_signing;
+mod reservation;
+pub mod sandbox;
+mod scheduler;
 mod sessions;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod spam_filter;
+mod state_archive;
+mod statistics;
+mod status_cache;
 mod storage;
+mod test_mode;
 #[cfg(test)]
 pub mod test_util;
+mod tls;
+mod transcript_format;
+mod transparency_log;
+mod upload;
 mod util;
+mod verification;
+mod waiting_room;
+mod webhook;
+mod witness_chain;
 
 pub type Engine = kzg_ceremony_crypto::DefaultEngine;
 pub type SharedTranscript = Arc<RwLock<BatchTranscript>>;
 pub type SharedCeremonyStatus = Arc<AtomicUsize>;
 
 pub const DEFAULT_CEREMONY_SIZES: &str = "4096,65:8192,65:16384,65:32768,65";
-pub const MAX_CONTRIBUTION_SIZE: usize = 10_485_760; // 10MB
+
+/// Runs the process on `curve`, before any point is read. Warns if the engine
+/// checks its results on BLS12-381, but can not on `curve`.
+///
+/// # Errors
+///
+/// Returns an error if the process runs on another curve already, or if the
+/// engine does not support `curve`.
+pub fn run_on_curve(curve: Curve) -> EyreResult<()> {
+    select_curve(curve).map_err(|current| eyre!("the process runs on {current} already"))?;
+    ensure!(
+        <Engine as kzg_ceremony_crypto::Engine>::supports(curve),
+        "the crypto engine does not support {curve}"
+    );
+    if <Engine as kzg_ceremony_crypto::Engine>::cross_checks(Curve::Bls12_381)
+        && !<Engine as kzg_ceremony_crypto::Engine>::cross_checks(curve)
+    {
+        warn!(
+            %curve,
+            "Contributions are verified by a single engine on this curve, without a cross-check"
+        );
+    }
+    Ok(())
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 #[group(skip)]
@@ -72,6 +236,16 @@ pub struct Options {
     #[clap(long, env, default_value = "http://127.0.0.1:3000/")]
     pub server: Url,
 
+    /// `test` runs a small local ceremony with an in-memory database, where
+    /// participants sign in with static tokens instead of OAuth. `mirror`
+    /// serves copies of the status, transcript and statistics of
+    /// `--upstream`.
+    #[clap(long, env, value_enum, default_value = "production")]
+    pub mode: Mode,
+
+    #[clap(flatten)]
+    pub config: config::Options,
+
     #[clap(flatten)]
     pub keys: keys::Options,
 
@@ -81,10 +255,36 @@ pub struct Options {
     #[clap(flatten)]
     pub ethereum: EthAuthOptions,
 
+    #[clap(flatten)]
+    pub discord: DiscordAuthOptions,
+
+    #[clap(flatten)]
+    pub oidc: OidcAuthOptions,
+
+    #[clap(flatten)]
+    pub auth_provider: ProviderOptions,
+
+    /// Identity providers participants can sign in with, separated by commas.
+    #[clap(
+        long,
+        env,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "github,eth"
+    )]
+    pub auth_providers: Vec<AuthProviderKind>,
+
     /// Allow multiple contributions from the same participant.
     #[clap(long, env, default_value = "false")]
     pub multi_contribution: bool,
 
+    /// How ECDSA signatures of Ethereum participants are checked. `prune`
+    /// drops invalid signatures from the transcript, `reject-invalid` rejects
+    /// contributions with an invalid signature and `require` also rejects
+    /// unsigned contributions.
+    #[clap(long, env, value_enum, default_value = "prune")]
+    pub ecdsa_signature: EcdsaSignaturePolicy,
+
     /// Storage location for the ceremony transcript json file.
     #[clap(long, env, default_value = "./transcript.json")]
     pub transcript_file: PathBuf,
@@ -93,42 +293,457 @@ pub struct Options {
     #[clap(long, env, default_value = "./transcript.json.next")]
     pub transcript_in_progress_file: PathBuf,
 
+    /// Directory for files that hold the points of the transcript and of
+    /// contributions, mapped into memory instead of kept on the heap so that
+    /// the OS can page them out on hosts with little memory.
+    #[clap(long, env)]
+    pub transcript_mmap_dir: Option<PathBuf>,
+
     /// Size of the ceremony in number of G1 and G2 points. Multiple ceremonies
     /// can be specified by separating them with a colon. The format is
     /// `G1_POINTS,G2_POINTS[:G1_POINTS,G2_POINTS]*`.
     #[clap(long, env, value_parser=CeremonySizes::parse_from_cmd, default_value=DEFAULT_CEREMONY_SIZES)]
     pub ceremony_sizes: CeremonySizes,
 
+    /// Curve of all ceremonies: `bls12-381`, as used by EIP-4844, or `bn254`
+    /// for the setups of Groth16 and Plonk circuits.
+    #[clap(long, env, default_value = "bls12-381")]
+    pub curve: Curve,
+
+    #[clap(flatten)]
+    pub audit: audit::Options,
+
+    #[clap(flatten)]
+    pub ceremonies: ceremony::Options,
+
+    #[clap(flatten)]
+    pub checkpoint: checkpoint::Options,
+
+    #[clap(flatten)]
+    pub db_backup: db_backup::Options,
+
+    #[clap(flatten)]
+    pub client_version: client_version::Options,
+
+    #[clap(flatten)]
+    pub eligibility: eligibility::Options,
+
     #[clap(flatten)]
     pub lobby: lobby::Options,
 
+    #[clap(flatten)]
+    pub lobby_store: lobby_store::Options,
+
+    #[clap(flatten)]
+    pub quotas: quotas::Options,
+
+    #[clap(flatten)]
+    pub quarantine: quarantine::Options,
+
+    #[clap(flatten)]
+    pub phases: phases::Options,
+
     #[clap(flatten)]
     pub storage: storage::Options,
+
+    #[clap(flatten)]
+    pub tls: tls::Options,
+
+    #[clap(flatten)]
+    pub rate_limit: rate_limit::Options,
+
+    #[clap(flatten)]
+    pub proof_of_work: proof_of_work::Options,
+
+    #[clap(flatten)]
+    pub checkin_proof: checkin_proof::Options,
+
+    #[clap(flatten)]
+    pub spam_filter: spam_filter::Options,
+
+    #[clap(flatten)]
+    pub request_limits: request_limits::Options,
+
+    #[clap(flatten)]
+    pub load_shedding: load_shedding::Options,
+
+    #[clap(flatten)]
+    pub feature_flags: feature_flags::Options,
+
+    #[clap(flatten)]
+    pub cors: cors::Options,
+
+    #[clap(flatten)]
+    pub scheduler: scheduler::Options,
+
+    #[clap(flatten)]
+    pub verification: verification::Options,
+
+    #[clap(flatten)]
+    pub sandbox: sandbox::Options,
+
+    #[clap(flatten)]
+    pub power_saving: power_saving::Options,
+
+    #[clap(flatten)]
+    pub lobby_tuning: lobby_tuning::Options,
+
+    #[clap(flatten)]
+    pub upload: upload::Options,
+
+    #[clap(flatten)]
+    pub webhook: webhook::Options,
+
+    #[clap(flatten)]
+    pub event_tail: event_tail::Options,
+
+    #[clap(flatten)]
+    pub waiting_room: waiting_room::Options,
+
+    #[clap(flatten)]
+    pub notifications: notifications::Options,
+
+    #[clap(flatten)]
+    pub geoip: geoip::Options,
+
+    #[clap(flatten)]
+    pub ens: ens::Options,
+
+    #[clap(flatten)]
+    pub lottery: lottery::Options,
+
+    #[clap(flatten)]
+    pub status_cache: status_cache::Options,
+
+    #[clap(flatten)]
+    pub statistics: statistics::Options,
+
+    #[clap(flatten)]
+    pub integrity: integrity::Options,
+
+    #[clap(flatten)]
+    pub admin: AdminOptions,
+
+    #[clap(flatten)]
+    pub request_signing: request_signing::Options,
+
+    #[clap(flatten)]
+    pub reservation: reservation::Options,
+
+    #[clap(flatten)]
+    pub replication: replication::Options,
+
+    #[clap(flatten)]
+    pub mirror: mirror::Options,
+
+    #[cfg(feature = "grpc")]
+    #[clap(flatten)]
+    pub grpc: grpc::Options,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Run the sequencer.
+    Serve(Box<Options>),
+
+    /// Verify every contribution in a transcript file, without starting the
+    /// server.
+    VerifyTranscript(commands::VerifyTranscriptOptions),
+
+    /// Convert a transcript file into one of the export formats.
+    Export(commands::ExportOptions),
+
+    /// Create the database if needed and run pending migrations.
+    Migrate(storage::Options),
+
+    /// Start a new sequencer from the transcript of a previous ceremony.
+    Init(Box<commands::InitOptions>),
+
+    /// Bundle the transcript, database and signing key into an archive, to
+    /// move the sequencer to another host.
+    ExportState(Box<state_archive::ExportOptions>),
+
+    /// Restore the state of a sequencer from an `export-state` archive.
+    ImportState(Box<state_archive::ImportOptions>),
+
+    /// Back up the database, or prune its backups.
+    #[clap(subcommand)]
+    Db(db_backup::Command),
+
+    /// Copy the database to another one, e.g. from Sqlite to Postgres, and
+    /// verify the copy.
+    MigrateDb(db_migrate::Options),
 }
 
 #[allow(clippy::missing_errors_doc)]
-pub async fn async_main(options: Options) -> EyreResult<()> {
+pub async fn async_main(cli: Cli) -> EyreResult<()> {
+    match cli.command {
+        Command::Serve(options) => serve(*options).await,
+        Command::VerifyTranscript(options) => commands::verify_transcript(options).await,
+        Command::Export(options) => commands::export(options).await,
+        Command::Migrate(options) => commands::migrate(options).await,
+        Command::Init(options) => commands::init(*options).await,
+        Command::ExportState(options) => state_archive::export_state(*options).await,
+        Command::ImportState(options) => state_archive::import_state(*options).await,
+        Command::Db(command) => db_backup::run(command).await,
+        Command::MigrateDb(options) => db_migrate::migrate_db(options).await,
+    }
+}
+
+async fn serve(options: Options) -> EyreResult<()> {
+    let options = config::load(options)?;
     debug!(?options, "Options");
 
     let addr = options.server.clone();
-    let server = start_server(options).await?;
-    info!("Listening on http://{}{}", server.local_addr(), addr.path());
-    server.with_graceful_shutdown(await_shutdown()).await?;
-    Ok(())
+    let (local_addr, server) = start_server(options, await_shutdown()).await?;
+    info!(
+        "Listening on {}://{}{}",
+        addr.scheme(),
+        local_addr,
+        addr.path()
+    );
+    server.await
 }
 
+/// Binds the server and returns the bound address together with a future
+/// serving requests.
+///
+/// Once `shutdown` resolves, the lobby stops accepting participants and the
+/// future waits (up to `--shutdown-deadline`) for the active contributor to
+/// finish or expire. It then stops the HTTP server, flushes the transcript and
+/// closes the database.
 #[allow(clippy::missing_errors_doc)]
 pub async fn start_server(
     options: Options,
-) -> EyreResult<Server<AddrIncoming, IntoMakeService<Router>>> {
-    info!(size=?options.ceremony_sizes, "Starting sequencer for KZG ceremony.");
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> EyreResult<(SocketAddr, impl Future<Output = EyreResult<()>> + Send)> {
+    let options = options.mode.apply(options)?;
+    run_on_curve(options.curve)?;
+    if options.mode == Mode::Test {
+        warn!(
+            transcript = %options.transcript_file.display(),
+            "Running in test mode, sign-in is not authenticated"
+        );
+    }
+    let (app, ceremonies) = if options.mode == Mode::Mirror {
+        info!("Starting read-only mirror.");
+        (mirror::start(&options)?, Vec::new())
+    } else {
+        info!(size=?options.ceremony_sizes, "Starting sequencer for KZG ceremony.");
+        host_ceremonies(&options).await?
+    };
+
+    // Run the server
+    let (addr, prefix) = parse_url(&options.server)?;
+    ensure!(
+        (options.server.scheme() == "https") == options.tls.enabled(),
+        "The server url must be https:// exactly when --tls-cert is set"
+    );
+    let tls_config = options.tls.config().await?;
+    let app = Router::new()
+        .nest(prefix, app)
+        .fallback(handle_404.into_service())
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(request_id::make_span::<Body>)
+                .on_response(DefaultOnResponse::default().level(Level::INFO)),
+        )
+        .layer(middleware::from_fn(request_id::assign_request_id));
+    let listener = TcpListener::bind(addr).wrap_err_with(|| format!("failed to bind {addr}"))?;
+    #[cfg(feature = "grpc")]
+    let grpc = grpc::GrpcServer::start(
+        &options,
+        app.clone(),
+        ceremonies
+            .iter()
+            .map(|ceremony| (ceremony.id.clone(), ceremony.lobby_state.clone()))
+            .collect(),
+    )
+    .await?;
+    listener.set_nonblocking(true)?;
+    let local_addr = listener.local_addr()?;
+
+    let drain_lobbies = ceremonies
+        .iter()
+        .map(|ceremony| ceremony.lobby_state.clone())
+        .collect::<Vec<_>>();
+    let graceful_shutdown = async move {
+        shutdown.await;
+        info!("Shutting down, waiting for active contributions to finish");
+        let drains = drain_lobbies
+            .into_iter()
+            .map(|lobby| {
+                tokio::spawn(async move {
+                    let shutdown_deadline = lobby.options().shutdown_deadline;
+                    lobby.drain(shutdown_deadline).await
+                })
+            })
+            .collect::<Vec<_>>();
+        for drain in drains {
+            if !drain.await.unwrap_or(false) {
+                warn!("Active contribution did not finish before the shutdown deadline");
+            }
+        }
+    };
+    let server: Pin<Box<dyn Future<Output = EyreResult<()>> + Send>> = match tls_config {
+        Some(config) => Box::pin(tls::serve(
+            listener,
+            config,
+            options.tls.clone(),
+            app,
+            graceful_shutdown,
+        )),
+        None => {
+            let server = Server::from_tcp(listener)?
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(graceful_shutdown);
+            Box::pin(async move { server.await.map_err(Into::into) })
+        }
+    };
+
+    let serve = async move {
+        server.await?;
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = grpc {
+            grpc.stop().await;
+        }
+        info!("Server stopped, flushing transcripts");
+        for ceremony in ceremonies {
+            write_json_file(
+                ceremony.options.transcript_file,
+                ceremony.options.transcript_in_progress_file,
+                ceremony.transcript,
+            )
+            .await?;
+            ceremony.storage.close().await?;
+        }
+        info!("Shutdown complete");
+        Ok(())
+    };
+    Ok((local_addr, serve))
+}
+
+/// Builds the services shared by the ceremonies and the router serving them
+/// all.
+async fn host_ceremonies(options: &Options) -> EyreResult<(Router, Vec<CeremonyHandle>)> {
+    if let Some(dir) = &options.transcript_mmap_dir {
+        map_points_in(dir.clone())
+            .wrap_err_with(|| format!("failed to map transcript points in {}", dir.display()))?;
+    }
+    let signing_keys = Arc::new(KeyringHandle::new(&options.request_signing)?);
+    let shared = SharedServices {
+        keys:            Arc::new(Keys::load(&options.keys).await?),
+        auth_providers:  AuthProviders::new(options)?,
+        scorer:          Arc::new(ScorerHandle::new(&options.eligibility)?),
+        provider_rules:  Arc::new(RuleSetHandle::new(&options.quotas)?),
+        phases:          Arc::new(ScheduleHandle::new(&options.phases)?),
+        rate_limiter:    Arc::new(RateLimiter::new(options.rate_limit.clone())),
+        request_limiter: Arc::new(RequestLimiter::new(options.request_limits.clone())),
+        load_shedder:    Arc::new(LoadShedder::new(options.load_shedding.clone())),
+        feature_flags:   Arc::new(FeatureFlags::new(&options.feature_flags)?),
+        cors:            Arc::new(Cors::new(&options.cors)?),
+        webhook:         Webhook::new(
+            &options.webhook,
+            signing_keys.clone(),
+            reqwest::Client::new(),
+        ),
+        geoip:           Arc::new(GeoIp::new(&options.geoip)?),
+        ens:             Arc::new(EnsResolver::new(&options.ens, reqwest::Client::new())?),
+        http_client:     reqwest::Client::new(),
+        spam_filters:    SpamFilters::new(&options.spam_filter, reqwest::Client::new())?,
+        keyring:         signing_keys,
+    };
+    shared.auth_providers.discover(&shared.http_client).await?;
+    shared.load_shedder.start_probe()?;
+
+    tokio::spawn(quotas::reload_on_signal(shared.provider_rules.clone()));
+    tokio::spawn(phases::reload_on_signal(shared.phases.clone()));
+    tokio::spawn(request_signing::reload_on_signal(shared.keyring.clone()));
+    tokio::spawn(feature_flags::reload_on_signal(
+        shared.feature_flags.clone(),
+    ));
+
+    let additional = load_ceremonies(options).await?;
+    let (mut app, default) = ceremony_app(options.clone(), CeremonyId::default(), &shared).await?;
+    let mut ceremonies = vec![default];
+    for config in additional {
+        let ceremony_options = config.apply(options)?;
+        info!(id = %config.id, size=?ceremony_options.ceremony_sizes, "Hosting additional ceremony.");
+        let id = CeremonyId(Some(config.id));
+        let (router, ceremony) = ceremony_app(ceremony_options, id.clone(), &shared).await?;
+        app = app.nest(&id.path_prefix(), router);
+        ceremonies.push(ceremony);
+    }
+    tokio::spawn(config::reload_on_signal(
+        options.clone(),
+        config::LiveSettings {
+            lobbies:      ceremonies
+                .iter()
+                .map(|ceremony| ceremony.lobby_state.clone())
+                .collect(),
+            rate_limiter: shared.rate_limiter.clone(),
+            scorer:       shared.scorer.clone(),
+        },
+    ));
+    shared.webhook.notify(WebhookEvent::SequencerStarted {
+        version:        env!("CARGO_PKG_VERSION"),
+        num_ceremonies: ceremonies.len(),
+    });
+    Ok((app, ceremonies))
+}
+
+/// Services shared by all ceremonies hosted by the server.
+struct SharedServices {
+    keys:            SharedKeys,
+    auth_providers:  AuthProviders,
+    scorer:          SharedScorer,
+    provider_rules:  SharedRuleSet,
+    phases:          SharedSchedule,
+    rate_limiter:    SharedRateLimiter,
+    request_limiter: SharedRequestLimiter,
+    load_shedder:    SharedLoadShedder,
+    feature_flags:   SharedFeatureFlags,
+    cors:            SharedCors,
+    webhook:         Webhook,
+    geoip:           SharedGeoIp,
+    ens:             SharedEnsResolver,
+    http_client:     reqwest::Client,
+    spam_filters:    SpamFilters,
+    keyring:         SharedKeyring,
+}
+
+/// State of a ceremony that needs to be persisted on shutdown.
+struct CeremonyHandle {
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    id:          CeremonyId,
+    options:     Options,
+    lobby_state: SharedLobbyState,
+    transcript:  SharedTranscript,
+    storage:     PersistentStorage,
+}
 
-    let keys = Arc::new(Keys::new(&options.keys)?);
+/// Loads the transcript and database of a single ceremony and builds the
+/// router serving its API.
+async fn ceremony_app(
+    options: Options,
+    id: CeremonyId,
+    shared: &SharedServices,
+) -> EyreResult<(Router, CeremonyHandle)> {
+    let storage = storage_client(&options.storage).await?;
 
+    recover_transcript(&options, &storage).await?;
     let transcript = read_or_create_transcript(
         options.transcript_file.clone(),
         options.transcript_in_progress_file.clone(),
         &options.ceremony_sizes,
+        &storage,
     )
     .await?;
 
@@ -136,51 +751,406 @@ pub async fn start_server(
         let lock = transcript.read().await;
         Arc::new(AtomicUsize::new(lock.num_participants()))
     };
-    let lobby_state = SharedLobbyState::new(options.lobby.clone());
+    let event_tail = Arc::new(EventTail::new(&options.event_tail));
+    let webhook = shared
+        .webhook
+        .for_ceremony(&id)
+        .with_event_tail(event_tail.clone());
+    let lobby_store = lobby_store(&options.lobby_store, &id).await?;
+    let lottery = Lottery::new(&options.lottery, shared.http_client.clone())?;
+    let mut lobby_state = SharedLobbyState::with_store(options.lobby.clone(), lobby_store.clone())
+        .with_webhook(webhook.clone())
+        .with_provider_rules(shared.provider_rules.clone())
+        .with_storage(storage.clone());
+    if lottery.is_some() {
+        lobby_state = lobby_state.with_lottery();
+    }
+    let restored_sessions = lobby_state
+        .restore_sessions(storage.load_sessions().await?)
+        .await;
+    if restored_sessions > 0 {
+        info!(restored_sessions, "Restored sessions from the database");
+    }
+    lobby_state
+        .restore_priorities(storage.load_priorities().await?)
+        .await;
     let auth_state = SharedAuthState::default();
+    let checkpointer = Arc::new(Checkpointer::new(&options.checkpoint)?.with_jobs(storage.clone()));
+    let audit_log = AuditLog::new(&options.audit, &storage, Some(event_tail.clone())).await?;
+    // Also runs the jobs left over from before a restart.
+    tokio::spawn(
+        Scheduler::new(
+            options.scheduler.clone(),
+            storage.clone(),
+            lobby_state.clone(),
+            checkpointer.clone(),
+            audit_log.clone(),
+        )
+        .run(),
+    );
+    let reservation_signer =
+        Arc::new(ReservationSigner::load(&options.reservation, &storage).await?);
+    let uploads = SharedUploads::default();
+    let proof_of_work = Arc::new(ProofOfWork::new(options.proof_of_work.clone()));
+    let verification_queue = Arc::new(VerificationQueue::new(&options.verification));
+    let quarantine = Arc::new(Quarantine::new(&options.quarantine));
+    let replication_log = Arc::new(ReplicationLog::new(
+        options.replication.replication_log_size,
+        ceremony_status.load(Ordering::Relaxed),
+    ));
+    let replica = Arc::new(Replica::new(options.replication.replicate_from.is_some()));
+    let statistics_snapshot = Arc::new(StatisticsSnapshot::load(&storage).await?);
+    tokio::spawn(statistics::refresh_on_interval(
+        options.statistics.clone(),
+        statistics_snapshot.clone(),
+        storage.clone(),
+    ));
+    if let Some(follower) = Follower::new(
+        &options,
+        &id,
+        shared.http_client.clone(),
+        replica.clone(),
+        transcript.clone(),
+        storage.clone(),
+        ceremony_status.clone(),
+        lobby_state.clone(),
+        checkpointer.clone(),
+    )? {
+        // Only the primary takes contributions until the standby is promoted.
+        lobby_state.set_paused(true).await;
+        tokio::spawn(follower.run());
+    }
+    let transcript_cache = SharedTranscriptCache::default();
+    let chain_verifier = SharedChainVerifier::default();
+    let transparency_log = Arc::new(TransparencyLog::load(storage.clone()).await?);
+    tokio::spawn(compression::refresh_on_contributions(
+        transcript_cache.clone(),
+        options.transcript_file.clone(),
+        lobby_state.clone(),
+        ceremony_status.clone(),
+    ));
+    let handoff = SharedHandoff::default();
+    tokio::spawn(handoff::prepare_on_contributions(
+        handoff.clone(),
+        transcript.clone(),
+        lobby_state.clone(),
+    ));
 
     // Spawn automatic queue flusher -- flushes those in the lobby whom have not
     // pinged in a considerable amount of time
+    let power_saving = Arc::new(PowerSaving::new(options.power_saving.clone()));
+    tokio::spawn(power_saving::idle_when_quiet(
+        power_saving.clone(),
+        lobby_state.clone(),
+        verification_queue.clone(),
+    ));
+    tokio::spawn(lobby_tuning::tune_on_interval(
+        options.lobby_tuning.clone(),
+        lobby_state.clone(),
+    ));
     tokio::spawn(clear_lobby_on_interval(
         lobby_state.clone(),
-        options.lobby.clone(),
+        storage.clone(),
+        power_saving.clone(),
+    ));
+    tokio::spawn(gc::collect_on_interval(
+        lobby_state.clone(),
+        storage.clone(),
+        power_saving,
     ));
+    if !options.db_backup.db_backup_interval.is_zero() {
+        tokio::spawn(db_backup::backup_on_interval(
+            options.db_backup.clone(),
+            options.storage.clone(),
+            storage.clone(),
+        ));
+    }
+    if !options.integrity.integrity_check_interval.is_zero() {
+        tokio::spawn(integrity::check_on_interval(
+            options.integrity.clone(),
+            transcript.clone(),
+            storage.clone(),
+            webhook,
+        ));
+    }
+    if let Some(lottery) = lottery {
+        tokio::spawn(lottery::draw_on_epochs(
+            lottery,
+            lobby_state.clone(),
+            storage.clone(),
+        ));
+    }
+    if options.waiting_room.waiting_room {
+        tokio::spawn(waiting_room::announce_on_interval(
+            options.waiting_room.clone(),
+            lobby_state.clone(),
+            storage.clone(),
+        ));
+    }
+    let notifier = Arc::new(Notifier::new(
+        &options.notifications,
+        shared.http_client.clone(),
+    )?);
+    if notifier.is_enabled() {
+        tokio::spawn(notifications::notify_on_interval(
+            notifier.clone(),
+            lobby_state.clone(),
+            storage.clone(),
+        ));
+    }
 
-    let app = Router::new()
+    let mut try_contribute_route = post(try_contribute).layer(CompressionLayer::new());
+    if options.checkin_proof.checkin_proof {
+        let checkin_options = options.checkin_proof.clone();
+        try_contribute_route = try_contribute_route.layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                checkin_proof::check(checkin_options.clone(), request, next)
+            },
+        ));
+    }
+    let mut app = Router::new()
         .route("/auth/request_link", get(auth_client_link))
-        .route("/auth/callback/github", get(github_callback))
-        .route("/auth/callback/eth", get(eth_callback))
-        .route("/lobby/try_contribute", post(try_contribute))
+        .route("/auth/callback/:provider", get(auth_callback))
+        .route("/lobby/try_contribute", try_contribute_route)
+        .route("/lobby/position", get(lobby_position))
+        .route(
+            "/lobby/powers",
+            get(lobby_powers).layer(CompressionLayer::new()),
+        )
+        .route("/lobby/waiting_room", post(waiting_room_notify))
+        .route(
+            "/lobby/notification_target",
+            post(register_notification_target).get(notification_target),
+        )
+        .route("/me", get(me).delete(delete_account))
         .route("/contribute", post(contribute))
         .route("/contribute/abort", post(contribute_abort))
+        .route("/contribute/extend", post(contribute_extend))
+        .route("/contribute/deadline", get(contribute_deadline))
+        .route("/contribute/heartbeat", post(contribute_heartbeat))
+        .route(
+            "/contribute/upload",
+            post(contribute)
+                .layer(middleware::from_fn(chunked_upload::commit))
+                .get(contribute_upload_status),
+        )
+        .route("/contribute/upload/:part", post(contribute_upload_part))
+        .route("/contribute/validate", post(contribute_validate))
+        .route("/contribute/status/:id", get(contribute_status))
+        .route("/contribute/progress", get(contribute_progress))
+        .route("/contribution/receipt/:uid", get(receipt))
         .route("/info/status", get(status))
-        .route("/info/current_state", get(current_state))
-        .layer(CorsLayer::permissive())
-        .layer(Extension(lobby_state))
+        .route(
+            "/info/current_state",
+            get(current_state).layer(CompressionLayer::new()),
+        )
+        .route("/info/statistics", get(statistics))
+        .route("/info/fairness", get(lobby_fairness))
+        .route("/info/lottery", get(lottery_latest))
+        .route("/info/lottery/:epoch", get(lottery_epoch))
+        .route("/info/identity", get(identity))
+        .route("/info/jwks", get(jwks))
+        .route("/info/contributions", get(contributions))
+        .route("/info/contributors", get(contributors))
+        .route("/info/contributor/:index", get(contributor))
+        .route("/info/sth", get(sth))
+        .route("/info/inclusion_proof", get(inclusion_proof))
+        .route(
+            "/replication/contributions/:num",
+            get(replicated_contribution),
+        )
+        .route("/transcript/diff", get(transcript_diff))
+        .route("/transcript/verify_chain", get(verify_chain))
+        .route("/transcript/stream", get(transcript_stream))
+        .route(
+            "/transcript/contributions",
+            get(transcript_contributions).layer(CompressionLayer::new()),
+        )
+        .route(
+            "/transcript/powers/:sub_ceremony",
+            get(transcript_powers).layer(CompressionLayer::new()),
+        )
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .route("/ws/lobby", get(lobby_events))
+        .route("/api-docs/openapi.json", get(openapi_json));
+
+    #[cfg(feature = "swagger-ui")]
+    {
+        app = app.merge(api::v1::openapi::swagger_ui());
+    }
+
+    #[cfg(feature = "explorer")]
+    {
+        app = app
+            .route("/explorer", get(explorer::explorer_root))
+            .route("/explorer/*path", get(explorer::explorer_asset));
+    }
+
+    if options.admin.admin_token.is_some() || options.request_signing.signing_keys_file.is_some() {
+        info!("Admin API enabled");
+        let keyring = shared.keyring.clone();
+        let nonces = lobby_store.clone();
+        // Routes without a role require an owner.
+        let viewer = Extension(AdminRole::Viewer);
+        let operator = Extension(AdminRole::Operator);
+        let admin = Router::new()
+            .route("/admin/lobby", get(admin::lobby).layer(viewer.clone()))
+            .route(
+                "/admin/overview",
+                get(admin::overview).layer(viewer.clone()),
+            )
+            .route("/admin/timings", get(admin::timings).layer(viewer.clone()))
+            .route(
+                "/admin/sybil_report",
+                get(admin::sybil_report).layer(viewer.clone()),
+            )
+            .route(
+                "/admin/events/tail",
+                get(admin::tail_events).layer(viewer.clone()),
+            )
+            .route(
+                "/admin/feature_flags",
+                get(admin::feature_flags).layer(viewer),
+            )
+            .route(
+                "/admin/feature_flags/:name",
+                put(admin::set_feature_flag).layer(operator.clone()),
+            )
+            .route(
+                "/admin/lobby/pause",
+                post(admin::pause).layer(operator.clone()),
+            )
+            .route(
+                "/admin/lobby/resume",
+                post(admin::resume).layer(operator.clone()),
+            )
+            .route(
+                "/admin/contributor/kick",
+                post(admin::kick).layer(operator.clone()),
+            )
+            .route("/admin/ban", post(admin::ban).layer(operator.clone()))
+            .route(
+                "/admin/ban/:uid",
+                delete(admin::delete_ban).layer(operator.clone()),
+            )
+            .route("/admin/unban", post(admin::unban).layer(operator.clone()))
+            .route(
+                "/admin/lobby/priority",
+                post(admin::set_priority).layer(operator),
+            )
+            .route("/admin/beacon", post(admin::apply_beacon))
+            .route("/admin/finalize", post(admin::finalize))
+            .route("/admin/promote", post(admin::promote))
+            .route("/admin/signing_key/rotate", post(admin::rotate_signing_key))
+            .route(
+                "/admin/keys",
+                get(admin::admin_keys).post(admin::create_admin_key),
+            )
+            .route(
+                "/admin/keys/:name",
+                put(admin::update_admin_key).delete(admin::delete_admin_key),
+            )
+            .route_layer(middleware::from_fn(
+                move |request: Request<Body>, next: Next<Body>| {
+                    verify_signed_request(keyring.clone(), nonces.clone(), request, next)
+                },
+            ));
+        app = app.merge(admin);
+    }
+
+    let rate_limiter = shared.rate_limiter.clone();
+    let request_limiter = shared.request_limiter.clone();
+    let load_shedder = shared.load_shedder.clone();
+    let feature_flags = shared.feature_flags.clone();
+    let cors = shared.cors.clone();
+    let responses = SharedResponseCounter::default();
+    let counter = responses.clone();
+    let app = app
+        .layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                gate_features(feature_flags.clone(), request, next)
+            },
+        ))
+        .layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                limit_requests(request_limiter.clone(), request, next)
+            },
+        ))
+        .layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                shed_load(load_shedder.clone(), request, next)
+            },
+        ))
+        .layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                rate_limit(rate_limiter.clone(), request, next)
+            },
+        ))
+        .layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                handle_cors(cors.clone(), request, next)
+            },
+        ))
+        .layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                count_responses(counter.clone(), request, next)
+            },
+        ))
+        .layer(Extension(responses))
+        .layer(Extension(id.clone()))
+        .layer(Extension(lobby_state.clone()))
         .layer(Extension(auth_state))
         .layer(Extension(ceremony_status))
-        .layer(Extension(keys))
-        .layer(Extension(eth_oauth_client(&options.ethereum)))
-        .layer(Extension(github_oauth_client(&options.github)))
-        .layer(Extension(reqwest::Client::new()))
-        .layer(Extension(storage_client(&options.storage).await?))
-        .layer(Extension(transcript))
-        .layer(Extension(options.clone()))
-        .layer(DefaultBodyLimit::disable())
-        .layer(RequestBodyLimitLayer::new(MAX_CONTRIBUTION_SIZE));
+        .layer(Extension(Arc::new(StatusCache::new(&options.status_cache))))
+        .layer(Extension(SharedCallbackCoalescer::default()))
+        .layer(Extension(statistics_snapshot))
+        .layer(Extension(shared.keys.clone()))
+        .layer(Extension(shared.auth_providers.clone()))
+        .layer(Extension(shared.scorer.clone()))
+        .layer(Extension(shared.provider_rules.clone()))
+        .layer(Extension(shared.feature_flags.clone()))
+        .layer(Extension(shared.phases.clone()))
+        .layer(Extension(shared.geoip.clone()))
+        .layer(Extension(shared.ens.clone()))
+        .layer(Extension(shared.spam_filters.clone()))
+        .layer(Extension(ContributionRecorders {
+            checkpointer:     checkpointer.clone(),
+            geoip:            shared.geoip.clone(),
+            ens:              shared.ens.clone(),
+            quarantine:       quarantine.clone(),
+            replication:      replication_log.clone(),
+            transparency_log: transparency_log.clone(),
+            handoff:          handoff.clone(),
+        }))
+        .layer(Extension(checkpointer))
+        .layer(Extension(audit_log))
+        .layer(Extension(event_tail))
+        .layer(Extension(reservation_signer))
+        .layer(Extension(uploads))
+        .layer(Extension(proof_of_work))
+        .layer(Extension(verification_queue))
+        .layer(Extension(quarantine))
+        .layer(Extension(replication_log))
+        .layer(Extension(replica))
+        .layer(Extension(transcript_cache))
+        .layer(Extension(handoff))
+        .layer(Extension(notifier))
+        .layer(Extension(chain_verifier))
+        .layer(Extension(transparency_log))
+        .layer(Extension(shared.http_client.clone()))
+        .layer(Extension(storage.clone()))
+        .layer(Extension(transcript.clone()))
+        .layer(Extension(options.clone()));
 
-    // Run the server
-    let (addr, prefix) = parse_url(&options.server)?;
-    let app = Router::new()
-        .nest(prefix, app)
-        .fallback(handle_404.into_service())
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::default().level(Level::INFO))
-                .on_response(DefaultOnResponse::default().level(Level::INFO)),
-        );
-    let server = Server::try_bind(&addr)?.serve(app.into_make_service());
-    Ok(server)
+    Ok((app, CeremonyHandle {
+        id,
+        options,
+        lobby_state,
+        transcript,
+        storage,
+    }))
 }
 
 #[allow(clippy::unused_async)] // Required for axum function signature
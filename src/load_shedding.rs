@@ -0,0 +1,288 @@
+//! Shedding load before the process tips over.
+//!
+//! A probe thread measures how long a task spawned onto the runtime waits
+//! before it is polled, which grows with the tasks queued ahead of it, and
+//! reads the resident memory of the process. Together with the number of
+//! contributions waiting for verification, they are compared against the
+//! `--shed-*` thresholds. While one is crossed, requests to routes that can
+//! be retried later are answered with `SEQ-LIMIT-003` and a `Retry-After`.
+//! The contribution in progress, health checks, metrics, replication and the
+//! admin API are never shed.
+
+use crate::metrics::{REQUESTS_SHED, VERIFICATION_BACKLOG};
+use axum::{
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use clap::Parser;
+use kzg_ceremony_crypto::ErrorCode;
+use std::{
+    num::ParseIntError,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::runtime::Handle;
+use tracing::warn;
+
+fn duration_from_millis(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_millis(u64::from_str(value)?))
+}
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Milliseconds a task may wait for the runtime before requests are
+    /// shed. 0 disables the check.
+    #[clap(long, env, value_parser = duration_from_millis, default_value = "0")]
+    pub shed_scheduling_delay: Duration,
+
+    /// Resident memory of the process in MiB above which requests are shed.
+    /// 0 disables the check.
+    #[clap(long, env, default_value = "0")]
+    pub shed_memory: u64,
+
+    /// Contributions waiting for verification, across all ceremonies, above
+    /// which requests are shed. 0 disables the check.
+    #[clap(long, env, default_value = "0")]
+    pub shed_verification_backlog: usize,
+
+    /// Milliseconds between measurements of the scheduling delay and memory.
+    #[clap(long, env, value_parser = duration_from_millis, default_value = "250")]
+    pub shed_probe_interval: Duration,
+
+    /// Seconds shed clients are asked to wait in `Retry-After`.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "5")]
+    pub shed_retry_after: Duration,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum LoadShedError {
+    #[error("shedding load, retry in {0} seconds")]
+    Shedding(u64),
+}
+
+impl ErrorCode for LoadShedError {
+    fn to_error_code(&self) -> String {
+        format!("LoadShedError::{}", <&str>::from(self))
+    }
+}
+
+/// The threshold that was crossed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum Pressure {
+    SchedulingDelay,
+    Memory,
+    VerificationBacklog,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Readings {
+    scheduling_delay:     Duration,
+    /// Resident memory in bytes, 0 where it can not be read.
+    memory:               u64,
+    verification_backlog: usize,
+}
+
+/// Whether a request may be shed. Routes of a contribution in progress,
+/// and those operators rely on to see what is going on, are not.
+fn is_critical(path: &str) -> bool {
+    path.starts_with("/contribute")
+        || path.starts_with("/admin/")
+        || path.starts_with("/replication/")
+        || matches!(path, "/healthz" | "/readyz" | "/metrics")
+}
+
+pub type SharedLoadShedder = Arc<LoadShedder>;
+
+pub struct LoadShedder {
+    options:          Options,
+    /// In microseconds.
+    scheduling_delay: AtomicU64,
+    memory:           AtomicU64,
+}
+
+impl LoadShedder {
+    #[must_use]
+    pub const fn new(options: Options) -> Self {
+        Self {
+            options,
+            scheduling_delay: AtomicU64::new(0),
+            memory: AtomicU64::new(0),
+        }
+    }
+
+    /// Starts the thread measuring the scheduling delay and memory of the
+    /// current runtime, if either is checked. It ends with the runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread can not be spawned.
+    pub fn start_probe(self: &Arc<Self>) -> std::io::Result<()> {
+        if self.options.shed_scheduling_delay.is_zero() && self.options.shed_memory == 0 {
+            return Ok(());
+        }
+        let shedder = self.clone();
+        let runtime = Handle::current();
+        std::thread::Builder::new()
+            .name("load-shedding-probe".to_string())
+            .spawn(move || shedder.probe(&runtime))?;
+        Ok(())
+    }
+
+    fn probe(&self, runtime: &Handle) {
+        loop {
+            std::thread::sleep(self.options.shed_probe_interval);
+            if let Some(memory) = resident_memory() {
+                self.memory.store(memory, Ordering::Relaxed);
+            }
+
+            // Spawned from outside the runtime, the task is queued behind
+            // all the tasks the workers have not got to yet.
+            let (sender, receiver) = mpsc::channel();
+            let start = Instant::now();
+            runtime.spawn(async move {
+                sender.send(()).ok();
+            });
+            loop {
+                match receiver.recv_timeout(self.options.shed_probe_interval) {
+                    Ok(()) => break,
+                    // Still waiting, what is known of the delay counts.
+                    Err(mpsc::RecvTimeoutError::Timeout) => self.record_delay(start.elapsed()),
+                    // The runtime is shutting down.
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            self.record_delay(start.elapsed());
+        }
+    }
+
+    fn record_delay(&self, delay: Duration) {
+        let micros = u64::try_from(delay.as_micros()).unwrap_or(u64::MAX);
+        self.scheduling_delay.store(micros, Ordering::Relaxed);
+    }
+
+    fn readings(&self) -> Readings {
+        Readings {
+            scheduling_delay:     Duration::from_micros(
+                self.scheduling_delay.load(Ordering::Relaxed),
+            ),
+            memory:               self.memory.load(Ordering::Relaxed),
+            verification_backlog: usize::try_from(VERIFICATION_BACKLOG.get()).unwrap_or(0),
+        }
+    }
+
+    /// The first threshold crossed by `readings`, if any.
+    fn pressure(&self, readings: Readings) -> Option<Pressure> {
+        let options = &self.options;
+        if !options.shed_scheduling_delay.is_zero()
+            && readings.scheduling_delay > options.shed_scheduling_delay
+        {
+            Some(Pressure::SchedulingDelay)
+        } else if options.shed_memory > 0 && readings.memory > options.shed_memory << 20 {
+            Some(Pressure::Memory)
+        } else if options.shed_verification_backlog > 0
+            && readings.verification_backlog > options.shed_verification_backlog
+        {
+            Some(Pressure::VerificationBacklog)
+        } else {
+            None
+        }
+    }
+}
+
+/// Resident memory of the process in bytes, from `/proc/self/status`.
+fn resident_memory() -> Option<u64> {
+    parse_vm_rss(&std::fs::read_to_string("/proc/self/status").ok()?)
+}
+
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib << 10)
+}
+
+/// Middleware shedding requests to routes that are not critical while the
+/// process is under pressure. Must be installed on the router without the
+/// server path prefix, so that routes are classified correctly.
+pub async fn shed_load<B>(
+    shedder: SharedLoadShedder,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let path = request.uri().path();
+    if is_critical(path) {
+        return next.run(request).await;
+    }
+    match shedder.pressure(shedder.readings()) {
+        Some(pressure) => {
+            let reason: &'static str = pressure.into();
+            warn!(path, reason, "shedding load");
+            REQUESTS_SHED.with_label_values(&[reason]).inc();
+            LoadShedError::Shedding(shedder.options.shed_retry_after.as_secs()).into_response()
+        }
+        None => next.run(request).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crosses_thresholds() {
+        let shedder = LoadShedder::new(Options::parse_from([
+            "shed",
+            "--shed-scheduling-delay",
+            "100",
+            "--shed-memory",
+            "512",
+        ]));
+        let calm = Readings {
+            scheduling_delay:     Duration::from_millis(20),
+            memory:               100 << 20,
+            verification_backlog: 1000,
+        };
+        assert_eq!(shedder.pressure(calm), None);
+        assert_eq!(
+            shedder.pressure(Readings {
+                scheduling_delay: Duration::from_millis(150),
+                ..calm
+            }),
+            Some(Pressure::SchedulingDelay)
+        );
+        assert_eq!(
+            shedder.pressure(Readings {
+                memory: 600 << 20,
+                ..calm
+            }),
+            Some(Pressure::Memory)
+        );
+
+        assert!(is_critical("/contribute/upload/3"));
+        assert!(is_critical("/healthz"));
+        assert!(!is_critical("/lobby/try_contribute"));
+        assert!(!is_critical("/info/current_state"));
+
+        let status = "Name:\tsequencer\nVmPeak:\t  204800 kB\nVmRSS:\t   10240 kB\n";
+        assert_eq!(parse_vm_rss(status), Some(10 << 20));
+        assert_eq!(parse_vm_rss("Name:\tsequencer\n"), None);
+    }
+}
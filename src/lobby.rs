@@ -1,11 +1,44 @@
 use crate::{
-    sessions::{SessionId, SessionInfo},
-    storage::PersistentStorage,
+    audit::{AuditAction, AuditLog},
+    fairness::{self, WaitOutcome},
+    lobby_store::{LobbyStoreError, MemoryLobbyStore, SharedLobbyStore},
+    lobby_tuning::LobbyTuning,
+    lottery::Draw,
+    metrics::{CONTRIBUTIONS_EXPIRED, CONTRIBUTIONS_REQUEUED},
+    power_saving::SharedPowerSaving,
+    quotas::SharedRuleSet,
+    rejection::RejectionReason,
+    scheduler::{self, JobKind},
+    sessions::{IdToken, SessionId, SessionInfo},
+    storage::{PersistentStorage, StorageError, StoredSession},
+    webhook::{Webhook, WebhookEvent},
+};
+use chrono::Utc;
+use clap::{Parser, ValueEnum};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    thread_rng, Rng, RngCore,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    future::Future,
+    num::ParseIntError,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
 };
-use clap::Parser;
-use std::{collections::BTreeMap, num::ParseIntError, str::FromStr, sync::Arc, time::Duration};
 use thiserror::Error;
-use tokio::{sync::Mutex, time::Instant};
+use tokio::{
+    sync::{broadcast, Mutex},
+    time::Instant,
+};
+use tracing::{error, warn};
+use utoipa::ToSchema;
 
 fn duration_from_str(value: &str) -> Result<Duration, ParseIntError> {
     Ok(Duration::from_secs(u64::from_str(value)?))
@@ -27,7 +60,8 @@ pub struct Options {
     #[clap(long, env, value_parser=duration_from_str, default_value="2")]
     pub lobby_checkin_tolerance: Duration,
 
-    /// How often the server should check for dead sessions in seconds.
+    /// How often the server should check for participants in the lobby who
+    /// missed their check-in, in seconds.
     #[clap(long, env, value_parser=duration_from_str, default_value="5")]
     pub lobby_flush_interval: Duration,
 
@@ -40,9 +74,60 @@ pub struct Options {
     #[clap(long, env, value_parser=duration_from_str, default_value="86400")]
     pub session_expiration: Duration,
 
+    /// How often expired sessions, abandoned lobby waits and unused sign-in
+    /// nonces are pruned, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="60")]
+    pub gc_interval: Duration,
+
     /// Maximum number of active sessions.
     #[clap(long, env, default_value = "100000")]
     pub max_sessions_count: usize,
+
+    /// How long to wait on shutdown for the active contributor to finish or
+    /// expire, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="60")]
+    pub shutdown_deadline: Duration,
+
+    /// How the next contributor is chosen once the slot is free. See
+    /// [`LobbyStrategyKind`].
+    #[clap(long, env, value_enum, default_value = "first-come")]
+    pub lobby_strategy: LobbyStrategyKind,
+
+    /// How many times the active contributor may extend their compute
+    /// deadline through `/contribute/extend`. 0 disables extensions.
+    #[clap(long, env, default_value = "1")]
+    pub max_deadline_extensions: usize,
+
+    /// By how much each extension moves the compute deadline, in seconds.
+    #[clap(long, env, value_parser=duration_from_str, default_value="60")]
+    pub deadline_extension: Duration,
+
+    /// Seconds the active contributor may go without calling
+    /// `/contribute/heartbeat` before their slot expires. 0 disables
+    /// heartbeats.
+    #[clap(long, env, value_parser=duration_from_str, default_value="0")]
+    pub contributor_heartbeat_timeout: Duration,
+
+    /// What happens to a contributor whose slot expires because their client
+    /// went silent. See [`ExpiryPolicy`].
+    #[clap(long, env, value_enum, default_value = "eject")]
+    pub expiry_policy: ExpiryPolicy,
+
+    /// Seconds a requeued contributor has to wait before they may get the
+    /// slot again.
+    #[clap(long, env, value_parser=duration_from_str, default_value="60")]
+    pub requeue_penalty: Duration,
+
+    /// How many times a contributor may get the slot under `--expiry-policy
+    /// requeue`.
+    #[clap(long, env, default_value = "3")]
+    pub max_slot_attempts: u32,
+
+    /// Seconds after which a waiting participant is picked as if they had the
+    /// highest priority, so that participants of lower priority are not
+    /// starved. 0 ignores priorities.
+    #[clap(long, env, value_parser=duration_from_str, default_value="1800")]
+    pub priority_max_wait: Duration,
 }
 
 impl Options {
@@ -50,6 +135,143 @@ impl Options {
         self.lobby_checkin_frequency
             .saturating_sub(self.lobby_checkin_tolerance)
     }
+
+    /// How long after their last ping a participant still counts as waiting.
+    pub fn max_checkin_delay(&self) -> Duration {
+        self.lobby_checkin_frequency + self.lobby_checkin_tolerance
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LobbyStrategyKind {
+    /// Whoever asks first once the slot is free.
+    FirstCome,
+    /// The participant that has waited in the lobby the longest.
+    Fifo,
+    /// A participant drawn uniformly at random.
+    Random,
+    /// A participant drawn at random, weighted by how long they have waited.
+    Weighted,
+}
+
+/// What happens to a contributor whose slot expires.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExpiryPolicy {
+    /// They may not contribute again.
+    Eject,
+    /// If the slot expired because the client went silent, they are put back
+    /// into the lobby after `--requeue-penalty`, until they had the slot
+    /// `--max-slot-attempts` times. Slots lost for any other reason, e.g. an
+    /// invalid contribution, are not requeued.
+    Requeue,
+}
+
+/// Expiry reasons of clients that went silent, e.g. because of a network
+/// failure. Only these are requeued.
+const REQUEUED_REASONS: [&str; 3] = ["timeout", "heartbeat", "orphaned"];
+
+impl LobbyStrategyKind {
+    #[must_use]
+    pub fn strategy(self) -> Arc<dyn SlotStrategy> {
+        match self {
+            Self::FirstCome => Arc::new(FirstCome),
+            Self::Fifo => Arc::new(Fifo),
+            Self::Random => Arc::new(RandomPick),
+            Self::Weighted => Arc::new(WeightedByWait),
+        }
+    }
+}
+
+/// A participant in the lobby that pinged within the check-in tolerance.
+pub struct Candidate<'a> {
+    pub id:       &'a SessionId,
+    /// Time since they entered the lobby.
+    pub waited:   Duration,
+    /// The higher of the priority of their provider, see
+    /// [`crate::quotas::ProviderRules::priority`], and the one set through
+    /// the admin API. 0 unless either is set.
+    pub priority: u32,
+}
+
+/// Chooses who gets the contribution slot next.
+///
+/// The slot is taken by calling `/lobby/try_contribute`, so a strategy only
+/// picks who the slot is held for. The pick is kept until that participant
+/// takes the slot or stops pinging, everyone else is turned away meanwhile.
+///
+/// Strategies only see the candidates of the highest priority. Those that
+/// waited longer than `--priority-max-wait` count as highest priority.
+pub trait SlotStrategy: Send + Sync {
+    /// Picks the next contributor among `candidates`, which always include
+    /// `caller`, the participant asking for the slot.
+    fn pick(
+        &self,
+        caller: &SessionId,
+        candidates: &[Candidate<'_>],
+        rng: &mut dyn RngCore,
+    ) -> SessionId;
+}
+
+pub struct FirstCome;
+
+impl SlotStrategy for FirstCome {
+    fn pick(&self, caller: &SessionId, _: &[Candidate<'_>], _: &mut dyn RngCore) -> SessionId {
+        caller.clone()
+    }
+}
+
+pub struct Fifo;
+
+impl SlotStrategy for Fifo {
+    fn pick(
+        &self,
+        caller: &SessionId,
+        candidates: &[Candidate<'_>],
+        _: &mut dyn RngCore,
+    ) -> SessionId {
+        candidates
+            .iter()
+            .max_by_key(|candidate| candidate.waited)
+            .map_or(caller, |candidate| candidate.id)
+            .clone()
+    }
+}
+
+pub struct RandomPick;
+
+impl SlotStrategy for RandomPick {
+    fn pick(
+        &self,
+        caller: &SessionId,
+        candidates: &[Candidate<'_>],
+        rng: &mut dyn RngCore,
+    ) -> SessionId {
+        if candidates.is_empty() {
+            return caller.clone();
+        }
+        candidates[rng.gen_range(0..candidates.len())].id.clone()
+    }
+}
+
+pub struct WeightedByWait;
+
+impl SlotStrategy for WeightedByWait {
+    fn pick(
+        &self,
+        caller: &SessionId,
+        candidates: &[Candidate<'_>],
+        rng: &mut dyn RngCore,
+    ) -> SessionId {
+        // Everyone gets a second of weight, so that participants who just
+        // arrived still have a chance.
+        let weights = candidates
+            .iter()
+            .map(|candidate| candidate.waited.as_secs_f64() + 1.0);
+        WeightedIndex::new(weights).map_or_else(
+            |_| caller.clone(),
+            |index| candidates[index.sample(rng)].id.clone(),
+        )
+    }
 }
 
 #[derive(Default)]
@@ -57,6 +279,103 @@ pub struct LobbyState {
     pub sessions_in_lobby:     BTreeMap<SessionId, SessionInfo>,
     pub sessions_out_of_lobby: BTreeMap<SessionId, SessionInfo>,
     pub active_contributor:    ActiveContributor,
+    /// While paused, no new contributor is picked from the lobby.
+    pub paused:                bool,
+    /// Set on shutdown. Nobody may enter the lobby or start contributing.
+    pub shutting_down:         bool,
+    /// Set when the ceremony is finalized. Like `shutting_down`, but for
+    /// good.
+    pub closed:                bool,
+    /// Participant the free slot is held for by the lobby strategy.
+    pub next_contributor:      Option<SessionId>,
+    /// Sessions loaded from the database on startup, by token hash. They are
+    /// moved to the other maps once their token is seen again.
+    pub restored_sessions:     BTreeMap<String, SessionInfo>,
+    /// Uids requeued after their slot expired, with the time until which they
+    /// may not get the slot again.
+    pub requeued:              BTreeMap<String, Instant>,
+    /// Lobby priorities set through the admin API, by uid.
+    pub priorities:            BTreeMap<String, u32>,
+    /// The lottery draw of the current epoch, see [`crate::lottery`].
+    pub lottery_draw:          Option<Draw>,
+    /// Hashes of the sessions admitted by `lottery_draw`.
+    pub admitted:              BTreeSet<String>,
+    /// When the last [`DRAIN_SAMPLE`] slots were handed out, oldest first.
+    pub slots_handed_out:      VecDeque<Instant>,
+}
+
+/// Number of recent slot hand-outs the drain rate of the lobby is measured
+/// over.
+const DRAIN_SAMPLE: usize = 20;
+
+/// Average time between the hand-outs in `handed_out`, or `fallback` if
+/// there are too few to tell.
+fn drain_interval(handed_out: &VecDeque<Instant>, fallback: Duration) -> Duration {
+    match (handed_out.front(), handed_out.back()) {
+        (Some(first), Some(last)) if handed_out.len() > 1 => {
+            (*last - *first) / u32::try_from(handed_out.len() - 1).unwrap_or(u32::MAX)
+        }
+        _ => fallback,
+    }
+}
+
+/// How long a participant turned away from the full lobby should wait: one
+/// to two drain intervals, `jitter` of the way, so that those turned away
+/// together do not all come back together. At least `min`.
+fn lobby_full_retry_after(drain_interval: Duration, jitter: f64, min: Duration) -> Duration {
+    drain_interval
+        .mul_f64(1.0 + jitter.clamp(0.0, 1.0))
+        .max(min)
+}
+
+/// Point in time view of the lobby, as exposed by the admin API.
+#[derive(Debug, Serialize)]
+pub struct LobbySnapshot {
+    pub paused:             bool,
+    pub active_contributor: Option<ActiveContributorSnapshot>,
+    pub lobby:              Vec<LobbyEntrySnapshot>,
+    pub session_count:      usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActiveContributorSnapshot {
+    pub uid:               String,
+    pub contributing:      bool,
+    /// Seconds until the slot expires, by the deadline or a missed
+    /// heartbeat. `None` once the contribution is being verified.
+    pub seconds_remaining: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LobbyEntrySnapshot {
+    pub uid:                String,
+    pub seconds_since_ping: u64,
+    pub priority:           u32,
+}
+
+/// Where a participant stands in the lobby.
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct LobbyPosition {
+    /// 1-based position among the participants waiting in the lobby, `0` for
+    /// the active contributor. Unless `--lobby-strategy` is `fifo`, the next
+    /// contributor need not be the first in line, so this is only an estimate
+    /// of the order.
+    pub position:                 usize,
+    pub lobby_size:               usize,
+    pub contribution_in_progress: bool,
+}
+
+/// Where a session is in the lobby, as shown on `GET /me`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionPlace {
+    /// Signed in, but not waiting in the lobby.
+    SignedIn,
+    InLobby,
+    /// Holds the contribution slot, the contribution has not arrived yet.
+    AwaitingContribution,
+    /// The contribution is being verified.
+    Contributing,
 }
 
 #[derive(Clone, Debug)]
@@ -70,9 +389,17 @@ pub enum ActiveContributor {
     None,
     AwaitingContribution {
         session: SessionInfoWithId,
+        /// The signed reservation handed out with the slot.
+        reservation: String,
         /// The last time this session requested the contribution base.
         /// This is large, so we only allow them to re-request it infrequently.
         last_contribution_file_request: Instant,
+        /// When the slot expires unless the contribution arrives.
+        deadline: Instant,
+        /// Number of times the deadline has been extended.
+        extensions: usize,
+        /// The last time the contributor showed they are still computing.
+        last_heartbeat: Instant,
     },
     Contributing(SessionInfoWithId),
 }
@@ -83,6 +410,17 @@ impl Default for ActiveContributor {
     }
 }
 
+impl ActiveContributor {
+    const fn session(&self) -> Option<&SessionInfoWithId> {
+        match self {
+            Self::None => None,
+            Self::AwaitingContribution { session, .. } | Self::Contributing(session) => {
+                Some(session)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ActiveContributorError {
     #[error("another contribution in progress")]
@@ -95,67 +433,535 @@ pub enum ActiveContributorError {
     NotActiveContributor,
     #[error("session count limit exceeded")]
     SessionCountLimitExceeded,
-    #[error("lobby size limit exceeded")]
-    LobbySizeLimitExceeded,
-    #[error("call came too early. rate limited")]
-    RateLimited,
+    /// With the time to wait before trying again.
+    #[error("lobby size limit exceeded, retry in {0:?}")]
+    LobbySizeLimitExceeded(Duration),
+    /// With the time left until the next call is accepted.
+    #[error("call came too early, rate limited for {0:?}")]
+    RateLimited(Duration),
+    #[error("requeued, the slot is not handed out for {0:?}")]
+    Requeued(Duration),
+    #[error("not admitted by the lottery, the next draw is in {0:?}")]
+    NotAdmitted(Duration),
+    #[error("lobby is paused")]
+    LobbyPaused,
+    #[error("sequencer is shutting down")]
+    ShuttingDown,
+    #[error("the ceremony is closed")]
+    CeremonyClosed,
+    #[error("no deadline extensions left")]
+    NoExtensionsLeft,
+    #[error("lobby store error: {0}")]
+    Store(#[from] LobbyStoreError),
+    #[error("error in storage layer: {0}")]
+    Storage(#[from] StorageError),
+}
+
+/// What [`SharedLobbyState::expire_slot`] found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotExpiry {
+    /// The slot expired now.
+    Expired,
+    /// The slot is still held, check again after the delay.
+    Pending(Duration),
+    /// This sequencer does not know the slot.
+    Unknown,
+}
+
+/// Capacity of the lobby event channel. Subscribers that fall further behind
+/// miss events.
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Lobby changes pushed to `/ws/lobby` subscribers.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LobbyEvent {
+    /// The number of participants waiting in the lobby changed.
+    LobbySize { lobby_size: usize },
+    /// A participant was picked to contribute.
+    ContributionStarted,
+    /// The contribution slot is free, the next participant may try to
+    /// contribute.
+    SlotOpened,
+    /// A contribution was verified and added to the transcript.
+    ContributionVerified { num_contributions: usize },
+    /// The admission lottery of an epoch was drawn, see `/info/lottery`.
+    LotteryDrawn { epoch: u64 },
 }
 
 #[derive(Clone)]
 pub struct SharedLobbyState {
-    inner:   Arc<Mutex<LobbyState>>,
-    options: Options,
+    inner:      Arc<Mutex<LobbyState>>,
+    /// Replaced when the configuration is reloaded, see [`crate::config`].
+    options:    Arc<RwLock<Options>>,
+    /// Set by [`crate::lobby_tuning`] in place of the configured options.
+    tuning:     Arc<RwLock<Option<LobbyTuning>>>,
+    events:     broadcast::Sender<LobbyEvent>,
+    /// Number of events published, see [`Self::generation`].
+    generation: Arc<AtomicU64>,
+    strategy:   Arc<dyn SlotStrategy>,
+    /// Queue and slot shared with the other replicas, see
+    /// [`crate::lobby_store`].
+    store:      SharedLobbyStore,
+    webhook:    Webhook,
+    /// Where slot expiries are scheduled, see [`crate::scheduler`], and the
+    /// slot is recorded, see [`PersistentStorage::claim_slot`]. Without it
+    /// slots do not expire.
+    storage:    Option<PersistentStorage>,
+    /// Priorities of the providers, see [`Candidate::priority`].
+    rules:      Option<SharedRuleSet>,
+    /// Whether only the sessions admitted by the lottery get the slot.
+    lottery:    bool,
 }
 
 impl SharedLobbyState {
     pub fn new(options: Options) -> Self {
+        Self::with_store(options, Arc::new(MemoryLobbyStore::default()))
+    }
+
+    pub fn with_store(options: Options, store: SharedLobbyStore) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
         Self {
             inner: Arc::default(),
-            options,
+            strategy: options.lobby_strategy.strategy(),
+            options: Arc::new(RwLock::new(options)),
+            tuning: Arc::default(),
+            events,
+            generation: Arc::default(),
+            store,
+            webhook: Webhook::default(),
+            storage: None,
+            rules: None,
+            lottery: false,
+        }
+    }
+
+    #[must_use]
+    pub fn with_storage(mut self, storage: PersistentStorage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    #[must_use]
+    pub fn with_webhook(mut self, webhook: Webhook) -> Self {
+        self.webhook = webhook;
+        self
+    }
+
+    #[must_use]
+    pub fn with_provider_rules(mut self, rules: SharedRuleSet) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
+    /// Admits only the sessions drawn by the lottery, nobody until the first
+    /// draw is set with [`Self::set_lottery_draw`].
+    #[must_use]
+    pub const fn with_lottery(mut self) -> Self {
+        self.lottery = true;
+        self
+    }
+
+    /// The current lobby options, with the tuned lobby size and check-in
+    /// frequency, if any.
+    #[must_use]
+    pub fn options(&self) -> Options {
+        let mut options = self.configured_options();
+        if let Some(tuning) = self.tuning() {
+            options.max_lobby_size = tuning.max_lobby_size;
+            options.lobby_checkin_frequency = tuning.lobby_checkin_frequency;
+        }
+        options
+    }
+
+    /// The lobby options as configured.
+    #[must_use]
+    pub fn configured_options(&self) -> Options {
+        self.options.read().unwrap().clone()
+    }
+
+    #[must_use]
+    pub fn tuning(&self) -> Option<LobbyTuning> {
+        *self.tuning.read().unwrap()
+    }
+
+    /// Overrides the configured lobby size and check-in frequency, until set
+    /// to `None`.
+    pub fn set_tuning(&self, tuning: Option<LobbyTuning>) {
+        *self.tuning.write().unwrap() = tuning;
+    }
+
+    /// Replaces the lobby options. The slot strategy is chosen once, so a
+    /// changed `--lobby-strategy` is ignored. Deadlines already handed out
+    /// are kept.
+    pub fn set_options(&self, options: Options) {
+        let mut current = self.options.write().unwrap();
+        if options.lobby_strategy != current.lobby_strategy {
+            warn!("--lobby-strategy can not be changed without a restart");
+        }
+        *current = Options {
+            lobby_strategy: current.lobby_strategy,
+            ..options
+        };
+    }
+
+    /// Sends `event` to the ceremony's webhook, if one is configured.
+    pub fn notify(&self, event: WebhookEvent) {
+        self.webhook.notify(event);
+    }
+
+    /// Frees the slot in the store and the database and drops its expiry.
+    /// Failures are only logged, the slot is freed anyway once its timeout
+    /// passes.
+    async fn release_slot(&self, participant: &SessionId) {
+        self.release_slot_hash(&participant.hash()).await;
+    }
+
+    async fn release_slot_hash(&self, session_hash: &str) {
+        if let Err(error) = self.store.release_slot(session_hash).await {
+            error!(?error, "failed to release the contribution slot");
+        }
+        if let Some(storage) = &self.storage {
+            if let Err(error) = storage.release_slot(session_hash).await {
+                error!(?error, "failed to release the recorded contribution slot");
+            }
+            if let Err(error) = storage
+                .delete_job(JobKind::SlotExpiry.into(), session_hash)
+                .await
+            {
+                error!(?error, "failed to drop the slot expiry");
+            }
+        }
+    }
+
+    /// Records the slot of `participant` for contribution `slot` until `ttl`
+    /// passes, see [`PersistentStorage::claim_slot`]. Always succeeds without
+    /// a database.
+    async fn claim_recorded_slot(
+        &self,
+        participant: &SessionId,
+        slot: usize,
+        ttl: Duration,
+    ) -> Result<bool, StorageError> {
+        match &self.storage {
+            Some(storage) => {
+                storage
+                    .claim_slot(&participant.hash(), slot, scheduler::after(ttl))
+                    .await
+            }
+            None => Ok(true),
+        }
+    }
+
+    /// Keeps the recorded slot of `participant` until `ttl` passes. Returns
+    /// whether they still hold it.
+    async fn extend_recorded_slot(
+        &self,
+        participant: &SessionId,
+        ttl: Duration,
+    ) -> Result<bool, StorageError> {
+        match &self.storage {
+            Some(storage) => {
+                storage
+                    .extend_slot(&participant.hash(), scheduler::after(ttl))
+                    .await
+            }
+            None => Ok(true),
+        }
+    }
+
+    async fn leave_store(&self, participant: &SessionId) {
+        if let Err(error) = self.store.leave(&participant.hash()).await {
+            error!(?error, "failed to remove participant from the lobby store");
+        }
+    }
+
+    /// See [`Candidate::priority`].
+    fn priority(&self, state: &LobbyState, uid: &str) -> u32 {
+        let rules = self
+            .rules
+            .as_ref()
+            .map_or(0, |rules| rules.current().priority(uid));
+        state
+            .priorities
+            .get(uid)
+            .copied()
+            .unwrap_or_default()
+            .max(rules)
+    }
+
+    /// Sets the lobby priority of `uid`, which outranks that of their
+    /// provider if higher. 0 removes it.
+    pub async fn set_priority(&self, uid: &str, priority: u32) {
+        let mut state = self.inner.lock().await;
+        if priority == 0 {
+            state.priorities.remove(uid);
+        } else {
+            state.priorities.insert(uid.to_string(), priority);
+        }
+    }
+
+    /// Sets the priorities loaded from the database on startup.
+    pub async fn restore_priorities(&self, priorities: Vec<(String, u32)>) {
+        self.inner.lock().await.priorities.extend(priorities);
+    }
+
+    /// Hashes of the sessions checked in to the lobby, which enter the
+    /// lottery.
+    pub async fn lottery_entrants(&self) -> BTreeSet<String> {
+        let now = Instant::now();
+        let max_delay = self.options().max_checkin_delay();
+        let state = self.inner.lock().await;
+        state
+            .sessions_in_lobby
+            .iter()
+            .filter(|(_, info)| now.duration_since(info.last_ping_time) <= max_delay)
+            .map(|(id, _)| id.hash())
+            .collect()
+    }
+
+    /// Admits the winners of `draw` until the next one.
+    pub async fn set_lottery_draw(&self, draw: Draw) {
+        let mut state = self.inner.lock().await;
+        state.admitted = draw.admitted();
+        state.lottery_draw = Some(draw);
+    }
+
+    /// Whether the lottery admits `session_id` in the current epoch.
+    fn is_admitted(&self, state: &LobbyState, session_id: &SessionId) -> bool {
+        !self.lottery || state.admitted.contains(&session_id.hash())
+    }
+
+    /// The participant the free slot is held for. Drawn by the strategy, and
+    /// drawn again once they stop pinging or leave the lobby.
+    fn next_contributor(&self, state: &mut LobbyState, caller: &SessionId) -> SessionId {
+        let now = Instant::now();
+        let options = self.options();
+        let max_delay = options.max_checkin_delay();
+        state.requeued.retain(|_, until| *until > now);
+        let requeued = &state.requeued;
+        let admitted = &state.admitted;
+        let is_waiting = |id: &SessionId, info: &SessionInfo| {
+            now.duration_since(info.last_ping_time) <= max_delay
+                && !requeued.contains_key(&info.token.unique_identifier())
+                && (!self.lottery || admitted.contains(&id.hash()))
+        };
+
+        if let Some(next) = &state.next_contributor {
+            if state
+                .sessions_in_lobby
+                .get(next)
+                .map_or(false, |info| is_waiting(next, info))
+            {
+                return next.clone();
+            }
+        }
+        let mut candidates = state
+            .sessions_in_lobby
+            .iter()
+            .filter(|(id, info)| is_waiting(id, info))
+            .map(|(id, info)| Candidate {
+                id,
+                waited: info
+                    .lobby_entered_at
+                    .map_or(Duration::ZERO, |entered| now.duration_since(entered)),
+                priority: self.priority(state, &info.token.unique_identifier()),
+            })
+            .collect::<Vec<_>>();
+        let rank = |candidate: &Candidate<'_>| {
+            if candidate.waited >= options.priority_max_wait {
+                u32::MAX
+            } else {
+                candidate.priority
+            }
+        };
+        if let Some(highest) = candidates.iter().map(rank).max() {
+            candidates.retain(|candidate| rank(candidate) == highest);
         }
+        // A caller of lower priority stands in for the candidate that waited
+        // the longest, so that strategies like first come pick among the
+        // candidates.
+        let caller = if candidates.iter().any(|candidate| candidate.id == caller) {
+            caller
+        } else {
+            candidates
+                .iter()
+                .max_by_key(|candidate| candidate.waited)
+                .map_or(caller, |candidate| candidate.id)
+        };
+        let next = self.strategy.pick(caller, &candidates, &mut thread_rng());
+        state.next_contributor = Some(next.clone());
+        next
+    }
+
+    /// Changes whenever an event is published, i.e. whenever what
+    /// `/info/status` shows may have changed on this sequencer.
+    #[must_use]
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LobbyEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn publish(&self, event: LobbyEvent) {
+        self.generation.fetch_add(1, Ordering::Release);
+        // Sending only fails if nobody is listening.
+        let _ = self.events.send(event);
     }
 
+    /// Hands the slot for contribution number `slot` to `participant`,
+    /// provided it is their turn.
     pub async fn set_current_contributor(
         &self,
         participant: &SessionId,
+        slot: usize,
+        reservation: String,
         compute_deadline: Duration,
-        storage: PersistentStorage,
     ) -> Result<(), ActiveContributorError> {
+        self.grant_slot(participant, compute_deadline, || async move {
+            Ok((slot, reservation, ()))
+        })
+        .await
+    }
+
+    /// Hands the slot to `participant`, provided it is their turn. Only once
+    /// the lobby store gave them the slot, `grant` is called for the number
+    /// of the contribution, the reservation handed out with it and what else
+    /// the caller needs, so that participants turned away cost nothing more.
+    /// If `grant` fails the slot is released again.
+    pub async fn grant_slot<T, E, F, Fut>(
+        &self,
+        participant: &SessionId,
+        compute_deadline: Duration,
+        grant: F,
+    ) -> Result<T, E>
+    where
+        F: FnOnce() -> Fut + Send,
+        Fut: Future<Output = Result<(usize, String, T), E>> + Send,
+        E: From<ActiveContributorError>,
+    {
         let mut state = self.inner.lock().await;
 
+        if state.closed {
+            return Err(ActiveContributorError::CeremonyClosed.into());
+        }
+        if state.shutting_down {
+            return Err(ActiveContributorError::ShuttingDown.into());
+        }
+        if state.paused {
+            return Err(ActiveContributorError::LobbyPaused.into());
+        }
+
         if matches!(state.active_contributor, ActiveContributor::None) {
-            let session_info = state
-                .sessions_in_lobby
-                .remove(participant)
-                .ok_or(ActiveContributorError::UserNotInLobby)?;
+            let uid = match state.sessions_in_lobby.get(participant) {
+                Some(info) => info.token.unique_identifier(),
+                None => return Err(ActiveContributorError::UserNotInLobby.into()),
+            };
+            if let Some(until) = state.requeued.get(&uid) {
+                let penalty = until.saturating_duration_since(Instant::now());
+                if !penalty.is_zero() {
+                    return Err(ActiveContributorError::Requeued(penalty).into());
+                }
+            }
+            if !self.is_admitted(&state, participant) {
+                let next_draw = state.lottery_draw.as_ref().map_or(Duration::ZERO, |draw| {
+                    (draw.ends_at - Utc::now()).to_std().unwrap_or_default()
+                });
+                return Err(ActiveContributorError::NotAdmitted(next_draw).into());
+            }
+            if &self.next_contributor(&mut state, participant) != participant {
+                return Err(ActiveContributorError::NotUsersTurn.into());
+            }
+            // Another replica may have handed out the slot.
+            match self
+                .store
+                .acquire_slot(&participant.hash(), compute_deadline)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(ActiveContributorError::AnotherContributionInProgress.into())
+                }
+                Err(error) => return Err(ActiveContributorError::from(error).into()),
+            }
+            let (slot, reservation, granted) = match grant().await {
+                Ok(granted) => granted,
+                Err(error) => {
+                    drop(state);
+                    self.release_slot(participant).await;
+                    return Err(error);
+                }
+            };
+            // The database keeps the slot across restarts, and has the final
+            // say between sequencers sharing it.
+            match self
+                .claim_recorded_slot(participant, slot, compute_deadline)
+                .await
+            {
+                Ok(true) => {}
+                Ok(false) => {
+                    drop(state);
+                    self.release_slot(participant).await;
+                    return Err(ActiveContributorError::AnotherContributionInProgress.into());
+                }
+                Err(error) => {
+                    drop(state);
+                    self.release_slot(participant).await;
+                    return Err(ActiveContributorError::from(error).into());
+                }
+            }
+            let session_info = match state.sessions_in_lobby.get(participant) {
+                Some(info) => info.clone(),
+                None => return Err(ActiveContributorError::UserNotInLobby.into()),
+            };
+            // Scheduled before the slot is handed out, so that it can not be
+            // held past a restart without an expiry.
+            if let Err(error) = self
+                .schedule_expiry(participant, &session_info, compute_deadline)
+                .await
+            {
+                drop(state);
+                self.release_slot(participant).await;
+                return Err(ActiveContributorError::from(error).into());
+            }
+            state.next_contributor = None;
+            state.sessions_in_lobby.remove(participant);
+            state.slots_handed_out.push_back(Instant::now());
+            if state.slots_handed_out.len() > DRAIN_SAMPLE {
+                state.slots_handed_out.pop_front();
+            }
 
             state.active_contributor = ActiveContributor::AwaitingContribution {
                 session: SessionInfoWithId {
                     id:   participant.clone(),
                     info: session_info,
                 },
+                reservation,
                 last_contribution_file_request: Instant::now(),
+                deadline: Instant::now() + compute_deadline,
+                extensions: 0,
+                last_heartbeat: Instant::now(),
             };
 
-            let inner = self.inner.clone();
-            let participant = participant.clone();
-
-            tokio::spawn(Self::expire_current_contributor(
-                inner,
-                participant,
-                compute_deadline,
-                storage,
-            ));
+            let lobby_size = state.sessions_in_lobby.len();
+            drop(state);
+            self.publish(LobbyEvent::ContributionStarted);
+            self.publish(LobbyEvent::LobbySize { lobby_size });
 
-            return Ok(());
+            return Ok(granted);
         }
 
-        Err(ActiveContributorError::AnotherContributionInProgress)
+        Err(ActiveContributorError::AnotherContributionInProgress.into())
     }
 
+    /// Marks `participant` as submitting, provided they hold the slot as
+    /// `uid`.
     pub async fn begin_contributing(
         &self,
         participant: &SessionId,
+        uid: &str,
     ) -> Result<SessionInfo, ActiveContributorError> {
         let mut state = self.inner.lock().await;
 
@@ -163,7 +969,21 @@ impl SharedLobbyState {
             ActiveContributor::AwaitingContribution {
                 session: info_with_id,
                 ..
-            } if &info_with_id.id == participant => {
+            } if &info_with_id.id == participant
+                && info_with_id.info.token.unique_identifier() == uid =>
+            {
+                // Keeps the slot through the verification, which may run past
+                // the compute deadline.
+                if !self
+                    .store
+                    .acquire_slot(&participant.hash(), self.options().compute_deadline)
+                    .await?
+                    || !self
+                        .extend_recorded_slot(participant, self.options().compute_deadline)
+                        .await?
+                {
+                    return Err(ActiveContributorError::NotUsersTurn);
+                }
                 let next_state = ActiveContributor::Contributing(info_with_id.clone());
                 let info = info_with_id.info.clone();
                 state.active_contributor = next_state;
@@ -173,48 +993,261 @@ impl SharedLobbyState {
         }
     }
 
+    /// Gives up the contribution slot of `participant`, provided they have not
+    /// yet submitted. Returns the token of the aborted contributor.
     pub async fn abort_contribution(
         &self,
         participant: &SessionId,
-    ) -> Result<(), ActiveContributorError> {
+    ) -> Result<IdToken, ActiveContributorError> {
         let mut state = self.inner.lock().await;
 
-        if !matches!(&state.active_contributor, ActiveContributor::AwaitingContribution { session: x, .. } if &x.id == participant)
-        {
-            return Err(ActiveContributorError::NotUsersTurn);
-        }
+        let token = match &state.active_contributor {
+            ActiveContributor::AwaitingContribution { session: x, .. } if &x.id == participant => {
+                x.info.token.clone()
+            }
+            _ => return Err(ActiveContributorError::NotUsersTurn),
+        };
 
         state.active_contributor = ActiveContributor::None;
+        drop(state);
+        self.release_slot(participant).await;
+        self.publish(LobbyEvent::SlotOpened);
 
-        Ok(())
+        Ok(token)
     }
 
     pub async fn clear_current_contributor(&self) {
         let mut state = self.inner.lock().await;
-        state.active_contributor = ActiveContributor::None;
+        let previous = std::mem::take(&mut state.active_contributor);
+        drop(state);
+        if let Some(session) = previous.session() {
+            self.release_slot(&session.id).await;
+        }
+        self.publish(LobbyEvent::SlotOpened);
+    }
+
+    pub async fn set_paused(&self, paused: bool) {
+        self.inner.lock().await.paused = paused;
+    }
+
+    /// Stops accepting participants and waits until nobody is contributing,
+    /// or until `deadline` passes. Returns whether the lobby became idle.
+    pub async fn drain(&self, deadline: Duration) -> bool {
+        self.inner.lock().await.shutting_down = true;
+        tokio::time::timeout(deadline, self.wait_until_idle())
+            .await
+            .is_ok()
+    }
+
+    /// Closes the lobby for good and waits like [`Self::drain`]. Returns
+    /// whether the lobby became idle.
+    pub async fn close(&self, deadline: Duration) -> bool {
+        self.inner.lock().await.closed = true;
+        tokio::time::timeout(deadline, self.wait_until_idle())
+            .await
+            .is_ok()
+    }
+
+    async fn wait_until_idle(&self) {
+        // Subscribe before checking, so that no change can be missed.
+        let mut events = self.subscribe();
+        loop {
+            if matches!(
+                self.inner.lock().await.active_contributor,
+                ActiveContributor::None
+            ) {
+                return;
+            }
+            if let Err(broadcast::error::RecvError::Closed) = events.recv().await {
+                return;
+            }
+        }
+    }
+
+    /// Removes the active contributor, provided they have not yet submitted
+    /// their contribution. Returns the token of the removed contributor.
+    pub async fn kick_current_contributor(&self) -> Result<IdToken, ActiveContributorError> {
+        let mut state = self.inner.lock().await;
+        match &state.active_contributor {
+            ActiveContributor::None => Err(ActiveContributorError::NotActiveContributor),
+            ActiveContributor::Contributing(_) => {
+                Err(ActiveContributorError::AnotherContributionInProgress)
+            }
+            ActiveContributor::AwaitingContribution { session, .. } => {
+                let token = session.info.token.clone();
+                let id = session.id.clone();
+                state.active_contributor = ActiveContributor::None;
+                drop(state);
+                self.release_slot(&id).await;
+                self.publish(LobbyEvent::SlotOpened);
+                Ok(token)
+            }
+        }
+    }
+
+    /// Drops all sessions belonging to `uid`, including the active contributor
+    /// if they have not yet submitted. Returns the number of sessions removed
+    /// and whether the active contributor was kicked.
+    pub async fn remove_uid(&self, uid: &str) -> (usize, bool) {
+        let mut state = self.inner.lock().await;
+        let count = |state: &LobbyState| {
+            state.sessions_in_lobby.len()
+                + state.sessions_out_of_lobby.len()
+                + state.restored_sessions.len()
+        };
+        let before = count(&state);
+        let left_lobby = state
+            .sessions_in_lobby
+            .iter()
+            .filter(|(_, info)| info.token.unique_identifier() == uid)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        state
+            .sessions_in_lobby
+            .retain(|_, info| info.token.unique_identifier() != uid);
+        state
+            .sessions_out_of_lobby
+            .retain(|_, info| info.token.unique_identifier() != uid);
+        state
+            .restored_sessions
+            .retain(|_, info| info.token.unique_identifier() != uid);
+        let removed = before - count(&state);
+
+        let kicked = match &state.active_contributor {
+            ActiveContributor::AwaitingContribution { session, .. }
+                if session.info.token.unique_identifier() == uid =>
+            {
+                Some(session.id.clone())
+            }
+            _ => None,
+        };
+        if kicked.is_some() {
+            state.active_contributor = ActiveContributor::None;
+        }
+        let lobby_size = state.sessions_in_lobby.len();
+        drop(state);
+        for id in &left_lobby {
+            self.leave_store(id).await;
+        }
+        self.publish(LobbyEvent::LobbySize { lobby_size });
+        if let Some(id) = &kicked {
+            self.release_slot(id).await;
+            self.publish(LobbyEvent::SlotOpened);
+        }
+        (removed, kicked.is_some())
+    }
+
+    pub async fn snapshot(&self) -> LobbySnapshot {
+        let heartbeat_timeout = self.options().contributor_heartbeat_timeout;
+        let state = self.inner.lock().await;
+        let now = Instant::now();
+        let active_contributor = match &state.active_contributor {
+            ActiveContributor::None => None,
+            ActiveContributor::AwaitingContribution {
+                session,
+                deadline,
+                last_heartbeat,
+                ..
+            } => {
+                let (expires_at, _) = expires_at(*deadline, *last_heartbeat, heartbeat_timeout);
+                Some(ActiveContributorSnapshot {
+                    uid:               session.info.token.unique_identifier(),
+                    contributing:      false,
+                    seconds_remaining: Some(expires_at.saturating_duration_since(now).as_secs()),
+                })
+            }
+            ActiveContributor::Contributing(session) => Some(ActiveContributorSnapshot {
+                uid:               session.info.token.unique_identifier(),
+                contributing:      true,
+                seconds_remaining: None,
+            }),
+        };
+        let lobby = state
+            .sessions_in_lobby
+            .values()
+            .map(|info| {
+                let uid = info.token.unique_identifier();
+                LobbyEntrySnapshot {
+                    priority: self.priority(&state, &uid),
+                    uid,
+                    seconds_since_ping: (now - info.last_ping_time).as_secs(),
+                }
+            })
+            .collect();
+        LobbySnapshot {
+            paused: state.paused,
+            active_contributor,
+            lobby,
+            session_count: state.sessions_out_of_lobby.len(),
+        }
     }
 
-    #[allow(clippy::needless_collect)]
-    pub async fn clear_lobby(&self, predicate: impl Fn(&SessionInfo) -> bool + Copy + Send) {
+    /// Moves the sessions matching `predicate` out of the lobby. Returns the
+    /// ids of the moved sessions.
+    pub async fn clear_lobby(
+        &self,
+        predicate: impl Fn(&SessionInfo) -> bool + Copy + Send,
+    ) -> Vec<SessionId> {
         let mut lobby_state = self.inner.lock().await;
         let sessions_to_remove = lobby_state
             .sessions_in_lobby
             .iter()
             .filter_map(|(id, info)| predicate(info).then(|| id.clone()))
             .collect::<Vec<_>>();
-        for id in sessions_to_remove {
-            let info = lobby_state.sessions_in_lobby.remove(&id);
+        if sessions_to_remove.is_empty() {
+            return sessions_to_remove;
+        }
+        for id in &sessions_to_remove {
+            let info = lobby_state.sessions_in_lobby.remove(id);
             if let Some(info) = info {
-                lobby_state.sessions_out_of_lobby.insert(id, info);
+                lobby_state.sessions_out_of_lobby.insert(id.clone(), info);
             }
         }
+        let lobby_size = lobby_state.sessions_in_lobby.len();
+        drop(lobby_state);
+        for id in &sessions_to_remove {
+            self.leave_store(id).await;
+        }
+        self.publish(LobbyEvent::LobbySize { lobby_size });
+        sessions_to_remove
     }
 
-    pub async fn clear_session(&self, predicate: impl Fn(&SessionInfo) -> bool + Send) {
+    /// Drops the sessions outside of the lobby matching `predicate`, and the
+    /// restored sessions that were never claimed. Returns the hashes of the
+    /// dropped sessions, as restored sessions are only known by theirs.
+    pub async fn clear_session(
+        &self,
+        predicate: impl Fn(&SessionInfo) -> bool + Send,
+    ) -> Vec<String> {
         let mut lobby_state = self.inner.lock().await;
-        lobby_state
-            .sessions_out_of_lobby
-            .retain(|_, info| !predicate(info));
+        let mut removed = Vec::new();
+        lobby_state.sessions_out_of_lobby.retain(|id, info| {
+            let expired = predicate(info);
+            if expired {
+                removed.push(id.hash());
+            }
+            !expired
+        });
+        lobby_state.restored_sessions.retain(|hash, info| {
+            let expired = predicate(info);
+            if expired {
+                removed.push(hash.clone());
+            }
+            !expired
+        });
+        removed
+    }
+
+    /// Hashes of the sessions known to this replica, restored ones included.
+    pub async fn session_hashes(&self) -> BTreeSet<String> {
+        let lobby_state = self.inner.lock().await;
+        lobby_state
+            .sessions_in_lobby
+            .keys()
+            .chain(lobby_state.sessions_out_of_lobby.keys())
+            .map(SessionId::hash)
+            .chain(lobby_state.restored_sessions.keys().cloned())
+            .collect()
     }
 
     pub async fn modify_participant<R>(
@@ -232,8 +1265,134 @@ impl SharedLobbyState {
             .map(fun)
     }
 
+    /// The uid of `session_id`, wherever the session is, and whether its
+    /// contribution is being verified.
+    pub async fn session_uid(&self, session_id: &SessionId) -> Option<(String, bool)> {
+        let state = self.inner.lock().await;
+        match &state.active_contributor {
+            ActiveContributor::AwaitingContribution { session, .. }
+                if &session.id == session_id =>
+            {
+                return Some((session.info.token.unique_identifier(), false));
+            }
+            ActiveContributor::Contributing(session) if &session.id == session_id => {
+                return Some((session.info.token.unique_identifier(), true));
+            }
+            _ => {}
+        }
+        state
+            .sessions_in_lobby
+            .get(session_id)
+            .or_else(|| state.sessions_out_of_lobby.get(session_id))
+            .or_else(|| state.restored_sessions.get(&session_id.hash()))
+            .map(|info| (info.token.unique_identifier(), false))
+    }
+
+    /// The info of `session_id` and where it is, wherever the session is.
+    pub async fn session_place(
+        &self,
+        session_id: &SessionId,
+    ) -> Option<(SessionInfo, SessionPlace)> {
+        let state = self.inner.lock().await;
+        match &state.active_contributor {
+            ActiveContributor::AwaitingContribution { session, .. }
+                if &session.id == session_id =>
+            {
+                return Some((session.info.clone(), SessionPlace::AwaitingContribution));
+            }
+            ActiveContributor::Contributing(session) if &session.id == session_id => {
+                return Some((session.info.clone(), SessionPlace::Contributing));
+            }
+            _ => {}
+        }
+        if let Some(info) = state.sessions_in_lobby.get(session_id) {
+            return Some((info.clone(), SessionPlace::InLobby));
+        }
+        state
+            .sessions_out_of_lobby
+            .get(session_id)
+            .map(|info| (info.clone(), SessionPlace::SignedIn))
+            .or_else(|| {
+                // Restored sessions are put back where they were once used.
+                state.restored_sessions.get(&session_id.hash()).map(|info| {
+                    let place = if info.lobby_entered_at.is_some() {
+                        SessionPlace::InLobby
+                    } else {
+                        SessionPlace::SignedIn
+                    };
+                    (info.clone(), place)
+                })
+            })
+    }
+
+    /// Returns the position of `session_id` among the participants waiting in
+    /// the lobby of every replica, ordered by the time they entered it.
+    pub async fn lobby_position(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<LobbyPosition, ActiveContributorError> {
+        let lobby_size = self.store.queue_len().await?;
+        let hash = session_id.hash();
+        let contribution_in_progress = match self.store.slot_holder().await? {
+            None => false,
+            Some(holder) if holder == hash => {
+                return Ok(LobbyPosition {
+                    position: 0,
+                    lobby_size,
+                    contribution_in_progress: true,
+                });
+            }
+            Some(_) => true,
+        };
+        let position = self
+            .store
+            .position(&hash)
+            .await?
+            .ok_or(ActiveContributorError::UserNotInLobby)?;
+        Ok(LobbyPosition {
+            position,
+            lobby_size,
+            contribution_in_progress,
+        })
+    }
+
+    /// 1-based position of the session with `session_hash` among the
+    /// participants waiting in the lobby of every replica, or `None` if it is
+    /// not waiting.
+    pub async fn queue_position(
+        &self,
+        session_hash: &str,
+    ) -> Result<Option<usize>, LobbyStoreError> {
+        self.store.position(session_hash).await
+    }
+
+    pub async fn is_in_lobby(&self, session_id: &SessionId) -> bool {
+        self.inner
+            .lock()
+            .await
+            .sessions_in_lobby
+            .contains_key(session_id)
+    }
+
+    /// Size of the lobby of every replica. Falls back to the lobby of this
+    /// replica if the store is unavailable.
     pub async fn get_lobby_size(&self) -> usize {
-        self.inner.lock().await.sessions_in_lobby.len()
+        match self.store.queue_len().await {
+            Ok(size) => size,
+            Err(error) => {
+                error!(?error, "failed to read the lobby size from the store");
+                self.inner.lock().await.sessions_in_lobby.len()
+            }
+        }
+    }
+
+    /// Whether the slot is free and nobody waits in the lobby of any replica.
+    pub async fn is_quiet(&self) -> bool {
+        let slot_free = matches!(
+            self.inner.lock().await.active_contributor,
+            ActiveContributor::None
+        );
+        slot_free && self.get_lobby_size().await == 0
     }
 
     pub async fn get_session_count(&self) -> usize {
@@ -259,30 +1418,140 @@ impl SharedLobbyState {
         }
 
         let sessions = &mut state.sessions_out_of_lobby;
-        if sessions.len() >= self.options.max_sessions_count && !sessions.contains_key(&session_id)
+        if sessions.len() >= self.options().max_sessions_count
+            && !sessions.contains_key(&session_id)
         {
             return Err(ActiveContributorError::SessionCountLimitExceeded);
         }
+        // A new sign-in replaces the session from before the restart.
+        let uid = session_info.token.unique_identifier();
         sessions.insert(session_id, session_info);
+        state
+            .restored_sessions
+            .retain(|_, info| info.token.unique_identifier() != uid);
 
         Ok(())
     }
 
-    pub async fn enter_lobby(&self, session_id: &SessionId) -> Result<(), ActiveContributorError> {
+    /// Keeps sessions loaded from the database until their token is seen
+    /// again, see [`Self::claim_restored_session`]. Returns the number of
+    /// restored sessions.
+    pub async fn restore_sessions(&self, sessions: Vec<StoredSession>) -> usize {
+        let now = Instant::now();
+        let utc_now = Utc::now();
         let mut state = self.inner.lock().await;
+        for session in sessions {
+            // Sessions saved under legacy uids have no participant id but
+            // their uid.
+            let participant_id = session.identity.as_deref().unwrap_or(&session.uid);
+            let identity = match participant_id.parse::<Identity>() {
+                Ok(identity) => identity,
+                Err(error) => {
+                    warn!(uid = %session.uid, ?error, "dropping stored session");
+                    continue;
+                }
+            };
+            // Instants can not be persisted, so the time spent in the lobby
+            // is carried over instead.
+            let lobby_entered_at = session.lobby_entered_at.map(|entered_at| {
+                let waited = (utc_now - entered_at).to_std().unwrap_or_default();
+                now.checked_sub(waited).unwrap_or(now)
+            });
+            state
+                .restored_sessions
+                .insert(session.token_hash, SessionInfo {
+                    token: IdToken {
+                        identity,
+                        exp: u64::MAX,
+                    },
+                    last_ping_time: now,
+                    is_first_ping_attempt: true,
+                    lobby_entered_at,
+                    eligibility_score: session.eligibility_score,
+                    failed_rules: None,
+                    checkin_challenge: None,
+                    min_poll_interval: None,
+                });
+        }
+        state.restored_sessions.len()
+    }
+
+    /// Moves the session restored for `session_id`, if any, back into the
+    /// lobby if it was in there, or next to it otherwise. Returns its uid.
+    pub async fn claim_restored_session(&self, session_id: &SessionId) -> Option<String> {
+        let mut state = self.inner.lock().await;
+        if state.restored_sessions.is_empty() {
+            return None;
+        }
+        let info = state.restored_sessions.remove(&session_id.hash())?;
+        let uid = info.token.unique_identifier();
+        if let Some(entered_at) = info.lobby_entered_at {
+            state.sessions_in_lobby.insert(session_id.clone(), info);
+            let lobby_size = state.sessions_in_lobby.len();
+            drop(state);
+            let joined_at = Utc::now()
+                - chrono::Duration::from_std(entered_at.elapsed())
+                    .unwrap_or_else(|_| chrono::Duration::zero());
+            if let Err(error) = self.store.join(&session_id.hash(), joined_at).await {
+                error!(?error, "failed to add restored session to the lobby store");
+            }
+            self.publish(LobbyEvent::LobbySize { lobby_size });
+        } else {
+            state.sessions_out_of_lobby.insert(session_id.clone(), info);
+        }
+        Some(uid)
+    }
+
+    /// Moves the session into the lobby. Returns whether it was not in the
+    /// lobby before.
+    pub async fn enter_lobby(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<bool, ActiveContributorError> {
+        let mut state = self.inner.lock().await;
+
+        if state.closed {
+            return Err(ActiveContributorError::CeremonyClosed);
+        }
+        if state.shutting_down {
+            return Err(ActiveContributorError::ShuttingDown);
+        }
 
         // If session is not in sessions_out_of_lobby, it was already moved to lobby or
         // to active contributor state
-        if let Some(session) = state.sessions_out_of_lobby.remove(session_id) {
-            let lobby = &mut state.sessions_in_lobby;
+        if !state.sessions_out_of_lobby.contains_key(session_id) {
+            return Ok(false);
+        }
 
-            if lobby.len() >= self.options.max_lobby_size {
-                return Err(ActiveContributorError::LobbySizeLimitExceeded);
-            }
-            lobby.insert(session_id.clone(), session);
+        // The store holds the lobby of every replica. Sessions turned away stay
+        // out of the lobby, so that they can try again.
+        let queued = self.store.queue_len().await?;
+        let options = self.options();
+        let max_lobby_size = options.max_lobby_size;
+        if queued >= max_lobby_size {
+            let drain = drain_interval(&state.slots_handed_out, options.compute_deadline);
+            return Err(ActiveContributorError::LobbySizeLimitExceeded(
+                lobby_full_retry_after(drain, thread_rng().gen(), options.min_checkin_delay()),
+            ));
+        }
+        self.store.join(&session_id.hash(), Utc::now()).await?;
+        if queued + 1 == max_lobby_size {
+            self.notify(WebhookEvent::LobbyFull {
+                lobby_size: max_lobby_size,
+            });
         }
 
-        Ok(())
+        if let Some(mut session) = state.sessions_out_of_lobby.remove(session_id) {
+            let lobby = &mut state.sessions_in_lobby;
+            session.lobby_entered_at = Some(Instant::now());
+            // In the lobby, the check-in frequency applies again.
+            session.min_poll_interval = None;
+            lobby.insert(session_id.clone(), session);
+            let lobby_size = lobby.len();
+            drop(state);
+            self.publish(LobbyEvent::LobbySize { lobby_size });
+        }
+        Ok(true)
     }
 
     #[cfg(test)]
@@ -299,55 +1568,330 @@ impl SharedLobbyState {
             .collect()
     }
 
-    async fn expire_current_contributor(
-        inner: Arc<Mutex<LobbyState>>,
-        participant: SessionId,
+    /// Stores the job that expires the slot of `participant` once their
+    /// deadline or heartbeat is missed.
+    async fn schedule_expiry(
+        &self,
+        participant: &SessionId,
+        info: &SessionInfo,
         compute_deadline: Duration,
-        storage: PersistentStorage,
-    ) {
-        tokio::time::sleep(compute_deadline).await;
+    ) -> Result<(), StorageError> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(()),
+        };
+        let now = Instant::now();
+        let heartbeat_timeout = self.options().contributor_heartbeat_timeout;
+        let (expires_at, _) = expires_at(now + compute_deadline, now, heartbeat_timeout);
+        storage
+            .schedule_job(
+                JobKind::SlotExpiry.into(),
+                &participant.hash(),
+                &info.token.unique_identifier(),
+                scheduler::after(expires_at.saturating_duration_since(now)),
+            )
+            .await
+    }
 
-        let mut state = inner.lock().await;
+    /// Expires the slot of the session with `session_hash`, if they have not
+    /// submitted and missed their deadline or heartbeat. Run by the
+    /// `slot_expiry` job, see [`crate::scheduler`].
+    pub async fn expire_slot(
+        &self,
+        session_hash: &str,
+        storage: &PersistentStorage,
+        audit: &AuditLog,
+    ) -> SlotExpiry {
+        let heartbeat_timeout = self.options().contributor_heartbeat_timeout;
+        let mut state = self.inner.lock().await;
+        let (session, reason) = match &state.active_contributor {
+            ActiveContributor::AwaitingContribution {
+                session: x,
+                deadline,
+                last_heartbeat,
+                ..
+            } if x.id.hash() == session_hash => {
+                let (expires_at, reason) =
+                    expires_at(*deadline, *last_heartbeat, heartbeat_timeout);
+                // The deadline may have been extended, and heartbeats arrived,
+                // since the job was scheduled.
+                let now = Instant::now();
+                if now < expires_at {
+                    return SlotExpiry::Pending(expires_at - now);
+                }
+                (x.clone(), reason)
+            }
+            // Verification may run past the deadline, the slot is released
+            // once it is done.
+            ActiveContributor::Contributing(x) if x.id.hash() == session_hash => {
+                return SlotExpiry::Pending(self.options().compute_deadline);
+            }
+            _ => return SlotExpiry::Unknown,
+        };
+        state.active_contributor = ActiveContributor::None;
+        drop(state);
+        let uid = session.info.token.unique_identifier();
+        let requeued = self.requeue(&uid, reason, storage).await;
+        let recorded = if requeued {
+            self.reenter_lobby(session).await;
+            Ok(())
+        } else if reason == "timeout" {
+            storage
+                .reject_contribution(&uid, &RejectionReason::DeadlineExceeded)
+                .await
+        } else {
+            storage.expire_contribution(&uid).await
+        };
+        if let Err(error) = recorded {
+            error!(%uid, ?error, "failed to record expired contribution");
+        }
+        self.expired(session_hash, uid, reason, requeued, audit)
+            .await;
+        SlotExpiry::Expired
+    }
 
-        if matches!(&state.active_contributor, ActiveContributor::AwaitingContribution{ session: x, .. } if x.id == participant)
+    /// Records that the slot of `uid`, expired for `reason`, is requeued,
+    /// provided `--expiry-policy` and their attempts allow it. Returns whether
+    /// it was.
+    async fn requeue(&self, uid: &str, reason: &str, storage: &PersistentStorage) -> bool {
+        let options = self.options();
+        if options.expiry_policy != ExpiryPolicy::Requeue || !REQUEUED_REASONS.contains(&reason) {
+            return false;
+        }
+        match storage
+            .requeue_contribution(uid, options.max_slot_attempts)
+            .await
         {
-            state.active_contributor = ActiveContributor::None;
+            Ok(true) => {}
+            Ok(false) => return false,
+            Err(error) => {
+                error!(%uid, ?error, "failed to requeue expired contribution");
+                return false;
+            }
+        }
+        CONTRIBUTIONS_REQUEUED.inc();
+        self.inner
+            .lock()
+            .await
+            .requeued
+            .insert(uid.to_string(), Instant::now() + options.requeue_penalty);
+        true
+    }
 
-            drop(state);
-            storage.expire_contribution(&participant.0).await.unwrap();
+    /// Puts the requeued contributor `session` back into the lobby. They keep
+    /// their place, but have to keep pinging like everyone else.
+    async fn reenter_lobby(&self, session: SessionInfoWithId) {
+        let mut state = self.inner.lock().await;
+        if state.closed || state.shutting_down {
+            return;
         }
+        let entered_at = session.info.lobby_entered_at.unwrap_or_else(Instant::now);
+        let hash = session.id.hash();
+        state.sessions_in_lobby.insert(session.id, SessionInfo {
+            last_ping_time: Instant::now(),
+            lobby_entered_at: Some(entered_at),
+            ..session.info
+        });
+        let lobby_size = state.sessions_in_lobby.len();
+        drop(state);
+        let joined_at = Utc::now()
+            - chrono::Duration::from_std(entered_at.elapsed())
+                .unwrap_or_else(|_| chrono::Duration::zero());
+        if let Err(error) = self.store.join(&hash, joined_at).await {
+            error!(
+                ?error,
+                "failed to add requeued contributor to the lobby store"
+            );
+        }
+        self.publish(LobbyEvent::LobbySize { lobby_size });
     }
 
+    /// Expires the slot of the session with `session_hash`, held by `uid` but
+    /// not known to the lobby, e.g. because the sequencer holding it
+    /// restarted. Returns whether the contribution was still in progress.
+    pub async fn expire_orphaned_slot(
+        &self,
+        session_hash: &str,
+        uid: String,
+        storage: &PersistentStorage,
+        audit: &AuditLog,
+    ) -> Result<bool, StorageError> {
+        // Their session is not known here, they sign in again once requeued.
+        let requeued = self.requeue(&uid, "orphaned", storage).await;
+        if !requeued && !storage.expire_unfinished_contribution(&uid).await? {
+            return Ok(false);
+        }
+        self.expired(session_hash, uid, "orphaned", requeued, audit)
+            .await;
+        Ok(true)
+    }
+
+    async fn expired(
+        &self,
+        session_hash: &str,
+        uid: String,
+        reason: &'static str,
+        requeued: bool,
+        audit: &AuditLog,
+    ) {
+        CONTRIBUTIONS_EXPIRED.with_label_values(&[reason]).inc();
+        self.notify(WebhookEvent::ContributionExpired {
+            uid: uid.clone(),
+            reason,
+        });
+        self.release_slot_hash(session_hash).await;
+        self.publish(LobbyEvent::SlotOpened);
+        audit
+            .record(
+                AuditAction::ContributionExpired,
+                Some(uid),
+                None,
+                if requeued {
+                    format!("{reason}, requeued")
+                } else {
+                    reason.to_string()
+                },
+            )
+            .await;
+    }
+
+    /// Moves the deadline of the active contributor back by
+    /// `--deadline-extension`, provided `session_id` is them, has not yet
+    /// submitted and has extensions left. `reservation` signs a new
+    /// reservation valid for the given time, it replaces the one handed out
+    /// with the slot. Returns the new reservation and the number of
+    /// extensions left.
+    pub async fn extend_deadline(
+        &self,
+        session_id: &SessionId,
+        reservation: impl FnOnce(&str, Duration) -> String + Send,
+    ) -> Result<(String, usize), ActiveContributorError> {
+        let mut state = self.inner.lock().await;
+        let options = self.options();
+        let max_extensions = options.max_deadline_extensions;
+        match &mut state.active_contributor {
+            ActiveContributor::AwaitingContribution {
+                session,
+                reservation: current,
+                deadline,
+                extensions,
+                ..
+            } if &session.id == session_id => {
+                if *extensions >= max_extensions {
+                    return Err(ActiveContributorError::NoExtensionsLeft);
+                }
+                let new_deadline = *deadline + options.deadline_extension;
+                let valid_for = new_deadline.saturating_duration_since(Instant::now());
+                // Keeps the slot in the store until the new deadline.
+                if !self
+                    .store
+                    .acquire_slot(&session_id.hash(), valid_for)
+                    .await?
+                    || !self.extend_recorded_slot(session_id, valid_for).await?
+                {
+                    return Err(ActiveContributorError::NotActiveContributor);
+                }
+                *deadline = new_deadline;
+                *extensions += 1;
+                *current = reservation(&session.info.token.unique_identifier(), valid_for);
+                Ok((current.clone(), max_extensions - *extensions))
+            }
+            _ => Err(ActiveContributorError::NotActiveContributor),
+        }
+    }
+
+    /// Time left until the deadline of the active contributor, if
+    /// `session_id` is them and has not yet submitted.
+    pub async fn time_left(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<Duration, ActiveContributorError> {
+        match &self.inner.lock().await.active_contributor {
+            ActiveContributor::AwaitingContribution {
+                session, deadline, ..
+            } if &session.id == session_id => {
+                Ok(deadline.saturating_duration_since(Instant::now()))
+            }
+            _ => Err(ActiveContributorError::NotActiveContributor),
+        }
+    }
+
+    /// Records that the active contributor is still computing, if
+    /// `session_id` is them and has not yet submitted. Returns the time left
+    /// until their deadline.
+    pub async fn heartbeat(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<Duration, ActiveContributorError> {
+        match &mut self.inner.lock().await.active_contributor {
+            ActiveContributor::AwaitingContribution {
+                session,
+                deadline,
+                last_heartbeat,
+                ..
+            } if &session.id == session_id => {
+                *last_heartbeat = Instant::now();
+                Ok(deadline.saturating_duration_since(Instant::now()))
+            }
+            _ => Err(ActiveContributorError::NotActiveContributor),
+        }
+    }
+
+    /// Returns the reservation of the active contributor, if `session_id` is
+    /// them and has not asked too recently.
     pub async fn request_contribution_file_again(
         &self,
         session_id: &SessionId,
-    ) -> Result<(), ActiveContributorError> {
+    ) -> Result<String, ActiveContributorError> {
         let mut lobby_state = self.inner.lock().await;
         if let ActiveContributor::AwaitingContribution {
             session,
+            reservation,
             last_contribution_file_request,
+            ..
         } = &mut lobby_state.active_contributor
         {
             if &session.id == session_id {
-                if last_contribution_file_request.elapsed() < self.options.min_checkin_delay() {
-                    return Err(ActiveContributorError::RateLimited);
+                let wait = self
+                    .options()
+                    .min_checkin_delay()
+                    .saturating_sub(last_contribution_file_request.elapsed());
+                if !wait.is_zero() {
+                    return Err(ActiveContributorError::RateLimited(wait));
                 }
                 *last_contribution_file_request = Instant::now();
-                return Ok(());
+                return Ok(reservation.clone());
             }
         }
         Err(ActiveContributorError::NotActiveContributor)
     }
 }
 
-pub async fn clear_lobby_on_interval(state: SharedLobbyState, options: Options) {
-    let max_lobby_diff = options.lobby_checkin_frequency + options.lobby_checkin_tolerance;
-    let max_session_diff = options.session_expiration;
-
-    let mut interval = tokio::time::interval(options.lobby_flush_interval);
+/// When a slot expires, and why: at `deadline`, unless the heartbeat is due
+/// earlier.
+fn expires_at(
+    deadline: Instant,
+    last_heartbeat: Instant,
+    heartbeat_timeout: Duration,
+) -> (Instant, &'static str) {
+    match last_heartbeat.checked_add(heartbeat_timeout) {
+        Some(missed_at) if !heartbeat_timeout.is_zero() && missed_at < deadline => {
+            (missed_at, "heartbeat")
+        }
+        _ => (deadline, "timeout"),
+    }
+}
 
+pub async fn clear_lobby_on_interval(
+    state: SharedLobbyState,
+    storage: PersistentStorage,
+    power_saving: SharedPowerSaving,
+) {
     loop {
-        interval.tick().await;
+        // Read on every round, as the options may be reloaded.
+        let options = state.options();
+        tokio::time::sleep(power_saving.poll_interval(options.lobby_flush_interval)).await;
+        let max_lobby_diff = options.lobby_checkin_frequency + options.lobby_checkin_tolerance;
 
         let now = Instant::now();
         // Predicate that returns true whenever users go over the ping deadline
@@ -355,13 +1899,12 @@ pub async fn clear_lobby_on_interval(state: SharedLobbyState, options: Options)
             let time_diff = now - session_info.last_ping_time;
             time_diff > max_lobby_diff
         };
-        state.clear_lobby(lobby_predicate).await;
-
-        let session_predicate = |session_info: &SessionInfo| -> bool {
-            let time_diff = now - session_info.last_ping_time;
-            time_diff > max_session_diff
-        };
-        state.clear_session(session_predicate).await;
+        for id in state.clear_lobby(lobby_predicate).await {
+            if let Err(error) = storage.update_session_lobby(&id.hash(), None).await {
+                error!(?error, "failed to record session leaving the lobby");
+            }
+            fairness::finish_wait(&storage, &id, WaitOutcome::Timeout).await;
+        }
     }
 }
 
@@ -414,3 +1957,660 @@ async fn flush_on_predicate() {
         assert_eq!(participant.info.token.exp % 2, 1);
     }
 }
+
+#[tokio::test]
+async fn keeps_sessions_turned_away_by_a_full_lobby() {
+    use crate::{sessions::SessionId, test_util::create_test_session_info};
+
+    let state = SharedLobbyState::new(Options {
+        max_lobby_size: 1,
+        ..crate::test_util::test_options().lobby
+    });
+    let first = SessionId::new();
+    let second = SessionId::new();
+    for id in [&first, &second] {
+        state
+            .insert_session(id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+    }
+    state.enter_lobby(&first).await.unwrap();
+    assert!(matches!(
+        state.enter_lobby(&second).await,
+        Err(ActiveContributorError::LobbySizeLimitExceeded(_))
+    ));
+    // The second session can try again.
+    assert_eq!(state.get_session_count().await, 1);
+}
+
+#[tokio::test]
+async fn tells_sessions_their_place() {
+    use crate::{
+        sessions::SessionId,
+        test_util::{create_test_session_info, test_options},
+    };
+
+    let options = test_options();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    let id = SessionId::new();
+    assert!(state.session_place(&id).await.is_none());
+    state
+        .insert_session(id.clone(), create_test_session_info(100))
+        .await
+        .unwrap();
+    assert_eq!(
+        state.session_place(&id).await.map(|(_, place)| place),
+        Some(SessionPlace::SignedIn)
+    );
+    state.enter_lobby(&id).await.unwrap();
+    assert_eq!(
+        state.session_place(&id).await.map(|(_, place)| place),
+        Some(SessionPlace::InLobby)
+    );
+    state
+        .set_current_contributor(&id, 1, String::new(), options.lobby.compute_deadline)
+        .await
+        .unwrap();
+    assert_eq!(
+        state.session_place(&id).await.map(|(_, place)| place),
+        Some(SessionPlace::AwaitingContribution)
+    );
+}
+
+#[tokio::test]
+async fn publishes_lobby_events() {
+    use crate::{
+        sessions::SessionId,
+        test_util::{create_test_session_info, test_options},
+    };
+
+    let options = test_options();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    let mut events = state.subscribe();
+
+    let id = SessionId::new();
+    state
+        .insert_session(id.clone(), create_test_session_info(100))
+        .await
+        .unwrap();
+    state.enter_lobby(&id).await.unwrap();
+    state
+        .set_current_contributor(&id, 1, String::new(), options.lobby.compute_deadline)
+        .await
+        .unwrap();
+    state.clear_current_contributor().await;
+
+    assert_eq!(events.recv().await.unwrap(), LobbyEvent::LobbySize {
+        lobby_size: 1,
+    });
+    assert_eq!(
+        events.recv().await.unwrap(),
+        LobbyEvent::ContributionStarted
+    );
+    assert_eq!(events.recv().await.unwrap(), LobbyEvent::LobbySize {
+        lobby_size: 0,
+    });
+    assert_eq!(events.recv().await.unwrap(), LobbyEvent::SlotOpened);
+}
+
+#[tokio::test]
+async fn drain_waits_for_contributor() {
+    use crate::{
+        sessions::SessionId,
+        test_util::{create_test_session_info, test_options},
+    };
+
+    let options = test_options();
+    let state = SharedLobbyState::new(options.lobby.clone());
+    let contributor = SessionId::new();
+    let latecomer = SessionId::new();
+    for id in [&contributor, &latecomer] {
+        state
+            .insert_session(id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+    }
+    state.enter_lobby(&contributor).await.unwrap();
+    state
+        .set_current_contributor(
+            &contributor,
+            1,
+            String::new(),
+            options.lobby.compute_deadline,
+        )
+        .await
+        .unwrap();
+
+    assert!(!state.drain(Duration::from_millis(10)).await);
+    assert!(matches!(
+        state.enter_lobby(&latecomer).await,
+        Err(ActiveContributorError::ShuttingDown)
+    ));
+
+    let drained = tokio::spawn({
+        let state = state.clone();
+        async move { state.drain(Duration::from_secs(10)).await }
+    });
+    tokio::task::yield_now().await;
+    state.clear_current_contributor().await;
+    assert!(drained.await.unwrap());
+}
+
+#[tokio::test]
+async fn grants_only_the_slot_holder() {
+    use crate::{
+        sessions::SessionId,
+        test_util::{create_test_session_info, test_options},
+    };
+
+    use std::sync::atomic::AtomicBool;
+
+    let options = test_options();
+    let deadline = options.lobby.compute_deadline;
+    let state = SharedLobbyState::new(options.lobby.clone());
+    let contributor = SessionId::new();
+    let waiting = SessionId::new();
+    for id in [&contributor, &waiting] {
+        state
+            .insert_session(id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+    }
+    state.enter_lobby(&contributor).await.unwrap();
+
+    // A failed grant gives the slot up again.
+    let failed = state
+        .grant_slot(&contributor, deadline, || async {
+            Err::<(usize, String, ()), _>(ActiveContributorError::LobbyPaused)
+        })
+        .await;
+    assert!(matches!(failed, Err(ActiveContributorError::LobbyPaused)));
+    assert_eq!(state.store.slot_holder().await.unwrap(), None);
+
+    let granted = state
+        .grant_slot(&contributor, deadline, || async {
+            Ok::<_, ActiveContributorError>((1, "reservation".to_string(), 7))
+        })
+        .await
+        .unwrap();
+    assert_eq!(granted, 7);
+
+    // Participants turned away are not granted anything.
+    state.enter_lobby(&waiting).await.unwrap();
+    let called = AtomicBool::new(false);
+    let turned_away = state
+        .grant_slot(&waiting, deadline, || async {
+            called.store(true, Ordering::Relaxed);
+            Ok::<_, ActiveContributorError>((2, String::new(), ()))
+        })
+        .await;
+    assert!(matches!(
+        turned_away,
+        Err(ActiveContributorError::AnotherContributionInProgress)
+    ));
+    assert!(!called.load(Ordering::Relaxed));
+}
+
+#[test]
+fn measures_the_drain_rate() {
+    let fallback = Duration::from_secs(180);
+    let start = Instant::now();
+    let mut handed_out = VecDeque::from([start]);
+    assert_eq!(drain_interval(&handed_out, fallback), fallback);
+    handed_out.extend([
+        start + Duration::from_secs(40),
+        start + Duration::from_secs(120),
+    ]);
+    let drain = drain_interval(&handed_out, fallback);
+    assert_eq!(drain, Duration::from_secs(60));
+
+    let min = Duration::from_secs(28);
+    assert_eq!(lobby_full_retry_after(drain, 0.0, min), drain);
+    assert_eq!(
+        lobby_full_retry_after(drain, 0.5, min),
+        Duration::from_secs(90)
+    );
+    assert_eq!(lobby_full_retry_after(drain, 2.0, min), 2 * drain);
+    assert_eq!(
+        lobby_full_retry_after(Duration::from_secs(5), 0.5, min),
+        min
+    );
+}
+
+#[test]
+fn strategies_pick_candidates() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let ids = (0..3).map(|_| SessionId::new()).collect::<Vec<_>>();
+    let candidates = ids
+        .iter()
+        .zip([10, 300, 0])
+        .map(|(id, waited)| Candidate {
+            id,
+            waited: Duration::from_secs(waited),
+            priority: 0,
+        })
+        .collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let caller = &ids[2];
+    assert_eq!(&FirstCome.pick(caller, &candidates, &mut rng), caller);
+    assert_eq!(Fifo.pick(caller, &candidates, &mut rng), ids[1]);
+
+    let mut picks = BTreeMap::<SessionId, usize>::new();
+    for _ in 0..1000 {
+        *picks
+            .entry(WeightedByWait.pick(caller, &candidates, &mut rng))
+            .or_default() += 1;
+        *picks
+            .entry(RandomPick.pick(caller, &candidates, &mut rng))
+            .or_default() += 1;
+    }
+    // Uniform picks give everyone about 333, weighted ones give the longest
+    // waiting participant about 960.
+    assert!(picks[&ids[1]] > 1200);
+    assert!(picks[&ids[2]] > 250);
+}
+
+#[tokio::test]
+async fn holds_slot_for_strategy_pick() {
+    use crate::test_util::{create_test_session_info, test_options};
+
+    let mut options = test_options();
+    options.lobby.lobby_strategy = LobbyStrategyKind::Fifo;
+    let state = SharedLobbyState::new(options.lobby.clone());
+    let first = SessionId::new();
+    let second = SessionId::new();
+    for id in [&first, &second] {
+        state
+            .insert_session(id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        state.enter_lobby(id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let take_slot = |id: SessionId| {
+        let state = state.clone();
+        let deadline = options.lobby.compute_deadline;
+        async move {
+            state
+                .set_current_contributor(&id, 1, String::new(), deadline)
+                .await
+        }
+    };
+
+    assert!(matches!(
+        take_slot(second.clone()).await,
+        Err(ActiveContributorError::NotUsersTurn)
+    ));
+    take_slot(first).await.unwrap();
+    state.clear_current_contributor().await;
+    take_slot(second).await.unwrap();
+}
+
+#[tokio::test]
+async fn prefers_priority_up_to_max_wait() {
+    use crate::{
+        identity,
+        test_util::{create_test_session_info, test_options},
+    };
+
+    let mut options = test_options();
+    options.lobby.priority_max_wait = Duration::from_millis(50);
+    let state = SharedLobbyState::new(options.lobby.clone());
+    let join = |id: u64| {
+        let state = state.clone();
+        async move {
+            let session = SessionId::new();
+            let mut info = create_test_session_info(100);
+            info.token.identity = Identity::Github {
+                id,
+                username: "test_user".to_string(),
+            };
+            state.insert_session(session.clone(), info).await.unwrap();
+            state.enter_lobby(&session).await.unwrap();
+            session
+        }
+    };
+    let take_slot = |id: SessionId| {
+        let state = state.clone();
+        let deadline = options.lobby.compute_deadline;
+        async move {
+            state
+                .set_current_contributor(&id, 1, String::new(), deadline)
+                .await
+        }
+    };
+    for id in [2, 3] {
+        let uid = identity::uid(&Identity::Github {
+            id,
+            username: "test_user".to_string(),
+        });
+        state.set_priority(&uid, 1).await;
+    }
+
+    let waiting = join(1).await;
+    let favored = join(2).await;
+    assert!(matches!(
+        take_slot(waiting.clone()).await,
+        Err(ActiveContributorError::NotUsersTurn)
+    ));
+    take_slot(favored).await.unwrap();
+    state.clear_current_contributor().await;
+
+    // Past `--priority-max-wait`, the wait outranks any priority.
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    let latecomer = join(3).await;
+    take_slot(waiting).await.unwrap();
+    state.clear_current_contributor().await;
+    take_slot(latecomer).await.unwrap();
+}
+
+#[tokio::test]
+async fn admits_lottery_winners_only() {
+    use crate::test_util::{create_test_session_info, test_options};
+
+    let options = test_options();
+    let state = SharedLobbyState::new(options.lobby.clone()).with_lottery();
+    let mut sessions = Vec::new();
+    for id in 0..3 {
+        let session = SessionId::new();
+        let mut info = create_test_session_info(100);
+        info.token.identity = Identity::Github {
+            id,
+            username: "test_user".to_string(),
+        };
+        state.insert_session(session.clone(), info).await.unwrap();
+        state.enter_lobby(&session).await.unwrap();
+        sessions.push(session);
+    }
+    let take_slot = |id: SessionId| {
+        let state = state.clone();
+        let deadline = options.lobby.compute_deadline;
+        async move {
+            state
+                .set_current_contributor(&id, 1, String::new(), deadline)
+                .await
+        }
+    };
+
+    // Nobody is admitted before the first draw.
+    assert!(matches!(
+        take_slot(sessions[0].clone()).await,
+        Err(ActiveContributorError::NotAdmitted(_))
+    ));
+
+    let entrants = state.lottery_entrants().await;
+    assert_eq!(entrants.len(), 3);
+    let draw = Draw::new(0, Duration::from_secs(600), 1, &[1; 32], entrants, 1);
+    let winner = sessions
+        .iter()
+        .find(|session| draw.admitted().contains(&session.hash()))
+        .unwrap()
+        .clone();
+    state.set_lottery_draw(draw).await;
+    for session in sessions.iter().filter(|session| **session != winner) {
+        assert!(matches!(
+            take_slot(session.clone()).await,
+            Err(ActiveContributorError::NotAdmitted(_))
+        ));
+    }
+    take_slot(winner).await.unwrap();
+}
+
+#[tokio::test]
+async fn restores_sessions_by_token() {
+    use crate::{identity, storage::StoredSession, test_util::test_options};
+
+    let state = SharedLobbyState::new(test_options().lobby);
+    let waiting = SessionId::new();
+    let signed_in = SessionId::new();
+    let uid = |participant_id: &str| identity::uid(&participant_id.parse().unwrap());
+    let stored =
+        |id: &SessionId, uid: &str, identity: Option<&str>, lobby_entered_at| StoredSession {
+            token_hash: id.hash(),
+            uid: uid.to_string(),
+            identity: identity.map(str::to_string),
+            expires_at: Utc::now() + chrono::Duration::hours(1),
+            lobby_entered_at,
+            eligibility_score: None,
+        };
+    let restored = state
+        .restore_sessions(vec![
+            // Saved under a legacy uid.
+            stored(
+                &waiting,
+                "git|1|alice",
+                None,
+                Some(Utc::now() - chrono::Duration::minutes(1)),
+            ),
+            stored(&signed_in, &uid("git|2|bob"), Some("git|2|bob"), None),
+            stored(&SessionId::new(), "invalid", None, None),
+        ])
+        .await;
+    assert_eq!(restored, 2);
+    assert!(!state.is_in_lobby(&waiting).await);
+
+    assert_eq!(
+        state.claim_restored_session(&waiting).await,
+        Some(uid("git|1|alice"))
+    );
+    assert!(state.is_in_lobby(&waiting).await);
+    assert_eq!(state.claim_restored_session(&waiting).await, None);
+    let position = state.lobby_position(&waiting).await.unwrap();
+    assert_eq!(position.position, 1);
+
+    assert_eq!(
+        state.claim_restored_session(&signed_in).await,
+        Some(uid("git|2|bob"))
+    );
+    assert!(!state.is_in_lobby(&signed_in).await);
+    assert_eq!(state.get_session_count().await, 1);
+}
+
+#[tokio::test]
+async fn records_slot_in_database() {
+    use crate::{
+        storage::storage_client,
+        test_util::{create_test_session_info, test_options},
+    };
+
+    let options = test_options();
+    let db = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone()).with_storage(db.clone());
+    // Shares nothing with the first lobby but the database, like the same
+    // sequencer after a restart.
+    let restarted = SharedLobbyState::new(options.lobby.clone()).with_storage(db.clone());
+    let contributor = SessionId::new();
+    let other = SessionId::new();
+    for (lobby, id) in [(&state, &contributor), (&restarted, &other)] {
+        lobby
+            .insert_session(id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby.enter_lobby(id).await.unwrap();
+    }
+
+    state
+        .set_current_contributor(&contributor, 3, String::new(), Duration::from_secs(60))
+        .await
+        .unwrap();
+    let recorded = db.ceremony_state().await.unwrap();
+    assert_eq!(recorded.slot_holder, Some(contributor.hash()));
+    assert_eq!(recorded.contribution_index, 3);
+    assert!(matches!(
+        restarted
+            .set_current_contributor(&other, 3, String::new(), Duration::from_secs(60))
+            .await,
+        Err(ActiveContributorError::AnotherContributionInProgress)
+    ));
+
+    state.abort_contribution(&contributor).await.unwrap();
+    assert_eq!(db.ceremony_state().await.unwrap().slot_holder, None);
+    restarted
+        .set_current_contributor(&other, 3, String::new(), Duration::from_secs(60))
+        .await
+        .unwrap();
+}
+
+/// A lobby whose slots expire, with the scheduler running.
+#[cfg(test)]
+async fn with_scheduler(options: &crate::Options) -> SharedLobbyState {
+    use crate::{checkpoint::Checkpointer, scheduler::Scheduler, storage::storage_client};
+
+    let db = storage_client(&options.storage).await.unwrap();
+    let state = SharedLobbyState::new(options.lobby.clone()).with_storage(db.clone());
+    let scheduler_options = crate::scheduler::Options {
+        job_poll_interval: Duration::from_millis(10),
+        ..options.scheduler.clone()
+    };
+    tokio::spawn(
+        Scheduler::new(
+            scheduler_options,
+            db,
+            state.clone(),
+            Arc::new(Checkpointer::new(&options.checkpoint).unwrap()),
+            AuditLog::default(),
+        )
+        .run(),
+    );
+    state
+}
+
+#[tokio::test]
+async fn extends_deadline() {
+    use crate::test_util::{create_test_session_info, test_options};
+
+    let mut options = test_options();
+    options.lobby.compute_deadline = Duration::from_millis(100);
+    options.lobby.deadline_extension = Duration::from_millis(200);
+    let state = with_scheduler(&options).await;
+    let id = SessionId::new();
+    state
+        .insert_session(id.clone(), create_test_session_info(100))
+        .await
+        .unwrap();
+    state.enter_lobby(&id).await.unwrap();
+    state
+        .set_current_contributor(&id, 1, String::new(), options.lobby.compute_deadline)
+        .await
+        .unwrap();
+
+    assert!(state.time_left(&id).await.unwrap() <= Duration::from_millis(100));
+    let (reservation, extensions_left) = state
+        .extend_deadline(&id, |uid, valid_for| {
+            assert!(valid_for > Duration::from_millis(200));
+            uid.to_string()
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        reservation,
+        create_test_session_info(0).token.unique_identifier()
+    );
+    assert_eq!(extensions_left, 0);
+    assert!(state.time_left(&id).await.unwrap() > Duration::from_millis(200));
+    assert!(matches!(
+        state.extend_deadline(&id, |_, _| String::new()).await,
+        Err(ActiveContributorError::NoExtensionsLeft)
+    ));
+    assert!(matches!(
+        state
+            .extend_deadline(&SessionId::new(), |_, _| String::new())
+            .await,
+        Err(ActiveContributorError::NotActiveContributor)
+    ));
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(state.snapshot().await.active_contributor.is_some());
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert!(state.snapshot().await.active_contributor.is_none());
+}
+
+#[tokio::test]
+async fn expires_without_heartbeat() {
+    use crate::test_util::{create_test_session_info, test_options};
+
+    let mut options = test_options();
+    options.lobby.contributor_heartbeat_timeout = Duration::from_millis(100);
+    let state = with_scheduler(&options).await;
+    let mut events = state.subscribe();
+    let id = SessionId::new();
+    state
+        .insert_session(id.clone(), create_test_session_info(100))
+        .await
+        .unwrap();
+    state.enter_lobby(&id).await.unwrap();
+    state
+        .set_current_contributor(&id, 1, String::new(), options.lobby.compute_deadline)
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(state.heartbeat(&id).await.unwrap() > Duration::from_millis(100));
+    }
+    assert!(matches!(
+        state.heartbeat(&SessionId::new()).await,
+        Err(ActiveContributorError::NotActiveContributor)
+    ));
+    assert!(state.snapshot().await.active_contributor.is_some());
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(state.snapshot().await.active_contributor.is_none());
+    assert!(matches!(
+        state.heartbeat(&id).await,
+        Err(ActiveContributorError::NotActiveContributor)
+    ));
+    let mut opened = false;
+    while let Ok(event) = events.try_recv() {
+        opened |= event == LobbyEvent::SlotOpened;
+    }
+    assert!(opened);
+}
+
+#[tokio::test]
+async fn requeues_expired_contributors() {
+    use crate::test_util::{create_test_session_info, test_options};
+
+    let mut options = test_options();
+    options.lobby.compute_deadline = Duration::from_millis(100);
+    options.lobby.expiry_policy = ExpiryPolicy::Requeue;
+    options.lobby.requeue_penalty = Duration::from_millis(200);
+    options.lobby.max_slot_attempts = 2;
+    let state = with_scheduler(&options).await;
+    let storage = state.storage.clone().unwrap();
+    let id = SessionId::new();
+    let info = create_test_session_info(100);
+    let uid = info.token.unique_identifier();
+    state.insert_session(id.clone(), info).await.unwrap();
+    state.enter_lobby(&id).await.unwrap();
+    let deadline = options.lobby.compute_deadline;
+
+    state
+        .set_current_contributor(&id, 1, String::new(), deadline)
+        .await
+        .unwrap();
+    storage.insert_contributor(&uid, None).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(state.snapshot().await.active_contributor.is_none());
+    assert!(state.is_in_lobby(&id).await);
+    assert!(!storage.has_contributed(&uid).await.unwrap());
+    assert!(matches!(
+        state
+            .set_current_contributor(&id, 1, String::new(), deadline)
+            .await,
+        Err(ActiveContributorError::Requeued(_))
+    ));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    state
+        .set_current_contributor(&id, 1, String::new(), deadline)
+        .await
+        .unwrap();
+    assert!(storage.retry_contribution(&uid).await.unwrap());
+    // The second attempt was the last one.
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(state.snapshot().await.active_contributor.is_none());
+    assert!(!state.is_in_lobby(&id).await);
+    assert!(storage.has_contributed(&uid).await.unwrap());
+}
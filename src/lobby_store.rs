@@ -0,0 +1,443 @@
+//! Where the lobby queue and the contribution slot are kept.
+//!
+//! By default they live in memory, so only a single sequencer can serve a
+//! ceremony. With `--lobby-store redis` they are kept in Redis instead, and
+//! replicas behind a load balancer share one queue and one active contributor.
+//! Everything else about a session, like its ping times, stays with the
+//! replica it signed in on, so the load balancer has to route a session to the
+//! same replica every time.
+//!
+//! Participants are identified by the [hash of their session
+//! id](crate::sessions::SessionId::hash), which is safe to share.
+//!
+//! The store also keeps the nonces of [signed
+//! requests](crate::request_signing), so that a request can not be replayed
+//! against another replica.
+
+use crate::ceremony::CeremonyId;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+use eyre::Result as EyreResult;
+use kzg_ceremony_crypto::ErrorCode;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::time::Instant;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Where the lobby queue and the contribution slot are kept. `redis`
+    /// shares them between replicas.
+    #[clap(long, env, value_enum, default_value = "memory")]
+    pub lobby_store: LobbyStoreKind,
+
+    /// Url of the Redis server used by `--lobby-store redis`.
+    #[clap(long, env)]
+    pub redis_url: Option<String>,
+
+    /// Prefix of the Redis keys, so that several deployments can share a
+    /// server.
+    #[clap(long, env, default_value = "kzg-ceremony-sequencer")]
+    pub redis_key_prefix: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LobbyStoreKind {
+    Memory,
+    /// Requires the `redis` feature.
+    Redis,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum LobbyStoreError {
+    #[error("lobby store unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl ErrorCode for LobbyStoreError {
+    fn to_error_code(&self) -> String {
+        format!("LobbyStoreError::{}", <&str>::from(self))
+    }
+}
+
+pub type SharedLobbyStore = Arc<dyn LobbyStore>;
+
+/// The lobby state shared by all replicas serving a ceremony.
+#[async_trait]
+pub trait LobbyStore: Send + Sync {
+    /// Adds `session` to the queue, unless it is already waiting.
+    async fn join(&self, session: &str, joined_at: DateTime<Utc>) -> Result<(), LobbyStoreError>;
+
+    /// Removes `session` from the queue.
+    async fn leave(&self, session: &str) -> Result<(), LobbyStoreError>;
+
+    /// Number of participants in the queue.
+    async fn queue_len(&self) -> Result<usize, LobbyStoreError>;
+
+    /// 1-based position of `session` in the queue, ordered by the time they
+    /// joined, or `None` if it is not waiting.
+    async fn position(&self, session: &str) -> Result<Option<usize>, LobbyStoreError>;
+
+    /// Takes the contribution slot for `session` and removes it from the
+    /// queue, unless someone else holds the slot. Also succeeds, and restarts
+    /// the timeout, if `session` already holds it. The slot is freed after
+    /// `ttl` even if it is never released, so that a crashed replica can not
+    /// block the ceremony. Returns whether `session` holds the slot.
+    async fn acquire_slot(&self, session: &str, ttl: Duration) -> Result<bool, LobbyStoreError>;
+
+    /// Frees the slot, provided `session` holds it.
+    async fn release_slot(&self, session: &str) -> Result<(), LobbyStoreError>;
+
+    /// The session holding the slot.
+    async fn slot_holder(&self) -> Result<Option<String>, LobbyStoreError>;
+
+    /// Records `nonce` for `ttl`. Returns `false` if it is already recorded.
+    async fn claim_nonce(&self, nonce: &str, ttl: Duration) -> Result<bool, LobbyStoreError>;
+}
+
+/// Builds the store configured by `--lobby-store` for `ceremony`.
+///
+/// # Errors
+///
+/// Returns an error if Redis is selected but not configured or not reachable,
+/// or if the sequencer was built without the `redis` feature.
+pub async fn lobby_store(options: &Options, ceremony: &CeremonyId) -> EyreResult<SharedLobbyStore> {
+    match options.lobby_store {
+        LobbyStoreKind::Memory => Ok(Arc::new(MemoryLobbyStore::default())),
+        #[cfg(feature = "redis")]
+        LobbyStoreKind::Redis => {
+            let url = options
+                .redis_url
+                .as_deref()
+                .ok_or_else(|| eyre::eyre!("--lobby-store redis requires --redis-url"))?;
+            let prefix = format!(
+                "{}:{}",
+                options.redis_key_prefix,
+                ceremony.0.as_deref().unwrap_or("default")
+            );
+            Ok(Arc::new(
+                redis_store::RedisLobbyStore::connect(url, prefix).await?,
+            ))
+        }
+        #[cfg(not(feature = "redis"))]
+        LobbyStoreKind::Redis => {
+            let _ = ceremony;
+            Err(eyre::eyre!(
+                "--lobby-store redis requires building with the `redis` feature"
+            ))
+        }
+    }
+}
+
+/// Keeps the lobby of a single replica.
+#[derive(Default)]
+pub struct MemoryLobbyStore {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    queue:  HashMap<String, DateTime<Utc>>,
+    slot:   Option<(String, Instant)>,
+    /// With the time they are forgotten.
+    nonces: HashMap<String, Instant>,
+}
+
+impl MemoryState {
+    fn slot_holder(&self) -> Option<&str> {
+        self.slot
+            .as_ref()
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(holder, _)| holder.as_str())
+    }
+}
+
+#[async_trait]
+impl LobbyStore for MemoryLobbyStore {
+    async fn join(&self, session: &str, joined_at: DateTime<Utc>) -> Result<(), LobbyStoreError> {
+        self.state
+            .lock()
+            .unwrap()
+            .queue
+            .entry(session.to_string())
+            .or_insert(joined_at);
+        Ok(())
+    }
+
+    async fn leave(&self, session: &str) -> Result<(), LobbyStoreError> {
+        self.state.lock().unwrap().queue.remove(session);
+        Ok(())
+    }
+
+    async fn queue_len(&self) -> Result<usize, LobbyStoreError> {
+        Ok(self.state.lock().unwrap().queue.len())
+    }
+
+    async fn position(&self, session: &str) -> Result<Option<usize>, LobbyStoreError> {
+        let state = self.state.lock().unwrap();
+        let joined_at = match state.queue.get(session) {
+            Some(joined_at) => (joined_at, session),
+            None => return Ok(None),
+        };
+        // Ties are broken by session, like in a Redis sorted set.
+        let ahead = state
+            .queue
+            .iter()
+            .filter(|(other, other_joined_at)| (*other_joined_at, other.as_str()) < joined_at)
+            .count();
+        Ok(Some(ahead + 1))
+    }
+
+    async fn acquire_slot(&self, session: &str, ttl: Duration) -> Result<bool, LobbyStoreError> {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .slot_holder()
+            .map_or(false, |holder| holder != session)
+        {
+            return Ok(false);
+        }
+        state.slot = Some((session.to_string(), Instant::now() + ttl));
+        state.queue.remove(session);
+        Ok(true)
+    }
+
+    async fn release_slot(&self, session: &str) -> Result<(), LobbyStoreError> {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .slot
+            .as_ref()
+            .map_or(false, |(holder, _)| holder == session)
+        {
+            state.slot = None;
+        }
+        Ok(())
+    }
+
+    async fn slot_holder(&self) -> Result<Option<String>, LobbyStoreError> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .slot_holder()
+            .map(ToString::to_string))
+    }
+
+    async fn claim_nonce(&self, nonce: &str, ttl: Duration) -> Result<bool, LobbyStoreError> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.nonces.retain(|_, expires| *expires > now);
+        if state.nonces.contains_key(nonce) {
+            return Ok(false);
+        }
+        state.nonces.insert(nonce.to_string(), now + ttl);
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "redis")]
+mod redis_store {
+    use super::{LobbyStore, LobbyStoreError};
+    use axum::async_trait;
+    use chrono::{DateTime, Utc};
+    use redis::{aio::ConnectionManager, AsyncCommands, Script};
+    use std::time::Duration;
+
+    /// Takes the slot unless another session holds it, and removes the new
+    /// holder from the queue in the same step, so that two replicas can not
+    /// both hand out the slot.
+    const ACQUIRE_SLOT: &str = r"
+        local holder = redis.call('GET', KEYS[1])
+        if holder and holder ~= ARGV[1] then
+            return 0
+        end
+        redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+        redis.call('ZREM', KEYS[2], ARGV[1])
+        return 1
+    ";
+
+    /// Frees the slot only if it is still held by the given session, it may
+    /// have expired and been taken by someone else meanwhile.
+    const RELEASE_SLOT: &str = r"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            redis.call('DEL', KEYS[1])
+        end
+        return 0
+    ";
+
+    impl From<redis::RedisError> for LobbyStoreError {
+        fn from(error: redis::RedisError) -> Self {
+            Self::Unavailable(error.to_string())
+        }
+    }
+
+    /// Keeps the queue in a sorted set scored by the time participants joined,
+    /// the slot in a key that expires after the compute deadline, and every
+    /// nonce in a key that expires with it. Queue
+    /// entries are removed by the replica the participant is connected to, so
+    /// those of a replica that dies stay until the key is deleted.
+    pub struct RedisLobbyStore {
+        connection:   ConnectionManager,
+        queue_key:    String,
+        slot_key:     String,
+        nonce_prefix: String,
+        acquire_slot: Script,
+        release_slot: Script,
+    }
+
+    impl RedisLobbyStore {
+        pub async fn connect(url: &str, prefix: String) -> Result<Self, LobbyStoreError> {
+            let client = redis::Client::open(url)?;
+            Ok(Self {
+                connection:   ConnectionManager::new(client).await?,
+                queue_key:    format!("{prefix}:queue"),
+                slot_key:     format!("{prefix}:slot"),
+                nonce_prefix: format!("{prefix}:nonce:"),
+                acquire_slot: Script::new(ACQUIRE_SLOT),
+                release_slot: Script::new(RELEASE_SLOT),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl LobbyStore for RedisLobbyStore {
+        async fn join(
+            &self,
+            session: &str,
+            joined_at: DateTime<Utc>,
+        ) -> Result<(), LobbyStoreError> {
+            let _: usize = redis::cmd("ZADD")
+                .arg(&self.queue_key)
+                .arg("NX")
+                .arg(joined_at.timestamp_millis())
+                .arg(session)
+                .query_async(&mut self.connection.clone())
+                .await?;
+            Ok(())
+        }
+
+        async fn leave(&self, session: &str) -> Result<(), LobbyStoreError> {
+            let _: usize = self
+                .connection
+                .clone()
+                .zrem(&self.queue_key, session)
+                .await?;
+            Ok(())
+        }
+
+        async fn queue_len(&self) -> Result<usize, LobbyStoreError> {
+            Ok(self.connection.clone().zcard(&self.queue_key).await?)
+        }
+
+        async fn position(&self, session: &str) -> Result<Option<usize>, LobbyStoreError> {
+            let rank: Option<usize> = self
+                .connection
+                .clone()
+                .zrank(&self.queue_key, session)
+                .await?;
+            Ok(rank.map(|rank| rank + 1))
+        }
+
+        async fn acquire_slot(
+            &self,
+            session: &str,
+            ttl: Duration,
+        ) -> Result<bool, LobbyStoreError> {
+            let acquired: i32 = self
+                .acquire_slot
+                .key(&self.slot_key)
+                .key(&self.queue_key)
+                .arg(session)
+                .arg(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1))
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            Ok(acquired == 1)
+        }
+
+        async fn release_slot(&self, session: &str) -> Result<(), LobbyStoreError> {
+            let _: i32 = self
+                .release_slot
+                .key(&self.slot_key)
+                .arg(session)
+                .invoke_async(&mut self.connection.clone())
+                .await?;
+            Ok(())
+        }
+
+        async fn slot_holder(&self) -> Result<Option<String>, LobbyStoreError> {
+            Ok(self.connection.clone().get(&self.slot_key).await?)
+        }
+
+        async fn claim_nonce(&self, nonce: &str, ttl: Duration) -> Result<bool, LobbyStoreError> {
+            let set: Option<String> = redis::cmd("SET")
+                .arg(format!("{}{nonce}", self.nonce_prefix))
+                .arg(1)
+                .arg("NX")
+                .arg("PX")
+                .arg(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX).max(1))
+                .query_async(&mut self.connection.clone())
+                .await?;
+            Ok(set.is_some())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_store_shares_one_slot() {
+        let store = MemoryLobbyStore::default();
+        let now = Utc::now();
+        store.join("b", now).await.unwrap();
+        store.join("a", now).await.unwrap();
+        store
+            .join("c", now - chrono::Duration::seconds(1))
+            .await
+            .unwrap();
+        // Joining again keeps the place in the queue.
+        store.join("c", now).await.unwrap();
+        assert_eq!(store.queue_len().await.unwrap(), 3);
+        assert_eq!(store.position("c").await.unwrap(), Some(1));
+        assert_eq!(store.position("a").await.unwrap(), Some(2));
+        assert_eq!(store.position("b").await.unwrap(), Some(3));
+
+        let ttl = Duration::from_secs(60);
+        assert!(store.acquire_slot("a", ttl).await.unwrap());
+        assert!(store.acquire_slot("a", ttl).await.unwrap());
+        assert!(!store.acquire_slot("b", ttl).await.unwrap());
+        assert_eq!(store.position("a").await.unwrap(), None);
+        assert_eq!(store.position("b").await.unwrap(), Some(2));
+        assert_eq!(store.slot_holder().await.unwrap().as_deref(), Some("a"));
+
+        // Only the holder can free the slot.
+        store.release_slot("b").await.unwrap();
+        assert!(!store.acquire_slot("b", ttl).await.unwrap());
+        store.release_slot("a").await.unwrap();
+        assert_eq!(store.slot_holder().await.unwrap(), None);
+        assert!(store.acquire_slot("b", Duration::ZERO).await.unwrap());
+        // An expired slot is free again.
+        assert!(store.acquire_slot("c", ttl).await.unwrap());
+        assert_eq!(store.queue_len().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn memory_store_claims_nonces_once() {
+        let store = MemoryLobbyStore::default();
+        let ttl = Duration::from_secs(60);
+        assert!(store.claim_nonce("ops:a", ttl).await.unwrap());
+        assert!(!store.claim_nonce("ops:a", ttl).await.unwrap());
+        assert!(store.claim_nonce("ci:a", ttl).await.unwrap());
+
+        // Expired nonces are forgotten.
+        assert!(store.claim_nonce("ops:b", Duration::ZERO).await.unwrap());
+        assert!(store.claim_nonce("ops:b", ttl).await.unwrap());
+        assert_eq!(store.state.lock().unwrap().nonces.len(), 3);
+    }
+}
@@ -0,0 +1,237 @@
+//! Tuning of the lobby to the throughput of the ceremony.
+//!
+//! A lobby sized for a quiet day makes participants wait for hours during a
+//! traffic spike. With `--lobby-autotune` the sequencer measures how often
+//! the slot was handed out over the last `--lobby-autotune-window`, and how
+//! many of those slots ended without a verified contribution. Every
+//! `--lobby-autotune-interval` it then sets
+//!
+//! - the lobby size to the number of participants that get the slot within
+//!   `--lobby-target-wait`, so that whoever gets in does not wait much longer,
+//!   between `--lobby-autotune-min-size` and `--lobby-autotune-max-size`,
+//! - the check-in frequency to the time from one slot to the next, between
+//!   `--lobby-checkin-frequency` and `--lobby-autotune-max-checkin-frequency`.
+//!   Participants waiting while the slot is handed out every few minutes need
+//!   not check in every thirty seconds. The more slots expire, because the
+//!   participant picked was gone, the shorter it gets.
+//!
+//! The check-in frequency never goes below `--lobby-checkin-frequency`, so
+//! clients checking in at that frequency stay in the lobby: early check-ins
+//! are turned away with `SEQ-LOBBY-002` and how long to wait. Without slots
+//! handed out in the window the tuned values are kept. A configuration reload
+//! only replaces the configured values, the tuned ones apply until the next
+//! round.
+
+use crate::lobby::{LobbyEvent, SharedLobbyState};
+use clap::Parser;
+use std::{collections::VecDeque, num::ParseIntError, str::FromStr, time::Duration};
+use tokio::{sync::broadcast::error::RecvError, time::Instant};
+use tracing::info;
+
+fn duration_from_str(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Tunes the lobby size and check-in frequency to the throughput of the
+    /// ceremony.
+    #[clap(long, env)]
+    pub lobby_autotune: bool,
+
+    /// Seconds participants joining the lobby should wait at most for the
+    /// slot.
+    #[clap(long, env, value_parser = duration_from_str, default_value = "3600")]
+    pub lobby_target_wait: Duration,
+
+    /// Seconds over which the throughput is measured.
+    #[clap(long, env, value_parser = duration_from_str, default_value = "900")]
+    pub lobby_autotune_window: Duration,
+
+    /// How often the lobby is tuned, in seconds.
+    #[clap(long, env, value_parser = duration_from_str, default_value = "60")]
+    pub lobby_autotune_interval: Duration,
+
+    /// Smallest lobby size the tuning sets.
+    #[clap(long, env, default_value = "10")]
+    pub lobby_autotune_min_size: usize,
+
+    /// Largest lobby size the tuning sets.
+    #[clap(long, env, default_value = "10000")]
+    pub lobby_autotune_max_size: usize,
+
+    /// Longest check-in frequency the tuning sets, in seconds.
+    #[clap(long, env, value_parser = duration_from_str, default_value = "120")]
+    pub lobby_autotune_max_checkin_frequency: Duration,
+}
+
+/// Lobby options set in place of the configured ones, see
+/// [`SharedLobbyState::set_tuning`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LobbyTuning {
+    pub max_lobby_size:          usize,
+    pub lobby_checkin_frequency: Duration,
+}
+
+/// Slots handed out and contributions verified within the window.
+struct Throughput {
+    window:   Duration,
+    since:    Instant,
+    started:  VecDeque<Instant>,
+    verified: VecDeque<Instant>,
+}
+
+impl Throughput {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            since: Instant::now(),
+            started: VecDeque::new(),
+            verified: VecDeque::new(),
+        }
+    }
+
+    fn record(&mut self, event: &LobbyEvent) {
+        match event {
+            LobbyEvent::ContributionStarted => self.started.push_back(Instant::now()),
+            LobbyEvent::ContributionVerified { .. } => self.verified.push_back(Instant::now()),
+            _ => {}
+        }
+    }
+
+    /// The tuning for the throughput of the window, if a slot was handed
+    /// out in it. The check-in frequency is not set below the configured
+    /// `lobby_checkin_frequency`.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn tuning(&mut self, options: &Options, checkin_frequency: Duration) -> Option<LobbyTuning> {
+        let now = Instant::now();
+        for times in [&mut self.started, &mut self.verified] {
+            while times
+                .front()
+                .map_or(false, |time| now.duration_since(*time) > self.window)
+            {
+                times.pop_front();
+            }
+        }
+        let observed = now.duration_since(self.since).min(self.window);
+        if self.started.is_empty() || observed.is_zero() {
+            return None;
+        }
+        let started = self.started.len();
+        let expired = started.saturating_sub(self.verified.len()) as f64 / started as f64;
+        let slot_interval = observed.as_secs_f64() / started as f64;
+
+        let max_lobby_size = (options.lobby_target_wait.as_secs_f64() / slot_interval) as usize;
+        let lobby_checkin_frequency =
+            Duration::from_secs((slot_interval * (1.0 - expired)).round() as u64);
+        Some(LobbyTuning {
+            max_lobby_size:          max_lobby_size
+                .min(options.lobby_autotune_max_size)
+                .max(options.lobby_autotune_min_size),
+            lobby_checkin_frequency: lobby_checkin_frequency
+                .min(options.lobby_autotune_max_checkin_frequency)
+                .max(checkin_frequency),
+        })
+    }
+}
+
+/// Tunes the lobby every `--lobby-autotune-interval`, if `--lobby-autotune`
+/// is set.
+pub async fn tune_on_interval(options: Options, lobby_state: SharedLobbyState) {
+    if !options.lobby_autotune {
+        return;
+    }
+    let mut events = lobby_state.subscribe();
+    let mut throughput = Throughput::new(options.lobby_autotune_window);
+    let mut interval = tokio::time::interval(options.lobby_autotune_interval);
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => throughput.record(&event),
+                // Missed events only make the estimate less accurate.
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+            _ = interval.tick() => {
+                let configured = lobby_state.configured_options();
+                let tuning = throughput.tuning(&options, configured.lobby_checkin_frequency);
+                if let Some(tuning) = tuning.filter(|tuning| lobby_state.tuning() != Some(*tuning)) {
+                    info!(
+                        max_lobby_size = tuning.max_lobby_size,
+                        checkin_frequency = tuning.lobby_checkin_frequency.as_secs(),
+                        "Tuned the lobby to the throughput"
+                    );
+                    lobby_state.set_tuning(Some(tuning));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+
+    #[tokio::test]
+    async fn tunes_to_the_throughput() {
+        tokio::time::pause();
+        let options = Options {
+            lobby_autotune_window: Duration::from_secs(600),
+            ..test_options().lobby_tuning
+        };
+        let checkin_frequency = Duration::from_secs(30);
+        let mut throughput = Throughput::new(options.lobby_autotune_window);
+        assert_eq!(throughput.tuning(&options, checkin_frequency), None);
+
+        // A slot a minute, two of ten expired.
+        for i in 0..10 {
+            tokio::time::advance(Duration::from_secs(60)).await;
+            throughput.record(&LobbyEvent::ContributionStarted);
+            if i >= 2 {
+                throughput.record(&LobbyEvent::ContributionVerified {
+                    num_contributions: i,
+                });
+            }
+        }
+        let tuning = throughput.tuning(&options, checkin_frequency).unwrap();
+        assert_eq!(tuning, LobbyTuning {
+            max_lobby_size:          60,
+            lobby_checkin_frequency: Duration::from_secs(48),
+        });
+
+        // Tuned values win over the configured ones until they are cleared.
+        let lobby_state = SharedLobbyState::new(test_options().lobby);
+        lobby_state.set_tuning(Some(tuning));
+        assert_eq!(lobby_state.options().max_lobby_size, 60);
+        assert_eq!(
+            lobby_state.options().lobby_checkin_frequency,
+            Duration::from_secs(48)
+        );
+        assert_eq!(lobby_state.configured_options(), test_options().lobby);
+        lobby_state.set_tuning(None);
+        assert_eq!(lobby_state.options(), test_options().lobby);
+
+        // A slot every ten minutes, but never a lobby below the floor or a
+        // check-in frequency above the ceiling.
+        tokio::time::advance(Duration::from_secs(6000)).await;
+        throughput.record(&LobbyEvent::ContributionStarted);
+        throughput.record(&LobbyEvent::ContributionVerified {
+            num_contributions: 10,
+        });
+        assert_eq!(
+            throughput.tuning(&options, checkin_frequency),
+            Some(LobbyTuning {
+                max_lobby_size:          10,
+                lobby_checkin_frequency: Duration::from_secs(120),
+            })
+        );
+        tokio::time::advance(Duration::from_secs(601)).await;
+        assert_eq!(throughput.tuning(&options, checkin_frequency), None);
+    }
+}
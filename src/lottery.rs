@@ -0,0 +1,330 @@
+//! Lobby admission by a lottery seeded with Ethereum block hashes.
+//!
+//! With `--lottery-rpc-url`, the contribution slot is only handed to
+//! participants admitted for the current epoch of `--lottery-epoch` seconds.
+//! At the start of each epoch the sequencer takes the hash of the block
+//! `--lottery-confirmations` below the head of the chain, which it can
+//! neither predict nor choose, and the hashes of the sessions checked in to
+//! the lobby. Each session draws the ticket `keccak256(block_hash ||
+//! session_hash)`, over the 32 bytes of each hash, and the
+//! `--lottery-admissions` lowest tickets are admitted. The others keep
+//! pinging and are entered again in the next epoch; sessions that join the
+//! lobby during an epoch wait for the next draw.
+//!
+//! Draws are stored, so a restarted sequencer and sequencers sharing the
+//! database use the same draw for an epoch, and published by `/info/lottery`
+//! with the block and every session hash and ticket. Anyone can check the
+//! draw against the chain, and participants, who know their session id, that
+//! they were entered. Session ids are bearer tokens, only their SHA-256 is
+//! published.
+
+use crate::{
+    lobby::{LobbyEvent, SharedLobbyState},
+    metrics::LOTTERY_DRAWS,
+    storage::PersistentStorage,
+    util::Secret,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use clap::Parser;
+use ethers_core::utils::keccak256;
+use eyre::{bail, eyre, Result as EyreResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeSet, num::ParseIntError, str::FromStr, time::Duration};
+use tracing::{error, info};
+
+/// Delay before a failed draw is tried again.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Ethereum JSON-RPC endpoint the block hashes of the admission lottery
+    /// are read from. The lobby admits everyone without it.
+    #[clap(long, env)]
+    pub lottery_rpc_url: Option<Secret>,
+
+    /// Seconds between lottery draws.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "600")]
+    pub lottery_epoch: Duration,
+
+    /// Number of participants admitted by each draw.
+    #[clap(long, env, default_value = "10")]
+    pub lottery_admissions: usize,
+
+    /// Depth below the head of the chain of the block whose hash seeds the
+    /// draw, so that a reorganization is unlikely to replace it.
+    #[clap(long, env, default_value = "5")]
+    pub lottery_confirmations: u64,
+}
+
+/// The lottery of one epoch.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Draw {
+    /// Seconds since the Unix epoch divided by `--lottery-epoch`.
+    pub epoch:        u64,
+    pub starts_at:    DateTime<Utc>,
+    pub ends_at:      DateTime<Utc>,
+    pub block_number: u64,
+    pub block_hash:   String,
+    /// The sessions entered, lowest ticket first.
+    pub entrants:     Vec<Entrant>,
+    /// Number of entrants admitted, from the first.
+    pub admitted:     usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entrant {
+    pub session_hash: String,
+    pub ticket:       String,
+}
+
+impl Draw {
+    /// Draws `admissions` of the sessions with `session_hashes`, seeded by
+    /// `block_hash`.
+    #[must_use]
+    pub fn new(
+        epoch: u64,
+        epoch_length: Duration,
+        block_number: u64,
+        block_hash: &[u8; 32],
+        session_hashes: impl IntoIterator<Item = String>,
+        admissions: usize,
+    ) -> Self {
+        let mut entrants = session_hashes
+            .into_iter()
+            .filter_map(|session_hash| {
+                let mut seed = block_hash.to_vec();
+                seed.extend(hex::decode(&session_hash).ok()?);
+                Some(Entrant {
+                    ticket: hex::encode(keccak256(seed)),
+                    session_hash,
+                })
+            })
+            .collect::<Vec<_>>();
+        entrants.sort_by(|a, b| a.ticket.cmp(&b.ticket));
+        let length = epoch_length.as_secs();
+        Self {
+            epoch,
+            starts_at: epoch_start(epoch * length),
+            ends_at: epoch_start((epoch + 1) * length),
+            block_number,
+            block_hash: format!("0x{}", hex::encode(block_hash)),
+            admitted: admissions.min(entrants.len()),
+            entrants,
+        }
+    }
+
+    /// Hashes of the admitted sessions.
+    #[must_use]
+    pub fn admitted(&self) -> BTreeSet<String> {
+        self.entrants[..self.admitted]
+            .iter()
+            .map(|entrant| entrant.session_hash.clone())
+            .collect()
+    }
+}
+
+fn epoch_start(seconds: u64) -> DateTime<Utc> {
+    Utc.timestamp_opt(i64::try_from(seconds).unwrap_or(i64::MAX), 0)
+        .single()
+        .unwrap_or_else(Utc::now)
+}
+
+#[derive(Debug)]
+pub struct Lottery {
+    options: Options,
+    rpc_url: String,
+    client:  reqwest::Client,
+}
+
+impl Lottery {
+    /// The lottery, if `--lottery-rpc-url` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `--lottery-epoch` is zero.
+    pub fn new(options: &Options, client: reqwest::Client) -> EyreResult<Option<Self>> {
+        let rpc_url = match &options.lottery_rpc_url {
+            Some(url) => url.get_secret().to_string(),
+            None => return Ok(None),
+        };
+        if options.lottery_epoch.is_zero() {
+            bail!("--lottery-epoch must not be zero");
+        }
+        Ok(Some(Self {
+            options: options.clone(),
+            rpc_url,
+            client,
+        }))
+    }
+
+    /// The epoch `time` falls in.
+    fn epoch_at(&self, time: DateTime<Utc>) -> u64 {
+        u64::try_from(time.timestamp()).unwrap_or_default() / self.options.lottery_epoch.as_secs()
+    }
+
+    /// Draws the epoch `epoch` among `session_hashes`.
+    async fn draw(&self, epoch: u64, session_hashes: BTreeSet<String>) -> EyreResult<Draw> {
+        let head = self.block("latest").await?;
+        let number = block_number(&head)?.saturating_sub(self.options.lottery_confirmations);
+        let block = self.block(&format!("{number:#x}")).await?;
+        let hash = block
+            .get("hash")
+            .and_then(Value::as_str)
+            .ok_or_else(|| eyre!("block {number} has no hash"))?;
+        let hash: [u8; 32] = hex::decode(hash.trim_start_matches("0x"))?
+            .try_into()
+            .map_err(|_| eyre!("invalid block hash {hash}"))?;
+        Ok(Draw::new(
+            epoch,
+            self.options.lottery_epoch,
+            number,
+            &hash,
+            session_hashes,
+            self.options.lottery_admissions,
+        ))
+    }
+
+    /// The block `block`, a number in hex or a tag like `latest`.
+    async fn block(&self, block: &str) -> EyreResult<Value> {
+        let request = json!({
+            "id": 1,
+            "jsonrpc": "2.0",
+            "method": "eth_getBlockByNumber",
+            "params": [block, false],
+        });
+        let response: Value = self
+            .client
+            .post(&self.rpc_url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if let Some(error) = response.get("error") {
+            bail!("eth_getBlockByNumber failed: {error}");
+        }
+        match response.get("result") {
+            Some(block) if block.is_object() => Ok(block.clone()),
+            _ => bail!("block {block} not found"),
+        }
+    }
+}
+
+fn block_number(block: &Value) -> EyreResult<u64> {
+    let number = block
+        .get("number")
+        .and_then(Value::as_str)
+        .ok_or_else(|| eyre!("malformed block"))?;
+    Ok(u64::from_str_radix(number.trim_start_matches("0x"), 16)?)
+}
+
+/// Draws each epoch at its start and admits the winners to the lobby. A draw
+/// stored for the epoch already, by an earlier run or another sequencer, is
+/// used instead. Failed draws are retried, nobody is admitted until one
+/// succeeds.
+pub async fn draw_on_epochs(
+    lottery: Lottery,
+    lobby_state: SharedLobbyState,
+    storage: PersistentStorage,
+) {
+    let mut drawn = None;
+    loop {
+        let now = Utc::now();
+        let epoch = lottery.epoch_at(now);
+        if drawn != Some(epoch) {
+            let result = async {
+                if let Some(draw) = storage.lottery_draw(Some(epoch)).await? {
+                    return Ok(draw);
+                }
+                let draw = lottery
+                    .draw(epoch, lobby_state.lottery_entrants().await)
+                    .await?;
+                // Another sequencer may have stored its draw first.
+                storage.insert_lottery_draw(&draw).await?;
+                storage
+                    .lottery_draw(Some(epoch))
+                    .await?
+                    .ok_or_else(|| eyre!("draw of epoch {epoch} not stored"))
+            }
+            .await;
+            match result {
+                Ok(draw) => {
+                    LOTTERY_DRAWS.with_label_values(&["ok"]).inc();
+                    info!(
+                        epoch,
+                        block_number = draw.block_number,
+                        entrants = draw.entrants.len(),
+                        admitted = draw.admitted,
+                        "Lottery drawn"
+                    );
+                    lobby_state.set_lottery_draw(draw).await;
+                    lobby_state.publish(LobbyEvent::LotteryDrawn { epoch });
+                    drawn = Some(epoch);
+                }
+                Err(error) => {
+                    LOTTERY_DRAWS.with_label_values(&["failed"]).inc();
+                    error!(?error, epoch, "Lottery draw failed");
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+            }
+        }
+        let length = lottery.options.lottery_epoch.as_secs();
+        let next = (epoch + 1) * length;
+        let wait = next.saturating_sub(u64::try_from(now.timestamp()).unwrap_or_default());
+        tokio::time::sleep(Duration::from_secs(wait.max(1))).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn draws_lowest_tickets() {
+        let block_hash = [7; 32];
+        let sessions = ["a", "b", "c", "d"]
+            .map(|id| hex::encode(Sha256::digest(id.as_bytes())))
+            .to_vec();
+        let draw = Draw::new(
+            3,
+            Duration::from_secs(600),
+            100,
+            &block_hash,
+            sessions.clone(),
+            2,
+        );
+        assert_eq!(draw.starts_at.timestamp(), 1800);
+        assert_eq!(draw.ends_at.timestamp(), 2400);
+        assert_eq!(draw.block_hash, format!("0x{}", "07".repeat(32)));
+        assert_eq!(draw.entrants.len(), 4);
+        assert!(draw
+            .entrants
+            .windows(2)
+            .all(|pair| pair[0].ticket < pair[1].ticket));
+        for entrant in &draw.entrants {
+            let mut seed = block_hash.to_vec();
+            seed.extend(hex::decode(&entrant.session_hash).unwrap());
+            assert_eq!(entrant.ticket, hex::encode(keccak256(seed)));
+        }
+        assert_eq!(draw.admitted().len(), 2);
+        assert!(draw.admitted().contains(&draw.entrants[1].session_hash));
+
+        // The order of the entrants does not matter, the block does.
+        let mut reversed = sessions.clone();
+        reversed.reverse();
+        let again = Draw::new(3, Duration::from_secs(600), 100, &block_hash, reversed, 2);
+        assert_eq!(again, draw);
+        let other = Draw::new(3, Duration::from_secs(600), 100, &[8; 32], sessions, 10);
+        assert_eq!(other.admitted, 4);
+        assert_ne!(other.entrants, draw.entrants);
+    }
+}
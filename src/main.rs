@@ -1,7 +1,11 @@
 use cli_batteries::version;
-use kzg_ceremony_sequencer::async_main;
+use kzg_ceremony_sequencer::{async_main, sandbox};
 
 #[allow(dead_code)] // Entry point
 fn main() {
+    // The verification worker starts none of what the CLI does.
+    if std::env::args_os().nth(1).as_deref() == Some(sandbox::WORKER_ARG.as_ref()) {
+        sandbox::run_worker();
+    }
     cli_batteries::run(version!(crypto, small_powers_of_tau), async_main);
 }
@@ -0,0 +1,213 @@
+//! Prometheus metrics. All metrics are registered in the default registry,
+//! which is exposed on `/metrics` and by the `cli-batteries` metrics server.
+
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge,
+};
+
+pub static CONTRIBUTIONS_STARTED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "sequencer_contributions_started",
+        "Number of contribution slots handed out to participants."
+    )
+    .unwrap()
+});
+
+pub static CONTRIBUTIONS_FINISHED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "sequencer_contributions_finished",
+        "Number of contributions verified and added to the transcript."
+    )
+    .unwrap()
+});
+
+pub static CONTRIBUTIONS_REQUEUED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "sequencer_contributions_requeued",
+        "Number of expired contribution slots whose contributor was put back into the lobby."
+    )
+    .unwrap()
+});
+
+pub static CONTRIBUTIONS_EXPIRED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_contributions_expired",
+        "Number of contribution slots that ended without a contribution.",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+pub static VERIFICATION_LATENCY: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "sequencer_verification_seconds",
+        "Time spent verifying a contribution.",
+        exponential_buckets(0.1, 2.0, 10).unwrap()
+    )
+    .unwrap()
+});
+
+pub static VERIFICATION_BACKLOG: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "sequencer_verification_backlog",
+        "Number of contributions submitted for verification and not yet verified."
+    )
+    .unwrap()
+});
+
+pub static REQUESTS_SHED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_requests_shed",
+        "Requests turned away under load, by the threshold that was crossed.",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+pub static SPAM_FILTER_VETOES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_spam_filter_vetoes",
+        "Requests turned away by spam filters, by filter and stage.",
+        &["filter", "stage"]
+    )
+    .unwrap()
+});
+
+pub static MIRROR_SYNCS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_mirror_syncs",
+        "Syncs of the mirrored endpoints with the upstream sequencer, by outcome.",
+        &["path", "outcome"]
+    )
+    .unwrap()
+});
+
+pub static LOBBY_SIZE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "sequencer_lobby_size",
+        "Number of participants waiting in the lobby."
+    )
+    .unwrap()
+});
+
+pub static LOBBY_WAIT_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "sequencer_lobby_wait_seconds",
+        "Time participants waited in the lobby for the contribution slot.",
+        &["provider"],
+        exponential_buckets(1.0, 2.0, 15).unwrap()
+    )
+    .unwrap()
+});
+
+pub static SESSION_COUNT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "sequencer_session_count",
+        "Number of authenticated sessions outside of the lobby."
+    )
+    .unwrap()
+});
+
+pub static GC_PRUNED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_gc_pruned",
+        "Items pruned by the garbage collection task, by kind.",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+pub static DB_BACKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_db_backups",
+        "Scheduled database backups, by whether they succeeded.",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+pub static LOTTERY_DRAWS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_lottery_draws",
+        "Lobby admission lottery draws, by whether they succeeded.",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+pub static INTEGRITY_CHECKS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_integrity_checks",
+        "Integrity self-checks of the stored transcript, by outcome: ok, mismatch or failed.",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+pub static INTEGRITY_MISMATCHES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "sequencer_integrity_mismatches",
+        "Sampled contributions that did not pass the latest integrity self-check."
+    )
+    .unwrap()
+});
+
+pub static AUTH_FUNNEL: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_auth_funnel",
+        "Sign-ins by auth provider and the stage they reached, with the rule of rejections.",
+        &["provider", "stage", "rule"]
+    )
+    .unwrap()
+});
+
+pub static AUTH_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_auth_failures",
+        "Number of failed authentication attempts.",
+        &["code"]
+    )
+    .unwrap()
+});
+
+pub static DB_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "sequencer_db_query_seconds",
+        "Latency of database queries.",
+        &["query"],
+        exponential_buckets(0.000_1, 4.0, 10).unwrap()
+    )
+    .unwrap()
+});
+
+pub static CONTRIBUTOR_CACHE_LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_contributor_cache_lookups",
+        "Lookups of whether a participant has contributed, by whether the cache answered.",
+        &["result"]
+    )
+    .unwrap()
+});
+
+pub static PROOFS_OF_WORK: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_proofs_of_work",
+        "Proof of work checks on joining the lobby, by whether the solution was missing, invalid \
+         or valid.",
+        &["result"]
+    )
+    .unwrap()
+});
+
+pub static NOTIFICATIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "sequencer_notifications",
+        "Deliveries of \"nearly up\" notifications, by channel and whether they were sent or \
+         failed.",
+        &["channel", "result"]
+    )
+    .unwrap()
+});
@@ -0,0 +1,369 @@
+//! Read-only mirror of another sequencer.
+//!
+//! `--mode mirror --upstream <url>` serves `/info/status`,
+//! `/info/current_state` and `/info/statistics` from copies of the upstream
+//! sequencer's responses, to take the read traffic off it during peak hours.
+//! Every `--mirror-interval` seconds the mirror asks the upstream for each of
+//! them again, with the `ETag` of its copy in `If-None-Match`, so unchanged
+//! responses are not transferred. Copies are served with the upstream's tag,
+//! so clients can poll the mirror conditionally as well, and with the time
+//! they were synced in `X-Mirror-Synced-At`. Query parameters are ignored:
+//! the transcript is only mirrored as JSON.
+//!
+//! The mirror has no lobby, database or keys, and serves nothing else. Until
+//! the first sync of an endpoint it answers it with `SEQ-MIRROR-001`, and
+//! `/readyz` fails. If the upstream can not be reached, the last copies are
+//! served on.
+
+use crate::{
+    api::v1::health::{healthz, ReadinessResponse},
+    cors::{handle_cors, Cors},
+    etag,
+    metrics::MIRROR_SYNCS,
+    rate_limit::{rate_limit, RateLimiter},
+    Options as AppOptions,
+};
+use axum::{
+    body::{Body, Bytes},
+    http::Request,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::Parser;
+use eyre::{bail, Result as EyreResult};
+use http::{
+    header::{ETAG, IF_NONE_MATCH},
+    HeaderMap, HeaderValue, StatusCode,
+};
+use kzg_ceremony_crypto::ErrorCode;
+use std::{
+    collections::{BTreeMap, HashMap},
+    num::ParseIntError,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tower_http::compression::CompressionLayer;
+use tracing::{info, warn};
+use url::Url;
+
+pub const SYNCED_AT_HEADER: &str = "x-mirror-synced-at";
+
+/// The mirrored endpoints, relative to the upstream url.
+pub const MIRRORED: [&str; 3] = ["info/status", "info/current_state", "info/statistics"];
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Url of the sequencer `--mode mirror` copies, as served, e.g.
+    /// `https://seq.ceremony.ethereum.org/`.
+    #[clap(long, env)]
+    pub upstream: Option<Url>,
+
+    /// Seconds between syncs with the upstream sequencer.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "10")]
+    pub mirror_interval: Duration,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum MirrorError {
+    #[error("the mirror has not synced with the upstream sequencer yet")]
+    NotSynced,
+}
+
+impl ErrorCode for MirrorError {
+    fn to_error_code(&self) -> String {
+        format!("MirrorError::{}", <&str>::from(self))
+    }
+}
+
+/// A response of the upstream sequencer.
+#[derive(Clone, Debug)]
+struct Snapshot {
+    body:      Bytes,
+    tag:       String,
+    synced_at: DateTime<Utc>,
+}
+
+/// The copies of the mirrored endpoints.
+#[derive(Debug, Default)]
+pub struct Mirror {
+    copies: RwLock<HashMap<&'static str, Snapshot>>,
+    /// Why the latest sync of an endpoint failed, until one succeeds.
+    errors: RwLock<HashMap<&'static str, String>>,
+}
+
+pub type SharedMirror = Arc<Mirror>;
+
+impl Mirror {
+    /// Fetches the mirrored endpoints that changed since the last sync.
+    pub async fn sync(&self, client: &reqwest::Client, upstream: &Url) {
+        for path in MIRRORED {
+            let outcome = match self.sync_endpoint(client, upstream, path).await {
+                Ok(true) => "updated",
+                Ok(false) => "not_modified",
+                Err(error) => {
+                    warn!(path, %error, "Failed to sync with the upstream sequencer");
+                    self.errors.write().unwrap().insert(path, error.to_string());
+                    MIRROR_SYNCS.with_label_values(&[path, "failed"]).inc();
+                    continue;
+                }
+            };
+            self.errors.write().unwrap().remove(path);
+            MIRROR_SYNCS.with_label_values(&[path, outcome]).inc();
+        }
+    }
+
+    /// Returns whether the copy of `path` changed.
+    async fn sync_endpoint(
+        &self,
+        client: &reqwest::Client,
+        upstream: &Url,
+        path: &'static str,
+    ) -> Result<bool, reqwest::Error> {
+        let tag = self
+            .copies
+            .read()
+            .unwrap()
+            .get(path)
+            .map(|copy| copy.tag.clone());
+        let mut request = client.get(upstream.join(path).expect("mirrored paths are relative"));
+        if let Some(tag) = &tag {
+            request = request.header(IF_NONE_MATCH, tag);
+        }
+        let response = request.send().await?.error_for_status()?;
+        let synced_at = Utc::now();
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(copy) = self.copies.write().unwrap().get_mut(path) {
+                copy.synced_at = synced_at;
+            }
+            return Ok(false);
+        }
+        let upstream_tag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|tag| tag.to_str().ok())
+            .map(ToString::to_string);
+        let body = response.bytes().await?;
+        let tag = upstream_tag.unwrap_or_else(|| etag::tag(&body));
+        self.copies.write().unwrap().insert(path, Snapshot {
+            body,
+            tag,
+            synced_at,
+        });
+        Ok(true)
+    }
+
+    /// Answers with the copy of `path`, or with `304 Not Modified` if the
+    /// client already has it.
+    fn respond(&self, path: &'static str, headers: &HeaderMap) -> Response {
+        let copy = match self.copies.read().unwrap().get(path) {
+            Some(copy) => copy.clone(),
+            None => return MirrorError::NotSynced.into_response(),
+        };
+        let mut response = etag::respond(headers, copy.body, copy.tag);
+        if let Ok(synced_at) =
+            HeaderValue::from_str(&copy.synced_at.to_rfc3339_opts(SecondsFormat::Secs, true))
+        {
+            response.headers_mut().insert(SYNCED_AT_HEADER, synced_at);
+        }
+        response
+    }
+
+    /// Ready once every endpoint was synced.
+    fn readiness(&self) -> ReadinessResponse {
+        let copies = self.copies.read().unwrap();
+        let errors = self.errors.read().unwrap();
+        let checks = MIRRORED
+            .iter()
+            .map(|path| {
+                let check = match (errors.get(path), copies.contains_key(path)) {
+                    (Some(error), _) => error.clone(),
+                    (None, true) => "ok".to_string(),
+                    (None, false) => "not synced yet".to_string(),
+                };
+                ((*path).to_string(), check)
+            })
+            .collect::<BTreeMap<_, _>>();
+        ReadinessResponse::new(
+            MIRRORED.iter().all(|path| copies.contains_key(path)),
+            checks,
+        )
+    }
+}
+
+/// Syncs `mirror` with `upstream` every `interval`, starting right away.
+pub async fn sync_on_interval(
+    mirror: SharedMirror,
+    client: reqwest::Client,
+    upstream: Url,
+    interval: Duration,
+) {
+    loop {
+        mirror.sync(&client, &upstream).await;
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// The router serving `mirror`.
+pub fn router(mirror: SharedMirror) -> Router {
+    let mut router = Router::new();
+    for path in MIRRORED {
+        let route = get(
+            move |Extension(mirror): Extension<SharedMirror>, headers: HeaderMap| async move {
+                mirror.respond(path, &headers)
+            },
+        );
+        router = router.route(&format!("/{path}"), route);
+    }
+    router
+        .layer(CompressionLayer::new())
+        .route("/healthz", get(healthz))
+        .route(
+            "/readyz",
+            get(|Extension(mirror): Extension<SharedMirror>| async move { mirror.readiness() }),
+        )
+        .layer(Extension(mirror))
+}
+
+/// Starts syncing with `--upstream` and returns the router serving the
+/// copies.
+///
+/// # Errors
+///
+/// Returns an error if `--upstream` is missing or the CORS options are
+/// invalid.
+pub fn start(options: &AppOptions) -> EyreResult<Router> {
+    let mut upstream = match &options.mirror.upstream {
+        Some(upstream) => upstream.clone(),
+        None => bail!("--mode mirror needs --upstream"),
+    };
+    // Joined with the mirrored paths, which would replace its last segment.
+    if !upstream.path().ends_with('/') {
+        upstream.set_path(&format!("{}/", upstream.path()));
+    }
+    info!(%upstream, interval = ?options.mirror.mirror_interval, "Mirroring upstream sequencer");
+    let mirror = SharedMirror::default();
+    tokio::spawn(sync_on_interval(
+        mirror.clone(),
+        reqwest::Client::new(),
+        upstream,
+        options.mirror.mirror_interval,
+    ));
+
+    let rate_limiter = Arc::new(RateLimiter::new(options.rate_limit.clone()));
+    let cors = Arc::new(Cors::new(&options.cors)?);
+    Ok(router(mirror)
+        .layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                rate_limit(rate_limiter.clone(), request, next)
+            },
+        ))
+        .layer(middleware::from_fn(
+            move |request: Request<Body>, next: Next<Body>| {
+                handle_cors(cors.clone(), request, next)
+            },
+        )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Server;
+    use std::{
+        net::TcpListener,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tower::ServiceExt;
+
+    /// An upstream whose status changes on every request, and whose
+    /// transcript never does.
+    async fn upstream() -> (Url, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        let app = Router::new()
+            .route(
+                "/seq/info/status",
+                get(move || {
+                    let requests = counted.clone();
+                    async move {
+                        let n = requests.fetch_add(1, Ordering::Relaxed);
+                        format!("{{\"num_contributions\":{n}}}")
+                    }
+                }),
+            )
+            .route(
+                "/seq/info/current_state",
+                get(|headers: HeaderMap| async move {
+                    etag::respond(&headers, Bytes::from_static(b"{}"), "W/\"t\"".to_string())
+                }),
+            )
+            .route("/seq/info/statistics", get(|| async { "{}" }));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        (format!("http://{addr}/seq/").parse().unwrap(), requests)
+    }
+
+    async fn get_path(router: &Router, path: &str, tag: Option<&'static str>) -> Response {
+        let mut request = Request::get(path);
+        if let Some(tag) = tag {
+            request = request.header(IF_NONE_MATCH, tag);
+        }
+        router
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_synced_copies() {
+        let (upstream, requests) = upstream().await;
+        let mirror = SharedMirror::default();
+        let router = router(mirror.clone());
+        assert_eq!(
+            get_path(&router, "/info/status", None).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            get_path(&router, "/readyz", None).await.status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let client = reqwest::Client::new();
+        mirror.sync(&client, &upstream).await;
+        let status = get_path(&router, "/info/status", None).await;
+        assert_eq!(status.status(), StatusCode::OK);
+        assert!(status.headers().contains_key(SYNCED_AT_HEADER));
+        let body = hyper::body::to_bytes(status.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"{\"num_contributions\":0}");
+        assert_eq!(
+            get_path(&router, "/readyz", None).await.status(),
+            StatusCode::OK
+        );
+
+        // The mirror answers on its own, with the upstream's tag.
+        let transcript = get_path(&router, "/info/current_state", Some("W/\"t\"")).await;
+        assert_eq!(transcript.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+
+        mirror.sync(&client, &upstream).await;
+        let status = get_path(&router, "/info/status", None).await;
+        let body = hyper::body::to_bytes(status.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"{\"num_contributions\":1}");
+    }
+}
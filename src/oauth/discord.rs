@@ -0,0 +1,184 @@
+use super::{
+    lookup::{token_exchange_error, with_retries, LookupError, ProviderOptions},
+    AuthProvider,
+};
+use crate::{api::v1::auth::AuthErrorPayload, eligibility::Evidence, util::Secret};
+use axum::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use clap::Parser;
+use eyre::eyre;
+use kzg_ceremony_crypto::signature::identity::Identity;
+use oauth2::{
+    basic::BasicClient, reqwest::async_http_client, AuthUrl, AuthorizationCode, ClientId,
+    ClientSecret, CsrfToken, RedirectUrl, Scope, TokenResponse, TokenUrl,
+};
+use serde::Deserialize;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Start of the Discord epoch (2015-01-01) in milliseconds since the unix
+/// epoch. Discord ids are snowflakes that encode their creation time relative
+/// to it.
+const DISCORD_EPOCH_MS: u64 = 1_420_070_400_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct DiscordAuthOptions {
+    /// The latest date a Discord account can have been created in order to
+    /// participate.
+    #[clap(long, env, default_value = "2022-08-01T00:00:00Z")]
+    pub discord_max_account_creation_time: DateTime<FixedOffset>,
+
+    /// Discord OAuth2 authorization url.
+    #[clap(long, env, default_value = "https://discord.com/oauth2/authorize")]
+    pub discord_auth_url: String,
+
+    /// Discord OAuth2 token url.
+    #[clap(long, env, default_value = "https://discord.com/api/oauth2/token")]
+    pub discord_token_url: String,
+
+    /// Discord OAuth2 user info url.
+    #[clap(long, env, default_value = "https://discord.com/api/users/@me")]
+    pub discord_userinfo_url: String,
+
+    /// Discord OAuth2 callback redirect url.
+    #[clap(
+        long,
+        env,
+        default_value = "http://127.0.0.1:3000/auth/callback/discord"
+    )]
+    pub discord_redirect_url: String,
+
+    /// Discord OAuth2 client access id. Required if the Discord provider is
+    /// enabled.
+    #[clap(long, env)]
+    pub discord_client_id: Option<Secret>,
+
+    /// Discord OAuth2 client access key. Required if the Discord provider is
+    /// enabled.
+    #[clap(long, env)]
+    pub discord_client_secret: Option<Secret>,
+}
+
+pub struct DiscordProvider {
+    client:  BasicClient,
+    options: DiscordAuthOptions,
+    lookup:  ProviderOptions,
+}
+
+impl DiscordProvider {
+    pub fn new(options: &DiscordAuthOptions, lookup: &ProviderOptions) -> eyre::Result<Self> {
+        let (client_id, client_secret) =
+            match (&options.discord_client_id, &options.discord_client_secret) {
+                (Some(client_id), Some(client_secret)) => (client_id, client_secret),
+                _ => {
+                    return Err(eyre!(
+                        "the discord provider requires --discord-client-id and \
+                         --discord-client-secret"
+                    ))
+                }
+            };
+        let client = BasicClient::new(
+            ClientId::new(client_id.get_secret().to_owned()),
+            Some(ClientSecret::new(client_secret.get_secret().to_owned())),
+            AuthUrl::new(options.discord_auth_url.clone())?,
+            Some(TokenUrl::new(options.discord_token_url.clone())?),
+        )
+        .set_redirect_uri(RedirectUrl::new(options.discord_redirect_url.clone())?);
+        Ok(Self {
+            client,
+            options: options.clone(),
+            lookup: lookup.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscordUserInfo {
+    id:       String,
+    username: String,
+}
+
+fn snowflake_creation_time(id: u64) -> DateTime<Utc> {
+    (UNIX_EPOCH + Duration::from_millis((id >> 22) + DISCORD_EPOCH_MS)).into()
+}
+
+#[async_trait]
+impl AuthProvider for DiscordProvider {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    fn authorize_url(&self, csrf_token: CsrfToken, _nonce: Option<&str>) -> String {
+        let (url, _) = self
+            .client
+            .authorize_url(|| csrf_token)
+            .add_scope(Scope::new("identify".to_string()))
+            .url();
+        url.to_string()
+    }
+
+    fn health_url(&self) -> &str {
+        &self.options.discord_auth_url
+    }
+
+    async fn authenticate(
+        &self,
+        code: String,
+        _nonce: Option<&str>,
+        http_client: &reqwest::Client,
+    ) -> Result<(Identity, Evidence), AuthErrorPayload> {
+        let token = self
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| {
+                token_exchange_error(&e).into_auth_error(AuthErrorPayload::InvalidAuthCode)
+            })?;
+
+        let user_info = with_retries(&self.lookup, self.name(), || async {
+            let response = http_client
+                .get(&self.options.discord_userinfo_url)
+                .bearer_auth(token.access_token().secret())
+                .send()
+                .await
+                .map_err(|e| LookupError::from_request(&e))?;
+            if !response.status().is_success() {
+                return Err(LookupError::from_status(response.status()));
+            }
+            response
+                .json::<DiscordUserInfo>()
+                .await
+                .map_err(|e| LookupError::Invalid(e.to_string()))
+        })
+        .await
+        .map_err(|e| e.into_auth_error(AuthErrorPayload::CouldNotExtractUserData))?;
+        let id = user_info
+            .id
+            .parse()
+            .map_err(|_| AuthErrorPayload::CouldNotExtractUserData)?;
+        let created_at = snowflake_creation_time(id);
+        if created_at > self.options.discord_max_account_creation_time {
+            return Err(AuthErrorPayload::UserCreatedAfterDeadline);
+        }
+        let identity = Identity::Discord {
+            id,
+            username: user_info.username,
+        };
+        Ok((identity, Evidence {
+            account_created_at: Some(created_at),
+            nonce:              None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_snowflake_time() {
+        // Example from the Discord API reference
+        let time = snowflake_creation_time(175_928_847_299_117_063);
+        assert_eq!(time.to_rfc3339(), "2016-04-30T11:18:25.796+00:00");
+    }
+}
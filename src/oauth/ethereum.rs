@@ -1,7 +1,25 @@
-use crate::util::Secret;
+use super::{
+    lookup::{token_exchange_error, with_retries, IdentityCache, LookupError, ProviderOptions},
+    AuthProvider,
+};
+use crate::{api::v1::auth::AuthErrorPayload, eligibility::Evidence, util::Secret};
+use axum::async_trait;
 use clap::Parser;
-use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
-use std::{num::ParseIntError, ops::Deref};
+use eyre::eyre;
+use kzg_ceremony_crypto::signature::identity::Identity;
+use oauth2::{
+    basic::{
+        BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
+        BasicTokenType,
+    },
+    reqwest::async_http_client,
+    AuthUrl, AuthorizationCode, Client, ClientId, ClientSecret, CsrfToken, ExtraTokenFields,
+    RedirectUrl, Scope, StandardRevocableToken, StandardTokenResponse, TokenResponse, TokenUrl,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{num::ParseIntError, str::FromStr, time::Duration};
+use tracing::error;
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 pub struct EthAuthOptions {
@@ -47,42 +65,260 @@ pub struct EthAuthOptions {
     #[clap(long, env, default_value = "http://127.0.0.1:3000/auth/callback/eth")]
     pub eth_redirect_url: String,
 
-    /// Sign-in-with-Ethereum OAuth2 client access id.
+    /// Sign-in-with-Ethereum OAuth2 client access id. Required if the Ethereum
+    /// provider is enabled.
     #[clap(long, env)]
-    pub eth_client_id: Secret,
+    pub eth_client_id: Option<Secret>,
 
-    /// Sign-in-with-Ethereum OAuth2 client access key.
+    /// Sign-in-with-Ethereum OAuth2 client access key. Required if the
+    /// Ethereum provider is enabled.
     #[clap(long, env)]
-    pub eth_client_secret: Secret,
+    pub eth_client_secret: Option<Secret>,
+
+    /// Number of seconds a Sign-in-with-Ethereum nonce stays valid after the
+    /// sign-in link was handed out.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "600")]
+    pub eth_auth_nonce_ttl: Duration,
 }
 
-#[derive(Clone)]
-pub struct EthOAuthClient {
-    client: BasicClient,
+/// The OpenID Connect id token returned next to the access token.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct IdTokenFields {
+    id_token: Option<String>,
 }
 
-impl Deref for EthOAuthClient {
-    type Target = BasicClient;
+impl ExtraTokenFields for IdTokenFields {}
 
-    fn deref(&self) -> &Self::Target {
-        &self.client
-    }
+type OidcClient = Client<
+    BasicErrorResponse,
+    StandardTokenResponse<IdTokenFields, BasicTokenType>,
+    BasicTokenType,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+>;
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    nonce: Option<String>,
+}
+
+/// The `nonce` claim of an id token. The token comes straight from the token
+/// endpoint over TLS, so its signature is not checked, as allowed by OpenID
+/// Connect Core 3.1.3.7.
+fn id_token_nonce(id_token: &str) -> Option<String> {
+    let claims = id_token.split('.').nth(1)?;
+    let claims = base64::decode_config(claims, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice::<IdTokenClaims>(&claims).ok()?.nonce
 }
 
-pub fn eth_oauth_client(options: &EthAuthOptions) -> EthOAuthClient {
-    EthOAuthClient {
-        client: BasicClient::new(
-            ClientId::new(options.eth_client_id.get_secret().to_owned()),
-            Some(ClientSecret::new(
-                options.eth_client_secret.get_secret().to_owned(),
-            )),
-            AuthUrl::new(options.eth_auth_url.clone()).unwrap(),
-            Some(TokenUrl::new(options.eth_token_url.clone()).unwrap()),
+pub struct EthProvider {
+    client:    OidcClient,
+    options:   EthAuthOptions,
+    lookup:    ProviderOptions,
+    /// Transaction counts at `--eth-nonce-verification-block`, by address.
+    tx_counts: IdentityCache<u64>,
+}
+
+impl EthProvider {
+    pub fn new(options: &EthAuthOptions, lookup: &ProviderOptions) -> eyre::Result<Self> {
+        let (client_id, client_secret) = match (&options.eth_client_id, &options.eth_client_secret)
+        {
+            (Some(client_id), Some(client_secret)) => (client_id, client_secret),
+            _ => {
+                return Err(eyre!(
+                    "the eth provider requires --eth-client-id and --eth-client-secret"
+                ))
+            }
+        };
+        let client = OidcClient::new(
+            ClientId::new(client_id.get_secret().to_owned()),
+            Some(ClientSecret::new(client_secret.get_secret().to_owned())),
+            AuthUrl::new(options.eth_auth_url.clone())?,
+            Some(TokenUrl::new(options.eth_token_url.clone())?),
         )
-        .set_redirect_uri(RedirectUrl::new(options.eth_redirect_url.clone()).unwrap()),
+        .set_redirect_uri(RedirectUrl::new(options.eth_redirect_url.clone())?);
+        Ok(Self {
+            client,
+            options: options.clone(),
+            lookup: lookup.clone(),
+            tx_counts: IdentityCache::new(lookup),
+        })
+    }
+
+    async fn get_tx_count(
+        &self,
+        address: &str,
+        client: &reqwest::Client,
+    ) -> Result<u64, LookupError> {
+        let rpc_payload = json!({
+            "id": 1,
+            "jsonrpc": "2.0",
+            "params": [&address, &self.options.eth_nonce_verification_block],
+            "method": "eth_getTransactionCount"
+        });
+
+        let rpc_response = client
+            .post(self.options.eth_rpc_url.get_secret())
+            .json(&rpc_payload)
+            .send()
+            .await
+            .map_err(|e| LookupError::from_request(&e))?;
+        if !rpc_response.status().is_success() {
+            return Err(LookupError::from_status(rpc_response.status()));
+        }
+
+        let rpc_response_json = rpc_response
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|e| LookupError::Invalid(e.to_string()))?;
+        // Nodes report overload and rate limits as JSON-RPC errors.
+        if let Some(error) = rpc_response_json.get("error") {
+            return Err(LookupError::Unavailable(error.to_string()));
+        }
+
+        let rpc_result = rpc_response_json
+            .get("result")
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| LookupError::Invalid("malformed response JSON".to_string()))?;
+
+        u64::from_str_radix(rpc_result.trim_start_matches("0x"), 16)
+            .map_err(|e| LookupError::Invalid(e.to_string()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct EthUserInfo {
+    sub: String,
+}
+
+// So Sequencer could give out fake identities, we are trusting the sequencer
+// to not do that.
+//
+// Now this is catchable by the client. They will clearly see that the sequencer
+// was malicious. What can happen is sequencer can claim that someone
+// participated when they did not. Is this Okay? Maybe that person can then just
+// say they did not
+#[async_trait]
+impl AuthProvider for EthProvider {
+    fn name(&self) -> &'static str {
+        "eth"
+    }
+
+    fn authorize_url(&self, csrf_token: CsrfToken, nonce: Option<&str>) -> String {
+        let mut request = self
+            .client
+            .authorize_url(|| csrf_token)
+            .add_scope(Scope::new("openid".to_string()));
+        if let Some(nonce) = nonce {
+            request = request.add_extra_param("nonce", nonce);
+        }
+        let (url, _) = request.url();
+        url.to_string()
+    }
+
+    fn requires_nonce(&self) -> bool {
+        true
+    }
+
+    fn health_url(&self) -> &str {
+        &self.options.eth_auth_url
+    }
+
+    async fn authenticate(
+        &self,
+        code: String,
+        nonce: Option<&str>,
+        http_client: &reqwest::Client,
+    ) -> Result<(Identity, Evidence), AuthErrorPayload> {
+        let token = self
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| {
+                token_exchange_error(&e).into_auth_error(AuthErrorPayload::InvalidAuthCode)
+            })?;
+        // The provider binds the nonce into the signed Sign-in-with-Ethereum
+        // message and echoes it in the id token.
+        if let Some(id_token) = &token.extra_fields().id_token {
+            if id_token_nonce(id_token).as_deref() != nonce {
+                return Err(AuthErrorPayload::InvalidNonce);
+            }
+        }
+
+        let eth_user = with_retries(&self.lookup, self.name(), || async {
+            let response = http_client
+                .get(&self.options.eth_userinfo_url)
+                .bearer_auth(token.access_token().secret())
+                .send()
+                .await
+                .map_err(|e| LookupError::from_request(&e))?;
+            if !response.status().is_success() {
+                return Err(LookupError::from_status(response.status()));
+            }
+            response
+                .json::<EthUserInfo>()
+                .await
+                .map_err(|e| LookupError::Invalid(e.to_string()))
+        })
+        .await
+        .map_err(|e| e.into_auth_error(AuthErrorPayload::CouldNotExtractUserData))?;
+
+        let addr_parts: Vec<_> = eth_user.sub.split(':').collect();
+        let address = (*addr_parts
+            .get(2)
+            .ok_or(AuthErrorPayload::CouldNotExtractUserData)?)
+        .to_string();
+
+        // The count at a fixed block does not change, so the cache is keyed by
+        // the address alone.
+        let tx_count = self
+            .tx_counts
+            .get_or_lookup(self.name(), &address.to_lowercase(), || {
+                self.get_tx_count(&address, http_client)
+            })
+            .await
+            .map_err(|e| {
+                error!("Could not get tx count for {address}: {e}");
+                e.into_auth_error(AuthErrorPayload::CouldNotExtractUserData)
+            })?;
+
+        if tx_count < self.options.eth_min_nonce {
+            return Err(AuthErrorPayload::UserCreatedAfterDeadline);
+        }
+
+        let identity = Identity::eth_from_str(&address)
+            .map_err(|_| AuthErrorPayload::CouldNotExtractUserData)?;
+        Ok((identity, Evidence {
+            account_created_at: None,
+            nonce:              Some(tx_count),
+        }))
     }
 }
 
 fn dec_to_hex(input: &str) -> Result<String, ParseIntError> {
     Ok(format!("0x{:x}", input.parse::<u64>()?))
 }
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_id_token_nonce() {
+        let claims =
+            base64::encode_config(br#"{"sub":"x","nonce":"abc"}"#, base64::URL_SAFE_NO_PAD);
+        assert_eq!(
+            id_token_nonce(&format!("header.{claims}.signature")),
+            Some("abc".to_string())
+        );
+        let claims = base64::encode_config(br#"{"sub":"x"}"#, base64::URL_SAFE_NO_PAD);
+        assert_eq!(id_token_nonce(&format!("header.{claims}.signature")), None);
+        assert_eq!(id_token_nonce("not a token"), None);
+    }
+}
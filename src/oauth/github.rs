@@ -1,8 +1,19 @@
-use crate::util::Secret;
-use chrono::{DateTime, FixedOffset};
+use super::{
+    lookup::{token_exchange_error, with_retries, LookupError, ProviderOptions},
+    AuthProvider,
+};
+use crate::{api::v1::auth::AuthErrorPayload, eligibility::Evidence, util::Secret};
+use axum::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
 use clap::Parser;
-use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
-use std::ops::Deref;
+use eyre::eyre;
+use kzg_ceremony_crypto::signature::identity::Identity;
+use oauth2::{
+    basic::BasicClient, reqwest::async_http_client, AuthUrl, AuthorizationCode, ClientId,
+    ClientSecret, CsrfToken, RedirectUrl, RequestTokenError, TokenResponse, TokenUrl,
+};
+use serde::Deserialize;
+use tracing::warn;
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 pub struct GithubAuthOptions {
@@ -35,38 +46,122 @@ pub struct GithubAuthOptions {
     )]
     pub gh_redirect_url: String,
 
-    /// Github OAuth2 client access id.
+    /// Github OAuth2 client access id. Required if the Github provider is
+    /// enabled.
     #[clap(long, env)]
-    pub gh_client_id: Secret,
+    pub gh_client_id: Option<Secret>,
 
-    /// Github OAuth2 client access key.
+    /// Github OAuth2 client access key. Required if the Github provider is
+    /// enabled.
     #[clap(long, env)]
-    pub gh_client_secret: Secret,
+    pub gh_client_secret: Option<Secret>,
 }
 
-#[derive(Clone)]
-pub struct GithubOAuthClient {
-    pub client: BasicClient,
+pub struct GithubProvider {
+    client:  BasicClient,
+    options: GithubAuthOptions,
+    lookup:  ProviderOptions,
 }
 
-impl Deref for GithubOAuthClient {
-    type Target = BasicClient;
-
-    fn deref(&self) -> &Self::Target {
-        &self.client
+impl GithubProvider {
+    pub fn new(options: &GithubAuthOptions, lookup: &ProviderOptions) -> eyre::Result<Self> {
+        let (client_id, client_secret) = match (&options.gh_client_id, &options.gh_client_secret) {
+            (Some(client_id), Some(client_secret)) => (client_id, client_secret),
+            _ => {
+                return Err(eyre!(
+                    "the github provider requires --gh-client-id and --gh-client-secret"
+                ))
+            }
+        };
+        let client = BasicClient::new(
+            ClientId::new(client_id.get_secret().to_owned()),
+            Some(ClientSecret::new(client_secret.get_secret().to_owned())),
+            AuthUrl::new(options.gh_auth_url.clone())?,
+            Some(TokenUrl::new(options.gh_token_url.clone())?),
+        )
+        .set_redirect_uri(RedirectUrl::new(options.gh_redirect_url.clone())?);
+        Ok(Self {
+            client,
+            options: options.clone(),
+            lookup: lookup.clone(),
+        })
     }
 }
 
-pub fn github_oauth_client(options: &GithubAuthOptions) -> GithubOAuthClient {
-    GithubOAuthClient {
-        client: BasicClient::new(
-            ClientId::new(options.gh_client_id.get_secret().to_owned()),
-            Some(ClientSecret::new(
-                options.gh_client_secret.get_secret().to_owned(),
-            )),
-            AuthUrl::new(options.gh_auth_url.clone()).unwrap(),
-            Some(TokenUrl::new(options.gh_token_url.clone()).unwrap()),
-        )
-        .set_redirect_uri(RedirectUrl::new(options.gh_redirect_url.clone()).unwrap()),
+#[derive(Debug, Deserialize)]
+struct GhUserInfo {
+    id:         u64,
+    login:      String,
+    created_at: String,
+}
+
+#[async_trait]
+impl AuthProvider for GithubProvider {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn authorize_url(&self, csrf_token: CsrfToken, _nonce: Option<&str>) -> String {
+        let (url, _) = self.client.authorize_url(|| csrf_token).url();
+        url.to_string()
+    }
+
+    fn health_url(&self) -> &str {
+        &self.options.gh_auth_url
+    }
+
+    async fn authenticate(
+        &self,
+        code: String,
+        _nonce: Option<&str>,
+        http_client: &reqwest::Client,
+    ) -> Result<(Identity, Evidence), AuthErrorPayload> {
+        let token = self
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| {
+                let error = token_exchange_error(&e);
+                if let RequestTokenError::Parse(_, bytes) = e {
+                    let response_str = String::from_utf8(bytes);
+                    warn!("Unexpected Github Token Exchange response: {response_str:?}");
+                } else {
+                    warn!("Github Token Exchange Error: {e}");
+                }
+                error.into_auth_error(AuthErrorPayload::InvalidAuthCode)
+            })?;
+
+        let gh_user_info = with_retries(&self.lookup, self.name(), || async {
+            let response = http_client
+                .get(&self.options.gh_userinfo_url)
+                .bearer_auth(token.access_token().secret())
+                .header("User-Agent", "ethereum-kzg-ceremony-sequencer")
+                .send()
+                .await
+                .map_err(|e| LookupError::from_request(&e))?;
+            if !response.status().is_success() {
+                return Err(LookupError::from_status(response.status()));
+            }
+            response
+                .json::<GhUserInfo>()
+                .await
+                .map_err(|e| LookupError::Invalid(e.to_string()))
+        })
+        .await
+        .map_err(|e| e.into_auth_error(AuthErrorPayload::CouldNotExtractUserData))?;
+        let creation_time = DateTime::parse_from_rfc3339(&gh_user_info.created_at)
+            .map_err(|_| AuthErrorPayload::CouldNotExtractUserData)?;
+        if creation_time > self.options.gh_max_account_creation_time {
+            return Err(AuthErrorPayload::UserCreatedAfterDeadline);
+        }
+        let identity = Identity::Github {
+            id:       gh_user_info.id,
+            username: gh_user_info.login,
+        };
+        Ok((identity, Evidence {
+            account_created_at: Some(creation_time.with_timezone(&Utc)),
+            nonce:              None,
+        }))
     }
 }
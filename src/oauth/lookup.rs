@@ -0,0 +1,276 @@
+//! Calls to the identity providers that survive brief outages.
+//!
+//! Idempotent requests to a provider are retried `--auth-provider-retries`
+//! times when the provider can not be reached or answers with a server error,
+//! starting after `--auth-provider-retry-backoff` milliseconds and doubling the
+//! delay each time. Lookups with a stable key, like the transaction count of
+//! an address at the verification block, are cached for
+//! `--identity-cache-ttl` seconds. While the provider is down, cached results
+//! up to `--identity-cache-max-stale` seconds old are used instead. Sign-ins
+//! that still fail because of the provider are answered with `SEQ-AUTH-013`.
+
+use crate::api::v1::auth::AuthErrorPayload;
+use clap::Parser;
+use oauth2::{ErrorResponse, RequestTokenError};
+use reqwest::StatusCode;
+use std::{
+    collections::HashMap,
+    future::Future,
+    num::ParseIntError,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::warn;
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct ProviderOptions {
+    /// How often requests to an identity provider are retried when it is
+    /// unreachable or fails.
+    #[clap(long, env, default_value = "2")]
+    pub auth_provider_retries: u32,
+
+    /// Delay before the first retry, in milliseconds. Doubles with every
+    /// retry.
+    #[clap(long, env, default_value = "200")]
+    pub auth_provider_retry_backoff: u64,
+
+    /// Seconds a successful identity lookup is reused without asking the
+    /// provider again.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "3600")]
+    pub identity_cache_ttl: Duration,
+
+    /// Age in seconds up to which a cached identity lookup is used while the
+    /// provider is unavailable.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "86400")]
+    pub identity_cache_max_stale: Duration,
+
+    /// Maximum number of cached identity lookups per provider.
+    #[clap(long, env, default_value = "100000")]
+    pub identity_cache_size: usize,
+}
+
+#[derive(Debug, Error)]
+pub enum LookupError {
+    /// The provider could not be reached or failed, trying again later may
+    /// succeed.
+    #[error("provider unavailable: {0}")]
+    Unavailable(String),
+    #[error("invalid response: {0}")]
+    Invalid(String),
+}
+
+impl LookupError {
+    /// Classifies a failed request.
+    pub fn from_request(error: &reqwest::Error) -> Self {
+        match error.status() {
+            Some(status) if !is_transient(status) => Self::Invalid(error.to_string()),
+            _ if error.is_decode() => Self::Invalid(error.to_string()),
+            _ => Self::Unavailable(error.to_string()),
+        }
+    }
+
+    /// Classifies an unsuccessful response status.
+    pub fn from_status(status: StatusCode) -> Self {
+        if is_transient(status) {
+            Self::Unavailable(status.to_string())
+        } else {
+            Self::Invalid(status.to_string())
+        }
+    }
+
+    /// The sign-in error, `invalid` if the provider answered but its answer
+    /// was not usable.
+    pub fn into_auth_error(self, invalid: AuthErrorPayload) -> AuthErrorPayload {
+        match self {
+            Self::Unavailable(_) => AuthErrorPayload::ProviderUnavailable,
+            Self::Invalid(_) => invalid,
+        }
+    }
+}
+
+/// Classifies a failed exchange of an authorization code. The exchange is
+/// not retried, as codes can only be used once. A response that is neither a
+/// token nor an OAuth2 error is taken for an outage.
+pub fn token_exchange_error<RE, T>(error: &RequestTokenError<RE, T>) -> LookupError
+where
+    RE: std::error::Error + 'static,
+    T: ErrorResponse + 'static,
+{
+    match error {
+        RequestTokenError::Request(_) | RequestTokenError::Parse(..) => {
+            LookupError::Unavailable(error.to_string())
+        }
+        RequestTokenError::ServerResponse(_) | RequestTokenError::Other(_) => {
+            LookupError::Invalid(error.to_string())
+        }
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Runs `request` until it succeeds, fails for good or runs out of retries.
+pub async fn with_retries<T, F, Fut>(
+    options: &ProviderOptions,
+    provider: &str,
+    mut request: F,
+) -> Result<T, LookupError>
+where
+    F: FnMut() -> Fut + Send,
+    Fut: Future<Output = Result<T, LookupError>> + Send,
+{
+    let mut backoff = Duration::from_millis(options.auth_provider_retry_backoff);
+    let mut attempt = 0;
+    loop {
+        match request().await {
+            Err(error @ LookupError::Unavailable(_)) if attempt < options.auth_provider_retries => {
+                warn!(
+                    provider,
+                    ?error,
+                    attempt,
+                    "identity provider request failed, retrying"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Successful lookups of one provider, by key.
+pub struct IdentityCache<V> {
+    options: ProviderOptions,
+    entries: Mutex<HashMap<String, (V, Instant)>>,
+}
+
+impl<V: Clone + Send> IdentityCache<V> {
+    pub fn new(options: &ProviderOptions) -> Self {
+        Self {
+            options: options.clone(),
+            entries: Mutex::default(),
+        }
+    }
+
+    /// The cached value of `key` if it is fresh, otherwise runs `lookup` with
+    /// retries. If the provider stays unavailable, a stale value is used.
+    pub async fn get_or_lookup<F, Fut>(
+        &self,
+        provider: &str,
+        key: &str,
+        lookup: F,
+    ) -> Result<V, LookupError>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<V, LookupError>> + Send,
+    {
+        if let Some(value) = self.get(key, self.options.identity_cache_ttl) {
+            return Ok(value);
+        }
+        match with_retries(&self.options, provider, lookup).await {
+            Ok(value) => {
+                self.insert(key, value.clone());
+                Ok(value)
+            }
+            Err(error @ LookupError::Unavailable(_)) => {
+                match self.get(key, self.options.identity_cache_max_stale) {
+                    Some(value) => {
+                        warn!(
+                            provider,
+                            ?error,
+                            "identity provider unavailable, using cache"
+                        );
+                        Ok(value)
+                    }
+                    None => Err(error),
+                }
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn get(&self, key: &str, max_age: Duration) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() <= max_age)
+            .map(|(value, _)| value.clone())
+    }
+
+    fn insert(&self, key: &str, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.options.identity_cache_size {
+            let max_stale = self.options.identity_cache_max_stale;
+            entries.retain(|_, (_, fetched_at)| fetched_at.elapsed() <= max_stale);
+        }
+        if entries.len() < self.options.identity_cache_size || entries.contains_key(key) {
+            entries.insert(key.to_string(), (value, Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn falls_back_to_stale_lookups() {
+        let mut options = test_options().auth_provider;
+        options.auth_provider_retry_backoff = 1;
+        options.identity_cache_ttl = Duration::ZERO;
+        let cache = IdentityCache::new(&options);
+        let calls = AtomicU32::new(0);
+
+        let value = cache
+            .get_or_lookup("eth", "0xabc", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(7)
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, 7);
+
+        // The provider is down, the expired entry is used after the retries.
+        let value = cache
+            .get_or_lookup("eth", "0xabc", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(LookupError::Unavailable("down".to_string()))
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, 7);
+        assert_eq!(
+            calls.load(Ordering::SeqCst),
+            1 + 1 + options.auth_provider_retries
+        );
+
+        // Unknown keys and invalid answers are not covered by the cache.
+        assert!(matches!(
+            cache
+                .get_or_lookup("eth", "0xdef", || async {
+                    Err(LookupError::Unavailable("down".to_string()))
+                })
+                .await,
+            Err(LookupError::Unavailable(_))
+        ));
+        assert!(matches!(
+            cache
+                .get_or_lookup("eth", "0xabc", || async {
+                    Err(LookupError::Invalid("garbage".to_string()))
+                })
+                .await,
+            Err(LookupError::Invalid(_))
+        ));
+    }
+}
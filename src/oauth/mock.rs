@@ -0,0 +1,190 @@
+//! Sign-in without OAuth apps for local development, only built with the
+//! `dev` feature.
+//!
+//! With `--auth-providers mock`, any bearer token of the form `mock:<name>`
+//! is a session of the mock account `<name>`. The session is minted on its
+//! first use, so clients can skip the sign-in flow and use the token right
+//! away. Frontends can also go through `/auth/request_link` and
+//! `/auth/callback/mock?code=mock:<name>`, which answers with the same
+//! session.
+
+use super::{AuthProvider, AuthProviders, SharedAuthState, StaticTokenProvider};
+use crate::{
+    api::v1::auth::{post_authenticate, AuthErrorPayload},
+    eligibility::{Evidence, SharedScorer},
+    lobby::SharedLobbyState,
+    sessions::SessionId,
+    storage::PersistentStorage,
+    Options,
+};
+use axum::{async_trait, http::Extensions};
+use chrono::{DateTime, Utc};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use oauth2::CsrfToken;
+use std::time::UNIX_EPOCH;
+use tracing::{info, warn};
+use url::Url;
+use uuid::Uuid;
+
+pub const MOCK_TOKEN_PREFIX: &str = "mock:";
+
+/// Longest name accepted for a mock account.
+const MAX_NAME_LEN: usize = 64;
+
+/// Signs participants in as mock accounts, see [the module](self).
+///
+/// Accounts are Github identities with the name as username, like those of
+/// [`StaticTokenProvider`], so the same name always is the same account.
+pub struct MockProvider {
+    callback_url: Url,
+}
+
+impl MockProvider {
+    #[must_use]
+    pub fn new(server: &Url) -> Self {
+        // The server url is a directory, so the callback is joined below it.
+        let callback_url = server
+            .join("auth/callback/mock")
+            .expect("relative path joins");
+        Self { callback_url }
+    }
+
+    /// The name of the mock account of `token`, if it is a mock token.
+    fn name(token: &str) -> Option<&str> {
+        let name = token.strip_prefix(MOCK_TOKEN_PREFIX)?;
+        let valid = !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then_some(name)
+    }
+
+    fn evidence() -> Evidence {
+        Evidence {
+            account_created_at: Some(DateTime::<Utc>::from(UNIX_EPOCH)),
+            nonce:              None,
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn authorize_url(&self, csrf_token: CsrfToken, _nonce: Option<&str>) -> String {
+        let mut url = self.callback_url.clone();
+        let code = format!("{MOCK_TOKEN_PREFIX}{}", Uuid::new_v4().simple());
+        url.query_pairs_mut()
+            .append_pair("code", &code)
+            .append_pair("state", csrf_token.secret());
+        url.to_string()
+    }
+
+    fn health_url(&self) -> &str {
+        self.callback_url.as_str()
+    }
+
+    fn session_id(&self, identity: &Identity) -> Option<SessionId> {
+        Some(SessionId(format!(
+            "{MOCK_TOKEN_PREFIX}{}",
+            identity.nickname()
+        )))
+    }
+
+    async fn authenticate(
+        &self,
+        code: String,
+        _nonce: Option<&str>,
+        _http_client: &reqwest::Client,
+    ) -> Result<(Identity, Evidence), AuthErrorPayload> {
+        let name = Self::name(&code).ok_or(AuthErrorPayload::InvalidAuthCode)?;
+        Ok((StaticTokenProvider::identity(name), Self::evidence()))
+    }
+}
+
+/// Signs in the mock account of a `mock:<name>` session on its first use.
+/// Does nothing for other sessions, sessions that are known already, or if
+/// the mock provider is not enabled.
+pub async fn mint_session(extensions: &Extensions, session_id: &SessionId) {
+    let name = match MockProvider::name(&session_id.0) {
+        Some(name) => name,
+        None => return,
+    };
+    let enabled = extensions
+        .get::<AuthProviders>()
+        .map_or(false, |providers| providers.get("mock").is_some());
+    let (lobby_state, auth_state, storage, scorer, options) = match (
+        extensions.get::<SharedLobbyState>(),
+        extensions.get::<SharedAuthState>(),
+        extensions.get::<PersistentStorage>(),
+        extensions.get::<SharedScorer>(),
+        extensions.get::<Options>(),
+    ) {
+        (Some(lobby), Some(auth), Some(storage), Some(scorer), Some(options)) if enabled => {
+            (lobby, auth, storage, scorer, options)
+        }
+        _ => return,
+    };
+    if lobby_state.session_uid(session_id).await.is_some() {
+        return;
+    }
+    let identity = StaticTokenProvider::identity(name);
+    let eligibility = scorer
+        .current()
+        .evaluate(&identity, &MockProvider::evidence());
+    if !eligibility.eligible {
+        warn!(
+            name,
+            score = eligibility.score,
+            "Mock account is not eligible."
+        );
+        return;
+    }
+    match post_authenticate(
+        auth_state.clone(),
+        lobby_state.clone(),
+        storage.clone(),
+        identity,
+        eligibility,
+        None,
+        options,
+        Some(session_id.clone()),
+    )
+    .await
+    {
+        Ok(_) => info!(name, "Minted mock session"),
+        Err(error) => warn!(?error, name, "failed to mint mock session"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn signs_in_mock_accounts() {
+        let provider = MockProvider::new(&"http://127.0.0.1:3000/".parse().unwrap());
+        let url = provider.authorize_url(CsrfToken::new("state".to_string()), None);
+        assert!(url.starts_with("http://127.0.0.1:3000/auth/callback/mock?code=mock%3A"));
+
+        let client = reqwest::Client::new();
+        let (alice, _) = provider
+            .authenticate("mock:alice".to_string(), None, &client)
+            .await
+            .unwrap();
+        assert_eq!(alice.nickname(), "alice");
+        assert_eq!(
+            provider.session_id(&alice),
+            Some(SessionId("mock:alice".to_string()))
+        );
+        for code in ["alice", "mock:", "mock:git|1|alice"] {
+            assert!(matches!(
+                provider.authenticate(code.to_string(), None, &client).await,
+                Err(AuthErrorPayload::InvalidAuthCode)
+            ));
+        }
+    }
+}
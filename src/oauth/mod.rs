@@ -1,13 +1,32 @@
+mod discord;
 mod ethereum;
 mod github;
+mod lookup;
+#[cfg(feature = "dev")]
+mod mock;
+mod oidc;
+mod static_token;
 
-use crate::sessions::SessionId;
+use crate::{
+    api::v1::auth::AuthErrorPayload, eligibility::Evidence, sessions::SessionId, test_mode::Mode,
+    Options,
+};
+use axum::async_trait;
+use clap::ValueEnum;
+use kzg_ceremony_crypto::signature::identity::Identity;
+use oauth2::CsrfToken;
 use std::{collections::BTreeMap, sync::Arc};
 use tokio::sync::RwLock;
 
+#[cfg(feature = "dev")]
+pub use self::mock::{mint_session, MockProvider};
 pub use self::{
-    ethereum::{eth_oauth_client, EthAuthOptions, EthOAuthClient},
-    github::{github_oauth_client, GithubAuthOptions, GithubOAuthClient},
+    discord::{DiscordAuthOptions, DiscordProvider},
+    ethereum::{EthAuthOptions, EthProvider},
+    github::{GithubAuthOptions, GithubProvider},
+    lookup::ProviderOptions,
+    oidc::{OidcAuthOptions, OidcProvider},
+    static_token::StaticTokenProvider,
 };
 
 pub type SharedAuthState = Arc<RwLock<AuthState>>;
@@ -20,3 +39,191 @@ pub struct AuthState {
     // We use this to check if a user has already entered the lobby
     pub unique_id_session: BTreeMap<IdTokenSub, SessionId>,
 }
+
+/// An identity provider participants can sign in with.
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Short name of the provider. It is used in the callback route
+    /// (`/auth/callback/<name>`) and in the `<name>_auth_url` field returned
+    /// by `/auth/request_link`.
+    fn name(&self) -> &'static str;
+
+    /// Fetches what the provider needs to know before the first sign-in, like
+    /// the endpoints of an OIDC issuer. Called once on startup.
+    async fn discover(&self, _http_client: &reqwest::Client) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// Url the participant needs to visit in order to get an authorization
+    /// code. `nonce` is set if the provider [requires
+    /// one](Self::requires_nonce).
+    fn authorize_url(&self, csrf_token: CsrfToken, nonce: Option<&str>) -> String;
+
+    /// Whether sign-ins need a single use nonce issued by the sequencer. The
+    /// nonce is handed out with the authorization url and consumed by the
+    /// callback, so a callback can not be replayed.
+    fn requires_nonce(&self) -> bool {
+        false
+    }
+
+    /// Url used by the readiness check to test that the provider is
+    /// reachable.
+    fn health_url(&self) -> &str;
+
+    /// Exchanges an authorization code for the participants identity and
+    /// checks that it satisfies the providers eligibility rules. Also returns
+    /// the account data used for anti-sybil scoring. `nonce` is the one
+    /// handed out with the authorization url, already consumed.
+    async fn authenticate(
+        &self,
+        code: String,
+        nonce: Option<&str>,
+        http_client: &reqwest::Client,
+    ) -> Result<(Identity, Evidence), AuthErrorPayload>;
+
+    /// The session to sign `identity` in with, instead of a random one.
+    fn session_id(&self, _identity: &Identity) -> Option<SessionId> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum AuthProviderKind {
+    Github,
+    Eth,
+    Discord,
+    Oidc,
+    /// Mock accounts for local development, see `MockProvider`. Only
+    /// available when built with the `dev` feature.
+    Mock,
+}
+
+/// The identity providers enabled through `--auth-providers`.
+#[derive(Clone)]
+pub struct AuthProviders(Arc<Vec<Box<dyn AuthProvider>>>);
+
+impl AuthProviders {
+    /// Builds the enabled providers from the command line options. In
+    /// `--mode test` only the static token provider is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an enabled provider is missing its client
+    /// credentials.
+    pub fn new(options: &Options) -> eyre::Result<Self> {
+        if options.mode == Mode::Test {
+            return Ok(Self(Arc::new(vec![Box::new(StaticTokenProvider::new(
+                &options.server,
+            ))])));
+        }
+        let mut kinds = options.auth_providers.clone();
+        kinds.sort_unstable();
+        kinds.dedup();
+
+        let providers = kinds
+            .into_iter()
+            .map(|kind| -> eyre::Result<Box<dyn AuthProvider>> {
+                Ok(match kind {
+                    AuthProviderKind::Github => Box::new(GithubProvider::new(
+                        &options.github,
+                        &options.auth_provider,
+                    )?),
+                    AuthProviderKind::Eth => {
+                        Box::new(EthProvider::new(&options.ethereum, &options.auth_provider)?)
+                    }
+                    AuthProviderKind::Discord => Box::new(DiscordProvider::new(
+                        &options.discord,
+                        &options.auth_provider,
+                    )?),
+                    AuthProviderKind::Oidc => {
+                        Box::new(OidcProvider::new(&options.oidc, &options.auth_provider)?)
+                    }
+                    #[cfg(feature = "dev")]
+                    AuthProviderKind::Mock => Box::new(MockProvider::new(&options.server)),
+                    #[cfg(not(feature = "dev"))]
+                    AuthProviderKind::Mock => {
+                        eyre::bail!("the mock provider requires building with --features dev")
+                    }
+                })
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        Ok(Self(Arc::new(providers)))
+    }
+
+    /// Runs the discovery of every provider.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a provider can not be set up.
+    pub async fn discover(&self, http_client: &reqwest::Client) -> eyre::Result<()> {
+        for provider in self.iter() {
+            provider.discover(http_client).await?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn AuthProvider> {
+        self.iter().find(|provider| provider.name() == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn AuthProvider> {
+        self.0.iter().map(AsRef::as_ref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+    use clap::Parser;
+
+    #[test]
+    fn enables_configured_providers() {
+        let providers = AuthProviders::new(&test_options()).unwrap();
+        let names: Vec<_> = providers.iter().map(AuthProvider::name).collect();
+        assert_eq!(names, vec!["github", "eth"]);
+        assert!(providers.get("discord").is_none());
+
+        let mut options = test_options();
+        options.auth_providers = vec![AuthProviderKind::Discord];
+        assert!(AuthProviders::new(&options).is_err());
+
+        options.discord = DiscordAuthOptions::parse_from([
+            "discord",
+            "--discord-client-id",
+            "INVALID",
+            "--discord-client-secret",
+            "INVALID",
+        ]);
+        let providers = AuthProviders::new(&options).unwrap();
+        assert!(providers.get("discord").is_some());
+        assert!(providers.get("github").is_none());
+
+        options.auth_providers = vec![AuthProviderKind::Oidc];
+        assert!(AuthProviders::new(&options).is_err());
+        options.oidc = OidcAuthOptions::parse_from([
+            "oidc",
+            "--oidc-issuer",
+            "https://id.example.org",
+            "--oidc-client-id",
+            "INVALID",
+            "--oidc-client-secret",
+            "INVALID",
+        ]);
+        let providers = AuthProviders::new(&options).unwrap();
+        assert!(providers.get("oidc").is_some());
+
+        options.auth_providers = vec![AuthProviderKind::Mock];
+        let providers = AuthProviders::new(&options);
+        if cfg!(feature = "dev") {
+            assert!(providers.unwrap().get("mock").is_some());
+        } else {
+            assert!(providers.is_err());
+        }
+
+        options.mode = Mode::Test;
+        let providers = AuthProviders::new(&options).unwrap();
+        let names: Vec<_> = providers.iter().map(AuthProvider::name).collect();
+        assert_eq!(names, vec!["test"]);
+    }
+}
@@ -0,0 +1,303 @@
+use super::{
+    lookup::{token_exchange_error, with_retries, LookupError, ProviderOptions},
+    AuthProvider,
+};
+use crate::{api::v1::auth::AuthErrorPayload, eligibility::Evidence, util::Secret};
+use axum::async_trait;
+use clap::Parser;
+use eyre::{bail, eyre, WrapErr};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use oauth2::{
+    basic::BasicClient, reqwest::async_http_client, AuthUrl, AuthorizationCode, ClientId,
+    ClientSecret, CsrfToken, RedirectUrl, Scope, TokenResponse, TokenUrl,
+};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::{error, info};
+
+/// Longest claim value accepted as a subject.
+const MAX_SUBJECT_LEN: usize = 256;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+pub struct OidcAuthOptions {
+    /// Issuer url of the OpenID Connect provider, e.g.
+    /// `https://id.example.org/realms/dao`. Its endpoints are discovered from
+    /// `<issuer>/.well-known/openid-configuration` on startup. Required if the
+    /// OIDC provider is enabled.
+    #[clap(long, env)]
+    pub oidc_issuer: Option<String>,
+
+    /// OIDC callback redirect url.
+    #[clap(long, env, default_value = "http://127.0.0.1:3000/auth/callback/oidc")]
+    pub oidc_redirect_url: String,
+
+    /// OIDC client id. Required if the OIDC provider is enabled.
+    #[clap(long, env)]
+    pub oidc_client_id: Option<Secret>,
+
+    /// OIDC client secret. Required if the OIDC provider is enabled.
+    #[clap(long, env)]
+    pub oidc_client_secret: Option<Secret>,
+
+    /// Scopes requested besides `openid`.
+    #[clap(long, env, value_delimiter = ',', default_value = "profile")]
+    pub oidc_scopes: Vec<String>,
+
+    /// User info claim that identifies an account and becomes the last part
+    /// of its uid. Must be stable and unique per account, like `sub`.
+    #[clap(long, env, default_value = "sub")]
+    pub oidc_uid_claim: String,
+}
+
+/// Signs participants in with any OpenID Connect provider.
+///
+/// The authorization code is exchanged for an access token, and the claims
+/// are read from the user info endpoint, so the ID token need not be
+/// verified. Participants are identified by the issuer and the
+/// `--oidc-uid-claim` of their user info.
+pub struct OidcProvider {
+    options:       OidcAuthOptions,
+    issuer:        String,
+    discovery_url: String,
+    client_id:     ClientId,
+    client_secret: ClientSecret,
+    lookup:        ProviderOptions,
+    metadata:      OnceCell<Discovered>,
+}
+
+/// The endpoints of the issuer, available after [`OidcProvider::discover`].
+struct Discovered {
+    client:            BasicClient,
+    userinfo_endpoint: String,
+}
+
+/// The part of the issuer's discovery document the sequencer uses.
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer:                 String,
+    authorization_endpoint: String,
+    token_endpoint:         String,
+    userinfo_endpoint:      Option<String>,
+}
+
+impl OidcProvider {
+    pub fn new(options: &OidcAuthOptions, lookup: &ProviderOptions) -> eyre::Result<Self> {
+        let (issuer, client_id, client_secret) = match (
+            &options.oidc_issuer,
+            &options.oidc_client_id,
+            &options.oidc_client_secret,
+        ) {
+            (Some(issuer), Some(client_id), Some(client_secret)) => {
+                (issuer, client_id, client_secret)
+            }
+            _ => {
+                return Err(eyre!(
+                    "the oidc provider requires --oidc-issuer, --oidc-client-id and \
+                     --oidc-client-secret"
+                ))
+            }
+        };
+        // The issuer is part of the uids, whose parts are separated by `|`.
+        if issuer.contains('|') {
+            bail!("--oidc-issuer must not contain |");
+        }
+        let issuer = issuer.trim_end_matches('/').to_string();
+        Ok(Self {
+            options: options.clone(),
+            discovery_url: format!("{issuer}/.well-known/openid-configuration"),
+            issuer,
+            client_id: ClientId::new(client_id.get_secret().to_owned()),
+            client_secret: ClientSecret::new(client_secret.get_secret().to_owned()),
+            lookup: lookup.clone(),
+            metadata: OnceCell::new(),
+        })
+    }
+
+    /// The subject of an account, the value of the uid claim in its user info.
+    fn subject(&self, claims: &Map<String, Value>) -> Option<String> {
+        let subject = match claims.get(&self.options.oidc_uid_claim)? {
+            Value::String(subject) => subject.clone(),
+            Value::Number(subject) => subject.to_string(),
+            _ => return None,
+        };
+        let valid = !subject.is_empty()
+            && subject.len() <= MAX_SUBJECT_LEN
+            && !subject.contains('|')
+            && !subject.chars().any(char::is_control);
+        valid.then_some(subject)
+    }
+}
+
+#[async_trait]
+impl AuthProvider for OidcProvider {
+    fn name(&self) -> &'static str {
+        "oidc"
+    }
+
+    async fn discover(&self, http_client: &reqwest::Client) -> eyre::Result<()> {
+        let metadata: ProviderMetadata = with_retries(&self.lookup, self.name(), || async {
+            let response = http_client
+                .get(&self.discovery_url)
+                .send()
+                .await
+                .map_err(|e| LookupError::from_request(&e))?;
+            if !response.status().is_success() {
+                return Err(LookupError::from_status(response.status()));
+            }
+            response
+                .json::<ProviderMetadata>()
+                .await
+                .map_err(|e| LookupError::Invalid(e.to_string()))
+        })
+        .await
+        .wrap_err_with(|| format!("OIDC discovery at {} failed", self.discovery_url))?;
+        // Required by OpenID Connect Discovery, so that one issuer can not
+        // pass itself off as another.
+        if metadata.issuer.trim_end_matches('/') != self.issuer {
+            bail!(
+                "OIDC discovery document is of issuer {}, not {}",
+                metadata.issuer,
+                self.issuer
+            );
+        }
+        let userinfo_endpoint = metadata
+            .userinfo_endpoint
+            .ok_or_else(|| eyre!("OIDC issuer {} has no userinfo_endpoint", self.issuer))?;
+        let client = BasicClient::new(
+            self.client_id.clone(),
+            Some(self.client_secret.clone()),
+            AuthUrl::new(metadata.authorization_endpoint)?,
+            Some(TokenUrl::new(metadata.token_endpoint)?),
+        )
+        .set_redirect_uri(RedirectUrl::new(self.options.oidc_redirect_url.clone())?);
+        info!(issuer = %self.issuer, "Discovered OIDC provider");
+        // Discovery runs once on startup.
+        self.metadata
+            .set(Discovered {
+                client,
+                userinfo_endpoint,
+            })
+            .ok();
+        Ok(())
+    }
+
+    fn authorize_url(&self, csrf_token: CsrfToken, _nonce: Option<&str>) -> String {
+        let discovered = match self.metadata.get() {
+            Some(discovered) => discovered,
+            None => {
+                error!(issuer = %self.issuer, "OIDC provider used before discovery");
+                return self.issuer.clone();
+            }
+        };
+        let (url, _) = discovered
+            .client
+            .authorize_url(|| csrf_token)
+            .add_scope(Scope::new("openid".to_string()))
+            .add_scopes(self.options.oidc_scopes.iter().cloned().map(Scope::new))
+            .url();
+        url.to_string()
+    }
+
+    fn health_url(&self) -> &str {
+        &self.discovery_url
+    }
+
+    async fn authenticate(
+        &self,
+        code: String,
+        _nonce: Option<&str>,
+        http_client: &reqwest::Client,
+    ) -> Result<(Identity, Evidence), AuthErrorPayload> {
+        let discovered = self
+            .metadata
+            .get()
+            .ok_or(AuthErrorPayload::ProviderUnavailable)?;
+        let token = discovered
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| {
+                token_exchange_error(&e).into_auth_error(AuthErrorPayload::InvalidAuthCode)
+            })?;
+
+        let claims = with_retries(&self.lookup, self.name(), || async {
+            let response = http_client
+                .get(&discovered.userinfo_endpoint)
+                .bearer_auth(token.access_token().secret())
+                .send()
+                .await
+                .map_err(|e| LookupError::from_request(&e))?;
+            if !response.status().is_success() {
+                return Err(LookupError::from_status(response.status()));
+            }
+            response
+                .json::<Map<String, Value>>()
+                .await
+                .map_err(|e| LookupError::Invalid(e.to_string()))
+        })
+        .await
+        .map_err(|e| e.into_auth_error(AuthErrorPayload::CouldNotExtractUserData))?;
+        let subject = self
+            .subject(&claims)
+            .ok_or(AuthErrorPayload::CouldNotExtractUserData)?;
+        let identity = Identity::Oidc {
+            issuer: self.issuer.clone(),
+            subject,
+        };
+        Ok((identity, Evidence {
+            account_created_at: None,
+            nonce:              None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider(uid_claim: &str) -> OidcProvider {
+        let options = OidcAuthOptions::parse_from([
+            "oidc",
+            "--oidc-issuer",
+            "https://id.example.org/realms/dao/",
+            "--oidc-client-id",
+            "INVALID",
+            "--oidc-client-secret",
+            "INVALID",
+            "--oidc-uid-claim",
+            uid_claim,
+        ]);
+        let lookup = ProviderOptions::parse_from(["lookup"]);
+        OidcProvider::new(&options, &lookup).unwrap()
+    }
+
+    #[test]
+    fn maps_claims_to_subjects() {
+        let provider = provider("preferred_username");
+        assert_eq!(
+            provider.discovery_url,
+            "https://id.example.org/realms/dao/.well-known/openid-configuration"
+        );
+        let claims = |value: Value| match json!({ "sub": "1234", "preferred_username": value }) {
+            Value::Object(claims) => claims,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            provider.subject(&claims(json!("alice"))).as_deref(),
+            Some("alice")
+        );
+        assert_eq!(provider.subject(&claims(json!(42))).as_deref(), Some("42"));
+        for invalid in [json!(""), json!("a|b"), json!(null), json!(["alice"])] {
+            assert_eq!(provider.subject(&claims(invalid)), None);
+        }
+        assert_eq!(
+            self::provider("sub")
+                .subject(&claims(json!("alice")))
+                .as_deref(),
+            Some("1234")
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use super::AuthProvider;
+use crate::{api::v1::auth::AuthErrorPayload, eligibility::Evidence};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use oauth2::CsrfToken;
+use sha2::{Digest, Sha256};
+use std::time::UNIX_EPOCH;
+use url::Url;
+use uuid::Uuid;
+
+/// Longest token accepted as a test account name.
+const MAX_TOKEN_LEN: usize = 64;
+
+/// Signs participants in without an identity provider, in `--mode test`.
+///
+/// The authorization code is a token of the participant's choice and every
+/// token is its own test account. Accounts are Github identities with the
+/// token as username, so transcripts keep their usual format. The link
+/// returned by `/auth/request_link` points straight at the callback with a
+/// fresh random token.
+pub struct StaticTokenProvider {
+    callback_url: Url,
+}
+
+impl StaticTokenProvider {
+    #[must_use]
+    pub fn new(server: &Url) -> Self {
+        // The server url is a directory, so the callback is joined below it.
+        let callback_url = server
+            .join("auth/callback/test")
+            .expect("relative path joins");
+        Self { callback_url }
+    }
+
+    /// The identity of the test account of `token`.
+    #[must_use]
+    pub fn identity(token: &str) -> Identity {
+        let hash = Sha256::digest(token.as_bytes());
+        let mut id = [0_u8; 8];
+        id.copy_from_slice(&hash[..8]);
+        Identity::Github {
+            id:       u64::from_be_bytes(id),
+            username: token.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for StaticTokenProvider {
+    fn name(&self) -> &'static str {
+        "test"
+    }
+
+    fn authorize_url(&self, csrf_token: CsrfToken, _nonce: Option<&str>) -> String {
+        let mut url = self.callback_url.clone();
+        url.query_pairs_mut()
+            .append_pair("code", &Uuid::new_v4().simple().to_string())
+            .append_pair("state", csrf_token.secret());
+        url.to_string()
+    }
+
+    fn health_url(&self) -> &str {
+        self.callback_url.as_str()
+    }
+
+    async fn authenticate(
+        &self,
+        code: String,
+        _nonce: Option<&str>,
+        _http_client: &reqwest::Client,
+    ) -> Result<(Identity, Evidence), AuthErrorPayload> {
+        let valid = !code.is_empty()
+            && code.len() <= MAX_TOKEN_LEN
+            && code
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(AuthErrorPayload::InvalidAuthCode);
+        }
+        let identity = Self::identity(&code);
+        let evidence = Evidence {
+            account_created_at: Some(DateTime::<Utc>::from(UNIX_EPOCH)),
+            nonce:              None,
+        };
+        Ok((identity, evidence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn signs_in_with_tokens() {
+        let provider = StaticTokenProvider::new(&"http://127.0.0.1:3000/".parse().unwrap());
+        let url = provider.authorize_url(CsrfToken::new("state".to_string()), None);
+        assert!(url.starts_with("http://127.0.0.1:3000/auth/callback/test?code="));
+        assert!(url.ends_with("&state=state"));
+
+        let client = reqwest::Client::new();
+        let (alice, _) = provider
+            .authenticate("alice".to_string(), None, &client)
+            .await
+            .unwrap();
+        let (again, _) = provider
+            .authenticate("alice".to_string(), None, &client)
+            .await
+            .unwrap();
+        let (bob, _) = provider
+            .authenticate("bob".to_string(), None, &client)
+            .await
+            .unwrap();
+        assert_eq!(alice, again);
+        assert_ne!(alice, bob);
+        assert_eq!(alice.nickname(), "alice");
+
+        for code in ["", "git|1|alice", &"a".repeat(65)] {
+            assert!(matches!(
+                provider.authenticate(code.to_string(), None, &client).await,
+                Err(AuthErrorPayload::InvalidAuthCode)
+            ));
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Time-sliced ceremony phases with their own eligibility.
+//!
+//! `--phases-file` is a TOML file with a list of phases:
+//!
+//! ```toml
+//! [[phase]]
+//! name = "early"
+//! start = "2022-12-01T00:00:00Z"
+//! end = "2022-12-08T00:00:00Z"
+//! providers = ["ethereum"]
+//! addresses_file = "early.txt"
+//!
+//! [[phase]]
+//! name = "open"
+//! start = "2022-12-08T00:00:00Z"
+//! ```
+//!
+//! Without the file anyone may take part at any time. With it, participants
+//! may only sign in and join the lobby while a phase they are eligible for is
+//! open, from `start` up to but excluding `end`; a phase without `end` stays
+//! open. `providers` limits a phase to some auth providers, and `addresses`
+//! and `addresses_file` (one address per line, relative to the phases file)
+//! limit it to some Ethereum addresses. Participants that are already in the
+//! lobby when their phase ends keep their place. On SIGHUP the file is read
+//! again; a file that fails to parse keeps the previous phases.
+
+use crate::reload_signal::{self, ReloadSignal};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use eyre::{bail, eyre, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use tracing::{error, info};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// TOML file with the participation phases of the ceremony.
+    #[clap(long, env)]
+    pub phases_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Github,
+    Ethereum,
+    Discord,
+    Oidc,
+}
+
+impl Provider {
+    const fn of(identity: &Identity) -> Option<Self> {
+        match identity {
+            Identity::Github { .. } => Some(Self::Github),
+            Identity::Ethereum { .. } => Some(Self::Ethereum),
+            Identity::Discord { .. } => Some(Self::Discord),
+            Identity::Oidc { .. } => Some(Self::Oidc),
+            Identity::None => None,
+        }
+    }
+}
+
+/// A phase as written in the phases file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PhaseConfig {
+    name:           String,
+    start:          DateTime<Utc>,
+    end:            Option<DateTime<Utc>>,
+    providers:      Option<Vec<Provider>>,
+    addresses:      Option<Vec<String>>,
+    addresses_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScheduleConfig {
+    #[serde(default)]
+    phase: Vec<PhaseConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Phase {
+    pub name:  String,
+    pub start: DateTime<Utc>,
+    pub end:   Option<DateTime<Utc>>,
+    /// Auth providers admitted, all if `None`.
+    providers: Option<Vec<Provider>>,
+    /// Ethereum addresses admitted, all participants if `None`.
+    addresses: Option<HashSet<[u8; 20]>>,
+}
+
+impl Phase {
+    fn from_config(config: PhaseConfig, base: &Path) -> EyreResult<Self> {
+        if config.end.map_or(false, |end| end <= config.start) {
+            bail!("phase ends before it starts");
+        }
+        let mut addresses = config.addresses;
+        if let Some(file) = &config.addresses_file {
+            let path = base.join(file);
+            let contents = std::fs::read_to_string(&path)
+                .wrap_err_with(|| format!("failed to read addresses {}", path.display()))?;
+            addresses.get_or_insert_with(Vec::new).extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string),
+            );
+        }
+        let allowed = addresses
+            .map(|addresses| {
+                addresses
+                    .iter()
+                    .map(|address| match Identity::eth_from_str(address) {
+                        Ok(Identity::Ethereum { address }) => Ok(address),
+                        _ => Err(eyre!("invalid address {address}")),
+                    })
+                    .collect::<EyreResult<HashSet<_>>>()
+            })
+            .transpose()?;
+        Ok(Self {
+            name:      config.name,
+            start:     config.start,
+            end:       config.end,
+            providers: config.providers,
+            addresses: allowed,
+        })
+    }
+
+    #[must_use]
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        self.start <= now && self.end.map_or(true, |end| now < end)
+    }
+
+    #[must_use]
+    pub fn admits(&self, identity: &Identity) -> bool {
+        if let Some(providers) = &self.providers {
+            if !Provider::of(identity).map_or(false, |provider| providers.contains(&provider)) {
+                return false;
+            }
+        }
+        match (&self.addresses, identity) {
+            (None, _) => true,
+            (Some(addresses), Identity::Ethereum { address }) => addresses.contains(address),
+            (Some(_), _) => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhaseViolation {
+    /// No phase is open.
+    Closed,
+    /// The open phases do not admit the participant.
+    NotEligible,
+}
+
+/// The phases of the ceremony. Without phases participation is not
+/// restricted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schedule {
+    phases: Vec<Phase>,
+}
+
+impl Schedule {
+    /// # Errors
+    ///
+    /// Returns an error if the file or an address file can not be read, or if
+    /// they are not valid.
+    pub fn from_file(path: &Path) -> EyreResult<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read phases {}", path.display()))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&contents, base)
+            .wrap_err_with(|| format!("invalid phases in {}", path.display()))
+    }
+
+    fn parse(contents: &str, base: &Path) -> EyreResult<Self> {
+        let config: ScheduleConfig = toml::from_str(contents)?;
+        let phases = config
+            .phase
+            .into_iter()
+            .map(|phase| {
+                let name = phase.name.clone();
+                Phase::from_config(phase, base).wrap_err_with(|| format!("phase {name}"))
+            })
+            .collect::<EyreResult<_>>()?;
+        Ok(Self { phases })
+    }
+
+    /// Phases open at `now`.
+    pub fn open(&self, now: DateTime<Utc>) -> impl Iterator<Item = &Phase> {
+        self.phases.iter().filter(move |phase| phase.is_open(now))
+    }
+
+    /// Checks that a phase open at `now` admits the participant.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the participant may not take part now.
+    pub fn check(&self, identity: &Identity, now: DateTime<Utc>) -> Result<(), PhaseViolation> {
+        if self.phases.is_empty() {
+            return Ok(());
+        }
+        let mut open = self.open(now).peekable();
+        if open.peek().is_none() {
+            return Err(PhaseViolation::Closed);
+        }
+        if open.any(|phase| phase.admits(identity)) {
+            Ok(())
+        } else {
+            Err(PhaseViolation::NotEligible)
+        }
+    }
+}
+
+pub type SharedSchedule = Arc<ScheduleHandle>;
+
+/// The current schedule, replaced as a whole on reload.
+#[derive(Debug, Default)]
+pub struct ScheduleHandle {
+    path:     Option<PathBuf>,
+    schedule: RwLock<Arc<Schedule>>,
+}
+
+impl ScheduleHandle {
+    /// Reads `--phases-file`, without it participation is not restricted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not valid.
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        let schedule = match &options.phases_file {
+            Some(path) => Schedule::from_file(path)?,
+            None => Schedule::default(),
+        };
+        Ok(Self {
+            path:     options.phases_file.clone(),
+            schedule: RwLock::new(Arc::new(schedule)),
+        })
+    }
+
+    #[must_use]
+    pub fn current(&self) -> Arc<Schedule> {
+        self.schedule.read().unwrap().clone()
+    }
+
+    /// Reads the phases file again. The current phases are kept if that
+    /// fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not valid.
+    pub fn reload(&self) -> EyreResult<()> {
+        if let Some(path) = &self.path {
+            let schedule = Schedule::from_file(path)?;
+            *self.schedule.write().unwrap() = Arc::new(schedule);
+        }
+        Ok(())
+    }
+}
+
+/// Reloads the phases on every reload signal.
+pub async fn reload_on_signal(schedule: SharedSchedule) {
+    if schedule.path.is_none() {
+        return;
+    }
+    let mut signals = match ReloadSignal::new() {
+        Ok(signals) => signals,
+        Err(error) => {
+            error!(
+                ?error,
+                "Failed to listen for {}, phases will not be reloaded",
+                reload_signal::NAME
+            );
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        match schedule.reload() {
+            Ok(()) => info!("Reloaded phases"),
+            Err(error) => error!(?error, "Failed to reload phases"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const ALICE: &str = "0x00000000000000000000000000000000000000a1";
+    const BOB: &str = "0x00000000000000000000000000000000000000b0";
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn checks_phases() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("early.txt"), format!("# early\n{ALICE}\n")).unwrap();
+        std::fs::write(
+            dir.path().join("phases.toml"),
+            r#"
+            [[phase]]
+            name = "early"
+            start = "2022-12-01T00:00:00Z"
+            end = "2022-12-08T00:00:00Z"
+            providers = ["ethereum"]
+            addresses_file = "early.txt"
+
+            [[phase]]
+            name = "github"
+            start = "2022-12-08T00:00:00Z"
+            end = "2022-12-15T00:00:00Z"
+            providers = ["github"]
+
+            [[phase]]
+            name = "open"
+            start = "2022-12-20T00:00:00Z"
+            "#,
+        )
+        .unwrap();
+        let schedule = Schedule::from_file(&dir.path().join("phases.toml")).unwrap();
+        let alice = Identity::eth_from_str(ALICE).unwrap();
+        let bob = Identity::eth_from_str(BOB).unwrap();
+        let github = Identity::Github {
+            id:       1,
+            username: "test_user".to_string(),
+        };
+
+        let early = at("2022-12-01T00:00:00Z");
+        assert_eq!(schedule.check(&alice, early), Ok(()));
+        assert_eq!(
+            schedule.check(&bob, early),
+            Err(PhaseViolation::NotEligible)
+        );
+        assert_eq!(
+            schedule.check(&github, early),
+            Err(PhaseViolation::NotEligible)
+        );
+
+        let second = at("2022-12-08T00:00:00Z");
+        assert_eq!(
+            schedule.check(&alice, second),
+            Err(PhaseViolation::NotEligible)
+        );
+        assert_eq!(schedule.check(&github, second), Ok(()));
+
+        let gap = at("2022-12-16T00:00:00Z");
+        assert_eq!(schedule.check(&github, gap), Err(PhaseViolation::Closed));
+        assert_eq!(
+            schedule.check(&bob, at("2022-11-01T00:00:00Z")),
+            Err(PhaseViolation::Closed)
+        );
+
+        let open = at("2030-01-01T00:00:00Z");
+        assert_eq!(schedule.check(&bob, open), Ok(()));
+        assert_eq!(schedule.check(&github, open), Ok(()));
+        assert_eq!(
+            schedule
+                .open(open)
+                .map(|phase| phase.name.as_str())
+                .collect::<Vec<_>>(),
+            ["open"]
+        );
+
+        assert_eq!(Schedule::default().check(&bob, gap), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_phases() {
+        let base = Path::new("");
+        let phase = |body: &str| Schedule::parse(&format!("[[phase]]\nname = \"a\"\n{body}"), base);
+        assert!(phase("start = \"2022-12-01T00:00:00Z\"").is_ok());
+        assert!(phase("start = \"2022-12-01T00:00:00Z\"\nend = \"2022-12-01T00:00:00Z\"").is_err());
+        assert!(phase("start = \"2022-12-01T00:00:00Z\"\nproviders = [\"twitter\"]").is_err());
+        assert!(phase("start = \"2022-12-01T00:00:00Z\"\naddresses = [\"0x1234\"]").is_err());
+        assert!(phase("start = \"2022-12-01T00:00:00Z\"\nuids = []").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Power saving while the lobby is empty.
+//!
+//! Community ceremonies may run for months with contributions hours apart.
+//! With `--idle-after <minutes>` the sequencer idles once the slot has been
+//! free and the lobby empty for that long: contributions are verified on
+//! `--idle-verification-threads` threads instead of one per core, and the
+//! lobby flush and the garbage collection run `--idle-poll-factor` times less
+//! often. The first participant to join the lobby wakes it up again, before
+//! they can get the slot.
+
+use crate::{lobby::SharedLobbyState, verification::SharedVerificationQueue};
+use clap::Parser;
+use std::{
+    num::ParseIntError,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{sync::broadcast::error::RecvError, time::Instant};
+use tracing::{error, info};
+
+fn duration_from_minutes(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)? * 60))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Minutes the lobby has to be empty before the sequencer idles. 0 never
+    /// idles.
+    #[clap(long, env, value_parser = duration_from_minutes, default_value = "0")]
+    pub idle_after: Duration,
+
+    /// Number of threads contributions are verified on while idle.
+    #[clap(long, env, default_value = "1")]
+    pub idle_verification_threads: usize,
+
+    /// How many times less often the lobby is flushed and sessions are
+    /// collected while idle.
+    #[clap(long, env, default_value = "4")]
+    pub idle_poll_factor: u32,
+}
+
+pub type SharedPowerSaving = Arc<PowerSaving>;
+
+#[derive(Debug)]
+pub struct PowerSaving {
+    options: Options,
+    idle:    AtomicBool,
+}
+
+impl PowerSaving {
+    #[must_use]
+    pub const fn new(options: Options) -> Self {
+        Self {
+            options,
+            idle: AtomicBool::new(false),
+        }
+    }
+
+    #[must_use]
+    pub fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::Relaxed)
+    }
+
+    /// `interval`, stretched by `--idle-poll-factor` while idle.
+    #[must_use]
+    pub fn poll_interval(&self, interval: Duration) -> Duration {
+        if self.is_idle() {
+            interval.saturating_mul(self.options.idle_poll_factor.max(1))
+        } else {
+            interval
+        }
+    }
+
+    /// Idles or wakes up, and resizes the verification pool to match.
+    fn set_idle(&self, idle: bool, queue: &SharedVerificationQueue) {
+        if self.idle.swap(idle, Ordering::Relaxed) == idle {
+            return;
+        }
+        let threads = idle.then_some(self.options.idle_verification_threads);
+        if let Err(error) = queue.resize_pool(threads) {
+            error!(?error, "failed to resize the verification pool");
+        }
+        let threads = queue.pool().threads();
+        if idle {
+            info!(threads, "Lobby is empty, idling");
+        } else {
+            info!(threads, "Lobby is active, waking up");
+        }
+    }
+}
+
+/// Idles once the lobby has been quiet for `--idle-after`, and wakes up on
+/// the first lobby event that finds it busy.
+pub async fn idle_when_quiet(
+    power_saving: SharedPowerSaving,
+    lobby_state: SharedLobbyState,
+    queue: SharedVerificationQueue,
+) {
+    let idle_after = power_saving.options.idle_after;
+    if idle_after.is_zero() {
+        return;
+    }
+    let mut events = lobby_state.subscribe();
+    let mut busy_at = Instant::now();
+    loop {
+        if lobby_state.is_quiet().await {
+            if busy_at.elapsed() >= idle_after {
+                power_saving.set_idle(true, &queue);
+            }
+        } else {
+            busy_at = Instant::now();
+            power_saving.set_idle(false, &queue);
+        }
+        let wake = (busy_at + idle_after).max(Instant::now() + idle_after / 2);
+        tokio::select! {
+            event = events.recv() => {
+                if matches!(event, Err(RecvError::Closed)) {
+                    return;
+                }
+            }
+            () = tokio::time::sleep_until(wake) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sessions::SessionId,
+        test_util::{create_test_session_info, test_options},
+        verification::VerificationQueue,
+    };
+
+    #[tokio::test]
+    async fn idles_while_the_lobby_is_quiet() {
+        let options = test_options();
+        let lobby_state = SharedLobbyState::new(options.lobby.clone());
+        let queue = Arc::new(VerificationQueue::new(&options.verification));
+        let power_saving = Arc::new(PowerSaving::new(Options {
+            idle_after:                Duration::from_secs(60),
+            idle_verification_threads: 1,
+            idle_poll_factor:          4,
+        }));
+        tokio::time::pause();
+        tokio::spawn(idle_when_quiet(
+            power_saving.clone(),
+            lobby_state.clone(),
+            queue.clone(),
+        ));
+        tokio::time::sleep(Duration::from_secs(61)).await;
+        assert!(power_saving.is_idle());
+        assert_eq!(queue.pool().threads(), 1);
+        assert_eq!(
+            power_saving.poll_interval(Duration::from_secs(5)),
+            Duration::from_secs(20)
+        );
+
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&session_id).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert!(!power_saving.is_idle());
+        assert_eq!(queue.pool().threads(), rayon::current_num_threads());
+    }
+}
@@ -0,0 +1,428 @@
+//! Hashcash style proof of work for joining the lobby during spam waves.
+//!
+//! When `/lobby/try_contribute` gets more than `--pow-threshold` calls a
+//! minute, sessions only join the lobby with a solved challenge. The challenge
+//! is sent in the `X-Pow-Challenge` header of the `SEQ-LOBBY-011` response as
+//! `<difficulty>.<expires_at>.<tag>`, where the tag ties it to the session.
+//! A solution is `<challenge>:<nonce>`, for any nonce that makes the SHA-256
+//! of the solution start with `<difficulty>` zero bits, and is sent back in
+//! the `X-Pow-Solution` header. Participants already in the lobby are not
+//! asked again, so the work is done once per session.
+
+use crate::{metrics::PROOFS_OF_WORK, SessionId};
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+    Extension,
+};
+use chrono::Utc;
+use clap::Parser;
+use hmac::{Hmac, Mac};
+use kzg_ceremony_crypto::ErrorCode;
+use rand::{thread_rng, RngCore};
+use sha2::{Digest, Sha256};
+use std::{
+    convert::Infallible,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::time::Instant;
+
+pub const POW_CHALLENGE_HEADER: &str = "x-pow-challenge";
+pub const POW_SOLUTION_HEADER: &str = "x-pow-solution";
+
+/// Longest nonce that is checked.
+const MAX_NONCE_LENGTH: usize = 64;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Calls to `/lobby/try_contribute` per minute, from all clients
+    /// together, above which joining the lobby takes a proof of work. 0
+    /// disables proofs of work.
+    #[clap(long, env, default_value = "0")]
+    pub pow_threshold: u64,
+
+    /// Leading zero bits the hash of a solution needs. Each bit doubles the
+    /// expected work, 20 bits take about a second in a browser.
+    #[clap(long, env, default_value = "20")]
+    pub pow_difficulty: u32,
+
+    /// Time to solve a challenge in (in seconds).
+    #[clap(long, env, value_parser=duration_from_secs, default_value="300")]
+    pub pow_challenge_ttl: Duration,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            pow_threshold:     0,
+            pow_difficulty:    20,
+            pow_challenge_ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+fn duration_from_secs(s: &str) -> Result<Duration, std::num::ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(s)?))
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ProofOfWorkError {
+    #[error("joining the lobby takes a proof of work, solve the challenge in X-Pow-Challenge")]
+    Required { challenge: String },
+    #[error("invalid proof of work: {reason}, solve the challenge in X-Pow-Challenge")]
+    Invalid {
+        reason:    InvalidSolution,
+        challenge: String,
+    },
+}
+
+impl ErrorCode for ProofOfWorkError {
+    fn to_error_code(&self) -> String {
+        format!("ProofOfWorkError::{}", <&str>::from(self))
+    }
+}
+
+impl ProofOfWorkError {
+    /// The fresh challenge to solve.
+    #[must_use]
+    pub fn challenge(&self) -> &str {
+        match self {
+            Self::Required { challenge } | Self::Invalid { challenge, .. } => challenge,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum InvalidSolution {
+    #[error("malformed solution")]
+    Malformed,
+    #[error("the challenge is not for this session")]
+    WrongChallenge,
+    #[error("the challenge expired")]
+    Expired,
+    #[error("the hash has too few leading zero bits")]
+    TooFewZeros,
+}
+
+/// Calls in the current and the previous window, to estimate the calls of
+/// the last minute.
+struct Window {
+    started:  Instant,
+    current:  u64,
+    previous: u64,
+}
+
+pub type SharedProofOfWork = Arc<ProofOfWork>;
+
+/// Tracks the rate of `/lobby/try_contribute` calls, and issues and checks
+/// the challenges with a key generated on startup.
+pub struct ProofOfWork {
+    options: Options,
+    key:     [u8; 32],
+    window:  Mutex<Window>,
+}
+
+impl Default for ProofOfWork {
+    fn default() -> Self {
+        Self::new(Options::default())
+    }
+}
+
+impl ProofOfWork {
+    #[must_use]
+    pub fn new(options: Options) -> Self {
+        let mut key = [0; 32];
+        thread_rng().fill_bytes(&mut key);
+        Self {
+            options,
+            key,
+            window: Mutex::new(Window {
+                started:  Instant::now(),
+                current:  0,
+                previous: 0,
+            }),
+        }
+    }
+
+    /// Counts a call and returns whether the threshold is crossed.
+    #[allow(clippy::cast_precision_loss)]
+    fn record_call(&self) -> bool {
+        if self.options.pow_threshold == 0 {
+            return false;
+        }
+        let mut window = self.window.lock().unwrap();
+        let mut elapsed = window.started.elapsed();
+        if elapsed >= 2 * WINDOW {
+            *window = Window {
+                started:  Instant::now(),
+                current:  0,
+                previous: 0,
+            };
+            elapsed = Duration::ZERO;
+        } else if elapsed >= WINDOW {
+            window.previous = window.current;
+            window.current = 0;
+            window.started += WINDOW;
+            elapsed -= WINDOW;
+        }
+        window.current += 1;
+        // The previous window is weighted by how much of it is still within
+        // the last minute.
+        let remaining = 1.0 - elapsed.as_secs_f64() / WINDOW.as_secs_f64();
+        let rate = (window.previous as f64).mul_add(remaining, window.current as f64);
+        rate > self.options.pow_threshold as f64
+    }
+
+    fn tag(&self, session_id: &SessionId, difficulty: u32, expires_at: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("any key size is valid");
+        mac.update(format!("{}.{difficulty}.{expires_at}", session_id.hash()).as_bytes());
+        mac
+    }
+
+    /// A new challenge for `session_id`.
+    #[must_use]
+    pub fn challenge(&self, session_id: &SessionId) -> String {
+        let difficulty = self.options.pow_difficulty;
+        let valid_for = i64::try_from(self.options.pow_challenge_ttl.as_secs()).unwrap_or(i64::MAX);
+        let expires_at = Utc::now().timestamp().saturating_add(valid_for);
+        let tag = self
+            .tag(session_id, difficulty, expires_at)
+            .finalize()
+            .into_bytes();
+        format!("{difficulty}.{expires_at}.{}", hex::encode(tag))
+    }
+
+    /// Checks a solution of a challenge issued to `session_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the challenge was not issued to the session by
+    /// this sequencer, has expired, or is not solved.
+    pub fn verify(&self, session_id: &SessionId, solution: &str) -> Result<(), InvalidSolution> {
+        let (challenge, nonce) = solution
+            .rsplit_once(':')
+            .ok_or(InvalidSolution::Malformed)?;
+        if nonce.len() > MAX_NONCE_LENGTH {
+            return Err(InvalidSolution::Malformed);
+        }
+        let parts = challenge.split('.').collect::<Vec<_>>();
+        let (difficulty, expires_at, tag) = match parts.as_slice() {
+            [difficulty, expires_at, tag] => (
+                difficulty.parse::<u32>(),
+                expires_at.parse::<i64>(),
+                hex::decode(tag),
+            ),
+            _ => return Err(InvalidSolution::Malformed),
+        };
+        let (difficulty, expires_at, tag) = match (difficulty, expires_at, tag) {
+            (Ok(difficulty), Ok(expires_at), Ok(tag)) => (difficulty, expires_at, tag),
+            _ => return Err(InvalidSolution::Malformed),
+        };
+        self.tag(session_id, difficulty, expires_at)
+            .verify_slice(&tag)
+            .map_err(|_| InvalidSolution::WrongChallenge)?;
+        if expires_at < Utc::now().timestamp() {
+            return Err(InvalidSolution::Expired);
+        }
+        if leading_zeros(&Sha256::digest(solution.as_bytes())) < difficulty {
+            return Err(InvalidSolution::TooFewZeros);
+        }
+        Ok(())
+    }
+}
+
+pub fn leading_zeros(hash: &[u8]) -> u32 {
+    let mut zeros = 0;
+    for byte in hash {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+/// Extractor counting `/lobby/try_contribute` calls towards the threshold,
+/// with the `X-Pow-Solution` header of the call.
+#[derive(Clone, Default)]
+pub struct PowSolution {
+    /// Set if the threshold is crossed.
+    pow:      Option<SharedProofOfWork>,
+    solution: Option<String>,
+}
+
+impl PowSolution {
+    /// Checks the solution of `session_id` if a proof of work is required.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error with a fresh challenge if the solution is missing or
+    /// invalid.
+    pub fn check(&self, session_id: &SessionId) -> Result<(), ProofOfWorkError> {
+        let pow = match &self.pow {
+            Some(pow) => pow,
+            None => return Ok(()),
+        };
+        let result = match &self.solution {
+            Some(solution) => {
+                pow.verify(session_id, solution)
+                    .map_err(|reason| ProofOfWorkError::Invalid {
+                        reason,
+                        challenge: pow.challenge(session_id),
+                    })
+            }
+            None => Err(ProofOfWorkError::Required {
+                challenge: pow.challenge(session_id),
+            }),
+        };
+        let label = match &result {
+            Ok(()) => "valid",
+            Err(ProofOfWorkError::Required { .. }) => "missing",
+            Err(ProofOfWorkError::Invalid { .. }) => "invalid",
+        };
+        PROOFS_OF_WORK.with_label_values(&[label]).inc();
+        result
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for PowSolution
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let pow = match Extension::<SharedProofOfWork>::from_request(req).await {
+            Ok(Extension(pow)) => pow,
+            Err(_) => return Ok(Self::default()),
+        };
+        if !pow.record_call() {
+            return Ok(Self::default());
+        }
+        let solution = req
+            .headers()
+            .get(POW_SOLUTION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string);
+        Ok(Self {
+            pow: Some(pow),
+            solution,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(challenge: &str) -> String {
+        (0_u64..)
+            .map(|nonce| format!("{challenge}:{nonce}"))
+            .find(|solution| {
+                leading_zeros(&Sha256::digest(solution.as_bytes()))
+                    >= challenge.split('.').next().unwrap().parse().unwrap()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn verifies_solutions() {
+        let pow = ProofOfWork::new(Options {
+            pow_difficulty: 8,
+            ..Options::default()
+        });
+        let session_id = SessionId::new();
+        let challenge = pow.challenge(&session_id);
+        let solution = solve(&challenge);
+        assert_eq!(pow.verify(&session_id, &solution), Ok(()));
+
+        assert_eq!(
+            pow.verify(&SessionId::new(), &solution),
+            Err(InvalidSolution::WrongChallenge)
+        );
+        assert_eq!(
+            ProofOfWork::default().verify(&session_id, &solution),
+            Err(InvalidSolution::WrongChallenge)
+        );
+        let unsolved = (0_u64..)
+            .map(|nonce| format!("{challenge}:{nonce}"))
+            .find(|solution| Sha256::digest(solution.as_bytes())[0] != 0)
+            .unwrap();
+        assert_eq!(
+            pow.verify(&session_id, &unsolved),
+            Err(InvalidSolution::TooFewZeros)
+        );
+        assert_eq!(
+            pow.verify(&session_id, "garbage"),
+            Err(InvalidSolution::Malformed)
+        );
+        let easier = challenge.replacen("8.", "0.", 1);
+        assert_eq!(
+            pow.verify(&session_id, &format!("{easier}:0")),
+            Err(InvalidSolution::WrongChallenge)
+        );
+
+        let expires_at = Utc::now().timestamp() - 1;
+        let tag = pow.tag(&session_id, 0, expires_at).finalize().into_bytes();
+        let stale = format!("0.{expires_at}.{}:0", hex::encode(tag));
+        assert_eq!(
+            pow.verify(&session_id, &stale),
+            Err(InvalidSolution::Expired)
+        );
+    }
+
+    #[test]
+    fn gates_sessions() {
+        let pow = Arc::new(ProofOfWork::new(Options {
+            pow_difficulty: 4,
+            ..Options::default()
+        }));
+        let session_id = SessionId::new();
+        assert!(PowSolution::default().check(&session_id).is_ok());
+
+        let gate = |solution: Option<String>| PowSolution {
+            pow: Some(pow.clone()),
+            solution,
+        };
+        let challenge = match gate(None).check(&session_id) {
+            Err(ProofOfWorkError::Required { challenge }) => challenge,
+            other => panic!("expected a challenge, got {other:?}"),
+        };
+        assert!(gate(Some(solve(&challenge))).check(&session_id).is_ok());
+        assert!(matches!(
+            gate(Some(solve(&challenge))).check(&SessionId::new()),
+            Err(ProofOfWorkError::Invalid {
+                reason: InvalidSolution::WrongChallenge,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn requires_work_above_threshold() {
+        tokio::time::pause();
+        let pow = ProofOfWork::new(Options {
+            pow_threshold: 3,
+            ..Options::default()
+        });
+        assert!(!(0..3).any(|_| pow.record_call()));
+        assert!(pow.record_call());
+
+        // Half of the previous minute still counts.
+        tokio::time::advance(Duration::from_secs(90)).await;
+        assert!(!pow.record_call());
+        assert!(pow.record_call());
+
+        tokio::time::advance(Duration::from_secs(120)).await;
+        assert!(!pow.record_call());
+        assert!(!ProofOfWork::default().record_call());
+    }
+}
@@ -0,0 +1,186 @@
+//! Capture of contributions that fail verification.
+//!
+//! With `--capture-rejections <dir>`, every contribution that fails
+//! verification is written to the directory as a gzip compressed JSON file,
+//! with the uid of the contributor, the time, the payload hash and the error.
+//! Payloads larger than `--capture-max-size` bytes of JSON are captured
+//! without the contribution, so that a flood of large submissions can not
+//! fill the disk. Other expired slots have no payload and are only recorded
+//! in the `contributors` table.
+
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use flate2::{write::GzEncoder, Compression};
+use kzg_ceremony_crypto::BatchContribution;
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+use tracing::{error, info};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Directory to keep contributions that fail verification in, for later
+    /// analysis. Disabled by default.
+    #[clap(long, env)]
+    pub capture_rejections: Option<PathBuf>,
+
+    /// Largest contribution, in bytes of JSON, that is captured with its
+    /// payload. Larger ones are captured without it.
+    #[clap(long, env, default_value = "33554432")]
+    pub capture_max_size: usize,
+}
+
+/// Why a contribution was rejected, as reported in
+/// `/contribute/status/:id`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Rejection {
+    pub uid:          String,
+    /// See [`crate::storage::StoredVerification::payload_hash`].
+    pub payload_hash: String,
+    pub code:         String,
+    pub kind:         String,
+    pub error:        String,
+}
+
+#[derive(Serialize)]
+struct Capture<'a> {
+    #[serde(flatten)]
+    rejection:    &'a Rejection,
+    rejected_at:  DateTime<Utc>,
+    payload_size: usize,
+    /// `None` if the payload is larger than `--capture-max-size`.
+    contribution: Option<&'a BatchContribution>,
+}
+
+pub type SharedQuarantine = Arc<Quarantine>;
+
+#[derive(Debug, Default)]
+pub struct Quarantine {
+    dir:      Option<PathBuf>,
+    max_size: usize,
+}
+
+impl Quarantine {
+    #[must_use]
+    pub fn new(options: &Options) -> Self {
+        Self {
+            dir:      options.capture_rejections.clone(),
+            max_size: options.capture_max_size,
+        }
+    }
+
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// Writes the rejected contribution in the background. Failures are only
+    /// logged.
+    pub fn capture(self: &Arc<Self>, rejection: Rejection, contribution: BatchContribution) {
+        if !self.is_enabled() {
+            return;
+        }
+        let quarantine = self.clone();
+        tokio::task::spawn_blocking(move || {
+            match quarantine.write(&rejection, &contribution, Utc::now()) {
+                Ok(path) => info!(path = %path.display(), "Captured rejected contribution"),
+                Err(error) => error!(?error, "failed to capture rejected contribution"),
+            }
+        });
+    }
+
+    fn write(
+        &self,
+        rejection: &Rejection,
+        contribution: &BatchContribution,
+        rejected_at: DateTime<Utc>,
+    ) -> io::Result<PathBuf> {
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "capture is disabled"))?;
+        let payload_size = serde_json::to_vec(contribution)?.len();
+        let capture = Capture {
+            rejection,
+            rejected_at,
+            payload_size,
+            contribution: (payload_size <= self.max_size).then_some(contribution),
+        };
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, &capture)?;
+        let contents = encoder.finish()?;
+
+        std::fs::create_dir_all(dir)?;
+        let hash_prefix = rejection.payload_hash.get(..16).unwrap_or("unknown");
+        let name = format!(
+            "{}-{hash_prefix}.json.gz",
+            rejected_at.format("%Y%m%dT%H%M%S%.3fZ")
+        );
+        let path = dir.join(name);
+        let work_path = path.with_extension("gz.next");
+        std::fs::write(&work_path, contents)?;
+        std::fs::rename(&work_path, &path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{invalid_contribution, test_transcript};
+    use flate2::read::GzDecoder;
+    use tempfile::TempDir;
+
+    fn rejection() -> Rejection {
+        Rejection {
+            uid:          "git|1234|test_user".to_string(),
+            payload_hash: "ab".repeat(32),
+            code:         "SEQ-CONTRIB-002".to_string(),
+            kind:         "CeremonyError::InvalidPubKey".to_string(),
+            error:        "invalid pubkey".to_string(),
+        }
+    }
+
+    fn read(path: &std::path::Path) -> serde_json::Value {
+        let file = std::fs::File::open(path).unwrap();
+        serde_json::from_reader(GzDecoder::new(file)).unwrap()
+    }
+
+    #[test]
+    fn captures_rejected_contributions() {
+        let dir = TempDir::new().unwrap();
+        let contribution = invalid_contribution(&test_transcript(), 1);
+        let quarantine = Quarantine::new(&Options {
+            capture_rejections: Some(dir.path().join("rejected")),
+            capture_max_size:   1 << 20,
+        });
+        let path = quarantine
+            .write(&rejection(), &contribution, Utc::now())
+            .unwrap();
+        assert!(path.starts_with(dir.path().join("rejected")));
+        let capture = read(&path);
+        assert_eq!(capture["uid"], "git|1234|test_user");
+        assert_eq!(capture["code"], "SEQ-CONTRIB-002");
+        assert_eq!(
+            serde_json::from_value::<BatchContribution>(capture["contribution"].clone()).unwrap(),
+            contribution
+        );
+
+        // Payloads above the cap are captured without the contribution.
+        let quarantine = Quarantine::new(&Options {
+            capture_rejections: Some(dir.path().join("rejected")),
+            capture_max_size:   16,
+        });
+        let capture = read(
+            &quarantine
+                .write(&rejection(), &contribution, Utc::now())
+                .unwrap(),
+        );
+        assert!(capture["contribution"].is_null());
+        assert!(capture["payload_size"].as_u64().unwrap() > 16);
+    }
+}
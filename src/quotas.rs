@@ -0,0 +1,315 @@
+//! Per-provider eligibility windows and contribution quotas.
+//!
+//! `--provider-rules-file` is a TOML file with an optional table per auth
+//! provider:
+//!
+//! ```toml
+//! [github]
+//! created_before = "2022-11-01T00:00:00Z"
+//! max_contributions = 5000
+//!
+//! [ethereum]
+//! min_nonce = 16
+//! max_contributions = 10000
+//! priority = 1
+//! ```
+//!
+//! The windows and quotas are checked when a participant authenticates. The
+//! quotas are enforced again when a participant takes the contribution slot,
+//! in the same statement that records the contributor, so concurrent
+//! sequencers sharing a database can not exceed them. The `priority` of a
+//! provider applies to the lobby, see [`crate::lobby::Candidate::priority`].
+//! On SIGHUP the file is read again; a file that fails to parse keeps the
+//! previous rules.
+
+use crate::{
+    eligibility::Evidence,
+    identity,
+    reload_signal::{self, ReloadSignal},
+};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use eyre::{Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::signature::identity::Identity;
+use serde::Deserialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use tracing::{error, info};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// TOML file with per-provider account windows and contribution quotas.
+    #[clap(long, env)]
+    pub provider_rules_file: Option<PathBuf>,
+}
+
+/// Rules for the participants of one auth provider.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderRules {
+    /// Accounts need to be created before this time.
+    pub created_before:    Option<DateTime<Utc>>,
+    /// Ethereum addresses need to have sent at least this many transactions.
+    pub min_nonce:         Option<u64>,
+    /// Maximum number of contributions by accounts of the provider. Expired
+    /// contributions do not count.
+    pub max_contributions: Option<usize>,
+    /// Lobby priority of the provider's participants. Higher goes first.
+    pub priority:          Option<u32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub github:   ProviderRules,
+    #[serde(default)]
+    pub ethereum: ProviderRules,
+    #[serde(default)]
+    pub discord:  ProviderRules,
+    #[serde(default)]
+    pub oidc:     ProviderRules,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleViolation {
+    CreatedAfterDeadline,
+    TooFewTransactions,
+}
+
+/// Cap on the contributions of the uids starting with `uid_prefix`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub uid_prefix:        &'static str,
+    pub max_contributions: usize,
+}
+
+/// Prefixes of the uids of each provider, see the `Display` implementation of
+/// [`Identity`].
+const GITHUB_PREFIX: &str = "git|";
+const ETHEREUM_PREFIX: &str = "eth|";
+const DISCORD_PREFIX: &str = "dsc|";
+const OIDC_PREFIX: &str = "oidc|";
+
+impl RuleSet {
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not a valid rule
+    /// set.
+    pub fn from_file(path: &Path) -> EyreResult<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read provider rules {}", path.display()))?;
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("invalid provider rules in {}", path.display()))
+    }
+
+    /// Rules and uid prefix of the provider of `uid`.
+    fn provider(&self, uid: &str) -> Option<(&ProviderRules, &'static str)> {
+        [
+            (&self.github, GITHUB_PREFIX),
+            (&self.ethereum, ETHEREUM_PREFIX),
+            (&self.discord, DISCORD_PREFIX),
+            (&self.oidc, OIDC_PREFIX),
+        ]
+        .into_iter()
+        .find(|(_, prefix)| uid.starts_with(prefix))
+    }
+
+    /// Checks the account windows of the participant's provider. Accounts
+    /// without the evidence a rule needs are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first rule the participant does not satisfy.
+    pub fn check(&self, identity: &Identity, evidence: &Evidence) -> Result<(), RuleViolation> {
+        let rules = match self.provider(&identity::uid(identity)) {
+            Some((rules, _)) => rules,
+            None => return Ok(()),
+        };
+        if let Some(deadline) = rules.created_before {
+            if !evidence
+                .account_created_at
+                .map_or(false, |created_at| created_at < deadline)
+            {
+                return Err(RuleViolation::CreatedAfterDeadline);
+            }
+        }
+        if let Some(min_nonce) = rules.min_nonce {
+            if !evidence.nonce.map_or(false, |nonce| nonce >= min_nonce) {
+                return Err(RuleViolation::TooFewTransactions);
+            }
+        }
+        Ok(())
+    }
+
+    /// Lobby priority of the provider of `uid`, 0 unless it has one.
+    #[must_use]
+    pub fn priority(&self, uid: &str) -> u32 {
+        self.provider(uid)
+            .and_then(|(rules, _)| rules.priority)
+            .unwrap_or_default()
+    }
+
+    /// Quota of the provider of `uid`, if it has one.
+    #[must_use]
+    pub fn quota(&self, uid: &str) -> Option<Quota> {
+        self.provider(uid).and_then(|(rules, uid_prefix)| {
+            rules.max_contributions.map(|max_contributions| Quota {
+                uid_prefix,
+                max_contributions,
+            })
+        })
+    }
+}
+
+pub type SharedRuleSet = Arc<RuleSetHandle>;
+
+/// The current rule set, replaced as a whole on reload.
+#[derive(Debug, Default)]
+pub struct RuleSetHandle {
+    path:  Option<PathBuf>,
+    rules: RwLock<Arc<RuleSet>>,
+}
+
+impl RuleSetHandle {
+    /// Reads `--provider-rules-file`, without it no rules apply.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not a valid rule
+    /// set.
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        let rules = match &options.provider_rules_file {
+            Some(path) => RuleSet::from_file(path)?,
+            None => RuleSet::default(),
+        };
+        Ok(Self {
+            path:  options.provider_rules_file.clone(),
+            rules: RwLock::new(Arc::new(rules)),
+        })
+    }
+
+    #[must_use]
+    pub fn current(&self) -> Arc<RuleSet> {
+        self.rules.read().unwrap().clone()
+    }
+
+    /// Reads the rules file again. The current rules are kept if that fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or is not a valid rule
+    /// set.
+    pub fn reload(&self) -> EyreResult<()> {
+        if let Some(path) = &self.path {
+            let rules = RuleSet::from_file(path)?;
+            *self.rules.write().unwrap() = Arc::new(rules);
+        }
+        Ok(())
+    }
+}
+
+/// Reloads the provider rules on every reload signal.
+pub async fn reload_on_signal(rules: SharedRuleSet) {
+    if rules.path.is_none() {
+        return;
+    }
+    let mut signals = match ReloadSignal::new() {
+        Ok(signals) => signals,
+        Err(error) => {
+            error!(
+                ?error,
+                "Failed to listen for {}, provider rules will not be reloaded",
+                reload_signal::NAME
+            );
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        match rules.reload() {
+            Ok(()) => info!("Reloaded provider rules"),
+            Err(error) => error!(?error, "Failed to reload provider rules"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn checks_provider_windows() {
+        let rules: RuleSet = toml::from_str(
+            r#"
+            [github]
+            created_before = "2022-11-01T00:00:00Z"
+            max_contributions = 2
+
+            [ethereum]
+            min_nonce = 4
+            priority = 2
+            "#,
+        )
+        .unwrap();
+        let github = Identity::Github {
+            id:       1,
+            username: "test_user".to_string(),
+        };
+        let ethereum = Identity::Ethereum { address: [0; 20] };
+        let old_account = Evidence {
+            account_created_at: Some("2022-01-01T00:00:00Z".parse().unwrap()),
+            nonce:              None,
+        };
+        let new_account = Evidence {
+            account_created_at: Some(Utc::now() - Duration::days(1)),
+            nonce:              Some(4),
+        };
+
+        assert_eq!(rules.check(&github, &old_account), Ok(()));
+        assert_eq!(
+            rules.check(&github, &new_account),
+            Err(RuleViolation::CreatedAfterDeadline)
+        );
+        assert_eq!(rules.check(&ethereum, &new_account), Ok(()));
+        assert_eq!(
+            rules.check(&ethereum, &old_account),
+            Err(RuleViolation::TooFewTransactions)
+        );
+        assert_eq!(
+            rules.quota(&identity::uid(&github)),
+            Some(Quota {
+                uid_prefix:        "git|",
+                max_contributions: 2,
+            })
+        );
+        assert_eq!(rules.quota(&identity::uid(&ethereum)), None);
+        assert_eq!(rules.priority(&identity::uid(&ethereum)), 2);
+        assert_eq!(rules.priority(&identity::uid(&github)), 0);
+    }
+
+    #[test]
+    fn reloads_rules() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "[discord]\nmax_contributions = 1").unwrap();
+        let handle = RuleSetHandle::new(&Options {
+            provider_rules_file: Some(file.path().to_path_buf()),
+        })
+        .unwrap();
+        assert_eq!(handle.current().discord.max_contributions, Some(1));
+
+        // Invalid rules keep the previous ones.
+        std::fs::write(file.path(), "[discord]\nmax_contributions = -1\n").unwrap();
+        assert!(handle.reload().is_err());
+        assert_eq!(handle.current().discord.max_contributions, Some(1));
+
+        std::fs::write(file.path(), "[discord]\nmax_contributions = 5\n").unwrap();
+        handle.reload().unwrap();
+        assert_eq!(handle.current().discord.max_contributions, Some(5));
+    }
+}
@@ -0,0 +1,307 @@
+//! Token bucket rate limits keyed by client IP and by session id.
+//!
+//! Every client IP and every bearer token has a bucket per route class that
+//! holds up to a minute worth of requests and refills continuously. A request
+//! is rejected if any of its buckets is empty. IPv6 clients are keyed by
+//! their `--rate-limit-ipv6-prefix`, since one client usually holds a whole
+//! prefix and could otherwise switch addresses to get fresh buckets.
+
+use crate::client_ip::{self, client_ip, ipv6_prefix};
+use axum::{
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use clap::Parser;
+use kzg_ceremony_crypto::ErrorCode;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex, RwLock},
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::time::Instant;
+
+/// Number of buckets above which full, and thus redundant, buckets are
+/// dropped.
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Requests per minute a client IP or session may send to routes without
+    /// a more specific limit. 0 disables the limit.
+    #[clap(long, env, default_value = "600")]
+    pub rate_limit_default: u32,
+
+    /// Requests per minute a client IP or session may send to `/auth/*`.
+    /// 0 disables the limit.
+    #[clap(long, env, default_value = "20")]
+    pub rate_limit_auth: u32,
+
+    /// Requests per minute a client IP or session may send to
+    /// `/lobby/try_contribute`. 0 disables the limit.
+    #[clap(long, env, default_value = "120")]
+    pub rate_limit_try_contribute: u32,
+
+    /// Requests per minute a client IP or session may send to
+    /// `/contribute/validate`. 0 disables the limit.
+    #[clap(long, env, default_value = "10")]
+    pub rate_limit_validate: u32,
+
+    /// Header a trusted reverse proxy puts the client IP in, e.g.
+    /// `Fly-Client-IP`. By default the address of the peer is used. Also used
+    /// for the audit log. With `--trusted-proxies`, only believed from them.
+    #[clap(long, env)]
+    pub rate_limit_ip_header: Option<String>,
+
+    /// Length of the prefix IPv6 clients are limited by.
+    #[clap(long, env, default_value = "64", value_parser = clap::value_parser!(u8).range(0..=128))]
+    pub rate_limit_ipv6_prefix: u8,
+
+    #[clap(flatten)]
+    pub client_ip: client_ip::Options,
+}
+
+impl Options {
+    const fn limit(&self, class: RouteClass) -> u32 {
+        match class {
+            RouteClass::Default => self.rate_limit_default,
+            RouteClass::Auth => self.rate_limit_auth,
+            RouteClass::TryContribute => self.rate_limit_try_contribute,
+            RouteClass::Validate => self.rate_limit_validate,
+        }
+    }
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum RateLimitError {
+    #[error("too many requests, retry in {0} seconds")]
+    TooManyRequests(u64),
+}
+
+impl ErrorCode for RateLimitError {
+    fn to_error_code(&self) -> String {
+        format!("RateLimitError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum RouteClass {
+    Default,
+    Auth,
+    TryContribute,
+    Validate,
+}
+
+impl RouteClass {
+    fn from_path(path: &str) -> Self {
+        if path.starts_with("/auth/") {
+            Self::Auth
+        } else if path == "/lobby/try_contribute" {
+            Self::TryContribute
+        } else if path == "/contribute/validate" {
+            Self::Validate
+        } else {
+            Self::Default
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Ip(IpAddr),
+    Session(String),
+}
+
+struct Bucket {
+    tokens:  f64,
+    updated: Instant,
+}
+
+pub type SharedRateLimiter = Arc<RateLimiter>;
+
+pub struct RateLimiter {
+    /// Replaced when the configuration is reloaded, see [`crate::config`].
+    options: RwLock<Options>,
+    buckets: Mutex<HashMap<(RouteClass, Key), Bucket>>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(options: Options) -> Self {
+        Self {
+            options: RwLock::new(options),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the limits. Buckets keep their tokens, up to the new limit.
+    pub fn set_options(&self, options: Options) {
+        *self.options.write().unwrap() = options;
+    }
+
+    /// Takes a token from each of the buckets, or none if one of them is
+    /// empty.
+    fn check(&self, class: RouteClass, keys: Vec<Key>) -> Result<(), RateLimitError> {
+        let options = self.options.read().unwrap().clone();
+        let limit = options.limit(class);
+        if limit == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(limit);
+        let per_second = capacity / 60.0;
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let mut wait: f64 = 0.0;
+        for key in &keys {
+            let bucket = buckets.entry((class, key.clone())).or_insert(Bucket {
+                tokens:  capacity,
+                updated: now,
+            });
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = elapsed.mul_add(per_second, bucket.tokens).min(capacity);
+            bucket.updated = now;
+            if bucket.tokens < 1.0 {
+                wait = wait.max((1.0 - bucket.tokens) / per_second);
+            }
+        }
+        if wait > 0.0 {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            return Err(RateLimitError::TooManyRequests(wait.ceil() as u64));
+        }
+        for key in keys {
+            if let Some(bucket) = buckets.get_mut(&(class, key)) {
+                bucket.tokens -= 1.0;
+            }
+        }
+
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            buckets.retain(|(class, _), bucket| {
+                let capacity = f64::from(options.limit(*class));
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                elapsed.mul_add(capacity / 60.0, bucket.tokens) < capacity
+            });
+        }
+        Ok(())
+    }
+
+    fn keys<B>(&self, request: &Request<B>) -> Vec<Key> {
+        let session = request
+            .headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| Key::Session(token.to_owned()));
+        let options = self.options.read().unwrap().clone();
+        client_ip(
+            request.headers(),
+            request.extensions(),
+            options.rate_limit_ip_header.as_deref(),
+            &options.client_ip,
+        )
+        .map(|ip| Key::Ip(ipv6_prefix(ip, options.rate_limit_ipv6_prefix)))
+        .into_iter()
+        .chain(session)
+        .collect()
+    }
+}
+
+/// Middleware rejecting requests that exceed the rate limits. Must be
+/// installed on the router without the server path prefix, so that routes
+/// are classified correctly.
+pub async fn rate_limit<B>(
+    limiter: SharedRateLimiter,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let class = RouteClass::from_path(request.uri().path());
+    match limiter.check(class, limiter.keys(&request)) {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware::from_fn, routing::get, Router};
+    use http::StatusCode;
+    use std::{net::Ipv4Addr, time::Duration};
+    use tower::ServiceExt;
+
+    fn limiter(limit: u32) -> RateLimiter {
+        RateLimiter::new(Options {
+            rate_limit_default:        0,
+            rate_limit_auth:           limit,
+            rate_limit_try_contribute: limit,
+            rate_limit_validate:       limit,
+            rate_limit_ip_header:      None,
+            rate_limit_ipv6_prefix:    64,
+            client_ip:                 client_ip::Options::default(),
+        })
+    }
+
+    fn ip(last: u8) -> Key {
+        Key::Ip(Ipv4Addr::new(10, 0, 0, last).into())
+    }
+
+    #[tokio::test]
+    async fn limits_ips_and_sessions() {
+        tokio::time::pause();
+        let limiter = limiter(2);
+        let session = Key::Session("session".to_string());
+
+        assert!(limiter.check(RouteClass::Auth, vec![ip(1)]).is_ok());
+        assert!(limiter.check(RouteClass::Auth, vec![ip(1)]).is_ok());
+        assert!(matches!(
+            limiter.check(RouteClass::Auth, vec![ip(1)]),
+            Err(RateLimitError::TooManyRequests(30))
+        ));
+        assert!(limiter
+            .check(RouteClass::TryContribute, vec![ip(1)])
+            .is_ok());
+        assert!(limiter.check(RouteClass::Default, vec![ip(1)]).is_ok());
+
+        assert!(limiter
+            .check(RouteClass::Auth, vec![ip(2), session.clone()])
+            .is_ok());
+        assert!(limiter
+            .check(RouteClass::Auth, vec![ip(3), session.clone()])
+            .is_ok());
+        assert!(limiter
+            .check(RouteClass::Auth, vec![ip(4), session])
+            .is_err());
+        // A rejected request does not use up the other buckets.
+        assert!(limiter.check(RouteClass::Auth, vec![ip(4)]).is_ok());
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert!(limiter.check(RouteClass::Auth, vec![ip(1)]).is_ok());
+        assert!(limiter.check(RouteClass::Auth, vec![ip(1)]).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_in_middleware() {
+        let limiter = Arc::new(limiter(1));
+        let app = Router::new()
+            .route("/auth/request_link", get(|| async { "ok" }))
+            .layer(from_fn(move |request: Request<Body>, next: Next<Body>| {
+                rate_limit(limiter.clone(), request, next)
+            }));
+        let request = || {
+            Request::builder()
+                .uri("/auth/request_link")
+                .header(http::header::AUTHORIZATION, "Bearer session")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[http::header::RETRY_AFTER], "60");
+    }
+}
@@ -1,16 +1,66 @@
-use crate::keys::{Keys, Signature, SignatureError};
-use kzg_ceremony_crypto::{signature::identity::Identity, G2};
+use crate::{
+    content_digest::ContentDigest,
+    keys::{Keys, Signature, SignatureError},
+};
+use chrono::Utc;
+use ethers_core::utils::keccak256;
+use kzg_ceremony_crypto::{signature::identity::Identity, BatchContribution, G2};
 use serde::Serialize;
 
 // Receipt for contributor that sequencer has
 // included their contribution
 #[derive(Serialize)]
 pub struct Receipt {
-    pub(crate) identity: Identity,
-    pub witness:         Vec<G2>,
+    pub(crate) identity:          Identity,
+    /// Position of the contribution in the transcript.
+    pub contribution_index:       usize,
+    /// Keccak256 hash of the JSON encoded powers resulting from the
+    /// contribution, one entry per sub-ceremony.
+    pub powers_hash:              String,
+    /// Unix timestamp (in seconds) at which the contribution was included.
+    pub timestamp:                i64,
+    pub witness:                  Vec<G2>,
+    /// Keccak256 hash of the entropy attestation sent with the contribution.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entropy_attestation_hash: Option<String>,
+    /// The `Content-Digest` the contribution was uploaded with, as checked
+    /// by the sequencer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_digest:           Option<String>,
 }
 
 impl Receipt {
+    pub fn new(
+        identity: Identity,
+        contribution_index: usize,
+        contribution: &BatchContribution,
+    ) -> Result<Self, SignatureError> {
+        let powers = contribution
+            .contributions
+            .iter()
+            .map(|c| &c.powers)
+            .collect::<Vec<_>>();
+        let powers = serde_json::to_vec(&powers).map_err(|_| SignatureError::SignatureCreation)?;
+        Ok(Self {
+            identity,
+            contribution_index,
+            powers_hash: format!("0x{}", hex::encode(keccak256(powers))),
+            timestamp: Utc::now().timestamp(),
+            witness: contribution.receipt(),
+            entropy_attestation_hash: contribution
+                .entropy_attestation
+                .as_ref()
+                .map(|attestation| format!("0x{}", hex::encode(keccak256(attestation)))),
+            content_digest: None,
+        })
+    }
+
+    #[must_use]
+    pub fn with_content_digest(mut self, content_digest: Option<ContentDigest>) -> Self {
+        self.content_digest = content_digest.map(|ContentDigest(digest)| digest);
+        self
+    }
+
     pub async fn sign(&self, keys: &Keys) -> Result<(String, Signature), SignatureError> {
         let receipt_message =
             serde_json::to_string(self).map_err(|_| SignatureError::SignatureCreation)?;
@@ -19,3 +69,44 @@ impl Receipt {
             .map(|sig| (receipt_message, sig))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+
+    #[tokio::test]
+    async fn signs_receipt() {
+        let keys = Keys::new(&crate::keys::Options {
+            signing_key:         None,
+            signing_key_file:    None,
+            signing_kms_key_id:  None,
+            signing_kms_region:  None,
+            signing_key_overlap: std::time::Duration::from_secs(3600),
+        })
+        .unwrap();
+        let contribution = valid_contribution(&test_transcript(), 1);
+        let receipt = Receipt::new(Identity::None, 1, &contribution).unwrap();
+        assert_eq!(receipt.powers_hash.len(), 66);
+        assert_eq!(
+            receipt.powers_hash,
+            Receipt::new(Identity::None, 1, &contribution)
+                .unwrap()
+                .powers_hash
+        );
+
+        let (message, signature) = receipt.sign(&keys).await.unwrap();
+        assert!(message.contains("\"contribution_index\":1"));
+        assert!(!message.contains("entropy_attestation_hash"));
+        keys.verify(&message, &signature).unwrap();
+
+        let mut attested = contribution;
+        attested.entropy_attestation = Some("drand round 2409021".to_string());
+        let receipt = Receipt::new(Identity::None, 1, &attested)
+            .unwrap()
+            .with_content_digest(Some(ContentDigest("sha-256=:AAAA:".to_string())));
+        assert_eq!(receipt.entropy_attestation_hash.as_ref().unwrap().len(), 66);
+        let (message, _) = receipt.sign(&keys).await.unwrap();
+        assert!(message.contains("\"content_digest\":\"sha-256=:AAAA:\""));
+    }
+}
@@ -0,0 +1,142 @@
+//! Why contributions are rejected.
+//!
+//! The outcome of a verification on `/contribute/status/:id` tells invalid
+//! contributions apart by a [`RejectionReason`], next to the code of the error
+//! response. The reason is also stored in the `rejection_reason` column of
+//! `contributors`, as JSON, for the analysis of failed contributions after
+//! the ceremony. Contributions expired by the compute deadline are recorded
+//! with [`RejectionReason::DeadlineExceeded`].
+
+use kzg_ceremony_crypto::{CeremoniesError, CeremonyError, ParseError};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The group of a point of the powers of tau.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PointGroup {
+    G1,
+    G2,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RejectionReason {
+    /// A power of tau is not a valid compressed point. `index` is that of the
+    /// power.
+    InvalidPointEncoding { index: usize },
+    /// A power of tau is on the curve but not in the prime order subgroup.
+    SubgroupCheckFailed { group: PointGroup, index: usize },
+    /// The pubkey or running product does not match the powers, or the
+    /// witness is inconsistent.
+    WitnessMismatch,
+    /// A BLS or ECDSA signature does not verify, or is missing.
+    SignatureInvalid,
+    /// The contribution was not submitted before the compute deadline.
+    DeadlineExceeded,
+    /// The contribution is malformed otherwise, e.g. it has the wrong number
+    /// of powers, or they are degenerate.
+    Malformed,
+}
+
+impl From<&CeremoniesError> for RejectionReason {
+    fn from(error: &CeremoniesError) -> Self {
+        match error {
+            CeremoniesError::InvalidCeremony(_, error) => error.into(),
+            CeremoniesError::InvalidEcdsaSignature
+            | CeremoniesError::MissingEcdsaSignature
+            | CeremoniesError::InvalidParticipantEcdsaSignature(_) => Self::SignatureInvalid,
+            _ => Self::Malformed,
+        }
+    }
+}
+
+impl From<&CeremonyError> for RejectionReason {
+    fn from(error: &CeremonyError) -> Self {
+        match error {
+            CeremonyError::InvalidG1Power(index, ParseError::InvalidSubgroup) => {
+                Self::SubgroupCheckFailed {
+                    group: PointGroup::G1,
+                    index: *index,
+                }
+            }
+            CeremonyError::InvalidG2Power(index, ParseError::InvalidSubgroup) => {
+                Self::SubgroupCheckFailed {
+                    group: PointGroup::G2,
+                    index: *index,
+                }
+            }
+            CeremonyError::InvalidG1Power(index, _) | CeremonyError::InvalidG2Power(index, _) => {
+                Self::InvalidPointEncoding { index: *index }
+            }
+            CeremonyError::PubKeyPairingFailed
+            | CeremonyError::InvalidWitnessProduct(..)
+            | CeremonyError::InvalidWitnessPubKey(..)
+            | CeremonyError::WitnessLengthMismatch(..)
+            | CeremonyError::WitnessSignaturesMismatch(..)
+            | CeremonyError::InvalidWitnessGenerators
+            | CeremonyError::WitnessPairingFailed(_)
+            | CeremonyError::WitnessProductMismatch => Self::WitnessMismatch,
+            CeremonyError::InvalidBlsSignature(_) => Self::SignatureInvalid,
+            _ => Self::Malformed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::test_transcript, Engine};
+    use kzg_ceremony_crypto::{
+        signature::identity::Identity,
+        test_utils::{ContributionBuilder, Defect},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn classifies_invalid_contributions() {
+        let transcript = test_transcript();
+        let reason = |defect| {
+            let invalid = ContributionBuilder::from_transcript(&transcript)
+                .defect(defect)
+                .build::<Engine>();
+            let error = transcript
+                .clone()
+                .verify_add::<Engine>(invalid, Identity::None)
+                .unwrap_err();
+            RejectionReason::from(&error)
+        };
+        assert_eq!(
+            reason(Defect::WrongSubgroup),
+            RejectionReason::SubgroupCheckFailed {
+                group: PointGroup::G1,
+                index: 1,
+            }
+        );
+        assert_eq!(
+            reason(Defect::MismatchedPubkey),
+            RejectionReason::WitnessMismatch
+        );
+        assert_eq!(reason(Defect::ZeroTau), RejectionReason::Malformed);
+        assert_eq!(
+            RejectionReason::from(&CeremoniesError::MissingEcdsaSignature),
+            RejectionReason::SignatureInvalid
+        );
+    }
+
+    #[test]
+    fn serializes_tagged() {
+        assert_eq!(
+            serde_json::to_value(RejectionReason::SubgroupCheckFailed {
+                group: PointGroup::G2,
+                index: 3,
+            })
+            .unwrap(),
+            json!({ "reason": "subgroup_check_failed", "group": "g2", "index": 3 })
+        );
+        assert_eq!(
+            serde_json::to_value(RejectionReason::DeadlineExceeded).unwrap(),
+            json!({ "reason": "deadline_exceeded" })
+        );
+    }
+}
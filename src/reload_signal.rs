@@ -0,0 +1,41 @@
+//! The signal that asks the sequencer to read its files again. That is SIGHUP
+//! on Unix, and Ctrl-Break on Windows, which has no SIGHUP.
+
+use std::io;
+
+/// Name of the signal, for logs.
+pub const NAME: &str = if cfg!(windows) {
+    "Ctrl-Break"
+} else {
+    "SIGHUP"
+};
+
+/// The reload signals received since [`ReloadSignal::new`]. Every listener
+/// gets every signal.
+pub struct ReloadSignal {
+    #[cfg(unix)]
+    inner: tokio::signal::unix::Signal,
+    #[cfg(windows)]
+    inner: tokio::signal::windows::CtrlBreak,
+}
+
+impl ReloadSignal {
+    /// Starts listening.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signal handler can not be installed.
+    pub fn new() -> io::Result<Self> {
+        #[cfg(unix)]
+        let inner = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        #[cfg(windows)]
+        let inner = tokio::signal::windows::ctrl_break()?;
+        Ok(Self { inner })
+    }
+
+    /// Waits for the next signal. Returns `None` once no more signals can be
+    /// received.
+    pub async fn recv(&mut self) -> Option<()> {
+        self.inner.recv().await
+    }
+}
@@ -0,0 +1,716 @@
+//! Replication of a ceremony to a standby sequencer.
+//!
+//! With `--replication-log-size N` the primary keeps its latest N verified
+//! contributions in memory, each signed by its key, and serves them from
+//! `/replication/contributions/:n`. A request for a contribution that is not
+//! verified yet waits for it for up to 30 seconds and is answered with `204
+//! No Content` if it does not arrive; one that is no longer kept is answered
+//! with `SEQ-REPL-001`.
+//!
+//! A sequencer started with `--replicate-from <primary url>` is a standby. Its
+//! lobby stays paused while it fetches the primary's contributions one after
+//! the other, checks their signature against `--primary-address`, verifies
+//! them like its own and records them in its transcript and database. When it
+//! falls behind the log, it fetches the primary's whole transcript from
+//! `/info/current_state`, verifies it and takes the contributions it is
+//! missing from there, with their receipts from `/info/contributor/:index`.
+//! `/admin/promote` stops the replication and resumes the lobby, once the
+//! primary is gone.
+
+use crate::{
+    checkpoint::SharedCheckpointer,
+    io::{write_json_file, TranscriptIoError},
+    keys::{verify_signed_by, Keys, SignatureError},
+    lobby::{LobbyEvent, SharedLobbyState},
+    storage::{PersistentStorage, StorageError, StoredReceipt},
+    CeremonyId, Engine, Options as AppOptions, SharedCeremonyStatus, SharedTranscript,
+};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use ethers_core::types::H160;
+use eyre::{eyre, Result as EyreResult};
+use kzg_ceremony_crypto::{
+    signature::identity::Identity, BatchContribution, BatchTranscript, CeremoniesError, ErrorCode,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    num::ParseIntError,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{sync::watch, task::JoinError};
+use tracing::{info, warn};
+use url::Url;
+
+/// How long a request for a contribution that is not verified yet waits.
+pub const POLL_WAIT: Duration = Duration::from_secs(30);
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Number of recent contributions kept for standby sequencers. Disabled
+    /// with 0.
+    #[clap(long, env, default_value = "0")]
+    pub replication_log_size: usize,
+
+    /// Url of the primary sequencer to follow as a standby.
+    #[clap(long, env, requires = "primary_address")]
+    pub replicate_from: Option<Url>,
+
+    /// Address of the primary's signing key, as in its `/info/status`.
+    #[clap(long, env)]
+    pub primary_address: Option<String>,
+
+    /// Seconds to wait after a failed request to the primary.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "5")]
+    pub replication_retry: Duration,
+}
+
+/// A verified contribution as recorded by the primary.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicatedContribution {
+    /// Number of contributions in the transcript with this one.
+    pub num_contributions: usize,
+    pub uid:               String,
+    /// The participant id the transcript records the contribution by. `None`
+    /// from primaries with legacy uids, which are participant ids.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity:          Option<Identity>,
+    pub contribution:      BatchContribution,
+    /// `None` if the primary lost track of the attempt.
+    pub started_at:        Option<DateTime<Utc>>,
+    pub finished_at:       DateTime<Utc>,
+    pub receipt:           Option<StoredReceipt>,
+}
+
+/// A [`ReplicatedContribution`] as JSON, signed by the primary's key.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedContribution {
+    pub payload:   String,
+    /// Hex encoded signature of the primary over `payload`.
+    pub signature: String,
+}
+
+impl SignedContribution {
+    /// # Errors
+    ///
+    /// Returns an error if the contribution is not signed by `primary`.
+    pub fn verify(&self, primary: H160) -> Result<ReplicatedContribution, FollowError> {
+        verify_signed_by(&self.payload, &self.signature, primary)?;
+        Ok(serde_json::from_str(&self.payload)?)
+    }
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ReplicationLogError {
+    #[error("the replication log is disabled")]
+    Disabled,
+    #[error("the contribution is no longer in the replication log")]
+    Evicted,
+}
+
+impl ErrorCode for ReplicationLogError {
+    fn to_error_code(&self) -> String {
+        format!("ReplicationLogError::{}", <&str>::from(self))
+    }
+}
+
+pub type SharedReplicationLog = Arc<ReplicationLog>;
+
+/// The latest contributions of the primary, for standby sequencers.
+#[derive(Debug)]
+pub struct ReplicationLog {
+    capacity: usize,
+    recent:   Mutex<VecDeque<(usize, Arc<SignedContribution>)>>,
+    /// Number of contributions of the transcript.
+    latest:   watch::Sender<usize>,
+}
+
+impl Default for ReplicationLog {
+    fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+impl ReplicationLog {
+    #[must_use]
+    pub fn new(capacity: usize, num_contributions: usize) -> Self {
+        Self {
+            capacity,
+            recent: Mutex::default(),
+            latest: watch::channel(num_contributions).0,
+        }
+    }
+
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Signs `contribution` and keeps it, dropping the oldest contribution if
+    /// the log is full.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contribution can not be signed.
+    pub async fn record(
+        &self,
+        keys: &Keys,
+        contribution: &ReplicatedContribution,
+    ) -> Result<(), SignatureError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let payload = serde_json::to_string(contribution).expect("contributions serialize");
+        let signature = keys.sign(&payload).await?.as_str().to_string();
+        {
+            let mut recent = self.recent.lock().unwrap();
+            recent.push_back((
+                contribution.num_contributions,
+                Arc::new(SignedContribution { payload, signature }),
+            ));
+            while recent.len() > self.capacity {
+                recent.pop_front();
+            }
+        }
+        self.latest.send_replace(contribution.num_contributions);
+        Ok(())
+    }
+
+    /// The contribution that made the transcript `num_contributions` long,
+    /// waiting up to `wait` for it to be verified. `None` if it was not in
+    /// time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log is disabled or no longer holds the
+    /// contribution.
+    pub async fn wait_for(
+        &self,
+        num_contributions: usize,
+        wait: Duration,
+    ) -> Result<Option<Arc<SignedContribution>>, ReplicationLogError> {
+        if !self.is_enabled() {
+            return Err(ReplicationLogError::Disabled);
+        }
+        let mut latest = self.latest.subscribe();
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            if num_contributions <= *latest.borrow_and_update() {
+                return self
+                    .recent
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|(num, _)| *num == num_contributions)
+                    .map(|(_, signed)| Some(signed.clone()))
+                    .ok_or(ReplicationLogError::Evicted);
+            }
+            if tokio::time::timeout_at(deadline, latest.changed())
+                .await
+                .is_err()
+            {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+pub type SharedReplica = Arc<Replica>;
+
+/// Whether the ceremony is a standby for another sequencer.
+#[derive(Debug, Default)]
+pub struct Replica {
+    standby:  AtomicBool,
+    /// Held while a contribution of the primary is being recorded.
+    applying: tokio::sync::Mutex<()>,
+}
+
+impl Replica {
+    #[must_use]
+    pub fn new(standby: bool) -> Self {
+        Self {
+            standby:  AtomicBool::new(standby),
+            applying: tokio::sync::Mutex::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_standby(&self) -> bool {
+        self.standby.load(Ordering::SeqCst)
+    }
+
+    /// Stops following the primary, once the contribution being recorded is
+    /// done. Returns whether the ceremony was a standby.
+    pub async fn promote(&self) -> bool {
+        let _applying = self.applying.lock().await;
+        self.standby.swap(false, Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FollowError {
+    #[error("request to the primary failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("invalid signature of the primary: {0}")]
+    Signature(#[from] SignatureError),
+    #[error("invalid replicated contribution: {0}")]
+    Payload(#[from] serde_json::Error),
+    #[error("expected contribution {expected}, got {received}")]
+    OutOfOrder { expected: usize, received: usize },
+    #[error("invalid participant id")]
+    InvalidIdentity,
+    #[error("replicated contribution is invalid: {0}")]
+    InvalidContribution(#[from] CeremoniesError),
+    #[error("the primary's transcript does not extend the replica")]
+    Diverged,
+    #[error("failed to write the transcript: {0}")]
+    TranscriptIo(#[from] TranscriptIoError),
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("background task error: {0}")]
+    Task(#[from] JoinError),
+}
+
+/// A contribution of the primary as listed by its `/info/contributor/:index`.
+#[derive(Debug, Deserialize)]
+struct PrimaryContributor {
+    uid:           String,
+    duration_secs: Option<f64>,
+    receipt:       String,
+    signature:     String,
+}
+
+/// Keeps the transcript and database of a standby ceremony in step with the
+/// primary.
+pub struct Follower {
+    /// Url of the ceremony on the primary, ending with a slash.
+    base:            Url,
+    primary:         H160,
+    retry:           Duration,
+    client:          reqwest::Client,
+    replica:         SharedReplica,
+    options:         AppOptions,
+    transcript:      SharedTranscript,
+    storage:         PersistentStorage,
+    ceremony_status: SharedCeremonyStatus,
+    lobby_state:     SharedLobbyState,
+    checkpointer:    SharedCheckpointer,
+}
+
+impl Follower {
+    /// A follower for the ceremony `id`, if `--replicate-from` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `--primary-address` is not a valid address.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        options: &AppOptions,
+        id: &CeremonyId,
+        client: reqwest::Client,
+        replica: SharedReplica,
+        transcript: SharedTranscript,
+        storage: PersistentStorage,
+        ceremony_status: SharedCeremonyStatus,
+        lobby_state: SharedLobbyState,
+        checkpointer: SharedCheckpointer,
+    ) -> EyreResult<Option<Self>> {
+        let replication = &options.replication;
+        let mut base = match &replication.replicate_from {
+            Some(url) => url.clone(),
+            None => return Ok(None),
+        };
+        let primary = replication
+            .primary_address
+            .as_deref()
+            .ok_or_else(|| eyre!("--replicate-from requires --primary-address"))?
+            .parse::<H160>()
+            .map_err(|error| eyre!("invalid --primary-address: {error}"))?;
+        let path = format!("{}{}/", base.path().trim_end_matches('/'), id.path_prefix());
+        base.set_path(&path);
+        Ok(Some(Self {
+            base,
+            primary,
+            retry: replication.replication_retry,
+            client,
+            replica,
+            options: options.clone(),
+            transcript,
+            storage,
+            ceremony_status,
+            lobby_state,
+            checkpointer,
+        }))
+    }
+
+    /// Follows the primary until the ceremony is promoted.
+    pub async fn run(self) {
+        info!(primary = %self.base, "Following the primary sequencer");
+        while self.replica.is_standby() {
+            if let Err(error) = self.step().await {
+                warn!(?error, "replication from the primary failed");
+                tokio::time::sleep(self.retry).await;
+            }
+        }
+        info!("Promoted, stopped following the primary sequencer");
+    }
+
+    /// Fetches and records the next contribution of the primary, if there
+    /// is one.
+    async fn step(&self) -> Result<(), FollowError> {
+        let next = self.ceremony_status.load(Ordering::Relaxed) + 1;
+        let response = self
+            .client
+            .get(self.url(&format!("replication/contributions/{next}")))
+            .timeout(POLL_WAIT * 2)
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            StatusCode::GONE => {
+                let _applying = self.replica.applying.lock().await;
+                if self.replica.is_standby() {
+                    self.resync().await?;
+                }
+                Ok(())
+            }
+            _ => {
+                let signed = response
+                    .error_for_status()?
+                    .json::<SignedContribution>()
+                    .await?;
+                let contribution = signed.verify(self.primary)?;
+                if contribution.num_contributions != next {
+                    return Err(FollowError::OutOfOrder {
+                        expected: next,
+                        received: contribution.num_contributions,
+                    });
+                }
+                let _applying = self.replica.applying.lock().await;
+                if self.replica.is_standby() {
+                    self.apply(contribution).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Verifies the next contribution and records it like the primary did.
+    async fn apply(&self, replicated: ReplicatedContribution) -> Result<(), FollowError> {
+        let identity = match replicated.identity.clone() {
+            Some(identity) => identity,
+            None => replicated
+                .uid
+                .parse::<Identity>()
+                .map_err(|_| FollowError::InvalidIdentity)?,
+        };
+        let mut transcript = self.transcript.clone().write_owned().await;
+        let num_contributions = transcript.num_participants() + 1;
+        if replicated.num_contributions != num_contributions {
+            return Err(FollowError::OutOfOrder {
+                expected: num_contributions,
+                received: replicated.num_contributions,
+            });
+        }
+        let contribution = replicated.contribution;
+        tokio::task::spawn_blocking(move || {
+            transcript.verify_add::<Engine>(contribution, identity)
+        })
+        .await??;
+
+        self.storage
+            .append_transcript_entry(&*self.transcript.read().await)
+            .await?;
+        self.checkpointer
+            .checkpoint(num_contributions, &self.transcript)
+            .await;
+        self.write_transcript().await?;
+        self.storage
+            .insert_replicated_contributor(
+                &replicated.uid,
+                num_contributions,
+                replicated.started_at.unwrap_or(replicated.finished_at),
+                replicated.finished_at,
+            )
+            .await?;
+        if let Some(receipt) = &replicated.receipt {
+            self.storage
+                .insert_receipt(&replicated.uid, num_contributions, receipt)
+                .await?;
+        }
+        self.ceremony_status
+            .store(num_contributions, Ordering::Relaxed);
+        self.lobby_state
+            .publish(LobbyEvent::ContributionVerified { num_contributions });
+        info!(num_contributions, uid = %replicated.uid, "Replicated contribution");
+        Ok(())
+    }
+
+    /// Takes the contributions the replica is missing from the primary's
+    /// transcript, after verifying all of it.
+    async fn resync(&self) -> Result<(), FollowError> {
+        let primary = self
+            .client
+            .get(self.url("info/current_state"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<BatchTranscript>()
+            .await?;
+        let first = self.transcript.read().await.num_participants() + 1;
+        if primary.num_participants() < first {
+            return Ok(());
+        }
+        if !extends(&primary, &*self.transcript.read().await) {
+            return Err(FollowError::Diverged);
+        }
+        info!(
+            from = first,
+            to = primary.num_participants(),
+            "Verifying the transcript of the primary sequencer"
+        );
+        let primary =
+            tokio::task::spawn_blocking(move || primary.verify_self::<Engine>().map(|()| primary))
+                .await??;
+        let num_contributions = primary.num_participants();
+
+        self.storage.import_transcript_from(&primary, first).await?;
+        *self.transcript.write().await = primary;
+        self.checkpointer
+            .checkpoint(num_contributions, &self.transcript)
+            .await;
+        self.write_transcript().await?;
+        for index in first..=num_contributions {
+            if let Err(error) = self.copy_contributor(index).await {
+                warn!(?error, index, "failed to replicate contributor");
+            }
+        }
+        self.ceremony_status
+            .store(num_contributions, Ordering::Relaxed);
+        self.lobby_state
+            .publish(LobbyEvent::ContributionVerified { num_contributions });
+        info!(
+            num_contributions,
+            "Resynchronized with the primary sequencer"
+        );
+        Ok(())
+    }
+
+    /// Records the contributor at `index` from the primary's listing, if it
+    /// has a receipt signed by the primary. The time the contribution took
+    /// is kept, not when it happened.
+    async fn copy_contributor(&self, index: usize) -> Result<(), FollowError> {
+        let response = self
+            .client
+            .get(self.url(&format!("info/contributor/{index}")))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        let contributor = response
+            .error_for_status()?
+            .json::<PrimaryContributor>()
+            .await?;
+        verify_signed_by(&contributor.receipt, &contributor.signature, self.primary)?;
+        let finished_at = Utc::now();
+        let duration = contributor
+            .duration_secs
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .and_then(|secs| chrono::Duration::from_std(Duration::from_secs_f64(secs)).ok())
+            .unwrap_or_else(chrono::Duration::zero);
+        self.storage
+            .insert_replicated_contributor(
+                &contributor.uid,
+                index,
+                finished_at - duration,
+                finished_at,
+            )
+            .await?;
+        self.storage
+            .insert_receipt(&contributor.uid, index, &StoredReceipt {
+                receipt:   contributor.receipt,
+                signature: contributor.signature,
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn write_transcript(&self) -> Result<(), TranscriptIoError> {
+        write_json_file(
+            self.options.transcript_file.clone(),
+            self.options.transcript_in_progress_file.clone(),
+            self.transcript.clone(),
+        )
+        .await
+    }
+
+    fn url(&self, path: &str) -> Url {
+        self.base.join(path).expect("paths are valid urls")
+    }
+}
+
+/// Whether `transcript` holds the contributions of `prefix`, followed by
+/// others.
+fn extends(transcript: &BatchTranscript, prefix: &BatchTranscript) -> bool {
+    let len = prefix.participant_ids.len();
+    transcript.transcripts.len() == prefix.transcripts.len()
+        && transcript.participant_ids.get(..len) == Some(&prefix.participant_ids[..])
+        && transcript
+            .transcripts
+            .iter()
+            .zip(&prefix.transcripts)
+            .all(|(transcript, prefix)| {
+                transcript.witness.products.get(..len) == Some(&prefix.witness.products[..])
+                    && transcript.witness.pubkeys.get(..len) == Some(&prefix.witness.pubkeys[..])
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checkpoint::Checkpointer,
+        keys,
+        storage::storage_client,
+        test_util::test_options,
+        tests::{invalid_contribution, test_transcript, valid_contribution},
+    };
+    use tempfile::TempDir;
+    use tokio::sync::RwLock;
+
+    fn test_keys() -> Keys {
+        Keys::new(&keys::Options {
+            signing_key:         None,
+            signing_key_file:    None,
+            signing_kms_key_id:  None,
+            signing_kms_region:  None,
+            signing_key_overlap: Duration::from_secs(3600),
+        })
+        .unwrap()
+    }
+
+    fn replicated(transcript: &BatchTranscript, no: u8) -> ReplicatedContribution {
+        ReplicatedContribution {
+            num_contributions: transcript.num_participants() + 1,
+            uid:               format!("git|{no}|user_{no}"),
+            identity:          None,
+            contribution:      valid_contribution(transcript, no),
+            started_at:        None,
+            finished_at:       Utc::now(),
+            receipt:           Some(StoredReceipt {
+                receipt:   format!("receipt {no}"),
+                signature: format!("signature {no}"),
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_recent_contributions() {
+        let keys = test_keys();
+        let primary = keys.address().to_string().parse::<H160>().unwrap();
+        let log = ReplicationLog::new(2, 0);
+        let wait = Duration::from_millis(10);
+        assert!(matches!(log.wait_for(1, wait).await, Ok(None)));
+        assert!(matches!(
+            ReplicationLog::default().wait_for(1, wait).await,
+            Err(ReplicationLogError::Disabled)
+        ));
+
+        let mut transcript = test_transcript();
+        for no in 1..=3 {
+            let contribution = replicated(&transcript, no);
+            log.record(&keys, &contribution).await.unwrap();
+            transcript
+                .verify_add::<Engine>(contribution.contribution, contribution.uid.parse().unwrap())
+                .unwrap();
+        }
+        assert!(matches!(
+            log.wait_for(1, wait).await,
+            Err(ReplicationLogError::Evicted)
+        ));
+        let signed = log.wait_for(3, wait).await.unwrap().unwrap();
+        assert_eq!(signed.verify(primary).unwrap().uid, "git|3|user_3");
+        assert!(signed.verify(H160::zero()).is_err());
+        let tampered = SignedContribution {
+            payload: signed.payload.replace("user_3", "user_4"),
+            ..(*signed).clone()
+        };
+        assert!(tampered.verify(primary).is_err());
+        assert!(matches!(log.wait_for(4, wait).await, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn follows_contributions() {
+        let dir = TempDir::new().unwrap();
+        let mut options = test_options();
+        options.transcript_file = dir.path().join("transcript.json");
+        options.transcript_in_progress_file = dir.path().join("transcript.json.next");
+        options.replication.replicate_from = Some("http://primary.example/api".parse().unwrap());
+        options.replication.primary_address = Some(format!("{:?}", H160::zero()));
+        let storage = storage_client(&options.storage).await.unwrap();
+        let transcript = Arc::new(RwLock::new(test_transcript()));
+        let replica = Arc::new(Replica::new(true));
+        let follower = Follower::new(
+            &options,
+            &CeremonyId(Some("small".to_string())),
+            reqwest::Client::new(),
+            replica.clone(),
+            transcript.clone(),
+            storage.clone(),
+            SharedCeremonyStatus::default(),
+            SharedLobbyState::new(options.lobby.clone()),
+            Arc::new(Checkpointer::new(&options.checkpoint).unwrap()),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            follower.url("info/current_state").as_str(),
+            "http://primary.example/api/ceremony/small/info/current_state"
+        );
+
+        let first = replicated(&test_transcript(), 1);
+        follower.apply(first.clone()).await.unwrap();
+        assert_eq!(transcript.read().await.num_participants(), 1);
+        assert_eq!(follower.ceremony_status.load(Ordering::Relaxed), 1);
+        assert!(storage.has_contributed("git|1|user_1").await.unwrap());
+        assert_eq!(
+            storage.get_receipt("git|1|user_1").await.unwrap(),
+            first.receipt
+        );
+        assert_eq!(storage.count_transcript_entries().await.unwrap(), 1);
+
+        // Replaying or skipping contributions fails.
+        assert!(matches!(
+            follower.apply(first).await,
+            Err(FollowError::OutOfOrder { .. })
+        ));
+        let mut invalid = replicated(&transcript.read().await, 2);
+        invalid.contribution = invalid_contribution(&transcript.read().await, 2);
+        assert!(matches!(
+            follower.apply(invalid).await,
+            Err(FollowError::InvalidContribution(_))
+        ));
+
+        let replica_transcript = transcript.read().await.clone();
+        assert!(extends(&replica_transcript, &test_transcript()));
+        assert!(!extends(&test_transcript(), &replica_transcript));
+
+        assert!(replica.promote().await);
+        assert!(!replica.is_standby());
+        assert!(!replica.promote().await);
+    }
+}
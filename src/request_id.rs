@@ -0,0 +1,123 @@
+//! Correlation ids of API requests.
+//!
+//! Every request gets an id: the `X-Request-Id` it came with, e.g. from a
+//! reverse proxy, or a new UUID. The id is recorded on the request span, so
+//! that every log line of the request, including those of its database
+//! queries and of the verification of its contribution, carries it. It is
+//! sent back in the `X-Request-Id` header and in the body of error responses,
+//! so that a bug report can be matched to the server logs.
+
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{info_span, Span};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest id taken over from a request.
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// The id of `request`, if it sent a usable one.
+    fn of<B>(request: &Request<B>) -> Option<Self> {
+        let id = request.headers().get(REQUEST_ID_HEADER)?.to_str().ok()?;
+        let usable = !id.is_empty()
+            && id.len() <= MAX_LENGTH
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        usable.then(|| Self(id.to_string()))
+    }
+}
+
+/// The id of the request being handled, if any.
+#[must_use]
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.0.clone()).ok()
+}
+
+/// Middleware assigning the request id. Must be installed outside of the
+/// `TraceLayer`, which makes its span with [`make_span`].
+pub async fn assign_request_id<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let id = RequestId::of(&request).unwrap_or_else(|| RequestId(Uuid::new_v4().to_string()));
+    request.extensions_mut().insert(id.clone());
+    let header = HeaderValue::from_str(&id.0);
+    let mut response = CURRENT.scope(id, next.run(request)).await;
+    if let Ok(header) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+    response
+}
+
+/// Span of a request, like that of `DefaultMakeSpan` with the request id.
+pub fn make_span<B>(request: &Request<B>) -> Span {
+    let id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.as_str())
+        .unwrap_or_default();
+    info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id = %id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::RateLimitError;
+    use axum::{body::Body, middleware::from_fn, response::IntoResponse, routing::get, Router};
+    use tower::ServiceExt;
+
+    async fn get_with(id: Option<&str>) -> (Option<String>, serde_json::Value) {
+        let app = Router::new()
+            .route(
+                "/limited",
+                get(|| async { RateLimitError::TooManyRequests(1).into_response() }),
+            )
+            .layer(from_fn(assign_request_id));
+        let mut request = Request::builder().uri("/limited");
+        if let Some(id) = id {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let header = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .map(|id| id.to_str().unwrap().to_string());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (header, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn echoes_request_ids() {
+        let (header, body) = get_with(None).await;
+        let id = header.unwrap();
+        assert!(Uuid::parse_str(&id).is_ok());
+        assert_eq!(body["request_id"], id);
+
+        let (header, body) = get_with(Some("fly-01GK2X")).await;
+        assert_eq!(header.as_deref(), Some("fly-01GK2X"));
+        assert_eq!(body["request_id"], "fly-01GK2X");
+
+        let (header, _) = get_with(Some("no spaces, please")).await;
+        assert_ne!(header.as_deref(), Some("no spaces, please"));
+        assert!(current().is_none());
+    }
+}
@@ -0,0 +1,214 @@
+//! Timeouts and a concurrency cap for API requests.
+//!
+//! A request must be answered within the timeout of its route, otherwise its
+//! handler is dropped and it is answered with `SEQ-LIMIT-002`. At most
+//! `--max-concurrent-requests` requests are handled at once across all
+//! ceremonies. Further requests wait for a turn within their timeout and are
+//! answered with `SEQ-LIMIT-001` if none comes. `/healthz` and `/metrics` are
+//! exempt, so that probes and scrapes still answer while the server is busy.
+
+use axum::{
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use clap::Parser;
+use kzg_ceremony_crypto::ErrorCode;
+use std::{num::ParseIntError, str::FromStr, sync::Arc, time::Duration};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{sync::Semaphore, time::Instant};
+use tracing::warn;
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Seconds within which requests to routes without a more specific
+    /// timeout must be answered.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "60")]
+    pub request_timeout: Duration,
+
+    /// Seconds within which requests to `/auth/*` must be answered.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "15")]
+    pub auth_timeout: Duration,
+
+    /// Seconds within which requests to `/lobby/*` must be answered.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "10")]
+    pub lobby_timeout: Duration,
+
+    /// Seconds within which uploads to `/contribute` and
+    /// `/contribute/validate`, and commits of uploads in parts, must be
+    /// answered.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "300")]
+    pub contribute_timeout: Duration,
+
+    /// Requests handled at once, across all ceremonies. 0 disables the limit.
+    #[clap(long, env, default_value = "1024")]
+    pub max_concurrent_requests: usize,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum RequestLimitError {
+    #[error("too many concurrent requests")]
+    Overloaded,
+    #[error("request timed out after {0:?}")]
+    TimedOut(Duration),
+}
+
+impl ErrorCode for RequestLimitError {
+    fn to_error_code(&self) -> String {
+        format!("RequestLimitError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RouteClass {
+    Default,
+    Auth,
+    Lobby,
+    Contribute,
+    Exempt,
+}
+
+impl RouteClass {
+    fn from_path(path: &str) -> Self {
+        if path.starts_with("/auth/") {
+            Self::Auth
+        } else if path.starts_with("/lobby/") {
+            Self::Lobby
+        } else if matches!(
+            path,
+            "/contribute" | "/contribute/upload" | "/contribute/validate"
+        ) {
+            Self::Contribute
+        } else if path == "/healthz" || path == "/metrics" {
+            Self::Exempt
+        } else {
+            Self::Default
+        }
+    }
+}
+
+pub type SharedRequestLimiter = Arc<RequestLimiter>;
+
+pub struct RequestLimiter {
+    options: Options,
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl RequestLimiter {
+    #[must_use]
+    pub fn new(options: Options) -> Self {
+        let permits = (options.max_concurrent_requests > 0)
+            .then(|| Arc::new(Semaphore::new(options.max_concurrent_requests)));
+        Self { options, permits }
+    }
+
+    const fn timeout(&self, class: RouteClass) -> Option<Duration> {
+        match class {
+            RouteClass::Default => Some(self.options.request_timeout),
+            RouteClass::Auth => Some(self.options.auth_timeout),
+            RouteClass::Lobby => Some(self.options.lobby_timeout),
+            RouteClass::Contribute => Some(self.options.contribute_timeout),
+            RouteClass::Exempt => None,
+        }
+    }
+}
+
+/// Middleware enforcing the timeouts and the concurrency cap. Must be
+/// installed on the router without the server path prefix, so that routes
+/// are classified correctly.
+pub async fn limit_requests<B>(
+    limiter: SharedRequestLimiter,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let path = request.uri().path().to_owned();
+    let timeout = match limiter.timeout(RouteClass::from_path(&path)) {
+        Some(timeout) => timeout,
+        None => return next.run(request).await,
+    };
+    let deadline = Instant::now() + timeout;
+
+    let _permit = match &limiter.permits {
+        Some(permits) => {
+            match tokio::time::timeout_at(deadline, permits.clone().acquire_owned()).await {
+                Ok(Ok(permit)) => Some(permit),
+                _ => {
+                    warn!(path, "too many concurrent requests");
+                    return RequestLimitError::Overloaded.into_response();
+                }
+            }
+        }
+        None => None,
+    };
+    match tokio::time::timeout_at(deadline, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!(path, ?timeout, "request timed out");
+            RequestLimitError::TimedOut(timeout).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware::from_fn, routing::get, Router};
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    fn app(limiter: SharedRequestLimiter) -> Router {
+        let slow = || async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "ok"
+        };
+        Router::new()
+            .route("/lobby/position", get(slow))
+            .route("/healthz", get(slow))
+            .layer(from_fn(move |request: Request<Body>, next: Next<Body>| {
+                limit_requests(limiter.clone(), request, next)
+            }))
+    }
+
+    fn request(path: &str) -> Request<Body> {
+        Request::builder().uri(path).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn times_out_and_caps_requests() {
+        tokio::time::pause();
+        let limiter = Arc::new(RequestLimiter::new(Options {
+            request_timeout:         Duration::from_secs(60),
+            auth_timeout:            Duration::from_secs(15),
+            lobby_timeout:           Duration::from_secs(1),
+            contribute_timeout:      Duration::from_secs(300),
+            max_concurrent_requests: 1,
+        }));
+        let app = app(limiter.clone());
+
+        let response = app
+            .clone()
+            .oneshot(request("/lobby/position"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let response = app.clone().oneshot(request("/healthz")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The only permit is taken by a request still in progress.
+        let _permit = limiter.permits.clone().unwrap().acquire_owned().await;
+        let response = app
+            .clone()
+            .oneshot(request("/lobby/position"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let response = app.oneshot(request("/healthz")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
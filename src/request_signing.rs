@@ -0,0 +1,657 @@
+//! Signed requests to the admin API, and signed webhook deliveries.
+//!
+//! `--signing-keys-file` is a JSON file of named keys:
+//!
+//! ```json
+//! {
+//!   "keys": {
+//!     "ops-2022-11": { "hmac": "shared secret" },
+//!     "ci": { "ed25519": "<hex encoded public key>" }
+//!   },
+//!   "webhook_key": "ops-2022-11"
+//! }
+//! ```
+//!
+//! A signed request carries the unix time in seconds in
+//! `X-Signature-Timestamp`, a unique nonce of at most 64 bytes in
+//! `X-Signature-Nonce`, the hex encoded SHA-256 of its body in
+//! `X-Content-Sha256`, and `<key>:<signature>` in `X-Signature`, where the
+//! hex encoded signature is over
+//!
+//! ```text
+//! <timestamp>\n<nonce>\n<method>\n<path and query>\n<body sha256>
+//! ```
+//!
+//! HMAC keys sign with HMAC-SHA256, Ed25519 keys with the private key of the
+//! listed public key. Requests whose timestamp is more than
+//! `--signature-max-age` away from the sequencer's clock are rejected, and so
+//! is a nonce the key already used while its request was valid. The nonces
+//! are kept in the [lobby store](crate::lobby_store), so that with Redis a
+//! request can not be replayed against another replica either. Webhook events
+//! are signed the same way with the HMAC key named `webhook_key`, in addition
+//! to `X-Webhook-Signature`.
+//!
+//! On SIGHUP the file is read again; a file that fails to parse keeps the
+//! previous keys. Keys are rotated by adding the new key, moving clients and
+//! `webhook_key` to it, and then removing the old one.
+
+use crate::{
+    lobby_store::{LobbyStore, LobbyStoreError, SharedLobbyStore},
+    reload_signal::{self, ReloadSignal},
+};
+use axum::{
+    body::{Body, HttpBody},
+    extract::OriginalUri,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use clap::Parser;
+use eyre::{bail, eyre, Result as EyreResult, WrapErr};
+use hmac::{Hmac, Mac};
+use http::{uri::PathAndQuery, HeaderMap, Request};
+use kzg_ceremony_crypto::ErrorCode;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+pub const SIGNATURE_HEADER: &str = "x-signature";
+pub const TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+pub const NONCE_HEADER: &str = "x-signature-nonce";
+pub const DIGEST_HEADER: &str = "x-content-sha256";
+
+/// Largest body of a signed request. The admin endpoints take small JSON
+/// bodies at most.
+const MAX_SIGNED_BODY_SIZE: usize = 64 * 1024;
+
+/// Longest nonce, which ends up in a key of the lobby store. A UUID fits.
+const MAX_NONCE_LENGTH: usize = 64;
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// JSON file with the keys admin requests and webhook events are signed
+    /// with. Enables the admin API like `--admin-token`.
+    #[clap(long, env)]
+    pub signing_keys_file: Option<PathBuf>,
+
+    /// Largest difference in seconds between the timestamp of a signed
+    /// request and the clock of the sequencer.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "300")]
+    pub signature_max_age: Duration,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum Key {
+    Hmac(Vec<u8>),
+    Ed25519([u8; 32]),
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Hmac(_) => formatter.write_str("Hmac([REDACTED])"),
+            Self::Ed25519(public_key) => write!(formatter, "Ed25519({})", hex::encode(public_key)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum KeyFile {
+    Hmac(String),
+    Ed25519(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyringFile {
+    keys:        BTreeMap<String, KeyFile>,
+    webhook_key: Option<String>,
+}
+
+/// The keys of `--signing-keys-file`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Keyring {
+    keys:        BTreeMap<String, Key>,
+    webhook_key: Option<String>,
+}
+
+impl Keyring {
+    fn from_file(path: &Path) -> EyreResult<Self> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read signing keys {}", path.display()))?;
+        Self::parse(&contents).wrap_err_with(|| format!("invalid signing keys {}", path.display()))
+    }
+
+    fn parse(contents: &str) -> EyreResult<Self> {
+        let file: KeyringFile = serde_json::from_str(contents)?;
+        let mut keys = BTreeMap::new();
+        for (name, key) in file.keys {
+            let key = match key {
+                KeyFile::Hmac(secret) if secret.is_empty() => bail!("empty hmac key {name}"),
+                KeyFile::Hmac(secret) => Key::Hmac(secret.into_bytes()),
+                KeyFile::Ed25519(public_key) => Key::Ed25519(
+                    hex::decode(public_key.trim_start_matches("0x"))
+                        .ok()
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .ok_or_else(|| eyre!("invalid ed25519 public key {name}"))?,
+                ),
+            };
+            keys.insert(name, key);
+        }
+        if let Some(name) = &file.webhook_key {
+            if !matches!(keys.get(name), Some(Key::Hmac(_))) {
+                bail!("webhook key {name} is not an hmac key");
+            }
+        }
+        Ok(Self {
+            keys,
+            webhook_key: file.webhook_key,
+        })
+    }
+
+    /// Whether `signature` is a signature of `message` by the key `name`.
+    #[must_use]
+    pub fn verify(&self, name: &str, message: &[u8], signature: &[u8]) -> bool {
+        match self.keys.get(name) {
+            Some(Key::Hmac(secret)) => hmac_sha256(secret)
+                .chain_update(message)
+                .verify_slice(signature)
+                .is_ok(),
+            Some(Key::Ed25519(public_key)) => UnparsedPublicKey::new(&ED25519, public_key)
+                .verify(message, signature)
+                .is_ok(),
+            None => false,
+        }
+    }
+
+    /// The signature headers of a webhook delivery to `path`, with the query
+    /// if there is one, if a webhook key is set.
+    #[must_use]
+    pub fn sign_webhook(&self, path: &str, body: &[u8]) -> Option<Vec<(&'static str, String)>> {
+        let name = self.webhook_key.as_ref()?;
+        let secret = match self.keys.get(name) {
+            Some(Key::Hmac(secret)) => secret,
+            _ => return None,
+        };
+        let timestamp = Utc::now().timestamp();
+        let nonce = Uuid::new_v4().to_string();
+        let digest = hex::encode(Sha256::digest(body));
+        let signature = hmac_sha256(secret)
+            .chain_update(signed_message(timestamp, &nonce, "POST", path, &digest))
+            .finalize()
+            .into_bytes();
+        Some(vec![
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (DIGEST_HEADER, digest),
+            (
+                SIGNATURE_HEADER,
+                format!("{name}:{}", hex::encode(signature)),
+            ),
+            (NONCE_HEADER, nonce),
+        ])
+    }
+}
+
+fn hmac_sha256(secret: &[u8]) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(secret).expect("any key size is valid")
+}
+
+/// The message a request is signed over. `path` includes the query, so that
+/// it can not be changed either.
+#[must_use]
+pub fn signed_message(
+    timestamp: i64,
+    nonce: &str,
+    method: &str,
+    path: &str,
+    digest: &str,
+) -> String {
+    format!("{timestamp}\n{nonce}\n{method}\n{path}\n{digest}")
+}
+
+pub type SharedKeyring = Arc<KeyringHandle>;
+
+/// The current keys, replaced as a whole on reload.
+#[derive(Debug, Default)]
+pub struct KeyringHandle {
+    path:    Option<PathBuf>,
+    max_age: Duration,
+    keys:    RwLock<Arc<Keyring>>,
+}
+
+impl KeyringHandle {
+    /// Reads `--signing-keys-file`, without it no request is accepted as
+    /// signed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or holds invalid keys.
+    pub fn new(options: &Options) -> EyreResult<Self> {
+        let keys = match &options.signing_keys_file {
+            Some(path) => Keyring::from_file(path)?,
+            None => Keyring::default(),
+        };
+        Ok(Self {
+            path:    options.signing_keys_file.clone(),
+            max_age: options.signature_max_age,
+            keys:    RwLock::new(Arc::new(keys)),
+        })
+    }
+
+    #[must_use]
+    pub fn current(&self) -> Arc<Keyring> {
+        self.keys.read().unwrap().clone()
+    }
+
+    /// Reads the keys file again. The current keys are kept if that fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be read or holds invalid keys.
+    pub fn reload(&self) -> EyreResult<()> {
+        if let Some(path) = &self.path {
+            let keys = Keyring::from_file(path)?;
+            *self.keys.write().unwrap() = Arc::new(keys);
+        }
+        Ok(())
+    }
+}
+
+/// Reloads the signing keys on every reload signal.
+pub async fn reload_on_signal(keyring: SharedKeyring) {
+    if keyring.path.is_none() {
+        return;
+    }
+    let mut signals = match ReloadSignal::new() {
+        Ok(signals) => signals,
+        Err(error) => {
+            error!(
+                ?error,
+                "Failed to listen for {}, signing keys will not be reloaded",
+                reload_signal::NAME
+            );
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        match keyring.reload() {
+            Ok(()) => info!("Reloaded signing keys"),
+            Err(error) => error!(?error, "Failed to reload signing keys"),
+        }
+    }
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum RequestSignatureError {
+    #[error("missing or malformed signature headers")]
+    Malformed,
+    #[error("request timestamp is too far from the server time")]
+    Expired,
+    #[error("body does not match its digest")]
+    DigestMismatch,
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("nonce was already used")]
+    Replayed,
+    #[error("lobby store error: {0}")]
+    LobbyStoreError(#[from] LobbyStoreError),
+    #[error("body of a signed request is larger than {MAX_SIGNED_BODY_SIZE} bytes")]
+    BodyTooLarge,
+    #[error("failed to read request body: {0}")]
+    ReadFailed(String),
+}
+
+impl ErrorCode for RequestSignatureError {
+    fn to_error_code(&self) -> String {
+        format!("RequestSignatureError::{}", <&str>::from(self))
+    }
+}
+
+/// Name of the key a request was signed with, set by
+/// [`verify_signed_request`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedBy(pub String);
+
+/// Checks the signature of requests that carry one, records their nonce in
+/// `nonces` and marks them with [`SignedBy`]. Requests without `X-Signature`
+/// are passed through unchanged.
+pub async fn verify_signed_request(
+    keyring: SharedKeyring,
+    nonces: SharedLobbyStore,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if !request.headers().contains_key(SIGNATURE_HEADER) {
+        return next.run(request).await;
+    }
+    let uri = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or_else(|| request.uri(), |uri| &uri.0);
+    let path = uri
+        .path_and_query()
+        .map_or_else(|| uri.path(), PathAndQuery::as_str)
+        .to_string();
+    let (mut parts, mut body) = request.into_parts();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        match chunk {
+            Ok(chunk) if bytes.len() + chunk.len() <= MAX_SIGNED_BODY_SIZE => {
+                bytes.extend_from_slice(&chunk);
+            }
+            Ok(_) => return RequestSignatureError::BodyTooLarge.into_response(),
+            Err(e) => return RequestSignatureError::ReadFailed(e.to_string()).into_response(),
+        }
+    }
+    let verified = match verify(
+        &keyring.current(),
+        keyring.max_age,
+        &parts.headers,
+        parts.method.as_str(),
+        &path,
+        &bytes,
+    ) {
+        Ok(signature) => claim_nonce(&*nonces, signature).await,
+        Err(e) => Err(e),
+    };
+    match verified {
+        Ok(name) => {
+            parts.extensions.insert(SignedBy(name));
+            next.run(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        }
+        Err(e) => {
+            warn!(%path, error = %e, "rejected signed request");
+            e.into_response()
+        }
+    }
+}
+
+/// A request with a valid signature, whose nonce is yet to be checked.
+#[derive(Debug)]
+struct ValidSignature {
+    key:       String,
+    nonce:     String,
+    /// Until the timestamp of the request is too old.
+    valid_for: Duration,
+}
+
+/// The key of a request, provided it did not use its nonce before. Nonces are
+/// remembered for as long as the request is valid, a replay after that fails
+/// on its timestamp.
+async fn claim_nonce(
+    nonces: &dyn LobbyStore,
+    signature: ValidSignature,
+) -> Result<String, RequestSignatureError> {
+    let nonce = format!("{}:{}", signature.key, signature.nonce);
+    if nonces.claim_nonce(&nonce, signature.valid_for).await? {
+        Ok(signature.key)
+    } else {
+        Err(RequestSignatureError::Replayed)
+    }
+}
+
+fn verify(
+    keyring: &Keyring,
+    max_age: Duration,
+    headers: &HeaderMap,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> Result<ValidSignature, RequestSignatureError> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(RequestSignatureError::Malformed)
+    };
+    let timestamp: i64 = header(TIMESTAMP_HEADER)?
+        .parse()
+        .map_err(|_| RequestSignatureError::Malformed)?;
+    let nonce = header(NONCE_HEADER)?;
+    if nonce.is_empty() || nonce.len() > MAX_NONCE_LENGTH {
+        return Err(RequestSignatureError::Malformed);
+    }
+    let digest = header(DIGEST_HEADER)?;
+    let (name, signature) = header(SIGNATURE_HEADER)?
+        .split_once(':')
+        .ok_or(RequestSignatureError::Malformed)?;
+    let signature = hex::decode(signature).map_err(|_| RequestSignatureError::Malformed)?;
+
+    let now = Utc::now().timestamp();
+    if now.abs_diff(timestamp) > max_age.as_secs() {
+        return Err(RequestSignatureError::Expired);
+    }
+    if !digest.eq_ignore_ascii_case(&hex::encode(Sha256::digest(body))) {
+        return Err(RequestSignatureError::DigestMismatch);
+    }
+    let message = signed_message(timestamp, nonce, method, path, &digest.to_ascii_lowercase());
+    if !keyring.verify(name, message.as_bytes(), &signature) {
+        return Err(RequestSignatureError::InvalidSignature);
+    }
+    let valid_until =
+        timestamp.saturating_add(i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX));
+    Ok(ValidSignature {
+        key:       name.to_string(),
+        nonce:     nonce.to_string(),
+        valid_for: Duration::from_secs(u64::try_from(valid_until - now).unwrap_or(0).max(1)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lobby_store::MemoryLobbyStore;
+    use axum::{middleware::from_fn, routing::post, Extension, Router};
+    use http::StatusCode;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+    use tower::ServiceExt;
+
+    const SEED: [u8; 32] = [7; 32];
+
+    fn keys_file(webhook_key: &str) -> String {
+        let ed25519 = Ed25519KeyPair::from_seed_unchecked(&SEED).unwrap();
+        serde_json::json!({
+            "keys": {
+                "ops": { "hmac": "secret" },
+                "ci": { "ed25519": hex::encode(ed25519.public_key()) },
+            },
+            "webhook_key": webhook_key,
+        })
+        .to_string()
+    }
+
+    fn keyring(contents: &str) -> (NamedTempFile, SharedKeyring) {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        let keyring = KeyringHandle::new(&Options {
+            signing_keys_file: Some(file.path().to_path_buf()),
+            signature_max_age: Duration::from_secs(300),
+        })
+        .unwrap();
+        (file, Arc::new(keyring))
+    }
+
+    fn signed_request(
+        name: &str,
+        timestamp: i64,
+        body: &str,
+        sign: impl Fn(&[u8]) -> Vec<u8>,
+    ) -> Request<Body> {
+        let nonce = Uuid::new_v4().to_string();
+        signed_request_to("/admin/ban", &nonce, name, timestamp, body, sign)
+    }
+
+    fn signed_request_to(
+        uri: &str,
+        nonce: &str,
+        name: &str,
+        timestamp: i64,
+        body: &str,
+        sign: impl Fn(&[u8]) -> Vec<u8>,
+    ) -> Request<Body> {
+        let digest = hex::encode(Sha256::digest(body));
+        let message = signed_message(timestamp, nonce, "POST", uri, &digest);
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(NONCE_HEADER, nonce)
+            .header(DIGEST_HEADER, digest)
+            .header(
+                SIGNATURE_HEADER,
+                format!("{name}:{}", hex::encode(sign(message.as_bytes()))),
+            )
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn hmac(message: &[u8]) -> Vec<u8> {
+        hmac_sha256(b"secret")
+            .chain_update(message)
+            .finalize()
+            .into_bytes()
+            .to_vec()
+    }
+
+    fn ed25519(message: &[u8]) -> Vec<u8> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&SEED).unwrap();
+        key_pair.sign(message).as_ref().to_vec()
+    }
+
+    fn app(keyring: SharedKeyring) -> Router {
+        let nonces: SharedLobbyStore = Arc::new(MemoryLobbyStore::default());
+        Router::new()
+            .route(
+                "/admin/ban",
+                post(
+                    |signed_by: Option<Extension<SignedBy>>, body: String| async move {
+                        format!("{:?} {body}", signed_by.map(|Extension(by)| by.0))
+                    },
+                ),
+            )
+            .layer(from_fn(move |request: Request<Body>, next: Next<Body>| {
+                verify_signed_request(keyring.clone(), nonces.clone(), request, next)
+            }))
+    }
+
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn verifies_signed_requests() {
+        let (_file, keyring) = keyring(&keys_file("ops"));
+        let app = app(keyring);
+        let send = |request: Request<Body>| send(&app, request);
+        let now = Utc::now().timestamp();
+
+        let response = send(signed_request("ops", now, "{}", hmac)).await;
+        assert_eq!(response, (StatusCode::OK, "Some(\"ops\") {}".to_string()));
+        let response = send(signed_request("ci", now, "{}", ed25519)).await;
+        assert_eq!(response, (StatusCode::OK, "Some(\"ci\") {}".to_string()));
+        let unsigned = Request::post("/admin/ban").body(Body::from("{}")).unwrap();
+        assert_eq!(
+            send(unsigned).await,
+            (StatusCode::OK, "None {}".to_string())
+        );
+
+        for request in [
+            signed_request("ops", now - 301, "{}", hmac),
+            signed_request("ci", now, "{}", hmac),
+            signed_request("unknown", now, "{}", hmac),
+        ] {
+            assert_eq!(send(request).await.0, StatusCode::UNAUTHORIZED);
+        }
+        let mut tampered = signed_request("ops", now, "{}", hmac);
+        *tampered.body_mut() = Body::from("{\"uid\": \"git|1|alice\"}");
+        assert_eq!(send(tampered).await.0, StatusCode::UNAUTHORIZED);
+        let mut without_nonce = signed_request("ops", now, "{}", hmac);
+        without_nonce.headers_mut().remove(NONCE_HEADER);
+        assert_eq!(send(without_nonce).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn covers_the_query() {
+        let (_file, keyring) = keyring(&keys_file("ops"));
+        let app = app(keyring);
+        let now = Utc::now().timestamp();
+
+        let request = signed_request_to("/admin/ban?uid=alice", "a", "ops", now, "{}", hmac);
+        assert_eq!(send(&app, request).await.0, StatusCode::OK);
+
+        let mut tampered = signed_request_to("/admin/ban?uid=alice", "b", "ops", now, "{}", hmac);
+        *tampered.uri_mut() = "/admin/ban?uid=bob".parse().unwrap();
+        assert_eq!(send(&app, tampered).await.0, StatusCode::UNAUTHORIZED);
+        let mut added = signed_request_to("/admin/ban", "c", "ops", now, "{}", hmac);
+        *added.uri_mut() = "/admin/ban?uid=bob".parse().unwrap();
+        assert_eq!(send(&app, added).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn rejects_replayed_requests() {
+        let (_file, keyring) = keyring(&keys_file("ops"));
+        let app = app(keyring);
+        let now = Utc::now().timestamp();
+        let request = || signed_request_to("/admin/ban", "nonce", "ops", now, "{}", hmac);
+
+        assert_eq!(send(&app, request()).await.0, StatusCode::OK);
+        assert_eq!(send(&app, request()).await.0, StatusCode::UNAUTHORIZED);
+        // The nonce is per key.
+        let other_key = signed_request_to("/admin/ban", "nonce", "ci", now, "{}", ed25519);
+        assert_eq!(send(&app, other_key).await.0, StatusCode::OK);
+        // A request with an invalid signature does not use up the nonce.
+        let forged = signed_request_to("/admin/ban", "fresh", "ops", now, "{}", ed25519);
+        assert_eq!(send(&app, forged).await.0, StatusCode::UNAUTHORIZED);
+        let request = signed_request_to("/admin/ban", "fresh", "ops", now, "{}", hmac);
+        assert_eq!(send(&app, request).await.0, StatusCode::OK);
+    }
+
+    #[test]
+    fn reloads_and_rotates_keys() {
+        let (file, keyring) = keyring(&keys_file("ops"));
+        let message = b"message";
+        assert!(keyring.current().verify("ops", message, &hmac(message)));
+        assert!(keyring.current().verify("ci", message, &ed25519(message)));
+        let headers = keyring.current().sign_webhook("/hook", b"{}").unwrap();
+        assert!(headers[2].1.starts_with("ops:"));
+
+        // A broken file keeps the previous keys.
+        std::fs::write(file.path(), r#"{"keys": {"ops": {"rsa": "key"}}}"#).unwrap();
+        assert!(keyring.reload().is_err());
+        assert!(keyring.current().verify("ops", message, &hmac(message)));
+
+        std::fs::write(file.path(), r#"{"keys": {"new": {"hmac": "secret"}}}"#).unwrap();
+        keyring.reload().unwrap();
+        assert!(!keyring.current().verify("ops", message, &hmac(message)));
+        assert!(keyring.current().verify("new", message, &hmac(message)));
+        assert_eq!(keyring.current().sign_webhook("/hook", b"{}"), None);
+
+        assert!(Keyring::parse(&keys_file("ci")).is_err());
+        assert!(Keyring::parse(&keys_file("missing")).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Signed reservations of the contribution slot.
+//!
+//! `/lobby/try_contribute` hands the participant that got the slot a
+//! reservation token in the `X-Reservation-Token` header. `/contribute` only
+//! accepts submissions carrying an unexpired token for the current slot, so a
+//! client holding a stale slot can never submit into someone else's.
+//!
+//! The tokens are signed with HMAC-SHA256 under `--reservation-key`, or else
+//! under a key generated by the first sequencer and kept in the database.
+
+use crate::{
+    storage::{PersistentStorage, StorageError},
+    util::Secret,
+};
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+};
+use chrono::Utc;
+use clap::Parser;
+use hmac::{Hmac, Mac};
+use kzg_ceremony_crypto::ErrorCode;
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{sync::Arc, time::Duration};
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+pub const RESERVATION_HEADER: &str = "x-reservation-token";
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Key the reservation tokens are signed with. Without it, the key is
+    /// generated once and kept in the database.
+    #[clap(long, env)]
+    pub reservation_key: Option<Secret>,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ReservationError {
+    #[error("missing reservation token")]
+    Missing,
+    #[error("malformed reservation token")]
+    Malformed,
+    #[error("invalid reservation signature")]
+    InvalidSignature,
+    #[error("reservation expired")]
+    Expired,
+    #[error("reservation is for another slot")]
+    WrongSlot,
+}
+
+impl ErrorCode for ReservationError {
+    fn to_error_code(&self) -> String {
+        format!("ReservationError::{}", <&str>::from(self))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reservation {
+    pub uid:        String,
+    /// Position in the transcript the contribution will take.
+    pub slot:       usize,
+    /// Unix timestamp (in seconds) after which the reservation is void.
+    pub expires_at: i64,
+}
+
+impl Reservation {
+    #[must_use]
+    pub fn new(uid: String, slot: usize, valid_for: Duration) -> Self {
+        let valid_for = i64::try_from(valid_for.as_secs()).unwrap_or(i64::MAX);
+        Self {
+            uid,
+            slot,
+            expires_at: Utc::now().timestamp().saturating_add(valid_for),
+        }
+    }
+}
+
+pub type SharedReservationSigner = Arc<ReservationSigner>;
+
+/// Signs and verifies reservation tokens. The slot is recorded in the
+/// database, so it outlives a restart and is shared by the sequencers using
+/// the database, see [`PersistentStorage::claim_slot`]. The key has to as
+/// well, or the holder of the slot could no longer submit.
+pub struct ReservationSigner {
+    key: Vec<u8>,
+}
+
+/// A signer with a random key of its own.
+impl Default for ReservationSigner {
+    fn default() -> Self {
+        let mut key = [0; 32];
+        thread_rng().fill_bytes(&mut key);
+        Self::new(&key)
+    }
+}
+
+impl ReservationSigner {
+    #[must_use]
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    /// Signs with `--reservation-key`, or else with the key in the database,
+    /// which is generated if there is none yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key can not be read from the database.
+    pub async fn load(
+        options: &Options,
+        storage: &PersistentStorage,
+    ) -> Result<Self, StorageError> {
+        if let Some(key) = &options.reservation_key {
+            return Ok(Self::new(key.get_secret().as_bytes()));
+        }
+        let mut generated = [0; 32];
+        thread_rng().fill_bytes(&mut generated);
+        Ok(Self::new(&storage.reservation_key(&generated).await?))
+    }
+
+    fn mac(&self, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("any key size is valid");
+        mac.update(payload);
+        mac
+    }
+
+    /// Encodes the reservation as `<payload>.<signature>`, both base64url
+    /// encoded.
+    #[must_use]
+    pub fn sign(&self, reservation: &Reservation) -> String {
+        let payload = serde_json::to_vec(reservation).expect("reservations serialize");
+        let signature = self.mac(&payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            base64::encode_config(&payload, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    /// Checks the signature and expiry of a token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the token was not signed by this signer or has
+    /// expired.
+    pub fn verify(&self, token: &str) -> Result<Reservation, ReservationError> {
+        let (payload, signature) = token.split_once('.').ok_or(ReservationError::Malformed)?;
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| ReservationError::Malformed)?;
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| ReservationError::Malformed)?;
+        self.mac(&payload)
+            .verify_slice(&signature)
+            .map_err(|_| ReservationError::InvalidSignature)?;
+        let reservation = serde_json::from_slice::<Reservation>(&payload)
+            .map_err(|_| ReservationError::Malformed)?;
+        if reservation.expires_at < Utc::now().timestamp() {
+            return Err(ReservationError::Expired);
+        }
+        Ok(reservation)
+    }
+}
+
+/// Extractor for the raw `X-Reservation-Token` header.
+pub struct ReservationToken(pub String);
+
+#[async_trait]
+impl<B> FromRequest<B> for ReservationToken
+where
+    B: Send,
+{
+    type Rejection = ReservationError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        req.headers()
+            .get(RESERVATION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(|token| Self(token.to_string()))
+            .ok_or(ReservationError::Missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_and_verifies() {
+        let signer = ReservationSigner::default();
+        let reservation = Reservation::new(
+            "git|1234|test_user".to_string(),
+            3,
+            Duration::from_secs(180),
+        );
+        let token = signer.sign(&reservation);
+        assert_eq!(signer.verify(&token).unwrap(), reservation);
+
+        assert!(matches!(
+            ReservationSigner::default().verify(&token),
+            Err(ReservationError::InvalidSignature)
+        ));
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = Reservation {
+            slot: 4,
+            ..reservation.clone()
+        };
+        let forged = format!(
+            "{}.{}",
+            base64::encode_config(
+                serde_json::to_vec(&forged).unwrap(),
+                base64::URL_SAFE_NO_PAD
+            ),
+            signature
+        );
+        assert!(matches!(
+            signer.verify(&forged),
+            Err(ReservationError::InvalidSignature)
+        ));
+        assert!(matches!(
+            signer.verify("garbage"),
+            Err(ReservationError::Malformed)
+        ));
+
+        let expired = Reservation {
+            expires_at: Utc::now().timestamp() - 1,
+            ..reservation
+        };
+        assert!(matches!(
+            signer.verify(&signer.sign(&expired)),
+            Err(ReservationError::Expired)
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn shares_the_key_through_the_database() {
+        let options = crate::test_util::test_options();
+        let storage = crate::storage::storage_client(&options.storage)
+            .await
+            .unwrap();
+        let reservation = Reservation::new(
+            "git|1234|test_user".to_string(),
+            3,
+            Duration::from_secs(180),
+        );
+
+        let signer = ReservationSigner::load(&options.reservation, &storage)
+            .await
+            .unwrap();
+        let token = signer.sign(&reservation);
+        let restarted = ReservationSigner::load(&options.reservation, &storage)
+            .await
+            .unwrap();
+        assert_eq!(restarted.verify(&token).unwrap(), reservation);
+
+        let configured = Options {
+            reservation_key: Some("configured".parse().unwrap()),
+        };
+        let configured = ReservationSigner::load(&configured, &storage)
+            .await
+            .unwrap();
+        assert!(matches!(
+            configured.verify(&token),
+            Err(ReservationError::InvalidSignature)
+        ));
+        assert_eq!(
+            configured
+                .verify(&ReservationSigner::new(b"configured").sign(&reservation))
+                .unwrap(),
+            reservation
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! Verification of contributions in a separate worker process.
+//!
+//! With `--verification-sandbox`, the point decoding and pairing checks of
+//! `/contribute` run in a child process instead of the sequencer, so that a
+//! crafted contribution that crashes the verifier or exhausts its memory only
+//! takes down the child, and the lobby and the transcript survive. The child
+//! is the sequencer binary started with [`WORKER_ARG`], on the curve of the
+//! sequencer. On Linux, before it reads any of the request, it limits its
+//! address space to `--verification-sandbox-memory` MiB, disables core dumps
+//! and file writes, and installs a seccomp filter that denies it the network,
+//! starting programs and tracing other processes.
+//!
+//! The checks only need the current powers, so the sequencer does not hand the
+//! child the transcript. It writes a line of JSON with the number of
+//! participants and the identity of the contributor to the standard input of
+//! the child, followed by the contribution base and the contribution in the
+//! binary encoding of [`crate::contribution_format`], each preceded by its
+//! length as a little endian `u64`. The child answers on its standard output
+//! with a line of JSON per [`WorkerMessage`]: one for every check as it is
+//! done, then the outcome, or why it failed, which the sequencer logs.
+
+use crate::{contribution_format, run_on_curve, Engine};
+use clap::Parser;
+use kzg_ceremony_crypto::{
+    curve, signature::identity::Identity, BatchContribution, BatchTranscript, CeremoniesError,
+    Check, Curve, ErrorCode, VerifiedContribution,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    io::{self, BufRead, Read, Write},
+    num::ParseIntError,
+    path::PathBuf,
+    process::{ExitStatus, Stdio},
+    str::FromStr,
+    time::Duration,
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::Command,
+};
+
+/// First argument that starts the binary as a verification worker instead of
+/// the CLI, see [`run_worker`]. The second one is the memory limit in MiB, the
+/// third the curve.
+pub const WORKER_ARG: &str = "__verification-worker";
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Verify contributions in a separate, constrained worker process.
+    #[clap(long, env)]
+    pub verification_sandbox: bool,
+
+    /// Address space the verification worker may use, in MiB.
+    #[clap(long, env, default_value = "16384")]
+    pub verification_sandbox_memory: u64,
+
+    /// Seconds after which the verification worker is killed.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "300")]
+    pub verification_sandbox_timeout: Duration,
+
+    /// Binary the verification worker runs, the sequencer's own by default.
+    #[clap(long, env)]
+    pub verification_sandbox_program: Option<PathBuf>,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum SandboxError {
+    #[error("failed to start the verification worker: {0}")]
+    Spawn(#[source] io::Error),
+    #[error("failed to talk to the verification worker: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid message from the verification worker: {0}")]
+    Protocol(#[from] serde_json::Error),
+    #[error("the verification worker exited with {0}")]
+    Crashed(ExitStatus),
+    #[error("the verification worker exited without an outcome")]
+    NoOutcome,
+    #[error("the verification worker failed: {0}")]
+    Failed(String),
+    #[error("the verification worker took too long")]
+    TimedOut,
+}
+
+impl ErrorCode for SandboxError {
+    fn to_error_code(&self) -> String {
+        format!("SandboxError::{}", <&str>::from(self))
+    }
+}
+
+/// First line of the request, see the [module documentation](self).
+#[derive(Debug, Serialize, Deserialize)]
+struct WorkerRequest {
+    num_participants: usize,
+    identity:         Identity,
+}
+
+/// What the worker sends back, one per line.
+#[derive(Debug, Serialize, Deserialize)]
+enum WorkerMessage {
+    /// The sub-ceremony with the index is done with the check.
+    Checked(usize, Check),
+    Done(Result<VerifiedContribution, CeremoniesError>),
+    /// The worker could not verify the contribution.
+    Failed(String),
+}
+
+/// Encodes the request to verify `contribution` against `transcript`.
+fn request(
+    transcript: &BatchTranscript,
+    contribution: &BatchContribution,
+    identity: &Identity,
+) -> Result<Vec<u8>, serde_json::Error> {
+    let mut request = serde_json::to_vec(&WorkerRequest {
+        num_participants: transcript.num_participants(),
+        identity:         identity.clone(),
+    })?;
+    request.push(b'\n');
+    for part in [
+        contribution_format::encode(&transcript.contribution()),
+        contribution_format::encode(contribution),
+    ] {
+        request.extend_from_slice(&(part.len() as u64).to_le_bytes());
+        request.extend_from_slice(&part);
+    }
+    Ok(request)
+}
+
+/// Reads a part of the request, preceded by its length.
+fn read_part(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 8];
+    input.read_exact(&mut len)?;
+    let len = usize::try_from(u64::from_le_bytes(len))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "request part too long"))?;
+    let mut part = vec![0; len];
+    input.read_exact(&mut part)?;
+    Ok(part)
+}
+
+/// Verifies `contribution` against `transcript` in a worker process running
+/// `threads` threads, like [`BatchTranscript::verify_observed`].
+///
+/// # Errors
+///
+/// Returns an error if the worker can not be started, crashes or runs out of
+/// time before it answers. The error of the contribution, if it is invalid,
+/// is the inner one.
+pub async fn verify(
+    options: &Options,
+    transcript: &BatchTranscript,
+    contribution: &BatchContribution,
+    identity: &Identity,
+    threads: usize,
+    observe: impl Fn(usize, Check) + Sync,
+) -> Result<Result<VerifiedContribution, CeremoniesError>, SandboxError> {
+    let request = request(transcript, contribution, identity)?;
+    let program = match &options.verification_sandbox_program {
+        Some(program) => program.clone(),
+        None => std::env::current_exe().map_err(SandboxError::Spawn)?,
+    };
+    let mut child = Command::new(program)
+        .arg(WORKER_ARG)
+        .arg(options.verification_sandbox_memory.to_string())
+        .arg(curve().to_string())
+        .env("RAYON_NUM_THREADS", threads.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(SandboxError::Spawn)?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let observe = &observe;
+    let exchange = async move {
+        // The worker only answers once it read the whole request.
+        stdin.write_all(&request).await?;
+        drop(stdin);
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            match serde_json::from_str(&line)? {
+                WorkerMessage::Checked(index, check) => observe(index, check),
+                WorkerMessage::Done(result) => return Ok(Some(result)),
+                WorkerMessage::Failed(error) => return Err(SandboxError::Failed(error)),
+            }
+        }
+        Ok::<_, SandboxError>(None)
+    };
+    let outcome = tokio::time::timeout(options.verification_sandbox_timeout, exchange).await;
+    let outcome = if let Ok(outcome) = outcome {
+        outcome
+    } else {
+        child.kill().await?;
+        return Err(SandboxError::TimedOut);
+    };
+    let status = child.wait().await?;
+    match outcome {
+        Ok(Some(result)) => Ok(result),
+        // The worker exits right after it explains why it failed.
+        Err(error @ SandboxError::Failed(_)) => Err(error),
+        // A worker that crashes while it is read from or written to closes
+        // the pipes, which is not the error to report.
+        _ if !status.success() => Err(SandboxError::Crashed(status)),
+        Ok(None) => Err(SandboxError::NoOutcome),
+        Err(error) => Err(error),
+    }
+}
+
+/// Runs the verification worker on the standard input and output, and exits
+/// the process.
+pub fn run_worker() -> ! {
+    let memory_limit = std::env::args().nth(2).and_then(|limit| limit.parse().ok());
+    let curve = std::env::args()
+        .nth(3)
+        .and_then(|curve| curve.parse::<Curve>().ok());
+    let result = memory_limit
+        .ok_or_else(|| Box::<dyn Error>::from("missing memory limit"))
+        .and_then(|memory_limit| {
+            let curve = curve.ok_or("missing curve")?;
+            run_on_curve(curve).map_err(|error| error.to_string())?;
+            Ok(memory_limit)
+        })
+        .and_then(confine)
+        .and_then(|()| answer(io::stdin().lock(), &mut io::stdout()));
+    if let Err(error) = result {
+        // The sequencer logs the error, the worker has no logging of its own.
+        let message = WorkerMessage::Failed(error.to_string());
+        if let Ok(line) = serde_json::to_string(&message) {
+            let _ = writeln!(io::stdout(), "{line}");
+        }
+        std::process::exit(1);
+    }
+    std::process::exit(0);
+}
+
+/// Reads the request from `input` and writes the messages to `output`.
+fn answer(input: impl Read, output: &mut (impl Write + Send)) -> Result<(), Box<dyn Error>> {
+    let mut input = io::BufReader::new(input);
+    let mut header = String::new();
+    input.read_line(&mut header)?;
+    let request: WorkerRequest = serde_json::from_str(&header)?;
+    let base = contribution_format::decode(&read_part(&mut input)?)?;
+    let contribution = contribution_format::decode(&read_part(&mut input)?)?;
+    let output = std::sync::Mutex::new(output);
+    let send = |message: &WorkerMessage| -> io::Result<()> {
+        let mut output = output.lock().expect("lock poisoned");
+        serde_json::to_writer(&mut **output, message)?;
+        output.write_all(b"\n")?;
+        output.flush()
+    };
+    let result = BatchTranscript::verify_on_base::<Engine>(
+        base,
+        request.num_participants,
+        contribution,
+        request.identity,
+        &|index, check| {
+            // The sequencer gets the outcome anyway, or notices the broken
+            // pipe.
+            let _ = send(&WorkerMessage::Checked(index, check));
+        },
+    );
+    send(&WorkerMessage::Done(result))?;
+    Ok(())
+}
+
+/// Limits what the worker can do, see the [module documentation](self).
+#[cfg(target_os = "linux")]
+fn confine(memory_limit: u64) -> Result<(), Box<dyn Error>> {
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter};
+
+    let limit = |resource, value| {
+        let limit = libc::rlimit {
+            rlim_cur: value,
+            rlim_max: value,
+        };
+        // SAFETY: `limit` outlives the call.
+        if unsafe { libc::setrlimit(resource, &limit) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    };
+    limit(libc::RLIMIT_AS, memory_limit.saturating_mul(1024 * 1024))?;
+    limit(libc::RLIMIT_CORE, 0)?;
+    limit(libc::RLIMIT_FSIZE, 0)?;
+
+    let denied = [
+        libc::SYS_execve,
+        libc::SYS_execveat,
+        libc::SYS_socket,
+        libc::SYS_socketpair,
+        libc::SYS_connect,
+        libc::SYS_bind,
+        libc::SYS_listen,
+        libc::SYS_accept,
+        libc::SYS_accept4,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+    ];
+    let filter: BpfProgram = SeccompFilter::new(
+        denied
+            .into_iter()
+            .map(|syscall| (syscall, vec![]))
+            .collect(),
+        SeccompAction::Allow,
+        SeccompAction::Errno(u32::try_from(libc::EPERM)?),
+        std::env::consts::ARCH.try_into()?,
+    )?
+    .try_into()?;
+    seccompiler::apply_filter(&filter)?;
+    Ok(())
+}
+
+/// Only the separate process protects the sequencer on other systems.
+#[cfg(not(target_os = "linux"))]
+#[allow(clippy::unnecessary_wraps)]
+fn confine(_memory_limit: u64) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+
+    fn messages(output: &[u8]) -> Vec<WorkerMessage> {
+        output
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn answers_with_checks_and_outcome() {
+        let transcript = test_transcript();
+        let identity = Identity::Github {
+            id:       1234,
+            username: "test_user".to_string(),
+        };
+        let contribution = valid_contribution(&transcript, 1);
+        let request = request(&transcript, &contribution, &identity).unwrap();
+        let mut output = Vec::new();
+        answer(request.as_slice(), &mut output).unwrap();
+        let messages = messages(&output);
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], WorkerMessage::Checked(0, _)));
+        let verified = match messages.into_iter().last() {
+            Some(WorkerMessage::Done(Ok(verified))) => verified,
+            other => panic!("unexpected outcome {other:?}"),
+        };
+        let mut transcript = transcript;
+        transcript.apply(verified).unwrap();
+        assert_eq!(transcript.num_participants(), 1);
+
+        let mut invalid = valid_contribution(&test_transcript(), 1);
+        invalid.contributions.clear();
+        let request = request(&transcript, &invalid, &identity).unwrap();
+        let mut output = Vec::new();
+        answer(request.as_slice(), &mut output).unwrap();
+        assert!(matches!(messages(&output).as_slice(), [
+            WorkerMessage::Done(Err(CeremoniesError::UnexpectedNumContributions(1, 0)))
+        ]));
+
+        // A request cut short fails the worker instead of the contribution.
+        let mut output = Vec::new();
+        assert!(answer(&request[..request.len() - 1], &mut output).is_err());
+        assert!(output.is_empty());
+    }
+}
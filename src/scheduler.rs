@@ -0,0 +1,302 @@
+//! Durable jobs, kept in the `jobs` table.
+//!
+//! Work that must happen later, or be retried, is stored as a job of a kind
+//! and a key, so that it survives a crash or restart:
+//!
+//! * `slot_expiry` expires the contribution slot of a session, keyed by the
+//!   hash of the session id, when the contributor misses their deadline or
+//!   heartbeat.
+//! * `backup` uploads a checkpoint, keyed by its number of contributions, to
+//!   the object store.
+//! * `prune_checkpoint` removes a checkpoint that left the retention window
+//!   from the checkpoint directory and the object store.
+//!
+//! Each ceremony runs a scheduler that looks for due jobs every
+//! `--job-poll-interval` seconds, and wakes up on time for jobs it already
+//! knows of. Failed jobs are retried after `--job-retry-delay` seconds, up to
+//! `--job-max-attempts` times.
+//!
+//! A slot is held in the memory of the sequencer that handed it out. A due
+//! `slot_expiry` job for a slot this sequencer does not know is left to its
+//! owner for `--orphaned-slot-grace` seconds; after that the sequencer holding
+//! it is taken to be gone, and the contribution is recorded as expired with
+//! the reason `orphaned` and its slot in the lobby store is freed.
+
+use crate::{
+    audit::AuditLog,
+    checkpoint::SharedCheckpointer,
+    lobby::{SharedLobbyState, SlotExpiry},
+    storage::{PersistentStorage, StorageError, StoredJob},
+};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use eyre::{eyre, Result as EyreResult};
+use std::{num::ParseIntError, str::FromStr, time::Duration};
+use strum::{EnumString, IntoStaticStr};
+use tracing::{error, info, warn};
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Seconds between looks for due jobs.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "1")]
+    pub job_poll_interval: Duration,
+
+    /// Seconds before a failed job is tried again.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "30")]
+    pub job_retry_delay: Duration,
+
+    /// Failed attempts after which a job is dropped.
+    #[clap(long, env, default_value = "10")]
+    pub job_max_attempts: usize,
+
+    /// Seconds a due slot expiry is left to the sequencer holding the slot,
+    /// before the slot is expired as orphaned.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "60")]
+    pub orphaned_slot_grace: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoStaticStr, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum JobKind {
+    SlotExpiry,
+    Backup,
+    PruneCheckpoint,
+}
+
+/// The time `delay` from now.
+#[must_use]
+pub fn after(delay: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(delay)
+        .ok()
+        .and_then(|delay| Utc::now().checked_add_signed(delay))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+/// What became of a job that ran.
+enum Outcome {
+    Done,
+    RunAgainAt(DateTime<Utc>),
+    /// Left for another sequencer, looked at again with the next poll.
+    Skipped,
+}
+
+pub struct Scheduler {
+    options:      Options,
+    storage:      PersistentStorage,
+    lobby_state:  SharedLobbyState,
+    checkpointer: SharedCheckpointer,
+    audit:        AuditLog,
+}
+
+impl Scheduler {
+    #[must_use]
+    pub fn new(
+        options: Options,
+        storage: PersistentStorage,
+        lobby_state: SharedLobbyState,
+        checkpointer: SharedCheckpointer,
+        audit: AuditLog,
+    ) -> Self {
+        Self {
+            options,
+            storage,
+            lobby_state,
+            checkpointer,
+            audit,
+        }
+    }
+
+    /// Runs due jobs until the process ends.
+    pub async fn run(self) {
+        loop {
+            let next_at = match self.run_due().await {
+                Ok(next_at) => next_at,
+                Err(error) => {
+                    error!(?error, "failed to run due jobs");
+                    None
+                }
+            };
+            let wait = next_at
+                .and_then(|next_at| (next_at - Utc::now()).to_std().ok())
+                .map_or(self.options.job_poll_interval, |wait| {
+                    wait.min(self.options.job_poll_interval)
+                });
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Runs the jobs that are due. Returns when the next known one is.
+    async fn run_due(&self) -> Result<Option<DateTime<Utc>>, StorageError> {
+        let now = Utc::now();
+        for job in self.storage.due_jobs(now).await? {
+            let result = self.run_job(&job, now).await;
+            let kind = job.kind.as_str();
+            match result {
+                Ok(Outcome::Done) => {
+                    self.storage.delete_job(kind, &job.key).await?;
+                }
+                Ok(Outcome::RunAgainAt(run_at)) => {
+                    self.storage.reschedule_job(kind, &job.key, run_at).await?;
+                }
+                Ok(Outcome::Skipped) => {}
+                Err(error) if job.attempts + 1 >= self.options.job_max_attempts => {
+                    error!(kind, key = %job.key, ?error, "job failed, giving up");
+                    self.storage.delete_job(kind, &job.key).await?;
+                }
+                Err(error) => {
+                    warn!(kind, key = %job.key, ?error, "job failed, retrying");
+                    let retry_at = after(self.options.job_retry_delay);
+                    self.storage
+                        .fail_job(kind, &job.key, &error.to_string(), retry_at)
+                        .await?;
+                }
+            }
+        }
+        self.storage.next_job_at(now).await
+    }
+
+    async fn run_job(&self, job: &StoredJob, now: DateTime<Utc>) -> EyreResult<Outcome> {
+        let kind = match JobKind::from_str(&job.kind) {
+            Ok(kind) => kind,
+            Err(_) => {
+                // Left for a newer sequencer sharing the database.
+                warn!(kind = %job.kind, "unknown job kind");
+                return Ok(Outcome::Skipped);
+            }
+        };
+        match kind {
+            JobKind::SlotExpiry => self.expire_slot(job, now).await,
+            JobKind::Backup => {
+                self.checkpointer.upload(checkpoint_number(job)?).await?;
+                Ok(Outcome::Done)
+            }
+            JobKind::PruneCheckpoint => {
+                self.checkpointer.prune(checkpoint_number(job)?).await?;
+                Ok(Outcome::Done)
+            }
+        }
+    }
+
+    async fn expire_slot(&self, job: &StoredJob, now: DateTime<Utc>) -> EyreResult<Outcome> {
+        match self
+            .lobby_state
+            .expire_slot(&job.key, &self.storage, &self.audit)
+            .await
+        {
+            SlotExpiry::Expired => Ok(Outcome::Done),
+            SlotExpiry::Pending(delay) => Ok(Outcome::RunAgainAt(after(delay))),
+            SlotExpiry::Unknown => {
+                let orphaned_at = chrono::Duration::from_std(self.options.orphaned_slot_grace)
+                    .ok()
+                    .and_then(|grace| job.run_at.checked_add_signed(grace))
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC);
+                if now < orphaned_at {
+                    return Ok(Outcome::Skipped);
+                }
+                // Only the first of the sequencers sharing the database finds
+                // the contribution still in progress.
+                if self
+                    .lobby_state
+                    .expire_orphaned_slot(&job.key, job.payload.clone(), &self.storage, &self.audit)
+                    .await?
+                {
+                    info!(uid = %job.payload, "Expired orphaned contribution slot");
+                }
+                Ok(Outcome::Done)
+            }
+        }
+    }
+}
+
+fn checkpoint_number(job: &StoredJob) -> EyreResult<usize> {
+    job.key
+        .parse()
+        .map_err(|_| eyre!("invalid checkpoint number {}", job.key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checkpoint::Checkpointer, lobby::LobbyEvent, storage::storage_client,
+        test_util::test_options,
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn expires_orphaned_slots() {
+        let options = test_options();
+        let storage = storage_client(&options.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(options.lobby.clone()).with_jobs(storage.clone());
+        let mut events = lobby_state.subscribe();
+        let scheduler = Scheduler::new(
+            options.scheduler.clone(),
+            storage.clone(),
+            lobby_state,
+            Arc::new(Checkpointer::new(&options.checkpoint).unwrap()),
+            AuditLog::default(),
+        );
+
+        // Left behind by a sequencer that crashed while the slots were held.
+        for uid in ["git|1|alice", "git|2|bob"] {
+            storage.insert_contributor(uid, None).await.unwrap();
+        }
+        let kind = JobKind::SlotExpiry.into();
+        let overdue = Utc::now() - chrono::Duration::minutes(5);
+        storage
+            .schedule_job(kind, "alice", "git|1|alice", overdue)
+            .await
+            .unwrap();
+        // Still within the grace of its owner.
+        storage
+            .schedule_job(kind, "bob", "git|2|bob", Utc::now())
+            .await
+            .unwrap();
+
+        scheduler.run_due().await.unwrap();
+        assert_eq!(events.recv().await.unwrap(), LobbyEvent::SlotOpened);
+        assert!(!storage
+            .expire_unfinished_contribution("git|1|alice")
+            .await
+            .unwrap());
+        let pending = storage.due_jobs(Utc::now()).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].key, "bob");
+    }
+
+    #[tokio::test]
+    async fn retries_failed_jobs() {
+        let mut options = test_options();
+        options.scheduler.job_max_attempts = 2;
+        let storage = storage_client(&options.storage).await.unwrap();
+        let scheduler = Scheduler::new(
+            options.scheduler.clone(),
+            storage.clone(),
+            SharedLobbyState::new(options.lobby.clone()),
+            Arc::new(Checkpointer::new(&options.checkpoint).unwrap()),
+            AuditLog::default(),
+        );
+
+        storage
+            .schedule_job(JobKind::Backup.into(), "latest", "", Utc::now())
+            .await
+            .unwrap();
+        let next_at = scheduler.run_due().await.unwrap().unwrap();
+        assert!(next_at > Utc::now());
+        storage
+            .reschedule_job(JobKind::Backup.into(), "latest", Utc::now())
+            .await
+            .unwrap();
+        let job = &storage.due_jobs(Utc::now()).await.unwrap()[0];
+        assert_eq!(job.attempts, 1);
+
+        // The second failure is the last.
+        assert_eq!(scheduler.run_due().await.unwrap(), None);
+        assert!(storage.due_jobs(Utc::now()).await.unwrap().is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::{identity, lobby::SharedLobbyState, oauth::SharedAuthState};
 use async_session::async_trait;
 use axum::{
     extract::{FromRequest, RequestParts},
@@ -6,7 +7,11 @@ use axum::{
 use headers::{authorization::Bearer, Authorization};
 use kzg_ceremony_crypto::{signature::identity::Identity, ErrorCode};
 use serde::{Deserialize, Serialize};
-use std::fmt::{Display, Formatter};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::{Display, Formatter},
+    time::Duration,
+};
 use strum::IntoStaticStr;
 use thiserror::Error;
 use tokio::time::Instant;
@@ -21,6 +26,12 @@ impl SessionId {
     pub fn new() -> Self {
         Self(Uuid::new_v4().to_string())
     }
+
+    /// Hex encoded SHA-256 of the session id, under which the session is
+    /// persisted.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.0.as_bytes()))
+    }
 }
 
 impl Default for SessionId {
@@ -57,9 +68,9 @@ impl IdToken {
     // The sub field is used as a unique identifier
     // For example, see: https://developers.google.com/identity/protocols/oauth2/openid-connect#obtainuserinfo
     // We can use this to identify when a user signs in with the same
-    // login and signup
+    // login and signup. See `crate::identity` for its form.
     pub fn unique_identifier(&self) -> String {
-        self.identity.unique_id()
+        identity::uid(&self.identity)
     }
 }
 
@@ -71,6 +82,19 @@ pub struct SessionInfo {
     // Indicates whether an early /lobby/try_contribute call is accepted.
     // (only allowed right after authentication)
     pub is_first_ping_attempt: bool,
+    // The time the user entered the lobby, used to compute their position.
+    pub lobby_entered_at:      Option<Instant>,
+    // Anti-sybil score assigned at authentication, stored with the
+    // contributor.
+    pub eligibility_score:     Option<u32>,
+    // Eligibility rules the user failed at authentication, `None` for
+    // sessions restored after a restart.
+    pub failed_rules:          Option<Vec<&'static str>>,
+    // Challenge the next ping has to answer, see `crate::checkin_proof`.
+    pub checkin_challenge:     Option<String>,
+    // Minimum time between the user's pings, as hinted to them when the
+    // lobby was full. `None` uses the check-in frequency.
+    pub min_poll_interval:     Option<Duration>,
 }
 
 #[async_trait]
@@ -87,6 +111,26 @@ where
                 .await
                 .map_err(|_| SessionError::InvalidSessionId)?;
 
-        Ok(Self(bearer.token().to_owned()))
+        let session_id = Self(bearer.token().to_owned());
+
+        // Sessions restored after a restart are only known by their hash until
+        // their token is seen again.
+        let lobby_state = req.extensions().get::<SharedLobbyState>().cloned();
+        if let Some(lobby_state) = lobby_state {
+            if let Some(uid) = lobby_state.claim_restored_session(&session_id).await {
+                let auth_state = req.extensions().get::<SharedAuthState>().cloned();
+                if let Some(auth_state) = auth_state {
+                    auth_state
+                        .write()
+                        .await
+                        .unique_id_session
+                        .entry(uid)
+                        .or_insert_with(|| session_id.clone());
+                }
+            }
+        }
+        #[cfg(feature = "dev")]
+        crate::oauth::mint_session(req.extensions(), &session_id).await;
+        Ok(session_id)
     }
 }
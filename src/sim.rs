@@ -0,0 +1,425 @@
+//! Simulated ceremonies, with the `sim` feature.
+//!
+//! [`run`] starts a sequencer in `--mode test` on a free port and drives
+//! simulated contributors through its HTTP API. Besides honest contributors
+//! there are ones that let their slot expire, submit invalid points or submit
+//! their contribution twice. The contributors take their turns one after the
+//! other, in an order and with entropy derived from the seed, so that a seed
+//! always produces the same transcript. Once all had their turn, the
+//! transcript served by the sequencer is verified from scratch and must hold
+//! exactly the contributions that should have been accepted.
+
+use crate::{
+    api::v1::error_response::ApiError, oauth::StaticTokenProvider, reservation::RESERVATION_HEADER,
+    start_server, test_mode, verification::VerificationStatus, Engine, Options,
+};
+use clap::Parser;
+use eyre::{bail, ensure, eyre, Result as EyreResult, WrapErr};
+use http::StatusCode;
+use kzg_ceremony_crypto::{BatchContribution, BatchTranscript, Entropy, Secret, G1};
+use once_cell::sync::Lazy;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::{
+    sync::{oneshot, Mutex},
+    time::{sleep, Instant},
+};
+use url::Url;
+
+/// How often the lobby is polled for the slot.
+const POLL_INTERVAL: Duration = Duration::from_millis(150);
+
+/// How long a contributor waits for the slot, on top of the compute deadline
+/// of the previous contributor.
+const SLOT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Test mode keeps its files in one directory per process, so simulations run
+/// one at a time.
+static RUNNING: Lazy<Mutex<()>> = Lazy::new(Mutex::default);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Behavior {
+    /// Contributes as expected.
+    Honest,
+    /// Takes the slot and only submits once the compute deadline passed.
+    Timeout,
+    /// Submits a contribution with a power that is not a curve point.
+    InvalidPoints,
+    /// Submits the contribution a second time once it was accepted, then
+    /// tries to sign in again.
+    Duplicate,
+}
+
+impl Behavior {
+    /// Whether the contribution ends up in the transcript.
+    const fn is_accepted(self) -> bool {
+        matches!(self, Self::Honest | Self::Duplicate)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub seed:             u64,
+    pub honest:           usize,
+    pub timeouts:         usize,
+    pub invalid_points:   usize,
+    pub duplicates:       usize,
+    /// `--compute-deadline` of the sequencer, which every [`Behavior::Timeout`]
+    /// contributor waits out.
+    pub compute_deadline: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seed:             0,
+            honest:           4,
+            timeouts:         1,
+            invalid_points:   1,
+            duplicates:       1,
+            compute_deadline: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contributor {
+    /// Token of the test account, see [`StaticTokenProvider`].
+    pub token:    String,
+    pub behavior: Behavior,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    /// In the order they took their turn.
+    pub contributors: Vec<Contributor>,
+    pub transcript:   BatchTranscript,
+}
+
+/// Runs a simulated ceremony.
+///
+/// # Errors
+///
+/// Returns an error if the sequencer fails to start, or if it answers any
+/// request of a contributor other than it should.
+pub async fn run(config: &Config) -> EyreResult<Report> {
+    let _running = RUNNING.lock().await;
+    let dir = test_mode::test_dir();
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)
+            .wrap_err_with(|| format!("failed to clear {}", dir.display()))?;
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let contributors = contributors(config, &mut rng);
+    let (shutdown, on_shutdown) = oneshot::channel::<()>();
+    let (addr, server) = start_server(options(config), async move {
+        on_shutdown.await.ok();
+    })
+    .await?;
+    let server = tokio::spawn(server);
+
+    let simulator = Simulator {
+        client:           reqwest::Client::new(),
+        base:             Url::parse(&format!("http://{addr}/"))?,
+        compute_deadline: config.compute_deadline,
+    };
+    let result = simulator.run(&contributors, &mut rng).await;
+    shutdown.send(()).ok();
+    server.await??;
+    Ok(Report {
+        contributors,
+        transcript: result?,
+    })
+}
+
+/// The contributors of `config` in a random order.
+fn contributors(config: &Config, rng: &mut StdRng) -> Vec<Contributor> {
+    let mut behaviors = [
+        (Behavior::Honest, config.honest),
+        (Behavior::Timeout, config.timeouts),
+        (Behavior::InvalidPoints, config.invalid_points),
+        (Behavior::Duplicate, config.duplicates),
+    ]
+    .into_iter()
+    .flat_map(|(behavior, count)| std::iter::repeat(behavior).take(count))
+    .collect::<Vec<_>>();
+    behaviors.shuffle(rng);
+    behaviors
+        .into_iter()
+        .enumerate()
+        .map(|(index, behavior)| Contributor {
+            token: format!("sim-{}-{index}", config.seed),
+            behavior,
+        })
+        .collect()
+}
+
+fn options(config: &Config) -> Options {
+    let mut options = Options::parse_from([
+        "kzg-ceremony-sequencer",
+        "--mode",
+        "test",
+        "--server",
+        "http://127.0.0.1:0/",
+    ]);
+    options.lobby.compute_deadline = config.compute_deadline;
+    options.lobby.lobby_checkin_frequency = Duration::from_millis(500);
+    options.lobby.lobby_checkin_tolerance = Duration::from_millis(400);
+    options.lobby.lobby_flush_interval = Duration::from_millis(100);
+    // All contributors connect from localhost.
+    options.rate_limit.rate_limit_default = 0;
+    options.rate_limit.rate_limit_auth = 0;
+    options.rate_limit.rate_limit_try_contribute = 0;
+    options
+}
+
+struct Simulator {
+    client:           reqwest::Client,
+    base:             Url,
+    compute_deadline: Duration,
+}
+
+impl Simulator {
+    async fn run(
+        &self,
+        contributors: &[Contributor],
+        rng: &mut StdRng,
+    ) -> EyreResult<BatchTranscript> {
+        for contributor in contributors {
+            let entropy = Secret::new(rng.gen::<[u8; 32]>());
+            self.contribute(contributor, &entropy)
+                .await
+                .wrap_err_with(|| format!("{contributor:?} failed"))?;
+        }
+
+        let transcript: BatchTranscript = self
+            .client
+            .get(self.url("info/current_state")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        transcript
+            .verify_self::<Engine>()
+            .map_err(|err| eyre!("invalid transcript: {err}"))?;
+        let expected = contributors
+            .iter()
+            .filter(|contributor| contributor.behavior.is_accepted())
+            .map(|contributor| StaticTokenProvider::identity(&contributor.token))
+            .collect::<Vec<_>>();
+        ensure!(
+            transcript.participant_ids.get(1..) == Some(&expected[..]),
+            "transcript has participants {:?}, expected {:?}",
+            transcript.participant_ids,
+            expected
+        );
+        Ok(transcript)
+    }
+
+    async fn contribute(&self, contributor: &Contributor, entropy: &Entropy) -> EyreResult<()> {
+        let identity = StaticTokenProvider::identity(&contributor.token);
+        let session_id = self.sign_in(&contributor.token).await?;
+        let (mut contribution, reservation) = self.await_slot(&session_id).await?;
+        contribution
+            .add_entropy::<Engine>(entropy, &identity)
+            .map_err(|err| eyre!("failed to add entropy: {err}"))?;
+
+        match contributor.behavior {
+            Behavior::Honest => {
+                let id = self
+                    .submit_accepted(&session_id, &reservation, &contribution)
+                    .await?;
+                self.expect_valid(&id).await
+            }
+            Behavior::Timeout => {
+                sleep(self.compute_deadline + Duration::from_millis(500)).await;
+                let response = self
+                    .submit(&session_id, &reservation, &contribution)
+                    .await?;
+                ensure!(
+                    response.status().is_client_error(),
+                    "late contribution answered with {}",
+                    response.status()
+                );
+                Ok(())
+            }
+            Behavior::InvalidPoints => {
+                contribution.contributions[0].powers.g1[1] = G1([0; 48]);
+                let response = self
+                    .submit(&session_id, &reservation, &contribution)
+                    .await?;
+                if response.status().is_client_error() {
+                    return Ok(());
+                }
+                let id = accepted_id(response).await?;
+                match self.await_verification(&id).await? {
+                    VerificationStatus::Invalid { .. } => Ok(()),
+                    status => bail!("invalid points verified as {status:?}"),
+                }
+            }
+            Behavior::Duplicate => {
+                let id = self
+                    .submit_accepted(&session_id, &reservation, &contribution)
+                    .await?;
+                self.expect_valid(&id).await?;
+                let again = self
+                    .submit_accepted(&session_id, &reservation, &contribution)
+                    .await?;
+                ensure!(
+                    again == id,
+                    "resubmission got verification {again} instead of {id}"
+                );
+                let response = self.request_sign_in(&contributor.token).await?;
+                expect_error(response, ApiError::AlreadyContributed).await
+            }
+        }
+    }
+
+    fn url(&self, path: &str) -> EyreResult<Url> {
+        Ok(self.base.join(path)?)
+    }
+
+    /// Signs in with the test account of `token`.
+    async fn request_sign_in(&self, token: &str) -> EyreResult<reqwest::Response> {
+        let links: Value = self
+            .client
+            .get(self.url("auth/request_link")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let link = links
+            .get("test_auth_url")
+            .and_then(Value::as_str)
+            .ok_or_else(|| eyre!("no test_auth_url in {links}"))?;
+        let state = Url::parse(link)?
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, state)| state.into_owned())
+            .ok_or_else(|| eyre!("no state in {link}"))?;
+        Ok(self
+            .client
+            .get(self.url("auth/callback/test")?)
+            .query(&[("code", token), ("state", &state)])
+            .send()
+            .await?)
+    }
+
+    async fn sign_in(&self, token: &str) -> EyreResult<String> {
+        let response: Value = self
+            .request_sign_in(token)
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        response
+            .get("session_id")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| eyre!("no session_id in {response}"))
+    }
+
+    /// Polls `/lobby/try_contribute` until the slot is free.
+    async fn await_slot(&self, session_id: &str) -> EyreResult<(BatchContribution, String)> {
+        let deadline = Instant::now() + self.compute_deadline + SLOT_TIMEOUT;
+        loop {
+            let response = self
+                .client
+                .post(self.url("lobby/try_contribute")?)
+                .bearer_auth(session_id)
+                .send()
+                .await?;
+            if let Some(reservation) = response.headers().get(RESERVATION_HEADER) {
+                let reservation = reservation.to_str()?.to_string();
+                return Ok((response.json().await?, reservation));
+            }
+            let body: Value = response.json().await?;
+            let code = body.get("code").and_then(Value::as_str);
+            ensure!(
+                code == Some(ApiError::AnotherContributionInProgress.code())
+                    || code == Some(ApiError::LobbyRateLimited.code()),
+                "unexpected answer while waiting for the slot: {body}"
+            );
+            ensure!(Instant::now() < deadline, "the slot did not become free");
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn submit(
+        &self,
+        session_id: &str,
+        reservation: &str,
+        contribution: &BatchContribution,
+    ) -> EyreResult<reqwest::Response> {
+        Ok(self
+            .client
+            .post(self.url("contribute")?)
+            .bearer_auth(session_id)
+            .header(RESERVATION_HEADER, reservation)
+            .json(contribution)
+            .send()
+            .await?)
+    }
+
+    /// Submits `contribution` and returns its verification id.
+    async fn submit_accepted(
+        &self,
+        session_id: &str,
+        reservation: &str,
+        contribution: &BatchContribution,
+    ) -> EyreResult<String> {
+        accepted_id(self.submit(session_id, reservation, contribution).await?).await
+    }
+
+    async fn await_verification(&self, id: &str) -> EyreResult<VerificationStatus> {
+        loop {
+            let status = self
+                .client
+                .get(self.url(&format!("contribute/status/{id}"))?)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            if status != VerificationStatus::Pending {
+                return Ok(status);
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    async fn expect_valid(&self, id: &str) -> EyreResult<()> {
+        match self.await_verification(id).await? {
+            VerificationStatus::Valid { .. } => Ok(()),
+            status => bail!("contribution verified as {status:?}"),
+        }
+    }
+}
+
+/// The verification id of a contribution the sequencer accepted.
+async fn accepted_id(response: reqwest::Response) -> EyreResult<String> {
+    let status = response.status();
+    let body: Value = response.json().await?;
+    ensure!(
+        status == StatusCode::ACCEPTED,
+        "contribution answered with {status}: {body}"
+    );
+    body.get("verification_id")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| eyre!("no verification_id in {body}"))
+}
+
+async fn expect_error(response: reqwest::Response, expected: ApiError) -> EyreResult<()> {
+    let body: Value = response.json().await?;
+    ensure!(
+        body.get("code").and_then(Value::as_str) == Some(expected.code()),
+        "expected {}, got {body}",
+        expected.code()
+    );
+    Ok(())
+}
@@ -0,0 +1,474 @@
+//! Filters that may turn away sign-ins and lobby joins before they cost
+//! anything.
+//!
+//! A [`SpamFilter`] sees the stage of the request, the address of the client,
+//! the request headers and, once the identity provider vouched for the
+//! participant, their identity and evidence. It allows the request or vetoes
+//! it, which answers it with `SEQ-AUTH-014`. Filters are asked in order of
+//! registration and the first veto wins. A filter that can not decide, e.g.
+//! because the service it asks is down, fails the request with
+//! `SEQ-AUTH-015`.
+//!
+//! The bundled [`CaptchaFilter`] is registered with `--captcha-provider`. It
+//! requires a token of hCaptcha or Cloudflare Turnstile in the
+//! `X-Captcha-Token` header of `/auth/request_link` and checks it with the
+//! siteverify endpoint of the service. Other filters are plugged in with
+//! [`register`], before or after the server started.
+
+pub use crate::eligibility::Evidence;
+use crate::{audit::Audit, metrics::SPAM_FILTER_VETOES, util::Secret};
+use axum::{
+    async_trait,
+    extract::{FromRequest, RequestParts},
+};
+use clap::{Parser, ValueEnum};
+use eyre::{bail, Result as EyreResult};
+use http::HeaderMap;
+use kzg_ceremony_crypto::{signature::identity::Identity, ErrorCode};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::{
+    convert::Infallible,
+    net::IpAddr,
+    sync::{Arc, RwLock},
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tracing::warn;
+use url::Url;
+
+pub const CAPTCHA_HEADER: &str = "x-captcha-token";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CaptchaProvider {
+    Hcaptcha,
+    Turnstile,
+}
+
+impl CaptchaProvider {
+    const fn verify_url(self) -> &'static str {
+        match self {
+            Self::Hcaptcha => "https://api.hcaptcha.com/siteverify",
+            Self::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Captcha service whose token `/auth/request_link` requires in the
+    /// `X-Captcha-Token` header. No token is required without it.
+    #[clap(long, env, value_enum)]
+    pub captcha_provider: Option<CaptchaProvider>,
+
+    /// Secret key of the site at the captcha service.
+    #[clap(long, env)]
+    pub captcha_secret: Option<Secret>,
+
+    /// Site key, sent along so that hCaptcha checks the token was issued for
+    /// the site.
+    #[clap(long, env)]
+    pub captcha_site_key: Option<String>,
+
+    /// Replaces the siteverify endpoint of the captcha service.
+    #[clap(long, env)]
+    pub captcha_verify_url: Option<Url>,
+}
+
+/// Where in the sign-in a request is checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoStaticStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum SpamStage {
+    /// `/auth/request_link`, before the participant signs in.
+    RequestLink,
+    /// `/auth/callback/:provider`, once the provider vouched for the
+    /// participant.
+    AuthCallback,
+    /// `/lobby/try_contribute`, when the participant is not in the lobby yet.
+    LobbyJoin,
+}
+
+/// What a filter sees of a request.
+#[derive(Debug)]
+pub struct SpamRequest<'a> {
+    pub stage:    SpamStage,
+    pub ip:       Option<IpAddr>,
+    pub headers:  &'a HeaderMap,
+    /// The identity vouched for by the provider, from the callback on.
+    pub identity: Option<&'a Identity>,
+    /// The evidence the provider gathered, on the callback.
+    pub evidence: Option<&'a Evidence>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Turns the request away, for the reason given.
+    Veto(String),
+    /// The filter could not decide, for the reason given.
+    Unavailable(String),
+}
+
+#[async_trait]
+pub trait SpamFilter: Send + Sync {
+    /// Name of the filter in errors and metrics.
+    fn name(&self) -> &'static str;
+
+    async fn check(&self, request: &SpamRequest<'_>) -> Verdict;
+}
+
+#[derive(Clone, Debug, Error, IntoStaticStr)]
+pub enum SpamFilterError {
+    #[error("turned away by {filter}: {reason}")]
+    Vetoed {
+        filter: &'static str,
+        reason: String,
+    },
+    #[error("spam filter {filter} is unavailable: {reason}")]
+    Unavailable {
+        filter: &'static str,
+        reason: String,
+    },
+}
+
+impl ErrorCode for SpamFilterError {
+    fn to_error_code(&self) -> String {
+        format!("SpamFilterError::{}", <&str>::from(self))
+    }
+}
+
+/// Filters registered with [`register`], for all ceremonies.
+static REGISTERED: Lazy<RwLock<Vec<Arc<dyn SpamFilter>>>> = Lazy::new(RwLock::default);
+
+/// Adds `filter` to those every guarded request passes, after the bundled
+/// ones.
+pub fn register(filter: Arc<dyn SpamFilter>) {
+    REGISTERED.write().unwrap().push(filter);
+}
+
+/// The bundled filters configured by the options, followed by the
+/// [`register`]ed ones.
+#[derive(Clone, Default)]
+pub struct SpamFilters {
+    bundled: Vec<Arc<dyn SpamFilter>>,
+}
+
+impl SpamFilters {
+    /// Builds the bundled filters from the command line options.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `--captcha-provider` is set without
+    /// `--captcha-secret`.
+    pub fn new(options: &Options, client: reqwest::Client) -> EyreResult<Self> {
+        let mut bundled: Vec<Arc<dyn SpamFilter>> = Vec::new();
+        if let Some(provider) = options.captcha_provider {
+            let secret = match &options.captcha_secret {
+                Some(secret) => secret.clone(),
+                None => bail!("--captcha-provider needs --captcha-secret"),
+            };
+            bundled.push(Arc::new(CaptchaFilter {
+                verify_url: options
+                    .captcha_verify_url
+                    .clone()
+                    .unwrap_or_else(|| Url::parse(provider.verify_url()).unwrap()),
+                secret,
+                site_key: options.captcha_site_key.clone(),
+                client,
+            }));
+        }
+        Ok(Self::with_filters(bundled))
+    }
+
+    #[must_use]
+    pub fn with_filters(bundled: Vec<Arc<dyn SpamFilter>>) -> Self {
+        Self { bundled }
+    }
+
+    /// Asks the filters in order, until one does not allow the request.
+    ///
+    /// # Errors
+    ///
+    /// Returns the veto, or that a filter could not decide.
+    pub async fn check(&self, request: &SpamRequest<'_>) -> Result<(), SpamFilterError> {
+        let registered = REGISTERED.read().unwrap().clone();
+        for filter in self.bundled.iter().chain(&registered) {
+            match filter.check(request).await {
+                Verdict::Allow => {}
+                Verdict::Veto(reason) => {
+                    SPAM_FILTER_VETOES
+                        .with_label_values(&[filter.name(), request.stage.into()])
+                        .inc();
+                    return Err(SpamFilterError::Vetoed {
+                        filter: filter.name(),
+                        reason,
+                    });
+                }
+                Verdict::Unavailable(reason) => {
+                    warn!(filter = filter.name(), %reason, "Spam filter is unavailable");
+                    return Err(SpamFilterError::Unavailable {
+                        filter: filter.name(),
+                        reason,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extractor for what the filters see of a request, to check it with
+/// [`Self::check`].
+#[derive(Clone, Default)]
+pub struct SpamGuard {
+    filters: SpamFilters,
+    ip:      Option<IpAddr>,
+    headers: HeaderMap,
+}
+
+impl SpamGuard {
+    /// Checks the request at `stage`, with the identity and evidence known by
+    /// then.
+    ///
+    /// # Errors
+    ///
+    /// Returns the veto, or that a filter could not decide.
+    pub async fn check(
+        &self,
+        stage: SpamStage,
+        identity: Option<&Identity>,
+        evidence: Option<&Evidence>,
+    ) -> Result<(), SpamFilterError> {
+        self.filters
+            .check(&SpamRequest {
+                stage,
+                ip: self.ip,
+                headers: &self.headers,
+                identity,
+                evidence,
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for SpamGuard
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        // The address the audit log records.
+        let Audit { ip, .. } = Audit::from_request(req).await?;
+        Ok(Self {
+            filters: req
+                .extensions()
+                .get::<SpamFilters>()
+                .cloned()
+                .unwrap_or_default(),
+            ip,
+            headers: req.headers().clone(),
+        })
+    }
+}
+
+/// Requires a valid hCaptcha or Turnstile token on `/auth/request_link`.
+pub struct CaptchaFilter {
+    verify_url: Url,
+    secret:     Secret,
+    site_key:   Option<String>,
+    client:     reqwest::Client,
+}
+
+/// Response of the siteverify endpoints, which hCaptcha and Turnstile share.
+#[derive(Debug, Deserialize)]
+struct SiteVerifyResponse {
+    success:     bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+#[async_trait]
+impl SpamFilter for CaptchaFilter {
+    fn name(&self) -> &'static str {
+        "captcha"
+    }
+
+    async fn check(&self, request: &SpamRequest<'_>) -> Verdict {
+        // Tokens are single use, so only the first step of the sign-in
+        // carries one.
+        if request.stage != SpamStage::RequestLink {
+            return Verdict::Allow;
+        }
+        let token = match request
+            .headers
+            .get(CAPTCHA_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|token| !token.is_empty())
+        {
+            Some(token) => token,
+            None => return Verdict::Veto("captcha token missing".to_string()),
+        };
+        let mut form = vec![("secret", self.secret.get_secret().to_string())];
+        form.push(("response", token.to_string()));
+        if let Some(ip) = request.ip {
+            form.push(("remoteip", ip.to_string()));
+        }
+        if let Some(site_key) = &self.site_key {
+            form.push(("sitekey", site_key.clone()));
+        }
+        let response = self
+            .client
+            .post(self.verify_url.clone())
+            .form(&form)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        let response = match response {
+            Ok(response) => response.json::<SiteVerifyResponse>().await,
+            Err(err) => Err(err),
+        };
+        match response {
+            Ok(response) if response.success => Verdict::Allow,
+            Ok(response) => Verdict::Veto(format!(
+                "captcha rejected: {}",
+                response.error_codes.join(", ")
+            )),
+            Err(err) => Verdict::Unavailable(format!("captcha verification failed: {err}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Form, routing::post, Json, Router, Server};
+    use http::HeaderValue;
+    use serde_json::json;
+    use std::{collections::HashMap, net::TcpListener};
+
+    /// Vetoes GitHub accounts once they signed in.
+    struct NoGithub;
+
+    #[async_trait]
+    impl SpamFilter for NoGithub {
+        fn name(&self) -> &'static str {
+            "no_github"
+        }
+
+        async fn check(&self, request: &SpamRequest<'_>) -> Verdict {
+            match request.identity {
+                Some(Identity::Github { .. }) => Verdict::Veto("no github accounts".to_string()),
+                _ => Verdict::Allow,
+            }
+        }
+    }
+
+    fn request<'a>(stage: SpamStage, headers: &'a HeaderMap) -> SpamRequest<'a> {
+        SpamRequest {
+            stage,
+            ip: Some("203.0.113.9".parse().unwrap()),
+            headers,
+            identity: None,
+            evidence: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn asks_filters_in_order() {
+        let filters = SpamFilters::with_filters(vec![Arc::new(NoGithub)]);
+        let headers = HeaderMap::new();
+        assert!(filters
+            .check(&request(SpamStage::RequestLink, &headers))
+            .await
+            .is_ok());
+        let github = Identity::Github {
+            id:       1,
+            username: "spammer".to_string(),
+        };
+        let vetoed = filters
+            .check(&SpamRequest {
+                identity: Some(&github),
+                ..request(SpamStage::AuthCallback, &headers)
+            })
+            .await;
+        assert!(matches!(
+            vetoed,
+            Err(SpamFilterError::Vetoed { filter: "no_github", reason }) if reason == "no github accounts"
+        ));
+    }
+
+    #[tokio::test]
+    async fn verifies_captcha_tokens() {
+        let app = Router::new().route(
+            "/siteverify",
+            post(|Form(form): Form<HashMap<String, String>>| async move {
+                assert_eq!(form["secret"], "secret");
+                assert_eq!(form["remoteip"], "203.0.113.9");
+                Json(if form["response"] == "valid" {
+                    json!({"success": true})
+                } else {
+                    json!({"success": false, "error-codes": ["invalid-input-response"]})
+                })
+            }),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        let filters = SpamFilters::new(
+            &Options {
+                captcha_provider:   Some(CaptchaProvider::Turnstile),
+                captcha_secret:     Some("secret".parse().unwrap()),
+                captcha_site_key:   None,
+                captcha_verify_url: Some(format!("http://{addr}/siteverify").parse().unwrap()),
+            },
+            reqwest::Client::new(),
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        let missing = filters
+            .check(&request(SpamStage::RequestLink, &headers))
+            .await;
+        assert!(
+            matches!(missing, Err(SpamFilterError::Vetoed { reason, .. }) if reason == "captcha token missing")
+        );
+        // Only the link needs a token.
+        assert!(filters
+            .check(&request(SpamStage::LobbyJoin, &headers))
+            .await
+            .is_ok());
+
+        headers.insert(CAPTCHA_HEADER, HeaderValue::from_static("valid"));
+        assert!(filters
+            .check(&request(SpamStage::RequestLink, &headers))
+            .await
+            .is_ok());
+        headers.insert(CAPTCHA_HEADER, HeaderValue::from_static("forged"));
+        let rejected = filters
+            .check(&request(SpamStage::RequestLink, &headers))
+            .await;
+        assert!(matches!(
+            rejected,
+            Err(SpamFilterError::Vetoed { filter: "captcha", reason })
+                if reason == "captcha rejected: invalid-input-response"
+        ));
+    }
+
+    #[test]
+    fn requires_a_secret() {
+        let options = Options {
+            captcha_provider:   Some(CaptchaProvider::Hcaptcha),
+            captcha_secret:     None,
+            captcha_site_key:   None,
+            captcha_verify_url: None,
+        };
+        assert!(SpamFilters::new(&options, reqwest::Client::new()).is_err());
+    }
+}
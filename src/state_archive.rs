@@ -0,0 +1,456 @@
+//! Moving a sequencer to another host mid-ceremony.
+//!
+//! `export-state --out snapshot.tar.zst` bundles what the sequencer needs to
+//! continue the ceremony elsewhere into a zstd compressed tar archive: the
+//! transcript, a consistent copy of the Sqlite database, which also holds the
+//! sessions, and the signing key if one is configured. `manifest.json` in the
+//! archive lists the SHA-256 of every file, the number of participants of the
+//! transcript and the address of the key.
+//!
+//! `import-state --input snapshot.tar.zst` checks the files against the
+//! manifest and verifies the transcript before it writes anything, then puts
+//! the files where `--transcript-file`, `--database-url` and
+//! `--signing-key-file` say. Existing files are only replaced with `--force`.
+//! Contributions made after the export are not in the archive, so the
+//! sequencer should be stopped or paused first.
+
+use crate::{
+    keys, run_on_curve,
+    storage::{self, storage_client},
+    Engine,
+};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use eyre::{ensure, eyre, Result as EyreResult, WrapErr};
+use kzg_ceremony_crypto::{BatchTranscript, Curve};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const TRANSCRIPT: &str = "transcript.json";
+const DATABASE: &str = "storage.db";
+const SIGNING_KEY: &str = "signing_key";
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct ExportOptions {
+    /// Archive to write.
+    #[clap(long)]
+    pub out: PathBuf,
+
+    /// Transcript file of the sequencer.
+    #[clap(long, env, default_value = "./transcript.json")]
+    pub transcript_file: PathBuf,
+
+    /// Curve of the transcript, as for `serve --curve`.
+    #[clap(long, env, default_value = "bls12-381")]
+    pub curve: Curve,
+
+    #[clap(flatten)]
+    pub storage: storage::Options,
+
+    #[clap(flatten)]
+    pub keys: keys::Options,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct ImportOptions {
+    /// Archive written by `export-state`.
+    #[clap(long)]
+    pub input: PathBuf,
+
+    /// Replace existing files.
+    #[clap(long)]
+    pub force: bool,
+
+    /// Where to put the transcript.
+    #[clap(long, env, default_value = "./transcript.json")]
+    pub transcript_file: PathBuf,
+
+    /// Curve of the transcript, as for `serve --curve`.
+    #[clap(long, env, default_value = "bls12-381")]
+    pub curve: Curve,
+
+    #[clap(flatten)]
+    pub storage: storage::Options,
+
+    #[clap(flatten)]
+    pub keys: keys::Options,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Manifest {
+    version:           u32,
+    created_at:        DateTime<Utc>,
+    num_participants:  usize,
+    sequencer_address: Option<String>,
+    /// Hex encoded SHA-256 of every other file in the archive, by name.
+    files:             BTreeMap<String, String>,
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+fn sqlite_path(options: &storage::Options) -> EyreResult<PathBuf> {
+    options.sqlite_path().ok_or_else(|| {
+        eyre!("only Sqlite databases in a file are supported, use the tools of the database")
+    })
+}
+
+/// Writes the state of the sequencer to an archive.
+///
+/// # Errors
+///
+/// Returns an error if the transcript, the database or the key can not be
+/// read, or the archive can not be written.
+pub async fn export_state(mut options: ExportOptions) -> EyreResult<()> {
+    run_on_curve(options.curve)?;
+    sqlite_path(&options.storage)?;
+    let transcript = tokio::fs::read(&options.transcript_file)
+        .await
+        .wrap_err_with(|| format!("failed to read {}", options.transcript_file.display()))?;
+    let num_participants = serde_json::from_slice::<BatchTranscript>(&transcript)
+        .wrap_err("failed to parse the transcript")?
+        .num_participants();
+
+    // The database has to be up to date already, exporting does not migrate.
+    options.storage.database_migrate = false;
+    let storage = storage_client(&options.storage).await?;
+    let copy = options.out.with_extension("db.partial");
+    remove_if_exists(&copy)?;
+    storage.vacuum_into(&copy).await?;
+    storage.close().await?;
+    let database = tokio::fs::read(&copy).await?;
+    tokio::fs::remove_file(&copy).await?;
+
+    let mut files = vec![(TRANSCRIPT, transcript), (DATABASE, database)];
+    let key = keys::configured_key(&options.keys)?;
+    let sequencer_address = match &key {
+        Some(key) => Some(keys::address_of(key)?.to_string()),
+        None => {
+            warn!("No signing key configured, the archive holds none");
+            None
+        }
+    };
+    if let Some(key) = key {
+        files.push((SIGNING_KEY, key.into_bytes()));
+    }
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        created_at: Utc::now(),
+        num_participants,
+        sequencer_address,
+        files: files
+            .iter()
+            .map(|(name, content)| ((*name).to_string(), sha256_hex(content)))
+            .collect(),
+    };
+    files.insert(0, (MANIFEST, serde_json::to_vec_pretty(&manifest)?));
+
+    let out = options.out.clone();
+    tokio::task::spawn_blocking(move || write_archive(&out, &files)).await??;
+    info!(
+        num_participants,
+        sequencer_address = ?manifest.sequencer_address,
+        out = %options.out.display(),
+        "State exported"
+    );
+    Ok(())
+}
+
+fn write_archive(path: &Path, files: &[(&str, Vec<u8>)]) -> EyreResult<()> {
+    let partial = path.with_extension("partial");
+    let file = File::create(&partial)
+        .wrap_err_with(|| format!("failed to create {}", partial.display()))?;
+    let mut builder = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+    for (name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o600);
+        builder.append_data(&mut header, name, content.as_slice())?;
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    fs::rename(&partial, path).wrap_err_with(|| format!("failed to write {}", path.display()))?;
+    Ok(())
+}
+
+fn read_archive(path: &Path) -> EyreResult<BTreeMap<String, Vec<u8>>> {
+    let file = File::open(path).wrap_err_with(|| format!("failed to open {}", path.display()))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut files = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        ensure!(
+            files.insert(name.clone(), content).is_none(),
+            "{name} is in the archive twice"
+        );
+    }
+    Ok(files)
+}
+
+/// Checks that the archive holds exactly the files of its manifest.
+fn check_files(manifest: &Manifest, files: &BTreeMap<String, Vec<u8>>) -> EyreResult<()> {
+    ensure!(
+        manifest.version == FORMAT_VERSION,
+        "unsupported archive version {}",
+        manifest.version
+    );
+    for (name, content) in files {
+        if name == MANIFEST {
+            continue;
+        }
+        let hash = manifest
+            .files
+            .get(name)
+            .ok_or_else(|| eyre!("{name} is not in the manifest"))?;
+        ensure!(
+            sha256_hex(content) == *hash,
+            "{name} does not match its hash in the manifest"
+        );
+    }
+    for name in manifest.files.keys() {
+        ensure!(
+            files.contains_key(name),
+            "{name} is missing from the archive"
+        );
+    }
+    Ok(())
+}
+
+/// Restores the state of a sequencer from an archive.
+///
+/// # Errors
+///
+/// Returns an error if the archive is corrupt, the transcript is invalid, a
+/// file exists already without `--force`, or the files can not be written.
+pub async fn import_state(options: ImportOptions) -> EyreResult<()> {
+    run_on_curve(options.curve)?;
+    let database_path = sqlite_path(&options.storage)?;
+    let input = options.input.clone();
+    let mut files = tokio::task::spawn_blocking(move || read_archive(&input)).await??;
+    let manifest: Manifest = serde_json::from_slice(
+        files
+            .get(MANIFEST)
+            .ok_or_else(|| eyre!("{MANIFEST} is missing from the archive"))?,
+    )
+    .wrap_err("failed to parse the manifest")?;
+    check_files(&manifest, &files)?;
+    let mut take = |name: &str| {
+        files
+            .remove(name)
+            .ok_or_else(|| eyre!("{name} is missing from the archive"))
+    };
+    let transcript = take(TRANSCRIPT)?;
+    let database = take(DATABASE)?;
+    let key = take(SIGNING_KEY).ok();
+
+    let parsed: BatchTranscript =
+        serde_json::from_slice(&transcript).wrap_err("failed to parse the transcript")?;
+    ensure!(
+        parsed.num_participants() == manifest.num_participants,
+        "the transcript has {} participants, the manifest {}",
+        parsed.num_participants(),
+        manifest.num_participants
+    );
+    info!(
+        num_participants = manifest.num_participants,
+        "Verifying transcript"
+    );
+    tokio::task::spawn_blocking(move || parsed.verify_self::<Engine>())
+        .await?
+        .wrap_err("transcript is invalid")?;
+
+    let mut targets = vec![
+        (options.transcript_file.clone(), transcript, 0o644),
+        (database_path.clone(), database, 0o600),
+    ];
+    if let Some(key) = key {
+        let address = keys::address_of(String::from_utf8(key.clone())?.trim())?.to_string();
+        ensure!(
+            manifest.sequencer_address.as_ref() == Some(&address),
+            "the signing key does not match the address in the manifest"
+        );
+        let path = options
+            .keys
+            .signing_key_file
+            .clone()
+            .ok_or_else(|| eyre!("the archive holds a signing key, pass --signing-key-file"))?;
+        targets.push((path, key, 0o600));
+    }
+    if !options.force {
+        for (path, ..) in &targets {
+            ensure!(
+                !path.exists(),
+                "{} exists, pass --force to replace it",
+                path.display()
+            );
+        }
+    }
+
+    // The write-ahead log of a replaced database must not be applied to the
+    // imported one.
+    for suffix in ["-wal", "-shm"] {
+        let mut path = database_path.clone().into_os_string();
+        path.push(suffix);
+        remove_if_exists(Path::new(&path))?;
+    }
+    for (path, content, mode) in &targets {
+        write_file(path, content, *mode)?;
+    }
+
+    // Also migrates a database exported by an older version.
+    let storage = storage_client(&options.storage).await?;
+    let contributions = storage.count_finished_contributions().await?;
+    storage.close().await?;
+    info!(
+        num_participants = manifest.num_participants,
+        contributions,
+        sequencer_address = ?manifest.sequencer_address,
+        "State imported"
+    );
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> EyreResult<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != ErrorKind::NotFound => {
+            Err(error).wrap_err_with(|| format!("failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Replaces `path` with `content` in one step.
+fn write_file(path: &Path, content: &[u8], mode: u32) -> EyreResult<()> {
+    let partial = path.with_extension("partial");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+    #[cfg(not(unix))]
+    let _ = mode;
+    options
+        .open(&partial)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&partial, path))
+        .wrap_err_with(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+    use kzg_ceremony_crypto::signature::identity::Identity;
+    use tempfile::tempdir;
+
+    #[cfg(feature = "sqlite")]
+    fn host(dir: &Path) -> (PathBuf, storage::Options, keys::Options) {
+        let mut storage = crate::test_util::test_options().storage;
+        storage.database_url = format!("sqlite://{}", dir.join("storage.db").display());
+        let keys = keys::Options {
+            signing_key:         None,
+            signing_key_file:    Some(dir.join("signing_key")),
+            signing_kms_key_id:  None,
+            signing_kms_region:  None,
+            signing_key_overlap: std::time::Duration::from_secs(3600),
+        };
+        (dir.join("transcript.json"), storage, keys)
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn moves_state_between_hosts() {
+        let (from, to) = (tempdir().unwrap(), tempdir().unwrap());
+        let (transcript_file, storage, keys) = host(from.path());
+        let mut transcript = test_transcript();
+        let contribution = valid_contribution(&transcript, 1);
+        transcript
+            .verify_add::<Engine>(contribution, Identity::None)
+            .unwrap();
+        fs::write(&transcript_file, serde_json::to_vec(&transcript).unwrap()).unwrap();
+        let db = storage_client(&storage).await.unwrap();
+        db.insert_contributor("git|1|alice", None).await.unwrap();
+        db.finish_contribution("git|1|alice").await.unwrap();
+        db.close().await.unwrap();
+        let address = crate::keys::Keys::new(&keys).unwrap().address();
+
+        let out = from.path().join("snapshot.tar.zst");
+        export_state(ExportOptions {
+            out: out.clone(),
+            transcript_file: transcript_file.clone(),
+            curve: Curve::Bls12_381,
+            storage,
+            keys: keys.clone(),
+        })
+        .await
+        .unwrap();
+
+        let (imported_transcript, storage, imported_keys) = host(to.path());
+        let import = |force| ImportOptions {
+            input: out.clone(),
+            force,
+            transcript_file: imported_transcript.clone(),
+            curve: Curve::Bls12_381,
+            storage: storage.clone(),
+            keys: imported_keys.clone(),
+        };
+        import_state(import(false)).await.unwrap();
+        assert_eq!(
+            fs::read(&imported_transcript).unwrap(),
+            fs::read(&transcript_file).unwrap()
+        );
+        assert_eq!(
+            crate::keys::Keys::new(&imported_keys).unwrap().address(),
+            address
+        );
+        let db = storage_client(&storage).await.unwrap();
+        assert!(db.has_contributed("git|1|alice").await.unwrap());
+        db.close().await.unwrap();
+
+        assert!(import_state(import(false)).await.is_err());
+        import_state(import(true)).await.unwrap();
+    }
+
+    #[test]
+    fn rejects_tampered_files() {
+        let files = BTreeMap::from([
+            (MANIFEST.to_string(), Vec::new()),
+            (TRANSCRIPT.to_string(), b"{}".to_vec()),
+        ]);
+        let mut manifest = Manifest {
+            version:           FORMAT_VERSION,
+            created_at:        Utc::now(),
+            num_participants:  0,
+            sequencer_address: None,
+            files:             BTreeMap::from([(TRANSCRIPT.to_string(), sha256_hex(b"{}"))]),
+        };
+        check_files(&manifest, &files).unwrap();
+
+        manifest
+            .files
+            .insert(TRANSCRIPT.to_string(), sha256_hex(b"[]"));
+        assert!(check_files(&manifest, &files).is_err());
+        manifest
+            .files
+            .insert(TRANSCRIPT.to_string(), sha256_hex(b"{}"));
+        manifest.files.insert(DATABASE.to_string(), sha256_hex(b""));
+        assert!(check_files(&manifest, &files).is_err());
+    }
+}
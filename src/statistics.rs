@@ -0,0 +1,134 @@
+//! Snapshot of the statistics served by `/info/statistics`.
+//!
+//! Aggregating the statistics reads the whole `contributors` table. Doing so
+//! for every request would have the readers of a popular page contend with
+//! the writes of finishing contributions, on SQLite for the database lock.
+//! Instead, the statistics are aggregated once at startup and then every
+//! `--statistics-refresh-interval` seconds by a single task, and requests are
+//! served the last snapshot from memory without touching the database.
+
+use crate::storage::{ContributionStatistics, PersistentStorage, StorageError};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use std::{
+    num::ParseIntError,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tracing::error;
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Seconds between refreshes of the `/info/statistics` snapshot.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "60")]
+    pub statistics_refresh_interval: Duration,
+}
+
+/// The statistics as aggregated at `refreshed_at`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Statistics {
+    pub contributions: ContributionStatistics,
+    /// The rows of `auth_funnel`, see [`crate::auth_funnel::funnels`].
+    pub auth_funnel:   Vec<(String, String, String, u64)>,
+    pub refreshed_at:  DateTime<Utc>,
+}
+
+impl Statistics {
+    async fn aggregate(storage: &PersistentStorage) -> Result<Self, StorageError> {
+        let refreshed_at = Utc::now();
+        Ok(Self {
+            contributions: storage.contribution_statistics().await?,
+            auth_funnel: storage.auth_funnel().await?,
+            refreshed_at,
+        })
+    }
+}
+
+pub type SharedStatistics = Arc<StatisticsSnapshot>;
+
+#[derive(Debug)]
+pub struct StatisticsSnapshot {
+    latest: RwLock<Arc<Statistics>>,
+}
+
+impl StatisticsSnapshot {
+    /// Aggregates the first snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statistics can not be read from storage.
+    pub async fn load(storage: &PersistentStorage) -> Result<Self, StorageError> {
+        Ok(Self {
+            latest: RwLock::new(Arc::new(Statistics::aggregate(storage).await?)),
+        })
+    }
+
+    #[must_use]
+    pub fn latest(&self) -> Arc<Statistics> {
+        self.latest.read().expect("lock poisoned").clone()
+    }
+
+    /// Replaces the snapshot with freshly aggregated statistics.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the statistics can not be read from storage, in
+    /// which case the last snapshot is kept.
+    pub async fn refresh(&self, storage: &PersistentStorage) -> Result<(), StorageError> {
+        let statistics = Arc::new(Statistics::aggregate(storage).await?);
+        *self.latest.write().expect("lock poisoned") = statistics;
+        Ok(())
+    }
+}
+
+pub async fn refresh_on_interval(
+    options: Options,
+    snapshot: SharedStatistics,
+    storage: PersistentStorage,
+) {
+    loop {
+        tokio::time::sleep(options.statistics_refresh_interval).await;
+        if let Err(error) = snapshot.refresh(&storage).await {
+            error!(
+                ?error,
+                "failed to refresh the statistics, serving the last ones"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage::storage_client, test_util::test_options};
+
+    #[tokio::test]
+    async fn serves_the_last_snapshot() {
+        let options = test_options();
+        let storage = storage_client(&options.storage).await.unwrap();
+        let snapshot = StatisticsSnapshot::load(&storage).await.unwrap();
+        let first = snapshot.latest();
+        assert_eq!(first.contributions, ContributionStatistics::default());
+
+        storage
+            .insert_contributor("git|1234|test_user", None)
+            .await
+            .unwrap();
+        storage
+            .finish_contribution("git|1234|test_user")
+            .await
+            .unwrap();
+        assert_eq!(snapshot.latest(), first);
+
+        snapshot.refresh(&storage).await.unwrap();
+        let latest = snapshot.latest();
+        assert_eq!(latest.contributions.contributions, 1);
+        assert!(latest.refreshed_at >= first.refreshed_at);
+    }
+}
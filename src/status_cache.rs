@@ -0,0 +1,150 @@
+//! Cache of the `/info/status` response.
+//!
+//! Every waiting client polls `/info/status`. Building it reads the lobby
+//! size from the lobby store, a round trip to Redis or the lobby lock, which
+//! with tens of thousands of clients polling is a hotspot. The response is
+//! therefore built once, tagged, and served from memory until the lobby
+//! publishes an event, see [`crate::lobby::SharedLobbyState::generation`].
+//! Replicas sharing the lobby store do not see each other's events, so the
+//! cached response is also rebuilt after `--status-cache-ttl` milliseconds at
+//! the latest.
+
+use axum::body::Bytes;
+use clap::Parser;
+use std::{
+    num::ParseIntError,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+
+fn duration_from_millis(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_millis(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Milliseconds the `/info/status` response is served from memory at
+    /// most. 0 builds it for every request.
+    #[clap(long, env, value_parser = duration_from_millis, default_value = "1000")]
+    pub status_cache_ttl: Duration,
+}
+
+/// A built response body and its entity tag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedResponse {
+    pub json: Bytes,
+    pub tag:  String,
+}
+
+#[derive(Debug)]
+struct Entry {
+    built_at:   Instant,
+    /// Lobby generation the response was built in.
+    generation: u64,
+    response:   CachedResponse,
+}
+
+#[derive(Debug)]
+pub struct StatusCache {
+    ttl:      Duration,
+    entry:    RwLock<Option<Entry>>,
+    /// Held while the response is built, so that a miss under load builds it
+    /// once instead of once per waiting request.
+    building: Mutex<()>,
+}
+
+pub type SharedStatusCache = Arc<StatusCache>;
+
+impl StatusCache {
+    #[must_use]
+    pub fn new(options: &Options) -> Self {
+        Self {
+            ttl:      options.status_cache_ttl,
+            entry:    RwLock::new(None),
+            building: Mutex::new(()),
+        }
+    }
+
+    /// The cached response, if it was built in lobby generation
+    /// `generation` and is still fresh.
+    #[must_use]
+    pub fn get(&self, generation: u64) -> Option<CachedResponse> {
+        self.entry
+            .read()
+            .unwrap()
+            .as_ref()
+            .filter(|entry| entry.generation == generation && entry.built_at.elapsed() < self.ttl)
+            .map(|entry| entry.response.clone())
+    }
+
+    /// The cached response, or the one `build` makes, which is then cached
+    /// for `generation`. The generation is to be read before `build` reads
+    /// the state, so that a response built while the state changes is not
+    /// kept.
+    pub async fn get_or_build<F>(&self, generation: u64, build: F) -> Option<CachedResponse>
+    where
+        F: std::future::Future<Output = Option<CachedResponse>> + Send,
+    {
+        if let Some(response) = self.get(generation) {
+            return Some(response);
+        }
+        let _building = self.building.lock().await;
+        // Another request may have built it while this one waited.
+        if let Some(response) = self.get(generation) {
+            return Some(response);
+        }
+        let response = build.await?;
+        if !self.ttl.is_zero() {
+            *self.entry.write().unwrap() = Some(Entry {
+                built_at: Instant::now(),
+                generation,
+                response: response.clone(),
+            });
+        }
+        Some(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(json: &'static str) -> CachedResponse {
+        CachedResponse {
+            json: Bytes::from_static(json.as_bytes()),
+            tag:  json.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn caches_within_generation_until_stale() {
+        let cache = StatusCache::new(&Options {
+            status_cache_ttl: Duration::from_millis(50),
+        });
+        assert_eq!(cache.get(0), None);
+        let built = cache.get_or_build(0, async { Some(response("a")) }).await;
+        assert_eq!(built, Some(response("a")));
+        let cached = cache.get_or_build(0, async { Some(response("b")) }).await;
+        assert_eq!(cached, Some(response("a")));
+
+        // An event since the response was built.
+        assert_eq!(cache.get(1), None);
+        let rebuilt = cache.get_or_build(1, async { Some(response("b")) }).await;
+        assert_eq!(rebuilt, Some(response("b")));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(cache.get(1), None);
+    }
+
+    #[tokio::test]
+    async fn zero_ttl_disables_caching() {
+        let cache = StatusCache::new(&Options {
+            status_cache_ttl: Duration::ZERO,
+        });
+        cache.get_or_build(0, async { Some(response("a")) }).await;
+        assert_eq!(cache.get(0), None);
+    }
+}
@@ -1,24 +1,69 @@
-use axum::{
-    response::{IntoResponse, Response},
-    Json,
+use crate::{
+    audit::AuditRecord,
+    circuit_breaker::CircuitBreaker,
+    contributor_cache::ContributorCache,
+    identity::{self, Account},
+    lottery::Draw,
+    metrics::DB_LATENCY,
+    notifications::{NotificationStatus, NotificationTarget, StoredNotification},
+    quotas::Quota,
+    rejection::RejectionReason,
+    util::Secret,
 };
-use chrono::Utc;
-use clap::Parser;
+use chrono::{DateTime, DurationRound, Utc};
+use clap::{Parser, ValueEnum};
 use eyre::{eyre, WrapErr};
-use http::StatusCode;
-use serde_json::json;
+use hmac::{Hmac, Mac};
+use kzg_ceremony_crypto::{
+    signature::{
+        identity::{Identity, IdentityError},
+        BlsSignature, EcdsaSignature,
+    },
+    BatchTranscript, ErrorCode, Powers, Transcript, VerifiedContribution, G1, G2,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use sqlx::{
-    any::{AnyConnectOptions, AnyKind},
+    any::{AnyArguments, AnyConnectOptions, AnyKind, AnyPool, AnyPoolOptions, AnyRow},
     migrate::{Migrate, MigrateDatabase, Migrator},
-    Any, AnyConnection, ConnectOptions, Executor, Row,
+    pool::PoolConnection,
+    query::Query,
+    Any, Connection, Decode, Executor, FromRow, Row, Type,
+};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
 };
-use std::{str::FromStr, sync::Arc};
+use strum::IntoStaticStr;
 use thiserror::Error;
-use tokio::sync::Mutex;
-use tracing::{error, info, warn};
+use tracing::{error, info, instrument, warn};
+use utoipa::ToSchema;
+
+// Statically link in migration files. The schemas differ slightly between
+// backends, but both directories must contain the same migration versions.
+#[cfg(feature = "sqlite")]
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+#[cfg(feature = "postgres")]
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// Selects the set of migrations matching the database backend.
+fn migrator(kind: AnyKind) -> eyre::Result<&'static Migrator> {
+    match kind {
+        #[cfg(feature = "sqlite")]
+        AnyKind::Sqlite => Ok(&SQLITE_MIGRATOR),
+
+        #[cfg(feature = "postgres")]
+        AnyKind::Postgres => Ok(&POSTGRES_MIGRATOR),
 
-// Statically link in migration files
-static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+        // Depending on compilation flags there may be more patterns.
+        #[allow(unreachable_patterns)]
+        kind => Err(eyre!("Unsupported database backend: {kind:?}")),
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 #[group(skip)]
@@ -34,20 +79,355 @@ pub struct Options {
     #[clap(long, env, default_value = "sqlite://storage.db")]
     pub database_url: String,
 
+    /// Connection string of a read replica, e.g. a Postgres hot standby.
+    /// Whether participants have contributed, the contribution statistics
+    /// and the contributor listings are read from it. Everything else,
+    /// including all writes, goes to `--database-url`. The replica is never
+    /// migrated.
+    #[clap(long, env)]
+    pub read_database_url: Option<String>,
+
     /// Allow creation or migration of the database schema.
     /// When set to false the process will terminate if the database is not
     /// up to date.
     #[clap(long, env, default_value = "true")]
     pub database_migrate: bool,
+
+    /// Maximum number of connections in the database pool. In-memory Sqlite
+    /// databases always use a single connection, as every connection would
+    /// see its own database.
+    #[clap(long, env, default_value = "10")]
+    pub database_max_connections: u32,
+
+    /// Number of prepared statements cached per connection.
+    #[clap(long, env, default_value = "100")]
+    pub database_statement_cache: usize,
+
+    /// Sqlite journal mode. `wal` lets readers run concurrently with the
+    /// writer.
+    #[clap(long, env, value_enum, default_value = "wal")]
+    pub sqlite_journal_mode: SqliteJournalMode,
+
+    /// Sqlite `synchronous` setting. `normal` is safe from corruption in WAL
+    /// mode, but may lose the latest transactions on power loss.
+    #[clap(long, env, value_enum, default_value = "full")]
+    pub sqlite_synchronous: SqliteSynchronous,
+
+    /// How long Sqlite waits for a lock held by another connection before
+    /// failing with `database is locked`, in milliseconds.
+    #[clap(long, env, default_value = "5000")]
+    pub sqlite_busy_timeout: u64,
+
+    /// How often writes of contributor records are retried when the
+    /// database is unreachable.
+    #[clap(long, env, default_value = "3")]
+    pub database_retries: u32,
+
+    /// Delay before the first retry, in milliseconds. Doubles with every
+    /// retry.
+    #[clap(long, env, default_value = "100")]
+    pub database_retry_backoff: u64,
+
+    /// Number of consecutive connection failures after which the sequencer
+    /// stops handing out contribution slots. 0 disables the circuit breaker.
+    #[clap(long, env, default_value = "5")]
+    pub database_failure_threshold: usize,
+
+    /// How long the circuit breaker stays open before the database is
+    /// probed again, in milliseconds.
+    #[clap(long, env, default_value = "10000")]
+    pub database_recovery_interval: u64,
+
+    /// How long answers to whether a participant has contributed are
+    /// cached, in milliseconds. Only matters when several sequencers share
+    /// the database, as contributions recorded by this one update the cache.
+    /// 0 disables the cache.
+    #[clap(long, env, default_value = "10000")]
+    pub contributor_cache_ttl: u64,
+
+    /// Key of the pseudonyms that replace the uids of contributors who asked
+    /// to be forgotten. Without it they can not be forgotten. Changing it
+    /// lets those forgotten before contribute again.
+    #[clap(long, env)]
+    pub pseudonym_key: Option<Secret>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SqliteJournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SqliteSynchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+#[cfg(feature = "sqlite")]
+impl From<SqliteJournalMode> for sqlx::sqlite::SqliteJournalMode {
+    fn from(mode: SqliteJournalMode) -> Self {
+        match mode {
+            SqliteJournalMode::Delete => Self::Delete,
+            SqliteJournalMode::Truncate => Self::Truncate,
+            SqliteJournalMode::Persist => Self::Persist,
+            SqliteJournalMode::Memory => Self::Memory,
+            SqliteJournalMode::Wal => Self::Wal,
+            SqliteJournalMode::Off => Self::Off,
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<SqliteSynchronous> for sqlx::sqlite::SqliteSynchronous {
+    fn from(synchronous: SqliteSynchronous) -> Self {
+        match synchronous {
+            SqliteSynchronous::Off => Self::Off,
+            SqliteSynchronous::Normal => Self::Normal,
+            SqliteSynchronous::Full => Self::Full,
+            SqliteSynchronous::Extra => Self::Extra,
+        }
+    }
+}
+
+impl Options {
+    fn is_in_memory(&self) -> bool {
+        self.database_url.starts_with("sqlite:")
+            && (self.database_url.contains(":memory:") || self.database_url.contains("mode=memory"))
+    }
+
+    /// The database file of a Sqlite url. `None` for in-memory and other
+    /// databases.
+    #[must_use]
+    pub fn sqlite_path(&self) -> Option<PathBuf> {
+        if self.is_in_memory() {
+            return None;
+        }
+        let path = self
+            .database_url
+            .strip_prefix("sqlite://")
+            .or_else(|| self.database_url.strip_prefix("sqlite:"))?;
+        let path = path.split('?').next().unwrap_or_default();
+        (!path.is_empty()).then(|| PathBuf::from(path))
+    }
+
+    /// Parses the database `url` and applies the backend specific tuning
+    /// options.
+    fn connect_options(&self, url: &str) -> eyre::Result<AnyConnectOptions> {
+        #[allow(unused_mut)] // Only modified with some database features.
+        let mut options = AnyConnectOptions::from_str(url)?;
+        #[cfg(feature = "sqlite")]
+        if let Some(sqlite) = options.as_sqlite_mut() {
+            *sqlite = sqlite
+                .clone()
+                .journal_mode(self.sqlite_journal_mode.into())
+                .synchronous(self.sqlite_synchronous.into())
+                .busy_timeout(Duration::from_millis(self.sqlite_busy_timeout))
+                .statement_cache_capacity(self.database_statement_cache);
+        }
+        #[cfg(feature = "postgres")]
+        if let Some(postgres) = options.as_postgres_mut() {
+            *postgres = postgres
+                .clone()
+                .statement_cache_capacity(self.database_statement_cache);
+        }
+        Ok(options)
+    }
+
+    fn pool_options(&self) -> AnyPoolOptions {
+        if self.is_in_memory() {
+            // Keep the one connection, and with it the database, alive.
+            AnyPoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+        } else {
+            AnyPoolOptions::new().max_connections(self.database_max_connections)
+        }
+    }
 }
 
+/// Shared database connection pool. The pool is closed on
+/// [`PersistentStorage::close`], after which all queries fail.
+///
+/// Connection failures feed a [`CircuitBreaker`]. While it is open the
+/// database is considered degraded: queries fail right away with
+/// [`StorageError::Degraded`] and no contribution slots are handed out, see
+/// [`PersistentStorage::check_healthy`].
+///
+/// [`PersistentStorage::has_contributed`] is answered from a
+/// [`ContributorCache`] when it can.
+///
+/// With `--read-database-url`, read-only queries that can tolerate replication
+/// lag use a second pool. The replica does not feed the circuit breaker.
 #[derive(Clone, Debug)]
-pub struct PersistentStorage(Arc<Mutex<AnyConnection>>);
+pub struct PersistentStorage {
+    pool:          AnyPool,
+    read_pool:     Option<AnyPool>,
+    breaker:       Arc<CircuitBreaker>,
+    contributors:  Arc<ContributorCache>,
+    retries:       u32,
+    retry_backoff: Duration,
+    pseudonym_key: Option<Secret>,
+}
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, IntoStaticStr)]
 pub enum StorageError {
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::error::Error),
+    #[error("Transcript serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+    #[error("Invalid participant id in stored transcript: {0}")]
+    InvalidIdentity(#[from] IdentityError),
+    #[error("Stored transcript is corrupt: {0}")]
+    CorruptTranscript(String),
+    #[error("Database connection is closed")]
+    Closed,
+    #[error("Database is unavailable, try again later")]
+    Degraded,
+    #[error("Uid {0} is registered to another account")]
+    UidCollision(String),
+    #[error("Stored row is corrupt: {0}")]
+    CorruptRow(String),
+    #[error("No --pseudonym-key configured")]
+    NoPseudonymKey,
+}
+
+impl StorageError {
+    /// Whether the error is caused by the connection to the database rather
+    /// than the query, so that retrying may succeed.
+    const fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::DatabaseError(
+                sqlx::Error::Io(_)
+                    | sqlx::Error::Tls(_)
+                    | sqlx::Error::Protocol(_)
+                    | sqlx::Error::PoolTimedOut
+                    | sqlx::Error::WorkerCrashed
+            )
+        )
+    }
+}
+
+/// The witness of a single contribution to one of the sub-ceremonies.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessEntry {
+    pub running_product: G1,
+    pub pot_pubkey:      G2,
+    pub bls_signature:   BlsSignature,
+}
+
+/// A stored contribution, as read back by
+/// [`PersistentStorage::read_transcript_entry`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredTranscriptEntry {
+    pub participant_id: Identity,
+    /// The witness of each sub-ceremony.
+    pub witness:        Vec<WitnessEntry>,
+}
+
+/// A row of the `transcript_entries` table. Every accepted contribution is
+/// stored as one row, keyed by its position in the transcript.
+struct TranscriptEntry {
+    position:            i64,
+    participant_id:      String,
+    ecdsa_signature:     String,
+    witness:             String,
+    powers:              Option<String>,
+    entropy_attestation: Option<String>,
+}
+
+impl TranscriptEntry {
+    /// Extracts the contribution at `position` from a transcript. The powers
+    /// are only available for the latest contribution, so they are only
+    /// included when requested.
+    fn from_transcript(
+        transcript: &BatchTranscript,
+        position: usize,
+        with_powers: bool,
+    ) -> Result<Self, StorageError> {
+        let witness = transcript
+            .transcripts
+            .iter()
+            .map(|t| WitnessEntry {
+                running_product: t.witness.products[position],
+                pot_pubkey:      t.witness.pubkeys[position],
+                bls_signature:   t.witness.signatures[position].clone(),
+            })
+            .collect::<Vec<_>>();
+        let powers = if with_powers {
+            let powers = transcript
+                .transcripts
+                .iter()
+                .map(|t| &t.powers)
+                .collect::<Vec<_>>();
+            Some(serde_json::to_string(&powers)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            position: i64::try_from(position)
+                .map_err(|_| StorageError::CorruptTranscript("position overflow".to_string()))?,
+            participant_id: transcript.participant_ids[position].to_string(),
+            ecdsa_signature: serde_json::to_string(
+                &transcript.participant_ecdsa_signatures[position],
+            )?,
+            witness: serde_json::to_string(&witness)?,
+            powers,
+            entropy_attestation: transcript.entropy_attestation(position).map(str::to_string),
+        })
+    }
+
+    /// The entry of a contribution that is verified but not yet applied, see
+    /// [`BatchTranscript::apply`].
+    fn from_verified(verified: &VerifiedContribution) -> Result<Self, StorageError> {
+        let witness = verified
+            .contributions()
+            .iter()
+            .map(|contribution| WitnessEntry {
+                running_product: contribution.powers.g1[1],
+                pot_pubkey:      contribution.pot_pubkey,
+                bls_signature:   contribution.bls_signature.clone(),
+            })
+            .collect::<Vec<_>>();
+        let powers = verified
+            .contributions()
+            .iter()
+            .map(|contribution| &contribution.powers)
+            .collect::<Vec<_>>();
+        Ok(Self {
+            position:            i64::try_from(verified.num_participants() + 1)
+                .map_err(|_| StorageError::CorruptTranscript("position overflow".to_string()))?,
+            participant_id:      verified.identity().to_string(),
+            ecdsa_signature:     serde_json::to_string(verified.ecdsa_signature())?,
+            witness:             serde_json::to_string(&witness)?,
+            powers:              Some(serde_json::to_string(&powers)?),
+            entropy_attestation: verified.entropy_attestation().map(str::to_string),
+        })
+    }
+
+    fn insert_query(self) -> Query<'static, Any, AnyArguments<'static>> {
+        let sql = "INSERT INTO transcript_entries (position, participant_id, ecdsa_signature, \
+                   witness, powers, entropy_attestation, created_at) VALUES ($1, $2, $3, $4, $5, \
+                   $6, $7)";
+        sqlx::query(sql)
+            .bind(self.position)
+            .bind(self.participant_id)
+            .bind(self.ecdsa_signature)
+            .bind(self.witness)
+            .bind(self.powers)
+            .bind(self.entropy_attestation)
+            .bind(Utc::now())
+    }
 }
 
 pub async fn storage_client(options: &Options) -> eyre::Result<PersistentStorage> {
@@ -59,10 +439,12 @@ pub async fn storage_client(options: &Options) -> eyre::Result<PersistentStorage
         Any::create_database(options.database_url.as_str()).await?;
     }
 
-    // Create a database connection
-    let mut connection = AnyConnectOptions::from_str(options.database_url.as_str())?
-        .connect()
+    // Create the connection pool
+    let pool = options
+        .pool_options()
+        .connect_with(options.connect_options(&options.database_url)?)
         .await?;
+    let mut connection = pool.acquire().await?;
 
     // Log DB version to test connection.
     let sql = match connection.kind() {
@@ -84,10 +466,11 @@ pub async fn storage_client(options: &Options) -> eyre::Result<PersistentStorage
     info!(url = %&options.database_url, kind = ?connection.kind(), ?version, "Connected to database");
 
     // Run migrations if requested.
-    let latest = MIGRATOR.migrations.last().unwrap().version;
+    let migrator = migrator(connection.kind())?;
+    let latest = migrator.migrations.last().unwrap().version;
     if options.database_migrate {
         info!(url = %&options.database_url, "Running database migrations if necessary");
-        MIGRATOR.run_direct(&mut connection).await?;
+        migrator.run_direct(&mut *connection).await?;
     }
 
     // Validate database schema version
@@ -133,62 +516,4503 @@ pub async fn storage_client(options: &Options) -> eyre::Result<PersistentStorage
         return Err(eyre!("Could not get database version."));
     }
 
-    Ok(PersistentStorage(Arc::new(Mutex::new(connection))))
+    drop(connection);
+    let read_pool = match &options.read_database_url {
+        Some(url) => Some(read_replica(options, url, latest).await?),
+        None => None,
+    };
+    Ok(PersistentStorage {
+        pool,
+        read_pool,
+        breaker: Arc::new(CircuitBreaker::new(
+            options.database_failure_threshold,
+            Duration::from_millis(options.database_recovery_interval),
+        )),
+        contributors: Arc::new(ContributorCache::new(Duration::from_millis(
+            options.contributor_cache_ttl,
+        ))),
+        retries: options.database_retries,
+        retry_backoff: Duration::from_millis(options.database_retry_backoff),
+        pseudonym_key: options.pseudonym_key.clone(),
+    })
 }
 
-impl IntoResponse for StorageError {
-    fn into_response(self) -> Response {
-        let message = match &self {
-            Self::DatabaseError(error) => error.to_string(),
-        };
-        let body = Json(json!({
-            "code": "StorageError::DatabaseError",
-            "error": message
-        }));
-        (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+/// Connects to the read replica at `url` and checks that its schema is that
+/// of migration `latest`.
+async fn read_replica(options: &Options, url: &str, latest: i64) -> eyre::Result<AnyPool> {
+    info!(%url, "Connecting to read replica");
+    let pool = AnyPoolOptions::new()
+        .max_connections(options.database_max_connections)
+        .connect_with(options.connect_options(url)?)
+        .await?;
+    let mut connection = pool.acquire().await?;
+    #[allow(deprecated)] // HACK: No good alternative to `version()`?
+    match connection.version().await? {
+        Some((version, false)) if version == latest => {
+            info!(%url, version, "Read replica is up to date.");
+        }
+        Some((version, dirty)) => {
+            error!(%url, version, dirty, expected = latest, "Read replica schema does not match");
+            return Err(eyre!("Read replica schema does not match the database."));
+        }
+        None => {
+            error!(%url, "Could not get read replica version");
+            return Err(eyre!("Could not get read replica version."));
+        }
+    }
+    drop(connection);
+    Ok(pool)
+}
+
+impl ErrorCode for StorageError {
+    fn to_error_code(&self) -> String {
+        format!("StorageError::{}", <&str>::from(self))
     }
 }
 
+// Queries use `$N` style placeholders, which are understood by both the
+// Postgres and the Sqlite drivers.
 impl PersistentStorage {
+    async fn connection(&self) -> Result<PoolConnection<Any>, StorageError> {
+        if !self.breaker.allow() {
+            return Err(StorageError::Degraded);
+        }
+        let result = self.pool.acquire().await.map_err(|error| match error {
+            sqlx::Error::PoolClosed => StorageError::Closed,
+            error => StorageError::DatabaseError(error),
+        });
+        match &result {
+            Ok(_) => self.breaker.record_success(),
+            Err(error) if error.is_transient() => self.breaker.record_failure(),
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// A connection for read-only queries, from the read replica if there is
+    /// one.
+    async fn read_connection(&self) -> Result<PoolConnection<Any>, StorageError> {
+        match &self.read_pool {
+            Some(pool) => pool.acquire().await.map_err(|error| match error {
+                sqlx::Error::PoolClosed => StorageError::Closed,
+                error => StorageError::DatabaseError(error),
+            }),
+            None => self.connection().await,
+        }
+    }
+
+    /// Runs `operation`, retrying it with exponential backoff as long as it
+    /// fails with a transient error.
+    async fn with_retries<T, F, Fut>(&self, mut operation: F) -> Result<T, StorageError>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T, StorageError>> + Send,
+    {
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Err(error) if error.is_transient() => {
+                    if attempt >= self.retries {
+                        return Err(error);
+                    }
+                    warn!(?error, attempt, "database operation failed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Whether the circuit breaker is open, see [`Self::check_healthy`].
+    #[must_use]
+    pub fn is_degraded(&self) -> bool {
+        self.breaker.is_open()
+    }
+
+    /// Fails with [`StorageError::Degraded`] while the circuit breaker is
+    /// open. Once the recovery interval passed, probes the database instead.
+    pub async fn check_healthy(&self) -> Result<(), StorageError> {
+        if self.breaker.is_open() {
+            self.ping().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes a consistent copy of the Sqlite database to `path`, which must
+    /// not exist yet, while the database stays in use.
+    #[instrument(level = "info", skip_all)]
+    pub async fn vacuum_into(&self, path: &Path) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["vacuum_into"]).start_timer();
+        let sql = "VACUUM INTO $1";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(path.to_string_lossy().into_owned()))
+            .await?;
+        Ok(())
+    }
+
+    /// Up to `limit` rows of `table` from `offset` on, in the order of
+    /// `order_by`, with the values of `columns`.
+    #[instrument(level = "info", skip_all, fields(%table))]
+    pub async fn read_rows(
+        &self,
+        table: &str,
+        columns: &[(&str, ColumnKind)],
+        order_by: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Vec<ColumnValue>>, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["read_rows"]).start_timer();
+        let names = columns
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!("SELECT {names} FROM {table} ORDER BY {order_by} LIMIT $1 OFFSET $2");
+        let rows = self
+            .connection()
+            .await?
+            .fetch_all(
+                sqlx::query(&sql)
+                    .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+                    .bind(i64::try_from(offset).unwrap_or(i64::MAX)),
+            )
+            .await?
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (_, kind))| ColumnValue::from_row(row, index, *kind))
+                    .collect()
+            })
+            .collect();
+        Ok(rows)
+    }
+
+    /// Inserts `rows` with the values of `columns` into `table`, in one
+    /// transaction.
+    #[instrument(level = "info", skip_all, fields(%table))]
+    pub async fn insert_rows(
+        &self,
+        table: &str,
+        columns: &[(&str, ColumnKind)],
+        rows: Vec<Vec<ColumnValue>>,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["insert_rows"]).start_timer();
+        let names = columns
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let placeholders = (1..=columns.len())
+            .map(|n| format!("${n}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!("INSERT INTO {table} ({names}) VALUES ({placeholders})");
+        let mut connection = self.connection().await?;
+        let mut tx = connection.begin().await?;
+        for row in rows {
+            let mut query = sqlx::query(&sql);
+            for value in row {
+                query = match value {
+                    ColumnValue::Text(value) => query.bind(value),
+                    ColumnValue::Integer(value) => query.bind(value),
+                    ColumnValue::Timestamp(value) => query.bind(value),
+                };
+            }
+            tx.execute(query).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Number of rows in `table`.
+    #[instrument(level = "info", skip_all, fields(%table))]
+    pub async fn count_rows(&self, table: &str) -> Result<u64, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["count_rows"]).start_timer();
+        let sql = format!("SELECT COUNT(*) AS count FROM {table}");
+        let row = self.connection().await?.fetch_one(sql.as_str()).await?;
+        int_column(&row, "count")
+    }
+
+    /// Moves the sequence of the serial `id` column of `table` past the
+    /// largest id, after rows were inserted with their ids. Sqlite does so
+    /// itself.
+    #[instrument(level = "info", skip_all, fields(%table))]
+    pub async fn reset_id_sequence(&self, table: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["reset_id_sequence"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        #[allow(clippy::single_match)] // Depends on compilation flags.
+        match connection.kind() {
+            #[cfg(feature = "postgres")]
+            AnyKind::Postgres => {
+                let sql = format!(
+                    "SELECT setval(pg_get_serial_sequence('{table}', 'id'), COALESCE(MAX(id), 0) \
+                     + 1, false) FROM {table}"
+                );
+                connection.execute(sql.as_str()).await?;
+            }
+            #[allow(unreachable_patterns)]
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Closes the connection pool, waiting for running queries to finish.
+    pub async fn close(&self) -> Result<(), StorageError> {
+        self.pool.close().await;
+        if let Some(read_pool) = &self.read_pool {
+            read_pool.close().await;
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all)]
     pub async fn has_contributed(&self, uid: &str) -> Result<bool, StorageError> {
-        let sql = "SELECT EXISTS(SELECT 1 FROM contributors WHERE uid = ?1)";
+        if let Some(contributed) = self.contributors.get(uid) {
+            return Ok(contributed);
+        }
+        let _timer = DB_LATENCY
+            .with_label_values(&["has_contributed"])
+            .start_timer();
+        // Contributors who were redacted still count as having contributed,
+        // those who were requeued do not yet. So do the contributions of the
+        // account under legacy uids, whatever its username was.
+        let sql = "SELECT EXISTS(SELECT 1 FROM contributors WHERE (uid = $1 OR uid = $2 OR \
+                   identity_id = (SELECT id FROM identities WHERE provider = $3 AND provider_id = \
+                   $4)) AND requeued_at IS NULL) AS contributed";
+        let account = Account::from_uid(uid);
         let result = self
-            .0
-            .lock()
-            .await
+            .read_connection()
+            .await?
+            .fetch_one(
+                sqlx::query(sql)
+                    .bind(uid)
+                    .bind(self.pseudonym(uid))
+                    .bind(account.as_ref().map_or("", |account| account.provider))
+                    .bind(account.map(|account| account.provider_id)),
+            )
+            .await?;
+        let result = column(&result, "contributed")?;
+        self.contributors.insert(uid, result);
+        Ok(result)
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_contributor(
+        &self,
+        uid: &str,
+        eligibility_score: Option<u32>,
+    ) -> Result<(), StorageError> {
+        let identity_id = self.upsert_identity(uid, eligibility_score).await?;
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_contributor"])
+            .start_timer();
+        let sql = "INSERT INTO contributors (uid, started_at, eligibility_score, identity_id) \
+                   VALUES ($1, $2, $3, $4)";
+        self.with_retries(|| async move {
+            self.connection()
+                .await?
+                .execute(
+                    sqlx::query(sql)
+                        .bind(uid)
+                        .bind(Utc::now())
+                        .bind(eligibility_score.map(i64::from))
+                        .bind(identity_id),
+                )
+                .await?;
+            Ok(())
+        })
+        .await?;
+        self.contributors.insert(uid, true);
+        Ok(())
+    }
+
+    /// Like [`Self::insert_contributor`], but only if fewer than
+    /// `quota.max_contributions` unexpired contributions were made by uids
+    /// starting with `quota.uid_prefix`. The check and the insert are a single
+    /// statement. Returns whether the contributor was inserted.
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_contributor_within_quota(
+        &self,
+        uid: &str,
+        eligibility_score: Option<u32>,
+        quota: Option<Quota>,
+    ) -> Result<bool, StorageError> {
+        let quota = match quota {
+            Some(quota) => quota,
+            None => {
+                self.insert_contributor(uid, eligibility_score).await?;
+                return Ok(true);
+            }
+        };
+        let identity_id = self.upsert_identity(uid, eligibility_score).await?;
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_contributor_within_quota"])
+            .start_timer();
+        let sql = "INSERT INTO contributors (uid, started_at, eligibility_score, identity_id) \
+                   SELECT $1, $2, $3, $4 WHERE (SELECT COUNT(*) FROM contributors WHERE uid LIKE \
+                   $5 AND expired_at IS NULL) < $6";
+        let inserted = self
+            .with_retries(|| async move {
+                let result = self
+                    .connection()
+                    .await?
+                    .execute(
+                        sqlx::query(sql)
+                            .bind(uid)
+                            .bind(Utc::now())
+                            .bind(eligibility_score.map(i64::from))
+                            .bind(identity_id)
+                            .bind(format!("{}%", quota.uid_prefix))
+                            .bind(i64::try_from(quota.max_contributions).unwrap_or(i64::MAX)),
+                    )
+                    .await?;
+                Ok(result.rows_affected() == 1)
+            })
+            .await?;
+        if inserted {
+            self.contributors.insert(uid, true);
+        }
+        Ok(inserted)
+    }
+
+    /// Number of unexpired contributions by uids starting with `uid_prefix`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn count_contributions_of(&self, uid_prefix: &str) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["count_contributions_of"])
+            .start_timer();
+        let sql =
+            "SELECT COUNT(*) AS count FROM contributors WHERE uid LIKE $1 AND expired_at IS NULL";
+        let row = self
+            .connection()
+            .await?
+            .fetch_one(sqlx::query(sql).bind(format!("{uid_prefix}%")))
+            .await?;
+        int_column(&row, "count")
+    }
+
+    /// Records the identity behind `uid`, or updates its handle, if `uid` is a
+    /// legacy uid that names one, and, if given, its eligibility score, and
+    /// returns its id. Uids that are not of an identity, like [`pseudonym`]s,
+    /// have none.
+    #[instrument(level = "info", skip_all)]
+    pub async fn upsert_identity(
+        &self,
+        uid: &str,
+        eligibility_score: Option<u32>,
+    ) -> Result<Option<i64>, StorageError> {
+        match IdentityKey::of(uid) {
+            Some(key) => self
+                .upsert_identity_key(&key, eligibility_score)
+                .await
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Records `identity` when it signs in, or updates the handle of the
+    /// account, which may have been renamed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StorageError::UidCollision`] if the uid of `identity` is
+    /// registered to another account.
+    #[instrument(level = "info", skip_all)]
+    pub async fn register_identity(&self, identity: &Identity) -> Result<(), StorageError> {
+        let key = match Account::of(identity) {
+            Some(account) => IdentityKey {
+                account,
+                handle: Some(identity.nickname()),
+            },
+            None => return Ok(()),
+        };
+        self.upsert_identity_key(&key, None).await?;
+        Ok(())
+    }
+
+    /// The row of `identities` is keyed by the account and records its uid,
+    /// which is unique. Rows backfilled from legacy uids get theirs here.
+    async fn upsert_identity_key(
+        &self,
+        key: &IdentityKey,
+        eligibility_score: Option<u32>,
+    ) -> Result<i64, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["upsert_identity"])
+            .start_timer();
+        let upsert = "INSERT INTO identities (provider, provider_id, handle, created_at, \
+                      eligibility_score, uid) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT \
+                      (provider, provider_id) DO UPDATE SET handle = COALESCE($3, \
+                      identities.handle), eligibility_score = COALESCE($5, \
+                      identities.eligibility_score), uid = COALESCE(identities.uid, $6)";
+        let select = "SELECT id, uid FROM identities WHERE provider = $1 AND provider_id = $2";
+        let uid = &key.account.uid();
+        let (id, stored_uid) = self
+            .with_retries(|| async move {
+                let mut connection = self.connection().await?;
+                connection
+                    .execute(
+                        sqlx::query(upsert)
+                            .bind(key.account.provider)
+                            .bind(&key.account.provider_id)
+                            .bind(&key.handle)
+                            .bind(Utc::now())
+                            .bind(eligibility_score.map(i64::from))
+                            .bind(uid),
+                    )
+                    .await?;
+                let row = connection
+                    .fetch_one(
+                        sqlx::query(select)
+                            .bind(key.account.provider)
+                            .bind(&key.account.provider_id),
+                    )
+                    .await?;
+                Ok((row.get::<i64, _>(0), row.get::<Option<String>, _>(1)))
+            })
+            .await?;
+        if stored_uid.as_ref() != Some(uid) {
+            return Err(StorageError::UidCollision(uid.clone()));
+        }
+        Ok(id)
+    }
+
+    /// The identity behind `uid`, if it ever signed in or asked for the
+    /// contribution slot.
+    #[instrument(level = "info", skip_all)]
+    pub async fn identity_of(&self, uid: &str) -> Result<Option<StoredIdentity>, StorageError> {
+        let key = match IdentityKey::of(uid) {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let _timer = DB_LATENCY.with_label_values(&["identity_of"]).start_timer();
+        let sql = "SELECT id, provider, provider_id, handle, created_at, eligibility_score FROM \
+                   identities WHERE provider = $1 AND provider_id = $2";
+        let identity = self
+            .read_connection()
+            .await?
+            .fetch_optional(
+                sqlx::query(sql)
+                    .bind(key.account.provider)
+                    .bind(&key.account.provider_id),
+            )
+            .await?;
+        identity.as_ref().map(StoredIdentity::from_row).transpose()
+    }
+
+    /// The uids an identity got the contribution slot with, oldest attempt
+    /// first. Under legacy uids, a GitHub or Discord account has one for each
+    /// username.
+    #[instrument(level = "info", skip_all)]
+    pub async fn uids_of_identity(&self, identity_id: i64) -> Result<Vec<String>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["uids_of_identity"])
+            .start_timer();
+        let sql = "SELECT uid FROM contributors WHERE identity_id = $1 GROUP BY uid ORDER BY \
+                   MIN(started_at)";
+        self.read_connection()
+            .await?
+            .fetch_all(sqlx::query(sql).bind(identity_id))
+            .await?
+            .iter()
+            .map(|row| column(row, "uid"))
+            .collect()
+    }
+
+    /// Reserves contribution `index` for the latest attempt of `uid`, which
+    /// just got the slot, unless a finished contribution has it. The check
+    /// and the update are a single statement, and a unique index over the
+    /// indexes of finished contributions keeps two from sharing one across
+    /// restarts and sequencers sharing the database. Returns whether the
+    /// index was reserved.
+    #[instrument(level = "info", skip_all)]
+    pub async fn reserve_contribution_index(
+        &self,
+        uid: &str,
+        index: usize,
+    ) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["reserve_contribution_index"])
+            .start_timer();
+        let sql = "UPDATE contributors SET contribution_index = $1 WHERE id = (SELECT MAX(id) \
+                   FROM contributors WHERE uid = $2) AND finished_at IS NULL AND NOT EXISTS \
+                   (SELECT 1 FROM contributors WHERE contribution_index = $1 AND finished_at IS \
+                   NOT NULL)";
+        let index = i64::try_from(index).unwrap_or(i64::MAX);
+        self.with_retries(|| async move {
+            let result = self
+                .connection()
+                .await?
+                .execute(sqlx::query(sql).bind(index).bind(uid))
+                .await?;
+            Ok(result.rows_affected() == 1)
+        })
+        .await
+    }
+
+    /// Marks the latest attempt of `uid` finished and returns the contribution
+    /// index reserved for it, see [`Self::reserve_contribution_index`].
+    /// Fails if a finished contribution has the index already. The attempt
+    /// is read back in the same transaction, so it is the one finished.
+    #[instrument(level = "info", skip_all)]
+    pub async fn finish_contribution(&self, uid: &str) -> Result<Option<usize>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["finish_contribution"])
+            .start_timer();
+        let update = "UPDATE contributors SET finished_at = $1 WHERE id = (SELECT MAX(id) FROM \
+                      contributors WHERE uid = $2)";
+        self.with_retries(|| async move {
+            let mut connection = self.connection().await?;
+            let mut tx = connection.begin().await?;
+            tx.execute(sqlx::query(update).bind(Utc::now()).bind(uid))
+                .await?;
+            let finished = sqlx::query_as::<_, Contributor>(LATEST_CONTRIBUTOR)
+                .bind(uid)
+                .fetch_optional(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(finished.and_then(|contributor| contributor.index))
+        })
+        .await
+    }
+
+    /// Records a contribution made elsewhere: replicated from the primary
+    /// sequencer, see [`crate::replication`], or imported with the transcript
+    /// of a previous ceremony, see [`crate::commands::init`].
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_replicated_contributor(
+        &self,
+        uid: &str,
+        index: usize,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+    ) -> Result<(), StorageError> {
+        let identity_id = self.upsert_identity(uid, None).await?;
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_replicated_contributor"])
+            .start_timer();
+        let sql = "INSERT INTO contributors (uid, started_at, finished_at, identity_id, \
+                   contribution_index) VALUES ($1, $2, $3, $4, $5)";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(uid)
+                    .bind(started_at)
+                    .bind(finished_at)
+                    .bind(identity_id)
+                    .bind(i64::try_from(index).unwrap_or(i64::MAX)),
+            )
+            .await?;
+        self.contributors.insert(uid, true);
+        Ok(())
+    }
+
+    /// The latest attempt of `uid`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn get_contributor_by_uid(
+        &self,
+        uid: &str,
+    ) -> Result<Option<Contributor>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["get_contributor_by_uid"])
+            .start_timer();
+        let contributor = sqlx::query_as::<_, Contributor>(LATEST_CONTRIBUTOR)
+            .bind(uid)
+            .fetch_optional(&mut *self.connection().await?)
+            .await?;
+        Ok(contributor)
+    }
+
+    /// The attempts that neither finished nor expired, oldest first: the
+    /// contribution in progress, and those of slots held by a sequencer that
+    /// stopped, until they expire.
+    #[instrument(level = "info", skip_all)]
+    pub async fn list_unfinished(&self) -> Result<Vec<Contributor>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["list_unfinished"])
+            .start_timer();
+        let sql = "SELECT uid, started_at, finished_at, expired_at, contribution_index FROM \
+                   contributors WHERE finished_at IS NULL AND expired_at IS NULL ORDER BY id";
+        let contributors = sqlx::query_as::<_, Contributor>(sql)
+            .fetch_all(&mut *self.read_connection().await?)
+            .await?;
+        Ok(contributors)
+    }
+
+    /// Records the country the contribution of `uid` was submitted from.
+    #[instrument(level = "info", skip_all)]
+    pub async fn set_contributor_country(
+        &self,
+        uid: &str,
+        country: &str,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["set_contributor_country"])
+            .start_timer();
+        let sql = "UPDATE contributors SET country = $1 WHERE uid = $2";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(country).bind(uid))
+            .await?;
+        Ok(())
+    }
+
+    /// Records the client, as `<name>/<version>`, that submitted the
+    /// contribution of `uid`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn set_contributor_client_version(
+        &self,
+        uid: &str,
+        client_version: &str,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["set_contributor_client_version"])
+            .start_timer();
+        let sql = "UPDATE contributors SET client_version = $1 WHERE uid = $2";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(client_version).bind(uid))
+            .await?;
+        Ok(())
+    }
+
+    /// Records the SHA-256 of the machine fingerprint and the subnet the
+    /// contribution of `uid` was submitted with, see [`crate::fingerprint`].
+    #[instrument(level = "info", skip_all)]
+    pub async fn set_contributor_fingerprint(
+        &self,
+        uid: &str,
+        fingerprint_hash: Option<&str>,
+        subnet: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["set_contributor_fingerprint"])
+            .start_timer();
+        let sql = "UPDATE contributors SET fingerprint_hash = $1, subnet = $2 WHERE uid = $3";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(fingerprint_hash)
+                    .bind(subnet)
+                    .bind(uid),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The fingerprint hashes and subnets of the finished contributions, as
+    /// `(uid, fingerprint_hash, subnet)`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn contributor_fingerprints(
+        &self,
+    ) -> Result<Vec<(String, Option<String>, Option<String>)>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["contributor_fingerprints"])
+            .start_timer();
+        let sql = "SELECT uid, fingerprint_hash, subnet FROM contributors WHERE finished_at IS \
+                   NOT NULL AND (fingerprint_hash IS NOT NULL OR subnet IS NOT NULL)";
+        self.connection()
+            .await?
+            .fetch_all(sql)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok((
+                    column(row, "uid")?,
+                    column(row, "fingerprint_hash")?,
+                    column(row, "subnet")?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Records the verified ENS name of the address `uid` signed in with.
+    #[instrument(level = "info", skip_all)]
+    pub async fn set_contributor_ens_name(
+        &self,
+        uid: &str,
+        ens_name: &str,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["set_contributor_ens_name"])
+            .start_timer();
+        let sql = "UPDATE contributors SET ens_name = $1 WHERE uid = $2";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(ens_name).bind(uid))
+            .await?;
+        Ok(())
+    }
+
+    /// Average time between the start and the end of the `limit` most recent
+    /// finished contributions. Returns `None` if nobody has contributed yet.
+    #[instrument(level = "info", skip_all)]
+    pub async fn average_contribution_duration(
+        &self,
+        limit: usize,
+    ) -> Result<Option<Duration>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["average_contribution_duration"])
+            .start_timer();
+        let sql = "SELECT started_at, finished_at FROM contributors WHERE finished_at IS NOT NULL \
+                   ORDER BY id DESC LIMIT $1";
+        let rows: Vec<FinishedAttempt> = self
+            .connection()
+            .await?
+            .fetch_all(sqlx::query(sql).bind(i64::try_from(limit).unwrap_or(i64::MAX)))
+            .await?
+            .iter()
+            .map(FinishedAttempt::from_row)
+            .collect::<Result<_, sqlx::Error>>()?;
+        let durations = rows
+            .iter()
+            .map(|row| {
+                (row.finished_at - row.started_at)
+                    .to_std()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        if durations.is_empty() {
+            return Ok(None);
+        }
+        let total: Duration = durations.iter().sum();
+        Ok(Some(
+            total / u32::try_from(durations.len()).unwrap_or(u32::MAX),
+        ))
+    }
+
+    /// Aggregates the history of all contribution attempts.
+    #[instrument(level = "info", skip_all)]
+    pub async fn contribution_statistics(&self) -> Result<ContributionStatistics, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["contribution_statistics"])
+            .start_timer();
+        let sql = "SELECT uid, started_at, finished_at, expired_at, country, client_version FROM \
+                   contributors";
+        let rows: Vec<ContributorRow> = self
+            .read_connection()
+            .await?
+            .fetch_all(sql)
+            .await?
+            .iter()
+            .map(ContributorRow::from_row)
+            .collect::<Result<_, sqlx::Error>>()?;
+
+        let mut statistics = ContributionStatistics {
+            attempts: rows.len(),
+            ..ContributionStatistics::default()
+        };
+        let mut durations = Vec::new();
+        let mut per_hour = BTreeMap::<DateTime<Utc>, usize>::new();
+        for ContributorRow {
+            uid,
+            started_at,
+            finished_at,
+            expired_at,
+            country,
+            client_version,
+        } in rows
+        {
+            match (finished_at, expired_at) {
+                (Some(finished_at), _) => {
+                    statistics.contributions += 1;
+                    durations.push((finished_at - started_at).to_std().unwrap_or_default());
+                    let hour = finished_at
+                        .duration_trunc(chrono::Duration::hours(1))
+                        .unwrap_or(finished_at);
+                    *per_hour.entry(hour).or_default() += 1;
+                    let provider = identity::provider_name(&uid);
+                    *statistics
+                        .contributions_by_provider
+                        .entry(provider)
+                        .or_default() += 1;
+                    if let Some(country) = country {
+                        *statistics
+                            .contributions_by_country
+                            .entry(country)
+                            .or_default() += 1;
+                    }
+                    if let Some(client_version) = client_version {
+                        *statistics
+                            .contributions_by_client
+                            .entry(client_version)
+                            .or_default() += 1;
+                    }
+                }
+                (None, Some(_)) => statistics.expired += 1,
+                (None, None) => {}
+            }
+        }
+        durations.sort_unstable();
+        statistics.median_contribution_duration = match durations.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(durations[n / 2]),
+            n => Some((durations[n / 2 - 1] + durations[n / 2]) / 2),
+        };
+        statistics.contributions_per_hour = per_hour.into_iter().collect();
+        Ok(statistics)
+    }
+
+    /// Number of contributors whose contribution was verified and finished.
+    #[instrument(level = "info", skip_all)]
+    pub async fn count_finished_contributions(&self) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["count_finished_contributions"])
+            .start_timer();
+        let sql = "SELECT COUNT(*) AS count FROM contributors WHERE finished_at IS NOT NULL";
+        let row = self.connection().await?.fetch_one(sql).await?;
+        int_column(&row, "count")
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn expire_contribution(&self, uid: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["expire_contribution"])
+            .start_timer();
+        let sql = "UPDATE contributors SET expired_at = $1 WHERE uid = $2";
+        self.with_retries(|| async move {
+            self.connection()
+                .await?
+                .execute(sqlx::query(sql).bind(Utc::now()).bind(uid))
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Records that `uid`'s contribution was rejected for `reason`, see
+    /// [`crate::rejection`]. It expires as with [`Self::expire_contribution`].
+    #[instrument(level = "info", skip_all)]
+    pub async fn reject_contribution(
+        &self,
+        uid: &str,
+        reason: &RejectionReason,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["reject_contribution"])
+            .start_timer();
+        let reason = serde_json::to_string(reason).expect("rejection reasons serialize");
+        let reason = reason.as_str();
+        let sql = "UPDATE contributors SET expired_at = $1, rejection_reason = $2 WHERE uid = $3";
+        self.with_retries(|| async move {
+            self.connection()
+                .await?
+                .execute(sqlx::query(sql).bind(Utc::now()).bind(reason).bind(uid))
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The reason the latest rejected contribution of `uid` was rejected for.
+    #[instrument(level = "info", skip_all)]
+    pub async fn rejection_reason(
+        &self,
+        uid: &str,
+    ) -> Result<Option<RejectionReason>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["rejection_reason"])
+            .start_timer();
+        let sql = "SELECT rejection_reason FROM contributors WHERE uid = $1 AND rejection_reason \
+                   IS NOT NULL ORDER BY id DESC LIMIT 1";
+        let row = match self
+            .connection()
+            .await?
+            .fetch_optional(sqlx::query(sql).bind(uid))
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let reason = column::<String>(&row, "rejection_reason")?;
+        Ok(Some(serde_json::from_str(&reason)?))
+    }
+
+    /// The contribution attempts of `uid`, oldest first.
+    #[instrument(level = "info", skip_all)]
+    pub async fn contribution_attempts(
+        &self,
+        uid: &str,
+    ) -> Result<Vec<StoredAttempt>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["contribution_attempts"])
+            .start_timer();
+        let sql = "SELECT started_at, finished_at, expired_at, attempts, rejection_reason FROM \
+                   contributors WHERE uid = $1 ORDER BY id";
+        let attempts = self
+            .read_connection()
+            .await?
+            .fetch_all(sqlx::query(sql).bind(uid))
+            .await?
+            .iter()
+            .map(StoredAttempt::from_row)
+            .collect::<Result<_, _>>()?;
+        Ok(attempts)
+    }
+
+    /// Records that `uid`'s contribution expired but that they may try again,
+    /// unless it finished or expired already or `max_attempts` were made.
+    /// Returns whether it was requeued. Until they get the slot again, `uid`
+    /// does not count as having contributed.
+    #[instrument(level = "info", skip_all)]
+    pub async fn requeue_contribution(
+        &self,
+        uid: &str,
+        max_attempts: u32,
+    ) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["requeue_contribution"])
+            .start_timer();
+        let sql = "UPDATE contributors SET expired_at = $1, requeued_at = $2 WHERE uid = $3 AND \
+                   finished_at IS NULL AND expired_at IS NULL AND attempts < $4";
+        let now = Utc::now();
+        let requeued = self
+            .with_retries(|| async move {
+                let result = self
+                    .connection()
+                    .await?
+                    .execute(
+                        sqlx::query(sql)
+                            .bind(now)
+                            .bind(now)
+                            .bind(uid)
+                            .bind(i64::from(max_attempts)),
+                    )
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            })
+            .await?;
+        if requeued {
+            self.contributors.insert(uid, false);
+        }
+        Ok(requeued)
+    }
+
+    /// Starts another attempt of the requeued contributor `uid`, see
+    /// [`Self::requeue_contribution`]. Returns whether `uid` was requeued.
+    #[instrument(level = "info", skip_all)]
+    pub async fn retry_contribution(&self, uid: &str) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["retry_contribution"])
+            .start_timer();
+        let sql = "UPDATE contributors SET attempts = attempts + 1, started_at = $1, expired_at = \
+                   NULL, requeued_at = NULL WHERE uid = $2 AND requeued_at IS NOT NULL";
+        let retried = self
+            .with_retries(|| async move {
+                let result = self
+                    .connection()
+                    .await?
+                    .execute(sqlx::query(sql).bind(Utc::now()).bind(uid))
+                    .await?;
+                Ok(result.rows_affected() > 0)
+            })
+            .await?;
+        if retried {
+            self.contributors.insert(uid, true);
+        }
+        Ok(retried)
+    }
+
+    /// Counts an extension of the compute deadline of `uid`'s contribution.
+    #[instrument(level = "info", skip_all)]
+    pub async fn record_deadline_extension(&self, uid: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["record_deadline_extension"])
+            .start_timer();
+        let sql =
+            "UPDATE contributors SET deadline_extensions = deadline_extensions + 1 WHERE uid = $1";
+        self.with_retries(|| async move {
+            self.connection()
+                .await?
+                .execute(sqlx::query(sql).bind(uid))
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The [`pseudonym`] of `uid` under `--pseudonym-key`, if configured.
+    #[must_use]
+    pub fn pseudonym(&self, uid: &str) -> Option<String> {
+        self.pseudonym_key
+            .as_ref()
+            .map(|key| pseudonym(key.get_secret().as_bytes(), uid))
+    }
+
+    /// Forgets the identity of `uid`: replaces it by its [`pseudonym`] in the
+    /// contributors, verifications, audit log and contribution timings, and
+    /// drops its row in the
+    /// identities, its country, ENS name, receipts, sessions, waiting room
+    /// entry and notification targets. The transcript is left
+    /// untouched. Returns the pseudonym.
+    #[instrument(level = "info", skip_all)]
+    pub async fn redact_contributor(&self, uid: &str) -> Result<String, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["redact_contributor"])
+            .start_timer();
+        let pseudonym = self.pseudonym(uid).ok_or(StorageError::NoPseudonymKey)?;
+        let mut connection = self.connection().await?;
+        let mut tx = connection.begin().await?;
+        let mut identity_id: Option<i64> = tx
+            .fetch_optional(
+                sqlx::query("SELECT identity_id FROM contributors WHERE uid = $1").bind(uid),
+            )
+            .await?
+            .map(|row| column(&row, "identity_id"))
+            .transpose()?
+            .flatten();
+        // Accounts are registered when they sign in, before they contribute.
+        if let (None, Some(account)) = (identity_id, Account::from_uid(uid)) {
+            let sql = "SELECT id FROM identities WHERE provider = $1 AND provider_id = $2";
+            identity_id = tx
+                .fetch_optional(
+                    sqlx::query(sql)
+                        .bind(account.provider)
+                        .bind(account.provider_id),
+                )
+                .await?
+                .map(|row| row.get(0));
+        }
+        if let Some(identity_id) = identity_id {
+            // Also unlinks the other uids of the identity, which are kept.
+            for sql in [
+                "UPDATE contributors SET identity_id = NULL WHERE identity_id = $1",
+                "DELETE FROM identities WHERE id = $1",
+            ] {
+                tx.execute(sqlx::query(sql).bind(identity_id)).await?;
+            }
+        }
+        for sql in [
+            "UPDATE contributors SET uid = $1, country = NULL, ens_name = NULL, fingerprint_hash \
+             = NULL, subnet = NULL WHERE uid = $2",
+            "UPDATE verifications SET uid = $1 WHERE uid = $2",
+            "UPDATE audit_log SET uid = $1 WHERE uid = $2",
+            "UPDATE contribution_timings SET uid = $1 WHERE uid = $2",
+        ] {
+            tx.execute(sqlx::query(sql).bind(&pseudonym).bind(uid))
+                .await?;
+        }
+        for sql in [
+            "DELETE FROM receipts WHERE uid = $1",
+            "DELETE FROM sessions WHERE uid = $1",
+            "DELETE FROM waiting_room WHERE uid = $1",
+            "DELETE FROM lobby_notifications WHERE uid = $1",
+        ] {
+            tx.execute(sqlx::query(sql).bind(uid)).await?;
+        }
+        tx.commit().await?;
+        Ok(pseudonym)
+    }
+
+    /// Checks that the database connection is alive.
+    #[instrument(level = "info", skip_all)]
+    pub async fn ping(&self) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["ping"]).start_timer();
+        self.connection().await?.ping().await?;
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn is_banned(&self, uid: &str) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["is_banned"]).start_timer();
+        let sql = "SELECT EXISTS(SELECT 1 FROM banned_uids WHERE uid = $1)";
+        let result = self
+            .connection()
+            .await?
             .fetch_one(sqlx::query(sql).bind(uid))
             .await
             .map(|row| row.get(0))?;
         Ok(result)
     }
 
-    pub async fn insert_contributor(&self, uid: &str) -> Result<(), StorageError> {
-        let sql = "INSERT INTO contributors (uid, started_at) VALUES (?1, ?2)";
-        self.0
-            .lock()
-            .await
+    #[instrument(level = "info", skip_all)]
+    pub async fn ban_uid(&self, uid: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["ban_uid"]).start_timer();
+        let sql =
+            "INSERT INTO banned_uids (uid, banned_at) VALUES ($1, $2) ON CONFLICT (uid) DO NOTHING";
+        self.connection()
+            .await?
             .execute(sqlx::query(sql).bind(uid).bind(Utc::now()))
             .await?;
         Ok(())
     }
 
-    pub async fn finish_contribution(&self, uid: &str) -> Result<(), StorageError> {
-        let sql = "UPDATE contributors SET finished_at = ?1 WHERE uid = ?2";
-        self.0
-            .lock()
-            .await
-            .execute(sqlx::query(sql).bind(Utc::now()).bind(uid))
+    #[instrument(level = "info", skip_all)]
+    pub async fn unban_uid(&self, uid: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["unban_uid"]).start_timer();
+        let sql = "DELETE FROM banned_uids WHERE uid = $1";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(uid))
             .await?;
         Ok(())
     }
 
-    pub async fn expire_contribution(&self, uid: &str) -> Result<(), StorageError> {
-        let sql = "UPDATE contributors SET expired_at = ?1 WHERE uid = ?2";
-        self.0
-            .lock()
-            .await
-            .execute(sqlx::query(sql).bind(Utc::now()).bind(uid))
+    /// Sets the lobby priority of `uid`, see
+    /// [`crate::lobby::SharedLobbyState::set_priority`]. 0 removes it.
+    #[instrument(level = "info", skip_all)]
+    pub async fn set_priority(&self, uid: &str, priority: u32) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["set_priority"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        if priority == 0 {
+            let sql = "DELETE FROM lobby_priorities WHERE uid = $1";
+            connection.execute(sqlx::query(sql).bind(uid)).await?;
+        } else {
+            let sql = "INSERT INTO lobby_priorities (uid, priority, updated_at) VALUES ($1, $2, \
+                       $3) ON CONFLICT (uid) DO UPDATE SET priority = $2, updated_at = $3";
+            connection
+                .execute(
+                    sqlx::query(sql)
+                        .bind(uid)
+                        .bind(i64::from(priority))
+                        .bind(Utc::now()),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// The lobby priorities set with [`Self::set_priority`], by uid.
+    #[instrument(level = "info", skip_all)]
+    pub async fn load_priorities(&self) -> Result<Vec<(String, u32)>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["load_priorities"])
+            .start_timer();
+        let sql = "SELECT uid, priority FROM lobby_priorities";
+        let priorities = self
+            .connection()
+            .await?
+            .fetch_all(sql)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let priority = u32::try_from(row.get::<i64, _>(1)).map_err(|_| {
+                    StorageError::CorruptRow("lobby priority out of range".to_string())
+                })?;
+                Ok((row.get(0), priority))
+            })
+            .collect::<Result<_, StorageError>>()?;
+        Ok(priorities)
+    }
+
+    /// Counts a sign-in with `provider` reaching `stage`, see
+    /// [`crate::auth_funnel`].
+    #[instrument(level = "info", skip_all)]
+    pub async fn count_auth_funnel(
+        &self,
+        provider: &str,
+        stage: &str,
+        rule: &str,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["count_auth_funnel"])
+            .start_timer();
+        let sql = "INSERT INTO auth_funnel (provider, stage, rule, count) VALUES ($1, $2, $3, 1) \
+                   ON CONFLICT (provider, stage, rule) DO UPDATE SET count = auth_funnel.count + 1";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(provider).bind(stage).bind(rule))
+            .await?;
+        Ok(())
+    }
+
+    /// The counts of [`Self::count_auth_funnel`], as `(provider, stage, rule,
+    /// count)`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn auth_funnel(&self) -> Result<Vec<(String, String, String, u64)>, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["auth_funnel"]).start_timer();
+        let sql = "SELECT provider, stage, rule, count FROM auth_funnel";
+        let rows = self
+            .read_connection()
+            .await?
+            .fetch_all(sql)
+            .await?
+            .into_iter()
+            .map(|row| {
+                Ok((
+                    column(&row, "provider")?,
+                    column(&row, "stage")?,
+                    column(&row, "rule")?,
+                    int_column(&row, "count")?,
+                ))
+            })
+            .collect::<Result<_, StorageError>>()?;
+        Ok(rows)
+    }
+
+    /// Stores the lottery draw of an epoch, unless one is stored already.
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_lottery_draw(&self, draw: &Draw) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_lottery_draw"])
+            .start_timer();
+        let sql = "INSERT INTO lottery_draws (epoch, block_number, block_hash, draw, drawn_at) \
+                   VALUES ($1, $2, $3, $4, $5) ON CONFLICT (epoch) DO NOTHING";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(i64::try_from(draw.epoch).unwrap_or(i64::MAX))
+                    .bind(i64::try_from(draw.block_number).unwrap_or(i64::MAX))
+                    .bind(&draw.block_hash)
+                    .bind(serde_json::to_string(draw)?)
+                    .bind(Utc::now()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The lottery draw of `epoch`, or the latest one.
+    #[instrument(level = "info", skip_all)]
+    pub async fn lottery_draw(&self, epoch: Option<u64>) -> Result<Option<Draw>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["lottery_draw"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        let row = match epoch {
+            Some(epoch) => {
+                let sql = "SELECT draw FROM lottery_draws WHERE epoch = $1";
+                connection
+                    .fetch_optional(sqlx::query(sql).bind(i64::try_from(epoch).unwrap_or(i64::MAX)))
+                    .await?
+            }
+            None => {
+                let sql = "SELECT draw FROM lottery_draws ORDER BY epoch DESC LIMIT 1";
+                connection.fetch_optional(sql).await?
+            }
+        };
+        Ok(row
+            .map(|row| serde_json::from_str(&row.get::<String, _>(0)))
+            .transpose()?)
+    }
+
+    /// Records the latest contribution of `transcript`, including the
+    /// resulting powers.
+    #[instrument(level = "info", skip_all)]
+    pub async fn append_transcript_entry(
+        &self,
+        transcript: &BatchTranscript,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["append_transcript_entry"])
+            .start_timer();
+        let entry =
+            TranscriptEntry::from_transcript(transcript, transcript.num_participants(), true)?;
+        self.connection()
+            .await?
+            .execute(entry.insert_query())
             .await?;
         Ok(())
     }
+
+    /// Records the contribution `verified` adds to the transcript, before it
+    /// is applied, so that a transcript never holds a contribution the
+    /// database does not.
+    #[instrument(level = "info", skip_all)]
+    pub async fn append_verified_entry(
+        &self,
+        verified: &VerifiedContribution,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["append_transcript_entry"])
+            .start_timer();
+        let entry = TranscriptEntry::from_verified(verified)?;
+        self.connection()
+            .await?
+            .execute(entry.insert_query())
+            .await?;
+        Ok(())
+    }
+
+    /// Records all contributions of an existing transcript, e.g. one loaded
+    /// from a transcript file. Intermediate powers are not part of the
+    /// transcript, so only the latest entry carries powers.
+    #[instrument(level = "info", skip_all)]
+    pub async fn import_transcript(
+        &self,
+        transcript: &BatchTranscript,
+    ) -> Result<(), StorageError> {
+        self.import_transcript_from(transcript, 1).await
+    }
+
+    /// Like [`Self::import_transcript`], but only the contributions from
+    /// position `first` on, e.g. those a standby sequencer is missing.
+    #[instrument(level = "info", skip_all)]
+    pub async fn import_transcript_from(
+        &self,
+        transcript: &BatchTranscript,
+        first: usize,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["import_transcript"])
+            .start_timer();
+        let num_participants = transcript.num_participants();
+        let mut connection = self.connection().await?;
+        let mut tx = connection.begin().await?;
+        for position in first.max(1)..=num_participants {
+            let entry = TranscriptEntry::from_transcript(
+                transcript,
+                position,
+                position == num_participants,
+            )?;
+            tx.execute(entry.insert_query()).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Stores the entry of the transparency log at `leaf_index`, see
+    /// [`crate::transparency_log`]. Entries are never replaced.
+    #[instrument(level = "info", skip_all)]
+    pub async fn append_log_entry(
+        &self,
+        leaf_index: usize,
+        entry: &str,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["append_log_entry"])
+            .start_timer();
+        let sql = "INSERT INTO transparency_log (leaf_index, entry) VALUES ($1, $2)";
+        let leaf_index = i64::try_from(leaf_index).unwrap_or(i64::MAX);
+        self.with_retries(|| async move {
+            self.connection()
+                .await?
+                .execute(sqlx::query(sql).bind(leaf_index).bind(entry))
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The entries of the transparency log, in order.
+    #[instrument(level = "info", skip_all)]
+    pub async fn log_entries(&self) -> Result<Vec<String>, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["log_entries"]).start_timer();
+        let sql = "SELECT entry FROM transparency_log ORDER BY leaf_index";
+        let entries = self
+            .connection()
+            .await?
+            .fetch_all(sql)
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        Ok(entries)
+    }
+
+    /// Stores how long the phases of handling a contribution took.
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_contribution_timings(
+        &self,
+        timings: &StoredContributionTimings,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_contribution_timings"])
+            .start_timer();
+        let sql = "INSERT INTO contribution_timings (uid, contribution_index, receive_us, \
+                   deserialize_us, subgroup_check_us, pairing_us, transcript_write_us, \
+                   respond_us, recorded_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)";
+        let micros = |duration: Duration| i64::try_from(duration.as_micros()).unwrap_or(i64::MAX);
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(&timings.uid)
+                    .bind(i64::try_from(timings.contribution_index).unwrap_or(i64::MAX))
+                    .bind(micros(timings.receive))
+                    .bind(micros(timings.deserialize))
+                    .bind(micros(timings.subgroup_check))
+                    .bind(micros(timings.pairing))
+                    .bind(micros(timings.transcript_write))
+                    .bind(micros(timings.respond))
+                    .bind(timings.recorded_at),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The timings of the last `limit` contributions, newest first.
+    #[instrument(level = "info", skip_all)]
+    pub async fn contribution_timings(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<StoredContributionTimings>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["contribution_timings"])
+            .start_timer();
+        let sql = "SELECT uid, contribution_index, receive_us, deserialize_us, subgroup_check_us, \
+                   pairing_us, transcript_write_us, respond_us, recorded_at FROM \
+                   contribution_timings ORDER BY id DESC LIMIT $1";
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        Ok(self
+            .connection()
+            .await?
+            .fetch_all(sqlx::query(sql).bind(limit))
+            .await?
+            .iter()
+            .map(StoredContributionTimings::from_row)
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Number of contributions recorded in the transcript entries.
+    #[instrument(level = "info", skip_all)]
+    pub async fn count_transcript_entries(&self) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["count_transcript_entries"])
+            .start_timer();
+        let sql = "SELECT COUNT(*) AS count FROM transcript_entries";
+        let row = self.connection().await?.fetch_one(sql).await?;
+        int_column(&row, "count")
+    }
+
+    /// The stored contribution at `position`, without the powers.
+    #[instrument(level = "info", skip_all)]
+    pub async fn read_transcript_entry(
+        &self,
+        position: usize,
+    ) -> Result<Option<StoredTranscriptEntry>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["read_transcript_entry"])
+            .start_timer();
+        let position = i64::try_from(position)
+            .map_err(|_| StorageError::CorruptTranscript("position overflow".to_string()))?;
+        let sql = "SELECT participant_id, witness FROM transcript_entries WHERE position = $1";
+        let row = match self
+            .connection()
+            .await?
+            .fetch_optional(sqlx::query(sql).bind(position))
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        Ok(Some(StoredTranscriptEntry {
+            participant_id: row.get::<String, _>(0).parse()?,
+            witness:        serde_json::from_str(&row.get::<String, _>(1))?,
+        }))
+    }
+
+    /// Reconstructs the full transcript from the stored contributions.
+    /// Returns `None` if no contributions have been recorded yet.
+    #[instrument(level = "info", skip_all)]
+    pub async fn read_transcript(&self) -> Result<Option<BatchTranscript>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["read_transcript"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        let sql = "SELECT powers FROM transcript_entries ORDER BY position DESC LIMIT 1";
+        let powers = match connection.fetch_optional(sql).await? {
+            Some(row) => row.get::<Option<String>, _>(0).ok_or_else(|| {
+                StorageError::CorruptTranscript("latest entry has no powers".to_string())
+            })?,
+            None => return Ok(None),
+        };
+        let powers: Vec<Powers> = serde_json::from_str(&powers)?;
+
+        let mut transcript = BatchTranscript::new(&[]);
+        transcript.transcripts = powers
+            .into_iter()
+            .map(|powers| {
+                let mut transcript = Transcript::new(powers.g1.len(), powers.g2.len());
+                transcript.powers = powers;
+                transcript
+            })
+            .collect();
+
+        let sql = "SELECT position, participant_id, ecdsa_signature, witness, entropy_attestation \
+                   FROM transcript_entries ORDER BY position ASC";
+        for row in connection.fetch_all(sql).await? {
+            push_transcript_entry(&mut transcript, &row)?;
+        }
+        Ok(Some(transcript))
+    }
+
+    /// Adds the contributions recorded after the last one of `transcript`,
+    /// e.g. by another sequencer sharing the database, and takes the powers
+    /// of the latest. Returns the number of contributions added. The
+    /// transcript is left as it was if they can not be read.
+    #[instrument(level = "info", skip_all)]
+    pub async fn read_transcript_tail(
+        &self,
+        transcript: &mut BatchTranscript,
+    ) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["read_transcript_tail"])
+            .start_timer();
+        let after = i64::try_from(transcript.num_participants())
+            .map_err(|_| StorageError::CorruptTranscript("position overflow".to_string()))?;
+        let sql = "SELECT position, participant_id, ecdsa_signature, witness, \
+                   entropy_attestation, powers FROM transcript_entries WHERE position > $1 ORDER \
+                   BY position ASC";
+        let rows = self
+            .connection()
+            .await?
+            .fetch_all(sqlx::query(sql).bind(after))
+            .await?;
+        let latest = match rows.last() {
+            Some(row) => row.get::<Option<String>, _>(5).ok_or_else(|| {
+                StorageError::CorruptTranscript("latest entry has no powers".to_string())
+            })?,
+            None => return Ok(0),
+        };
+        let powers: Vec<Powers> = serde_json::from_str(&latest)?;
+        if powers.len() != transcript.transcripts.len() {
+            return Err(StorageError::CorruptTranscript(format!(
+                "latest entry has {} powers, expected {}",
+                powers.len(),
+                transcript.transcripts.len()
+            )));
+        }
+
+        let mut extended = transcript.clone();
+        for row in &rows {
+            push_transcript_entry(&mut extended, row)?;
+        }
+        for (transcript, powers) in extended.transcripts.iter_mut().zip(powers) {
+            transcript.powers = powers;
+        }
+        *transcript = extended;
+        Ok(rows.len())
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_audit_record(&self, record: &AuditRecord) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_audit_record"])
+            .start_timer();
+        let sql = "INSERT INTO audit_log (created_at, action, uid, ip, outcome) VALUES ($1, $2, \
+                   $3, $4, $5)";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(record.timestamp)
+                    .bind(<&str>::from(record.action))
+                    .bind(record.uid.clone())
+                    .bind(record.ip.map(|ip| ip.to_string()))
+                    .bind(record.outcome.as_str()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    #[instrument(level = "info", skip_all)]
+    pub async fn count_audit_records(&self) -> Result<usize, StorageError> {
+        let row = self
+            .connection()
+            .await?
+            .fetch_one("SELECT COUNT(*) AS count FROM audit_log")
+            .await?;
+        int_column(&row, "count")
+    }
+
+    /// Stores the signed receipt for the contribution at `position`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_receipt(
+        &self,
+        uid: &str,
+        position: usize,
+        receipt: &StoredReceipt,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_receipt"])
+            .start_timer();
+        let position = i64::try_from(position)
+            .map_err(|_| StorageError::CorruptTranscript("position overflow".to_string()))?;
+        let sql = "INSERT INTO receipts (position, uid, receipt, signature, created_at) VALUES \
+                   ($1, $2, $3, $4, $5)";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(position)
+                    .bind(uid)
+                    .bind(&receipt.receipt)
+                    .bind(&receipt.signature)
+                    .bind(Utc::now()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the receipt of the latest contribution made by `uid`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn get_receipt(&self, uid: &str) -> Result<Option<StoredReceipt>, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["get_receipt"]).start_timer();
+        let sql =
+            "SELECT receipt, signature FROM receipts WHERE uid = $1 ORDER BY position DESC LIMIT 1";
+        let receipt = self
+            .connection()
+            .await?
+            .fetch_optional(sqlx::query(sql).bind(uid))
+            .await?
+            .map(|row| StoredReceipt {
+                receipt:   row.get(0),
+                signature: row.get(1),
+            });
+        Ok(receipt)
+    }
+
+    /// Number of contributions with a stored receipt.
+    #[instrument(level = "info", skip_all)]
+    pub async fn count_receipts(&self) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["count_receipts"])
+            .start_timer();
+        let row = self
+            .read_connection()
+            .await?
+            .fetch_one("SELECT COUNT(*) AS count FROM receipts")
+            .await?;
+        int_column(&row, "count")
+    }
+
+    /// Returns `limit` contributions with a stored receipt, skipping the first
+    /// `offset` in `order`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn list_contributors(
+        &self,
+        order: ContributorOrder,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<StoredContributor>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["list_contributors"])
+            .start_timer();
+        let to_i64 = |value: usize| i64::try_from(value).unwrap_or(i64::MAX);
+        let mut connection = self.read_connection().await?;
+        match order {
+            ContributorOrder::Index => {
+                let sql = format!("{CONTRIBUTOR_SELECT} ORDER BY r.position LIMIT $1 OFFSET $2");
+                let rows = connection
+                    .fetch_all(sqlx::query(&sql).bind(to_i64(limit)).bind(to_i64(offset)))
+                    .await?;
+                rows.iter().map(StoredContributor::from_row).collect()
+            }
+            ContributorOrder::Fastest => {
+                // The timestamps are stored differently by the backends, so
+                // the durations are compared here rather than in SQL.
+                let sql = format!(
+                    "SELECT position, started_at, finished_at FROM ({CONTRIBUTOR_SELECT}) attempts"
+                );
+                let mut durations = connection
+                    .fetch_all(sql.as_str())
+                    .await?
+                    .iter()
+                    .map(|row| {
+                        let started_at: Option<DateTime<Utc>> = column(row, "started_at")?;
+                        let finished_at: Option<DateTime<Utc>> = column(row, "finished_at")?;
+                        let duration = started_at
+                            .zip(finished_at)
+                            .map(|(started_at, finished_at)| finished_at - started_at);
+                        Ok((duration, column::<i64>(row, "position")?))
+                    })
+                    .collect::<Result<Vec<_>, StorageError>>()?;
+                // Contributions without a known duration go last.
+                durations.sort_unstable_by_key(|&(duration, position)| {
+                    (duration.is_none(), duration, position)
+                });
+                let positions = durations
+                    .into_iter()
+                    .skip(offset)
+                    .take(limit)
+                    .map(|(_, position)| position)
+                    .collect::<Vec<_>>();
+                if positions.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let placeholders = (1..=positions.len())
+                    .map(|n| format!("${n}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let sql = format!("{CONTRIBUTOR_SELECT} WHERE r.position IN ({placeholders})");
+                let query = positions
+                    .iter()
+                    .fold(sqlx::query(&sql), |query, position| query.bind(*position));
+                let mut contributors = connection
+                    .fetch_all(query)
+                    .await?
+                    .iter()
+                    .map(StoredContributor::from_row)
+                    .collect::<Result<Vec<_>, _>>()?;
+                contributors.sort_by_key(|contributor| {
+                    positions
+                        .iter()
+                        .position(|&position| to_i64(contributor.position) == position)
+                });
+                Ok(contributors)
+            }
+        }
+    }
+
+    /// Returns the contribution at `position` in the transcript, if its
+    /// receipt is stored.
+    #[instrument(level = "info", skip_all)]
+    pub async fn get_contributor(
+        &self,
+        position: usize,
+    ) -> Result<Option<StoredContributor>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["get_contributor"])
+            .start_timer();
+        let position = match i64::try_from(position) {
+            Ok(position) => position,
+            Err(_) => return Ok(None),
+        };
+        let sql = format!("{CONTRIBUTOR_SELECT} WHERE r.position = $1");
+        self.read_connection()
+            .await?
+            .fetch_optional(sqlx::query(&sql).bind(position))
+            .await?
+            .as_ref()
+            .map(StoredContributor::from_row)
+            .transpose()
+    }
+
+    /// Records a submitted contribution, or its outcome once `status` is set.
+    /// Replaces a record with the same payload hash only if it is of the same
+    /// uid and either of the same verification or still pending.
+    #[instrument(level = "info", skip_all)]
+    pub async fn save_verification(
+        &self,
+        verification: &StoredVerification,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["save_verification"])
+            .start_timer();
+        let sql = "INSERT INTO verifications (payload_hash, uid, verification_id, status, \
+                   created_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (payload_hash) DO UPDATE \
+                   SET verification_id = $3, status = $4 WHERE verifications.uid = $2 AND \
+                   (verifications.verification_id = $3 OR verifications.status IS NULL)";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(&verification.payload_hash)
+                    .bind(&verification.uid)
+                    .bind(&verification.verification_id)
+                    .bind(verification.status.clone())
+                    .bind(Utc::now()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the verification of the contribution with `payload_hash`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn get_verification_by_hash(
+        &self,
+        payload_hash: &str,
+    ) -> Result<Option<StoredVerification>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["get_verification_by_hash"])
+            .start_timer();
+        let sql = "SELECT payload_hash, uid, verification_id, status FROM verifications WHERE \
+                   payload_hash = $1";
+        let verification = self
+            .connection()
+            .await?
+            .fetch_optional(sqlx::query(sql).bind(payload_hash))
+            .await?
+            .map(|row| StoredVerification::from_row(&row));
+        Ok(verification)
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn get_verification(
+        &self,
+        verification_id: &str,
+    ) -> Result<Option<StoredVerification>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["get_verification"])
+            .start_timer();
+        let sql = "SELECT payload_hash, uid, verification_id, status FROM verifications WHERE \
+                   verification_id = $1";
+        let verification = self
+            .connection()
+            .await?
+            .fetch_optional(sqlx::query(sql).bind(verification_id))
+            .await?
+            .map(|row| StoredVerification::from_row(&row));
+        Ok(verification)
+    }
+
+    /// Stores a new session and drops any other session of the same uid. An
+    /// existing session only has its expiry and score updated, so that its
+    /// place in the lobby is kept.
+    #[instrument(level = "info", skip_all)]
+    pub async fn save_session(&self, session: &StoredSession) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["save_session"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        let mut tx = connection.begin().await?;
+        let sql = "DELETE FROM sessions WHERE uid = $1 AND token_hash <> $2";
+        tx.execute(
+            sqlx::query(sql)
+                .bind(&session.uid)
+                .bind(&session.token_hash),
+        )
+        .await?;
+        let sql = "INSERT INTO sessions (token_hash, uid, expires_at, lobby_entered_at, \
+                   eligibility_score, identity) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT \
+                   (token_hash) DO UPDATE SET expires_at = $3, eligibility_score = $5";
+        tx.execute(
+            sqlx::query(sql)
+                .bind(&session.token_hash)
+                .bind(&session.uid)
+                .bind(session.expires_at)
+                .bind(session.lobby_entered_at)
+                .bind(session.eligibility_score.map(i64::from))
+                .bind(&session.identity),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Records that the session entered the lobby at `entered_at`, or left it
+    /// if `None`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn update_session_lobby(
+        &self,
+        token_hash: &str,
+        entered_at: Option<DateTime<Utc>>,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["update_session_lobby"])
+            .start_timer();
+        let sql = "UPDATE sessions SET lobby_entered_at = $1 WHERE token_hash = $2";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(entered_at).bind(token_hash))
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn delete_session(&self, token_hash: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["delete_session"])
+            .start_timer();
+        let sql = "DELETE FROM sessions WHERE token_hash = $1";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(token_hash))
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes the sessions that expired by `now`. Returns how many.
+    #[instrument(level = "info", skip_all)]
+    pub async fn delete_expired_sessions(&self, now: DateTime<Utc>) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["delete_expired_sessions"])
+            .start_timer();
+        let sql = "DELETE FROM sessions WHERE expires_at <= $1";
+        let result = self
+            .connection()
+            .await?
+            .execute(sqlx::query(sql).bind(now))
+            .await?;
+        Ok(usize::try_from(result.rows_affected()).unwrap_or(usize::MAX))
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn delete_sessions_of(&self, uid: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["delete_sessions_of"])
+            .start_timer();
+        let sql = "DELETE FROM sessions WHERE uid = $1";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(uid))
+            .await?;
+        Ok(())
+    }
+
+    /// Stores a sign-in nonce and drops expired ones.
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_auth_nonce(
+        &self,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_auth_nonce"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        connection
+            .execute(sqlx::query("DELETE FROM auth_nonces WHERE expires_at <= $1").bind(Utc::now()))
+            .await?;
+        let sql = "INSERT INTO auth_nonces (nonce, expires_at) VALUES ($1, $2)";
+        connection
+            .execute(sqlx::query(sql).bind(nonce).bind(expires_at))
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes the sign-in nonces that expired by `now` without being used.
+    /// Returns how many.
+    #[instrument(level = "info", skip_all)]
+    pub async fn delete_expired_auth_nonces(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["delete_expired_auth_nonces"])
+            .start_timer();
+        let sql = "DELETE FROM auth_nonces WHERE expires_at <= $1";
+        let result = self
+            .connection()
+            .await?
+            .execute(sqlx::query(sql).bind(now))
+            .await?;
+        Ok(usize::try_from(result.rows_affected()).unwrap_or(usize::MAX))
+    }
+
+    /// Deletes a sign-in nonce. Returns whether it existed and had not
+    /// expired, which is the case only once per nonce.
+    #[instrument(level = "info", skip_all)]
+    pub async fn consume_auth_nonce(&self, nonce: &str) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["consume_auth_nonce"])
+            .start_timer();
+        let sql = "DELETE FROM auth_nonces WHERE nonce = $1 AND expires_at > $2";
+        let result = self
+            .connection()
+            .await?
+            .execute(sqlx::query(sql).bind(nonce).bind(Utc::now()))
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Adds `uid` to the end of the waiting room, unless it is waiting
+    /// already. Returns its position, starting at 1.
+    #[instrument(level = "info", skip_all)]
+    pub async fn join_waiting_room(&self, uid: &str) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["join_waiting_room"])
+            .start_timer();
+        let sql = "INSERT INTO waiting_room (uid, joined_at) VALUES ($1, $2) ON CONFLICT (uid) DO \
+                   NOTHING";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(uid).bind(Utc::now()))
+            .await?;
+        Ok(self.waiting_room_position(uid).await?.unwrap_or_default())
+    }
+
+    /// Position of `uid` in the waiting room, starting at 1.
+    #[instrument(level = "info", skip_all)]
+    pub async fn waiting_room_position(&self, uid: &str) -> Result<Option<usize>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["waiting_room_position"])
+            .start_timer();
+        let sql = "SELECT COUNT(*) AS count FROM waiting_room WHERE joined_at <= (SELECT \
+                   joined_at FROM waiting_room WHERE uid = $1)";
+        let row = self
+            .connection()
+            .await?
+            .fetch_one(sqlx::query(sql).bind(uid))
+            .await?;
+        Ok(Some(int_column::<usize>(&row, "count")?).filter(|&count| count > 0))
+    }
+
+    /// Sets the address `uid` is told at that a slot is free. Returns whether
+    /// `uid` is in the waiting room.
+    #[instrument(level = "info", skip_all)]
+    pub async fn set_waiting_room_email(
+        &self,
+        uid: &str,
+        email: &str,
+    ) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["set_waiting_room_email"])
+            .start_timer();
+        let sql = "UPDATE waiting_room SET email = $1 WHERE uid = $2";
+        let result = self
+            .connection()
+            .await?
+            .execute(sqlx::query(sql).bind(email).bind(uid))
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn leave_waiting_room(&self, uid: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["leave_waiting_room"])
+            .start_timer();
+        self.connection()
+            .await?
+            .execute(sqlx::query("DELETE FROM waiting_room WHERE uid = $1").bind(uid))
+            .await?;
+        Ok(())
+    }
+
+    /// Drops the participants that were announced a free slot before
+    /// `announced_before` and did not take it. Returns the number of waiting
+    /// participants that were announced a slot and are still expected.
+    #[instrument(level = "info", skip_all)]
+    pub async fn expire_waiting_room(
+        &self,
+        announced_before: DateTime<Utc>,
+    ) -> Result<usize, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["expire_waiting_room"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        connection
+            .execute(
+                sqlx::query("DELETE FROM waiting_room WHERE announced_at < $1")
+                    .bind(announced_before),
+            )
+            .await?;
+        let row = connection
+            .fetch_one("SELECT COUNT(*) AS count FROM waiting_room WHERE announced_at IS NOT NULL")
+            .await?;
+        int_column(&row, "count")
+    }
+
+    /// Marks up to `limit` of the longest waiting participants that were not
+    /// announced a slot yet as announced, and returns them. A participant is
+    /// returned by only one of concurrent calls.
+    #[instrument(level = "info", skip_all)]
+    pub async fn announce_waiting(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<WaitingParticipant>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["announce_waiting"])
+            .start_timer();
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let mut connection = self.connection().await?;
+        let sql = "SELECT uid, email FROM waiting_room WHERE announced_at IS NULL ORDER BY \
+                   joined_at LIMIT $1";
+        let candidates = connection
+            .fetch_all(sqlx::query(sql).bind(limit))
+            .await?
+            .into_iter()
+            .map(|row| WaitingParticipant {
+                uid:   row.get(0),
+                email: row.get(1),
+            })
+            .collect::<Vec<_>>();
+        let now = Utc::now();
+        let mut announced = Vec::with_capacity(candidates.len());
+        for participant in candidates {
+            let sql =
+                "UPDATE waiting_room SET announced_at = $1 WHERE uid = $2 AND announced_at IS NULL";
+            let result = connection
+                .execute(sqlx::query(sql).bind(now).bind(&participant.uid))
+                .await?;
+            if result.rows_affected() == 1 {
+                announced.push(participant);
+            }
+        }
+        Ok(announced)
+    }
+
+    /// Registers `target` to be notified when the session with `token_hash`
+    /// is nearly up, replacing a target it registered before and resetting
+    /// its delivery.
+    #[instrument(level = "info", skip_all)]
+    pub async fn register_notification(
+        &self,
+        token_hash: &str,
+        uid: &str,
+        target: &NotificationTarget,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["register_notification"])
+            .start_timer();
+        let sql = "INSERT INTO lobby_notifications (token_hash, uid, channel, target, status, \
+                   attempts, created_at) VALUES ($1, $2, $3, $4, $5, 0, $6) ON CONFLICT \
+                   (token_hash) DO UPDATE SET channel = $3, target = $4, status = $5, attempts = \
+                   0, last_error = NULL, created_at = $6, sent_at = NULL";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(token_hash)
+                    .bind(uid)
+                    .bind(target.channel())
+                    .bind(target.address())
+                    .bind(<&str>::from(NotificationStatus::Pending))
+                    .bind(Utc::now()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The notification target of the session with `token_hash`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn notification(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<StoredNotification>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["notification"])
+            .start_timer();
+        let sql = "SELECT token_hash, uid, channel, target, status, attempts, last_error, sent_at \
+                   FROM lobby_notifications WHERE token_hash = $1";
+        let row = self
+            .connection()
+            .await?
+            .fetch_optional(sqlx::query(sql).bind(token_hash))
+            .await?;
+        Ok(row.as_ref().map(stored_notification).transpose()?.flatten())
+    }
+
+    /// The notifications not sent yet, oldest first.
+    #[instrument(level = "info", skip_all)]
+    pub async fn pending_notifications(&self) -> Result<Vec<StoredNotification>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["pending_notifications"])
+            .start_timer();
+        let sql = "SELECT token_hash, uid, channel, target, status, attempts, last_error, sent_at \
+                   FROM lobby_notifications WHERE status = $1 ORDER BY created_at";
+        let rows = self
+            .connection()
+            .await?
+            .fetch_all(sqlx::query(sql).bind(<&str>::from(NotificationStatus::Pending)))
+            .await?;
+        rows.iter()
+            .filter_map(|row| stored_notification(row).transpose())
+            .collect()
+    }
+
+    /// Cancels the pending notifications of sessions that are no longer in
+    /// the `sessions` table.
+    #[instrument(level = "info", skip_all)]
+    pub async fn cancel_orphaned_notifications(&self) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["cancel_orphaned_notifications"])
+            .start_timer();
+        let sql = "UPDATE lobby_notifications SET status = $1 WHERE status = $2 AND token_hash \
+                   NOT IN (SELECT token_hash FROM sessions)";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(<&str>::from(NotificationStatus::Cancelled))
+                    .bind(<&str>::from(NotificationStatus::Pending)),
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn record_notification_sent(&self, token_hash: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["record_notification_sent"])
+            .start_timer();
+        let sql = "UPDATE lobby_notifications SET status = $1, attempts = attempts + 1, sent_at = \
+                   $2 WHERE token_hash = $3 AND status = $4";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(<&str>::from(NotificationStatus::Sent))
+                    .bind(Utc::now())
+                    .bind(token_hash)
+                    .bind(<&str>::from(NotificationStatus::Pending)),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Records a failed delivery. The notification is given up after
+    /// `max_attempts`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn record_notification_failure(
+        &self,
+        token_hash: &str,
+        error: &str,
+        max_attempts: u32,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["record_notification_failure"])
+            .start_timer();
+        let sql = "UPDATE lobby_notifications SET attempts = attempts + 1, last_error = $1, \
+                   status = CASE WHEN attempts + 1 >= $2 THEN $3 ELSE status END WHERE token_hash \
+                   = $4 AND status = $5";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(error)
+                    .bind(i64::from(max_attempts))
+                    .bind(<&str>::from(NotificationStatus::Failed))
+                    .bind(token_hash)
+                    .bind(<&str>::from(NotificationStatus::Pending)),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Records that `uid`'s contribution expired, unless it finished or
+    /// expired already. Returns whether it was still in progress.
+    #[instrument(level = "info", skip_all)]
+    pub async fn expire_unfinished_contribution(&self, uid: &str) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["expire_unfinished_contribution"])
+            .start_timer();
+        let sql = "UPDATE contributors SET expired_at = $1 WHERE uid = $2 AND finished_at IS NULL \
+                   AND expired_at IS NULL";
+        let result = self
+            .connection()
+            .await?
+            .execute(sqlx::query(sql).bind(Utc::now()).bind(uid))
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Adds the job `kind` for `key`, or moves the pending one to `run_at`
+    /// with the new payload and its attempts reset. See
+    /// [`crate::scheduler`].
+    #[instrument(level = "info", skip_all)]
+    pub async fn schedule_job(
+        &self,
+        kind: &str,
+        key: &str,
+        payload: &str,
+        run_at: DateTime<Utc>,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["schedule_job"])
+            .start_timer();
+        let sql = "INSERT INTO jobs (kind, key, payload, run_at) VALUES ($1, $2, $3, $4) ON \
+                   CONFLICT (kind, key) DO UPDATE SET payload = excluded.payload, run_at = \
+                   excluded.run_at, attempts = 0, last_error = NULL";
+        self.with_retries(|| async move {
+            self.connection()
+                .await?
+                .execute(
+                    sqlx::query(sql)
+                        .bind(kind)
+                        .bind(key)
+                        .bind(payload)
+                        .bind(run_at),
+                )
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Moves the job `kind` for `key` to `run_at`, if it is still pending.
+    #[instrument(level = "info", skip_all)]
+    pub async fn reschedule_job(
+        &self,
+        kind: &str,
+        key: &str,
+        run_at: DateTime<Utc>,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["reschedule_job"])
+            .start_timer();
+        let sql = "UPDATE jobs SET run_at = $1 WHERE kind = $2 AND key = $3";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(run_at).bind(kind).bind(key))
+            .await?;
+        Ok(())
+    }
+
+    /// Counts a failed attempt of the job `kind` for `key` and moves it to
+    /// `retry_at`.
+    #[instrument(level = "info", skip_all)]
+    pub async fn fail_job(
+        &self,
+        kind: &str,
+        key: &str,
+        error: &str,
+        retry_at: DateTime<Utc>,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["fail_job"]).start_timer();
+        let sql = "UPDATE jobs SET attempts = attempts + 1, last_error = $1, run_at = $2 WHERE \
+                   kind = $3 AND key = $4";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(error)
+                    .bind(retry_at)
+                    .bind(kind)
+                    .bind(key),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the job `kind` for `key`. Returns whether it was pending, so
+    /// that only one of concurrent callers runs it.
+    #[instrument(level = "info", skip_all)]
+    pub async fn delete_job(&self, kind: &str, key: &str) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["delete_job"]).start_timer();
+        let result = self
+            .connection()
+            .await?
+            .execute(
+                sqlx::query("DELETE FROM jobs WHERE kind = $1 AND key = $2")
+                    .bind(kind)
+                    .bind(key),
+            )
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The jobs due at `now`, the longest overdue first.
+    #[instrument(level = "info", skip_all)]
+    pub async fn due_jobs(&self, now: DateTime<Utc>) -> Result<Vec<StoredJob>, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["due_jobs"]).start_timer();
+        let sql = "SELECT kind, key, payload, run_at, attempts FROM jobs WHERE run_at <= $1 ORDER \
+                   BY run_at";
+        let jobs = self
+            .connection()
+            .await?
+            .fetch_all(sqlx::query(sql).bind(now))
+            .await?
+            .into_iter()
+            .map(|row| {
+                Ok(StoredJob {
+                    kind:     column(&row, "kind")?,
+                    key:      column(&row, "key")?,
+                    payload:  column(&row, "payload")?,
+                    run_at:   column(&row, "run_at")?,
+                    attempts: int_column(&row, "attempts")?,
+                })
+            })
+            .collect::<Result<_, StorageError>>()?;
+        Ok(jobs)
+    }
+
+    /// When the next job after `now` is due, if any.
+    #[instrument(level = "info", skip_all)]
+    pub async fn next_job_at(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["next_job_at"]).start_timer();
+        let sql = "SELECT run_at FROM jobs WHERE run_at > $1 ORDER BY run_at LIMIT 1";
+        Ok(self
+            .connection()
+            .await?
+            .fetch_optional(sqlx::query(sql).bind(now))
+            .await?
+            .map(|row| row.get(0)))
+    }
+
+    /// Drops expired sessions and returns the remaining ones.
+    #[instrument(level = "info", skip_all)]
+    pub async fn load_sessions(&self) -> Result<Vec<StoredSession>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["load_sessions"])
+            .start_timer();
+        let now = Utc::now();
+        let mut connection = self.connection().await?;
+        connection
+            .execute(sqlx::query("DELETE FROM sessions WHERE expires_at <= $1").bind(now))
+            .await?;
+        let sql = "SELECT token_hash, uid, expires_at, lobby_entered_at, eligibility_score, \
+                   identity FROM sessions";
+        let sessions = connection
+            .fetch_all(sql)
+            .await?
+            .into_iter()
+            .map(|row| {
+                Ok(StoredSession {
+                    token_hash:        column(&row, "token_hash")?,
+                    uid:               column(&row, "uid")?,
+                    identity:          column(&row, "identity")?,
+                    expires_at:        column(&row, "expires_at")?,
+                    lobby_entered_at:  column(&row, "lobby_entered_at")?,
+                    eligibility_score: optional_int_column(&row, "eligibility_score")?,
+                })
+            })
+            .collect::<Result<_, StorageError>>()?;
+        Ok(sessions)
+    }
+
+    /// Starts a wait of the session in the lobby, counting the poll it joined
+    /// with. See [`crate::fairness`].
+    #[instrument(level = "info", skip_all)]
+    pub async fn start_lobby_wait(
+        &self,
+        session_hash: &str,
+        provider: &str,
+        joined_at: DateTime<Utc>,
+    ) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["start_lobby_wait"])
+            .start_timer();
+        let sql = "INSERT INTO lobby_waits (session_hash, provider, joined_at, polls) VALUES ($1, \
+                   $2, $3, 1)";
+        self.connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(session_hash)
+                    .bind(provider)
+                    .bind(joined_at),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Counts a poll of the session's current wait in the lobby.
+    #[instrument(level = "info", skip_all)]
+    pub async fn count_lobby_poll(&self, session_hash: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["count_lobby_poll"])
+            .start_timer();
+        let sql =
+            "UPDATE lobby_waits SET polls = polls + 1 WHERE session_hash = $1 AND outcome IS NULL";
+        self.connection()
+            .await?
+            .execute(sqlx::query(sql).bind(session_hash))
+            .await?;
+        Ok(())
+    }
+
+    /// Ends the session's current wait in the lobby with `outcome`. Returns
+    /// the wait as it was, if there was one.
+    #[instrument(level = "info", skip_all)]
+    pub async fn finish_lobby_wait(
+        &self,
+        session_hash: &str,
+        outcome: &str,
+        finished_at: DateTime<Utc>,
+    ) -> Result<Option<StoredLobbyWait>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["finish_lobby_wait"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        let sql = "SELECT id, provider, joined_at, polls, outcome, finished_at FROM lobby_waits \
+                   WHERE session_hash = $1 AND outcome IS NULL ORDER BY id DESC LIMIT 1";
+        let row = match connection
+            .fetch_optional(sqlx::query(sql).bind(session_hash))
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let sql = "UPDATE lobby_waits SET outcome = $1, finished_at = $2 WHERE id = $3";
+        connection
+            .execute(
+                sqlx::query(sql)
+                    .bind(outcome)
+                    .bind(finished_at)
+                    .bind(column::<i64>(&row, "id")?),
+            )
+            .await?;
+        Ok(Some(StoredLobbyWait::from_row(&row)?))
+    }
+
+    /// Hashes of the sessions with an unfinished wait in the lobby but no
+    /// longer a row in the `sessions` table.
+    #[instrument(level = "info", skip_all)]
+    pub async fn abandoned_lobby_waits(&self) -> Result<Vec<String>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["abandoned_lobby_waits"])
+            .start_timer();
+        let sql = "SELECT DISTINCT session_hash FROM lobby_waits WHERE outcome IS NULL AND \
+                   session_hash NOT IN (SELECT token_hash FROM sessions)";
+        let hashes = self
+            .connection()
+            .await?
+            .fetch_all(sql)
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        Ok(hashes)
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn lobby_waits(&self) -> Result<Vec<StoredLobbyWait>, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["lobby_waits"]).start_timer();
+        let sql = "SELECT provider, joined_at, polls, outcome, finished_at FROM lobby_waits";
+        self.connection()
+            .await?
+            .fetch_all(sql)
+            .await?
+            .iter()
+            .map(StoredLobbyWait::from_row)
+            .collect()
+    }
+
+    /// Stores a new admin key. Returns `false` if the name is taken.
+    #[instrument(level = "info", skip_all)]
+    pub async fn insert_admin_key(&self, key: &StoredAdminKey) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["insert_admin_key"])
+            .start_timer();
+        let sql = "INSERT INTO admin_keys (name, key_hash, role, created_at) VALUES ($1, $2, $3, \
+                   $4) ON CONFLICT (name) DO NOTHING";
+        let result = self
+            .connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(&key.name)
+                    .bind(&key.key_hash)
+                    .bind(&key.role)
+                    .bind(key.created_at),
+            )
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn admin_key_by_hash(
+        &self,
+        key_hash: &str,
+    ) -> Result<Option<StoredAdminKey>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["admin_key_by_hash"])
+            .start_timer();
+        let sql = "SELECT name, key_hash, role, created_at FROM admin_keys WHERE key_hash = $1";
+        Ok(self
+            .connection()
+            .await?
+            .fetch_optional(sqlx::query(sql).bind(key_hash))
+            .await?
+            .map(|row| StoredAdminKey::from_row(&row)))
+    }
+
+    #[instrument(level = "info", skip_all)]
+    pub async fn admin_keys(&self) -> Result<Vec<StoredAdminKey>, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["admin_keys"]).start_timer();
+        let sql = "SELECT name, key_hash, role, created_at FROM admin_keys ORDER BY name";
+        Ok(self
+            .connection()
+            .await?
+            .fetch_all(sql)
+            .await?
+            .iter()
+            .map(StoredAdminKey::from_row)
+            .collect())
+    }
+
+    /// Changes the role of an admin key. Returns `false` if there is no key of
+    /// that name.
+    #[instrument(level = "info", skip_all)]
+    pub async fn update_admin_key_role(
+        &self,
+        name: &str,
+        role: &str,
+    ) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["update_admin_key_role"])
+            .start_timer();
+        let sql = "UPDATE admin_keys SET role = $1 WHERE name = $2";
+        let result = self
+            .connection()
+            .await?
+            .execute(sqlx::query(sql).bind(role).bind(name))
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Returns `false` if there is no key of that name.
+    #[instrument(level = "info", skip_all)]
+    pub async fn delete_admin_key(&self, name: &str) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["delete_admin_key"])
+            .start_timer();
+        let sql = "DELETE FROM admin_keys WHERE name = $1";
+        let result = self
+            .connection()
+            .await?
+            .execute(sqlx::query(sql).bind(name))
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Records `session` as the active contributor of contribution `index`
+    /// until `expires_at`, unless another session holds the slot for `index`
+    /// or a later contribution was handed out already. A slot held for an
+    /// earlier contribution is taken over, as that can no longer be
+    /// submitted. Also succeeds, and moves the expiry, if `session` already
+    /// holds the slot. The row is only changed by such compare-and-swap
+    /// updates, so that of all sequencers sharing the database, and across
+    /// restarts, at most one contributor holds the slot. Returns whether
+    /// `session` holds it.
+    #[instrument(level = "info", skip_all)]
+    pub async fn claim_slot(
+        &self,
+        session: &str,
+        index: usize,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["claim_slot"]).start_timer();
+        let index = i64::try_from(index).unwrap_or(i64::MAX);
+        let sql = "UPDATE ceremony_state SET slot_holder = $1, slot_expires_at = $2, \
+                   contribution_index = $3 WHERE id = 1 AND (contribution_index < $3 OR \
+                   (contribution_index = $3 AND (slot_holder IS NULL OR slot_holder = $1 OR \
+                   slot_expires_at <= $4)))";
+        let result = self
+            .connection()
+            .await?
+            .execute(
+                sqlx::query(sql)
+                    .bind(session)
+                    .bind(expires_at)
+                    .bind(index)
+                    .bind(Utc::now()),
+            )
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Moves the expiry of the slot to `expires_at`, provided `session`
+    /// holds it. Returns whether it does.
+    #[instrument(level = "info", skip_all)]
+    pub async fn extend_slot(
+        &self,
+        session: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, StorageError> {
+        let _timer = DB_LATENCY.with_label_values(&["extend_slot"]).start_timer();
+        let sql =
+            "UPDATE ceremony_state SET slot_expires_at = $1 WHERE id = 1 AND slot_holder = $2";
+        let result = self
+            .connection()
+            .await?
+            .execute(sqlx::query(sql).bind(expires_at).bind(session))
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Frees the slot, provided `session` holds it. The contribution index
+    /// is kept.
+    #[instrument(level = "info", skip_all)]
+    pub async fn release_slot(&self, session: &str) -> Result<(), StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["release_slot"])
+            .start_timer();
+        let sql = "UPDATE ceremony_state SET slot_holder = NULL, slot_expires_at = NULL WHERE id \
+                   = 1 AND slot_holder = $1";
+        self.with_retries(|| async move {
+            self.connection()
+                .await?
+                .execute(sqlx::query(sql).bind(session))
+                .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The key reservation tokens are signed with, kept in the
+    /// `ceremony_state` table so that all sequencers sharing the database,
+    /// and the sequencer after a restart, accept the same tokens. Stores
+    /// `generated` if there is none yet, only the first of concurrent callers
+    /// succeeds.
+    #[instrument(level = "info", skip_all)]
+    pub async fn reservation_key(&self, generated: &[u8]) -> Result<Vec<u8>, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["reservation_key"])
+            .start_timer();
+        let mut connection = self.connection().await?;
+        let sql = "UPDATE ceremony_state SET reservation_key = $1 WHERE id = 1 AND \
+                   reservation_key IS NULL";
+        connection
+            .execute(sqlx::query(sql).bind(hex::encode(generated)))
+            .await?;
+        let sql = "SELECT reservation_key FROM ceremony_state WHERE id = 1";
+        let row = connection.fetch_one(sqlx::query(sql)).await?;
+        let key: String = column(&row, "reservation_key")?;
+        hex::decode(key)
+            .map_err(|error| StorageError::CorruptRow(format!("column reservation_key: {error}")))
+    }
+
+    /// The row of the `ceremony_state` table.
+    #[instrument(level = "info", skip_all)]
+    pub async fn ceremony_state(&self) -> Result<StoredCeremonyState, StorageError> {
+        let _timer = DB_LATENCY
+            .with_label_values(&["ceremony_state"])
+            .start_timer();
+        let sql = "SELECT slot_holder, slot_expires_at, contribution_index FROM ceremony_state \
+                   WHERE id = 1";
+        let row = self.connection().await?.fetch_one(sqlx::query(sql)).await?;
+        Ok(StoredCeremonyState {
+            slot_holder:        column(&row, "slot_holder")?,
+            slot_expires_at:    column(&row, "slot_expires_at")?,
+            contribution_index: int_column(&row, "contribution_index")?,
+        })
+    }
+}
+
+/// A row of the `contributors` table, read by column name.
+///
+/// The queries are checked against the schema by the tests rather than at
+/// compile time: the `query!` macros need a concrete driver, while the
+/// storage runs on `Any` to serve both backends.
+#[derive(Clone, Debug, PartialEq, Eq, FromRow)]
+struct ContributorRow {
+    uid:            String,
+    started_at:     DateTime<Utc>,
+    finished_at:    Option<DateTime<Utc>>,
+    expired_at:     Option<DateTime<Utc>>,
+    country:        Option<String>,
+    client_version: Option<String>,
+}
+
+/// The latest attempt of a uid in the `contributors` table.
+const LATEST_CONTRIBUTOR: &str = "SELECT uid, started_at, finished_at, expired_at, \
+                                  contribution_index FROM contributors WHERE uid = $1 ORDER BY id \
+                                  DESC LIMIT 1";
+
+/// An attempt of a contributor, as kept in the `contributors` table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contributor {
+    pub uid:         String,
+    /// When the slot was last handed out.
+    pub started_at:  DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub expired_at:  Option<DateTime<Utc>>,
+    /// The contribution index reserved for the attempt, see
+    /// [`PersistentStorage::reserve_contribution_index`].
+    pub index:       Option<usize>,
+}
+
+impl<'r> FromRow<'r, AnyRow> for Contributor {
+    fn from_row(row: &'r AnyRow) -> Result<Self, sqlx::Error> {
+        let index = row
+            .try_get::<Option<i64>, _>("contribution_index")?
+            .map(usize::try_from)
+            .transpose()
+            .map_err(|error| sqlx::Error::ColumnDecode {
+                index:  "contribution_index".to_string(),
+                source: Box::new(error),
+            })?;
+        Ok(Self {
+            uid: row.try_get("uid")?,
+            started_at: row.try_get("started_at")?,
+            finished_at: row.try_get("finished_at")?,
+            expired_at: row.try_get("expired_at")?,
+            index,
+        })
+    }
+}
+
+/// Reads the column `name` of `row`, reporting a missing or undecodable
+/// column as [`StorageError::CorruptRow`].
+fn column<'r, T>(row: &'r AnyRow, name: &str) -> Result<T, StorageError>
+where
+    T: Decode<'r, Any> + Type<Any>,
+{
+    row.try_get(name)
+        .map_err(|error| StorageError::CorruptRow(format!("column {name}: {error}")))
+}
+
+/// Reads the integer column `name` of `row`, reporting a value out of the
+/// range of `T` as [`StorageError::CorruptRow`].
+fn int_column<T: TryFrom<i64>>(row: &AnyRow, name: &str) -> Result<T, StorageError> {
+    T::try_from(column::<i64>(row, name)?)
+        .map_err(|_| StorageError::CorruptRow(format!("column {name} out of range")))
+}
+
+/// Reads the nullable integer column `name` of `row`, see [`int_column`].
+fn optional_int_column<T: TryFrom<i64>>(
+    row: &AnyRow,
+    name: &str,
+) -> Result<Option<T>, StorageError> {
+    column::<Option<i64>>(row, name)?
+        .map(|value| {
+            T::try_from(value)
+                .map_err(|_| StorageError::CorruptRow(format!("column {name} out of range")))
+        })
+        .transpose()
+}
+
+/// Adds the contribution of a `transcript_entries` row, read with the
+/// columns `position, participant_id, ecdsa_signature, witness,
+/// entropy_attestation`, to `transcript`. Rows have to be added in order.
+fn push_transcript_entry(
+    transcript: &mut BatchTranscript,
+    row: &AnyRow,
+) -> Result<(), StorageError> {
+    let position = row.get::<i64, _>(0);
+    let expected = transcript.participant_ids.len();
+    if usize::try_from(position).ok() != Some(expected) {
+        return Err(StorageError::CorruptTranscript(format!(
+            "expected entry {expected} but found {position}"
+        )));
+    }
+    let witness: Vec<WitnessEntry> = serde_json::from_str(&row.get::<String, _>(3))?;
+    if witness.len() != transcript.transcripts.len() {
+        return Err(StorageError::CorruptTranscript(format!(
+            "entry {position} has {} witnesses, expected {}",
+            witness.len(),
+            transcript.transcripts.len()
+        )));
+    }
+    for (transcript, witness) in transcript.transcripts.iter_mut().zip(witness) {
+        transcript.witness.products.push(witness.running_product);
+        transcript.witness.pubkeys.push(witness.pot_pubkey);
+        transcript.witness.signatures.push(witness.bls_signature);
+    }
+    transcript.push_entropy_attestation(row.get::<Option<String>, _>(4));
+    transcript
+        .participant_ids
+        .push(row.get::<String, _>(1).parse()?);
+    transcript
+        .participant_ecdsa_signatures
+        .push(serde_json::from_str::<EcdsaSignature>(
+            &row.get::<String, _>(2),
+        )?);
+    Ok(())
+}
+
+/// The times of a finished attempt in the `contributors` table.
+#[derive(Clone, Debug, PartialEq, Eq, FromRow)]
+struct FinishedAttempt {
+    started_at:  DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+}
+
+/// Middle part of the uids of redacted contributors.
+const REDACTED: &str = "redacted";
+
+/// The uid stored instead of `uid` once its contributor asked to be
+/// forgotten. It keeps the provider prefix, so that quotas and statistics
+/// still count the contribution, and an HMAC-SHA256 of `uid` under `key`, so
+/// that the account can not contribute again. Uids are ids and addresses
+/// that can be enumerated, only the key keeps the hash from being reversed.
+#[must_use]
+pub fn pseudonym(key: &[u8], uid: &str) -> String {
+    let provider = uid.split('|').next().unwrap_or_default();
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("any key size is valid");
+    mac.update(uid.as_bytes());
+    let hash = hex::encode(mac.finalize().into_bytes());
+    format!("{provider}|{REDACTED}|{hash}")
+}
+
+/// Aggregates over the `contributors` table.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContributionStatistics {
+    /// Number of times a participant got the contribution slot.
+    pub attempts: usize,
+    /// Number of verified contributions.
+    pub contributions: usize,
+    /// Number of attempts that expired or were aborted without contributing.
+    pub expired: usize,
+    pub median_contribution_duration: Option<Duration>,
+    /// Finished contributions per hour, oldest hour first. Hours without
+    /// contributions are left out.
+    pub contributions_per_hour: Vec<(DateTime<Utc>, usize)>,
+    /// Finished contributions by identity provider.
+    pub contributions_by_provider: BTreeMap<String, usize>,
+    /// Finished contributions by country code of the contributor. Those
+    /// without a known country are left out.
+    pub contributions_by_country: BTreeMap<String, usize>,
+    /// Finished contributions by `<name>/<version>` of the client. Those
+    /// without an `X-Client-Version` header are left out.
+    pub contributions_by_client: BTreeMap<String, usize>,
+}
+
+/// A signed receipt as handed out to the contributor.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct StoredReceipt {
+    pub receipt:   String,
+    pub signature: String,
+}
+
+/// Every receipt with the attempt of its contributor that produced it: the
+/// latest finished one that started before the receipt was issued.
+const CONTRIBUTOR_SELECT: &str = "SELECT r.position, r.uid, r.receipt, r.signature, c.started_at, \
+                                  c.finished_at, c.ens_name FROM receipts r LEFT JOIN \
+                                  contributors c ON c.id = (SELECT id FROM contributors WHERE uid \
+                                  = r.uid AND finished_at IS NOT NULL AND started_at <= \
+                                  r.created_at ORDER BY started_at DESC LIMIT 1)";
+
+/// Order of [`PersistentStorage::list_contributors`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContributorOrder {
+    /// By position in the transcript.
+    #[default]
+    Index,
+    /// By the time from getting the slot to the verified contribution,
+    /// shortest first.
+    Fastest,
+}
+
+/// A contribution with a stored receipt, with the attempt that produced it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredContributor {
+    /// Position in the transcript, as in the receipt's `contribution_index`.
+    pub position:    usize,
+    pub uid:         String,
+    pub receipt:     StoredReceipt,
+    /// `None` if the attempt is no longer recorded.
+    pub started_at:  Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// ENS name of the attempt, if it was looked up.
+    pub ens_name:    Option<String>,
+}
+
+impl StoredContributor {
+    fn from_row(row: &AnyRow) -> Result<Self, StorageError> {
+        let position = usize::try_from(column::<i64>(row, "position")?).map_err(|_| {
+            StorageError::CorruptTranscript("negative receipt position".to_string())
+        })?;
+        Ok(Self {
+            position,
+            uid: column(row, "uid")?,
+            receipt: StoredReceipt {
+                receipt:   column(row, "receipt")?,
+                signature: column(row, "signature")?,
+            },
+            started_at: column(row, "started_at")?,
+            finished_at: column(row, "finished_at")?,
+            ens_name: column(row, "ens_name")?,
+        })
+    }
+
+    /// Time from getting the slot to the verified contribution.
+    #[must_use]
+    pub fn duration(&self) -> Option<Duration> {
+        let (started_at, finished_at) = self.started_at.zip(self.finished_at)?;
+        (finished_at - started_at).to_std().ok()
+    }
+}
+
+/// A submitted contribution as kept in the `verifications` table, so that
+/// resubmitting it returns the original outcome.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredVerification {
+    /// Hex encoded SHA-256 hash of the JSON encoded contribution.
+    pub payload_hash:    String,
+    pub uid:             String,
+    pub verification_id: String,
+    /// JSON encoded
+    /// [`VerificationStatus`](crate::verification::VerificationStatus),
+    /// `None` while the contribution is verified.
+    pub status:          Option<String>,
+}
+
+impl StoredVerification {
+    fn from_row(row: &AnyRow) -> Self {
+        Self {
+            payload_hash:    row.get(0),
+            uid:             row.get(1),
+            verification_id: row.get(2),
+            status:          row.get(3),
+        }
+    }
+}
+
+/// A row of `lobby_notifications`, unless its channel or status is unknown.
+fn stored_notification(row: &AnyRow) -> Result<Option<StoredNotification>, StorageError> {
+    let target =
+        NotificationTarget::from_parts(&column::<String>(row, "channel")?, column(row, "target")?);
+    let status = NotificationStatus::from_str(&column::<String>(row, "status")?).ok();
+    let (target, status) = match target.zip(status) {
+        Some(known) => known,
+        None => return Ok(None),
+    };
+    Ok(Some(StoredNotification {
+        token_hash: column(row, "token_hash")?,
+        uid: column(row, "uid")?,
+        target,
+        status,
+        attempts: int_column(row, "attempts")?,
+        last_error: column(row, "last_error")?,
+        sent_at: column(row, "sent_at")?,
+    }))
+}
+
+/// A participant in the `waiting_room` table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WaitingParticipant {
+    pub uid:   String,
+    /// Set if the participant asked to be told when a slot frees up.
+    pub email: Option<String>,
+}
+
+/// A pending job of the `jobs` table, see [`crate::scheduler`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredJob {
+    pub kind:     String,
+    pub key:      String,
+    pub payload:  String,
+    pub run_at:   DateTime<Utc>,
+    /// Failed attempts so far.
+    pub attempts: usize,
+}
+
+/// The active contributor slot as kept in the `ceremony_state` table, see
+/// [`PersistentStorage::claim_slot`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredCeremonyState {
+    /// [Hash of the session](crate::SessionId::hash) holding the slot.
+    pub slot_holder:        Option<String>,
+    pub slot_expires_at:    Option<DateTime<Utc>>,
+    /// The contribution the slot was last handed out for.
+    pub contribution_index: usize,
+}
+
+/// A row of the `identities` table: an account of an identity provider,
+/// which keeps its identity when its handle changes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredIdentity {
+    pub id:                i64,
+    /// Prefix of the uids of the provider, like `git`.
+    pub provider:          String,
+    /// What the provider identifies the account by: the address of an
+    /// Ethereum account, the numeric id of a GitHub or Discord account, and
+    /// `<issuer>|<subject>` of an OIDC account.
+    pub provider_id:       String,
+    /// The latest username, address or subject of the account.
+    pub handle:            Option<String>,
+    pub created_at:        DateTime<Utc>,
+    pub eligibility_score: Option<u32>,
+}
+
+impl StoredIdentity {
+    fn from_row(row: &AnyRow) -> Result<Self, StorageError> {
+        Ok(Self {
+            id:                column(row, "id")?,
+            provider:          column(row, "provider")?,
+            provider_id:       column(row, "provider_id")?,
+            handle:            column(row, "handle")?,
+            created_at:        column(row, "created_at")?,
+            eligibility_score: optional_int_column(row, "eligibility_score")?,
+        })
+    }
+}
+
+/// How the identity behind a uid is found in the `identities` table.
+struct IdentityKey {
+    account: Account,
+    /// Only legacy uids name the handle.
+    handle:  Option<String>,
+}
+
+impl IdentityKey {
+    fn of(uid: &str) -> Option<Self> {
+        let account = Account::from_uid(uid)?;
+        let handle = if account.uid() == uid {
+            None
+        } else {
+            uid.parse::<Identity>()
+                .ok()
+                .map(|identity| identity.nickname())
+        };
+        Some(Self { account, handle })
+    }
+}
+
+/// A session as kept in the `sessions` table, so that it survives restarts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredSession {
+    /// See [`SessionId::hash`](crate::SessionId::hash). The session id itself
+    /// is a bearer token and is not stored.
+    pub token_hash:        String,
+    pub uid:               String,
+    /// The participant id of the identity the session signed in with. `None`
+    /// in sessions saved under legacy uids, which are participant ids.
+    pub identity:          Option<String>,
+    pub expires_at:        DateTime<Utc>,
+    /// `None` while the participant is not in the lobby.
+    pub lobby_entered_at:  Option<DateTime<Utc>>,
+    pub eligibility_score: Option<u32>,
+}
+
+/// How long the phases of handling a contribution took.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredContributionTimings {
+    pub uid:                String,
+    pub contribution_index: usize,
+    /// Reading the body, see [`crate::upload::UploadTimings`].
+    pub receive:            Duration,
+    pub deserialize:        Duration,
+    /// The point encoding and subgroup checks of the slowest sub-ceremony.
+    pub subgroup_check:     Duration,
+    /// The pairing checks of the slowest sub-ceremony, in parallel with the
+    /// subgroup checks.
+    pub pairing:            Duration,
+    /// Storing, checkpointing and writing the transcript.
+    pub transcript_write:   Duration,
+    /// Signing and storing the receipt until the outcome is reported.
+    pub respond:            Duration,
+    pub recorded_at:        DateTime<Utc>,
+}
+
+impl StoredContributionTimings {
+    fn from_row(row: &AnyRow) -> Result<Self, StorageError> {
+        let duration = |name: &str| int_column(row, name).map(Duration::from_micros);
+        Ok(Self {
+            uid:                column(row, "uid")?,
+            contribution_index: int_column(row, "contribution_index")?,
+            receive:            duration("receive_us")?,
+            deserialize:        duration("deserialize_us")?,
+            subgroup_check:     duration("subgroup_check_us")?,
+            pairing:            duration("pairing_us")?,
+            transcript_write:   duration("transcript_write_us")?,
+            respond:            duration("respond_us")?,
+            recorded_at:        column(row, "recorded_at")?,
+        })
+    }
+}
+
+/// A row of `contributors`: the contribution slot as held by a uid, possibly
+/// more than once if it was requeued.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct StoredAttempt {
+    /// When the slot was last handed out.
+    #[schema(value_type = String)]
+    pub started_at:       DateTime<Utc>,
+    #[schema(value_type = Option<String>)]
+    pub finished_at:      Option<DateTime<Utc>>,
+    #[schema(value_type = Option<String>)]
+    pub expired_at:       Option<DateTime<Utc>>,
+    /// Times the slot was handed out.
+    pub attempts:         u32,
+    pub rejection_reason: Option<RejectionReason>,
+}
+
+impl StoredAttempt {
+    fn from_row(row: &AnyRow) -> Result<Self, StorageError> {
+        Ok(Self {
+            started_at:       column(row, "started_at")?,
+            finished_at:      column(row, "finished_at")?,
+            expired_at:       column(row, "expired_at")?,
+            attempts:         int_column(row, "attempts")?,
+            rejection_reason: column::<Option<&str>>(row, "rejection_reason")?
+                .map(serde_json::from_str)
+                .transpose()?,
+        })
+    }
+}
+
+/// A wait of a session in the lobby, see [`crate::fairness`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredLobbyWait {
+    pub provider:    String,
+    pub joined_at:   DateTime<Utc>,
+    /// Calls to `/lobby/try_contribute` during the wait.
+    pub polls:       u64,
+    /// `None` while the session waits.
+    pub outcome:     Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl StoredLobbyWait {
+    fn from_row(row: &AnyRow) -> Result<Self, StorageError> {
+        Ok(Self {
+            provider:    column(row, "provider")?,
+            joined_at:   column(row, "joined_at")?,
+            polls:       int_column(row, "polls")?,
+            outcome:     column(row, "outcome")?,
+            finished_at: column(row, "finished_at")?,
+        })
+    }
+}
+
+/// A key of the `admin_keys` table, see [`crate::api::v1::admin::AdminRole`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredAdminKey {
+    pub name:       String,
+    /// Hex encoded SHA-256 of the key. The key itself is a bearer token and
+    /// is not stored.
+    pub key_hash:   String,
+    pub role:       String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl StoredAdminKey {
+    fn from_row(row: &AnyRow) -> Self {
+        Self {
+            name:       row.get(0),
+            key_hash:   row.get(1),
+            role:       row.get(2),
+            created_at: row.get(3),
+        }
+    }
+}
+
+/// Type of a column copied by [`PersistentStorage::read_rows`] and
+/// [`PersistentStorage::insert_rows`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnKind {
+    Text,
+    Integer,
+    Timestamp,
+}
+
+/// A value of a column of [`ColumnKind`], `None` for `NULL`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColumnValue {
+    Text(Option<String>),
+    Integer(Option<i64>),
+    Timestamp(Option<DateTime<Utc>>),
+}
+
+impl ColumnValue {
+    fn from_row(row: &AnyRow, index: usize, kind: ColumnKind) -> Self {
+        match kind {
+            ColumnKind::Text => Self::Text(row.get(index)),
+            ColumnKind::Integer => Self::Integer(row.get(index)),
+            ColumnKind::Timestamp => Self::Timestamp(row.get(index)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{test_transcript, valid_contribution},
+        Engine,
+    };
+    use kzg_ceremony_crypto::Identity;
+    use uuid::Uuid;
+
+    #[cfg(feature = "sqlite")]
+    fn contributed_transcript(contributions: u8) -> BatchTranscript {
+        let mut transcript = test_transcript();
+        for no in 1..=contributions {
+            let mut contribution = valid_contribution(&transcript, no);
+            // Stored from the first attestation on.
+            contribution.entropy_attestation = (no == 2).then(|| format!("drand round {no}"));
+            transcript
+                .verify_add::<Engine>(contribution, Identity::Github {
+                    id:       u64::from(no),
+                    username: format!("user_{no}"),
+                })
+                .unwrap();
+        }
+        transcript
+    }
+
+    async fn check_contributor_lifecycle(storage: &PersistentStorage) {
+        let uid = format!("git|{}|storage_test", Uuid::new_v4());
+        assert!(!storage.has_contributed(&uid).await.unwrap());
+        storage.insert_contributor(&uid, Some(1)).await.unwrap();
+        assert!(storage.has_contributed(&uid).await.unwrap());
+        storage.finish_contribution(&uid).await.unwrap();
+        assert!(storage
+            .average_contribution_duration(10)
+            .await
+            .unwrap()
+            .is_some());
+        storage.expire_contribution(&uid).await.unwrap();
+        assert!(storage.has_contributed(&uid).await.unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_contributor_lifecycle() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        assert_eq!(
+            storage.average_contribution_duration(10).await.unwrap(),
+            None
+        );
+        assert_eq!(storage.count_finished_contributions().await.unwrap(), 0);
+        check_contributor_lifecycle(&storage).await;
+        assert_eq!(storage.count_finished_contributions().await.unwrap(), 1);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_aggregates_statistics() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        assert_eq!(
+            storage.contribution_statistics().await.unwrap(),
+            ContributionStatistics::default()
+        );
+
+        for uid in [
+            "git|1|alice",
+            "eth|0x0000000000000000000000000000000000000001",
+        ] {
+            storage.insert_contributor(uid, None).await.unwrap();
+            storage.finish_contribution(uid).await.unwrap();
+        }
+        storage
+            .set_contributor_country("git|1|alice", "FR")
+            .await
+            .unwrap();
+        storage
+            .set_contributor_client_version("git|1|alice", "powers-of-tau-cli/1.2.0")
+            .await
+            .unwrap();
+        storage.insert_contributor("git|2|bob", None).await.unwrap();
+        storage.expire_contribution("git|2|bob").await.unwrap();
+        storage
+            .insert_contributor("git|3|carol", None)
+            .await
+            .unwrap();
+
+        let statistics = storage.contribution_statistics().await.unwrap();
+        assert_eq!(statistics.attempts, 4);
+        assert_eq!(statistics.contributions, 2);
+        assert_eq!(statistics.expired, 1);
+        assert!(statistics.median_contribution_duration.is_some());
+        assert_eq!(
+            statistics
+                .contributions_per_hour
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<usize>(),
+            2
+        );
+        assert_eq!(
+            statistics.contributions_by_provider,
+            BTreeMap::from([("Ethereum".to_string(), 1), ("Github".to_string(), 1)])
+        );
+        assert_eq!(
+            statistics.contributions_by_country,
+            BTreeMap::from([("FR".to_string(), 1)])
+        );
+        assert_eq!(
+            statistics.contributions_by_client,
+            BTreeMap::from([("powers-of-tau-cli/1.2.0".to_string(), 1)])
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_enforces_quotas() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let quota = Some(Quota {
+            uid_prefix:        "git|",
+            max_contributions: 1,
+        });
+        storage
+            .insert_contributor("eth|0x0000000000000000000000000000000000000001", None)
+            .await
+            .unwrap();
+        assert!(storage
+            .insert_contributor_within_quota("git|1|alice", None, quota)
+            .await
+            .unwrap());
+        assert!(!storage
+            .insert_contributor_within_quota("git|2|bob", None, quota)
+            .await
+            .unwrap());
+        assert_eq!(storage.count_contributions_of("git|").await.unwrap(), 1);
+
+        // Expired contributions free their place.
+        storage.expire_contribution("git|1|alice").await.unwrap();
+        assert!(storage
+            .insert_contributor_within_quota("git|2|bob", None, quota)
+            .await
+            .unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_reserves_contribution_indexes() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        storage
+            .insert_contributor("git|1|alice", None)
+            .await
+            .unwrap();
+        assert!(storage
+            .reserve_contribution_index("git|1|alice", 1)
+            .await
+            .unwrap());
+        assert_eq!(
+            storage.finish_contribution("git|1|alice").await.unwrap(),
+            Some(1)
+        );
+
+        // Finished indexes are not reserved again, those of expired attempts
+        // are.
+        storage.insert_contributor("git|2|bob", None).await.unwrap();
+        assert!(!storage
+            .reserve_contribution_index("git|2|bob", 1)
+            .await
+            .unwrap());
+        assert!(storage
+            .reserve_contribution_index("git|2|bob", 2)
+            .await
+            .unwrap());
+        storage.expire_contribution("git|2|bob").await.unwrap();
+        storage
+            .insert_contributor("git|3|carol", None)
+            .await
+            .unwrap();
+        assert!(storage
+            .reserve_contribution_index("git|3|carol", 2)
+            .await
+            .unwrap());
+        assert_eq!(
+            storage.finish_contribution("git|3|carol").await.unwrap(),
+            Some(2)
+        );
+
+        // The expired attempt can not finish with the index any more.
+        assert!(storage.finish_contribution("git|2|bob").await.is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_reads_contributors() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        assert_eq!(
+            storage.get_contributor_by_uid("git|1|alice").await.unwrap(),
+            None
+        );
+        for uid in ["git|1|alice", "git|2|bob", "git|3|carol"] {
+            storage.insert_contributor(uid, None).await.unwrap();
+        }
+        storage
+            .reserve_contribution_index("git|1|alice", 1)
+            .await
+            .unwrap();
+        storage.finish_contribution("git|1|alice").await.unwrap();
+        storage.expire_contribution("git|2|bob").await.unwrap();
+
+        let alice = storage
+            .get_contributor_by_uid("git|1|alice")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(alice.uid, "git|1|alice");
+        assert_eq!(alice.index, Some(1));
+        assert!(alice.finished_at.is_some());
+        assert_eq!(alice.expired_at, None);
+        let bob = storage
+            .get_contributor_by_uid("git|2|bob")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(bob.expired_at.is_some());
+        assert_eq!(bob.index, None);
+
+        let unfinished = storage.list_unfinished().await.unwrap();
+        assert_eq!(
+            unfinished
+                .iter()
+                .map(|contributor| contributor.uid.as_str())
+                .collect::<Vec<_>>(),
+            vec!["git|3|carol"]
+        );
+
+        // Corrupt rows are reported rather than skipped.
+        storage
+            .connection()
+            .await
+            .unwrap()
+            .execute("UPDATE contributors SET rejection_reason = 'garbage'")
+            .await
+            .unwrap();
+        assert!(matches!(
+            storage.contribution_attempts("git|3|carol").await,
+            Err(StorageError::SerializationError(_))
+        ));
+        storage
+            .connection()
+            .await
+            .unwrap()
+            .execute("UPDATE contributors SET rejection_reason = NULL, attempts = -1")
+            .await
+            .unwrap();
+        assert!(matches!(
+            storage.contribution_attempts("git|3|carol").await,
+            Err(StorageError::CorruptRow(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_records_rejection_reasons() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let uid = "git|1|alice";
+        storage.insert_contributor(uid, None).await.unwrap();
+        assert_eq!(storage.rejection_reason(uid).await.unwrap(), None);
+        let reason = RejectionReason::SubgroupCheckFailed {
+            group: crate::rejection::PointGroup::G1,
+            index: 3,
+        };
+        storage.reject_contribution(uid, &reason).await.unwrap();
+        assert_eq!(storage.rejection_reason(uid).await.unwrap(), Some(reason));
+        assert!(storage.has_contributed(uid).await.unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_lists_contribution_attempts() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let uid = "git|1|alice";
+        assert!(storage.contribution_attempts(uid).await.unwrap().is_empty());
+        storage.insert_contributor(uid, None).await.unwrap();
+        assert!(storage.requeue_contribution(uid, 2).await.unwrap());
+        assert!(storage.retry_contribution(uid).await.unwrap());
+        let reason = RejectionReason::SubgroupCheckFailed {
+            group: crate::rejection::PointGroup::G2,
+            index: 0,
+        };
+        storage.reject_contribution(uid, &reason).await.unwrap();
+
+        let attempts = storage.contribution_attempts(uid).await.unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].attempts, 2);
+        assert_eq!(attempts[0].finished_at, None);
+        assert_eq!(attempts[0].rejection_reason, Some(reason));
+        assert!(storage
+            .contribution_attempts("git|2|bob")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_requeues_contributors() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let uid = "git|1|alice";
+        assert!(!storage.retry_contribution(uid).await.unwrap());
+        storage.insert_contributor(uid, None).await.unwrap();
+
+        assert!(storage.requeue_contribution(uid, 2).await.unwrap());
+        assert!(!storage.has_contributed(uid).await.unwrap());
+        // Already expired.
+        assert!(!storage.requeue_contribution(uid, 2).await.unwrap());
+        assert!(storage.retry_contribution(uid).await.unwrap());
+        assert!(storage.has_contributed(uid).await.unwrap());
+        assert!(!storage.retry_contribution(uid).await.unwrap());
+
+        // The second attempt was the last one.
+        assert!(!storage.requeue_contribution(uid, 2).await.unwrap());
+        assert!(storage.has_contributed(uid).await.unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_retries_and_degrades() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let mut options = crate::test_util::test_options().storage;
+        options.database_retry_backoff = 1;
+        options.database_failure_threshold = 1;
+        options.database_recovery_interval = 50;
+        let storage = storage_client(&options).await.unwrap();
+
+        let attempts = AtomicU32::new(0);
+        let attempt = storage
+            .with_retries(|| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    if attempt == 0 {
+                        Err(StorageError::DatabaseError(sqlx::Error::PoolTimedOut))
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(attempt, 1);
+        let result: Result<(), _> = storage
+            .with_retries(|| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err(StorageError::Closed) }
+            })
+            .await;
+        assert!(matches!(result, Err(StorageError::Closed)));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        storage.breaker.record_failure();
+        assert!(matches!(
+            storage.check_healthy().await,
+            Err(StorageError::Degraded)
+        ));
+        assert!(matches!(
+            storage.has_contributed("git|1|alice").await,
+            Err(StorageError::Degraded)
+        ));
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        storage.check_healthy().await.unwrap();
+        assert!(!storage.has_contributed("git|1|alice").await.unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_caches_contributors() {
+        let mut options = crate::test_util::test_options().storage;
+        options.database_failure_threshold = 1;
+        let storage = storage_client(&options).await.unwrap();
+        assert!(!storage.has_contributed("git|1|alice").await.unwrap());
+        assert!(!storage.has_contributed("git|2|bob").await.unwrap());
+        storage
+            .insert_contributor("git|1|alice", None)
+            .await
+            .unwrap();
+        assert!(storage.has_contributed("git|1|alice").await.unwrap());
+
+        // A contributor recorded by another sequencer on the same database
+        // goes unnoticed until the cached answer expires.
+        storage
+            .connection()
+            .await
+            .unwrap()
+            .execute(
+                sqlx::query("INSERT INTO contributors (uid, started_at) VALUES ($1, $2)")
+                    .bind("git|2|bob")
+                    .bind(Utc::now()),
+            )
+            .await
+            .unwrap();
+
+        assert!(!storage.has_contributed("git|2|bob").await.unwrap());
+
+        // Cached answers do not need the database.
+        storage.breaker.record_failure();
+        assert!(storage.has_contributed("git|1|alice").await.unwrap());
+        assert!(matches!(
+            storage.has_contributed("git|3|carol").await,
+            Err(StorageError::Degraded)
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_bans_and_unbans() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        storage.ping().await.unwrap();
+        let uid = "git|1234|banned_user";
+        assert!(!storage.is_banned(uid).await.unwrap());
+        storage.ban_uid(uid).await.unwrap();
+        storage.ban_uid(uid).await.unwrap();
+        assert!(storage.is_banned(uid).await.unwrap());
+        storage.unban_uid(uid).await.unwrap();
+        assert!(!storage.is_banned(uid).await.unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_stores_priorities() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        storage.set_priority("git|1|a", 2).await.unwrap();
+        storage.set_priority("git|2|b", 1).await.unwrap();
+        storage.set_priority("git|1|a", 3).await.unwrap();
+        storage.set_priority("git|2|b", 0).await.unwrap();
+        assert_eq!(storage.load_priorities().await.unwrap(), vec![(
+            "git|1|a".to_string(),
+            3
+        )]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_counts_auth_funnel() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        storage
+            .count_auth_funnel("github", "started", "")
+            .await
+            .unwrap();
+        storage
+            .count_auth_funnel("github", "started", "")
+            .await
+            .unwrap();
+        storage
+            .count_auth_funnel("github", "rejected", "banned")
+            .await
+            .unwrap();
+        let mut rows = storage.auth_funnel().await.unwrap();
+        rows.sort();
+        assert_eq!(rows, vec![
+            (
+                "github".to_string(),
+                "rejected".to_string(),
+                "banned".to_string(),
+                1
+            ),
+            (
+                "github".to_string(),
+                "started".to_string(),
+                String::new(),
+                2
+            ),
+        ]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_stores_lottery_draws() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        assert_eq!(storage.lottery_draw(None).await.unwrap(), None);
+        let draw = |epoch, block_hash| {
+            Draw::new(
+                epoch,
+                Duration::from_secs(600),
+                1,
+                &block_hash,
+                [hex::encode([1; 32])],
+                1,
+            )
+        };
+        storage
+            .insert_lottery_draw(&draw(1, [1; 32]))
+            .await
+            .unwrap();
+        storage
+            .insert_lottery_draw(&draw(2, [2; 32]))
+            .await
+            .unwrap();
+        // The first draw of an epoch is kept.
+        storage
+            .insert_lottery_draw(&draw(1, [3; 32]))
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.lottery_draw(Some(1)).await.unwrap(),
+            Some(draw(1, [1; 32]))
+        );
+        assert_eq!(
+            storage.lottery_draw(None).await.unwrap(),
+            Some(draw(2, [2; 32]))
+        );
+        assert_eq!(storage.lottery_draw(Some(3)).await.unwrap(), None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_records_lobby_waits() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let joined_at = Utc::now() - chrono::Duration::seconds(30);
+        storage
+            .start_lobby_wait("a", "Github", joined_at)
+            .await
+            .unwrap();
+        storage.count_lobby_poll("a").await.unwrap();
+        storage.count_lobby_poll("b").await.unwrap();
+
+        let wait = storage
+            .finish_lobby_wait("a", "slot", Utc::now())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(wait.provider, "Github");
+        assert_eq!(wait.polls, 2);
+        assert_eq!(wait.outcome, None);
+        assert_eq!(
+            storage
+                .finish_lobby_wait("a", "timeout", Utc::now())
+                .await
+                .unwrap(),
+            None
+        );
+        // A later poll does not count towards the finished wait.
+        storage.count_lobby_poll("a").await.unwrap();
+
+        let waits = storage.lobby_waits().await.unwrap();
+        assert_eq!(waits.len(), 1);
+        assert_eq!(waits[0].polls, 2);
+        assert_eq!(waits[0].outcome.as_deref(), Some("slot"));
+        assert!(waits[0].finished_at.is_some());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_stores_contribution_timings() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let timings = |uid: &str, contribution_index| StoredContributionTimings {
+            uid: uid.to_string(),
+            contribution_index,
+            receive: Duration::from_millis(800),
+            deserialize: Duration::from_micros(1500),
+            subgroup_check: Duration::from_secs(2),
+            pairing: Duration::from_secs(3),
+            transcript_write: Duration::from_millis(40),
+            respond: Duration::from_millis(5),
+            recorded_at: Utc::now(),
+        };
+        for (uid, index) in [("git|1|alice", 1), ("git|2|bob", 2)] {
+            storage
+                .insert_contribution_timings(&timings(uid, index))
+                .await
+                .unwrap();
+        }
+
+        let stored = storage.contribution_timings(10).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].uid, "git|2|bob");
+        assert_eq!(stored[0].contribution_index, 2);
+        assert_eq!(stored[1].contribution_index, 1);
+        assert_eq!(stored[1].deserialize, Duration::from_micros(1500));
+        assert_eq!(stored[1].pairing, Duration::from_secs(3));
+        assert_eq!(storage.contribution_timings(1).await.unwrap().len(), 1);
+
+        let pseudonym = storage.redact_contributor("git|1|alice").await.unwrap();
+        let stored = storage.contribution_timings(10).await.unwrap();
+        assert_eq!(stored[1].uid, pseudonym);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_swaps_the_slot() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let later = Utc::now() + chrono::Duration::minutes(1);
+        assert_eq!(
+            storage.ceremony_state().await.unwrap(),
+            StoredCeremonyState {
+                slot_holder:        None,
+                slot_expires_at:    None,
+                contribution_index: 0,
+            }
+        );
+
+        assert!(storage.claim_slot("a", 1, later).await.unwrap());
+        assert!(!storage.claim_slot("b", 1, later).await.unwrap());
+        assert!(storage.claim_slot("a", 1, later).await.unwrap());
+        assert!(!storage.extend_slot("b", later).await.unwrap());
+        assert!(storage.extend_slot("a", later).await.unwrap());
+        // Only the holder can free the slot.
+        storage.release_slot("b").await.unwrap();
+        assert!(!storage.claim_slot("b", 1, later).await.unwrap());
+        storage.release_slot("a").await.unwrap();
+        assert!(storage.claim_slot("b", 1, later).await.unwrap());
+
+        // A slot held for an earlier contribution is taken over, but not the
+        // other way around.
+        assert!(storage.claim_slot("c", 2, later).await.unwrap());
+        assert!(!storage.claim_slot("b", 1, later).await.unwrap());
+        let state = storage.ceremony_state().await.unwrap();
+        assert_eq!(state.slot_holder.as_deref(), Some("c"));
+        assert_eq!(state.contribution_index, 2);
+
+        // An expired slot is free again.
+        let earlier = Utc::now() - chrono::Duration::seconds(1);
+        assert!(storage.claim_slot("c", 2, earlier).await.unwrap());
+        assert!(storage.claim_slot("d", 2, later).await.unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_tracks_identities() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        storage
+            .insert_contributor("git|1|alice", Some(3))
+            .await
+            .unwrap();
+        // Renamed on GitHub.
+        storage
+            .insert_contributor("git|1|alice2", None)
+            .await
+            .unwrap();
+        storage
+            .insert_contributor("eth|0x0000000000000000000000000000000000000001", None)
+            .await
+            .unwrap();
+
+        let identity = storage.identity_of("git|1|alice").await.unwrap().unwrap();
+        assert_eq!(identity.provider, "git");
+        assert_eq!(identity.provider_id, "1");
+        assert_eq!(identity.handle.as_deref(), Some("alice2"));
+        assert_eq!(identity.eligibility_score, Some(3));
+        assert_eq!(
+            storage.identity_of("git|1|alice2").await.unwrap(),
+            Some(identity.clone())
+        );
+        assert_eq!(storage.uids_of_identity(identity.id).await.unwrap(), vec![
+            "git|1|alice".to_string(),
+            "git|1|alice2".to_string()
+        ]);
+        assert_eq!(storage.identity_of("git|2|bob").await.unwrap(), None);
+        assert_eq!(
+            storage
+                .upsert_identity(&storage.pseudonym("git|2|bob").unwrap(), None)
+                .await
+                .unwrap(),
+            None
+        );
+
+        storage.redact_contributor("git|1|alice").await.unwrap();
+        assert_eq!(storage.identity_of("git|1|alice2").await.unwrap(), None);
+        assert_eq!(
+            storage.uids_of_identity(identity.id).await.unwrap(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_ties_legacy_uids_to_accounts() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let github = |id, username: &str| Identity::Github {
+            id,
+            username: username.to_string(),
+        };
+        let uid = identity::uid(&github(1, "alice2"));
+        storage
+            .insert_contributor("git|1|alice", None)
+            .await
+            .unwrap();
+        assert!(storage.has_contributed(&uid).await.unwrap());
+        assert!(!storage
+            .has_contributed(&identity::uid(&github(2, "alice")))
+            .await
+            .unwrap());
+
+        // Renamed since.
+        storage
+            .register_identity(&github(1, "alice2"))
+            .await
+            .unwrap();
+        let identity = storage.identity_of(&uid).await.unwrap().unwrap();
+        assert_eq!(identity.handle.as_deref(), Some("alice2"));
+        assert_eq!(
+            storage.identity_of("git|1|alice").await.unwrap(),
+            Some(identity)
+        );
+        storage.insert_contributor(&uid, Some(2)).await.unwrap();
+        let identity = storage.identity_of(&uid).await.unwrap().unwrap();
+        assert_eq!(identity.handle.as_deref(), Some("alice2"));
+        assert_eq!(identity.eligibility_score, Some(2));
+
+        // The row of the account records another uid.
+        storage
+            .connection()
+            .await
+            .unwrap()
+            .execute("UPDATE identities SET uid = 'git|2#00000000'")
+            .await
+            .unwrap();
+        assert!(matches!(
+            storage.register_identity(&github(1, "alice")).await,
+            Err(StorageError::UidCollision(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_manages_admin_keys() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let key = StoredAdminKey {
+            name:       "on-call".to_string(),
+            key_hash:   "ab".repeat(32),
+            role:       "viewer".to_string(),
+            created_at: Utc::now(),
+        };
+        assert!(storage.insert_admin_key(&key).await.unwrap());
+        assert!(!storage.insert_admin_key(&key).await.unwrap());
+        assert_eq!(
+            storage
+                .admin_key_by_hash(&key.key_hash)
+                .await
+                .unwrap()
+                .unwrap()
+                .name,
+            key.name
+        );
+        assert_eq!(storage.admin_key_by_hash("cd").await.unwrap(), None);
+
+        assert!(storage
+            .update_admin_key_role(&key.name, "operator")
+            .await
+            .unwrap());
+        let keys = storage.admin_keys().await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].role, "operator");
+
+        assert!(storage.delete_admin_key(&key.name).await.unwrap());
+        assert!(!storage.delete_admin_key(&key.name).await.unwrap());
+        assert!(!storage
+            .update_admin_key_role(&key.name, "owner")
+            .await
+            .unwrap());
+        assert!(storage.admin_keys().await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_stores_receipts() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let uid = "git|1234|test_user";
+        assert_eq!(storage.get_receipt(uid).await.unwrap(), None);
+
+        for position in [1, 3] {
+            let receipt = StoredReceipt {
+                receipt:   format!("receipt {position}"),
+                signature: format!("signature {position}"),
+            };
+            storage
+                .insert_receipt(uid, position, &receipt)
+                .await
+                .unwrap();
+        }
+        storage
+            .insert_receipt("git|5678|other_user", 2, &StoredReceipt {
+                receipt:   "other".to_string(),
+                signature: "other".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let receipt = storage.get_receipt(uid).await.unwrap().unwrap();
+        assert_eq!(receipt.receipt, "receipt 3");
+        assert_eq!(receipt.signature, "signature 3");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_reads_from_read_replica() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = |name: &str| format!("sqlite://{}", dir.path().join(name).display());
+        let mut options = crate::test_util::test_options().storage;
+        options.database_url = url("replica.db");
+        storage_client(&options)
+            .await
+            .unwrap()
+            .close()
+            .await
+            .unwrap();
+        options.database_url = url("primary.db");
+        options.read_database_url = Some(url("replica.db"));
+        options.contributor_cache_ttl = 0;
+        let storage = storage_client(&options).await.unwrap();
+
+        let uid = "git|1234|test_user";
+        storage.insert_contributor(uid, None).await.unwrap();
+        storage
+            .insert_receipt(uid, 1, &StoredReceipt {
+                receipt:   "receipt".to_string(),
+                signature: "signature".to_string(),
+            })
+            .await
+            .unwrap();
+        // Nothing replicates the writes to the replica.
+        assert!(!storage.has_contributed(uid).await.unwrap());
+        assert_eq!(storage.count_receipts().await.unwrap(), 0);
+        assert_eq!(
+            storage.get_receipt(uid).await.unwrap().unwrap().receipt,
+            "receipt"
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_lists_contributors() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let receipt = |position: usize| StoredReceipt {
+            receipt:   format!("receipt {position}"),
+            signature: format!("signature {position}"),
+        };
+        for (position, uid, delay) in [(1, "git|1|alice", 20), (2, "git|2|bob", 0)] {
+            storage.insert_contributor(uid, None).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            storage.finish_contribution(uid).await.unwrap();
+            storage
+                .insert_receipt(uid, position, &receipt(position))
+                .await
+                .unwrap();
+        }
+        storage
+            .set_contributor_ens_name("git|1|alice", "alice.eth")
+            .await
+            .unwrap();
+        // No attempt recorded for the last one.
+        storage
+            .insert_receipt("git|3|carol", 3, &receipt(3))
+            .await
+            .unwrap();
+        assert_eq!(storage.count_receipts().await.unwrap(), 3);
+
+        let positions = |contributors: Vec<StoredContributor>| {
+            contributors
+                .iter()
+                .map(|contributor| contributor.position)
+                .collect::<Vec<_>>()
+        };
+        let page = |order, offset, limit| storage.list_contributors(order, offset, limit);
+        assert_eq!(
+            positions(page(ContributorOrder::Index, 0, 10).await.unwrap()),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            positions(page(ContributorOrder::Index, 1, 1).await.unwrap()),
+            [2]
+        );
+        assert_eq!(
+            positions(page(ContributorOrder::Fastest, 0, 10).await.unwrap()),
+            [2, 1, 3]
+        );
+        assert_eq!(
+            positions(page(ContributorOrder::Fastest, 1, 10).await.unwrap()),
+            [1, 3]
+        );
+        assert!(page(ContributorOrder::Fastest, 3, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let alice = storage.get_contributor(1).await.unwrap().unwrap();
+        assert_eq!(alice.uid, "git|1|alice");
+        assert_eq!(alice.receipt, receipt(1));
+        assert!(alice.duration().unwrap() >= Duration::from_millis(20));
+        assert_eq!(alice.ens_name.as_deref(), Some("alice.eth"));
+        let carol = storage.get_contributor(3).await.unwrap().unwrap();
+        assert_eq!(carol.duration(), None);
+        assert_eq!(storage.get_contributor(4).await.unwrap(), None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_consumes_auth_nonces_once() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let expires_at = Utc::now() + chrono::Duration::minutes(10);
+        storage
+            .insert_auth_nonce("fresh", expires_at)
+            .await
+            .unwrap();
+        storage
+            .insert_auth_nonce("stale", Utc::now() - chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+
+        assert!(storage.consume_auth_nonce("fresh").await.unwrap());
+        assert!(!storage.consume_auth_nonce("fresh").await.unwrap());
+        assert!(!storage.consume_auth_nonce("stale").await.unwrap());
+        assert!(!storage.consume_auth_nonce("unknown").await.unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_keeps_the_waiting_room() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        assert_eq!(storage.join_waiting_room("git|1|alice").await.unwrap(), 1);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(storage.join_waiting_room("git|2|bob").await.unwrap(), 2);
+        // Joining again keeps the place.
+        assert_eq!(storage.join_waiting_room("git|1|alice").await.unwrap(), 1);
+        assert_eq!(
+            storage.waiting_room_position("git|3|carol").await.unwrap(),
+            None
+        );
+
+        assert!(storage
+            .set_waiting_room_email("git|2|bob", "bob@example.com")
+            .await
+            .unwrap());
+        assert!(!storage
+            .set_waiting_room_email("git|3|carol", "carol@example.com")
+            .await
+            .unwrap());
+
+        let announced = storage.announce_waiting(1).await.unwrap();
+        assert_eq!(announced, vec![WaitingParticipant {
+            uid:   "git|1|alice".to_string(),
+            email: None,
+        }]);
+        let announced = storage.announce_waiting(5).await.unwrap();
+        assert_eq!(announced, vec![WaitingParticipant {
+            uid:   "git|2|bob".to_string(),
+            email: Some("bob@example.com".to_string()),
+        }]);
+        assert!(storage.announce_waiting(5).await.unwrap().is_empty());
+
+        // Alice takes her slot, Bob misses his.
+        storage.leave_waiting_room("git|1|alice").await.unwrap();
+        let cutoff = Utc::now() - chrono::Duration::minutes(1);
+        assert_eq!(storage.expire_waiting_room(cutoff).await.unwrap(), 1);
+        let cutoff = Utc::now() + chrono::Duration::minutes(1);
+        assert_eq!(storage.expire_waiting_room(cutoff).await.unwrap(), 0);
+        assert_eq!(
+            storage.waiting_room_position("git|2|bob").await.unwrap(),
+            None
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_persists_sessions() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let session = |token_hash: &str, uid: &str, expires_in: i64| StoredSession {
+            token_hash:        token_hash.to_string(),
+            uid:               uid.to_string(),
+            identity:          None,
+            expires_at:        Utc::now() + chrono::Duration::seconds(expires_in),
+            lobby_entered_at:  None,
+            eligibility_score: Some(3),
+        };
+        storage
+            .save_session(&session("a", "git|1|alice", 60))
+            .await
+            .unwrap();
+        storage
+            .save_session(&session("b", "git|2|bob", 60))
+            .await
+            .unwrap();
+        storage
+            .save_session(&session("expired", "git|3|carol", -60))
+            .await
+            .unwrap();
+        let entered_at = Utc::now();
+        storage
+            .update_session_lobby("b", Some(entered_at))
+            .await
+            .unwrap();
+        // Signing in again keeps the place in the lobby.
+        storage
+            .save_session(&session("b", "git|2|bob", 120))
+            .await
+            .unwrap();
+
+        let mut sessions = storage.load_sessions().await.unwrap();
+        sessions.sort_by(|a, b| a.token_hash.cmp(&b.token_hash));
+        assert_eq!(
+            sessions
+                .iter()
+                .map(|session| session.token_hash.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+        assert_eq!(sessions[0].lobby_entered_at, None);
+        assert_eq!(
+            sessions[1].lobby_entered_at.map(|at| at.timestamp()),
+            Some(entered_at.timestamp())
+        );
+        assert_eq!(sessions[1].eligibility_score, Some(3));
+
+        // A new session replaces the old one of the same uid.
+        storage
+            .save_session(&session("c", "git|1|alice", 60))
+            .await
+            .unwrap();
+        storage.delete_session("b").await.unwrap();
+        let sessions = storage.load_sessions().await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].token_hash, "c");
+        storage.delete_sessions_of("git|1|alice").await.unwrap();
+        assert!(storage.load_sessions().await.unwrap().is_empty());
+
+        // Scores out of range are reported rather than dropped.
+        storage
+            .save_session(&session("d", "git|1|alice", 60))
+            .await
+            .unwrap();
+        storage
+            .connection()
+            .await
+            .unwrap()
+            .execute("UPDATE sessions SET eligibility_score = -1")
+            .await
+            .unwrap();
+        assert!(matches!(
+            storage.load_sessions().await,
+            Err(StorageError::CorruptRow(_))
+        ));
+    }
+
+    #[test]
+    fn pseudonyms_depend_on_the_key() {
+        let uid = "git|1234|test_user";
+        let pseudonym_a = pseudonym(b"key a", uid);
+        assert!(pseudonym_a.starts_with("git|redacted|"));
+        assert_eq!(pseudonym_a, pseudonym(b"key a", uid));
+        assert_ne!(pseudonym_a, pseudonym(b"key b", uid));
+        assert_ne!(pseudonym_a, pseudonym(b"key a", "git|1235|test_user"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_redacts_only_with_a_pseudonym_key() {
+        let options = Options {
+            pseudonym_key: None,
+            ..crate::test_util::test_options().storage
+        };
+        let storage = storage_client(&options).await.unwrap();
+        let uid = "git|1234|test_user";
+        storage.insert_contributor(uid, None).await.unwrap();
+        assert!(matches!(
+            storage.redact_contributor(uid).await,
+            Err(StorageError::NoPseudonymKey)
+        ));
+        assert!(storage.has_contributed(uid).await.unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_redacts_contributors() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let uid = "git|1234|test_user";
+        storage.insert_contributor(uid, None).await.unwrap();
+        storage.set_contributor_country(uid, "FR").await.unwrap();
+        storage.finish_contribution(uid).await.unwrap();
+        storage
+            .insert_receipt(uid, 1, &StoredReceipt {
+                receipt:   "receipt".to_string(),
+                signature: "signature".to_string(),
+            })
+            .await
+            .unwrap();
+        storage.join_waiting_room(uid).await.unwrap();
+        storage
+            .save_session(&StoredSession {
+                token_hash:        "a".to_string(),
+                uid:               uid.to_string(),
+                identity:          None,
+                expires_at:        Utc::now() + chrono::Duration::seconds(60),
+                lobby_entered_at:  None,
+                eligibility_score: None,
+            })
+            .await
+            .unwrap();
+
+        let redacted = storage.redact_contributor(uid).await.unwrap();
+        assert_eq!(Some(redacted.clone()), storage.pseudonym(uid));
+        assert!(redacted.starts_with("git|redacted|"));
+        assert!(!redacted.contains("test_user"));
+        assert_eq!(storage.get_receipt(uid).await.unwrap(), None);
+        assert_eq!(storage.waiting_room_position(uid).await.unwrap(), None);
+        assert!(storage.load_sessions().await.unwrap().is_empty());
+        // The contribution still counts, without the country.
+        assert!(storage.has_contributed(uid).await.unwrap());
+        assert_eq!(storage.count_contributions_of("git|").await.unwrap(), 1);
+        let statistics = storage.contribution_statistics().await.unwrap();
+        assert_eq!(
+            statistics.contributions_by_provider,
+            BTreeMap::from([("Github".to_string(), 1)])
+        );
+        assert!(statistics.contributions_by_country.is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_appends_and_reads_transcript() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        assert_eq!(storage.read_transcript().await.unwrap(), None);
+
+        let mut transcript = test_transcript();
+        for no in 1..=3 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+            storage.append_transcript_entry(&transcript).await.unwrap();
+            assert_eq!(
+                storage.read_transcript().await.unwrap().as_ref(),
+                Some(&transcript)
+            );
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_appends_verified_contributions() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let mut transcript = test_transcript();
+        for no in 1..=2 {
+            let mut contribution = valid_contribution(&transcript, no);
+            contribution.entropy_attestation = (no == 2).then(|| format!("drand round {no}"));
+            let verified = transcript
+                .verify_observed::<Engine>(contribution, Identity::None, &|_, _| ())
+                .unwrap();
+            storage.append_verified_entry(&verified).await.unwrap();
+            transcript.apply(verified).unwrap();
+            assert_eq!(
+                storage.read_transcript().await.unwrap().as_ref(),
+                Some(&transcript)
+            );
+        }
+
+        // Each position is recorded only once.
+        let contribution = valid_contribution(&transcript, 3);
+        let verified = transcript
+            .verify_observed::<Engine>(contribution, Identity::None, &|_, _| ())
+            .unwrap();
+        storage.append_verified_entry(&verified).await.unwrap();
+        assert!(storage.append_verified_entry(&verified).await.is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_reads_transcript_tail() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let mut transcript = test_transcript();
+        let mut stale = transcript.clone();
+        assert_eq!(storage.read_transcript_tail(&mut stale).await.unwrap(), 0);
+
+        // Contributions another sequencer verified.
+        for no in 1..=2 {
+            let contribution = valid_contribution(&transcript, no);
+            let verified = transcript
+                .verify_observed::<Engine>(contribution, Identity::None, &|_, _| ())
+                .unwrap();
+            storage.append_verified_entry(&verified).await.unwrap();
+            transcript.apply(verified).unwrap();
+        }
+        assert_eq!(storage.read_transcript_tail(&mut stale).await.unwrap(), 2);
+        assert_eq!(stale, transcript);
+        assert_eq!(storage.read_transcript_tail(&mut stale).await.unwrap(), 0);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn sqlite_imports_transcript() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let transcript = contributed_transcript(3);
+        assert_eq!(storage.count_transcript_entries().await.unwrap(), 0);
+        storage.import_transcript(&transcript).await.unwrap();
+        assert_eq!(storage.count_transcript_entries().await.unwrap(), 3);
+        assert_eq!(storage.read_transcript().await.unwrap(), Some(transcript));
+    }
+
+    /// Only runs when a Postgres server is provided through
+    /// `TEST_POSTGRES_URL`.
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn postgres_contributor_lifecycle() {
+        let database_url = match std::env::var("TEST_POSTGRES_URL") {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let options = Options {
+            database_url,
+            ..crate::test_util::test_options().storage
+        };
+        let storage = storage_client(&options).await.unwrap();
+        check_contributor_lifecycle(&storage).await;
+    }
 }
@@ -0,0 +1,73 @@
+//! A local sequencer for integration testing clients.
+//!
+//! `--mode test` runs a small ceremony with an in-memory database and signs
+//! participants in with static tokens instead of OAuth, see
+//! [`StaticTokenProvider`](crate::oauth::StaticTokenProvider). Transcript and
+//! checkpoints are written to a fresh temporary directory, so nothing from a
+//! previous run or a real ceremony is picked up.
+
+use crate::{io::CeremonySizes, Options};
+use clap::ValueEnum;
+use eyre::{Result as EyreResult, WrapErr};
+use std::path::PathBuf;
+
+/// One ceremony of 2^8 G1 powers.
+pub const TEST_CEREMONY_SIZES: &str = "256,65";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    Production,
+    /// Not suitable for a real ceremony.
+    Test,
+    /// Read-only copy of another sequencer, see [`crate::mirror`].
+    Mirror,
+}
+
+impl Mode {
+    /// Overrides the options `--mode test` replaces.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the temporary directory can not be created.
+    pub fn apply(self, mut options: Options) -> EyreResult<Options> {
+        if self != Self::Test {
+            return Ok(options);
+        }
+        let dir = test_dir();
+        std::fs::create_dir_all(&dir)
+            .wrap_err_with(|| format!("failed to create {}", dir.display()))?;
+        options.ceremony_sizes =
+            CeremonySizes::parse_from_cmd(TEST_CEREMONY_SIZES).expect("test sizes are valid");
+        options.storage.database_url = "sqlite::memory:".to_string();
+        options.storage.read_database_url = None;
+        options.transcript_file = dir.join("transcript.json");
+        options.transcript_in_progress_file = dir.join("transcript.json.next");
+        options.checkpoint.checkpoint_dir = dir.join("checkpoints");
+        options.admin.final_setup_dir = dir.join("final_setup");
+        options.ceremonies.ceremonies_file = None;
+        Ok(options)
+    }
+}
+
+/// Directory of the files of `--mode test`, one per process.
+pub fn test_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("kzg-ceremony-test-{}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_options;
+
+    #[test]
+    fn test_mode_overrides_options() {
+        let options = test_options();
+        assert_eq!(Mode::Production.apply(options.clone()).unwrap(), options);
+        assert_eq!(Mode::Mirror.apply(options.clone()).unwrap(), options);
+
+        let test = Mode::Test.apply(options).unwrap();
+        assert_eq!(test.ceremony_sizes.sizes(), &[(256, 65)]);
+        assert_eq!(test.storage.database_url, "sqlite::memory:");
+        assert!(test.transcript_file.starts_with(std::env::temp_dir()));
+    }
+}
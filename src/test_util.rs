@@ -25,6 +25,11 @@ pub fn create_test_session_info(exp: u64) -> SessionInfo {
         token:                 test_jwt(exp),
         last_ping_time:        Instant::now(),
         is_first_ping_attempt: true,
+        lobby_entered_at:      None,
+        eligibility_score:     None,
+        failed_rules:          None,
+        checkin_challenge:     None,
+        min_poll_interval:     None,
     }
 }
 
@@ -44,6 +49,8 @@ pub fn test_options() -> Options {
         "INVALID",
         "--database-url",
         "sqlite://:memory:",
+        "--pseudonym-key",
+        "INVALID",
     ];
     Options::parse_from(args)
 }
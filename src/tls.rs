@@ -0,0 +1,129 @@
+//! Serving the API over TLS, for deployments without a reverse proxy.
+//!
+//! With `--tls-cert` and `--tls-key` set the server speaks HTTPS and
+//! negotiates HTTP/2 or HTTP/1.1 with ALPN. On SIGHUP the certificate and key
+//! are read again, so renewed certificates are picked up without a restart.
+
+use crate::reload_signal::{self, ReloadSignal};
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use clap::Parser;
+use eyre::{Result as EyreResult, WrapErr};
+use std::{
+    future::Future,
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+};
+use tracing::{error, info};
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// PEM encoded certificate chain to serve HTTPS with. Requires
+    /// `--tls-key` and an `https://` server url.
+    #[clap(long, env, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM encoded private key of the certificate.
+    #[clap(long, env, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+}
+
+impl Options {
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.tls_cert.is_some()
+    }
+
+    /// Reads the certificate and key, if TLS is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the files can not be read or do not hold a valid
+    /// certificate and key.
+    pub async fn config(&self) -> EyreResult<Option<RustlsConfig>> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => RustlsConfig::from_pem_file(cert, key)
+                .await
+                .map(Some)
+                .wrap_err("failed to load TLS certificate"),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Serves `app` over TLS on `listener` until `shutdown` resolves, then waits
+/// for open connections to finish.
+///
+/// # Errors
+///
+/// Returns an error if accepting connections fails.
+pub async fn serve(
+    listener: TcpListener,
+    config: RustlsConfig,
+    options: Options,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> EyreResult<()> {
+    let handle = Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown.await;
+            handle.graceful_shutdown(None);
+        }
+    });
+    let reload = tokio::spawn(reload_on_signal(config.clone(), options));
+
+    let result = axum_server::from_tcp_rustls(listener, config)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await;
+    reload.abort();
+    result.wrap_err("TLS server failed")
+}
+
+/// Reads the certificate and key into `config` again on every reload signal.
+/// A failed reload keeps the previous certificate.
+async fn reload_on_signal(config: RustlsConfig, options: Options) {
+    let mut signals = match ReloadSignal::new() {
+        Ok(signals) => signals,
+        Err(error) => {
+            error!(
+                ?error,
+                "Failed to listen for {}, TLS certificates will not be reloaded",
+                reload_signal::NAME
+            );
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        if let (Some(cert), Some(key)) = (&options.tls_cert, &options.tls_key) {
+            match config.reload_from_pem_file(cert, key).await {
+                Ok(()) => info!("Reloaded TLS certificate"),
+                Err(error) => error!(?error, "Failed to reload TLS certificate"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn requires_certificate_and_key() {
+        assert!(Options::try_parse_from(["tls", "--tls-cert", "cert.pem"]).is_err());
+        assert!(Options::try_parse_from(["tls", "--tls-key", "key.pem"]).is_err());
+
+        let options =
+            Options::try_parse_from(["tls", "--tls-cert", "cert.pem", "--tls-key", "key.pem"])
+                .unwrap();
+        assert!(options.enabled());
+        assert!(options.config().await.is_err());
+
+        let options = Options::try_parse_from(["tls"]).unwrap();
+        assert!(!options.enabled());
+        assert!(options.config().await.unwrap().is_none());
+    }
+}
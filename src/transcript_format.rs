@@ -0,0 +1,257 @@
+//! Export formats for the transcript served by `/info/current_state`.
+//!
+//! The transcript file itself is always served as is. The other formats are
+//! rendered from the in-memory transcript and only contain the powers, as
+//! needed by downstream tooling.
+
+use clap::ValueEnum;
+use kzg_ceremony_crypto::{lagrange_g1, BatchTranscript, CeremonyError, ErrorCode, Powers, G1, G2};
+use serde::{Deserialize, Serialize};
+use strum::IntoStaticStr;
+use thiserror::Error;
+
+/// Magic bytes starting a file in the [`Binary`] format.
+pub const BINARY_MAGIC: &[u8; 4] = b"KZGT";
+pub const BINARY_VERSION: u8 = 1;
+
+/// Length of the hash a PPoT file starts with.
+const PPOT_HASH_SIZE: usize = 64;
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum TranscriptFormatError {
+    #[error("the transcript has no sub-ceremony {0}")]
+    UnknownCeremony(usize),
+    #[error("failed to serialize transcript: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("failed to derive the lagrange form: {0}")]
+    Lagrange(#[from] CeremonyError),
+}
+
+impl ErrorCode for TranscriptFormatError {
+    fn to_error_code(&self) -> String {
+        format!("TranscriptFormatError::{}", <&str>::from(self))
+    }
+}
+
+/// A way to serialize the transcript for downstream tooling.
+pub trait TranscriptFormat: Send + Sync {
+    /// Value of the `Content-Type` header the export is served with.
+    fn content_type(&self) -> &'static str;
+
+    /// Serializes the transcript.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transcript can not be represented in this
+    /// format.
+    fn export(&self, transcript: &BatchTranscript) -> Result<Vec<u8>, TranscriptFormatError>;
+}
+
+/// Value of the `format` parameter of `/info/current_state` and of
+/// `export --format`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum TranscriptFormatKind {
+    /// The full transcript file, including witnesses.
+    #[default]
+    Json,
+    KzgJson,
+    Binary,
+    Ppot,
+}
+
+impl TranscriptFormatKind {
+    /// The exporter for this format, or `None` for the transcript file.
+    /// `ceremony` selects the sub-ceremony for formats that hold only one.
+    #[must_use]
+    pub fn exporter(self, ceremony: usize) -> Option<Box<dyn TranscriptFormat>> {
+        match self {
+            Self::Json => None,
+            Self::KzgJson => Some(Box::new(KzgJson)),
+            Self::Binary => Some(Box::new(Binary)),
+            Self::Ppot => Some(Box::new(Ppot { ceremony })),
+        }
+    }
+}
+
+/// The trusted setup JSON schema used by KZG libraries, one entry per
+/// sub-ceremony: `[{"setup_G1": [...], "setup_G2": [...]}, ...]` with hex
+/// encoded compressed points. Lagrange form points are not included.
+pub struct KzgJson;
+
+#[derive(Serialize)]
+struct KzgSetup<'a> {
+    #[serde(rename = "setup_G1")]
+    setup_g1: &'a [G1],
+    #[serde(rename = "setup_G2")]
+    setup_g2: &'a [G2],
+}
+
+impl TranscriptFormat for KzgJson {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn export(&self, transcript: &BatchTranscript) -> Result<Vec<u8>, TranscriptFormatError> {
+        let setups = transcript
+            .transcripts
+            .iter()
+            .map(|transcript| KzgSetup {
+                setup_g1: &transcript.powers.g1,
+                setup_g2: &transcript.powers.g2,
+            })
+            .collect::<Vec<_>>();
+        Ok(serde_json::to_vec(&setups)?)
+    }
+}
+
+/// Compact binary encoding of the powers: the magic `KZGT`, a version byte
+/// and the number of sub-ceremonies, followed for each sub-ceremony by the
+/// number of G1 and G2 points and the compressed points themselves. All
+/// integers are little endian `u32`.
+pub struct Binary;
+
+impl TranscriptFormat for Binary {
+    fn content_type(&self) -> &'static str {
+        "application/octet-stream"
+    }
+
+    fn export(&self, transcript: &BatchTranscript) -> Result<Vec<u8>, TranscriptFormatError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(BINARY_MAGIC);
+        out.push(BINARY_VERSION);
+        push_u32(&mut out, transcript.transcripts.len());
+        for transcript in &transcript.transcripts {
+            push_u32(&mut out, transcript.powers.g1.len());
+            push_u32(&mut out, transcript.powers.g2.len());
+            push_powers(&mut out, &transcript.powers);
+        }
+        Ok(out)
+    }
+}
+
+/// The layout of a compressed Perpetual Powers of Tau response for a single
+/// sub-ceremony: a 64 byte hash, here all zeros as there is no previous
+/// challenge, followed by the tau powers in G1 and G2. The ceremony has no
+/// alpha and beta powers, so those sections are left out.
+pub struct Ppot {
+    pub ceremony: usize,
+}
+
+impl TranscriptFormat for Ppot {
+    fn content_type(&self) -> &'static str {
+        "application/octet-stream"
+    }
+
+    fn export(&self, transcript: &BatchTranscript) -> Result<Vec<u8>, TranscriptFormatError> {
+        let transcript = transcript
+            .transcripts
+            .get(self.ceremony)
+            .ok_or(TranscriptFormatError::UnknownCeremony(self.ceremony))?;
+        let mut out = vec![0; PPOT_HASH_SIZE];
+        push_powers(&mut out, &transcript.powers);
+        Ok(out)
+    }
+}
+
+/// The final trusted setup of a single sub-ceremony, as written by
+/// `/admin/finalize`: `{"g1_monomial": [...], "g1_lagrange": [...],
+/// "g2_monomial": [...]}` with hex encoded compressed points. The Lagrange
+/// form is in bit-reversal permutation order, as used by EIP-4844. Deriving it
+/// takes a while for large ceremonies, so this format is not served by
+/// `/info/current_state`.
+pub struct TrustedSetup {
+    pub ceremony: usize,
+}
+
+#[derive(Serialize)]
+struct TrustedSetupJson<'a> {
+    g1_monomial: &'a [G1],
+    g1_lagrange: Vec<G1>,
+    g2_monomial: &'a [G2],
+}
+
+impl TranscriptFormat for TrustedSetup {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn export(&self, transcript: &BatchTranscript) -> Result<Vec<u8>, TranscriptFormatError> {
+        let transcript = transcript
+            .transcripts
+            .get(self.ceremony)
+            .ok_or(TranscriptFormatError::UnknownCeremony(self.ceremony))?;
+        let setup = TrustedSetupJson {
+            g1_monomial: &transcript.powers.g1,
+            g1_lagrange: lagrange_g1(&transcript.powers.g1)?,
+            g2_monomial: &transcript.powers.g2,
+        };
+        Ok(serde_json::to_vec(&setup)?)
+    }
+}
+
+fn push_u32(out: &mut Vec<u8>, value: usize) {
+    let value = u32::try_from(value).expect("transcript sizes fit in u32");
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_powers(out: &mut Vec<u8>, powers: &Powers) {
+    for point in &powers.g1 {
+        out.extend_from_slice(point.encoded());
+    }
+    for point in &powers.g2 {
+        out.extend_from_slice(point.encoded());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_transcript;
+
+    #[test]
+    fn exports_formats() {
+        let transcript = test_transcript();
+        let powers = &transcript.transcripts[0].powers;
+        let points_size = powers.g1.len() * 48 + powers.g2.len() * 96;
+
+        let json = KzgJson.export(&transcript).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), transcript.transcripts.len());
+        assert_eq!(
+            json[0]["setup_G1"].as_array().unwrap().len(),
+            powers.g1.len()
+        );
+        assert_eq!(
+            json[0]["setup_G2"][0],
+            serde_json::to_value(&powers.g2[0]).unwrap()
+        );
+
+        let binary = Binary.export(&transcript).unwrap();
+        assert_eq!(&binary[..4], BINARY_MAGIC);
+        assert_eq!(binary[4], BINARY_VERSION);
+        let num_ceremonies = u32::from_le_bytes(binary[5..9].try_into().unwrap());
+        assert_eq!(num_ceremonies as usize, transcript.transcripts.len());
+        let num_g1 = u32::from_le_bytes(binary[9..13].try_into().unwrap());
+        assert_eq!(num_g1 as usize, powers.g1.len());
+        assert_eq!(&binary[17..17 + 48], powers.g1[0].encoded());
+
+        let ppot = Ppot { ceremony: 0 }.export(&transcript).unwrap();
+        assert_eq!(ppot.len(), PPOT_HASH_SIZE + points_size);
+        assert!(matches!(
+            Ppot { ceremony: 99 }.export(&transcript),
+            Err(TranscriptFormatError::UnknownCeremony(99))
+        ));
+
+        let setup = TrustedSetup { ceremony: 0 }.export(&transcript).unwrap();
+        let setup: serde_json::Value = serde_json::from_slice(&setup).unwrap();
+        assert_eq!(
+            setup["g1_lagrange"].as_array().unwrap().len(),
+            powers.g1.len()
+        );
+        assert_eq!(
+            setup["g2_monomial"][0],
+            serde_json::to_value(&powers.g2[0]).unwrap()
+        );
+    }
+}
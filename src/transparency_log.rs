@@ -0,0 +1,372 @@
+//! Append-only log of the contributions, as a Merkle tree.
+//!
+//! Every verified contribution appends an entry with its position in the
+//! transcript, the SHA-256 hash of the contributor's uid and the
+//! `powers_hash` and `timestamp` of its receipt. The entries are the leaves
+//! of a Merkle tree as in [RFC 6962](https://www.rfc-editor.org/rfc/rfc6962#section-2.1):
+//! a leaf hashes to `SHA-256(0x00 || entry)`, a node to
+//! `SHA-256(0x01 || left || right)`.
+//!
+//! `GET /info/sth` answers with the signed tree head, the size and root hash
+//! of the tree. `GET /info/inclusion_proof?index=<i>&tree_size=<n>` answers
+//! with entry `i` and the audit path that proves it is part of the tree of
+//! the first `n` entries. Third parties keeping the tree heads they were
+//! served can so show that a contribution was dropped or reordered, as no
+//! tree head signed later would agree with it.
+
+use crate::{
+    attestation::sign_statement,
+    keys::{Keys, Signature, SignatureError},
+    receipt::Receipt,
+    storage::{PersistentStorage, StorageError},
+};
+use chrono::Utc;
+use kzg_ceremony_crypto::ErrorCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{sync::RwLock, task::JoinError};
+
+type Hash = [u8; 32];
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum TransparencyLogError {
+    #[error("leaf {leaf_index} is not part of a tree of size {tree_size}")]
+    InvalidLeaf {
+        leaf_index: usize,
+        tree_size:  usize,
+    },
+    #[error("tree size {tree_size} is larger than the log of {log_size} entries")]
+    InvalidTreeSize { tree_size: usize, log_size: usize },
+    #[error("storage error: {0}")]
+    StorageError(#[from] StorageError),
+    #[error("background task error: {0}")]
+    TaskError(#[from] JoinError),
+}
+
+impl ErrorCode for TransparencyLogError {
+    fn to_error_code(&self) -> String {
+        format!("TransparencyLogError::{}", <&str>::from(self))
+    }
+}
+
+/// A leaf of the log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Position of the contribution in the transcript.
+    pub contribution_index: usize,
+    /// Hex encoded SHA-256 hash of the contributor's uid.
+    pub uid_hash:           String,
+    /// The `powers_hash` of the receipt.
+    pub powers_hash:        String,
+    /// The `timestamp` of the receipt.
+    pub timestamp:          i64,
+}
+
+impl LogEntry {
+    #[must_use]
+    pub fn new(uid: &str, receipt: &Receipt) -> Self {
+        Self {
+            contribution_index: receipt.contribution_index,
+            uid_hash:           hex::encode(Sha256::digest(uid.as_bytes())),
+            powers_hash:        receipt.powers_hash.clone(),
+            timestamp:          receipt.timestamp,
+        }
+    }
+}
+
+/// Statement by the sequencer about the state of its log.
+#[derive(Debug, Serialize)]
+pub struct TreeHead {
+    /// Id of the ceremony, `None` for the default one.
+    pub ceremony:  Option<String>,
+    pub tree_size: usize,
+    /// Hex encoded root hash of the tree of the first `tree_size` entries.
+    pub root_hash: String,
+    /// Unix timestamp (in seconds) at which the tree head was signed.
+    pub timestamp: i64,
+}
+
+impl TreeHead {
+    pub async fn sign(&self, keys: &Keys) -> Result<(String, Signature), SignatureError> {
+        sign_statement(self, keys).await
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InclusionProof {
+    pub leaf_index: usize,
+    pub tree_size:  usize,
+    /// The JSON encoded [`LogEntry`] as it was hashed.
+    pub entry:      String,
+    pub leaf_hash:  String,
+    /// Hex encoded hashes of the siblings on the way from the leaf to the
+    /// root, lowest first.
+    pub audit_path: Vec<String>,
+}
+
+pub type SharedTransparencyLog = Arc<TransparencyLog>;
+
+pub struct TransparencyLog {
+    storage: PersistentStorage,
+    tree:    RwLock<Tree>,
+}
+
+#[derive(Default)]
+struct Tree {
+    entries: Vec<String>,
+    leaves:  Vec<Hash>,
+    /// Size and root hash of the perfect subtrees the tree is made of, the
+    /// largest first, so that the root is known without hashing every leaf.
+    peaks:   Vec<(usize, Hash)>,
+}
+
+impl Tree {
+    fn push(&mut self, entry: String) {
+        let leaf = leaf_hash(&entry);
+        self.entries.push(entry);
+        self.leaves.push(leaf);
+        self.peaks.push((1, leaf));
+        while let [.., (left_size, left), (right_size, right)] = self.peaks[..] {
+            if left_size != right_size {
+                break;
+            }
+            self.peaks.truncate(self.peaks.len() - 2);
+            self.peaks.push((left_size * 2, node_hash(&left, &right)));
+        }
+    }
+
+    fn root(&self) -> Hash {
+        let mut peaks = self.peaks.iter().rev();
+        match peaks.next() {
+            Some((_, last)) => peaks.fold(*last, |right, (_, left)| node_hash(left, &right)),
+            None => Sha256::digest(b"").into(),
+        }
+    }
+}
+
+impl TransparencyLog {
+    /// Loads the entries logged so far.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entries can not be read.
+    pub async fn load(storage: PersistentStorage) -> Result<Self, StorageError> {
+        let mut tree = Tree::default();
+        for entry in storage.log_entries().await? {
+            tree.push(entry);
+        }
+        Ok(Self {
+            storage,
+            tree: RwLock::new(tree),
+        })
+    }
+
+    /// Appends `entry` to the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry can not be stored. It is then not part
+    /// of the log either.
+    pub async fn append(&self, entry: &LogEntry) -> Result<(), TransparencyLogError> {
+        let entry = serde_json::to_string(entry).expect("log entries serialize");
+        let mut tree = self.tree.write().await;
+        self.storage
+            .append_log_entry(tree.entries.len(), &entry)
+            .await?;
+        tree.push(entry);
+        Ok(())
+    }
+
+    pub async fn tree_head(&self, ceremony: Option<String>) -> TreeHead {
+        let tree = self.tree.read().await;
+        TreeHead {
+            ceremony,
+            tree_size: tree.leaves.len(),
+            root_hash: hex::encode(tree.root()),
+            timestamp: Utc::now().timestamp(),
+        }
+    }
+
+    /// Proves that the entry at `leaf_index` is part of the tree of the first
+    /// `tree_size` entries, or of all of them if `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree is larger than the log, or the leaf not
+    /// part of the tree.
+    pub async fn inclusion_proof(
+        &self,
+        leaf_index: usize,
+        tree_size: Option<usize>,
+    ) -> Result<InclusionProof, TransparencyLogError> {
+        let tree = self.tree.read().await;
+        let log_size = tree.leaves.len();
+        let tree_size = tree_size.unwrap_or(log_size);
+        if tree_size > log_size {
+            return Err(TransparencyLogError::InvalidTreeSize {
+                tree_size,
+                log_size,
+            });
+        }
+        if leaf_index >= tree_size {
+            return Err(TransparencyLogError::InvalidLeaf {
+                leaf_index,
+                tree_size,
+            });
+        }
+        let entry = tree.entries[leaf_index].clone();
+        let leaves = tree.leaves[..tree_size].to_vec();
+        drop(tree);
+        // Hashes most of the tree.
+        let (leaf, audit_path) = tokio::task::spawn_blocking(move || {
+            (leaves[leaf_index], audit_path(leaf_index, &leaves))
+        })
+        .await?;
+        Ok(InclusionProof {
+            leaf_index,
+            tree_size,
+            entry,
+            leaf_hash: hex::encode(leaf),
+            audit_path: audit_path.iter().map(hex::encode).collect(),
+        })
+    }
+}
+
+fn leaf_hash(entry: &str) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0]);
+    hasher.update(entry.as_bytes());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([1]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Largest power of two smaller than `n`, for `n > 1`.
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - (n - 1).leading_zeros() - 1)
+}
+
+/// `MTH` of RFC 6962.
+fn tree_hash(leaves: &[Hash]) -> Hash {
+    match leaves {
+        [] => Sha256::digest(b"").into(),
+        [leaf] => *leaf,
+        _ => {
+            let (left, right) = leaves.split_at(split(leaves.len()));
+            node_hash(&tree_hash(left), &tree_hash(right))
+        }
+    }
+}
+
+/// `PATH` of RFC 6962.
+fn audit_path(index: usize, leaves: &[Hash]) -> Vec<Hash> {
+    if leaves.len() <= 1 {
+        return Vec::new();
+    }
+    let k = split(leaves.len());
+    let (left, right) = leaves.split_at(k);
+    let (mut path, sibling) = if index < k {
+        (audit_path(index, left), tree_hash(right))
+    } else {
+        (audit_path(index - k, right), tree_hash(left))
+    };
+    path.push(sibling);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::storage_client;
+
+    /// The root of a tree of `size` leaves with `leaf` at `index`, as
+    /// computed by a verifier from the audit path.
+    fn root_from_path(index: usize, size: usize, leaf: Hash, path: &[Hash]) -> Hash {
+        match path.split_last() {
+            None => leaf,
+            Some((sibling, rest)) => {
+                let k = split(size);
+                if index < k {
+                    node_hash(&root_from_path(index, k, leaf, rest), sibling)
+                } else {
+                    node_hash(sibling, &root_from_path(index - k, size - k, leaf, rest))
+                }
+            }
+        }
+    }
+
+    fn entry(contribution_index: usize) -> LogEntry {
+        LogEntry {
+            contribution_index,
+            uid_hash: hex::encode(Sha256::digest(b"git|1|alice")),
+            powers_hash: format!("0x{contribution_index:064x}"),
+            timestamp: 1_669_000_000,
+        }
+    }
+
+    #[test]
+    fn computes_roots_incrementally() {
+        let mut tree = Tree::default();
+        assert_eq!(tree.root(), tree_hash(&[]));
+        for index in 1..=13 {
+            tree.push(serde_json::to_string(&entry(index)).unwrap());
+            assert_eq!(tree.root(), tree_hash(&tree.leaves));
+        }
+        assert_eq!(
+            tree.peaks.iter().map(|(size, _)| *size).collect::<Vec<_>>(),
+            vec![8, 4, 1]
+        );
+    }
+
+    #[tokio::test]
+    async fn proves_inclusion() {
+        let options = crate::test_util::test_options().storage;
+        let storage = storage_client(&options).await.unwrap();
+        let log = TransparencyLog::load(storage.clone()).await.unwrap();
+        for index in 1..=7 {
+            log.append(&entry(index)).await.unwrap();
+        }
+        let head = log.tree_head(None).await;
+        assert_eq!(head.tree_size, 7);
+
+        // Also against an earlier tree head.
+        for tree_size in [7, 5, 1] {
+            let root = tree_hash(&log.tree.read().await.leaves[..tree_size]);
+            for leaf_index in 0..tree_size {
+                let proof = log
+                    .inclusion_proof(leaf_index, Some(tree_size))
+                    .await
+                    .unwrap();
+                let leaf = leaf_hash(&proof.entry);
+                assert_eq!(proof.leaf_hash, hex::encode(leaf));
+                let path = proof
+                    .audit_path
+                    .iter()
+                    .map(|hash| hex::decode(hash).unwrap().try_into().unwrap())
+                    .collect::<Vec<Hash>>();
+                assert_eq!(root_from_path(leaf_index, tree_size, leaf, &path), root);
+            }
+        }
+        assert!(matches!(
+            log.inclusion_proof(7, None).await,
+            Err(TransparencyLogError::InvalidLeaf { .. })
+        ));
+        assert!(matches!(
+            log.inclusion_proof(0, Some(8)).await,
+            Err(TransparencyLogError::InvalidTreeSize { .. })
+        ));
+
+        // Survives a restart.
+        let reloaded = TransparencyLog::load(storage).await.unwrap();
+        assert_eq!(reloaded.tree_head(None).await.root_hash, head.root_hash);
+    }
+}
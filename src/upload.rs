@@ -0,0 +1,695 @@
+//! Size limited, streaming JSON request bodies.
+//!
+//! Contributions are several megabytes of hex encoded points. Instead of
+//! buffering the whole body before parsing it, [`StreamingJson`] feeds the body
+//! chunks to `serde_json` on the blocking thread pool as they arrive, so only
+//! a few chunks and the decoded points are held in memory. Bodies larger than
+//! `--max-body-size` are rejected upfront if they declare their length, and as
+//! soon as the limit is crossed otherwise.
+//!
+//! [`ContributionBody`] also accepts contributions in the binary encoding of
+//! [`crate::contribution_format`]. Those are half the size and are buffered
+//! under the same limit before they are decoded.
+//!
+//! Bodies sent with `Content-Encoding: gzip` or `br` are decompressed while
+//! they are read. `--max-body-size` limits the compressed body and
+//! `--max-decompressed-size` what it decompresses to, so small bodies can not
+//! expand into a large allocation.
+//!
+//! Bodies are hashed while they are read if the request declares digests of
+//! them, see [`crate::content_digest`].
+
+use crate::{
+    compression::Encoding,
+    content_digest::{BodyHasher, ContentDigest, DeclaredDigests},
+    contribution_format::{self, DecodeError, BINARY_CONTENT_TYPE},
+    Options as AppOptions,
+};
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::{FromRequest, RequestParts},
+    BoxError,
+};
+use clap::Parser;
+use flate2::read::MultiGzDecoder;
+use http::header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
+use kzg_ceremony_crypto::{BatchContribution, ErrorCode};
+use serde::de::DeserializeOwned;
+use std::{
+    convert::Infallible,
+    io::{self, BufReader, Read},
+    time::{Duration, Instant},
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{sync::mpsc, task::JoinError};
+
+/// Number of body chunks buffered ahead of the parser.
+const CHUNK_BUFFER: usize = 4;
+
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Maximum size of a contribution upload in bytes.
+    #[clap(long, env, default_value = "10485760")]
+    pub max_body_size: usize,
+
+    /// Maximum size of a compressed contribution upload in bytes, after
+    /// decompressing it.
+    #[clap(long, env, default_value = "104857600")]
+    pub max_decompressed_size: usize,
+
+    /// Maximum size of a part of a contribution uploaded in parts, see
+    /// [`crate::chunked_upload`], in bytes.
+    #[clap(long, env, default_value = "1048576")]
+    pub max_upload_part_size: usize,
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum UploadError {
+    #[error("request body is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("request body decompresses to more than {0} bytes")]
+    DecompressedTooLarge(usize),
+    #[error("expected request with `Content-Encoding: gzip`, `br` or `identity`")]
+    UnsupportedContentEncoding,
+    #[error(
+        "expected request with `Content-Type: application/json` or `application/octet-stream`"
+    )]
+    UnsupportedContentType,
+    #[error("invalid json: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("invalid binary contribution: {0}")]
+    InvalidBinary(#[from] DecodeError),
+    #[error("failed to read request body: {0}")]
+    ReadFailed(String),
+    #[error("invalid digest field: {0}")]
+    InvalidDigest(String),
+    #[error("the body does not match its {0} digest")]
+    DigestMismatch(&'static str),
+    #[error("background task error: {0}")]
+    TaskError(#[from] JoinError),
+}
+
+impl ErrorCode for UploadError {
+    fn to_error_code(&self) -> String {
+        format!("UploadError::{}", <&str>::from(self))
+    }
+}
+
+/// Extractor deserializing a JSON body of at most `--max-body-size` bytes
+/// while it is received.
+pub struct StreamingJson<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for StreamingJson<T>
+where
+    T: DeserializeOwned + Send + 'static,
+    B: HttpBody<Data = Bytes> + Send + Unpin,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = UploadError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        Self::parse(req).await.map(|(parsed, ..)| parsed)
+    }
+}
+
+impl<T> StreamingJson<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    /// Parses the body, and returns when its last chunk was received and its
+    /// checked digest.
+    async fn parse<B>(
+        req: &mut RequestParts<B>,
+    ) -> Result<(Self, Instant, Option<ContentDigest>), UploadError>
+    where
+        B: HttpBody<Data = Bytes> + Send + Unpin,
+        B::Error: Into<BoxError>,
+    {
+        if !has_content_type(req, "application/json") {
+            return Err(UploadError::UnsupportedContentType);
+        }
+        let LimitedBody {
+            mut body,
+            max_size,
+            max_decompressed_size,
+            encoding,
+            digests,
+        } = take_body(req)?;
+
+        let mut hasher = digests.as_ref().map(DeclaredDigests::hasher);
+        let (sender, receiver) = mpsc::channel(CHUNK_BUFFER);
+        let parser = tokio::task::spawn_blocking(move || {
+            let mut chunks = ChunkReader {
+                receiver,
+                chunk: Bytes::new(),
+                hasher: hasher.as_mut(),
+            };
+            let mut input = Decompressed::new(&mut chunks, encoding, max_decompressed_size);
+            let parsed = serde_json::from_reader::<_, T>(BufReader::new(&mut input));
+            let exceeded = input.exceeded;
+            drop(input);
+            if chunks.hasher.is_some() {
+                // The digest is over all of the body, also what the parser
+                // left unread.
+                io::copy(&mut chunks, &mut io::sink()).ok();
+            }
+            drop(chunks);
+            let parsed = if exceeded {
+                Err(UploadError::DecompressedTooLarge(max_decompressed_size))
+            } else {
+                parsed.map_err(UploadError::from)
+            };
+            (parsed, hasher)
+        });
+
+        let mut received = 0_usize;
+        let mut read_error = None;
+        while let Some(chunk) = body.data().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    read_error = Some(UploadError::ReadFailed(error.into().to_string()));
+                    break;
+                }
+            };
+            received += chunk.len();
+            if received > max_size {
+                read_error = Some(UploadError::TooLarge(max_size));
+                break;
+            }
+            // Fails once the parser gave up, its error is reported below.
+            if sender.send(chunk).await.is_err() {
+                break;
+            }
+        }
+        // Ends the input of the parser.
+        drop(sender);
+        let received_at = Instant::now();
+        let parsed = parser.await;
+        if let Some(error) = read_error {
+            return Err(error);
+        }
+        let (parsed, hasher) = parsed?;
+        // A corrupted body fails to parse too, the digest tells why.
+        let content_digest = check_digests(digests.as_ref(), hasher)?;
+        Ok((Self(parsed?), received_at, content_digest))
+    }
+}
+
+/// Checks the declared digests of a body against its `hasher`.
+fn check_digests(
+    digests: Option<&DeclaredDigests>,
+    hasher: Option<BodyHasher>,
+) -> Result<Option<ContentDigest>, UploadError> {
+    match (digests, hasher) {
+        (Some(digests), Some(hasher)) => digests
+            .verify(hasher)
+            .map(Some)
+            .map_err(UploadError::DigestMismatch),
+        _ => Ok(None),
+    }
+}
+
+/// Extractor for a contribution in either JSON or, with `Content-Type:
+/// application/octet-stream`, the binary encoding.
+pub struct ContributionBody(pub BatchContribution);
+
+#[async_trait]
+impl<B> FromRequest<B> for ContributionBody
+where
+    B: HttpBody<Data = Bytes> + Send + Unpin,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = UploadError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let start = Instant::now();
+        if !has_content_type(req, BINARY_CONTENT_TYPE) {
+            let (StreamingJson(contribution), received_at, content_digest) =
+                StreamingJson::parse(req).await?;
+            req.extensions_mut().insert(Upload {
+                timings: UploadTimings {
+                    receive:     received_at - start,
+                    deserialize: received_at.elapsed(),
+                },
+                content_digest,
+            });
+            return Ok(Self(contribution));
+        }
+        let LimitedBody {
+            mut body,
+            max_size,
+            max_decompressed_size,
+            encoding,
+            digests,
+        } = take_body(req)?;
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|error| UploadError::ReadFailed(error.into().to_string()))?;
+            if bytes.len() + chunk.len() > max_size {
+                return Err(UploadError::TooLarge(max_size));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        let received_at = Instant::now();
+        let (contribution, content_digest) = tokio::task::spawn_blocking(move || {
+            let content_digest = check_digests(
+                digests.as_ref(),
+                digests.as_ref().map(|digests| {
+                    let mut hasher = digests.hasher();
+                    hasher.update(&bytes);
+                    hasher
+                }),
+            )?;
+            decode_binary(&bytes, encoding, max_decompressed_size)
+                .map(|contribution| (contribution, content_digest))
+        })
+        .await??;
+        req.extensions_mut().insert(Upload {
+            timings: UploadTimings {
+                receive:     received_at - start,
+                deserialize: received_at.elapsed(),
+            },
+            content_digest,
+        });
+        Ok(Self(contribution))
+    }
+}
+
+/// Decodes a binary contribution, decompressing it first if it has an
+/// `encoding`.
+fn decode_binary(
+    bytes: &[u8],
+    encoding: Option<Encoding>,
+    max_decompressed_size: usize,
+) -> Result<BatchContribution, UploadError> {
+    if encoding.is_none() {
+        return Ok(contribution_format::decode(bytes)?);
+    }
+    let mut input = Decompressed::new(bytes, encoding, max_decompressed_size);
+    let mut decompressed = Vec::new();
+    if let Err(error) = input.read_to_end(&mut decompressed) {
+        if input.exceeded {
+            return Err(UploadError::DecompressedTooLarge(max_decompressed_size));
+        }
+        return Err(UploadError::ReadFailed(error.to_string()));
+    }
+    Ok(contribution_format::decode(&decompressed)?)
+}
+
+/// How long reading and decoding the [`ContributionBody`] of the request
+/// took. JSON is parsed while it is received, so `deserialize` is only the
+/// part of parsing left after the last chunk. Zero if the request had no
+/// contribution body.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UploadTimings {
+    pub receive:     Duration,
+    pub deserialize: Duration,
+}
+
+/// What reading the [`ContributionBody`] of the request found out about it.
+/// Default if the request had no contribution body.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Upload {
+    pub timings:        UploadTimings,
+    /// The checked digest the body was sent with.
+    pub content_digest: Option<ContentDigest>,
+}
+
+#[async_trait]
+impl<B: Send> FromRequest<B> for Upload {
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        Ok(req.extensions().get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+fn has_content_type<B>(req: &RequestParts<B>, content_type: &str) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.starts_with(content_type))
+}
+
+/// A request body with the limits it is read under.
+struct LimitedBody<B> {
+    body:                  B,
+    max_size:              usize,
+    max_decompressed_size: usize,
+    encoding:              Option<Encoding>,
+    digests:               Option<DeclaredDigests>,
+}
+
+/// Takes the body of a request that does not declare a length above
+/// `--max-body-size`.
+fn take_body<B>(req: &mut RequestParts<B>) -> Result<LimitedBody<B>, UploadError> {
+    let options = req
+        .extensions()
+        .get::<AppOptions>()
+        .map(|options| &options.upload);
+    let max_size = options.map_or(usize::MAX, |upload| upload.max_body_size);
+    let max_decompressed_size = options.map_or(usize::MAX, |upload| upload.max_decompressed_size);
+    let encoding = match req.headers().get(CONTENT_ENCODING) {
+        Some(value) => match value.to_str().map(str::trim) {
+            Ok("" | "identity") => None,
+            Ok(name) => {
+                Some(Encoding::from_name(name).ok_or(UploadError::UnsupportedContentEncoding)?)
+            }
+            Err(_) => return Err(UploadError::UnsupportedContentEncoding),
+        },
+        None => None,
+    };
+    let declared_size = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared_size.map_or(false, |size| size > max_size) {
+        return Err(UploadError::TooLarge(max_size));
+    }
+    let digests =
+        DeclaredDigests::from_headers(req.headers()).map_err(UploadError::InvalidDigest)?;
+    let body = req
+        .take_body()
+        .ok_or_else(|| UploadError::ReadFailed("body already taken".to_string()))?;
+    Ok(LimitedBody {
+        body,
+        max_size,
+        max_decompressed_size,
+        encoding,
+        digests,
+    })
+}
+
+/// Reader decompressing a body, which fails once the body decompresses to
+/// more than the limit. Uncompressed bodies are passed through.
+struct Decompressed<'a> {
+    inner:     Box<dyn Read + Send + 'a>,
+    remaining: usize,
+    exceeded:  bool,
+}
+
+impl<'a> Decompressed<'a> {
+    fn new<R: Read + Send + 'a>(input: R, encoding: Option<Encoding>, limit: usize) -> Self {
+        let (inner, remaining): (Box<dyn Read + Send + 'a>, _) = match encoding {
+            None => (Box::new(input), usize::MAX),
+            Some(Encoding::Gzip) => (Box::new(MultiGzDecoder::new(input)), limit),
+            Some(Encoding::Brotli) => (
+                Box::new(brotli::Decompressor::new(input, BROTLI_BUFFER_SIZE)),
+                limit,
+            ),
+        };
+        Self {
+            inner,
+            remaining,
+            exceeded: false,
+        }
+    }
+}
+
+impl Read for Decompressed<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            // One more byte tells a body of exactly the limit from a larger
+            // one.
+            if self.inner.read(&mut [0])? == 0 {
+                return Ok(0);
+            }
+            self.exceeded = true;
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "decompressed body too large",
+            ));
+        }
+        let len = buf.len().min(self.remaining);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+/// Blocking reader over the chunks sent by the extractor, which feeds them
+/// to `hasher` as they arrive.
+struct ChunkReader<'a> {
+    receiver: mpsc::Receiver<Bytes>,
+    chunk:    Bytes,
+    hasher:   Option<&'a mut BodyHasher>,
+}
+
+impl Read for ChunkReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    if let Some(hasher) = &mut self.hasher {
+                        hasher.update(&chunk);
+                    }
+                    self.chunk = chunk;
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len());
+        buf[..len].copy_from_slice(&self.chunk.split_to(len));
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{content_digest::CONTENT_DIGEST, test_util::test_options, tests::test_transcript};
+    use axum::body::Body;
+    use http::{HeaderValue, Request};
+    use sha2::{Digest, Sha256};
+
+    fn request(
+        body: Body,
+        content_length: Option<usize>,
+        max_body_size: usize,
+    ) -> RequestParts<Body> {
+        request_with_type(body, content_length, max_body_size, "application/json")
+    }
+
+    fn request_with_type(
+        body: Body,
+        content_length: Option<usize>,
+        max_body_size: usize,
+        content_type: &str,
+    ) -> RequestParts<Body> {
+        let mut options = test_options();
+        options.upload.max_body_size = max_body_size;
+        let mut builder = Request::builder()
+            .method("POST")
+            .header(CONTENT_TYPE, content_type);
+        if let Some(length) = content_length {
+            builder = builder.header(CONTENT_LENGTH, length);
+        }
+        let mut request = builder.body(body).unwrap();
+        request.extensions_mut().insert(options);
+        RequestParts::new(request)
+    }
+
+    fn compressed_request(
+        encoding: Encoding,
+        content_type: &str,
+        data: &[u8],
+        max_decompressed_size: usize,
+    ) -> RequestParts<Body> {
+        let body = encoding.compress(data).unwrap();
+        let mut req = request_with_type(chunked(&body), None, body.len(), content_type);
+        req.headers_mut().insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        req.extensions_mut()
+            .get_mut::<AppOptions>()
+            .unwrap()
+            .upload
+            .max_decompressed_size = max_decompressed_size;
+        req
+    }
+
+    /// A body of several small chunks without a declared length.
+    fn chunked(json: &[u8]) -> Body {
+        let chunks = json
+            .chunks(100)
+            .map(Bytes::copy_from_slice)
+            .collect::<Vec<_>>();
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in chunks {
+                if sender.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+        });
+        body
+    }
+
+    #[tokio::test]
+    async fn parses_streamed_contribution() {
+        let contribution = test_transcript().contribution();
+        let json = serde_json::to_vec(&contribution).unwrap();
+
+        let mut req = request(chunked(&json), None, json.len());
+        let StreamingJson(parsed) = StreamingJson::<BatchContribution>::from_request(&mut req)
+            .await
+            .unwrap();
+        assert_eq!(parsed, contribution);
+
+        let mut req = request(chunked(&json[..json.len() - 1]), None, json.len());
+        assert!(matches!(
+            StreamingJson::<BatchContribution>::from_request(&mut req).await,
+            Err(UploadError::InvalidJson(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_bodies() {
+        let json = serde_json::to_vec(&test_transcript().contribution()).unwrap();
+        let limit = json.len() - 1;
+
+        let mut req = request(Body::from(json.clone()), Some(json.len()), limit);
+        assert!(matches!(
+            StreamingJson::<BatchContribution>::from_request(&mut req).await,
+            Err(UploadError::TooLarge(size)) if size == limit
+        ));
+
+        let mut req = request(chunked(&json), None, limit);
+        assert!(matches!(
+            StreamingJson::<BatchContribution>::from_request(&mut req).await,
+            Err(UploadError::TooLarge(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn parses_binary_contribution() {
+        let contribution = test_transcript().contribution();
+        let bytes = contribution_format::encode(&contribution);
+
+        let mut req = request_with_type(chunked(&bytes), None, bytes.len(), BINARY_CONTENT_TYPE);
+        let ContributionBody(parsed) = ContributionBody::from_request(&mut req).await.unwrap();
+        assert_eq!(parsed, contribution);
+
+        let json = serde_json::to_vec(&contribution).unwrap();
+        let mut req = request(chunked(&json), None, json.len());
+        let ContributionBody(parsed) = ContributionBody::from_request(&mut req).await.unwrap();
+        assert_eq!(parsed, contribution);
+        // Read by the handler with the contribution.
+        assert!(req.extensions().get::<Upload>().is_some());
+
+        let mut req =
+            request_with_type(chunked(&bytes), None, bytes.len() - 1, BINARY_CONTENT_TYPE);
+        assert!(matches!(
+            ContributionBody::from_request(&mut req).await,
+            Err(UploadError::TooLarge(_))
+        ));
+
+        let mut req =
+            request_with_type(chunked(&bytes[1..]), None, bytes.len(), BINARY_CONTENT_TYPE);
+        assert!(matches!(
+            ContributionBody::from_request(&mut req).await,
+            Err(UploadError::InvalidBinary(DecodeError::InvalidMagic))
+        ));
+
+        let mut req = request_with_type(Body::from(bytes), None, usize::MAX, "text/plain");
+        assert!(matches!(
+            ContributionBody::from_request(&mut req).await,
+            Err(UploadError::UnsupportedContentType)
+        ));
+    }
+
+    #[tokio::test]
+    async fn checks_content_digests() {
+        let contribution = test_transcript().contribution();
+        let digest = |body: &[u8]| format!("sha-256=:{}:", base64::encode(Sha256::digest(body)));
+        let with_digest = |mut req: RequestParts<Body>, digest: &str| {
+            req.headers_mut()
+                .insert(CONTENT_DIGEST, HeaderValue::from_str(digest).unwrap());
+            req
+        };
+
+        let json = serde_json::to_vec(&contribution).unwrap();
+        let mut req = with_digest(request(chunked(&json), None, json.len()), &digest(&json));
+        let ContributionBody(parsed) = ContributionBody::from_request(&mut req).await.unwrap();
+        assert_eq!(parsed, contribution);
+        assert_eq!(
+            req.extensions().get::<Upload>().unwrap().content_digest,
+            Some(ContentDigest(digest(&json)))
+        );
+
+        let mut corrupted = json.clone();
+        corrupted[json.len() / 2] ^= 1;
+        let mut req = with_digest(
+            request(chunked(&corrupted), None, json.len()),
+            &digest(&json),
+        );
+        assert!(matches!(
+            ContributionBody::from_request(&mut req).await,
+            Err(UploadError::DigestMismatch("sha-256"))
+        ));
+
+        // The digest is over the compressed body.
+        let compressed = Encoding::Gzip
+            .compress(&contribution_format::encode(&contribution))
+            .unwrap();
+        let mut req = request_with_type(
+            chunked(&compressed),
+            None,
+            compressed.len(),
+            BINARY_CONTENT_TYPE,
+        );
+        req.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        let mut req = with_digest(req, &digest(&compressed));
+        let ContributionBody(parsed) = ContributionBody::from_request(&mut req).await.unwrap();
+        assert_eq!(parsed, contribution);
+
+        let mut req = with_digest(request(chunked(&json), None, json.len()), "md5=:AAAA:");
+        assert!(matches!(
+            ContributionBody::from_request(&mut req).await,
+            Err(UploadError::InvalidDigest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn decompresses_bodies() {
+        let contribution = test_transcript().contribution();
+        let json = serde_json::to_vec(&contribution).unwrap();
+        let binary = contribution_format::encode(&contribution);
+
+        for encoding in [Encoding::Gzip, Encoding::Brotli] {
+            for (content_type, data) in
+                [("application/json", &json), (BINARY_CONTENT_TYPE, &binary)]
+            {
+                let mut req = compressed_request(encoding, content_type, data, data.len());
+                let ContributionBody(parsed) =
+                    ContributionBody::from_request(&mut req).await.unwrap();
+                assert_eq!(parsed, contribution);
+
+                let limit = data.len() - 1;
+                let mut req = compressed_request(encoding, content_type, data, limit);
+                assert!(matches!(
+                    ContributionBody::from_request(&mut req).await,
+                    Err(UploadError::DecompressedTooLarge(size)) if size == limit
+                ));
+            }
+        }
+
+        let mut req = request(Body::from(json), None, usize::MAX);
+        req.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("compress"));
+        assert!(matches!(
+            ContributionBody::from_request(&mut req).await,
+            Err(UploadError::UnsupportedContentEncoding)
+        ));
+    }
+}
@@ -1,4 +1,5 @@
 use eyre::{bail, ensure, Result as EyreResult};
+use http::HeaderMap;
 use std::{
     convert::Infallible,
     fmt,
@@ -9,8 +10,8 @@ use url::{Host, Url};
 
 pub fn parse_url(url: &Url) -> EyreResult<(SocketAddr, &str)> {
     ensure!(
-        url.scheme() == "http",
-        "Only http:// is supported in {}",
+        matches!(url.scheme(), "http" | "https"),
+        "Only http:// and https:// are supported in {}",
         url
     );
     let prefix = url.path();
@@ -25,6 +26,19 @@ pub fn parse_url(url: &Url) -> EyreResult<(SocketAddr, &str)> {
     Ok((addr, prefix))
 }
 
+/// Header a reconnecting `EventSource` sends the id of the last event with.
+const LAST_EVENT_ID: &str = "last-event-id";
+
+/// The id of the last server-sent event a reconnecting client got, for
+/// streams whose events are numbered.
+#[must_use]
+pub fn last_event_id(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(LAST_EVENT_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct Secret(String);
 
@@ -0,0 +1,457 @@
+//! Background verification of contributions.
+//!
+//! `/contribute` only checks that it is the participant's turn and hands
+//! the contribution to the queue. The pairing checks then run on the blocking
+//! thread pool, bounded by `--verification-workers`, while the participant
+//! polls `/contribute/status/:id` for the outcome, or follows the checks as
+//! they finish on `/contribute/progress`.
+
+use crate::{metrics::VERIFICATION_BACKLOG, rejection::RejectionReason, storage::StorageError};
+use clap::Parser;
+use kzg_ceremony_crypto::{Check, ErrorCode};
+use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::{Arc, Mutex, RwLock},
+};
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::sync::{watch, Semaphore};
+use tracing::Instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Number of contributions verified concurrently.
+    #[clap(long, env, default_value = "1")]
+    pub verification_workers: usize,
+
+    /// Number of finished verifications whose outcome can still be queried.
+    #[clap(long, env, default_value = "1000")]
+    pub verification_history: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum VerificationStatus {
+    Pending,
+    /// The contribution is part of the transcript.
+    Valid {
+        receipt:   String,
+        signature: String,
+    },
+    /// `code` and `kind` are those of the error response the contribution
+    /// would have been rejected with, see
+    /// [`ApiError`](crate::api::v1::error_response::ApiError). `reason` is
+    /// set for contributions that were verified and failed.
+    Invalid {
+        code:   String,
+        kind:   String,
+        error:  String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<RejectionReason>,
+    },
+}
+
+/// Where the verification of a contribution is. The subgroup checks and the
+/// pairings of all sub-ceremonies run in parallel, the counts tell how many
+/// of each are done.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct VerificationProgress {
+    pub stage:           VerificationStage,
+    pub sub_ceremonies:  usize,
+    /// Sub-ceremonies whose subgroup checks are done.
+    pub subgroup_checks: usize,
+    /// Sub-ceremonies whose pairings are done.
+    pub pairings:        usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationStage {
+    /// Waiting for a worker.
+    #[default]
+    Queued,
+    SubgroupChecks,
+    Pairings,
+    TranscriptWrite,
+    /// The outcome is available from `/contribute/status/:id`.
+    Finished,
+}
+
+/// Handle a verification job reports its progress through.
+#[derive(Clone, Debug)]
+pub struct ProgressReporter(Arc<watch::Sender<VerificationProgress>>);
+
+impl ProgressReporter {
+    /// The checks of `sub_ceremonies` sub-ceremonies start.
+    pub fn verifying(&self, sub_ceremonies: usize) {
+        self.0.send_modify(|progress| {
+            progress.sub_ceremonies = sub_ceremonies;
+            progress.stage = VerificationStage::SubgroupChecks;
+        });
+    }
+
+    /// `check` of a sub-ceremony is done.
+    pub fn checked(&self, check: Check) {
+        self.0.send_modify(|progress| {
+            match check {
+                Check::Points => progress.subgroup_checks += 1,
+                Check::Pairings => progress.pairings += 1,
+            }
+            progress.stage = if progress.subgroup_checks < progress.sub_ceremonies {
+                VerificationStage::SubgroupChecks
+            } else {
+                VerificationStage::Pairings
+            };
+        });
+    }
+
+    pub fn writing_transcript(&self) {
+        self.0
+            .send_modify(|progress| progress.stage = VerificationStage::TranscriptWrite);
+    }
+}
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum VerificationError {
+    #[error("unknown verification id")]
+    UnknownId,
+    #[error("storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+impl ErrorCode for VerificationError {
+    fn to_error_code(&self) -> String {
+        format!("VerificationError::{}", <&str>::from(self))
+    }
+}
+
+/// A fresh verification id.
+#[must_use]
+pub fn new_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// The threads the checks of a contribution fan out to. The global rayon
+/// pool, unless it was resized, see [`VerificationQueue::resize_pool`].
+#[derive(Clone, Default)]
+pub struct VerificationPool(Option<Arc<ThreadPool>>);
+
+impl VerificationPool {
+    /// Runs `op` on the pool. Blocks until it returns.
+    pub fn install<R, F>(&self, op: F) -> R
+    where
+        F: FnOnce() -> R + Send,
+        R: Send,
+    {
+        match &self.0 {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// Number of threads of the pool.
+    #[must_use]
+    pub fn threads(&self) -> usize {
+        self.0
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |pool| {
+                pool.current_num_threads()
+            })
+    }
+}
+
+pub type SharedVerificationQueue = Arc<VerificationQueue>;
+
+pub struct VerificationQueue {
+    workers: Arc<Semaphore>,
+    history: usize,
+    jobs:    Mutex<Jobs>,
+    pool:    RwLock<VerificationPool>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    statuses: HashMap<String, watch::Receiver<VerificationStatus>>,
+    progress: HashMap<String, watch::Receiver<VerificationProgress>>,
+    /// Ids of finished jobs, oldest first.
+    finished: VecDeque<String>,
+}
+
+impl VerificationQueue {
+    #[must_use]
+    pub fn new(options: &Options) -> Self {
+        Self {
+            workers: Arc::new(Semaphore::new(options.verification_workers.max(1))),
+            history: options.verification_history,
+            jobs:    Mutex::new(Jobs::default()),
+            pool:    RwLock::default(),
+        }
+    }
+
+    /// The pool verifications started now run on.
+    #[must_use]
+    pub fn pool(&self) -> VerificationPool {
+        self.pool.read().unwrap().clone()
+    }
+
+    /// Runs the verifications started from now on `threads` threads, or on
+    /// the global pool if `None`. Running verifications keep their pool, its
+    /// threads exit once they are done.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the threads can not be spawned.
+    pub fn resize_pool(&self, threads: Option<usize>) -> Result<(), ThreadPoolBuildError> {
+        let pool = match threads {
+            Some(threads) => VerificationPool(Some(Arc::new(
+                ThreadPoolBuilder::new()
+                    .num_threads(threads.max(1))
+                    .thread_name(|index| format!("verification-{index}"))
+                    .build()?,
+            ))),
+            None => VerificationPool::default(),
+        };
+        *self.pool.write().unwrap() = pool;
+        Ok(())
+    }
+
+    /// Number of submitted contributions that are not verified yet.
+    #[must_use]
+    pub fn backlog(&self) -> usize {
+        self.jobs
+            .lock()
+            .unwrap()
+            .statuses
+            .values()
+            .filter(|status| *status.borrow() == VerificationStatus::Pending)
+            .count()
+    }
+
+    /// Runs the future `job` makes once a worker is free. Its status can be
+    /// queried with `id`, see [`new_id`], and it reports its progress to the
+    /// reporter it is made with.
+    pub fn submit<J, F>(self: &Arc<Self>, id: String, job: J)
+    where
+        J: FnOnce(ProgressReporter) -> F,
+        F: Future<Output = VerificationStatus> + Send + 'static,
+    {
+        let (sender, receiver) = watch::channel(VerificationStatus::Pending);
+        let (progress, progress_receiver) = watch::channel(VerificationProgress::default());
+        let progress = Arc::new(progress);
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.statuses.insert(id.clone(), receiver);
+            jobs.progress.insert(id.clone(), progress_receiver);
+        }
+        let job = job(ProgressReporter(progress.clone()));
+
+        let queue = self.clone();
+        VERIFICATION_BACKLOG.inc();
+        tokio::spawn(
+            async move {
+                let permit = queue.workers.clone().acquire_owned().await;
+                let _permit = permit.expect("the semaphore is never closed");
+                sender.send_replace(job.await);
+                progress.send_modify(|progress| progress.stage = VerificationStage::Finished);
+                VERIFICATION_BACKLOG.dec();
+                queue.finish(id);
+            }
+            .in_current_span(),
+        );
+    }
+
+    fn finish(&self, id: String) {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.finished.push_back(id);
+        while jobs.finished.len() > self.history {
+            if let Some(expired) = jobs.finished.pop_front() {
+                jobs.statuses.remove(&expired);
+                jobs.progress.remove(&expired);
+            }
+        }
+    }
+
+    /// Current status of a verification.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the id is unknown or has been dropped from the
+    /// history.
+    pub fn status(&self, id: &str) -> Result<VerificationStatus, VerificationError> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .statuses
+            .get(id)
+            .map(|receiver| receiver.borrow().clone())
+            .ok_or(VerificationError::UnknownId)
+    }
+
+    /// Receivers of the progress and the status of a verification. The
+    /// status is final once the progress is
+    /// [`Finished`](VerificationStage::Finished).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the id is unknown or has been dropped from the
+    /// history.
+    pub fn subscribe(
+        &self,
+        id: &str,
+    ) -> Result<
+        (
+            watch::Receiver<VerificationProgress>,
+            watch::Receiver<VerificationStatus>,
+        ),
+        VerificationError,
+    > {
+        let jobs = self.jobs.lock().unwrap();
+        match (jobs.progress.get(id), jobs.statuses.get(id)) {
+            (Some(progress), Some(status)) => Ok((progress.clone(), status.clone())),
+            _ => Err(VerificationError::UnknownId),
+        }
+    }
+
+    /// Waits until a verification finished and returns its outcome.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the id is unknown or has been dropped from the
+    /// history.
+    pub async fn wait(&self, id: &str) -> Result<VerificationStatus, VerificationError> {
+        let mut receiver = self
+            .jobs
+            .lock()
+            .unwrap()
+            .statuses
+            .get(id)
+            .cloned()
+            .ok_or(VerificationError::UnknownId)?;
+        loop {
+            let status = receiver.borrow_and_update().clone();
+            if status != VerificationStatus::Pending {
+                return Ok(status);
+            }
+            if receiver.changed().await.is_err() {
+                return Ok(receiver.borrow().clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn reports_status_and_forgets_old_jobs() {
+        let queue = Arc::new(VerificationQueue::new(&Options {
+            verification_workers: 1,
+            verification_history: 1,
+        }));
+        let (release, released) = oneshot::channel::<()>();
+        let first = new_id();
+        queue.submit(first.clone(), |_| async move {
+            released.await.unwrap();
+            VerificationStatus::Valid {
+                receipt:   "receipt".to_string(),
+                signature: "signature".to_string(),
+            }
+        });
+        // Waits for the single worker.
+        let second = new_id();
+        queue.submit(second.clone(), |_| async {
+            VerificationStatus::Invalid {
+                code:   "code".to_string(),
+                kind:   "kind".to_string(),
+                error:  "error".to_string(),
+                reason: None,
+            }
+        });
+        assert_eq!(queue.status(&first).unwrap(), VerificationStatus::Pending);
+        assert_eq!(queue.status(&second).unwrap(), VerificationStatus::Pending);
+        assert_eq!(queue.backlog(), 2);
+        let (progress, _) = queue.subscribe(&second).unwrap();
+        assert_eq!(progress.borrow().stage, VerificationStage::Queued);
+
+        release.send(()).unwrap();
+        assert!(matches!(
+            queue.wait(&first).await.unwrap(),
+            VerificationStatus::Valid { .. }
+        ));
+        assert!(matches!(
+            queue.wait(&second).await.unwrap(),
+            VerificationStatus::Invalid { .. }
+        ));
+        assert_eq!(queue.backlog(), 0);
+        // Only the latest finished job is kept.
+        assert!(matches!(
+            queue.status(&first),
+            Err(VerificationError::UnknownId)
+        ));
+        assert!(matches!(
+            queue.status("unknown"),
+            Err(VerificationError::UnknownId)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_progress() {
+        let queue = Arc::new(VerificationQueue::new(&Options {
+            verification_workers: 1,
+            verification_history: 10,
+        }));
+        let (release, released) = oneshot::channel::<()>();
+        let id = new_id();
+        queue.submit(id.clone(), |progress| async move {
+            progress.verifying(2);
+            progress.checked(Check::Points);
+            progress.checked(Check::Pairings);
+            progress.checked(Check::Points);
+            released.await.unwrap();
+            progress.writing_transcript();
+            VerificationStatus::Valid {
+                receipt:   "receipt".to_string(),
+                signature: "signature".to_string(),
+            }
+        });
+        let (mut progress, _) = queue.subscribe(&id).unwrap();
+        while progress.borrow_and_update().subgroup_checks < 2 {
+            progress.changed().await.unwrap();
+        }
+        assert_eq!(*progress.borrow(), VerificationProgress {
+            stage:           VerificationStage::Pairings,
+            sub_ceremonies:  2,
+            subgroup_checks: 2,
+            pairings:        1,
+        });
+
+        release.send(()).unwrap();
+        queue.wait(&id).await.unwrap();
+        while progress.borrow_and_update().stage != VerificationStage::Finished {
+            progress.changed().await.unwrap();
+        }
+    }
+
+    #[test]
+    fn resizes_the_pool() {
+        let queue = VerificationQueue::new(&Options {
+            verification_workers: 1,
+            verification_history: 1,
+        });
+        assert_eq!(queue.pool().threads(), rayon::current_num_threads());
+        queue.resize_pool(Some(1)).unwrap();
+        let pool = queue.pool();
+        assert_eq!(pool.threads(), 1);
+        assert_eq!(pool.install(rayon::current_num_threads), 1);
+        queue.resize_pool(None).unwrap();
+        assert_eq!(queue.pool().threads(), rayon::current_num_threads());
+    }
+}
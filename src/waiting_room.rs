@@ -0,0 +1,122 @@
+//! Waiting room for participants turned away by a full lobby.
+//!
+//! With `--waiting-room`, a participant that finds the lobby at
+//! `--max-lobby-size` is recorded in the `waiting_room` table and answered
+//! with `SEQ-LOBBY-008` and their position, instead of `SEQ-LOBBY-001`. They
+//! may leave an email address with `POST /lobby/waiting_room` and close the
+//! tab. The sequencer sends no mail itself: every `--waiting-room-interval`,
+//! as many participants as the lobby has room for are announced, longest
+//! waiting first, with a `waiting_room_slot_available` webhook event carrying
+//! their uid and address, for the operator's mailer to send them the link to
+//! the ceremony. Those not back in the lobby within `--waiting-room-grace`
+//! lose their place, and with it their address.
+
+use crate::{lobby::SharedLobbyState, storage::PersistentStorage, webhook::WebhookEvent};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use std::{num::ParseIntError, str::FromStr, time::Duration};
+use tracing::{error, info};
+
+fn duration_from_secs(value: &str) -> Result<Duration, ParseIntError> {
+    Ok(Duration::from_secs(u64::from_str(value)?))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Keep participants turned away by a full lobby in a waiting room and
+    /// announce free slots to them.
+    #[clap(long, env)]
+    pub waiting_room: bool,
+
+    /// Seconds between checks for free slots in the lobby.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "30")]
+    pub waiting_room_interval: Duration,
+
+    /// Seconds an announced participant has to join the lobby before
+    /// losing their place.
+    #[clap(long, env, value_parser = duration_from_secs, default_value = "900")]
+    pub waiting_room_grace: Duration,
+}
+
+/// Announces free slots to the waiting room every `--waiting-room-interval`.
+pub async fn announce_on_interval(
+    options: Options,
+    lobby_state: SharedLobbyState,
+    storage: PersistentStorage,
+) {
+    let mut interval = tokio::time::interval(options.waiting_room_interval);
+    loop {
+        interval.tick().await;
+        let max_lobby_size = lobby_state.options().max_lobby_size;
+        match announce_free_slots(&options, max_lobby_size, &lobby_state, &storage).await {
+            Ok(0) => {}
+            Ok(announced) => info!(announced, "Announced free lobby slots to the waiting room"),
+            Err(error) => error!(?error, "failed to announce free slots to the waiting room"),
+        }
+    }
+}
+
+/// Announces the free slots of the lobby that are not already promised to
+/// announced participants. Returns the number of participants announced.
+async fn announce_free_slots(
+    options: &Options,
+    max_lobby_size: usize,
+    lobby_state: &SharedLobbyState,
+    storage: &PersistentStorage,
+) -> Result<usize, crate::storage::StorageError> {
+    let announced_before = chrono::Duration::from_std(options.waiting_room_grace)
+        .ok()
+        .and_then(|grace| Utc::now().checked_sub_signed(grace))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let expected = storage.expire_waiting_room(announced_before).await?;
+    let free = max_lobby_size
+        .saturating_sub(lobby_state.get_lobby_size().await)
+        .saturating_sub(expected);
+    if free == 0 {
+        return Ok(0);
+    }
+    let announced = storage.announce_waiting(free).await?;
+    for participant in &announced {
+        lobby_state.notify(WebhookEvent::WaitingRoomSlotAvailable {
+            uid:   participant.uid.clone(),
+            email: participant.email.clone(),
+        });
+    }
+    Ok(announced.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sessions::SessionId,
+        storage::storage_client,
+        test_util::{create_test_session_info, test_options},
+    };
+
+    #[tokio::test]
+    async fn announces_free_slots() {
+        let mut options = test_options();
+        options.lobby.max_lobby_size = 2;
+        let storage = storage_client(&options.storage).await.unwrap();
+        let lobby_state = SharedLobbyState::new(options.lobby.clone());
+        let session_id = SessionId::new();
+        lobby_state
+            .insert_session(session_id.clone(), create_test_session_info(100))
+            .await
+            .unwrap();
+        lobby_state.enter_lobby(&session_id).await.unwrap();
+        for uid in ["git|1|alice", "git|2|bob"] {
+            storage.join_waiting_room(uid).await.unwrap();
+        }
+
+        let announce = || announce_free_slots(&options.waiting_room, 2, &lobby_state, &storage);
+        assert_eq!(announce().await.unwrap(), 1);
+        // The free slot is promised to Alice.
+        assert_eq!(announce().await.unwrap(), 0);
+        storage.leave_waiting_room("git|1|alice").await.unwrap();
+        assert_eq!(announce().await.unwrap(), 1);
+        assert_eq!(announce().await.unwrap(), 0);
+    }
+}
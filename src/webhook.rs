@@ -0,0 +1,307 @@
+//! Ceremony events POSTed to `--webhook-url`.
+//!
+//! Events are JSON objects with an `event` name, the `ceremony` id (`null`
+//! for the default one) and a unix `timestamp`. The body is signed with
+//! HMAC-SHA256 under `--webhook-secret`, the hex encoded signature is sent in
+//! the `X-Webhook-Signature` header as `sha256=<signature>`. With a
+//! `webhook_key` in `--signing-keys-file` the deliveries are also signed like
+//! admin requests, see [`request_signing`](crate::request_signing), which
+//! adds a timestamp and a nonce and allows rotating the key. Events are
+//! delivered one at a time in the order they happened. A failed delivery is
+//! retried with exponential backoff up to `--webhook-retries` times before the
+//! event is dropped; events that do not fit the queue meanwhile are dropped
+//! right away.
+
+use crate::{
+    ceremony::CeremonyId, event_tail::SharedEventTail, request_signing::SharedKeyring, util::Secret,
+};
+use chrono::Utc;
+use clap::Parser;
+use hmac::{Hmac, Mac};
+use http::header::CONTENT_TYPE;
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{error, warn};
+use url::{Position, Url};
+
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+/// Number of events waiting for delivery before new ones are dropped.
+const QUEUE_CAPACITY: usize = 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Url ceremony events are POSTed to. Requires `--webhook-secret`.
+    #[clap(long, env, requires = "webhook_secret")]
+    pub webhook_url: Option<Url>,
+
+    /// Key the webhook events are signed with.
+    #[clap(long, env)]
+    pub webhook_secret: Option<Secret>,
+
+    /// Number of times the delivery of an event is retried.
+    #[clap(long, env, default_value = "5")]
+    pub webhook_retries: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    SequencerStarted {
+        version:        &'static str,
+        num_ceremonies: usize,
+    },
+    ContributionVerified {
+        uid:               String,
+        num_contributions: usize,
+    },
+    /// The contributor lost the slot without contributing. `reason` is one
+    /// of the labels of the `contributions_expired` metric.
+    ContributionExpired {
+        uid:    String,
+        reason: &'static str,
+    },
+    /// The lobby reached `--max-lobby-size`.
+    LobbyFull { lobby_size: usize },
+    /// A participant of the waiting room may join the lobby now. `email` is
+    /// set if they asked to be told.
+    WaitingRoomSlotAvailable {
+        uid:   String,
+        email: Option<String>,
+    },
+    /// A stored contribution failed the integrity self-check.
+    IntegrityMismatch { position: usize, reason: String },
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    ceremony:  Option<&'a str>,
+    timestamp: i64,
+    #[serde(flatten)]
+    event:     &'a WebhookEvent,
+}
+
+/// Handle to the webhook dispatcher. Without `--webhook-url` events are
+/// discarded, but still kept in the event tail if the handle has one.
+#[derive(Clone, Debug, Default)]
+pub struct Webhook {
+    sender:   Option<mpsc::Sender<String>>,
+    ceremony: Option<String>,
+    tail:     Option<SharedEventTail>,
+}
+
+impl Webhook {
+    /// Spawns the dispatcher if `--webhook-url` is set.
+    #[must_use]
+    pub fn new(options: &Options, keyring: SharedKeyring, http_client: reqwest::Client) -> Self {
+        let (url, secret) = match (&options.webhook_url, &options.webhook_secret) {
+            (Some(url), Some(secret)) => (url.clone(), secret.get_secret().as_bytes().to_vec()),
+            _ => return Self::default(),
+        };
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(dispatch(
+            receiver,
+            url,
+            secret,
+            options.webhook_retries,
+            keyring,
+            http_client,
+        ));
+        Self {
+            sender:   Some(sender),
+            ceremony: None,
+            tail:     None,
+        }
+    }
+
+    /// The same dispatcher, tagging events with `ceremony`.
+    #[must_use]
+    pub fn for_ceremony(&self, ceremony: &CeremonyId) -> Self {
+        Self {
+            sender:   self.sender.clone(),
+            ceremony: ceremony.0.clone(),
+            tail:     self.tail.clone(),
+        }
+    }
+
+    /// The same dispatcher, also keeping events in `tail`.
+    #[must_use]
+    pub fn with_event_tail(mut self, tail: SharedEventTail) -> Self {
+        self.tail = Some(tail);
+        self
+    }
+
+    /// Queues `event` for delivery.
+    pub fn notify(&self, event: WebhookEvent) {
+        if let Some(tail) = &self.tail {
+            tail.push_ceremony(&event);
+        }
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+        let body = serde_json::to_string(&Envelope {
+            ceremony:  self.ceremony.as_deref(),
+            timestamp: Utc::now().timestamp(),
+            event:     &event,
+        })
+        .expect("webhook events serialize");
+        match sender.try_send(body) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => warn!(?event, "Webhook queue is full, dropping event"),
+            Err(TrySendError::Closed(_)) => error!(?event, "Webhook dispatcher stopped"),
+        }
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of `body`.
+fn sign(secret: &[u8], body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("any key size is valid");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+async fn dispatch(
+    mut receiver: mpsc::Receiver<String>,
+    url: Url,
+    secret: Vec<u8>,
+    retries: u32,
+    keyring: SharedKeyring,
+    http_client: reqwest::Client,
+) {
+    while let Some(body) = receiver.recv().await {
+        let signature = format!("sha256={}", sign(&secret, &body));
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 0..=retries {
+            // Signed anew on every attempt, for a current timestamp.
+            let signed_headers = keyring
+                .current()
+                .sign_webhook(
+                    &url[Position::BeforePath..Position::AfterQuery],
+                    body.as_bytes(),
+                )
+                .unwrap_or_default();
+            let result = signed_headers
+                .into_iter()
+                .fold(http_client.post(url.clone()), |request, (name, value)| {
+                    request.header(name, value)
+                })
+                .header(CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .body(body.clone())
+                .timeout(REQUEST_TIMEOUT)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            match result {
+                Ok(_) => break,
+                Err(error) if attempt < retries => {
+                    warn!(?error, attempt, "Webhook delivery failed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(error) => error!(?error, %body, "Webhook delivery failed, dropping event"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request_signing::{self, KeyringHandle};
+    use axum::{body::Bytes, http::HeaderMap, routing::post, Extension, Router, Server};
+    use sha2::Digest;
+    use std::{
+        net::{SocketAddr, TcpListener},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    #[tokio::test]
+    async fn delivers_signed_events_with_retries() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let (delivered, mut deliveries) = mpsc::unbounded_channel::<(HeaderMap, Bytes)>();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |Extension(attempts): Extension<Arc<AtomicUsize>>,
+                     Extension(delivered): Extension<mpsc::UnboundedSender<(HeaderMap, Bytes)>>,
+                     headers: HeaderMap,
+                     body: Bytes| async move {
+                        // Fails the first attempt.
+                        if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                            return http::StatusCode::SERVICE_UNAVAILABLE;
+                        }
+                        delivered.send((headers, body)).unwrap();
+                        http::StatusCode::OK
+                    },
+                ),
+            )
+            .layer(Extension(attempts.clone()))
+            .layer(Extension(delivered));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let mut keys_file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut keys_file,
+            br#"{"keys": {"hook": {"hmac": "rotating"}}, "webhook_key": "hook"}"#,
+        )
+        .unwrap();
+        let keyring = KeyringHandle::new(&request_signing::Options {
+            signing_keys_file: Some(keys_file.path().to_path_buf()),
+            signature_max_age: Duration::from_secs(300),
+        })
+        .unwrap();
+        let webhook = Webhook::new(
+            &Options {
+                webhook_url:     Some(format!("http://{addr}/hook").parse().unwrap()),
+                webhook_secret:  Some("secret".parse().unwrap()),
+                webhook_retries: 1,
+            },
+            Arc::new(keyring),
+            reqwest::Client::new(),
+        )
+        .for_ceremony(&CeremonyId(Some("test".to_string())));
+        webhook.notify(WebhookEvent::LobbyFull { lobby_size: 3 });
+
+        let (headers, body) = deliveries.recv().await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(body.contains("\"event\":\"lobby_full\""));
+        assert!(body.contains("\"ceremony\":\"test\""));
+        assert!(body.contains("\"lobby_size\":3"));
+        assert_eq!(
+            headers[SIGNATURE_HEADER].to_str().unwrap(),
+            format!("sha256={}", sign(b"secret", &body))
+        );
+        let timestamp: i64 = headers[request_signing::TIMESTAMP_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let digest = headers[request_signing::DIGEST_HEADER].to_str().unwrap();
+        assert_eq!(digest, hex::encode(Sha256::digest(&body)));
+        let nonce = headers[request_signing::NONCE_HEADER].to_str().unwrap();
+        let message = request_signing::signed_message(timestamp, nonce, "POST", "/hook", digest);
+        assert_eq!(
+            headers[request_signing::SIGNATURE_HEADER].to_str().unwrap(),
+            format!("hook:{}", sign(b"rotating", &message))
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Signed reports on the witness chain of the transcript.
+//!
+//! `GET /transcript/verify_chain?up_to=N` checks that each of the first `N`
+//! contributions, all of them without `up_to`, builds on the running product
+//! of the one before, and their BLS signatures, and answers with a report
+//! signed by the sequencer. A light client that trusts the sequencer's key
+//! can then verify only the contributions after `N` itself, without
+//! downloading the powers.
+//!
+//! The transcript only grows and links never change once added, so the
+//! longest verified prefix of the chain is remembered and each request only
+//! checks the contributions added since.
+
+use crate::{
+    attestation::sign_statement,
+    keys::{Keys, Signature, SignatureError},
+    Engine,
+};
+use chrono::Utc;
+use kzg_ceremony_crypto::{BatchTranscript, ErrorCode, G1, G2};
+use serde::Serialize;
+use std::sync::Arc;
+use strum::IntoStaticStr;
+use thiserror::Error;
+use tokio::{sync::Mutex, task::JoinError};
+
+#[derive(Debug, Error, IntoStaticStr)]
+pub enum ChainReportError {
+    #[error("up_to {up_to} is past the {num_contributions} contributions of the transcript")]
+    InvalidUpTo {
+        up_to:             usize,
+        num_contributions: usize,
+    },
+    #[error("background task error: {0}")]
+    TaskError(#[from] JoinError),
+    #[error("failed to sign the report: {0}")]
+    Signature(#[from] SignatureError),
+}
+
+impl ErrorCode for ChainReportError {
+    fn to_error_code(&self) -> String {
+        format!("ChainReportError::{}", <&str>::from(self))
+    }
+}
+
+/// Statement by the sequencer about the witness chain of its transcript.
+#[derive(Debug, Serialize)]
+pub struct ChainReport {
+    /// Id of the ceremony, `None` for the default one.
+    pub ceremony:          Option<String>,
+    pub num_contributions: usize,
+    /// Contributions 1 to `up_to` were checked.
+    pub up_to:             usize,
+    /// The running product of each sub-ceremony after contribution `up_to`.
+    pub running_products:  Vec<G1>,
+    pub valid:             bool,
+    /// Why the chain is not valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error:             Option<String>,
+    /// Unix timestamp (in seconds) at which the report was made.
+    pub timestamp:         i64,
+}
+
+impl ChainReport {
+    pub async fn sign(&self, keys: &Keys) -> Result<(String, Signature), SignatureError> {
+        sign_statement(self, keys).await
+    }
+}
+
+pub type SharedChainVerifier = Arc<ChainVerifier>;
+
+#[derive(Default)]
+pub struct ChainVerifier {
+    /// Locked while verifying, so that concurrent requests wait for one
+    /// verification instead of all running it.
+    verified: Mutex<Option<VerifiedPrefix>>,
+}
+
+/// The longest prefix of the chain verified so far.
+struct VerifiedPrefix {
+    up_to:    usize,
+    /// The last links of the prefix, of each sub-ceremony. The chain is
+    /// checked from the start if they change.
+    products: Vec<G1>,
+    pubkeys:  Vec<G2>,
+}
+
+impl VerifiedPrefix {
+    fn new(transcript: &BatchTranscript, up_to: usize) -> Self {
+        let witnesses = transcript.transcripts.iter().map(|t| &t.witness);
+        Self {
+            up_to,
+            products: witnesses.clone().map(|w| w.products[up_to]).collect(),
+            pubkeys: witnesses.map(|w| w.pubkeys[up_to]).collect(),
+        }
+    }
+
+    /// Whether `transcript` has the prefix.
+    fn is_prefix_of(&self, transcript: &BatchTranscript) -> bool {
+        if transcript.participant_ids.len() <= self.up_to {
+            return false;
+        }
+        let links = Self::new(transcript, self.up_to);
+        links.products == self.products && links.pubkeys == self.pubkeys
+    }
+}
+
+impl ChainVerifier {
+    /// Checks the chain of `transcript` up to contribution `up_to`, or all of
+    /// it if `None`, and reports on it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `up_to` is past the end of the transcript. An
+    /// invalid chain is not an error, but reported.
+    pub async fn report(
+        &self,
+        ceremony: Option<String>,
+        transcript: BatchTranscript,
+        up_to: Option<usize>,
+    ) -> Result<ChainReport, ChainReportError> {
+        let num_contributions = transcript.num_participants();
+        let up_to = up_to.unwrap_or(num_contributions);
+        if up_to > num_contributions {
+            return Err(ChainReportError::InvalidUpTo {
+                up_to,
+                num_contributions,
+            });
+        }
+
+        let mut verified = self.verified.lock().await;
+        let from = verified
+            .as_ref()
+            .filter(|prefix| prefix.is_prefix_of(&transcript))
+            .map_or(0, |prefix| prefix.up_to.min(up_to));
+        let (transcript, result) = tokio::task::spawn_blocking(move || {
+            let result = transcript.verify_witness_chain::<Engine>(from, up_to);
+            (transcript, result)
+        })
+        .await?;
+        if result.is_ok()
+            && verified
+                .as_ref()
+                .map_or(true, |prefix| prefix.up_to < up_to)
+        {
+            *verified = Some(VerifiedPrefix::new(&transcript, up_to));
+        }
+        drop(verified);
+
+        Ok(ChainReport {
+            ceremony,
+            num_contributions,
+            up_to,
+            running_products: transcript
+                .transcripts
+                .iter()
+                .map(|t| t.witness.products[up_to])
+                .collect(),
+            valid: result.is_ok(),
+            error: result.err().map(|err| err.to_string()),
+            timestamp: Utc::now().timestamp(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{test_transcript, valid_contribution};
+    use kzg_ceremony_crypto::signature::identity::Identity;
+
+    #[tokio::test]
+    async fn reports_on_the_chain() {
+        let mut transcript = test_transcript();
+        for no in 1..=3 {
+            let contribution = valid_contribution(&transcript, no);
+            transcript
+                .verify_add::<Engine>(contribution, Identity::None)
+                .unwrap();
+        }
+        let verifier = ChainVerifier::default();
+
+        let report = verifier
+            .report(None, transcript.clone(), Some(2))
+            .await
+            .unwrap();
+        assert!(report.valid, "{:?}", report.error);
+        assert_eq!(report.up_to, 2);
+        assert_eq!(report.num_contributions, 3);
+        assert_eq!(
+            report.running_products[0],
+            transcript.transcripts[0].witness.products[2]
+        );
+        let report = verifier
+            .report(None, transcript.clone(), None)
+            .await
+            .unwrap();
+        assert!(report.valid);
+        assert_eq!(report.up_to, 3);
+        assert!(matches!(
+            verifier.report(None, transcript.clone(), Some(4)).await,
+            Err(ChainReportError::InvalidUpTo { up_to: 4, .. })
+        ));
+
+        let mut broken = transcript;
+        broken.transcripts[0].witness.pubkeys.swap(1, 2);
+        let report = ChainVerifier::default()
+            .report(None, broken, Some(2))
+            .await
+            .unwrap();
+        assert!(!report.valid);
+        assert!(report.error.is_some());
+    }
+}
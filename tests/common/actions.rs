@@ -4,10 +4,10 @@ use crate::{
 };
 use ethers_core::types::Signature;
 use http::StatusCode;
-use kzg_ceremony_crypto::{BatchContribution, BatchTranscript, G2};
+use kzg_ceremony_crypto::{BatchContribution, BatchTranscript, DefaultEngine, G2};
 use secrecy::Secret;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 use url::Url;
 
 /// This function acts both as a test and a utility. This way, we'll test the
@@ -137,11 +137,22 @@ pub async fn request_try_contribute(
         .unwrap()
 }
 
+pub fn extract_reservation(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get("x-reservation-token")
+        .expect("Response must contain a reservation token")
+        .to_str()
+        .expect("reservation token must be a string")
+        .to_string()
+}
+
+/// Returns the contribution base together with the reservation token.
 pub async fn try_contribute(
     harness: &Harness,
     http_client: &reqwest::Client,
     session_id: &str,
-) -> BatchContribution {
+) -> (BatchContribution, String) {
     let response = request_try_contribute(harness, http_client, session_id).await;
 
     assert_eq!(
@@ -150,21 +161,25 @@ pub async fn try_contribute(
         "Response must be successful"
     );
 
-    response
+    let reservation = extract_reservation(&response);
+    let contribution = response
         .json::<BatchContribution>()
         .await
-        .expect("Successful response must be a contribution")
+        .expect("Successful response must be a contribution");
+    (contribution, reservation)
 }
 
 pub async fn request_contribute(
     harness: &Harness,
     http_client: &reqwest::Client,
     session_id: &str,
+    reservation: &str,
     contribution: &BatchContribution,
 ) -> reqwest::Response {
     http_client
         .post(harness.options.server.join("contribute").unwrap())
         .header("Authorization", format!("Bearer {session_id}"))
+        .header("X-Reservation-Token", reservation)
         .json(contribution)
         .send()
         .await
@@ -187,24 +202,83 @@ async fn get_sequencer_eth_address(harness: &Harness, http_client: &reqwest::Cli
         .to_string()
 }
 
+/// Polls the status of a queued contribution until it is verified.
+pub async fn await_verification(
+    harness: &Harness,
+    http_client: &reqwest::Client,
+    verification_id: &str,
+) -> Value {
+    loop {
+        let status = http_client
+            .get(harness.app_path(&format!("contribute/status/{verification_id}")))
+            .send()
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .expect("Must return valid JSON");
+        if status.get("status") != Some(&Value::from("pending")) {
+            return status;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Signs in a new GitHub user and makes their contribution. Returns the user
+/// and the contribution as submitted.
+pub async fn create_user_and_contribute(
+    harness: &Harness,
+    http_client: &reqwest::Client,
+    name: String,
+) -> (TestUser, BatchContribution) {
+    let (user, session_id) = create_and_login_gh_user(harness, http_client, name.clone()).await;
+    let (mut contribution, reservation) = try_contribute(harness, http_client, &session_id).await;
+    contribution
+        .add_entropy::<DefaultEngine>(&entropy_from_str(&name), &user.identity())
+        .expect("Adding entropy must be possible");
+    contribute_successfully(
+        harness,
+        http_client,
+        &session_id,
+        &reservation,
+        &contribution,
+        &user.identity().to_string(),
+    )
+    .await;
+    (user, contribution)
+}
+
 pub async fn contribute_successfully(
     harness: &Harness,
     http_client: &reqwest::Client,
     session_id: &str,
+    reservation: &str,
     contribution: &BatchContribution,
     user_id: &str,
 ) {
-    let response = request_contribute(harness, http_client, session_id, contribution).await;
+    let response =
+        request_contribute(harness, http_client, session_id, reservation, contribution).await;
 
-    if response.status() != StatusCode::OK {
+    if response.status() != StatusCode::ACCEPTED {
         println!("Response: {:?}", response.text().await);
         panic!("Response must be successful");
     }
 
-    let response_json = response
+    let verification_id = response
         .json::<Value>()
         .await
-        .expect("Must return valid JSON");
+        .expect("Must return valid JSON")
+        .get("verification_id")
+        .expect("must contain the verification id")
+        .as_str()
+        .expect("verification id must be a string")
+        .to_string();
+    let response_json = await_verification(harness, http_client, &verification_id).await;
+    assert_eq!(
+        response_json.get("status"),
+        Some(&Value::from("valid")),
+        "Contribution must be valid: {response_json}"
+    );
 
     let receipt = response_json
         .get("receipt")
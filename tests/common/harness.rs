@@ -137,12 +137,13 @@ impl Harness {
         let mut app_shutdown_receiver = self.app_shutdown_sender.subscribe();
         let (app_start_sender, app_start_receiver) = oneshot::channel::<()>();
         let app_handle = tokio::spawn(async move {
-            let server = start_server(options).await.unwrap();
+            let (_, server) = start_server(options, async move {
+                app_shutdown_receiver.recv().await.unwrap();
+            })
+            .await
+            .unwrap();
             app_start_sender.send(()).unwrap();
-            server
-                .with_graceful_shutdown(async move { app_shutdown_receiver.recv().await.unwrap() })
-                .await
-                .unwrap();
+            server.await.unwrap();
         });
         app_start_receiver.await.unwrap();
         self.app_handle = Some(app_handle);
@@ -210,6 +211,10 @@ impl Builder {
         options.lobby.lobby_checkin_frequency = Duration::from_millis(2000);
         options.lobby.lobby_checkin_tolerance = Duration::from_millis(2000);
         options.lobby.compute_deadline = Duration::from_millis(800);
+        // All participants connect from localhost.
+        options.rate_limit.rate_limit_default = 0;
+        options.rate_limit.rate_limit_auth = 0;
+        options.rate_limit.rate_limit_try_contribute = 0;
         Self { options }
     }
 
@@ -19,17 +19,21 @@ async fn await_contribution_slot(
     client: &reqwest::Client,
     session_id: &str,
     wait_longer_than_timeout: bool,
-) -> BatchContribution {
+) -> (BatchContribution, String) {
     loop {
         let try_contribute_response =
             actions::request_try_contribute(harness, client, session_id).await;
         assert_eq!(try_contribute_response.status(), StatusCode::OK);
+        let reservation = try_contribute_response
+            .headers()
+            .contains_key("x-reservation-token")
+            .then(|| actions::extract_reservation(&try_contribute_response));
         let maybe_contribution = try_contribute_response
             .json::<BatchContribution>()
             .await
             .ok();
-        if let Some(contrib) = maybe_contribution {
-            return contrib;
+        if let (Some(contrib), Some(reservation)) = (maybe_contribution, reservation) {
+            return (contrib, reservation);
         }
 
         let sleep_duration = if wait_longer_than_timeout {
@@ -52,7 +56,7 @@ pub async fn well_behaved(
     ping_too_slow: bool,
 ) -> PostConditionCheck {
     let session_id = actions::login(harness, client, &user).await;
-    let mut contribution =
+    let (mut contribution, reservation) =
         await_contribution_slot(harness, client, &session_id, ping_too_slow).await;
     contribution
         .add_entropy::<BLST>(
@@ -74,6 +78,7 @@ pub async fn well_behaved(
         harness,
         client,
         &session_id,
+        &reservation,
         &contribution,
         &user.identity().to_string(),
     )
@@ -96,7 +101,8 @@ pub async fn wrong_ecdsa(
     user: TestUser,
 ) -> PostConditionCheck {
     let session_id = actions::login(harness, client, &user).await;
-    let mut contribution = await_contribution_slot(harness, client, &session_id, false).await;
+    let (mut contribution, reservation) =
+        await_contribution_slot(harness, client, &session_id, false).await;
     contribution
         .add_entropy::<DefaultEngine>(
             &entropy_from_str(&user.identity().to_string()),
@@ -116,6 +122,7 @@ pub async fn wrong_ecdsa(
         harness,
         client,
         &session_id,
+        &reservation,
         &contribution,
         &user.identity().to_string(),
     )
@@ -132,7 +139,8 @@ pub async fn wrong_bls(
     user: TestUser,
 ) -> PostConditionCheck {
     let session_id = actions::login(harness, client, &user).await;
-    let mut contribution = await_contribution_slot(harness, client, &session_id, false).await;
+    let (mut contribution, reservation) =
+        await_contribution_slot(harness, client, &session_id, false).await;
     contribution
         .add_entropy::<DefaultEngine>(
             &entropy_from_str(&user.identity().to_string()),
@@ -148,6 +156,7 @@ pub async fn wrong_bls(
         harness,
         client,
         &session_id,
+        &reservation,
         &contribution,
         &user.identity().to_string(),
     )
@@ -164,7 +173,8 @@ pub async fn slow_compute(
     user: TestUser,
 ) -> PostConditionCheck {
     let session_id = actions::login(harness, client, &user).await;
-    let mut contribution = await_contribution_slot(harness, client, &session_id, false).await;
+    let (mut contribution, reservation) =
+        await_contribution_slot(harness, client, &session_id, false).await;
     tokio::time::sleep(harness.options.lobby.compute_deadline).await;
     contribution
         .add_entropy::<Arkworks>(
@@ -172,7 +182,9 @@ pub async fn slow_compute(
             &user.identity(),
         )
         .expect("Adding entropy must be possible");
-    let response = actions::request_contribute(harness, client, &session_id, &contribution).await;
+    let response =
+        actions::request_contribute(harness, client, &session_id, &reservation, &contribution)
+            .await;
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     Box::new(|_| {})
 }
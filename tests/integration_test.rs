@@ -12,6 +12,7 @@ use ethers_signers::{LocalWallet, Signer};
 use http::StatusCode;
 use kzg_ceremony_crypto::{
     signature::{BlsSignature, ContributionTypedData, EcdsaSignature},
+    test_utils::{ContributionBuilder, Defect},
     Arkworks, DefaultEngine, G1,
 };
 use rand::thread_rng;
@@ -164,6 +165,24 @@ async fn test_nonexistent_eth_user() {
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 }
 
+#[tokio::test]
+async fn test_eth_sign_in_link_is_single_use() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let user = harness.create_eth_user().await;
+    let csrf = actions::get_and_validate_csrf_token(&harness, None).await;
+    let response = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let replayed = actions::request_auth_callback(&harness, &http_client, &user, &csrf).await;
+    assert_eq!(replayed.status(), StatusCode::BAD_REQUEST);
+    assert!(replayed
+        .text()
+        .await
+        .unwrap()
+        .contains("AuthErrorPayload::InvalidNonce"));
+}
+
 #[tokio::test]
 async fn test_gh_auth_with_custom_frontend_redirect() {
     let harness = run_test_harness().await;
@@ -241,7 +260,8 @@ async fn test_gh_contribution_happy_path() {
     let (user, session_id) =
         actions::create_and_login_gh_user(&harness, &http_client, "kustosz".to_string()).await;
 
-    let mut contribution = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let (mut contribution, reservation) =
+        actions::try_contribute(&harness, &http_client, &session_id).await;
 
     // not only is it unguessable, it is also 32 characters long and we depend on
     // this.
@@ -259,6 +279,7 @@ async fn test_gh_contribution_happy_path() {
         &harness,
         &http_client,
         &session_id,
+        &reservation,
         &contribution,
         &user.identity().to_string(),
     )
@@ -295,7 +316,74 @@ async fn test_gh_contribution_happy_path() {
     assert_eq!(
         contrib_pubkeys, transcript_pubkeys,
         "the pubkeys recorded in transcript must be the ones submitted"
-    )
+    );
+}
+
+#[tokio::test]
+async fn test_info_contributions() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let (user, contribution) =
+        actions::create_user_and_contribute(&harness, &http_client, "kustosz".to_string()).await;
+    let pot_pubkeys = contribution
+        .contributions
+        .iter()
+        .map(|contribution| contribution.pot_pubkey)
+        .collect::<Vec<_>>();
+
+    let page = http_client
+        .get(harness.app_path("info/contributions"))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(page["num_contributions"], 1);
+    assert_eq!(page["contributions"][0]["index"], 1);
+    assert_eq!(
+        page["contributions"][0]["participant"],
+        user.identity().to_string()
+    );
+    assert_eq!(
+        page["contributions"][0]["pot_pubkeys"],
+        serde_json::to_value(&pot_pubkeys).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_transcript_diff() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let (user, contribution) =
+        actions::create_user_and_contribute(&harness, &http_client, "kustosz".to_string()).await;
+    let transcript = harness.read_transcript_file().await;
+
+    let diff = http_client
+        .get(harness.app_path("transcript/diff?from=0&to=1"))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(diff["participants"][0], user.identity().to_string());
+    assert_eq!(diff["ceremonies"][0]["changed_g2_powers"]["start"], 1);
+    assert_eq!(
+        diff["ceremonies"][0]["running_products"][1],
+        serde_json::to_value(transcript.transcripts[0].witness.products[1]).unwrap()
+    );
+    assert_eq!(
+        diff["ceremonies"][0]["pot_pubkeys"],
+        serde_json::json!([contribution.contributions[0].pot_pubkey])
+    );
+
+    let invalid = http_client
+        .get(harness.app_path("transcript/diff?from=1&to=2"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
 }
 
 #[tokio::test]
@@ -310,7 +398,8 @@ async fn test_double_contribution() {
     )
     .await;
 
-    let mut contribution = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let (mut contribution, reservation) =
+        actions::try_contribute(&harness, &http_client, &session_id).await;
 
     // not only is it unguessable, it is also 32 characters long and we depend on
     // this.
@@ -329,6 +418,7 @@ async fn test_double_contribution() {
         &harness,
         &http_client,
         &session_id,
+        &reservation,
         &contribution,
         &user_id.to_string(),
     )
@@ -344,8 +434,14 @@ async fn test_double_contribution() {
 
     // Try contributing again right away – fails because the contribution spot is
     // emptied
-    let second_contribute_response =
-        actions::request_contribute(&harness, &http_client, &session_id, &contribution).await;
+    let second_contribute_response = actions::request_contribute(
+        &harness,
+        &http_client,
+        &session_id,
+        &reservation,
+        &contribution,
+    )
+    .await;
     assert_eq!(second_contribute_response.status(), StatusCode::BAD_REQUEST);
 
     // Try pinging the lobby again – fails because the user got logged out
@@ -388,7 +484,8 @@ async fn test_double_contribution_when_allowed() {
     )
     .await;
 
-    let mut contribution1 = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let (mut contribution1, reservation1) =
+        actions::try_contribute(&harness, &http_client, &session_id).await;
 
     contribution1
         .add_entropy::<DefaultEngine>(
@@ -401,6 +498,7 @@ async fn test_double_contribution_when_allowed() {
         &harness,
         &http_client,
         &session_id,
+        &reservation1,
         &contribution1,
         &user_id.to_string(),
     )
@@ -412,7 +510,8 @@ async fn test_double_contribution_when_allowed() {
     )
     .await;
 
-    let mut contribution2 = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let (mut contribution2, reservation2) =
+        actions::try_contribute(&harness, &http_client, &session_id).await;
     contribution2
         .add_entropy::<DefaultEngine>(
             &actions::entropy_from_str("another unguessable string, wow!"),
@@ -424,6 +523,7 @@ async fn test_double_contribution_when_allowed() {
         &harness,
         &http_client,
         &session_id,
+        &reservation2,
         &contribution2,
         &user_id.to_string(),
     )
@@ -552,7 +652,8 @@ async fn test_contribution_after_lobby_cleanup() {
     let (user, session_id) =
         actions::create_and_login_gh_user(&harness, &http_client, "kustosz".to_string()).await;
 
-    let mut contribution = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let (mut contribution, reservation) =
+        actions::try_contribute(&harness, &http_client, &session_id).await;
 
     let entropy = "such an unguessable string, wow!"
         .bytes()
@@ -570,6 +671,7 @@ async fn test_contribution_after_lobby_cleanup() {
         &harness,
         &http_client,
         &session_id,
+        &reservation,
         &contribution,
         &user.identity().to_string(),
     )
@@ -583,7 +685,8 @@ async fn test_wrong_ecdsa_signature() {
     let http_client = reqwest::Client::new();
     let user = harness.create_eth_user().await;
     let session_id = actions::login(&harness, &http_client, &user).await;
-    let mut contribution = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let (mut contribution, reservation) =
+        actions::try_contribute(&harness, &http_client, &session_id).await;
     let entropy = actions::entropy_from_str("foo bar baz");
     contribution
         .add_entropy::<DefaultEngine>(&entropy, &user.identity())
@@ -599,6 +702,7 @@ async fn test_wrong_ecdsa_signature() {
         &harness,
         &http_client,
         &session_id,
+        &reservation,
         &contribution,
         &user.identity().to_string(),
     )
@@ -615,7 +719,8 @@ async fn test_wrong_bls_signature() {
     let http_client = reqwest::Client::new();
     let user = harness.create_eth_user().await;
     let session_id = actions::login(&harness, &http_client, &user).await;
-    let mut contribution = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let (mut contribution, reservation) =
+        actions::try_contribute(&harness, &http_client, &session_id).await;
     let entropy = actions::entropy_from_str("foo bar baz");
     contribution
         .add_entropy::<DefaultEngine>(&entropy, &user.identity())
@@ -629,6 +734,7 @@ async fn test_wrong_bls_signature() {
         &harness,
         &http_client,
         &session_id,
+        &reservation,
         &contribution,
         &user.identity().to_string(),
     )
@@ -639,6 +745,39 @@ async fn test_wrong_bls_signature() {
     actions::assert_includes_contribution(&transcript, &contribution, &user, false, false)
 }
 
+#[tokio::test]
+async fn test_shuffled_powers_are_rejected() {
+    let harness = run_test_harness().await;
+    let http_client = reqwest::Client::new();
+    let user = harness.create_eth_user().await;
+    let session_id = actions::login(&harness, &http_client, &user).await;
+    let (start, reservation) = actions::try_contribute(&harness, &http_client, &session_id).await;
+    let contribution = ContributionBuilder::new(start)
+        .identity(user.identity())
+        .defect(Defect::ShuffledPowers)
+        .build::<DefaultEngine>();
+
+    let response = actions::request_contribute(
+        &harness,
+        &http_client,
+        &session_id,
+        &reservation,
+        &contribution,
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let verification_id = response.json::<serde_json::Value>().await.unwrap()["verification_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let status = actions::await_verification(&harness, &http_client, &verification_id).await;
+    assert_eq!(status["status"], "invalid");
+    assert_eq!(status["code"], "SEQ-CONTRIB-002");
+
+    let transcript = actions::get_transcript(&harness, &http_client).await;
+    assert_eq!(transcript.num_participants(), 0);
+}
+
 #[tokio::test]
 async fn test_graceful_restart() {
     let harness = Arc::new(RwLock::new(run_test_harness().await));
@@ -0,0 +1,60 @@
+//! Verification in the sandbox on BN254. The curve is fixed for the whole
+//! process, so this test has a test binary of its own.
+
+use kzg_ceremony_crypto::{
+    BatchTranscript, CeremoniesError, Curve, DefaultEngine, Identity, Secret,
+};
+use kzg_ceremony_sequencer::{run_on_curve, sandbox};
+use std::time::Duration;
+
+#[tokio::test]
+async fn verifies_bn254_contributions_in_the_sandbox() {
+    run_on_curve(Curve::Bn254).unwrap();
+    let options = sandbox::Options {
+        verification_sandbox:         true,
+        verification_sandbox_memory:  16384,
+        verification_sandbox_timeout: Duration::from_secs(300),
+        verification_sandbox_program: Some(env!("CARGO_BIN_EXE_kzg-ceremony-sequencer").into()),
+    };
+    let mut transcript = BatchTranscript::new(&[(8, 3), (4, 2)]);
+    let identity = Identity::Github {
+        id:       1234,
+        username: "test_user".to_string(),
+    };
+    let mut contribution = transcript.contribution();
+    contribution
+        .add_entropy::<DefaultEngine>(&Secret::new([7; 32]), &identity)
+        .unwrap();
+    let verified = sandbox::verify(
+        &options,
+        &transcript,
+        &contribution,
+        &identity,
+        1,
+        |_, _| (),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    transcript.apply(verified).unwrap();
+    assert_eq!(transcript.num_participants(), 1);
+
+    // The worker checks the points on BN254 too.
+    let mut shuffled = transcript.contribution();
+    shuffled
+        .add_entropy::<DefaultEngine>(&Secret::new([8; 32]), &Identity::None)
+        .unwrap();
+    shuffled.contributions[0].powers.g1.swap(6, 7);
+    assert!(matches!(
+        sandbox::verify(
+            &options,
+            &transcript,
+            &shuffled,
+            &Identity::None,
+            1,
+            |_, _| ()
+        )
+        .await,
+        Ok(Err(CeremoniesError::InvalidCeremony(0, _)))
+    ));
+}
@@ -0,0 +1,33 @@
+#![cfg(feature = "sim")]
+
+use kzg_ceremony_sequencer::sim::{self, Config};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_simulated_ceremony() {
+    let config = Config::default();
+    let report = sim::run(&config).await.unwrap();
+    assert_eq!(report.contributors.len(), 7);
+    assert_eq!(
+        report.transcript.num_participants(),
+        config.honest + config.duplicates
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_simulations_are_deterministic() {
+    let config = Config {
+        seed: 7,
+        honest: 2,
+        timeouts: 0,
+        invalid_points: 1,
+        duplicates: 1,
+        ..Config::default()
+    };
+    let first = sim::run(&config).await.unwrap();
+    let again = sim::run(&config).await.unwrap();
+    assert_eq!(first.contributors, again.contributors);
+    assert_eq!(first.transcript, again.transcript);
+
+    let other = sim::run(&Config { seed: 8, ..config }).await.unwrap();
+    assert_ne!(first.transcript, other.transcript);
+}